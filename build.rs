@@ -0,0 +1,26 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+fn main() {
+    // Put `memory.x` where linker can find it (only needed for the firmware). With the bootloader,
+    // firmware is placed after it. Memory layouts are kept in `memory/`, so linker does not pick
+    // them up from the working directory.
+    if env::var("CARGO_CFG_TARGET_ARCH").as_deref() == Ok("arm") {
+        let memory = if env::var_os("CARGO_FEATURE_STM32DUINO_BOOTLOADER").is_some() {
+            "memory/stm32duino.x"
+        } else {
+            "memory/default.x"
+        };
+        let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+        fs::copy(memory, out.join("memory.x")).unwrap();
+        println!("cargo:rustc-link-search={}", out.display());
+    }
+
+    // `defmt` needs its own linker script to place log strings
+    if env::var_os("CARGO_FEATURE_DEBUG_LOG").is_some() {
+        println!("cargo:rustc-link-arg=-Tdefmt.x");
+    }
+    println!("cargo:rerun-if-changed=memory");
+    println!("cargo:rerun-if-changed=build.rs");
+}
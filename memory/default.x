@@ -0,0 +1,7 @@
+/* Last eight 1K pages of flash are left for the key-value store, the temperature log and the
+   access log (see `storage` module) */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08000000, LENGTH = 120K
+  RAM : ORIGIN = 0x20000000, LENGTH = 20K
+}
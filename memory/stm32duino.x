@@ -0,0 +1,7 @@
+/* First 8K of flash are taken by the stm32duino (Maple) USB bootloader, last eight 1K pages are
+   left for the key-value store, the temperature log and the access log (see `storage` module) */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08002000, LENGTH = 112K
+  RAM : ORIGIN = 0x20000000, LENGTH = 20K
+}
@@ -0,0 +1,436 @@
+//! Access log: door lock and alarm events, kept in a circular area of `PAGES` flash pages (see
+//! `storage` for the one on the board), browsed on the events screen, newest first, and dumped as
+//! CSV by the `events` command of the shell.
+//!
+//! Pages are used the way the temperature log uses them (see `logger`): a header (the magic value
+//! and the page sequence number) and the records, the next page is erased when the current one is
+//! full, and the newest page is found on boot by the sequence numbers. Records are fixed-size,
+//! six half-words: time, event kind and its detail, and the CRC-32 of them (see `crc32`), low
+//! halves first. Records which do not pass the check are shown as corrupted, and counted on mount
+//! (see `EventLog::corrupt`), to warn about them.
+//!
+//! Time is the total runtime (see `backup::BootStats`), in seconds, as there is no calendar clock:
+//! it keeps going over resets, so the records are in order, and the boots screen tells how long
+//! ago that was.
+
+use core::fmt::{self, Write};
+use crate::crc32;
+use crate::fmt::write_uint;
+use crate::text;
+use crate::threshold::Source;
+use crate::ui::Event;
+
+/// Pages in the area, half-words in each of them (1K pages).
+pub const PAGES: usize = 2;
+pub const PAGE_WORDS: usize = 512;
+/// Records in a page (header takes the first two half-words).
+pub const PAGE_RECORDS: usize = (PAGE_WORDS - HEADER_WORDS) / RECORD_WORDS;
+const HEADER_WORDS: usize = 2;
+const RECORD_WORDS: usize = 6;
+
+const MAGIC: u16 = 0xacc5;
+/// Erased flash.
+const ERASED: u16 = 0xffff;
+
+/// Header of the CSV dump.
+pub const CSV_HEADER: &str = "record,runtime_s,event,detail\r\n";
+
+/// Flash area the records are kept in: `PAGES` pages of `PAGE_WORDS` half-words. Erased flash
+/// reads as all ones, and only erased half-words can be programmed.
+pub trait EventArea {
+    fn read(&self, page: usize, index: usize) -> u16;
+    fn program(&mut self, page: usize, index: usize, value: u16);
+    fn erase(&mut self, page: usize);
+
+    /// CRC-32 of the `words` (see `crc32`).
+    fn crc32(&self, words: &[u32]) -> u32 {
+        crc32::software(words)
+    }
+}
+
+/// What has happened.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u16)]
+pub enum Kind {
+    /// Right PIN, door opened.
+    DoorOpened = 1,
+    /// Wrong PIN (detail is the attempts left).
+    WrongPin = 2,
+    /// Keypad locked out (detail is the lockout, in seconds).
+    LockedOut = 3,
+    PinChanged = 4,
+    /// Alarm clock rang (detail is the alarm, from zero).
+    AlarmRang = 5,
+    /// Threshold alarm latched (detail is the source, see `threshold::Source`).
+    Threshold = 6,
+    /// Written by a newer firmware.
+    Unknown = ERASED,
+    /// Record did not pass the check (never written).
+    Corrupt = 0,
+}
+
+impl Kind {
+    fn from_code(code: u16) -> Kind {
+        match code {
+            1 => Kind::DoorOpened,
+            2 => Kind::WrongPin,
+            3 => Kind::LockedOut,
+            4 => Kind::PinChanged,
+            5 => Kind::AlarmRang,
+            6 => Kind::Threshold,
+            _ => Kind::Unknown,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::DoorOpened => "door opened",
+            Kind::WrongPin => "wrong PIN",
+            Kind::LockedOut => "locked out",
+            Kind::PinChanged => "PIN changed",
+            Kind::AlarmRang => "alarm",
+            Kind::Threshold => "threshold",
+            Kind::Unknown => "unknown",
+            Kind::Corrupt => "corrupt",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// Total runtime when it happened, in seconds.
+    pub runtime_s: u32,
+    pub kind: Kind,
+    pub detail: u16,
+}
+
+impl Entry {
+    /// Write the event, with its detail (`Alarm 2`, `Temp alarm`), up to 11 characters.
+    pub fn write_event<W: Write>(&self, w: &mut W) -> fmt::Result {
+        match self.kind {
+            Kind::DoorOpened => w.write_str("Door opened"),
+            Kind::WrongPin => w.write_str("Wrong PIN"),
+            Kind::LockedOut => w.write_str("Locked out"),
+            Kind::PinChanged => w.write_str("PIN changed"),
+            Kind::AlarmRang => {
+                w.write_str("Alarm ")?;
+                write_uint(w, u32::from(self.detail) + 1)
+            }
+            Kind::Threshold => {
+                w.write_str(Source::from_index(usize::from(self.detail)).map_or("?", Source::name))?;
+                w.write_str(" alarm")
+            }
+            Kind::Unknown => w.write_str("Unknown"),
+            Kind::Corrupt => w.write_str("Corrupt"),
+        }
+    }
+}
+
+/// Position of the newest record in the area.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EventLog {
+    /// Page the records go to, and its sequence number.
+    page: usize,
+    seq: u16,
+    /// Records in the page.
+    used: usize,
+    /// Records in the log.
+    count: usize,
+    /// Records which did not pass the check on mount.
+    corrupt: usize,
+}
+
+impl Default for EventLog {
+    /// Empty log, the first record starts the first page.
+    fn default() -> EventLog {
+        EventLog {
+            page: PAGES - 1,
+            seq: ERASED,
+            used: PAGE_RECORDS,
+            count: 0,
+            corrupt: 0,
+        }
+    }
+}
+
+impl EventLog {
+    /// Find the records kept in the area.
+    pub fn mount<A: EventArea>(area: &A) -> EventLog {
+        let valid = |page: usize| area.read(page, 0) == MAGIC;
+        let seq = |page: usize| area.read(page, 1);
+        // Both pages are in the log, one right after the other
+        let follows = |page: usize, next: usize| valid(page) && valid(next) && seq(next) == seq(page).wrapping_add(1);
+        let Some(page) = (0..PAGES).find(|&page| valid(page) && !follows(page, (page + 1) % PAGES)) else {
+            return EventLog::default();
+        };
+        // Kind is never erased in a programmed record
+        let used = (0..PAGE_RECORDS)
+            .take_while(|&i| area.read(page, HEADER_WORDS + RECORD_WORDS * i + 2) != ERASED)
+            .count();
+        let mut count = used;
+        let mut newer = page;
+        for _ in 1..PAGES {
+            let older = (newer + PAGES - 1) % PAGES;
+            if !follows(older, newer) {
+                break;
+            }
+            count += PAGE_RECORDS;
+            newer = older;
+        }
+        let mut log = EventLog { page, seq: seq(page), used, count, corrupt: 0 };
+        log.corrupt = (0..count)
+            .filter(|&back| log.get(area, back).is_some_and(|entry| entry.kind == Kind::Corrupt))
+            .count();
+        log
+    }
+
+    /// Records which did not pass the check on mount.
+    pub fn corrupt(&self) -> usize {
+        self.corrupt
+    }
+
+    /// Records in the log.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Add the record, erasing the next page when the current one is full. Page erase stalls the
+    /// CPU for 20-40ms.
+    pub fn append<A: EventArea>(&mut self, area: &mut A, entry: Entry) {
+        if self.used == PAGE_RECORDS {
+            // Oldest page is dropped once all of them are used (older pages are always full)
+            self.count = self.count.min((PAGES - 1) * PAGE_RECORDS);
+            self.page = (self.page + 1) % PAGES;
+            self.seq = self.seq.wrapping_add(1);
+            self.used = 0;
+            area.erase(self.page);
+            area.program(self.page, 0, MAGIC);
+            area.program(self.page, 1, self.seq);
+        }
+        let index = HEADER_WORDS + RECORD_WORDS * self.used;
+        let crc = area.crc32(&[entry.runtime_s, (entry.kind as u32) << 16 | u32::from(entry.detail)]);
+        let (low, high) = (entry.runtime_s as u16, (entry.runtime_s >> 16) as u16);
+        for (i, word) in [low, high, entry.kind as u16, entry.detail, crc as u16, (crc >> 16) as u16].into_iter().enumerate() {
+            area.program(self.page, index + i, word);
+        }
+        self.used += 1;
+        self.count += 1;
+    }
+
+    /// Record `back` records before the newest one (zero is the newest).
+    pub fn get<A: EventArea>(&self, area: &A, back: usize) -> Option<Entry> {
+        if back >= self.count {
+            return None;
+        }
+        let (mut page, mut used, mut back) = (self.page, self.used, back);
+        while back >= used {
+            back -= used;
+            page = (page + PAGES - 1) % PAGES;
+            used = PAGE_RECORDS;
+        }
+        let index = HEADER_WORDS + RECORD_WORDS * (used - 1 - back);
+        let [low, high, code, detail, crc_low, crc_high] = core::array::from_fn(|i| area.read(page, index + i));
+        let runtime_s = (u32::from(high) << 16) | u32::from(low);
+        let crc = (u32::from(crc_high) << 16) | u32::from(crc_low);
+        let kind = if crc == area.crc32(&[runtime_s, u32::from(code) << 16 | u32::from(detail)]) {
+            Kind::from_code(code)
+        } else {
+            Kind::Corrupt
+        };
+        Some(Entry { runtime_s, kind, detail })
+    }
+
+    /// Write the CSV line of the record `index` (zero is the oldest one): `12,345678,wrong PIN,2`.
+    pub fn write_csv<A: EventArea, W: Write>(&self, area: &A, w: &mut W, index: usize) -> fmt::Result {
+        let entry = self.get(area, self.count.wrapping_sub(index + 1)).ok_or(fmt::Error)?;
+        write_uint(w, index as u32)?;
+        w.write_char(',')?;
+        write_uint(w, entry.runtime_s)?;
+        w.write_char(',')?;
+        w.write_str(entry.kind.name())?;
+        w.write_char(',')?;
+        write_uint(w, u32::from(entry.detail))?;
+        w.write_str("\r\n")
+    }
+}
+
+/// What the events screen shows: one of the records, newest first, scrolled through by the
+/// encoder (up goes back in time). Updated by the main loop from the `EventLog` (see `update`).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventView {
+    /// Record shown, counted back from the newest one.
+    back: usize,
+    count: usize,
+    entry: Option<Entry>,
+}
+
+impl EventView {
+    /// Records in the log.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Read the record shown.
+    pub fn update<A: EventArea>(&mut self, log: &EventLog, area: &A) {
+        self.count = log.count();
+        self.back = self.back.min(self.count.saturating_sub(1));
+        self.entry = log.get(area, self.back);
+    }
+
+    /// Returns `true` if the event was used (the view must be updated then).
+    pub fn handle(&mut self, event: Event) -> bool {
+        match event {
+            Event::EncoderUp => self.back = (self.back + 1).min(self.count.saturating_sub(1)),
+            Event::EncoderDown => self.back = self.back.saturating_sub(1),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Write the number of the record shown (counted back) and the event (`#  1 Door opened`).
+    pub fn write_event<W: Write>(&self, w: &mut W) -> fmt::Result {
+        let Some(entry) = self.entry else {
+            return w.write_str("No events");
+        };
+        w.write_char('#')?;
+        text::uint(w, self.back as u32 + 1, 3)?;
+        w.write_char(' ')?;
+        entry.write_event(w)
+    }
+
+    /// Write the runtime of the record shown (`run 12d 03:25:17`).
+    pub fn write_time<W: Write>(&self, w: &mut W) -> fmt::Result {
+        let Some(entry) = self.entry else {
+            return Ok(());
+        };
+        w.write_str("run")?;
+        text::duration(w, entry.runtime_s, 13)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn access_log() {
+        use crate::access_log::{Entry, EventLog, EventView, Kind, PAGES, PAGE_RECORDS, PAGE_WORDS};
+        use crate::mock::MockFlash;
+        use crate::ui::Event;
+
+        let entry = |runtime_s: u32, kind: Kind, detail: u16| Entry { runtime_s, kind, detail };
+        let rows = |view: &EventView| {
+            let (mut event, mut time) = (std::string::String::new(), std::string::String::new());
+            view.write_event(&mut event).unwrap();
+            view.write_time(&mut time).unwrap();
+            (event, time)
+        };
+
+        // Never used area is empty, garbage is not taken for the records
+        let mut area = MockFlash::<PAGES, PAGE_WORDS>::new();
+        area.pages[1] = [0x1234; PAGE_WORDS];
+        let mut log = EventLog::mount(&area);
+        assert_eq!(log.count(), 0);
+        let mut view = EventView::default();
+        view.update(&log, &area);
+        assert_eq!(rows(&view), ("No events".into(), "".into()));
+
+        log.append(&mut area, entry(100_000, Kind::DoorOpened, 0));
+        log.append(&mut area, entry(100_030, Kind::WrongPin, 2));
+        log.append(&mut area, entry(100_060, Kind::AlarmRang, 1));
+        log.append(&mut area, entry(100_090, Kind::Threshold, 1));
+        assert_eq!(area.erases.iter().sum::<usize>(), 1);
+        assert_eq!(log.get(&area, 0), Some(entry(100_090, Kind::Threshold, 1)));
+        assert_eq!(log.get(&area, 3), Some(entry(100_000, Kind::DoorOpened, 0)));
+        assert_eq!(log.get(&area, 4), None);
+        // Found again after a reset
+        assert_eq!(EventLog::mount(&area), log);
+
+        // Newest first, encoder goes back in time (and stops at the oldest one)
+        view.update(&log, &area);
+        assert_eq!(rows(&view), ("#  1 Vdd alarm".into(), "run  1d 03:48:10".into()));
+        assert!(view.handle(Event::EncoderUp));
+        view.update(&log, &area);
+        assert_eq!(rows(&view).0, "#  2 Alarm 2");
+        (0..5).for_each(|_| assert!(view.handle(Event::EncoderUp)));
+        view.update(&log, &area);
+        assert_eq!(rows(&view).0, "#  4 Door opened");
+        assert!(view.handle(Event::EncoderDown));
+        view.update(&log, &area);
+        assert_eq!(rows(&view).0, "#  3 Wrong PIN");
+        assert!(!view.handle(Event::Button));
+
+        // CSV, from the oldest record
+        let mut csv = std::string::String::new();
+        (0..log.count()).for_each(|i| log.write_csv(&area, &mut csv, i).unwrap());
+        assert_eq!(
+            csv,
+            "0,100000,door opened,0\r\n1,100030,wrong PIN,2\r\n2,100060,alarm,1\r\n3,100090,threshold,1\r\n"
+        );
+
+        // Oldest page is dropped once both are used
+        for i in 4..2 * PAGE_RECORDS as u32 + 10 {
+            log.append(&mut area, entry(100_000 + 30 * i, Kind::LockedOut, 30));
+        }
+        assert_eq!(area.erases.iter().sum::<usize>(), 3);
+        assert_eq!(log.count(), PAGE_RECORDS + 10);
+        let mounted = EventLog::mount(&area);
+        assert_eq!(mounted, log);
+        let oldest = mounted.get(&area, mounted.count() - 1).unwrap();
+        assert_eq!(oldest.runtime_s, 100_000 + 30 * PAGE_RECORDS as u32);
+        // Unknown kinds (from a newer firmware) are shown as such, records which do not pass the
+        // check too
+        let runtime_s = 100_000 + 30 * (2 * PAGE_RECORDS as u32 + 9);
+        let crc = crate::crc32::software(&[runtime_s, 0x0042 << 16 | 30]);
+        area.pages[0][2 + 6 * 9 + 2] = 0x0042;
+        area.pages[0][2 + 6 * 9 + 4..2 + 6 * 9 + 6].copy_from_slice(&[crc as u16, (crc >> 16) as u16]);
+        let mut event = std::string::String::new();
+        mounted.get(&area, 0).unwrap().write_event(&mut event).unwrap();
+        assert_eq!(event, "Unknown");
+        assert_eq!(EventLog::mount(&area).corrupt(), 0);
+        area.pages[0][2 + 6 * 9 + 3] = 31;
+        let mut event = std::string::String::new();
+        mounted.get(&area, 0).unwrap().write_event(&mut event).unwrap();
+        assert_eq!(event, "Corrupt");
+        assert_eq!(EventLog::mount(&area).corrupt(), 1);
+    }
+
+    #[test]
+    #[cfg(any(feature = "shell", feature = "rtt-console"))]
+    fn events_export() {
+        use heapless::Deque;
+        use crate::access_log::{Entry, EventLog, Kind, PAGES, PAGE_RECORDS, PAGE_WORDS};
+        use crate::mock::MockFlash;
+        use crate::remote::RemoteText;
+        use crate::screens::{self, Settings, Stats};
+        use crate::shell::Shell;
+
+        let mut area = MockFlash::<PAGES, PAGE_WORDS>::new();
+        let mut log = EventLog::default();
+        for i in 0..PAGE_RECORDS as u32 + 5 {
+            log.append(&mut area, Entry { runtime_s: 1000 + i, kind: Kind::DoorOpened, detail: 0 });
+        }
+        let mut settings = Settings::default();
+        settings.events.update(&log, &area);
+        let mut ui = screens::navigation();
+        let mut remote = RemoteText::new();
+        let mut shell = Shell::new();
+        for byte in b"events\r".iter().copied() {
+            shell.receive(byte, &mut settings, &mut ui, &Stats::default(), &mut remote);
+        }
+        // Dump does not fit into the output at once, the prompt follows it
+        let mut out: Deque<u8, 64> = Deque::new();
+        let mut output = std::vec::Vec::new();
+        loop {
+            shell.export(&log, &area);
+            shell.poll(&mut out);
+            if out.is_empty() {
+                break;
+            }
+            output.extend(core::iter::from_fn(|| out.pop_front()));
+        }
+        let output = std::string::String::from_utf8(output).unwrap();
+        let lines: std::vec::Vec<&str> = output.split("\r\n").collect();
+        assert_eq!(lines.len(), PAGE_RECORDS + 8);
+        assert_eq!(lines[..3], ["events", "record,runtime_s,event,detail", "0,1000,door opened,0"]);
+        assert_eq!(lines[PAGE_RECORDS + 6..], [&*std::format!("{},{},door opened,0", PAGE_RECORDS + 4, PAGE_RECORDS + 1004), "> "]);
+    }
+}
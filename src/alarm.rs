@@ -0,0 +1,397 @@
+//! Alarm clock: day of the week and time, kept from the millisecond timer, and `ALARMS` alarms,
+//! each ringing on its own days of the week.
+//!
+//! There is no battery-backed clock, so time starts at Monday 00:00 after every reset and has to
+//! be set again. Alarms are kept in the backup registers (see `to_backup`), so they survive
+//! resets.
+//!
+//! When an alarm rings, button snoozes it for `SNOOZE_MS` and encoder turns it off, on any screen
+//! (see `handle_ringing`). Otherwise it rings for `RING_MS`.
+//!
+//! On the alarm clock screen, button goes through the alarms and the clock, and the encoder edits
+//! the one shown, the way the inspector address is edited: turning it starts editing the first
+//! field, button moves to the next one, and after the last one editing is done. Alarm fields are
+//! the hour, the minute and the days (each day is toggled by the encoder, alarm without days is
+//! off); clock fields are the day, the hour and the minute.
+//!
+//! Like the stopwatch, time is passed to `advance`, which must be called before every event and
+//! every render (and often enough for the beeps, see `buzzing`).
+
+use core::fmt::{self, Write};
+use crate::text;
+use crate::ui::Event;
+
+/// Number of alarms.
+pub const ALARMS: usize = 3;
+/// Snoozed alarm rings again after this long, in milliseconds.
+pub const SNOOZE_MS: u32 = 5 * 60_000;
+/// Alarm goes silent by itself after this long, in milliseconds.
+pub const RING_MS: u32 = 60_000;
+
+const MINUTE_MS: u32 = 60_000;
+const DAY_MS: u32 = 24 * 60 * MINUTE_MS;
+const WEEK_MS: u32 = 7 * DAY_MS;
+
+const DAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const DAY_LETTERS: [char; 7] = ['M', 'T', 'W', 'T', 'F', 'S', 'S'];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Alarm {
+    pub hour: u8,
+    pub minute: u8,
+    /// Days of the week the alarm rings on, bit 0 is Monday. Alarm without days is off.
+    pub days: u8,
+}
+
+impl Default for Alarm {
+    /// 07:00, off.
+    fn default() -> Alarm {
+        Alarm { hour: 7, minute: 0, days: 0 }
+    }
+}
+
+impl Alarm {
+    /// Minute of the day the alarm rings at.
+    fn minute_of_day(&self) -> u32 {
+        u32::from(self.hour) * 60 + u32::from(self.minute)
+    }
+}
+
+/// Field being edited.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Field {
+    Day,
+    Hour,
+    Minute,
+    /// Day of the alarm, 0 is Monday.
+    AlarmDay(u8),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AlarmClock {
+    /// Time since Monday 00:00, in milliseconds.
+    week_ms: u32,
+    /// Timer value of the last `advance`.
+    last_ms: u32,
+    alarms: [Alarm; ALARMS],
+    /// Alarm ringing and how long it rings for.
+    ringing: Option<(usize, u32)>,
+    /// Alarm snoozed and the time left until it rings again.
+    snoozed: Option<(usize, u32)>,
+    /// Page shown in the second row: alarms, then the clock.
+    page: usize,
+    cursor: Option<Field>,
+}
+
+impl Default for AlarmClock {
+    fn default() -> AlarmClock {
+        AlarmClock {
+            week_ms: 0,
+            last_ms: 0,
+            alarms: [Alarm::default(); ALARMS],
+            ringing: None,
+            snoozed: None,
+            page: 0,
+            cursor: None,
+        }
+    }
+}
+
+impl AlarmClock {
+    /// Account the time passed since the last call (`now_ms` is the millisecond timer, which can
+    /// wrap around) and ring the alarms which are due.
+    pub fn advance(&mut self, now_ms: u32) {
+        let passed = now_ms.wrapping_sub(self.last_ms);
+        self.last_ms = now_ms;
+        let before = self.week_ms;
+        self.week_ms = (before + passed % WEEK_MS) % WEEK_MS;
+
+        if let Some((alarm, ringing_ms)) = self.ringing {
+            self.ringing = (ringing_ms.saturating_add(passed) < RING_MS).then_some((alarm, ringing_ms + passed));
+        }
+        if let Some((alarm, left_ms)) = self.snoozed {
+            if left_ms > passed {
+                self.snoozed = Some((alarm, left_ms - passed));
+            } else {
+                self.snoozed = None;
+                self.ringing = Some((alarm, passed - left_ms));
+            }
+        }
+        for (i, alarm) in self.alarms.iter().enumerate() {
+            for day in (0..7).filter(|day| alarm.days & (1 << day) != 0) {
+                let due_ms = day * DAY_MS + alarm.minute_of_day() * MINUTE_MS;
+                // Time from the previous call till the alarm, alarm rings if it is within the time passed
+                let until_ms = (due_ms + WEEK_MS - before) % WEEK_MS;
+                if until_ms > 0 && until_ms <= passed {
+                    self.ringing = Some((i, passed - until_ms));
+                    self.snoozed = None;
+                }
+            }
+        }
+    }
+
+    /// Should the buzzer be on now? Alarm beeps twice a second.
+    pub fn buzzing(&self) -> bool {
+        match self.ringing {
+            Some((_, ringing_ms)) => matches!(ringing_ms % 1000, 0..=99 | 200..=299),
+            None => false,
+        }
+    }
+
+    pub fn is_ringing(&self) -> bool {
+        self.ringing.is_some()
+    }
+
+    /// Alarm ringing (from zero).
+    pub fn ringing(&self) -> Option<usize> {
+        self.ringing.map(|(alarm, _)| alarm)
+    }
+
+    pub fn alarms(&self) -> &[Alarm; ALARMS] {
+        &self.alarms
+    }
+
+    /// Minute of the day on the clock.
+    pub fn minute_of_day(&self) -> u32 {
+        self.week_ms % DAY_MS / MINUTE_MS
+    }
+
+    /// Alarms, as kept in the backup registers (see `scratchpad::ALARM`): the minute of the day
+    /// and the days from bit 11 (18 bits each).
+    pub fn to_backup(&self) -> [u32; ALARMS] {
+        self.alarms.map(|alarm| alarm.minute_of_day() | u32::from(alarm.days) << 11)
+    }
+
+    /// Restore alarms saved by `to_backup`. Invalid alarms are reset to the default.
+    pub fn set_backup(&mut self, saved: [u32; ALARMS]) {
+        for (alarm, saved) in self.alarms.iter_mut().zip(saved) {
+            let (minute_of_day, days) = (saved & 0x7ff, saved >> 11);
+            *alarm = if minute_of_day < 24 * 60 && days < 1 << 7 {
+                Alarm { hour: (minute_of_day / 60) as u8, minute: (minute_of_day % 60) as u8, days: days as u8 }
+            } else {
+                Alarm::default()
+            };
+        }
+    }
+
+    /// Snooze (button) or turn off (encoder) the ringing alarm, on any screen. Returns `true` if
+    /// the event was used.
+    pub fn handle_ringing(&mut self, event: Event) -> bool {
+        let Some((alarm, _)) = self.ringing else {
+            return false;
+        };
+        match event {
+            Event::Button => self.snoozed = Some((alarm, SNOOZE_MS)),
+            Event::EncoderUp | Event::EncoderDown => {}
+            _ => return false,
+        }
+        self.ringing = None;
+        true
+    }
+
+    /// Edit the alarms and the clock. Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        let step: i32 = match (event, self.cursor) {
+            (Event::Button, Some(field)) => {
+                self.cursor = match field {
+                    Field::Day => Some(Field::Hour),
+                    Field::Hour => Some(Field::Minute),
+                    Field::Minute if self.page < ALARMS => Some(Field::AlarmDay(0)),
+                    Field::AlarmDay(day) if day < 6 => Some(Field::AlarmDay(day + 1)),
+                    Field::Minute | Field::AlarmDay(_) => None,
+                };
+                return true;
+            }
+            (Event::Button, None) if self.page < ALARMS => {
+                self.page += 1;
+                return true;
+            }
+            (Event::Button, None) => {
+                self.page = 0;
+                return false;
+            }
+            (Event::EncoderUp, _) => 1,
+            (Event::EncoderDown, _) => -1,
+            _ => return false,
+        };
+        let first = if self.page < ALARMS { Field::Hour } else { Field::Day };
+        let field = *self.cursor.get_or_insert(first);
+        if let Some(alarm) = self.alarms.get_mut(self.page) {
+            match field {
+                Field::Hour => alarm.hour = (i32::from(alarm.hour) + step).rem_euclid(24) as u8,
+                Field::Minute => alarm.minute = (i32::from(alarm.minute) + step).rem_euclid(60) as u8,
+                Field::AlarmDay(day) => alarm.days ^= 1 << day,
+                Field::Day => {}
+            }
+            return true;
+        }
+        let (unit_ms, within_ms) = match field {
+            Field::Day => (DAY_MS, WEEK_MS),
+            Field::Hour => (60 * MINUTE_MS, DAY_MS),
+            // Seconds start over when the minute is set
+            _ => {
+                self.week_ms -= self.week_ms % MINUTE_MS;
+                (MINUTE_MS, 60 * MINUTE_MS)
+            }
+        };
+        // Change one unit within the larger one (hours wrap around within the day, etc.)
+        let start_ms = self.week_ms - self.week_ms % within_ms;
+        let offset_ms = (self.week_ms % within_ms) as i64 + i64::from(step) * i64::from(unit_ms);
+        self.week_ms = start_ms + offset_ms.rem_euclid(i64::from(within_ms)) as u32;
+        true
+    }
+
+    /// Write day of the week and time (`Mon 07:29:13`), with the clock field being edited in
+    /// brackets (`Mon [07]:29:13`). Snoozed alarm is marked with `z`.
+    pub fn write_time<W: Write>(&self, w: &mut W) -> fmt::Result {
+        let seconds = self.week_ms / 1000;
+        let edited = |field: Field| self.page == ALARMS && self.cursor == Some(field);
+        write_field(w, DAY_NAMES[(seconds / 86_400) as usize].chars(), edited(Field::Day))?;
+        w.write_char(' ')?;
+        write_field(w, two(seconds / 3600 % 24), edited(Field::Hour))?;
+        w.write_char(':')?;
+        write_field(w, two(seconds / 60 % 60), edited(Field::Minute))?;
+        w.write_char(':')?;
+        write_field(w, two(seconds % 60), false)?;
+        if self.snoozed.is_some() {
+            w.write_str(" z")?;
+        }
+        Ok(())
+    }
+
+    /// Write the alarm ringing (`Alarm 1   07:30`) or the page shown: alarm with its days
+    /// (`1 07:30 MTWTF--`, `1 07:30 off`) or the clock (`Set clock`). While editing, the alarm
+    /// number is left out and the field being edited is in brackets (`07:30 M[T]WTF--`).
+    pub fn write_page<W: Write>(&self, w: &mut W) -> fmt::Result {
+        if let Some((alarm, _)) = self.ringing {
+            let alarm_number = alarm as u32 + 1;
+            w.write_str("Alarm")?;
+            text::uint(w, alarm_number, 2)?;
+            let alarm = self.alarms[alarm];
+            w.write_str("    ")?;
+            write_field(w, two(u32::from(alarm.hour)), false)?;
+            w.write_char(':')?;
+            return write_field(w, two(u32::from(alarm.minute)), false);
+        }
+        let Some(alarm) = self.alarms.get(self.page) else {
+            return w.write_str("Set clock");
+        };
+        let edited = |field: Field| self.cursor == Some(field);
+        if self.cursor.is_none() {
+            text::uint(w, self.page as u32 + 1, 1)?;
+            w.write_char(' ')?;
+        }
+        write_field(w, two(u32::from(alarm.hour)), edited(Field::Hour))?;
+        w.write_char(':')?;
+        write_field(w, two(u32::from(alarm.minute)), edited(Field::Minute))?;
+        w.write_char(' ')?;
+        if alarm.days == 0 && self.cursor.is_none() {
+            return w.write_str("off");
+        }
+        for (day, &letter) in DAY_LETTERS.iter().enumerate() {
+            let letter = if alarm.days & (1 << day) != 0 { letter } else { '-' };
+            write_field(w, [letter], edited(Field::AlarmDay(day as u8)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Two-digit number.
+fn two(value: u32) -> [char; 2] {
+    [(b'0' + (value / 10 % 10) as u8) as char, (b'0' + (value % 10) as u8) as char]
+}
+
+/// Write a field, in brackets if it is being edited.
+fn write_field<W: Write, I: IntoIterator<Item = char>>(w: &mut W, chars: I, edited: bool) -> fmt::Result {
+    if edited {
+        w.write_char('[')?;
+    }
+    for c in chars {
+        w.write_char(c)?;
+    }
+    if edited {
+        w.write_char(']')?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn alarm_clock() {
+        use crate::alarm::{AlarmClock, RING_MS, SNOOZE_MS};
+        use crate::mock;
+        use crate::screens::{Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        const DAY_MS: u32 = 86_400_000;
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::Alarm, &Stats::default(), settings);
+        let edit = |settings: &mut Settings, events: &[Event]| {
+            for &event in events {
+                assert!(settings.handle(Screen::Alarm, event));
+            }
+        };
+        assert_eq!(render(&settings), ("Mon 00:00:00    ".into(), "1 07:00 off     ".into()));
+
+        // Alarm at 06:30 on Mondays and Wednesdays
+        edit(&mut settings, &[Event::EncoderDown]);
+        assert_eq!(render(&settings).1, "[06]:00 ------- ");
+        edit(&mut settings, &[Event::Button]);
+        edit(&mut settings, &[Event::EncoderUp; 30]);
+        assert_eq!(render(&settings).1, "06:[30] ------- ");
+        edit(&mut settings, &[Event::Button, Event::EncoderUp]);
+        assert_eq!(render(&settings).1, "06:30 [M]------ ");
+        edit(&mut settings, &[Event::Button, Event::Button, Event::EncoderDown]);
+        assert_eq!(render(&settings).1, "06:30 M-[W]---- ");
+        edit(&mut settings, &[Event::Button; 5]);
+        assert_eq!(render(&settings).1, "1 06:30 M-W---- ");
+        let saved = settings.alarm_clock.to_backup();
+        assert_eq!(saved, [390 | 0b101 << 11, 420, 420]);
+
+        settings.advance(6 * 3_600_000 + 30 * 60_000 - 100);
+        assert!(!settings.alarm_clock.is_ringing());
+        settings.advance(6 * 3_600_000 + 30 * 60_000 + 50);
+        assert!(settings.alarm_clock.buzzing());
+        assert_eq!(render(&settings), ("Mon 06:30:00    ".into(), "Alarm 1    06:30".into()));
+
+        // Button snoozes the alarm on any screen, encoder turns it off
+        assert!(settings.handle(Screen::Hello, Event::Button));
+        assert!(!settings.alarm_clock.is_ringing());
+        assert_eq!(render(&settings).0, "Mon 06:30:00 z  ");
+        settings.advance(6 * 3_600_000 + 30 * 60_000 + 50 + SNOOZE_MS);
+        assert!(settings.alarm_clock.is_ringing());
+        assert!(settings.handle(Screen::Hello, Event::EncoderUp));
+        assert!(!settings.alarm_clock.is_ringing());
+        assert!(!settings.handle(Screen::Hello, Event::Button));
+
+        // Alarm does not ring on Tuesday, rings on Wednesday and goes silent by itself
+        let now_ms = 6 * 3_600_000 + 30 * 60_000 + 50;
+        settings.advance(now_ms + DAY_MS);
+        assert!(!settings.alarm_clock.is_ringing());
+        settings.advance(now_ms + 2 * DAY_MS);
+        assert!(settings.alarm_clock.is_ringing());
+        settings.advance(now_ms + 2 * DAY_MS + RING_MS);
+        assert!(!settings.alarm_clock.is_ringing());
+
+        // Button goes to the clock (after the alarms), then leaves the screen
+        edit(&mut settings, &[Event::Button; 3]);
+        assert_eq!(render(&settings), ("Wed 06:31:00    ".into(), "Set clock       ".into()));
+        edit(&mut settings, &[Event::EncoderUp]);
+        assert_eq!(render(&settings).0, "[Thu] 06:31:00  ");
+        edit(&mut settings, &[Event::Button]);
+        edit(&mut settings, &[Event::EncoderDown; 7]);
+        assert_eq!(render(&settings).0, "Thu [23]:31:00  ");
+        edit(&mut settings, &[Event::Button, Event::EncoderUp, Event::Button]);
+        assert_eq!(render(&settings).0, "Thu 23:32:00    ");
+        assert!(!settings.handle(Screen::Alarm, Event::Button));
+        assert_eq!(render(&settings).1, "1 06:30 M-W---- ");
+
+        // Alarms are restored from the backup registers, garbage gives the defaults
+        let mut restored = AlarmClock::default();
+        restored.set_backup(saved);
+        assert_eq!(restored.alarms(), settings.alarm_clock.alarms());
+        restored.set_backup([u32::MAX; 3]);
+        assert_eq!(restored, AlarmClock::default());
+    }
+}
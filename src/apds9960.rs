@@ -0,0 +1,255 @@
+//! APDS-9960 gesture sensor on I2C (see `bus::I2c`), for paging through the screens with a swipe
+//! of the hand over it.
+//!
+//! Gesture engine of the sensor starts once the proximity gets over `GPENTH` (hand is close) and
+//! fills its FIFO with the counts of the four directional photodiodes (up, down, left, right)
+//! until the proximity drops below `GEXTH`. `Apds9960::poll` drains the FIFO and, once the engine
+//! has stopped, the `Decoder` compares the datasets at the start and at the end of the gesture:
+//! hand crossing the sensor shades one photodiode of the pair first and the other one last. Which
+//! way is "left" depends on the way the sensor is mounted; this is the way of the breakout boards
+//! (the IR LED up).
+//!
+//! Sensor is (re-)initialized on the first poll and after any bus error, so it can be connected
+//! at any time.
+
+use crate::bus::I2c;
+
+/// 7-bit address.
+pub const ADDRESS: u8 = 0x39;
+
+/// Registers.
+const ENABLE: u8 = 0x80;
+const CONTROL: u8 = 0x8f;
+const ID: u8 = 0x92;
+const GPENTH: u8 = 0xa0;
+const GEXTH: u8 = 0xa1;
+const GCONF1: u8 = 0xa2;
+const GCONF2: u8 = 0xa3;
+const GPULSE: u8 = 0xa6;
+const GCONF4: u8 = 0xab;
+const GFLVL: u8 = 0xae;
+const GFIFO_U: u8 = 0xfc;
+
+/// Device IDs (APDS-9960 and the clones).
+const IDS: [u8; 3] = [0xab, 0xa8, 0x9c];
+/// Power on, proximity and gesture engines enabled.
+const ENABLE_GESTURE: u8 = 0x45;
+/// Gesture mode bit of `GCONF4`, cleared by the sensor when the gesture ends.
+const GMODE: u8 = 0x01;
+/// Datasets of the gesture FIFO.
+const FIFO_DATASETS: usize = 32;
+
+/// Dataset counts a photodiode must be over to be taken into account (noise and far hands are
+/// below).
+const THRESHOLD: u8 = 10;
+/// Change of the ratio between the photodiodes of a pair (in percents of their sum) needed for
+/// a swipe.
+const SENSITIVITY: i32 = 50;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Gesture {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Direction of the gesture by its first and last datasets with all the counts over `THRESHOLD`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Decoder {
+    first: Option<[u8; 4]>,
+    last: [u8; 4],
+}
+
+impl Decoder {
+    /// Account the dataset (up, down, left and right counts).
+    pub fn push(&mut self, dataset: [u8; 4]) {
+        if dataset.iter().all(|&count| count > THRESHOLD) {
+            self.first.get_or_insert(dataset);
+            self.last = dataset;
+        }
+    }
+
+    /// End of the gesture: its direction, if it was a swipe. Decoder starts over.
+    pub fn finish(&mut self) -> Option<Gesture> {
+        let first = self.first.take()?;
+        let last = self.last;
+        // Balance of the pair, in percents of the sum (both are over the threshold)
+        let ratio = |[a, b]: [u8; 2]| (i32::from(a) - i32::from(b)) * 100 / (i32::from(a) + i32::from(b));
+        let up_down = ratio([last[0], last[1]]) - ratio([first[0], first[1]]);
+        let left_right = ratio([last[2], last[3]]) - ratio([first[2], first[3]]);
+        if up_down.abs().max(left_right.abs()) < SENSITIVITY {
+            return None;
+        }
+        Some(match (left_right.abs() >= up_down.abs(), left_right > 0, up_down > 0) {
+            (true, true, _) => Gesture::Right,
+            (true, false, _) => Gesture::Left,
+            (false, _, true) => Gesture::Down,
+            (false, _, false) => Gesture::Up,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Apds9960 {
+    /// Is the sensor found and configured?
+    ready: bool,
+    /// Has the gesture engine filled the FIFO since the last gesture?
+    active: bool,
+    decoder: Decoder,
+}
+
+impl Apds9960 {
+    /// Check the ID and configure the gesture engine. Returns `false` if there is no sensor.
+    fn init<B: I2c>(bus: &mut B) -> bool {
+        let mut id = [0];
+        if bus.write_read(ADDRESS, &[ID], &mut id).is_err() || !IDS.contains(&id[0]) {
+            return false;
+        }
+        [
+            // Everything off while configuring
+            (ENABLE, 0),
+            // 100mA LED drive, 4x proximity gain
+            (CONTROL, 0x08),
+            // Engine enters at proximity 40, exits below 30
+            (GPENTH, 40),
+            (GEXTH, 30),
+            // FIFO interrupt after 4 datasets (only polled), exit after the first low dataset
+            (GCONF1, 0x40),
+            // 4x gain, 100mA LED drive, 2.8ms between datasets
+            (GCONF2, 0x41),
+            // Ten 32us pulses
+            (GPULSE, 0xc9),
+            (GCONF4, 0),
+            (ENABLE, ENABLE_GESTURE),
+        ]
+        .into_iter()
+        .all(|(register, value)| bus.write(ADDRESS, &[register, value]).is_ok())
+    }
+
+    /// Drain the gesture FIFO. Returns the gesture once it has ended, `None` until then (or if
+    /// there is no sensor).
+    pub fn poll<B: I2c>(&mut self, bus: &mut B) -> Option<Gesture> {
+        if !self.ready {
+            self.ready = Apds9960::init(bus);
+            self.active = false;
+            self.decoder = Decoder::default();
+            return None;
+        }
+        match self.read_fifo(bus) {
+            Ok(true) => None,
+            Ok(false) => {
+                let gesture = if self.active { self.decoder.finish() } else { None };
+                self.active = false;
+                gesture
+            }
+            Err(_) => {
+                self.ready = false;
+                None
+            }
+        }
+    }
+
+    /// Feed the datasets in the FIFO to the decoder. Returns `true` while the gesture goes on.
+    fn read_fifo<B: I2c>(&mut self, bus: &mut B) -> Result<bool, B::Error> {
+        let mut level = [0];
+        bus.write_read(ADDRESS, &[GFLVL], &mut level)?;
+        let datasets = usize::from(level[0]).min(FIFO_DATASETS);
+        if datasets > 0 {
+            // FIFO is read in a burst, the register address wraps around the four photodiodes
+            let mut buffer = [0; 4 * FIFO_DATASETS];
+            let buffer = &mut buffer[..4 * datasets];
+            bus.write_read(ADDRESS, &[GFIFO_U], buffer)?;
+            for dataset in buffer.chunks_exact(4) {
+                self.decoder.push([dataset[0], dataset[1], dataset[2], dataset[3]]);
+            }
+            self.active = true;
+            return Ok(true);
+        }
+        let mut gconf4 = [0];
+        bus.write_read(ADDRESS, &[GCONF4], &mut gconf4)?;
+        Ok(gconf4[0] & GMODE != 0)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn apds9960_gestures() {
+        use std::vec::Vec;
+        use crate::apds9960::{Apds9960, Gesture, ADDRESS};
+        use crate::mock::{MockDevice, MockI2c};
+
+        /// Sensor registers: the gesture FIFO (drained four datasets per poll) and whether the
+        /// gesture engine is running. Register writes are recorded.
+        #[derive(Default)]
+        struct Sensor {
+            writes: Vec<[u8; 2]>,
+            fifo: Vec<[u8; 4]>,
+            engine: bool,
+        }
+
+        impl MockDevice for Sensor {
+            fn write(&mut self, bytes: &[u8]) -> bool {
+                self.writes.push([bytes[0], bytes[1]]);
+                true
+            }
+
+            fn read(&mut self, bytes: &[u8], buffer: &mut [u8]) -> bool {
+                match bytes[0] {
+                    0x92 => buffer[0] = 0xab,
+                    0xae => buffer[0] = self.fifo.len().min(4) as u8,
+                    0xab => buffer[0] = u8::from(self.engine),
+                    0xfc => {
+                        for chunk in buffer.chunks_exact_mut(4) {
+                            chunk.copy_from_slice(&self.fifo.remove(0));
+                        }
+                    }
+                    _ => return false,
+                }
+                true
+            }
+        }
+
+        let mut sensor = MockI2c::new(&[], Sensor::default());
+        let mut apds = Apds9960::default();
+        let swipe = |apds: &mut Apds9960, sensor: &mut MockI2c<Sensor>, fifo: &[[u8; 4]]| {
+            sensor.device.fifo.extend_from_slice(fifo);
+            sensor.device.engine = true;
+            let mut gestures = Vec::new();
+            for _ in 0..4 {
+                gestures.extend(apds.poll(sensor));
+            }
+            sensor.device.engine = false;
+            gestures.extend(apds.poll(sensor));
+            gestures
+        };
+
+        // No sensor yet
+        assert_eq!(apds.poll(&mut sensor), None);
+        sensor.present = vec![ADDRESS];
+        assert_eq!(apds.poll(&mut sensor), None);
+        assert_eq!(sensor.device.writes.first(), Some(&[0x80, 0]));
+        assert_eq!(sensor.device.writes.last(), Some(&[0x80, 0x45]));
+
+        // Lens mirrors the hand: one going right reflects to the right photodiode first and to the
+        // left one last; the far datasets at both ends are ignored
+        let right = [[5, 5, 5, 5], [60, 60, 20, 90], [80, 80, 80, 80], [60, 60, 90, 20], [8, 8, 9, 2]];
+        assert_eq!(swipe(&mut apds, &mut sensor, &right), [Gesture::Right]);
+        let left = [[60, 60, 90, 20], [80, 80, 80, 80], [60, 60, 20, 90]];
+        assert_eq!(swipe(&mut apds, &mut sensor, &left), [Gesture::Left]);
+        let down = [[20, 90, 60, 60], [90, 20, 60, 60]];
+        assert_eq!(swipe(&mut apds, &mut sensor, &down), [Gesture::Down]);
+        // Hand coming and going straight is no swipe
+        let hover = [[40, 40, 40, 40], [90, 85, 88, 92], [40, 42, 41, 40]];
+        assert!(swipe(&mut apds, &mut sensor, &hover).is_empty());
+
+        // Sensor is configured again once it is back
+        sensor.present.clear();
+        assert!(swipe(&mut apds, &mut sensor, &[]).is_empty());
+        sensor.present = vec![ADDRESS];
+        sensor.device.writes.clear();
+        assert_eq!(swipe(&mut apds, &mut sensor, &right), [Gesture::Right]);
+        assert_eq!(sensor.device.writes.last(), Some(&[0x80, 0x45]));
+    }
+}
@@ -0,0 +1,199 @@
+//! Backlight brightness following the ambient light: light dependent resistor in a divider is read
+//! by the ADC (see `sensor`), the backlight LED is driven by the PWM (see `backlight_pwm`).
+//!
+//! Readings are smoothed (lamps flicker, shadows pass by), and the brightness goes from `MIN_DUTY`
+//! in the dark to full in bright light. Brightness only follows the ambient light once it has
+//! changed by `HYSTERESIS` (so the backlight does not hunt around a level in the dusk), and then
+//! ramps by `RAMP` per update instead of jumping. Manual brightness set on the settings screen
+//! (or by the day and night profiles, see `schedule`) overrides the ambient light.
+
+use core::fmt::{self, Write};
+use crate::text::{self, Align};
+
+/// Full scale of the 12-bit ADC (the brightest light).
+const FULL_SCALE: u32 = 4095;
+/// Backlight duty in the dark, in percents.
+const MIN_DUTY: u8 = 10;
+/// Change of the ambient light the brightness follows, in percents of the full scale.
+const HYSTERESIS: u8 = 8;
+/// Largest change of the duty per update, in percents.
+const RAMP: u8 = 5;
+/// Manual brightness step, in percents.
+const MANUAL_STEP: u8 = 10;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Backlight {
+    /// Smoothed ambient light, in ADC counts, `None` until read.
+    ambient: Option<u32>,
+    /// Ambient light the brightness follows, in percents of the full scale.
+    settled: u8,
+    /// Backlight duty, in percents.
+    duty: u8,
+    /// Manual brightness, in percents, `None` if it follows the ambient light.
+    manual: Option<u8>,
+}
+
+impl Default for Backlight {
+    /// Full brightness until the ambient light is read.
+    fn default() -> Backlight {
+        Backlight {
+            ambient: None,
+            settled: 100,
+            duty: 100,
+            manual: None,
+        }
+    }
+}
+
+impl Backlight {
+    /// Account the ambient light reading (ADC counts, higher is brighter) and move the duty
+    /// towards the brightness for it.
+    pub fn update(&mut self, reading: u16) {
+        let reading = u32::from(reading).min(FULL_SCALE);
+        // Exponential average over about four readings
+        let ambient = match self.ambient {
+            Some(ambient) => (3 * ambient + reading + 2) / 4,
+            None => reading,
+        };
+        let percent = (ambient * 100 / FULL_SCALE) as u8;
+        if self.ambient.is_none() || percent.abs_diff(self.settled) >= HYSTERESIS || percent == 0 || percent == 100 {
+            self.settled = percent;
+        }
+        self.ambient = Some(ambient);
+        let target = self.target();
+        self.duty = if target > self.duty {
+            target.min(self.duty + RAMP)
+        } else {
+            target.max(self.duty.saturating_sub(RAMP))
+        };
+    }
+
+    /// Brightness for the settled ambient light (or the manual one), in percents.
+    fn target(&self) -> u8 {
+        self.manual.unwrap_or(MIN_DUTY + (u16::from(self.settled) * u16::from(100 - MIN_DUTY) / 100) as u8)
+    }
+
+    /// Ambient light, in percents of the full scale, `None` until read.
+    pub fn ambient(&self) -> Option<u8> {
+        self.ambient.map(|ambient| (ambient * 100 / FULL_SCALE) as u8)
+    }
+
+    /// Backlight duty, in percents.
+    pub fn duty(&self) -> u8 {
+        self.duty
+    }
+
+    /// Manual brightness, in percents, `None` if it follows the ambient light.
+    pub fn manual(&self) -> Option<u8> {
+        self.manual
+    }
+
+    /// Set the manual brightness, in percents, `None` to follow the ambient light.
+    pub fn set_manual(&mut self, manual: Option<u8>) {
+        self.manual = manual.map(|percent| percent.min(100));
+    }
+
+    /// Change the brightness by `step` steps of `MANUAL_STEP`; below the lowest one it follows the
+    /// ambient light again.
+    pub fn adjust(&mut self, step: i8) {
+        self.manual = adjust_manual(self.manual, step);
+    }
+
+    /// Write the brightness preference (`Backlight  auto` or `Backlight   60%`).
+    pub fn write_preference<W: Write>(&self, w: &mut W) -> fmt::Result {
+        text::str(w, "Backlight", 11, Align::Left)?;
+        write_manual(w, self.manual)
+    }
+}
+
+/// Manual brightness `manual` changed by `step` steps of `MANUAL_STEP` (see `Backlight::adjust`).
+pub fn adjust_manual(manual: Option<u8>, step: i8) -> Option<u8> {
+    let max = i16::from(100 / MANUAL_STEP);
+    let level = (i16::from(manual.unwrap_or(0) / MANUAL_STEP) + i16::from(step)).clamp(0, max);
+    (level > 0).then_some(level as u8 * MANUAL_STEP)
+}
+
+/// Write manual brightness (` 60%`, or `auto` if it follows the ambient light).
+pub fn write_manual<W: Write>(w: &mut W, manual: Option<u8>) -> fmt::Result {
+    match manual {
+        Some(percent) => {
+            text::uint(w, u32::from(percent), 3)?;
+            w.write_str("%")
+        }
+        None => w.write_str("auto"),
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn backlight_follows_ambient_light() {
+        use crate::backlight::Backlight;
+        use crate::mock;
+        use crate::screens::{Preference, Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        // Full brightness until read, then ramps to the level for the light
+        let mut backlight = Backlight::default();
+        assert_eq!((backlight.ambient(), backlight.duty()), (None, 100));
+        backlight.update(0);
+        assert_eq!((backlight.ambient(), backlight.duty()), (Some(0), 95));
+        for _ in 0..20 {
+            backlight.update(0);
+        }
+        assert_eq!(backlight.duty(), 10);
+
+        // Readings are smoothed, small changes are ignored
+        backlight.update(4095);
+        assert_eq!(backlight.ambient(), Some(25));
+        assert_eq!(backlight.duty(), 15);
+        for _ in 0..20 {
+            backlight.update(2048);
+        }
+        assert_eq!(backlight.ambient(), Some(49));
+        assert_eq!(backlight.duty(), 49);
+        for _ in 0..20 {
+            backlight.update(1900);
+        }
+        assert_eq!(backlight.ambient(), Some(46));
+        assert_eq!(backlight.duty(), 49);
+        for _ in 0..20 {
+            backlight.update(2500);
+        }
+        assert_eq!(backlight.ambient(), Some(61));
+        assert_eq!(backlight.duty(), 64);
+
+        // Manual brightness overrides the light, below the lowest one it follows it again
+        let mut settings = Settings { backlight, ..Settings::default() };
+        let render = |settings: &Settings| mock::render(Screen::Settings, &Stats::default(), settings).1;
+        while settings.preference != Preference::Backlight {
+            assert!(settings.handle(Screen::Settings, Event::Button));
+        }
+        assert_eq!(render(&settings), "Backlight  auto ");
+        assert!(settings.handle(Screen::Settings, Event::EncoderUp));
+        assert!(settings.handle(Screen::Settings, Event::EncoderUp));
+        assert_eq!(render(&settings), "Backlight   20% ");
+        settings.backlight.update(2500);
+        assert_eq!(settings.backlight.duty(), 59);
+        for _ in 0..20 {
+            settings.backlight.update(2500);
+        }
+        assert_eq!(settings.backlight.duty(), 20);
+        for _ in 0..12 {
+            settings.handle(Screen::Settings, Event::EncoderUp);
+        }
+        assert_eq!(settings.backlight.manual(), Some(100));
+        for _ in 0..11 {
+            settings.handle(Screen::Settings, Event::EncoderDown);
+        }
+        assert_eq!(settings.backlight.manual(), None);
+        assert_eq!(render(&settings), "Backlight  auto ");
+        #[cfg(feature = "day-night")]
+        for _ in 0..6 {
+            assert!(settings.handle(Screen::Settings, Event::Button));
+        }
+        #[cfg(feature = "battery")]
+        assert!(settings.handle(Screen::Settings, Event::Button));
+        assert!(!settings.handle(Screen::Settings, Event::Button));
+    }
+}
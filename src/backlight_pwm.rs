@@ -0,0 +1,75 @@
+//! Backlight PWM on TIM1: PA8 (channel 1) drives the backlight LED of the display through a
+//! transistor (the LED takes more current than a pin gives). Used by the adaptive backlight.
+//!
+//! With the `day-night` feature, PA11 (channel 4) sets the contrast: filtered by an RC network
+//! (10K and 10uF), it replaces the contrast potentiometer on V0. Lower voltage is more contrast,
+//! and `MAX_V0_DUTY` (about 1.3V) is already too light for most displays. PA11 is USB D-, so the
+//! USB cable is to be left unplugged.
+
+use stm32f1::stm32f103::{RCC, TIM1};
+use crate::board::PortName;
+use crate::clock::Clocks;
+use crate::gpio::{self, GPIOExtras};
+
+#[cfg(feature = "modbus")]
+compile_error!("`backlight` and `modbus` features both use PA8");
+
+const PWM: usize = 8;
+#[cfg(feature = "day-night")]
+const CONTRAST: usize = 11;
+/// Duty at zero contrast, in percent.
+#[cfg(feature = "day-night")]
+const MAX_V0_DUTY: u32 = 40;
+/// Timer tick, in Hz.
+const TICK_FREQ: u32 = 1_000_000;
+/// PWM frequency, fast enough not to flicker (and too slow to whine).
+const PWM_FREQ: u32 = 1_000;
+
+pub struct BacklightPwm {
+    tim: TIM1,
+}
+
+impl BacklightPwm {
+    /// Configure TIM1 for the PWM, backlight is at full brightness.
+    pub fn new(tim: TIM1, rcc: &RCC, clocks: &Clocks) -> BacklightPwm {
+        let port = gpio::enable_port(rcc, PortName::A);
+        port.pin_config(PWM).output2().push_pull().alternate();
+
+        rcc.apb2enr.modify(|_, w| w.tim1en().set_bit());
+        // Timer clock is doubled if APB2 is divided
+        let timer_clk = if clocks.pclk2 == clocks.hclk {
+            clocks.pclk2
+        } else {
+            clocks.pclk2 * 2
+        };
+        tim.psc.write(|w| w.psc().bits((timer_clk / TICK_FREQ - 1) as u16));
+        tim.arr.write(|w| w.arr().bits((TICK_FREQ / PWM_FREQ - 1) as u16));
+        tim.ccr[0].write(|w| w.ccr().bits((TICK_FREQ / PWM_FREQ) as u16));
+        tim.ccmr1_output().write(|w| w.oc1m().pwm_mode1().oc1pe().enabled());
+        tim.ccer.write(|w| w.cc1e().set_bit());
+        #[cfg(feature = "day-night")]
+        {
+            port.pin_config(CONTRAST).output2().push_pull().alternate();
+            tim.ccmr2_output().write(|w| w.oc4m().pwm_mode1().oc4pe().enabled());
+            tim.ccer.modify(|_, w| w.cc4e().set_bit());
+        }
+        // Outputs of the advanced timer are only enabled by the main output enable
+        tim.bdtr.write(|w| w.moe().set_bit());
+        tim.egr.write(|w| w.ug().set_bit());
+        tim.cr1.write(|w| w.arpe().set_bit().cen().set_bit());
+        BacklightPwm { tim }
+    }
+
+    /// Set PWM duty, in percent.
+    pub fn set_duty(&mut self, percent: u8) {
+        let compare = TICK_FREQ / PWM_FREQ * u32::from(percent.min(100)) / 100;
+        self.tim.ccr[0].write(|w| w.ccr().bits(compare as u16));
+    }
+
+    /// Set display contrast, in percent (zero is the lightest).
+    #[cfg(feature = "day-night")]
+    pub fn set_contrast(&mut self, percent: u8) {
+        let duty = MAX_V0_DUTY * u32::from(100 - percent.min(100)) / 100;
+        self.tim.ccr[3].write(|w| w.ccr().bits((TICK_FREQ / PWM_FREQ * duty / 100) as u16));
+    }
+}
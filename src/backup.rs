@@ -0,0 +1,123 @@
+//! Boot statistics kept in the backup registers: number of boots and total runtime over all of
+//! them. The rest of the registers keep the application data (alarms of the alarm clock, threshold
+//! alarm not acknowledged yet, speedometer trip), see `scratchpad` for the register map.
+//!
+//! Backup registers survive resets, but not the power loss (unless there is a battery on VBAT).
+//! Registers are validated by a magic value, so garbage after the power-up starts the counters
+//! over. Backup domain could also be reset by the Stop mode setup (see `power`), so every save
+//! re-writes all the registers.
+//!
+//! With the `tamper` feature, pulling the TAMPER pin (PC13) low clears the registers, in hardware
+//! (even while the chip is held in reset). The event is reported by the next boot or the next save,
+//! and the counters start over. On the Blue Pill, PC13 is the LED, which no longer blinks the
+//! error code then.
+
+use stm32f1::stm32f103::{BKP, PWR, RCC};
+use crate::scratchpad::{Field, Registers, Scratchpad, Value, BOOTS, RUNTIME};
+
+#[cfg(all(feature = "tamper", feature = "nucleo-f103rb"))]
+compile_error!("TAMPER pin (PC13) is the user button on Nucleo");
+
+/// Data registers of the backup domain.
+pub struct DataRegisters(BKP);
+
+impl Registers for DataRegisters {
+    fn read(&self, index: usize) -> u16 {
+        self.0.dr[index].read().d().bits()
+    }
+
+    fn write(&mut self, index: usize, value: u16) {
+        self.0.dr[index].write(|w| w.d().bits(value));
+    }
+}
+
+pub struct BootStats {
+    scratchpad: Scratchpad<DataRegisters>,
+    /// Number of boots, including this one.
+    boots: u16,
+    /// Total runtime of the previous boots, in seconds.
+    previous_s: u32,
+    /// Registers were cleared by the tamper pin.
+    #[cfg(feature = "tamper")]
+    tampered: bool,
+}
+
+impl BootStats {
+    /// Count this boot. Enables write access to the backup domain, which is left enabled.
+    pub fn start(bkp: BKP, pwr: &PWR, rcc: &RCC) -> BootStats {
+        rcc.apb1enr.modify(|_, w| w.pwren().set_bit().bkpen().set_bit());
+        pwr.cr.modify(|_, w| w.dbp().set_bit());
+
+        let scratchpad = Scratchpad::mount(DataRegisters(bkp));
+        #[cfg(feature = "tamper")]
+        let tampered = {
+            let bkp = &scratchpad.registers().0;
+            let tampered = bkp.csr.read().tef().bit_is_set();
+            bkp.csr.modify(|_, w| w.cte().set_bit());
+            // Active low, the level is set before the pin is enabled
+            bkp.cr.write(|w| w.tpal().set_bit());
+            bkp.cr.write(|w| w.tpal().set_bit().tpe().set_bit());
+            tampered
+        };
+        let mut stats = BootStats {
+            boots: scratchpad.get(BOOTS).saturating_add(1),
+            previous_s: scratchpad.get(RUNTIME),
+            scratchpad,
+            #[cfg(feature = "tamper")]
+            tampered,
+        };
+        stats.save(0);
+        stats
+    }
+
+    /// Number of boots, including this one (saturates at 65535).
+    pub fn boots(&self) -> u16 {
+        self.boots
+    }
+
+    /// Total runtime over all boots, given the `uptime_s` of this one, in seconds.
+    pub fn runtime_secs(&self, uptime_s: u32) -> u32 {
+        self.previous_s.saturating_add(uptime_s)
+    }
+
+    /// Were the registers cleared (not initialized, or cleared by the tamper pin), so there is no
+    /// application data saved?
+    pub fn fresh(&self) -> bool {
+        self.scratchpad.fresh()
+    }
+
+    /// Were the registers cleared by the tamper pin (since the previous boot, or during this one)?
+    #[cfg(feature = "tamper")]
+    pub fn tampered(&self) -> bool {
+        self.tampered
+    }
+
+    /// Application data saved by the previous boots (zero if the registers were cleared).
+    pub fn get<T: Value>(&self, field: Field<T>) -> T {
+        self.scratchpad.get(field)
+    }
+
+    /// Save the application data (it is also re-written by every `save`).
+    pub fn set<T: Value>(&mut self, field: Field<T>, value: T) {
+        self.scratchpad.set(field, value);
+    }
+
+    /// Save the counters, given the `uptime_s` of this boot.
+    pub fn save(&mut self, uptime_s: u32) {
+        #[cfg(feature = "tamper")]
+        {
+            let bkp = &self.scratchpad.registers().0;
+            if bkp.csr.read().tef().bit_is_set() {
+                bkp.csr.modify(|_, w| w.cte().set_bit());
+                self.tampered = true;
+                self.boots = 1;
+                self.previous_s = 0;
+                self.scratchpad.clear();
+            }
+        }
+        let runtime = self.runtime_secs(uptime_s);
+        self.scratchpad.set(BOOTS, self.boots);
+        self.scratchpad.set(RUNTIME, runtime);
+        self.scratchpad.rewrite();
+    }
+}
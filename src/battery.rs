@@ -0,0 +1,207 @@
+//! Battery mode: supply voltage (measured against the internal reference, see `sensor`) as the
+//! battery gauge, shown as the level icon in the top right corner of the screens (those that leave
+//! it blank, the icon never covers the text), and the shutdown once the battery is empty.
+//!
+//! Board runs right off the battery (two alkaline or NiMH cells, or a LiFePO4 one, no regulator),
+//! so VDD is the battery voltage. Level is linear from the cutoff voltage (empty) to `FULL_MV`,
+//! which is rough (discharge curves are flat in the middle), but good enough for an icon. Readings
+//! are averaged, so the load spikes (backlight, buzzer, heater relay) do not show.
+//!
+//! Once the average stays below the cutoff for `LOW_READINGS` readings in a row, the main loop
+//! shuts down: it resets, and the startup check (see `can_start`) parks the display (clears it and
+//! turns it off) and puts the chip into Standby mode, until the batteries are replaced. Cutoff is
+//! set on the settings screen (and kept in flash by the main loop).
+//!
+//! Main loop also sleeps in `TICK_MS` steps (see `align`), so the tasks due within the same step
+//! are run together and the chip stays in Stop mode in between.
+
+use core::fmt::{self, Write};
+use crate::display::COLUMNS;
+use crate::text::{self, Align};
+
+/// Voltage of the full battery, in millivolts.
+pub const FULL_MV: u16 = 3000;
+/// Cutoff range and the step it is set by, in millivolts (chip runs down to 2V).
+const MIN_CUTOFF_MV: u16 = 2000;
+const MAX_CUTOFF_MV: u16 = 2800;
+const CUTOFF_STEP_MV: u16 = 50;
+/// Cutoff until it is set.
+const DEFAULT_CUTOFF_MV: u16 = 2200;
+/// Readings (a second apart) below the cutoff before the shutdown.
+const LOW_READINGS: u8 = 5;
+/// Battery must be that much over the cutoff to start (it recovers a bit without the load).
+pub const RESTART_MV: u16 = 200;
+/// Main loop wakes up on multiples of this, in milliseconds.
+pub const TICK_MS: u32 = 10;
+/// Rows of the icon inside the outline.
+const ICON_LEVELS: usize = 5;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Battery {
+    /// Average supply voltage, in millivolts, zero before the first reading.
+    average_mv: u16,
+    cutoff_mv: u16,
+    /// Readings in a row below the cutoff.
+    low: u8,
+}
+
+impl Default for Battery {
+    fn default() -> Battery {
+        Battery { average_mv: 0, cutoff_mv: DEFAULT_CUTOFF_MV, low: 0 }
+    }
+}
+
+impl Battery {
+    /// Take the supply voltage reading. Returns `true` if the battery is empty (shut down now).
+    pub fn update(&mut self, vdd_mv: u16) -> bool {
+        self.average_mv = match self.average_mv {
+            0 => vdd_mv,
+            average_mv => ((u32::from(average_mv) * 3 + u32::from(vdd_mv)) / 4) as u16,
+        };
+        self.low = if self.average_mv < self.cutoff_mv { self.low.saturating_add(1) } else { 0 };
+        self.low >= LOW_READINGS
+    }
+
+    /// Is the battery good enough to start on (`vdd_mv` is a single reading, taken at startup)?
+    pub fn can_start(&self, vdd_mv: u16) -> bool {
+        vdd_mv >= self.cutoff_mv + RESTART_MV
+    }
+
+    /// Is there a reading yet?
+    pub fn is_measured(&self) -> bool {
+        self.average_mv != 0
+    }
+
+    /// Charge left, in percent (100 before the first reading).
+    pub fn percent(&self) -> u8 {
+        if self.average_mv == 0 {
+            return 100;
+        }
+        let span = u32::from(FULL_MV - self.cutoff_mv);
+        let above = u32::from(self.average_mv.saturating_sub(self.cutoff_mv));
+        (above * 100 / span).min(100) as u8
+    }
+
+    /// Cutoff voltage, in millivolts.
+    pub fn cutoff_mv(&self) -> u16 {
+        self.cutoff_mv
+    }
+
+    /// Set the cutoff (rounded down to the step and limited to the range). `u16::MAX` (erased
+    /// flash) keeps the default.
+    pub fn set_cutoff_mv(&mut self, cutoff_mv: u16) {
+        if cutoff_mv != u16::MAX {
+            self.cutoff_mv = (cutoff_mv / CUTOFF_STEP_MV * CUTOFF_STEP_MV).clamp(MIN_CUTOFF_MV, MAX_CUTOFF_MV);
+        }
+    }
+
+    /// Change the cutoff by `steps`.
+    pub fn adjust(&mut self, steps: i8) {
+        let cutoff_mv = i32::from(self.cutoff_mv) + i32::from(steps) * i32::from(CUTOFF_STEP_MV);
+        self.set_cutoff_mv(cutoff_mv.clamp(0, i32::from(MAX_CUTOFF_MV)) as u16);
+    }
+
+    /// Custom character: battery outline, filled from the bottom to the charge left (any charge
+    /// fills the bottom row).
+    pub fn glyph(&self) -> [u8; 8] {
+        let filled = (usize::from(self.percent()) * ICON_LEVELS).div_ceil(100);
+        core::array::from_fn(|row| match row {
+            0 => 0b01110,
+            1 | 7 => 0b11111,
+            _ if 7 - row <= filled => 0b11111,
+            _ => 0b10001,
+        })
+    }
+
+    /// Write the cutoff preference (`Cutoff     2.20V`).
+    pub fn write_preference<W: Write>(&self, w: &mut W) -> fmt::Result {
+        text::str(w, "Cutoff", COLUMNS - 5, Align::Left)?;
+        text::fixed(w, i32::from(self.cutoff_mv), 2, 4)?;
+        w.write_char('V')
+    }
+}
+
+/// Deadline the main loop sleeps until: the `deadline` of the next task, rounded up to `TICK_MS`
+/// (wrapping around with the millisecond timer).
+pub fn align(deadline: u32) -> u32 {
+    deadline.wrapping_add(TICK_MS - 1) / TICK_MS * TICK_MS
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn battery_gauge() {
+        use crate::battery::{align, Battery};
+
+        let mut battery = Battery::default();
+        assert_eq!(battery.percent(), 100);
+        assert!(battery.can_start(2400));
+        assert!(!battery.can_start(2399));
+
+        // Level is linear from the cutoff to full, and averaged
+        assert!(!battery.update(2600));
+        assert_eq!(battery.percent(), 50);
+        assert_eq!(battery.glyph(), [0b01110, 0b11111, 0b10001, 0b10001, 0b11111, 0b11111, 0b11111, 0b11111]);
+        assert!(!battery.update(3400));
+        assert_eq!(battery.percent(), 75);
+        (0..20).for_each(|_| assert!(!battery.update(3100)));
+        assert_eq!(battery.percent(), 100);
+        assert_eq!(battery.glyph(), [0b01110, 0b11111, 0b11111, 0b11111, 0b11111, 0b11111, 0b11111, 0b11111]);
+
+        // Spike below the cutoff does not shut it down, staying below it does
+        assert!(!battery.update(1900));
+        assert!(!battery.update(2190));
+        for _ in 0..10 {
+            battery.update(2100);
+        }
+        assert_eq!(battery.percent(), 0);
+        assert_eq!(battery.glyph(), [0b01110, 0b11111, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11111]);
+        assert!(battery.update(2100));
+
+        // Cutoff is set in steps, within the range; erased flash keeps it
+        let mut preference = std::string::String::new();
+        battery.write_preference(&mut preference).unwrap();
+        assert_eq!(preference, "Cutoff     2.20V");
+        battery.adjust(3);
+        assert_eq!(battery.cutoff_mv(), 2350);
+        battery.adjust(-100);
+        assert_eq!(battery.cutoff_mv(), 2000);
+        battery.set_cutoff_mv(2479);
+        assert_eq!(battery.cutoff_mv(), 2450);
+        battery.set_cutoff_mv(u16::MAX);
+        assert_eq!(battery.cutoff_mv(), 2450);
+        battery.set_cutoff_mv(5000);
+        assert_eq!(battery.cutoff_mv(), 2800);
+
+        // Main loop wakes up on the tick
+        assert_eq!(align(1000), 1000);
+        assert_eq!(align(1001), 1010);
+        assert_eq!(align(1009), 1010);
+    }
+
+    #[test]
+    fn battery_icon() {
+        use crate::mock;
+        use crate::screens::{Preference, Screen, Settings, Stats, LOW_BATTERY};
+        use crate::ui::Event;
+
+        // Level icon is in the corner of the screens, in place of the low battery one, once the
+        // battery is measured
+        let mut settings = Settings::default();
+        assert_eq!(mock::render(Screen::Hello, &Stats::default(), &settings).0, "Hello!          ");
+        settings.battery.update(2500);
+        let lcd = mock::render_lcd(Screen::Hello, &Stats::default(), &settings);
+        assert_eq!(lcd.row(0, 16), "Hello!         #");
+        assert_eq!(lcd.glyph(LOW_BATTERY as usize), settings.battery.glyph());
+
+        // Cutoff is the last preference
+        while settings.preference != Preference::BatteryCutoff {
+            assert!(settings.handle(Screen::Settings, Event::Button));
+        }
+        assert!(settings.handle(Screen::Settings, Event::EncoderUp));
+        assert_eq!(settings.battery.cutoff_mv(), 2250);
+        assert_eq!(mock::render(Screen::Settings, &Stats::default(), &settings).1, "Cutoff     2.25V");
+        assert!(!settings.handle(Screen::Settings, Event::Button));
+    }
+
+}
@@ -0,0 +1,108 @@
+//! Display throughput benchmark (`bench` feature): how fast characters are written and how long
+//! it takes to redraw the whole display, so changes to the hardware layer could be measured on the
+//! target.
+//!
+//! Results depend on how the driver waits for the display: polling the busy flag (`input`
+//! feature) or fixed delays. `LcdHardware::use_busy_flag` switches between the two.
+
+use core::fmt::{self, Write};
+use crate::display::{Line, TextDisplay, COLUMNS, ROWS};
+use crate::text;
+use lcd::Display;
+
+/// Characters written for the throughput measurement (the whole display memory, so the address
+/// counter wraps around to where it has started).
+const CHARS: u32 = 80;
+
+/// Source of timestamps for the measurements.
+pub trait Clock {
+    /// Current timestamp, in implementation-defined units (wraps around).
+    fn now(&self) -> u32;
+
+    /// Microseconds elapsed since the `start` timestamp.
+    fn since_us(&self, start: u32) -> u32;
+}
+
+/// Benchmark results.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Results {
+    /// Characters written per second.
+    pub chars_per_sec: u32,
+    /// Time to position the cursor and write every row, in microseconds.
+    pub redraw_us: u32,
+}
+
+/// Run the benchmark. Display is left with the garbage, it should be cleared afterwards.
+pub fn measure<HW: lcd::Hardware + lcd::Delay, C: Clock>(display: &mut Display<HW>, clock: &C) -> Results {
+    display.position(0, 0);
+    let start = clock.now();
+    for i in 0..CHARS {
+        display.write(b'0' + (i % 10) as u8);
+    }
+    let write_us = clock.since_us(start).max(1);
+
+    let start = clock.now();
+    for row in 0..ROWS as u8 {
+        display.position(0, row);
+        for col in 0..COLUMNS as u8 {
+            display.write(b'A' + col);
+        }
+    }
+    let redraw_us = clock.since_us(start);
+
+    Results {
+        chars_per_sec: (u64::from(CHARS) * 1_000_000 / u64::from(write_us)) as u32,
+        redraw_us,
+    }
+}
+
+/// Show results measured in the given `mode` ("busy" or "fixed"): characters per second on the
+/// first row, redraw time on the second one.
+pub fn report<D: TextDisplay>(display: &mut D, mode: &str, results: &Results) -> fmt::Result {
+    let mut line = Line::new(display, 0);
+    line.write_str(mode)?;
+    text::uint(&mut line, results.chars_per_sec, (COLUMNS - 4).saturating_sub(mode.len()))?;
+    line.write_str(" c/s")?;
+    line.finish()?;
+
+    let mut line = Line::new(display, 1);
+    line.write_str("frame")?;
+    text::uint(&mut line, results.redraw_us, COLUMNS - 7)?;
+    line.write_str("us")?;
+    line.finish()
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn bench_measures_delays() {
+        use lcd::{Display, FunctionDots, FunctionLine};
+        use crate::bench::{self, Results};
+        use crate::framebuffer::Framebuffer;
+        use crate::hardware::LcdHardware;
+        use crate::mock::MockHardware;
+
+        let mock = MockHardware::new();
+        let mut display = Display::new(LcdHardware::new(&mock, &mock));
+        display.init(FunctionLine::Line2, FunctionDots::Dots5x8);
+        // Cost of a single character and of a single positioning command
+        mock.reset();
+        display.write(b'x');
+        let char_us = mock.elapsed_us();
+        mock.reset();
+        display.position(0, 1);
+        let position_us = mock.elapsed_us();
+
+        mock.reset();
+        let results = bench::measure(&mut display, &mock);
+        assert_eq!(mock.transfers().len(), (1 + 80 + 2 + 32) * 2);
+        assert_eq!(results.chars_per_sec, 1_000_000 / char_us);
+        assert_eq!(results.redraw_us, 2 * position_us + 32 * char_us);
+
+        let mut fb = Framebuffer::new(crate::display::NoDisplay);
+        let results = Results { chars_per_sec: 21052, redraw_us: 1640 };
+        bench::report(&mut fb, "fixed", &results).unwrap();
+        assert_eq!(fb.row(0), "fixed  21052 c/s");
+        assert_eq!(fb.row(1), "frame     1640us");
+    }
+}
@@ -0,0 +1,64 @@
+//! Big digits, spanning both rows of the display. Each digit is three columns wide and is made of
+//! four custom characters (bars at the top, at the bottom, at both and a full block), uploaded by
+//! `screens::init`.
+
+use core::fmt::{self, Write};
+
+/// Custom character: bar at the top of the cell.
+pub const TOP: char = '\u{4}';
+/// Custom character: bar at the bottom of the cell.
+pub const BOTTOM: char = '\u{5}';
+/// Custom character: bars at the top and at the bottom of the cell.
+pub const BOTH: char = '\u{6}';
+/// Custom character: full block (the one in the display character set cannot be written as text).
+pub const FULL: char = '\u{7}';
+
+pub const TOP_MAP: [u8; 8] = [0b11111, 0b11111, 0, 0, 0, 0, 0, 0];
+pub const BOTTOM_MAP: [u8; 8] = [0, 0, 0, 0, 0, 0, 0b11111, 0b11111];
+pub const BOTH_MAP: [u8; 8] = [0b11111, 0b11111, 0, 0, 0, 0, 0b11111, 0b11111];
+pub const FULL_MAP: [u8; 8] = [0b11111; 8];
+
+/// Digits, top and bottom rows: `T`op, `B`ottom, bot`H` and `F`ull cells.
+const DIGITS: [[&str; 2]; 10] = [
+    ["FTF", "FBF"],
+    ["TF ", "BFB"],
+    ["HHF", "FBB"],
+    ["HHF", "BBF"],
+    ["FBF", "  F"],
+    ["FHH", "BBF"],
+    ["FHH", "FBF"],
+    ["TTF", "  F"],
+    ["FHF", "FBF"],
+    ["FHF", "BBF"],
+];
+
+/// Width of a digit, in columns.
+pub const DIGIT_WIDTH: usize = 3;
+
+/// Write one `row` (0 or 1) of `text` in big digits. Colon takes a single column, other
+/// characters are written as is (in both rows).
+pub fn write_row<W: Write>(w: &mut W, text: &str, row: usize) -> fmt::Result {
+    for c in text.chars() {
+        let digit = match c.to_digit(10) {
+            Some(digit) => digit as usize,
+            None if c == ':' => {
+                w.write_char('.')?;
+                continue;
+            }
+            None => {
+                w.write_char(c)?;
+                continue;
+            }
+        };
+        for cell in DIGITS[digit][row].chars() {
+            w.write_char(match cell {
+                'T' => TOP,
+                'B' => BOTTOM,
+                'H' => BOTH,
+                'F' => FULL,
+                _ => ' ',
+            })?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,118 @@
+//! Simulator: runs the demo screens against the simulated 16x2 display, rendered in the terminal.
+//!
+//! Run with `make simulator`. Keys:
+//!  * `Space` or `Enter` is the button, `Tab` is the long button press;
+//!  * `Left` / `Right` arrows turn the encoder (change the value on the settings screen);
+//!  * `h`, `b`, `d`, `r`, `u`, `s` are sent as serial commands;
+//!  * `p` toggles automatic screen rotation;
+//!  * `q` or `Esc` exits.
+//!
+//! Time runs as on the board: the timers, games and the rest of the time-driven screens are
+//! advanced before every event and every refresh (every `REFRESH_MS`).
+
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use crossterm::{cursor, execute, queue, terminal};
+use crossterm::event::{self, Event, KeyCode};
+use crossterm::style::Print;
+use lcd::Display;
+use lcd_example_bluepill::display::{COLUMNS, ROWS};
+use lcd_example_bluepill::hardware::LcdHardware;
+use lcd_example_bluepill::mock::MockHardware;
+use lcd_example_bluepill::screens::{self, Settings, Stats, SCREEN_TIME_MS};
+use lcd_example_bluepill::sim::Hd44780;
+use lcd_example_bluepill::ui;
+
+/// Display refresh period, in milliseconds (as in the firmware).
+const REFRESH_MS: u64 = 100;
+
+fn draw<W: Write>(out: &mut W, lcd: &Hd44780) -> io::Result<()> {
+    let border = format!("+{}+", "-".repeat(COLUMNS));
+    queue!(out, cursor::MoveTo(0, 0), Print(&border))?;
+    for row in 0..ROWS {
+        queue!(out, cursor::MoveTo(0, row as u16 + 1), Print(format!("|{}|", lcd.row(row, COLUMNS))))?;
+    }
+    queue!(out, cursor::MoveTo(0, ROWS as u16 + 1), Print(&border))?;
+    out.flush()
+}
+
+enum Input {
+    Quit,
+    TogglePause,
+    Ui(ui::Event),
+}
+
+/// Wait for a key for up to `timeout`.
+fn read_input(timeout: Duration) -> io::Result<Option<Input>> {
+    if !event::poll(timeout)? {
+        return Ok(None);
+    }
+    let key = match event::read()? {
+        Event::Key(key) => key,
+        _ => return Ok(None),
+    };
+    Ok(match key.code {
+        KeyCode::Char('q') | KeyCode::Esc => Some(Input::Quit),
+        KeyCode::Char('p') => Some(Input::TogglePause),
+        KeyCode::Char(' ') | KeyCode::Enter => Some(Input::Ui(ui::Event::Button)),
+        KeyCode::Tab => Some(Input::Ui(ui::Event::LongPress)),
+        KeyCode::Right => Some(Input::Ui(ui::Event::EncoderUp)),
+        KeyCode::Left => Some(Input::Ui(ui::Event::EncoderDown)),
+        KeyCode::Backspace => Some(Input::Ui(ui::Event::Serial(0x08))),
+        KeyCode::Char(c) if c.is_ascii() => Some(Input::Ui(ui::Event::Serial(c as u8))),
+        _ => None,
+    })
+}
+
+fn run<W: Write>(out: &mut W) -> io::Result<()> {
+    let hw = MockHardware::new();
+    let mut lcd = Hd44780::new();
+    let mut display = Display::new(LcdHardware::new(&hw, &hw));
+    screens::init(&mut display);
+
+    // Nothing to measure on the host, except for the uptime
+    let mut stats = Stats::default();
+    let started = Instant::now();
+    let mut settings = Settings::default();
+    let mut nav = screens::navigation();
+    let now_ms = || started.elapsed().as_millis() as u32;
+    let period = Duration::from_millis(u64::from(SCREEN_TIME_MS));
+    let refresh = Duration::from_millis(REFRESH_MS);
+    let mut next_tick = Instant::now() + period;
+    loop {
+        stats.uptime_s = started.elapsed().as_secs() as u32;
+        settings.advance(now_ms());
+        nav.state().render(&mut display, &stats, &settings).unwrap();
+        lcd.feed(&hw.transfers());
+        hw.reset();
+        draw(out, &lcd)?;
+
+        let event = match read_input(next_tick.saturating_duration_since(Instant::now()).min(refresh))? {
+            Some(Input::Quit) => return Ok(()),
+            Some(Input::TogglePause) => {
+                settings.auto_rotate = !settings.auto_rotate;
+                continue;
+            }
+            Some(Input::Ui(event)) => event,
+            None if Instant::now() >= next_tick => {
+                next_tick += period;
+                ui::Event::Timer
+            }
+            None => continue,
+        };
+        settings.advance(now_ms());
+        if !settings.handle(nav.state(), event) {
+            nav.handle(event, &settings);
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut out = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(out, terminal::Clear(terminal::ClearType::All), cursor::Hide)?;
+    let result = run(&mut out);
+    execute!(out, cursor::Show, cursor::MoveTo(0, ROWS as u16 + 3))?;
+    terminal::disable_raw_mode()?;
+    result
+}
@@ -0,0 +1,183 @@
+//! Per-board pin maps and clock quirks.
+//!
+//! Board is selected by cargo feature: `maple-mini` or `nucleo-f103rb` (Blue Pill is used if
+//! neither of them is enabled). On all boards, LCD is connected to the GPIOB port, but to different
+//! pins. `LcdHardware` works with any GPIO port, though, so it could be moved to another one.
+//!
+//! With the `open-drain` feature, LCD data bus is always on PB12-PB15 (5V-tolerant pins), the
+//! control lines of Blue Pill and Nucleo-F103RB move to PB6-PB8. PB6 and PB7 are the I2C1 SCL and
+//! SDA, so I2C1 stays unavailable (as with the default wiring, where they are DB4 and DB5); I2C
+//! features use I2C2 (PB10 and PB11), which is still free on those boards.
+
+/// GPIO port
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PortName {
+    A,
+    B,
+    C,
+}
+
+/// Single GPIO pin
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Pin {
+    pub port: PortName,
+    pub index: usize,
+    pub active_low: bool,
+}
+
+impl Pin {
+    /// Pin level for the given logical state.
+    pub fn level(&self, active: bool) -> bool {
+        active != self.active_low
+    }
+}
+
+/// Source of the high-speed external clock.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Hse {
+    /// Crystal oscillator on the board
+    Crystal,
+    /// External clock signal (oscillator is bypassed)
+    Bypass,
+}
+
+/// HSE frequency, all supported boards use 8Mhz
+pub const HSE_FREQ: u32 = 8_000_000;
+
+/// STM32 "Blue Pill" development board (STM32F103C8)
+pub mod blue_pill {
+    use super::{Hse, Pin, PortName};
+
+    pub const NAME: &str = "Blue Pill";
+    #[cfg(not(feature = "open-drain"))]
+    pub const LCD_RS: usize = 12; // PB12 is RS
+    #[cfg(not(feature = "open-drain"))]
+    pub const LCD_RW: usize = 13; // PB13 is RW
+    #[cfg(not(feature = "open-drain"))]
+    pub const LCD_E: usize = 14; // PB14 is E
+    #[cfg(not(feature = "open-drain"))]
+    pub const LCD_DATA: usize = 6; // PB6-PB9 is DB4-DB7
+    // Data bus goes to the 5V-tolerant PB12-PB15, control lines take its place
+    #[cfg(feature = "open-drain")]
+    pub const LCD_RS: usize = 6; // PB6 is RS
+    #[cfg(feature = "open-drain")]
+    pub const LCD_RW: usize = 7; // PB7 is RW
+    #[cfg(feature = "open-drain")]
+    pub const LCD_E: usize = 8; // PB8 is E
+    #[cfg(feature = "open-drain")]
+    pub const LCD_DATA: usize = 12; // PB12-PB15 is DB4-DB7
+    /// On-board LED
+    pub const LED: Pin = Pin { port: PortName::C, index: 13, active_low: true };
+    /// There is no user button on the board, should be connected between PA0 and the ground
+    pub const BUTTON: Pin = Pin { port: PortName::A, index: 0, active_low: true };
+    /// Active buzzer (driven through a transistor), should be connected to PB5
+    pub const BUZZER: Pin = Pin { port: PortName::B, index: 5, active_low: false };
+    /// Heater relay (module with its own driver), should be connected to PA3
+    pub const RELAY: Pin = Pin { port: PortName::A, index: 3, active_low: false };
+    /// Tachometer (or totalizer) sensor, open collector, should be connected to PA2
+    pub const TACH: Pin = Pin { port: PortName::A, index: 2, active_low: true };
+    /// Hour meter sense input, high while the equipment runs (through a divider or an optocoupler),
+    /// should be connected to PB15
+    #[cfg(not(feature = "open-drain"))]
+    pub const SENSE: Pin = Pin { port: PortName::B, index: 15, active_low: false };
+    /// Should be connected to PB9 with the `open-drain` data bus (PB15 is taken by the LCD)
+    #[cfg(feature = "open-drain")]
+    pub const SENSE: Pin = Pin { port: PortName::B, index: 9, active_low: false };
+    /// PIR motion sensor (HC-SR501 or alike, high while motion is detected), should be connected
+    /// to PB1
+    pub const PIR: Pin = Pin { port: PortName::B, index: 1, active_low: false };
+    /// USB D+ pull-up is hard-wired
+    pub const USB_DISCONNECT: Option<Pin> = None;
+    pub const HSE: Hse = Hse::Crystal;
+}
+
+/// Maple Mini (STM32F103CB)
+pub mod maple_mini {
+    use super::{Hse, Pin, PortName};
+
+    pub const NAME: &str = "Maple Mini";
+    // PB6-PB9 cannot be used: PB8 is the button and PB9 controls USB pull-up
+    pub const LCD_RS: usize = 10; // PB10 is RS
+    pub const LCD_RW: usize = 11; // PB11 is RW
+    pub const LCD_E: usize = 5; // PB5 is E
+    pub const LCD_DATA: usize = 12; // PB12-PB15 is DB4-DB7
+    /// On-board LED
+    pub const LED: Pin = Pin { port: PortName::B, index: 1, active_low: false };
+    /// On-board button ("BUT")
+    pub const BUTTON: Pin = Pin { port: PortName::B, index: 8, active_low: false };
+    /// Active buzzer (driven through a transistor), should be connected to PB6
+    pub const BUZZER: Pin = Pin { port: PortName::B, index: 6, active_low: false };
+    /// Heater relay (module with its own driver), should be connected to PA3
+    pub const RELAY: Pin = Pin { port: PortName::A, index: 3, active_low: false };
+    /// Tachometer (or totalizer) sensor, open collector, should be connected to PA2
+    pub const TACH: Pin = Pin { port: PortName::A, index: 2, active_low: true };
+    /// Hour meter sense input, high while the equipment runs (through a divider or an optocoupler),
+    /// should be connected to PC14 (PB15 is taken by the LCD)
+    pub const SENSE: Pin = Pin { port: PortName::C, index: 14, active_low: false };
+    /// PIR motion sensor (HC-SR501 or alike, high while motion is detected), should be connected
+    /// to PC15 (PB1 is the LED)
+    pub const PIR: Pin = Pin { port: PortName::C, index: 15, active_low: false };
+    /// USB D+ pull-up is disconnected when PB9 is high ("DISC")
+    pub const USB_DISCONNECT: Option<Pin> = Some(Pin { port: PortName::B, index: 9, active_low: false });
+    pub const HSE: Hse = Hse::Crystal;
+}
+
+/// Nucleo-F103RB (STM32F103RB)
+pub mod nucleo_f103rb {
+    use super::{Hse, Pin, PortName};
+
+    pub const NAME: &str = "Nucleo-F103RB";
+    #[cfg(not(feature = "open-drain"))]
+    pub const LCD_RS: usize = 12; // PB12 is RS
+    #[cfg(not(feature = "open-drain"))]
+    pub const LCD_RW: usize = 13; // PB13 is RW
+    #[cfg(not(feature = "open-drain"))]
+    pub const LCD_E: usize = 14; // PB14 is E
+    #[cfg(not(feature = "open-drain"))]
+    pub const LCD_DATA: usize = 6; // PB6-PB9 is DB4-DB7
+    // Data bus goes to the 5V-tolerant PB12-PB15, control lines take its place
+    #[cfg(feature = "open-drain")]
+    pub const LCD_RS: usize = 6; // PB6 is RS
+    #[cfg(feature = "open-drain")]
+    pub const LCD_RW: usize = 7; // PB7 is RW
+    #[cfg(feature = "open-drain")]
+    pub const LCD_E: usize = 8; // PB8 is E
+    #[cfg(feature = "open-drain")]
+    pub const LCD_DATA: usize = 12; // PB12-PB15 is DB4-DB7
+    /// On-board LED (LD2)
+    pub const LED: Pin = Pin { port: PortName::A, index: 5, active_low: false };
+    /// On-board user button (B1)
+    pub const BUTTON: Pin = Pin { port: PortName::C, index: 13, active_low: true };
+    /// Active buzzer (driven through a transistor), should be connected to PB5 (D4)
+    pub const BUZZER: Pin = Pin { port: PortName::B, index: 5, active_low: false };
+    /// Heater relay (module with its own driver), should be connected to PC0 (A5, PA3 goes to
+    /// the ST-LINK)
+    pub const RELAY: Pin = Pin { port: PortName::C, index: 0, active_low: false };
+    /// Tachometer (or totalizer) sensor, open collector, should be connected to PC1 (A4, PA2
+    /// goes to the ST-LINK)
+    pub const TACH: Pin = Pin { port: PortName::C, index: 1, active_low: true };
+    /// Hour meter sense input, high while the equipment runs (through a divider or an optocoupler),
+    /// should be connected to PB15 (on the morpho header)
+    #[cfg(not(feature = "open-drain"))]
+    pub const SENSE: Pin = Pin { port: PortName::B, index: 15, active_low: false };
+    /// Should be connected to PB9 (D14) with the `open-drain` data bus (PB15 is taken by the LCD)
+    #[cfg(feature = "open-drain")]
+    pub const SENSE: Pin = Pin { port: PortName::B, index: 9, active_low: false };
+    /// PIR motion sensor (HC-SR501 or alike, high while motion is detected), should be connected
+    /// to PB1 (on the morpho header)
+    pub const PIR: Pin = Pin { port: PortName::B, index: 1, active_low: false };
+    /// USB is not routed to the connector
+    pub const USB_DISCONNECT: Option<Pin> = None;
+    /// No crystal by default, HSE is driven by the 8Mhz MCO output of the on-board ST-LINK
+    pub const HSE: Hse = Hse::Bypass;
+}
+
+#[cfg(all(feature = "maple-mini", feature = "nucleo-f103rb"))]
+compile_error!("only one board could be selected");
+
+#[cfg(not(any(feature = "maple-mini", feature = "nucleo-f103rb")))]
+pub use self::blue_pill::*;
+#[cfg(feature = "maple-mini")]
+pub use self::maple_mini::*;
+#[cfg(feature = "nucleo-f103rb")]
+pub use self::nucleo_f103rb::*;
@@ -0,0 +1,44 @@
+//! Support for running after the stm32duino (Maple) USB bootloader.
+//!
+//! Bootloader occupies the first 8K of flash, so firmware is linked after it (see
+//! `memory/stm32duino.x`) and relocates the vector table on startup (`set-vtor` feature of the
+//! `cortex-m-rt`).
+//!
+//! Bootloader leaves USB connected when it jumps to the firmware, so host still sees the
+//! bootloader device. Firmware does not use USB, so it resets the peripheral and makes host notice
+//! the device is gone: either via the board disconnect pin, or by pulling D+ (PA12) low for a while
+//! (on boards where D+ pull-up is hard-wired).
+
+use stm32f1::stm32f103::RCC;
+use crate::board::{self, PortName};
+use crate::delay::CycleDelay;
+use crate::gpio::{self, GPIOExtras};
+
+/// USB D+ pin.
+const USB_DP: usize = 12;
+
+/// How long to keep D+ low, host needs at least 2.5us of SE0 to detect disconnect.
+const DISCONNECT_US: u32 = 10_000;
+
+/// Reset USB peripheral left by the bootloader and force the host to re-enumerate.
+pub fn release_usb(rcc: &RCC, delay: CycleDelay) {
+    rcc.apb1rstr.modify(|_, w| w.usbrst().set_bit());
+    rcc.apb1rstr.modify(|_, w| w.usbrst().clear_bit());
+    rcc.apb1enr.modify(|_, w| w.usben().clear_bit());
+
+    match board::USB_DISCONNECT {
+        Some(pin) => {
+            // Keep pull-up disconnected for good
+            let port = gpio::enable_port(rcc, pin.port);
+            port.pin_config(pin.index).push_pull().output2();
+            port.write_pin(pin.index, pin.level(true));
+        }
+        None => {
+            let port = gpio::enable_port(rcc, PortName::A);
+            port.write_pin(USB_DP, false);
+            port.pin_config(USB_DP).push_pull().output2();
+            delay.delay_us(DISCONNECT_US);
+            port.pin_config(USB_DP).input().floating();
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Sharing of a single I2C bus between several devices (display backpack, sensors, RTC).
+//!
+//! Drivers get a `BusProxy` instead of the bus itself. Every method of `I2c` is a complete bus
+//! transaction, so transactions of different devices never interleave. The bus is kept in a
+//! `RefCell`, so proxies are meant to be used from one context (the main loop): using the bus from
+//! an interrupt handler while it is in the middle of a transaction panics.
+//!
+//! Note that there is no I2C display backend yet (on all supported boards, LCD data pins overlap
+//! with the I2C1 pins), so nothing in the firmware uses the shared bus so far.
+//!
+//! `Scanner` finds devices on the bus (for the I2C scanner screen, `i2c-scan` feature).
+
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use crate::text;
+use crate::ui::Event;
+
+/// Blocking I2C master.
+pub trait I2c {
+    type Error;
+
+    /// Write `bytes` to the device at 7-bit `address`.
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error>;
+
+    /// Write `bytes` to the device at 7-bit `address`, then fill `buffer` from it (with a repeated
+    /// start in between).
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Self::Error>;
+}
+
+/// I2C bus shared between devices.
+pub struct SharedBus<B> {
+    bus: RefCell<B>,
+}
+
+impl<B: I2c> SharedBus<B> {
+    pub fn new(bus: B) -> SharedBus<B> {
+        SharedBus { bus: RefCell::new(bus) }
+    }
+
+    /// Get a handle for one device on the bus.
+    pub fn acquire(&self) -> BusProxy<'_, B> {
+        BusProxy { bus: &self.bus }
+    }
+
+    /// Get the bus back (all proxies must be gone by then).
+    pub fn into_inner(self) -> B {
+        self.bus.into_inner()
+    }
+}
+
+/// Handle to the shared bus, given to a device driver in place of the bus.
+pub struct BusProxy<'a, B> {
+    bus: &'a RefCell<B>,
+}
+
+impl<B: I2c> I2c for BusProxy<'_, B> {
+    type Error = B::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), B::Error> {
+        self.bus.borrow_mut().write(address, bytes)
+    }
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), B::Error> {
+        self.bus.borrow_mut().write_read(address, bytes, buffer)
+    }
+}
+
+/// First and last address probed by the scanner (others are reserved).
+pub const SCAN_FIRST: u8 = 0x08;
+pub const SCAN_LAST: u8 = 0x77;
+
+/// Probes one address at a time (so the main loop is not blocked for the whole scan), scans
+/// repeat forever.
+pub struct Scanner {
+    next: u8,
+    /// Devices responded so far in this scan, bit per address.
+    found: u128,
+}
+
+impl Default for Scanner {
+    fn default() -> Scanner {
+        Scanner::new()
+    }
+}
+
+impl Scanner {
+    pub const fn new() -> Scanner {
+        Scanner { next: SCAN_FIRST, found: 0 }
+    }
+
+    /// Probe the next address with an empty write (device acknowledges its address). Returns
+    /// the devices found (bit per address) once the scan is complete.
+    pub fn poll<B: I2c>(&mut self, bus: &mut B) -> Option<u128> {
+        if bus.write(self.next, &[]).is_ok() {
+            self.found |= 1 << self.next;
+        }
+        if self.next < SCAN_LAST {
+            self.next += 1;
+            return None;
+        }
+        let found = self.found;
+        *self = Scanner::new();
+        Some(found)
+    }
+}
+
+/// Devices shown on a single page.
+const PAGE_SIZE: u32 = 5;
+
+/// Results of the last complete scan, shown page by page.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScanResults {
+    /// Bit per address, `None` until the first scan is complete.
+    devices: Option<u128>,
+    page: u32,
+}
+
+impl ScanResults {
+    /// Replace results with the new scan. Page is kept, if it still exists.
+    pub fn update(&mut self, devices: u128) {
+        self.devices = Some(devices);
+        if self.page * PAGE_SIZE >= self.count() {
+            self.page = 0;
+        }
+    }
+
+    /// Number of devices found.
+    pub fn count(&self) -> u32 {
+        self.devices.unwrap_or(0).count_ones()
+    }
+
+    /// Button shows the next page. Returns `false` on the last page (button is left for the
+    /// navigation then, and the first page is shown next time).
+    pub fn handle(&mut self, event: Event) -> bool {
+        if event != Event::Button {
+            return false;
+        }
+        if (self.page + 1) * PAGE_SIZE < self.count() {
+            self.page += 1;
+            true
+        } else {
+            self.page = 0;
+            false
+        }
+    }
+
+    /// Write the summary (`I2C  3 found`).
+    pub fn write_summary<W: Write>(&self, w: &mut W) -> fmt::Result {
+        w.write_str("I2C")?;
+        if self.devices.is_none() {
+            return w.write_str(" scanning");
+        }
+        text::uint(w, self.count(), 3)?;
+        w.write_str(" found")
+    }
+
+    /// Write addresses on the current page (`08 3c 48 68 76 >`), `>` means there are more pages.
+    pub fn write_page<W: Write>(&self, w: &mut W) -> fmt::Result {
+        let devices = self.devices.unwrap_or(0);
+        let mut addresses = (SCAN_FIRST..=SCAN_LAST).filter(|&address| devices & (1 << address) != 0);
+        let mut page = addresses.by_ref().skip((self.page * PAGE_SIZE) as usize).take(PAGE_SIZE as usize);
+        if let Some(first) = page.next() {
+            text::hex8(w, first)?;
+        }
+        for address in page {
+            w.write_char(' ')?;
+            text::hex8(w, address)?;
+        }
+        if addresses.next().is_some() {
+            w.write_str(" >")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn shared_bus_transactions() {
+        use crate::bus::{I2c, SharedBus};
+        use crate::mock::MockI2c;
+
+        // Reads are answered with the chip ID of a BME280
+        let chip_id = |_: &[u8], buffer: &mut [u8]| {
+            buffer.fill(0x60);
+            true
+        };
+        let bus = SharedBus::new(MockI2c::new(&[0x27, 0x76], chip_id));
+        let mut display = bus.acquire();
+        let mut sensor = bus.acquire();
+
+        display.write(0x27, &[0x08, 0x0c]).unwrap();
+        let mut buffer = [0; 2];
+        sensor.write_read(0x76, &[0xd0], &mut buffer).unwrap();
+        display.write(0x27, &[0x08]).unwrap();
+        assert_eq!(buffer, [0x60, 0x60]);
+
+        drop((display, sensor));
+        assert_eq!(bus.into_inner().transactions, vec![(0x27, vec![0x08, 0x0c]), (0x76, vec![0xd0]), (0x27, vec![0x08])]);
+    }
+
+    #[test]
+    #[cfg(feature = "i2c-scan")]
+    fn i2c_scanner_pages() {
+        use crate::bus::{Scanner, SCAN_FIRST, SCAN_LAST};
+        use crate::mock::{self, MockI2c};
+        use crate::screens::{Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        // Reserved addresses are not probed
+        let mut bus = MockI2c::new(&[0x00, 0x08, 0x1d, 0x3c, 0x48, 0x50, 0x68, 0x76, 0x77, 0x78], ());
+        let mut scanner = Scanner::new();
+        for _ in SCAN_FIRST..SCAN_LAST {
+            assert_eq!(scanner.poll(&mut bus), None);
+        }
+        let devices = scanner.poll(&mut bus).unwrap();
+        assert_eq!(devices.count_ones(), 8);
+
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::I2cScan, &Stats::default(), settings);
+        assert_eq!(render(&settings).0, "I2C scanning    ");
+
+        settings.i2c.update(devices);
+        assert_eq!(render(&settings), ("I2C  8 found    ".into(), "08 1d 3c 48 50 >".into()));
+        assert!(settings.handle(Screen::I2cScan, Event::Button));
+        assert_eq!(render(&settings).1, "68 76 77        ");
+        assert!(!settings.handle(Screen::I2cScan, Event::Button));
+        assert_eq!(render(&settings).1, "08 1d 3c 48 50 >");
+    }
+}
@@ -0,0 +1,70 @@
+//! User button (see `board::BUTTON`), scanned periodically and debounced. Press, release and the
+//! button held down for `LONG_PRESS_SCANS` are reported (see `Press`).
+
+use stm32f1::stm32f103::{gpioa, RCC};
+use crate::board;
+use crate::gpio::{self, GPIOExtras};
+
+/// Button level must be stable for that many consecutive scans to be accepted.
+const STABLE_SCANS: u8 = 3;
+/// Button held for that many scans is a long press (a second, with the 10ms scan period).
+const LONG_PRESS_SCANS: u16 = 100;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Press {
+    /// Press is confirmed.
+    Down,
+    /// Button is held down for `LONG_PRESS_SCANS` (reported once per press).
+    Long,
+    /// Release is confirmed.
+    Up,
+}
+
+pub struct Button {
+    port: &'static gpioa::RegisterBlock,
+    pressed: bool,
+    /// Consecutive scans with the level different from `pressed`.
+    changed: u8,
+    /// Scans since the press was confirmed.
+    held: u16,
+}
+
+impl Button {
+    /// Configure button pin as an input, with pull-up for active low button and pull-down
+    /// otherwise.
+    pub fn new(rcc: &RCC) -> Button {
+        let button = board::BUTTON;
+        let port = gpio::enable_port(rcc, button.port);
+        port.pin_config(button.index).input().pull_up_down();
+        port.write_pin(button.index, button.active_low);
+        Button {
+            port,
+            pressed: false,
+            changed: 0,
+            held: 0,
+        }
+    }
+
+    /// Sample the button, returns what happened to it.
+    pub fn scan(&mut self) -> Option<Press> {
+        let button = board::BUTTON;
+        let pressed = self.port.read_pin(button.index) == button.level(true);
+        if self.pressed {
+            self.held = self.held.saturating_add(1);
+        }
+        let long = (self.pressed && self.held == LONG_PRESS_SCANS).then_some(Press::Long);
+        if pressed == self.pressed {
+            self.changed = 0;
+            return long;
+        }
+
+        self.changed += 1;
+        if self.changed < STABLE_SCANS {
+            return long;
+        }
+        self.changed = 0;
+        self.pressed = pressed;
+        self.held = 0;
+        Some(if pressed { Press::Down } else { Press::Up })
+    }
+}
@@ -0,0 +1,26 @@
+//! Active buzzer (see `board::BUZZER`): it has its own oscillator, so it only needs to be switched
+//! on and off. Beep patterns are up to the caller.
+
+use stm32f1::stm32f103::{gpioa, RCC};
+use crate::board;
+use crate::gpio::{self, GPIOExtras};
+
+pub struct Buzzer {
+    port: &'static gpioa::RegisterBlock,
+}
+
+impl Buzzer {
+    /// Configure buzzer pin as an output, buzzer is off.
+    pub fn new(rcc: &RCC) -> Buzzer {
+        let buzzer = board::BUZZER;
+        let port = gpio::enable_port(rcc, buzzer.port);
+        port.write_pin(buzzer.index, buzzer.level(false));
+        port.pin_config(buzzer.index).push_pull().output2();
+        Buzzer { port }
+    }
+
+    pub fn set(&mut self, on: bool) {
+        let buzzer = board::BUZZER;
+        self.port.write_pin(buzzer.index, buzzer.level(on));
+    }
+}
@@ -0,0 +1,328 @@
+//! Fixed-point calculator: the expression on the first row, its value on the second one.
+//!
+//! There is no keypad on the board, so the expression is typed over serial (on this screen,
+//! serial input goes to the expression): digits, decimal point, `+ - * /` and parentheses,
+//! backspace deletes, `=` (or enter) evaluates, `c` (or escape) clears. Operator typed right after
+//! the value continues from it. Button and encoder are left for the navigation.
+//!
+//! Numbers are thousandths in an `i32` (up to 2147483.647), like the other fixed-point values in
+//! the demo; digits after the third decimal are dropped, and so are the ones of the quotient.
+//! Multiplication and division go before addition and subtraction.
+
+use core::fmt::{self, Write};
+use heapless::String;
+use crate::display::COLUMNS;
+use crate::fmt::write_fixed;
+use crate::text::{self, Align};
+use crate::ui::Event;
+
+/// Expression length, in characters.
+const EXPR_LEN: usize = 32;
+/// One in thousandths.
+const ONE: i64 = 1000;
+/// Parentheses nested deeper are a syntax error (keeps the recursion bounded).
+const MAX_DEPTH: u8 = 8;
+
+/// Expressions which have no value.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    Syntax,
+    DivByZero,
+    /// Value (or any intermediate one) does not fit.
+    Overflow,
+}
+
+impl Error {
+    fn message(self) -> &'static str {
+        match self {
+            Error::Syntax => "Syntax error",
+            Error::DivByZero => "Div by zero",
+            Error::Overflow => "Overflow",
+        }
+    }
+}
+
+/// Evaluate the expression, value is in thousandths.
+pub fn evaluate(expr: &str) -> Result<i32, Error> {
+    let mut parser = Parser { bytes: expr.as_bytes(), pos: 0, depth: 0 };
+    let value = parser.sum()?;
+    if parser.pos != parser.bytes.len() {
+        return Err(Error::Syntax);
+    }
+    Ok(value as i32)
+}
+
+/// Recursive descent parser, evaluates as it goes. All values returned fit into `i32`.
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// Parentheses open.
+    depth: u8,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    /// Terms added or subtracted.
+    fn sum(&mut self) -> Result<i64, Error> {
+        let mut value = self.product()?;
+        while let Some(op @ (b'+' | b'-')) = self.peek() {
+            self.pos += 1;
+            let term = self.product()?;
+            value = checked(if op == b'+' { value + term } else { value - term })?;
+        }
+        Ok(value)
+    }
+
+    /// Factors multiplied or divided.
+    fn product(&mut self) -> Result<i64, Error> {
+        let mut value = self.factor()?;
+        while let Some(op @ (b'*' | b'/')) = self.peek() {
+            self.pos += 1;
+            let factor = self.factor()?;
+            value = if op == b'*' {
+                checked(value * factor / ONE)?
+            } else if factor == 0 {
+                return Err(Error::DivByZero);
+            } else {
+                checked(value * ONE / factor)?
+            };
+        }
+        Ok(value)
+    }
+
+    /// Number, negated factor or expression in parentheses.
+    fn factor(&mut self) -> Result<i64, Error> {
+        match self.peek() {
+            Some(b'-') => {
+                self.pos += 1;
+                checked(-self.factor()?)
+            }
+            Some(b'(') if self.depth < MAX_DEPTH => {
+                self.pos += 1;
+                self.depth += 1;
+                let value = self.sum()?;
+                if self.peek() != Some(b')') {
+                    return Err(Error::Syntax);
+                }
+                self.pos += 1;
+                self.depth -= 1;
+                Ok(value)
+            }
+            _ => self.number(),
+        }
+    }
+
+    /// Decimal number (`12`, `3.25`, `.5`).
+    fn number(&mut self) -> Result<i64, Error> {
+        let start = self.pos;
+        let mut value: i64 = 0;
+        let mut scale = ONE;
+        let mut point = false;
+        while let Some(c) = self.peek() {
+            match c {
+                b'0'..=b'9' if !point => value = checked(value * 10 + i64::from(c - b'0') * ONE)?,
+                b'0'..=b'9' => {
+                    scale /= 10;
+                    value += i64::from(c - b'0') * scale;
+                }
+                b'.' if !point => point = true,
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        // Point alone is not a number
+        if self.pos - start == usize::from(point) {
+            return Err(Error::Syntax);
+        }
+        checked(value)
+    }
+}
+
+/// Value, if it fits.
+fn checked(value: i64) -> Result<i64, Error> {
+    if i32::try_from(value).is_ok() {
+        Ok(value)
+    } else {
+        Err(Error::Overflow)
+    }
+}
+
+/// Write the value with as few decimals as needed (`2.5`, `-3`).
+fn write_value<W: Write>(w: &mut W, value: i32) -> fmt::Result {
+    let mut decimals = 3;
+    while decimals > 0 && value % 10i32.pow(u32::from(4 - decimals)) == 0 {
+        decimals -= 1;
+    }
+    write_fixed(w, value, decimals)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Calculator {
+    expr: [u8; EXPR_LEN],
+    len: usize,
+    /// Value of the expression, once evaluated (typing goes on with a new expression then).
+    value: Option<Result<i32, Error>>,
+}
+
+impl Default for Calculator {
+    fn default() -> Calculator {
+        Calculator {
+            expr: [b' '; EXPR_LEN],
+            len: 0,
+            value: None,
+        }
+    }
+}
+
+impl Calculator {
+    /// Expression typed.
+    pub fn expr(&self) -> &str {
+        // Only ASCII characters are ever put into the expression
+        core::str::from_utf8(&self.expr[..self.len]).unwrap_or_default()
+    }
+
+    /// Append characters to the expression, the ones which do not fit are dropped.
+    fn push(&mut self, chars: &[u8]) {
+        let count = chars.len().min(EXPR_LEN - self.len);
+        self.expr[self.len..self.len + count].copy_from_slice(&chars[..count]);
+        self.len += count;
+    }
+
+    /// Value of the expression, once evaluated.
+    pub fn value(&self) -> Option<Result<i32, Error>> {
+        self.value
+    }
+
+    /// Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        let Event::Serial(c) = event else {
+            return false;
+        };
+        match c {
+            b'=' | b'\r' | b'\n' if self.len > 0 => self.value = Some(evaluate(self.expr())),
+            b'c' | b'C' | 0x1b => *self = Calculator::default(),
+            0x08 | 0x7f => {
+                self.value = None;
+                self.len = self.len.saturating_sub(1);
+            }
+            b'0'..=b'9' | b'.' | b'(' | b')' | b'+' | b'-' | b'*' | b'/' => {
+                if let Some(value) = self.value.take() {
+                    self.len = 0;
+                    // Operator continues from the value
+                    if let (Ok(value), b'+' | b'-' | b'*' | b'/') = (value, c) {
+                        let mut buf: String<COLUMNS> = String::new();
+                        // Cannot fail, the longest value (`-2147483.648`) fits
+                        let _ = write_value(&mut buf, value);
+                        self.push(buf.as_bytes());
+                    }
+                }
+                self.push(&[c]);
+            }
+            // Anything else (navigation keys included) is swallowed, as it would go to the
+            // expression
+            _ => {}
+        }
+        true
+    }
+
+    /// Write the expression: its end with the cursor (`12*(3+4_`), or, once evaluated, without it
+    /// (`12*(3+4)`).
+    pub fn write_expr<W: Write>(&self, w: &mut W) -> fmt::Result {
+        let expr = self.expr();
+        if expr.is_empty() {
+            return w.write_str("Calc_");
+        }
+        let shown = if self.value.is_some() { COLUMNS - 1 } else { COLUMNS - 2 };
+        w.write_str(&expr[expr.len().saturating_sub(shown)..])?;
+        if self.value.is_none() {
+            w.write_char('_')?;
+        }
+        Ok(())
+    }
+
+    /// Write the value (or the error), right-aligned; nothing before the expression is evaluated.
+    pub fn write_value<W: Write>(&self, w: &mut W) -> fmt::Result {
+        match self.value {
+            Some(Ok(value)) => {
+                let mut buf: String<COLUMNS> = String::new();
+                write_value(&mut buf, value)?;
+                text::str(w, &buf, COLUMNS, Align::Right)
+            }
+            Some(Err(err)) => text::str(w, err.message(), COLUMNS, Align::Right),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn calculator() {
+        use crate::calculator::{evaluate, Error};
+        use crate::mock;
+        use crate::screens::{self, Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        // Fixed-point arithmetic, in thousandths
+        assert_eq!(evaluate("1+2*3"), Ok(7000));
+        assert_eq!(evaluate("(1+2)*3"), Ok(9000));
+        assert_eq!(evaluate("7/2"), Ok(3500));
+        assert_eq!(evaluate("1/3"), Ok(333));
+        assert_eq!(evaluate("-2.5*-.4"), Ok(1000));
+        assert_eq!(evaluate("10-4-3"), Ok(3000));
+        assert_eq!(evaluate("1.23456"), Ok(1234));
+        assert_eq!(evaluate("1/0"), Err(Error::DivByZero));
+        assert_eq!(evaluate("2000000+200000"), Err(Error::Overflow));
+        assert_eq!(evaluate("1500*1500"), Err(Error::Overflow));
+        assert_eq!(evaluate("2*"), Err(Error::Syntax));
+        assert_eq!(evaluate("(1+2"), Err(Error::Syntax));
+        assert_eq!(evaluate("1.2.3"), Err(Error::Syntax));
+        assert_eq!(evaluate("."), Err(Error::Syntax));
+        assert_eq!(evaluate("((((((((((1))))))))))"), Err(Error::Syntax));
+
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::Calculator, &Stats::default(), settings);
+        let type_text = |settings: &mut Settings, text: &str| {
+            for c in text.bytes() {
+                assert!(settings.handle(Screen::Calculator, Event::Serial(c)));
+            }
+        };
+
+        // Expression with the cursor, value once evaluated (as few decimals as needed)
+        assert_eq!(render(&settings), ("Calc_           ".into(), "                ".into()));
+        type_text(&mut settings, "12x*(3+5\x084)");
+        assert_eq!(render(&settings), ("12*(3+4)_       ".into(), "                ".into()));
+        type_text(&mut settings, "/5=");
+        assert_eq!(render(&settings), ("12*(3+4)/5      ".into(), "            16.8".into()));
+
+        // Operator continues from the value, digit starts over
+        type_text(&mut settings, "-0.3=");
+        assert_eq!(render(&settings), ("16.8-0.3        ".into(), "            16.5".into()));
+        type_text(&mut settings, "9/0\r");
+        assert_eq!(render(&settings), ("9/0             ".into(), "     Div by zero".into()));
+        type_text(&mut settings, "*2=");
+        assert_eq!(render(&settings).1, "    Syntax error");
+        type_text(&mut settings, "c");
+        assert_eq!(settings.calculator.expr(), "");
+
+        // Long expression shows its end
+        type_text(&mut settings, "1+2+3+4+5+6+7+8+9");
+        assert_eq!(render(&settings).0, "+3+4+5+6+7+8+9_ ");
+        type_text(&mut settings, "=");
+        assert_eq!(render(&settings), ("2+3+4+5+6+7+8+9 ".into(), "              45".into()));
+
+        // Button and encoder are left for the navigation
+        assert!(!settings.handle(Screen::Calculator, Event::Button));
+        assert!(!settings.handle(Screen::Calculator, Event::EncoderUp));
+        let mut ui = screens::navigation();
+        ui.handle(Event::Serial(b'='), &settings);
+        assert_eq!(ui.state(), Screen::Calculator);
+        ui.handle(Event::Button, &settings);
+        assert_eq!(ui.state(), Screen::Settings);
+        ui.handle(Event::EncoderDown, &settings);
+        assert_eq!(ui.state(), Screen::Calculator);
+    }
+}
@@ -0,0 +1,375 @@
+//! ADC calibration: offset and gain of the values read by the ADC (see `sensor`), the supply
+//! voltage, the chip temperature and, with the `backlight` feature, the ambient light. `sensor`
+//! goes by the typical figures of the datasheet, which are off by a few percent for VREFINT (so
+//! for the supply voltage), and by several degrees for the temperature sensor.
+//!
+//! Values are corrected as `(value - offset) * gain`, the gain in 16.16 fixed point, before they
+//! are shown (on the diagnostics screen and the ones fed with the temperature) or checked against
+//! the thresholds. Constants are kept in the key-value store (see `kv`) by the main loop.
+//!
+//! Calibration screen guides through it: button selects the channel, long press starts the
+//! calibration. First, the input is shorted to the ground, and the button takes the reading as the
+//! offset. Then a known reference is applied (or measured, for the supply voltage and the
+//! temperature), encoder sets its value, and the button takes the reading to find the gain. Long
+//! press cancels either step. Internal channels cannot be shorted, so the first step is skipped
+//! for them: supply voltage is measured against VREFINT, so its error is in the gain (offset stays
+//! zero), and the temperature sensor one is mostly in the offset (gain stays one).
+
+use core::fmt::{self, Write};
+use crate::display::COLUMNS;
+use crate::text::{self, Align};
+use crate::ui::Event;
+use crate::units::TempUnit;
+
+/// Gain of one, in 16.16 fixed point.
+const UNITY: u32 = 1 << 16;
+/// Gains taken, from half to double (anything else is a mistake in the setup).
+const MIN_GAIN: u32 = UNITY / 2;
+const MAX_GAIN: u32 = UNITY * 2;
+
+/// Value read by the ADC.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Channel {
+    /// Supply voltage, in millivolts.
+    Supply,
+    /// Chip temperature, in tenths of °C.
+    Temperature,
+    /// Ambient light, in ADC counts.
+    #[cfg(feature = "backlight")]
+    Light,
+}
+
+/// Number of channels.
+pub const CHANNELS: usize = Channel::ALL.len();
+
+/// How the constants are found.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Fit {
+    /// Gain from the reference, offset from the shorted input (zero if it cannot be shorted).
+    Gain,
+    /// Offset from the reference, gain of one.
+    Offset,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 2 + cfg!(feature = "backlight") as usize] = [
+        Channel::Supply,
+        Channel::Temperature,
+        #[cfg(feature = "backlight")]
+        Channel::Light,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Channel::Supply => "Supply",
+            Channel::Temperature => "Temp",
+            #[cfg(feature = "backlight")]
+            Channel::Light => "Light",
+        }
+    }
+
+    fn fit(self) -> Fit {
+        match self {
+            Channel::Temperature => Fit::Offset,
+            _ => Fit::Gain,
+        }
+    }
+
+    /// Can the input be shorted?
+    fn shorted(self) -> bool {
+        match self {
+            #[cfg(feature = "backlight")]
+            Channel::Light => true,
+            _ => false,
+        }
+    }
+
+    /// Encoder step of the reference.
+    fn step(self) -> i32 {
+        match self {
+            Channel::Supply => 10,
+            Channel::Temperature => 1,
+            #[cfg(feature = "backlight")]
+            Channel::Light => 10,
+        }
+    }
+
+    /// Write the `value` of the channel (`3.291V`, `23.5C`, `1234`), right-aligned to `width`.
+    fn write_value<W: Write>(self, w: &mut W, value: i32, unit: TempUnit, width: usize) -> fmt::Result {
+        match self {
+            Channel::Supply => {
+                text::fixed(w, value, 3, width - 1)?;
+                w.write_char('V')
+            }
+            Channel::Temperature => {
+                let value = value.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16;
+                text::fixed(w, unit.from_tenths_c(value), 1, width - 1)?;
+                w.write_str(unit.symbol())
+            }
+            #[cfg(feature = "backlight")]
+            Channel::Light => text::fixed(w, value * 1000, 0, width),
+        }
+    }
+}
+
+/// Calibration constants of a channel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Constants {
+    /// Value read at zero input, in the units of the channel.
+    pub offset: i32,
+    /// Gain, in 16.16 fixed point.
+    pub gain: u32,
+}
+
+impl Default for Constants {
+    fn default() -> Constants {
+        Constants { offset: 0, gain: UNITY }
+    }
+}
+
+impl Constants {
+    fn apply(self, value: i32) -> i32 {
+        (((i64::from(value) - i64::from(self.offset)) * i64::from(self.gain)) >> 16) as i32
+    }
+}
+
+/// Calibration step in progress.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Step {
+    /// Input is shorted, waiting for the button.
+    Short,
+    /// Reference is applied, encoder sets its value. Reading of the shorted input, if any.
+    Reference { zero: Option<i32>, value: i32 },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Calibration {
+    constants: [Constants; CHANNELS],
+    /// Latest readings, not corrected.
+    readings: [i32; CHANNELS],
+    /// Channel shown.
+    channel: Channel,
+    step: Option<Step>,
+    /// Last calibration was out of range (and was not taken).
+    failed: bool,
+}
+
+impl Default for Calibration {
+    fn default() -> Calibration {
+        Calibration {
+            constants: [Constants::default(); CHANNELS],
+            readings: [0; CHANNELS],
+            channel: Channel::Supply,
+            step: None,
+            failed: false,
+        }
+    }
+}
+
+impl Calibration {
+    /// Take the `reading` of the `channel`, returns it corrected.
+    pub fn update(&mut self, channel: Channel, reading: i32) -> i32 {
+        self.readings[channel as usize] = reading;
+        self.constants[channel as usize].apply(reading)
+    }
+
+    /// Constants of the `channel` (to keep them in flash).
+    pub fn constants(&self, channel: Channel) -> Constants {
+        self.constants[channel as usize]
+    }
+
+    /// Restore constants of the `channel` kept in flash. Gain out of range (erased flash, or
+    /// garbage) keeps the defaults.
+    pub fn restore(&mut self, channel: Channel, constants: Constants) {
+        if (MIN_GAIN..=MAX_GAIN).contains(&constants.gain) {
+            self.constants[channel as usize] = constants;
+        }
+    }
+
+    /// Button selects the channel (going on to the next screen after the last one), long press
+    /// starts the calibration or cancels it, button takes the readings and encoder sets the
+    /// reference while it is in progress.
+    pub fn handle(&mut self, event: Event) -> bool {
+        let reading = self.readings[self.channel as usize];
+        match (event, self.step) {
+            (Event::LongPress, None) => {
+                self.failed = false;
+                self.step = Some(if self.channel.shorted() {
+                    Step::Short
+                } else {
+                    Step::Reference { zero: None, value: self.corrected() }
+                });
+            }
+            (Event::LongPress, Some(_)) => self.step = None,
+            (Event::Button, None) => {
+                self.failed = false;
+                let next = Channel::ALL.get(self.channel as usize + 1).copied();
+                self.channel = next.unwrap_or(Channel::Supply);
+                return next.is_some();
+            }
+            (Event::Button, Some(Step::Short)) => {
+                self.step = Some(Step::Reference { zero: Some(reading), value: self.corrected() });
+            }
+            (Event::Button, Some(Step::Reference { zero, value })) => {
+                self.step = None;
+                match self.fit(zero, reading, value) {
+                    Some(constants) => self.constants[self.channel as usize] = constants,
+                    None => self.failed = true,
+                }
+            }
+            (Event::EncoderUp | Event::EncoderDown, Some(Step::Reference { zero, value })) => {
+                let step = if event == Event::EncoderUp { self.channel.step() } else { -self.channel.step() };
+                self.step = Some(Step::Reference { zero, value: value + step });
+            }
+            (Event::EncoderUp | Event::EncoderDown, Some(Step::Short)) => {}
+            _ => return false,
+        }
+        true
+    }
+
+    /// Latest reading of the channel shown, corrected.
+    fn corrected(&self) -> i32 {
+        self.constants[self.channel as usize].apply(self.readings[self.channel as usize])
+    }
+
+    /// Constants which turn the `reading` into the `reference` (and the `zero` reading into zero),
+    /// `None` if the gain is out of range.
+    fn fit(&self, zero: Option<i32>, reading: i32, reference: i32) -> Option<Constants> {
+        let constants = match self.channel.fit() {
+            Fit::Offset => Constants { offset: reading - reference, gain: UNITY },
+            Fit::Gain => {
+                let offset = zero.unwrap_or(0);
+                let span = i64::from(reading) - i64::from(offset);
+                if span <= 0 || reference <= 0 {
+                    return None;
+                }
+                let gain = (i64::from(reference) << 16) / span;
+                Constants { offset, gain: u32::try_from(gain).ok()? }
+            }
+        };
+        (MIN_GAIN..=MAX_GAIN).contains(&constants.gain).then_some(constants)
+    }
+
+    /// Write the channel and its corrected reading (`Supply     3.291V`), or the step in progress
+    /// (`Short input`, `Ref       3.300V`).
+    pub fn write_status<W: Write>(&self, w: &mut W, unit: TempUnit) -> fmt::Result {
+        let name = match self.step {
+            None => self.channel.name(),
+            Some(Step::Short) => return w.write_str("Short input"),
+            Some(Step::Reference { .. }) => "Ref",
+        };
+        let value = match self.step {
+            Some(Step::Reference { value, .. }) => value,
+            _ => self.corrected(),
+        };
+        w.write_str(name)?;
+        self.channel.write_value(w, value, unit, COLUMNS - 1 - name.len())
+    }
+
+    /// Write the constants of the channel (`   -1.2  x1.003`, the offset in the units of the
+    /// channel, in °C for the temperature), or the raw reading while the calibration is in progress
+    /// (`Reading   3.264V`).
+    pub fn write_page<W: Write>(&self, w: &mut W, unit: TempUnit) -> fmt::Result {
+        if self.step.is_some() {
+            w.write_str("Reading")?;
+            return self.channel.write_value(w, self.readings[self.channel as usize], unit, COLUMNS - 8);
+        }
+        if self.failed {
+            return text::str(w, "Out of range", COLUMNS - 1, Align::Left);
+        }
+        let constants = self.constants[self.channel as usize];
+        let (offset, decimals) = match self.channel {
+            Channel::Supply => (constants.offset, 3),
+            Channel::Temperature => (constants.offset * 100, 1),
+            #[cfg(feature = "backlight")]
+            Channel::Light => (constants.offset * 1000, 0),
+        };
+        text::fixed(w, offset, decimals, 7)?;
+        w.write_str("  x")?;
+        text::fixed(w, ((u64::from(constants.gain) * 1000) >> 16) as i32, 3, 6)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn calibration_wizard() {
+        use crate::calibration::{Calibration, Channel, Constants};
+        use crate::ui::Event;
+        use crate::units::TempUnit;
+
+        let rows = |calibration: &Calibration| {
+            let (mut status, mut page) = (std::string::String::new(), std::string::String::new());
+            calibration.write_status(&mut status, TempUnit::Celsius).unwrap();
+            calibration.write_page(&mut page, TempUnit::Celsius).unwrap();
+            (status, page)
+        };
+        let mut calibration = Calibration::default();
+        assert_eq!(calibration.update(Channel::Supply, 3200), 3200);
+        assert_eq!(rows(&calibration), ("Supply   3.200V".into(), "  0.000  x 1.000".into()));
+
+        // Supply voltage error is in the gain: the reference starts at the reading
+        assert!(calibration.handle(Event::LongPress));
+        for _ in 0..10 {
+            assert!(calibration.handle(Event::EncoderUp));
+        }
+        assert_eq!(rows(&calibration), ("Ref      3.300V".into(), "Reading  3.200V".into()));
+        assert!(calibration.handle(Event::Button));
+        assert_eq!(calibration.constants(Channel::Supply), Constants { offset: 0, gain: 67584 });
+        assert_eq!(calibration.update(Channel::Supply, 3200), 3300);
+        assert_eq!(rows(&calibration), ("Supply   3.300V".into(), "  0.000  x 1.031".into()));
+
+        // Gain out of range is not taken, long press cancels
+        assert!(calibration.handle(Event::LongPress));
+        for _ in 0..200 {
+            assert!(calibration.handle(Event::EncoderDown));
+        }
+        assert!(calibration.handle(Event::Button));
+        assert_eq!(rows(&calibration).1, "Out of range   ");
+        assert_eq!(calibration.constants(Channel::Supply).gain, 67584);
+        assert!(calibration.handle(Event::LongPress));
+        assert!(calibration.handle(Event::EncoderDown));
+        assert!(calibration.handle(Event::LongPress));
+        assert_eq!(calibration.constants(Channel::Supply).gain, 67584);
+
+        // Temperature sensor error is in the offset
+        assert!(calibration.handle(Event::Button));
+        assert_eq!(calibration.update(Channel::Temperature, 300), 300);
+        assert!(calibration.handle(Event::LongPress));
+        for _ in 0..50 {
+            assert!(calibration.handle(Event::EncoderDown));
+        }
+        assert_eq!(rows(&calibration).0, "Ref       25.0C");
+        assert!(calibration.handle(Event::Button));
+        assert_eq!(calibration.update(Channel::Temperature, 300), 250);
+        assert_eq!(rows(&calibration), ("Temp      25.0C".into(), "    5.0  x 1.000".into()));
+
+        // Light input is shorted first, for the offset
+        #[cfg(feature = "backlight")]
+        {
+            assert!(calibration.handle(Event::Button));
+            calibration.update(Channel::Light, 100);
+            assert!(calibration.handle(Event::LongPress));
+            assert_eq!(rows(&calibration), ("Short input".into(), "Reading     100".into()));
+            assert!(calibration.handle(Event::EncoderUp));
+            assert!(calibration.handle(Event::Button));
+            for _ in 0..90 {
+                assert!(calibration.handle(Event::EncoderUp));
+            }
+            assert_eq!(calibration.update(Channel::Light, 1300), 1300);
+            assert!(calibration.handle(Event::Button));
+            assert_eq!(calibration.constants(Channel::Light), Constants { offset: 100, gain: 54613 });
+            assert_eq!(calibration.update(Channel::Light, 1300), 999);
+        }
+        // Button goes on to the next screen after the last channel
+        assert!(!calibration.handle(Event::Button));
+        assert_eq!(rows(&calibration).0, "Supply   3.300V");
+
+        // Constants kept in flash, erased ones keep the defaults
+        let mut restored = Calibration::default();
+        restored.restore(Channel::Supply, calibration.constants(Channel::Supply));
+        restored.restore(Channel::Temperature, Constants { offset: -1, gain: u32::MAX });
+        assert_eq!(restored.update(Channel::Supply, 3200), 3300);
+        assert_eq!(restored.update(Channel::Temperature, 300), 300);
+    }
+}
@@ -0,0 +1,133 @@
+//! Clock configuration: SYSCLK is 72Mhz, driven by PLL from the HSE.
+
+use core::fmt;
+use stm32f1::stm32f103::{FLASH, RCC};
+use crate::board::{self, Hse};
+
+/// How many times to poll for oscillator / PLL to get ready before giving up
+const READY_ATTEMPTS: u32 = 100_000;
+
+pub const SYSCLK: u32 = 72_000_000;
+
+/// Internal RC oscillator frequency, SYSCLK runs from it after reset.
+pub const HSI_FREQ: u32 = 8_000_000;
+
+// PLL multiplier is fixed
+const _: () = assert!(board::HSE_FREQ * 9 == SYSCLK);
+
+/// Frequencies of the configured clocks
+#[derive(Copy, Clone, Debug)]
+pub struct Clocks {
+    pub sysclk: u32,
+    pub hclk: u32,
+    pub pclk1: u32,
+    pub pclk2: u32,
+}
+
+/// Clocks after reset (or after failed `setup`): everything runs from HSI.
+pub const HSI_CLOCKS: Clocks = Clocks {
+    sysclk: HSI_FREQ,
+    hclk: HSI_FREQ,
+    pclk1: HSI_FREQ,
+    pclk2: HSI_FREQ,
+};
+
+/// Clock configuration failure. SYSCLK is left running from HSI (see `HSI_CLOCKS`).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClockError {
+    /// External oscillator did not start (no crystal or no clock on OSC_IN).
+    HseNotReady,
+    /// PLL did not lock.
+    PllNotLocked,
+    /// SYSCLK did not switch to PLL.
+    SwitchFailed,
+}
+
+impl ClockError {
+    /// Short description, fits on the display.
+    pub fn message(self) -> &'static str {
+        match self {
+            ClockError::HseNotReady => "HSE not ready",
+            ClockError::PllNotLocked => "PLL not locked",
+            ClockError::SwitchFailed => "PLL switch fail",
+        }
+    }
+
+    /// Number of LED blinks used to report this error when display is not available.
+    pub fn code(self) -> u8 {
+        match self {
+            ClockError::HseNotReady => 1,
+            ClockError::PllNotLocked => 2,
+            ClockError::SwitchFailed => 3,
+        }
+    }
+}
+
+impl fmt::Display for ClockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+fn wait_ready<F: Fn() -> bool>(ready: F) -> bool {
+    (0..READY_ATTEMPTS).any(|_| ready())
+}
+
+/// Switch SYSCLK to PLL (72Mhz), driven by HSE. APB1 is 36Mhz (maximum allowed), APB2 is 72Mhz.
+///
+/// On failure, HSE and PLL are turned off and SYSCLK keeps running from HSI.
+pub fn setup(rcc: &RCC, flash: &FLASH) -> Result<Clocks, ClockError> {
+    let result = switch_to_pll(rcc, flash);
+    if result.is_err() {
+        rcc.cfgr.modify(|_, w| w.sw().hsi());
+        wait_ready(|| rcc.cfgr.read().sws().is_hsi());
+        rcc.cr.modify(|_, w| w.pllon().clear_bit());
+        rcc.cr.modify(|_, w| w.hseon().clear_bit().hsebyp().clear_bit());
+    }
+    result
+}
+
+fn switch_to_pll(rcc: &RCC, flash: &FLASH) -> Result<Clocks, ClockError> {
+    if board::HSE == Hse::Bypass {
+        rcc.cr.modify(|_, w| w.hsebyp().set_bit());
+    }
+    rcc.cr.modify(|_, w| w.hseon().set_bit());
+    if !wait_ready(|| rcc.cr.read().hserdy().bit_is_set()) {
+        return Err(ClockError::HseNotReady);
+    }
+
+    // Two wait states are required for SYSCLK above 48Mhz
+    flash.acr.modify(|_, w| w.prftbe().set_bit().latency().ws2());
+
+    // 8Mhz HSE * 9 = 72Mhz
+    rcc.cfgr.modify(|_, w| {
+        w.pllsrc()
+            .hse_div_prediv()
+            .pllxtpre()
+            .div1()
+            .pllmul()
+            .mul9()
+            .hpre()
+            .div1()
+            .ppre1()
+            .div2()
+            .ppre2()
+            .div1()
+    });
+    rcc.cr.modify(|_, w| w.pllon().set_bit());
+    if !wait_ready(|| rcc.cr.read().pllrdy().bit_is_set()) {
+        return Err(ClockError::PllNotLocked);
+    }
+
+    rcc.cfgr.modify(|_, w| w.sw().pll());
+    if !wait_ready(|| rcc.cfgr.read().sws().is_pll()) {
+        return Err(ClockError::SwitchFailed);
+    }
+
+    Ok(Clocks {
+        sysclk: SYSCLK,
+        hclk: SYSCLK,
+        pclk1: SYSCLK / 2,
+        pclk2: SYSCLK,
+    })
+}
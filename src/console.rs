@@ -0,0 +1,191 @@
+//! Scrolling console: text written to it goes to the bottom line, a new line pushes the old ones
+//! up, and the last `N` lines are kept in a ring (the oldest one is dropped). Display is a window
+//! of two of them, at the bottom, unless the encoder scrolled it back through the history (up goes
+//! back in time); it stays on the same lines then, while new ones come in.
+//!
+//! Console is a `fmt::Write`, so log records, shell output or anything else formatted could be
+//! written to it. Text wraps at the end of the line, `\n` starts a new line, `\r` is ignored,
+//! characters other than printable ASCII are shown as `?`. It is drawn by a screen (`write_row`),
+//! or copied to the remote text (`show`), like the terminal, to be shown instead of the screens.
+
+use core::fmt::{self, Write};
+use crate::display::{COLUMNS, ROWS};
+use crate::remote::RemoteText;
+use crate::ui::Event;
+
+pub struct Console<const N: usize> {
+    lines: [[u8; COLUMNS]; N],
+    /// Bottom line, the one written to.
+    last: usize,
+    /// Lines kept, up to `N` (bottom one included).
+    len: usize,
+    /// Column of the next character; `COLUMNS` after the last column is written (next character
+    /// wraps).
+    col: usize,
+    /// Bottom line of the window, counted back from the bottom line of the console.
+    back: usize,
+    /// Lines have changed since the last `show`.
+    changed: bool,
+}
+
+impl<const N: usize> Console<N> {
+    pub const fn new() -> Console<N> {
+        Console {
+            lines: [[b' '; COLUMNS]; N],
+            last: 0,
+            len: 1,
+            col: 0,
+            back: 0,
+            changed: false,
+        }
+    }
+
+    /// Lines kept.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 1 && self.col == 0
+    }
+
+    /// Line `back` lines above the bottom one, `None` if it is not kept.
+    pub fn line(&self, back: usize) -> Option<&str> {
+        let line = &self.lines[(self.last + N - back % N) % N];
+        // Only printable ASCII is stored
+        (back < self.len).then(|| unsafe { core::str::from_utf8_unchecked(line) })
+    }
+
+    /// Drop all the lines.
+    pub fn clear(&mut self) {
+        *self = Console::new();
+        self.changed = true;
+    }
+
+    /// Start a new line at the bottom, dropping the oldest one if all `N` are taken.
+    fn new_line(&mut self) {
+        self.last = (self.last + 1) % N;
+        self.lines[self.last] = [b' '; COLUMNS];
+        self.len = (self.len + 1).min(N);
+        self.col = 0;
+        if self.back > 0 {
+            self.back = (self.back + 1).min(self.len.saturating_sub(ROWS));
+        }
+    }
+
+    /// Encoder scrolls the window through the history, up goes back. Returns `true` if the event
+    /// was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        let oldest = self.len.saturating_sub(ROWS);
+        match event {
+            Event::EncoderUp => self.back = (self.back + 1).min(oldest),
+            Event::EncoderDown => self.back = self.back.saturating_sub(1),
+            _ => return false,
+        }
+        self.changed = true;
+        true
+    }
+
+    /// Write the `row` (0 or 1) of the window.
+    pub fn write_row<W: Write>(&self, w: &mut W, row: usize) -> fmt::Result {
+        let back = self.back + ROWS - 1 - row;
+        w.write_str(self.line(back).unwrap_or(""))
+    }
+
+    /// Copy the window to the remote text, if the lines have changed since the last call. Returns
+    /// `true` if they have.
+    pub fn show(&mut self, text: &mut RemoteText) -> bool {
+        if !core::mem::replace(&mut self.changed, false) {
+            return false;
+        }
+        for row in 0..ROWS {
+            text.set_cursor(if row == 0 { 0x00 } else { 0x40 });
+            let line = self.line(self.back + ROWS - 1 - row).unwrap_or("");
+            line.bytes().chain(core::iter::repeat(b' ')).take(COLUMNS).for_each(|byte| text.write(byte));
+        }
+        true
+    }
+}
+
+impl<const N: usize> Write for Console<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            match c {
+                '\n' => self.new_line(),
+                '\r' => {}
+                _ => {
+                    if self.col >= COLUMNS {
+                        self.new_line();
+                    }
+                    self.lines[self.last][self.col] = if c.is_ascii_graphic() || c == ' ' { c as u8 } else { b'?' };
+                    self.col += 1;
+                }
+            }
+        }
+        self.changed = true;
+        Ok(())
+    }
+}
+
+impl<const N: usize> Default for Console<N> {
+    fn default() -> Console<N> {
+        Console::new()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn console_scrolls() {
+        use core::fmt::Write;
+        use std::string::String;
+        use crate::console::Console;
+        use crate::remote::RemoteText;
+        use crate::ui::Event;
+
+        let window = |console: &Console<4>| {
+            let mut rows = (String::new(), String::new());
+            console.write_row(&mut rows.0, 0).unwrap();
+            console.write_row(&mut rows.1, 1).unwrap();
+            rows
+        };
+
+        // New text goes to the bottom line, pushing the old ones up
+        let mut console = Console::<4>::new();
+        assert!(console.is_empty());
+        write!(console, "boot\r\n").unwrap();
+        assert_eq!(window(&console), ("boot            ".into(), "                ".into()));
+        write!(console, "temp {}C", 23).unwrap();
+        assert_eq!(window(&console), ("boot            ".into(), "temp 23C        ".into()));
+
+        // Long lines wrap, the oldest lines are dropped
+        write!(console, "\nhello, wide world!\n\u{b0}").unwrap();
+        assert_eq!(console.len(), 4);
+        assert_eq!(console.line(0), Some("?               "));
+        assert_eq!(console.line(1), Some("d!              "));
+        assert_eq!(console.line(3), Some("temp 23C        "));
+        assert_eq!(console.line(4), None);
+
+        // Encoder scrolls back through the history, the window stays put while new lines come in
+        assert!(console.handle(Event::EncoderUp));
+        assert!(console.handle(Event::EncoderUp));
+        assert!(console.handle(Event::EncoderUp));
+        assert_eq!(window(&console), ("temp 23C        ".into(), "hello, wide worl".into()));
+        writeln!(console).unwrap();
+        assert_eq!(window(&console), ("hello, wide worl".into(), "d!              ".into()));
+        assert!(console.handle(Event::EncoderDown));
+        assert!(console.handle(Event::EncoderDown));
+        assert!(!console.handle(Event::Button));
+        assert_eq!(window(&console).1, "                ");
+
+        // Window is copied to the remote text only if it has changed
+        let mut text = RemoteText::new();
+        assert!(console.show(&mut text));
+        assert_eq!((text.row(0), text.row(1)), ("?               ", "                "));
+        assert!(!console.show(&mut text));
+        console.clear();
+        assert!(console.is_empty());
+        assert!(console.show(&mut text));
+        assert_eq!(text.row(0), "                ");
+    }
+}
@@ -0,0 +1,241 @@
+//! Countdown timer with an alarm.
+//!
+//! Time is set the way the inspector address is edited: turning the encoder starts editing the
+//! minutes, button moves to the seconds, and button on the seconds starts the countdown. Turning
+//! the encoder while counting cancels it. At zero, the alarm goes off (buzzer beeps and the time
+//! blinks, board has no backlight control to flash) until any button or encoder event, or for
+//! `ALARM_MS`. Countdown keeps running while other screens are shown.
+//!
+//! Like the stopwatch, time is passed to `advance`, which must be called before every event and
+//! every render (and often enough for the beeps, see `buzzing`).
+
+use core::fmt::{self, Write};
+use crate::big;
+use crate::ui::Event;
+
+/// Alarm goes silent by itself after this long, in milliseconds.
+pub const ALARM_MS: u32 = 30_000;
+/// Beep (and blink) period, in milliseconds, half of it on.
+const BEEP_MS: u32 = 500;
+
+/// Field being edited.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Field {
+    Minutes,
+    Seconds,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    Stopped,
+    Running { remaining_ms: u32 },
+    Alarm { since_ms: u32 },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Countdown {
+    /// Time set, up to 99:59.
+    minutes: u8,
+    seconds: u8,
+    cursor: Option<Field>,
+    state: State,
+    /// Timer value of the last `advance`.
+    last_ms: u32,
+}
+
+impl Default for Countdown {
+    /// Five minutes.
+    fn default() -> Countdown {
+        Countdown {
+            minutes: 5,
+            seconds: 0,
+            cursor: None,
+            state: State::Stopped,
+            last_ms: 0,
+        }
+    }
+}
+
+impl Countdown {
+    /// Account the time passed since the last call (`now_ms` is the millisecond timer, which can
+    /// wrap around).
+    pub fn advance(&mut self, now_ms: u32) {
+        let passed = now_ms.wrapping_sub(self.last_ms);
+        self.last_ms = now_ms;
+        self.state = match self.state {
+            State::Running { remaining_ms } if remaining_ms > passed => State::Running { remaining_ms: remaining_ms - passed },
+            State::Running { remaining_ms } => State::Alarm { since_ms: passed - remaining_ms },
+            State::Alarm { since_ms } if since_ms.saturating_add(passed) < ALARM_MS => State::Alarm { since_ms: since_ms + passed },
+            State::Alarm { .. } | State::Stopped => State::Stopped,
+        };
+    }
+
+    pub fn is_running(&self) -> bool {
+        matches!(self.state, State::Running { .. })
+    }
+
+    /// Should the buzzer be on now? Buzzer beeps while the alarm is on.
+    pub fn buzzing(&self) -> bool {
+        match self.state {
+            State::Alarm { since_ms } => since_ms % BEEP_MS < BEEP_MS / 2,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        let step = match (self.state, event) {
+            (State::Alarm { .. }, Event::Button | Event::EncoderUp | Event::EncoderDown) => {
+                self.state = State::Stopped;
+                return true;
+            }
+            (State::Running { .. }, Event::EncoderUp | Event::EncoderDown) => {
+                self.state = State::Stopped;
+                return true;
+            }
+            (State::Stopped, Event::Button) => {
+                match self.cursor {
+                    Some(Field::Minutes) => self.cursor = Some(Field::Seconds),
+                    Some(Field::Seconds) => {
+                        self.cursor = None;
+                        let set_ms = (u32::from(self.minutes) * 60 + u32::from(self.seconds)) * 1000;
+                        if set_ms > 0 {
+                            self.state = State::Running { remaining_ms: set_ms };
+                        }
+                    }
+                    None => return false,
+                }
+                return true;
+            }
+            (State::Stopped, Event::EncoderUp) => 1,
+            (State::Stopped, Event::EncoderDown) => -1,
+            _ => return false,
+        };
+        match *self.cursor.get_or_insert(Field::Minutes) {
+            Field::Minutes => self.minutes = (self.minutes as i8 + step).rem_euclid(100) as u8,
+            Field::Seconds => self.seconds = (self.seconds as i8 + step).rem_euclid(60) as u8,
+        }
+        true
+    }
+
+    /// Time shown, in seconds: time left (rounded up, so zero is only shown when the time is up)
+    /// or the time set.
+    fn shown_secs(&self) -> u32 {
+        match self.state {
+            State::Stopped => u32::from(self.minutes) * 60 + u32::from(self.seconds),
+            State::Running { remaining_ms } => remaining_ms.div_ceil(1000),
+            State::Alarm { .. } => 0,
+        }
+    }
+
+    /// Write the given row of the screen: time in big digits (`1 2:0 5`), or, while editing, the
+    /// time with the edited field in brackets (`Set [12]:05`) in the first row. During the alarm,
+    /// time blinks.
+    pub fn write_row<W: Write>(&self, w: &mut W, row: usize) -> fmt::Result {
+        if let Some(field) = self.cursor {
+            if row == 0 {
+                w.write_str("Set ")?;
+                write_field(w, self.minutes, field == Field::Minutes)?;
+                w.write_char(':')?;
+                write_field(w, self.seconds, field == Field::Seconds)?;
+            }
+            return Ok(());
+        }
+        if let State::Alarm { since_ms } = self.state {
+            if since_ms % BEEP_MS >= BEEP_MS / 2 {
+                return Ok(());
+            }
+        }
+        let secs = self.shown_secs();
+        let digit = |value: u32| b'0' + value as u8;
+        // Digits are spaced apart, so 15 columns are taken (last one is left for the flag)
+        let text = [digit(secs / 600), b' ', digit(secs / 60 % 10), b':', digit(secs % 60 / 10), b' ', digit(secs % 10)];
+        // Only ASCII digits, spaces and colon
+        big::write_row(w, core::str::from_utf8(&text).unwrap_or(""), row)
+    }
+}
+
+/// Write two-digit value, in brackets if it is being edited.
+fn write_field<W: Write>(w: &mut W, value: u8, edited: bool) -> fmt::Result {
+    if edited {
+        w.write_char('[')?;
+    }
+    w.write_char((b'0' + value / 10) as char)?;
+    w.write_char((b'0' + value % 10) as char)?;
+    if edited {
+        w.write_char(']')?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn countdown_alarm() {
+        use crate::countdown::ALARM_MS;
+        use crate::mock;
+        use crate::screens::{Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        let mut settings = Settings::default();
+        let event = |settings: &mut Settings, now_ms: u32, event: Event| {
+            settings.advance(now_ms);
+            settings.handle(Screen::Countdown, event)
+        };
+        let render = |settings: &mut Settings, now_ms: u32| {
+            settings.advance(now_ms);
+            mock::render(Screen::Countdown, &Stats::default(), settings)
+        };
+
+        // Big digits are made of custom characters
+        assert_eq!(render(&mut settings, 0), ("### ###.### ### ".into(), "### ###.### ### ".into()));
+        assert!(!event(&mut settings, 0, Event::Button));
+
+        // Encoder starts editing the minutes, button moves to the seconds and then starts
+        assert!(event(&mut settings, 0, Event::EncoderDown));
+        assert!(event(&mut settings, 0, Event::EncoderDown));
+        assert_eq!(render(&mut settings, 0), ("Set [03]:00     ".into(), "                ".into()));
+        assert!(event(&mut settings, 0, Event::Button));
+        assert!(event(&mut settings, 0, Event::EncoderDown));
+        assert_eq!(render(&mut settings, 0).0, "Set 03:[59]     ");
+        assert!(event(&mut settings, 1000, Event::Button));
+        assert!(settings.countdown.is_running());
+
+        // Counting goes on on other screens, button is left for the navigation
+        assert!(!event(&mut settings, 2000, Event::Button));
+        settings.advance(1000 + 238_900);
+        assert!(settings.countdown.is_running());
+        assert!(!settings.countdown.buzzing());
+        settings.advance(1000 + 239_100);
+        assert!(settings.countdown.buzzing());
+        assert_eq!(render(&mut settings, 1000 + 239_200).0, "### ###.### ### ");
+        settings.advance(1000 + 239_300);
+        assert!(!settings.countdown.buzzing());
+        // Time blinks in sync with the beeps
+        assert_eq!(render(&mut settings, 1000 + 239_400), ("                ".into(), "                ".into()));
+
+        // Any event silences the alarm, time set is kept
+        assert!(event(&mut settings, 1000 + 239_500, Event::Button));
+        assert!(!settings.countdown.buzzing());
+        assert!(!event(&mut settings, 1000 + 239_600, Event::Button));
+
+        // Alarm goes silent by itself
+        assert!(event(&mut settings, 300_000, Event::EncoderUp));
+        assert!(event(&mut settings, 300_000, Event::EncoderDown));
+        assert!(event(&mut settings, 300_000, Event::Button));
+        assert!(event(&mut settings, 300_000, Event::Button));
+        settings.advance(300_000 + 239_000);
+        settings.advance(300_000 + 239_000 + ALARM_MS / 2);
+        assert!(settings.countdown.buzzing());
+        settings.advance(300_000 + 239_000 + ALARM_MS);
+        assert!(!settings.countdown.buzzing());
+        assert!(!settings.countdown.is_running());
+
+        // Encoder cancels the countdown
+        assert!(event(&mut settings, 600_000, Event::EncoderUp));
+        assert!(event(&mut settings, 600_000, Event::Button));
+        assert!(event(&mut settings, 600_000, Event::Button));
+        assert!(event(&mut settings, 610_000, Event::EncoderUp));
+        assert!(!settings.countdown.is_running());
+    }
+}
@@ -0,0 +1,17 @@
+//! CRC-32 of the records kept in flash (see `kv` and `access_log`), computed the way the CRC unit
+//! of the F103 does it: polynomial 0x04c11db7, all ones to start with, 32-bit words fed most
+//! significant bit first, no reflection and no final xor (CRC-32/MPEG-2 of the words, big-endian).
+//!
+//! On the board, records are checked by the CRC unit (see `storage`), this software one is the
+//! default for the areas which do not have it (the host tests).
+
+const POLYNOMIAL: u32 = 0x04c1_1db7;
+
+/// CRC-32 of the `words`.
+pub fn software(words: &[u32]) -> u32 {
+    words.iter().fold(u32::MAX, |crc, &word| {
+        (0..32).fold(crc ^ word, |crc, _| {
+            if crc & 0x8000_0000 != 0 { (crc << 1) ^ POLYNOMIAL } else { crc << 1 }
+        })
+    })
+}
@@ -0,0 +1,69 @@
+//! Busy-wait delays based on the DWT cycle counter.
+//!
+//! SysTick is used as a millisecond time base (see `time`), so delays use the cycle counter, which
+//! keeps working with interrupts disabled (in fault handlers).
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use cortex_m::peripheral::{DCB, DWT};
+
+/// Cycle counter ticks per microsecond. Core runs at 8Mhz from HSI with default clock settings.
+static TICKS_PER_US: AtomicU32 = AtomicU32::new(8);
+
+/// Busy-wait delays. Cycle counter is never reconfigured once enabled, so `CycleDelay` itself is
+/// just a token and could be freely copied.
+#[derive(Clone, Copy)]
+pub struct CycleDelay {
+    _private: (),
+}
+
+impl CycleDelay {
+    /// Enable the cycle counter. `hclk` is the core clock frequency.
+    pub fn new(mut dcb: DCB, mut dwt: DWT, hclk: u32) -> CycleDelay {
+        set_hclk(hclk);
+        CycleDelay::ensure_running(&mut dcb, &mut dwt)
+    }
+
+    /// Make sure cycle counter is running. Only for fault handlers, which could run both before
+    /// and after delays are configured via `new`.
+    pub fn ensure_running(dcb: &mut DCB, dwt: &mut DWT) -> CycleDelay {
+        dcb.enable_trace();
+        dwt.enable_cycle_counter();
+        CycleDelay { _private: () }
+    }
+
+    /// Delay for a given amount of microseconds. Should not be used for precise delays.
+    /// `delay` must be less than 2^32 cycles, which is 59s with 72Mhz core clock.
+    pub fn delay_us(&self, delay: u32) {
+        let ticks = delay * TICKS_PER_US.load(Ordering::Relaxed);
+        let start = DWT::cycle_count();
+        while DWT::cycle_count().wrapping_sub(start) < ticks {}
+    }
+}
+
+/// Microseconds elapsed since the cycle counter value `start` (must be less than 2^32 cycles ago).
+pub fn since_us(start: u32) -> u32 {
+    DWT::cycle_count().wrapping_sub(start) / TICKS_PER_US.load(Ordering::Relaxed)
+}
+
+/// Update core clock frequency, must be called every time clocks are reconfigured.
+pub fn set_hclk(hclk: u32) {
+    TICKS_PER_US.store(hclk / 1_000_000, Ordering::Relaxed);
+}
+
+impl lcd::Delay for CycleDelay {
+    fn delay_us(&mut self, delay_usec: u32) {
+        CycleDelay::delay_us(self, delay_usec);
+    }
+}
+
+/// Timestamps are cycle counter values, so intervals must be less than 2^32 cycles.
+#[cfg(feature = "bench")]
+impl crate::bench::Clock for CycleDelay {
+    fn now(&self) -> u32 {
+        DWT::cycle_count()
+    }
+
+    fn since_us(&self, start: u32) -> u32 {
+        since_us(start)
+    }
+}
@@ -0,0 +1,150 @@
+//! Dice roller: the result in big digits, with the die on the left.
+//!
+//! Turning the encoder up rolls the die (numbers come from the [`random`](crate::random)
+//! generator), turning it down switches to the next die size. Button is left for the navigation.
+
+use core::fmt::{self, Write};
+use heapless::String;
+use crate::big;
+use crate::random;
+use crate::text::{self, Align};
+use crate::ui::Event;
+
+/// Die sizes to choose from, with their names.
+const DICE: [(u32, &str); 7] = [(4, "d4"), (6, "d6"), (8, "d8"), (10, "d10"), (12, "d12"), (20, "d20"), (100, "d100")];
+/// Die selected by default (six sides).
+const DEFAULT_DIE: usize = 1;
+/// Digits of the largest result.
+const DIGITS: usize = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Dice {
+    /// Index into `DICE`.
+    die: usize,
+    /// Last result, if rolled with this die.
+    result: Option<u32>,
+    /// Rolls done with this die.
+    rolls: u32,
+}
+
+impl Default for Dice {
+    fn default() -> Dice {
+        Dice {
+            die: DEFAULT_DIE,
+            result: None,
+            rolls: 0,
+        }
+    }
+}
+
+impl Dice {
+    /// Number of sides of the die selected.
+    pub fn sides(&self) -> u32 {
+        DICE[self.die].0
+    }
+
+    /// Last result, if any.
+    pub fn result(&self) -> Option<u32> {
+        self.result
+    }
+
+    /// Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        match event {
+            Event::EncoderUp => {
+                self.result = Some(random::below(self.sides()) + 1);
+                self.rolls += 1;
+            }
+            Event::EncoderDown => {
+                *self = Dice {
+                    die: (self.die + 1) % DICE.len(),
+                    ..Dice::default()
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Write the given row of the screen: the die (`d20`) or the number of rolls (`#  3`) on the
+    /// left, the result in big digits on the right (`1 7`, 15 columns in total).
+    pub fn write_row<W: Write>(&self, w: &mut W, row: usize) -> fmt::Result {
+        if row == 0 {
+            text::str(w, DICE[self.die].1, 4, Align::Left)?;
+        } else {
+            w.write_char('#')?;
+            text::uint(w, self.rolls % 1000, 3)?;
+        }
+        let Some(result) = self.result else {
+            return Ok(());
+        };
+        // Digits are spaced apart, missing ones are blank (as wide as a digit)
+        let mut digits: String<{ DIGITS * (big::DIGIT_WIDTH + 1) }> = String::new();
+        for place in (0..DIGITS as u32).rev() {
+            let digit = result / 10u32.pow(place) % 10;
+            if place + 1 < DIGITS as u32 {
+                digits.push(' ').map_err(|_| fmt::Error)?;
+            }
+            if result < 10u32.pow(place) && place > 0 {
+                digits.push_str("   ").map_err(|_| fmt::Error)?;
+            } else {
+                digits.push(char::from(b'0' + digit as u8)).map_err(|_| fmt::Error)?;
+            }
+        }
+        big::write_row(w, &digits, row)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn dice_rolls() {
+        use crate::mock;
+        use crate::random;
+        use crate::screens::{self, Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        // Every side comes up about as often as the others
+        let mut counts = [0; 6];
+        for _ in 0..6000 {
+            counts[random::below(6) as usize] += 1;
+        }
+        assert!(counts.iter().all(|&count| (800..1200).contains(&count)), "{:?}", counts);
+        assert_eq!(random::below(0), 0);
+
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::Dice, &Stats::default(), settings);
+
+        assert_eq!(render(&settings), ("d6              ".into(), "#  0            ".into()));
+        assert!(!settings.handle(Screen::Dice, Event::Button));
+        for roll in 1..=20 {
+            assert!(settings.handle(Screen::Dice, Event::EncoderUp));
+            assert!((1..=6).contains(&settings.dice.result().unwrap()));
+            assert_eq!(render(&settings).1[..4], format!("#{:3}", roll));
+        }
+        // Single big digit on the right
+        assert_eq!(render(&settings).0[4..], *"        ### ");
+
+        // Encoder down goes through the dice
+        for sides in [8, 10, 12, 20, 100, 4, 6, 8, 10, 12, 20, 100] {
+            assert!(settings.handle(Screen::Dice, Event::EncoderDown));
+            assert_eq!(settings.dice.sides(), sides);
+            assert_eq!(settings.dice.result(), None);
+        }
+        while settings.dice.result() != Some(100) {
+            assert!(settings.handle(Screen::Dice, Event::EncoderUp));
+            assert!((1..=100).contains(&settings.dice.result().unwrap()));
+        }
+        assert_eq!(render(&settings).0, "d100##  ### ### ");
+
+        // Dice screen goes after the snake one
+        let mut ui = screens::navigation();
+        ui.handle(Event::Serial(b'k'), &settings);
+        ui.handle(Event::Button, &settings);
+        assert_eq!(ui.state(), Screen::Dice);
+        ui.handle(Event::Button, &settings);
+        assert_eq!(ui.state(), Screen::Morse);
+        ui.handle(Event::EncoderDown, &settings);
+        assert_eq!(ui.state(), Screen::Dice);
+    }
+}
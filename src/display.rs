@@ -0,0 +1,236 @@
+//! Text display interface used by the screens. Implemented both by the blocking `lcd::Display` and
+//! by the queued driver (`queue::Writer`).
+
+use core::fmt::{self, Write};
+use heapless::String;
+use lcd::Display;
+
+/// Display width, in characters.
+pub const COLUMNS: usize = 16;
+/// Display height, in characters.
+pub const ROWS: usize = 2;
+
+pub trait TextDisplay: fmt::Write {
+    /// Clear the display and move cursor to the top left corner.
+    fn clear(&mut self) -> fmt::Result;
+
+    /// Move cursor to the given position.
+    fn position(&mut self, col: u8, row: u8) -> fmt::Result;
+
+    /// Upload custom character image (5x8, row by row) to the given location (0-7). Text written
+    /// afterwards must be positioned first (address counter is left in CGRAM).
+    fn upload(&mut self, location: u8, map: [u8; 8]) -> fmt::Result;
+
+    /// Write formatted text at the given position. Text which does not fit into the row is
+    /// truncated (otherwise it would go to the invisible part of the display memory or wrap to the
+    /// other row). Fails if position is outside of the display.
+    fn write_at(&mut self, col: u8, row: u8, args: fmt::Arguments) -> fmt::Result {
+        if usize::from(col) >= COLUMNS || usize::from(row) >= ROWS {
+            return Err(fmt::Error);
+        }
+        let mut text = Truncated {
+            text: String::new(),
+            limit: COLUMNS - usize::from(col),
+        };
+        text.write_fmt(args)?;
+        self.position(col, row)?;
+        self.write_str(&text.text)
+    }
+}
+
+/// Display which is not connected: everything written to it is dropped.
+pub struct NoDisplay;
+
+impl Write for NoDisplay {
+    fn write_str(&mut self, _s: &str) -> fmt::Result {
+        Ok(())
+    }
+}
+
+impl TextDisplay for NoDisplay {
+    fn clear(&mut self) -> fmt::Result {
+        Ok(())
+    }
+
+    fn position(&mut self, _col: u8, _row: u8) -> fmt::Result {
+        Ok(())
+    }
+
+    fn upload(&mut self, _location: u8, _map: [u8; 8]) -> fmt::Result {
+        Ok(())
+    }
+}
+
+/// Keeps first `limit` characters of the text, drops the rest.
+struct Truncated {
+    text: String<COLUMNS>,
+    limit: usize,
+}
+
+impl Write for Truncated {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.text.len() >= self.limit {
+                break;
+            }
+            // Cannot fail, `limit` is not more than the capacity
+            self.text.push(c).ok();
+        }
+        Ok(())
+    }
+}
+
+impl<HW: lcd::Hardware + lcd::Delay> TextDisplay for Display<HW> {
+    fn clear(&mut self) -> fmt::Result {
+        Display::clear(self);
+        Ok(())
+    }
+
+    fn position(&mut self, col: u8, row: u8) -> fmt::Result {
+        Display::position(self, col, row);
+        Ok(())
+    }
+
+    fn upload(&mut self, location: u8, map: [u8; 8]) -> fmt::Result {
+        if location > 7 {
+            return Err(fmt::Error);
+        }
+        self.upload_character(location, map);
+        Ok(())
+    }
+}
+
+/// Staging buffer for a single row. Text is formatted into the buffer first and sent to the
+/// display in one burst by `finish`, so the row does not appear character by character while
+/// formatting is still in progress.
+pub struct Line<'a, D: TextDisplay> {
+    display: &'a mut D,
+    row: u8,
+    text: String<COLUMNS>,
+    flag: Option<char>,
+    indicator: Option<char>,
+}
+
+impl<'a, D: TextDisplay> Line<'a, D> {
+    pub fn new(display: &'a mut D, row: u8) -> Line<'a, D> {
+        Line {
+            display,
+            row,
+            text: String::new(),
+            flag: None,
+            indicator: None,
+        }
+    }
+
+    /// Show `flag` in the last column of the row, over the text.
+    pub fn flag(&mut self, flag: char) {
+        self.flag = Some(flag);
+    }
+
+    /// Show `indicator` in the last column of the row, if the text leaves it blank (and there is
+    /// no flag).
+    pub fn indicator(&mut self, indicator: char) {
+        self.indicator = Some(indicator);
+    }
+
+    /// Write the whole row to the display, padded with spaces to the display width.
+    pub fn finish(mut self) -> fmt::Result {
+        while self.text.push(' ').is_ok() {}
+        let blank = self.text.ends_with(' ');
+        if let Some(flag) = self.flag.or(self.indicator.filter(|_| blank)) {
+            self.text.pop();
+            self.text.push(flag).ok();
+        }
+        self.display.position(0, self.row)?;
+        self.display.write_str(&self.text)
+    }
+}
+
+impl<D: TextDisplay> Write for Line<'_, D> {
+    /// Fails if text does not fit into the row.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.text.push_str(s).map_err(|_| fmt::Error)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn rows_are_written_in_one_burst() {
+        use lcd::Display;
+        use core::fmt::Write;
+        use crate::display::Line;
+        use crate::hardware::LcdHardware;
+        use crate::mock::{command, data, init, join_nibbles, MockHardware};
+
+        let mock = MockHardware::new();
+        init(&mock);
+        mock.reset();
+
+        let mut display = Display::new(LcdHardware::new(&mock, &mock));
+        let mut line = Line::new(&mut display, 1);
+        write!(line, "{}:{}", 12, 34).unwrap();
+        // Nothing is sent until the row is complete
+        assert!(mock.transfers().is_empty());
+        line.finish().unwrap();
+
+        let mut expected = vec![command(0x80 | 0x40)];
+        expected.extend(b"12:34           ".iter().map(|&b| data(b)));
+        assert_eq!(join_nibbles(&mock.transfers()), expected);
+    }
+
+    #[test]
+    fn indicator_only_in_blank_corner() {
+        use lcd::Display;
+        use core::fmt::Write;
+        use crate::display::Line;
+        use crate::hardware::LcdHardware;
+        use crate::mock::{init, MockHardware};
+        use crate::sim::Hd44780;
+
+        let mock = MockHardware::new();
+        init(&mock);
+        let mut display = Display::new(LcdHardware::new(&mock, &mock));
+        let mut lcd = Hd44780::new();
+        let mut row = |text: &str, flag: Option<char>, indicator: char| {
+            let mut line = Line::new(&mut display, 0);
+            line.write_str(text).unwrap();
+            flag.into_iter().for_each(|flag| line.flag(flag));
+            line.indicator(indicator);
+            line.finish().unwrap();
+            lcd.feed(&mock.transfers());
+            mock.reset();
+            lcd.row(0, 16)
+        };
+
+        // Flag goes over the text, indicator only into the blank corner, flag takes precedence
+        assert_eq!(row("Hello!", None, '#'), "Hello!         #");
+        assert_eq!(row("0123456789abcdef", None, '#'), "0123456789abcdef");
+        assert_eq!(row("0123456789abcdef", Some('!'), '#'), "0123456789abcde!");
+        assert_eq!(row("Hello!", Some('!'), '#'), "Hello!         !");
+    }
+
+    #[test]
+    fn write_at_truncates_to_row() {
+        use lcd::Display;
+        use crate::display::TextDisplay;
+        use crate::hardware::LcdHardware;
+        use crate::mock::{command, data, init, join_nibbles, MockHardware};
+
+        let mock = MockHardware::new();
+        init(&mock);
+        mock.reset();
+
+        let mut display = Display::new(LcdHardware::new(&mock, &mock));
+        display.write_at(12, 0, format_args!("{}%", 12345)).unwrap();
+        assert_eq!(
+            join_nibbles(&mock.transfers()),
+            vec![command(0x80 | 12), data(b'1'), data(b'2'), data(b'3'), data(b'4')]
+        );
+
+        mock.reset();
+        assert!(display.write_at(16, 0, format_args!("x")).is_err());
+        assert!(display.write_at(0, 2, format_args!("x")).is_err());
+        assert!(mock.transfers().is_empty());
+    }
+}
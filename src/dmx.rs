@@ -0,0 +1,277 @@
+//! DMX512 monitor: values of the lighting channels received on USART1 (see `serial`), as bars.
+//!
+//! DMX512 is 250 kbaud, 8 data bits and 2 stop bits; every frame starts with a break (the line
+//! held low for longer than a character, so the USART reports it as a framing error with a zero
+//! byte), then comes the start code and up to 512 slots, one per channel. `Receiver` is fed byte
+//! by byte by the interrupt and keeps the slots of the dimmer frames (zero start code); frames with
+//! other start codes (RDM, text packets) are skipped.
+//!
+//! `Monitor` is what the screen shows: the values of 16 channels (one page), as bars in the bottom
+//! row, and the value of the selected channel, with the frame rate, in the top row. Button goes
+//! through the pages of the channels received (and then on to the next screen), encoder selects
+//! the channel.
+
+use core::fmt::{self, Write};
+use crate::text::{self, Align};
+use crate::ui::Event;
+
+/// Channels in a frame, at most.
+pub const CHANNELS: usize = 512;
+/// Channels on a page (one bar per column).
+pub const PAGE: usize = 16;
+/// Start code of the dimmer data.
+const START_DIMMER: u8 = 0;
+/// Frame rate is measured over this period, in milliseconds.
+const RATE_MS: u32 = 1000;
+/// Bar heights (bars are the trend glyphs, see `trend::GLYPHS`).
+const LEVELS: u32 = 7;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Receiver {
+    slots: [u8; CHANNELS],
+    /// Slot the next byte goes to (0 is the start code), `None` until the next break.
+    next: Option<usize>,
+    /// Slots in the last dimmer frame.
+    length: usize,
+    /// Dimmer frames received, wrapping around.
+    frames: u32,
+}
+
+impl Receiver {
+    pub const fn new() -> Receiver {
+        Receiver { slots: [0; CHANNELS], next: None, length: 0, frames: 0 }
+    }
+
+    /// Account the received byte (`framing_error` is set if the stop bit was missing). Framing
+    /// error with a zero byte is the break which ends the frame; with anything else it is noise,
+    /// and the rest of the frame is skipped.
+    pub fn receive(&mut self, byte: u8, framing_error: bool) {
+        if framing_error {
+            if let Some(next) = self.next.filter(|&next| next > 1) {
+                self.length = next - 1;
+                self.frames = self.frames.wrapping_add(1);
+            }
+            self.next = (byte == 0).then_some(0);
+            return;
+        }
+        self.next = match self.next {
+            Some(0) if byte == START_DIMMER => Some(1),
+            Some(next) if (1..=CHANNELS).contains(&next) => {
+                self.slots[next - 1] = byte;
+                Some(next + 1)
+            }
+            _ => None,
+        };
+    }
+
+    /// Value of the `channel` (1 to 512) in the last frame, `None` if the frame was shorter.
+    pub fn value(&self, channel: usize) -> Option<u8> {
+        (1..=self.length).contains(&channel).then(|| self.slots[channel - 1])
+    }
+
+    /// Slots in the last dimmer frame.
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Dimmer frames received, wrapping around.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+}
+
+impl Default for Receiver {
+    fn default() -> Receiver {
+        Receiver::new()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Monitor {
+    /// Selected channel, 1 to 512.
+    channel: usize,
+    /// Values of the channels on the page of the selected one, `None` past the frame.
+    values: [Option<u8>; PAGE],
+    /// Slots in the last frame.
+    length: usize,
+    /// Frames per second, over the last `RATE_MS`.
+    rate: u32,
+    /// Frames received at the start of the rate period, and the timer value then.
+    counted: u32,
+    counted_ms: u32,
+}
+
+impl Default for Monitor {
+    fn default() -> Monitor {
+        Monitor { channel: 1, values: [None; PAGE], length: 0, rate: 0, counted: 0, counted_ms: 0 }
+    }
+}
+
+impl Monitor {
+    /// Copy the values of the page shown from the receiver and measure the frame rate (`now_ms` is
+    /// the millisecond timer, which can wrap around).
+    pub fn update(&mut self, receiver: &Receiver, now_ms: u32) {
+        let first = self.first();
+        for (i, value) in self.values.iter_mut().enumerate() {
+            *value = receiver.value(first + i);
+        }
+        self.length = receiver.length();
+        let elapsed_ms = now_ms.wrapping_sub(self.counted_ms);
+        if elapsed_ms >= RATE_MS {
+            let frames = receiver.frames().wrapping_sub(self.counted);
+            self.rate = (u64::from(frames) * u64::from(RATE_MS) / u64::from(elapsed_ms)) as u32;
+            self.counted = receiver.frames();
+            self.counted_ms = now_ms;
+        }
+    }
+
+    /// Are frames being received?
+    pub fn signal(&self) -> bool {
+        self.rate > 0
+    }
+
+    /// Selected channel, 1 to 512.
+    pub fn channel(&self) -> usize {
+        self.channel
+    }
+
+    /// First channel on the page of the selected one.
+    fn first(&self) -> usize {
+        (self.channel - 1) / PAGE * PAGE + 1
+    }
+
+    /// Value of the selected channel, `None` if it is not received.
+    pub fn value(&self) -> Option<u8> {
+        self.values[(self.channel - 1) % PAGE]
+    }
+
+    /// Button goes to the next page of the channels received, past the last one back to the
+    /// first; encoder selects the channel (values of another page are shown from the next
+    /// update). Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        match event {
+            Event::Button => {
+                let next = self.first() + PAGE;
+                if next > self.length.max(1) {
+                    self.select(1);
+                    return false;
+                }
+                self.select(next);
+            }
+            Event::EncoderUp => self.select(self.channel % CHANNELS + 1),
+            Event::EncoderDown => self.select((self.channel + CHANNELS - 2) % CHANNELS + 1),
+            _ => return false,
+        }
+        true
+    }
+
+    fn select(&mut self, channel: usize) {
+        if (channel - 1) / PAGE != (self.channel - 1) / PAGE {
+            self.values = [None; PAGE];
+        }
+        self.channel = channel;
+    }
+
+    /// Write the selected channel, its value and the frame rate (`Ch 17 255  44Hz`).
+    pub fn write_channel<W: Write>(&self, w: &mut W) -> fmt::Result {
+        w.write_str("Ch")?;
+        text::uint(w, self.channel as u32, 3)?;
+        match self.value().filter(|_| self.signal()) {
+            Some(value) => text::uint(w, u32::from(value), 4)?,
+            None => text::str(w, "--", 4, Align::Right)?,
+        }
+        text::uint(w, self.rate, 4)?;
+        w.write_str("Hz")
+    }
+
+    /// Write the bars of the channels on the page (blank past the frame), or the missing signal.
+    pub fn write_bars<W: Write>(&self, w: &mut W) -> fmt::Result {
+        if !self.signal() {
+            return w.write_str("no signal");
+        }
+        self.values.iter().try_for_each(|value| {
+            let level = value.map_or(0, |value| (u32::from(value) * LEVELS).div_ceil(255));
+            w.write_char(if level == 0 { ' ' } else { char::from(level as u8) })
+        })
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn dmx_monitor() {
+        use crate::dmx::{Monitor, Receiver};
+        use crate::mock;
+        use crate::screens::{self, Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        // Break (framing error with a zero byte), start code, then the slots
+        let frame = |receiver: &mut Receiver, start: u8, slots: &[u8]| {
+            receiver.receive(0, true);
+            receiver.receive(start, false);
+            slots.iter().for_each(|&slot| receiver.receive(slot, false));
+        };
+        let mut receiver = Receiver::new();
+        frame(&mut receiver, 0, &[255, 128, 0, 37]);
+        // Frame is only complete at the next break
+        assert_eq!((receiver.frames(), receiver.length()), (0, 0));
+        // Frames with other start codes (RDM) are skipped
+        frame(&mut receiver, 0xcc, &[1, 2, 3]);
+        frame(&mut receiver, 0, &[]);
+        assert_eq!((receiver.frames(), receiver.length()), (1, 4));
+        assert_eq!((receiver.value(1), receiver.value(4), receiver.value(5)), (Some(255), Some(37), None));
+        assert_eq!(receiver.value(0), None);
+
+        let mut monitor = Monitor::default();
+        monitor.update(&receiver, 0);
+        let mut bars = std::string::String::new();
+        monitor.write_bars(&mut bars).unwrap();
+        assert_eq!(bars, "no signal");
+        for _ in 0..44 {
+            frame(&mut receiver, 0, &[255, 128, 0, 37]);
+        }
+        monitor.update(&receiver, 1000);
+        assert!(monitor.signal());
+        // Bar heights are the trend glyphs, blank past the frame
+        let mut bars = std::string::String::new();
+        monitor.write_bars(&mut bars).unwrap();
+        assert_eq!(bars, format!("\u{7}\u{4} \u{2}{}", " ".repeat(12)));
+
+        let mut settings = Settings { dmx: monitor, ..Settings::default() };
+        let render = |settings: &Settings| {
+            let lcd = mock::render_lcd(Screen::Dmx, &Stats::default(), settings);
+            (lcd.row(0, 16), lcd.row(1, 16), lcd.glyph(3))
+        };
+        let (top, bottom, glyph) = render(&settings);
+        assert_eq!((top.as_str(), bottom.as_str()), ("Ch  1 255  44Hz ", "## #            "));
+        assert_eq!(glyph, [0, 0, 0, 0, 0, 0b11111, 0b11111, 0b11111]);
+
+        // Encoder selects the channel, wrapping around
+        assert!(settings.handle(Screen::Dmx, Event::EncoderDown));
+        assert_eq!(settings.dmx.channel(), 512);
+        assert!(settings.handle(Screen::Dmx, Event::EncoderUp));
+        assert!(settings.handle(Screen::Dmx, Event::EncoderUp));
+        // Values of another page are only shown from the next update
+        assert_eq!(render(&settings).0, "Ch  2  --  44Hz ");
+        settings.dmx.update(&receiver, 1200);
+        assert_eq!(render(&settings).0, "Ch  2 128  44Hz ");
+        // Button goes through the pages received, then on to the next screen
+        assert!(!settings.handle(Screen::Dmx, Event::Button));
+        assert_eq!(settings.dmx.channel(), 1);
+        let slots: std::vec::Vec<u8> = (1..=20).collect();
+        frame(&mut receiver, 0, &slots);
+        frame(&mut receiver, 0, &[]);
+        settings.dmx.update(&receiver, 1500);
+        assert!(settings.handle(Screen::Dmx, Event::Button));
+        assert_eq!(settings.dmx.value(), None);
+        settings.dmx.update(&receiver, 2000);
+        assert_eq!((settings.dmx.channel(), settings.dmx.value()), (17, Some(17)));
+        assert_eq!(render(&settings).0, "Ch 17  17   2Hz ");
+        assert!(!settings.handle(Screen::Dmx, Event::Button));
+        assert_eq!(settings.dmx.channel(), 1);
+
+        let mut ui = screens::navigation();
+        ui.handle(Event::Serial(b'%'), &settings);
+        assert_eq!(ui.state(), Screen::Dmx);
+    }
+}
@@ -0,0 +1,90 @@
+//! Edge counters on EXTI for the pin monitor (see `pin_monitor`): every edge of the monitored
+//! GPIOA pins raises an interrupt (see `interrupt`), which counts it as rising or falling.
+//!
+//! Pins are left configured as they are, so outputs of the other features (relay) are counted, too;
+//! unused pins are floating inputs and must be pulled externally. Features toggling the pins fast
+//! (SPI, fan PWM, I/O strobe, and the LED and ST-LINK UART on Nucleo-F103RB) are not allowed with
+//! it: every edge is an interrupt, so they would keep the CPU in the handler. EXTI tells only that
+//! there was an edge, so its direction is taken from the level at the interrupt: a pulse shorter
+//! than the interrupt latency (about a microsecond) counts twice the same way. There is no input
+//! filter either, so a bouncing contact counts every bounce.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use stm32f1::stm32f103::{Interrupt, AFIO, EXTI, GPIOA, RCC};
+use crate::pin_monitor::{PINS, PIN_COUNT};
+
+#[cfg(any(feature = "tachometer", feature = "totalizer"))]
+compile_error!("`pin-monitor` feature uses EXTI1 and EXTI2, so does `tachometer` and `totalizer`");
+#[cfg(any(feature = "spi-flash", feature = "spi-slave"))]
+compile_error!("`pin-monitor` feature counts PA5-PA7, SPI1 pins of `spi-flash` and `spi-slave`");
+#[cfg(feature = "fan")]
+compile_error!("`pin-monitor` feature counts PA6 and PA7, TIM3 pins of `fan`");
+#[cfg(feature = "io-strobe")]
+compile_error!("`pin-monitor` feature counts PA1, the `io-strobe` output");
+#[cfg(feature = "nucleo-f103rb")]
+compile_error!("`pin-monitor` feature counts PA2, PA3 (ST-LINK UART) and PA5 (LED) on Nucleo");
+
+/// EXTI interrupts of the pins (lines 5-7 share one), the caller must unmask them in NVIC.
+pub const INTERRUPTS: [Interrupt; 4] =
+    [Interrupt::EXTI1, Interrupt::EXTI2, Interrupt::EXTI3, Interrupt::EXTI9_5];
+
+/// EXTI lines of the pins.
+const LINES: u32 = {
+    let mut lines = 0;
+    let mut i = 0;
+    while i < PIN_COUNT {
+        lines |= 1 << PINS[i];
+        i += 1;
+    }
+    lines
+};
+
+/// Edges counted, wrapping around, by pin.
+static RISING: [AtomicU32; PIN_COUNT] = [const { AtomicU32::new(0) }; PIN_COUNT];
+static FALLING: [AtomicU32; PIN_COUNT] = [const { AtomicU32::new(0) }; PIN_COUNT];
+
+/// Connect the EXTI lines of the pins to GPIOA and make them interrupt on both edges. EXTI itself
+/// is shared with the Stop mode, which only touches its own line.
+pub fn init(rcc: &RCC) {
+    rcc.apb2enr.modify(|_, w| w.afioen().set_bit());
+    let afio = unsafe { &*AFIO::ptr() };
+    // GPIOA is code 0, four bits per line, four lines per register
+    let mask = |first: usize| {
+        PINS.iter()
+            .filter(|pin| (first..first + 4).contains(*pin))
+            .fold(0, |mask, pin| mask | 0xf << (4 * (pin - first)))
+    };
+    afio.exticr1.modify(|r, w| unsafe { w.bits(r.bits() & !mask(0)) });
+    afio.exticr2.modify(|r, w| unsafe { w.bits(r.bits() & !mask(4)) });
+
+    let exti = unsafe { &*EXTI::ptr() };
+    exti.pr.write(|w| unsafe { w.bits(LINES) });
+    exti.rtsr.modify(|r, w| unsafe { w.bits(r.bits() | LINES) });
+    exti.ftsr.modify(|r, w| unsafe { w.bits(r.bits() | LINES) });
+    exti.imr.modify(|r, w| unsafe { w.bits(r.bits() | LINES) });
+}
+
+/// Edges counted so far (wrapping around), rising and falling, by pin.
+pub fn read() -> [(u32, u32); PIN_COUNT] {
+    cortex_m::interrupt::free(|_| {
+        core::array::from_fn(|i| {
+            (RISING[i].load(Ordering::Relaxed), FALLING[i].load(Ordering::Relaxed))
+        })
+    })
+}
+
+/// Count the edges pending. Called from the EXTI interrupt handlers.
+pub fn interrupt() {
+    let exti = unsafe { &*EXTI::ptr() };
+    let gpioa = unsafe { &*GPIOA::ptr() };
+    let pending = exti.pr.read().bits() & LINES;
+    exti.pr.write(|w| unsafe { w.bits(pending) });
+    let levels = gpioa.idr.read().bits();
+    for (i, pin) in PINS.iter().enumerate() {
+        if pending & (1 << pin) == 0 {
+            continue;
+        }
+        let counter = if levels & (1 << pin) != 0 { &RISING[i] } else { &FALLING[i] };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,249 @@
+//! Fan control: PWM duty follows the chip temperature along a curve, speed is measured by the
+//! tachometer (see `fan_timer` for the TIM3 side).
+//!
+//! Curve is given by `POINTS` points (temperature and duty), duty is interpolated between them.
+//! Below the first point, fan runs at the duty of the first point, above the last one at the duty
+//! of the last one.
+//!
+//! Button goes through the pages: fan temperature, then the curve points. On a point, turning the
+//! encoder starts editing its temperature (kept between the neighbours), button moves to the duty
+//! and then finishes editing. After the last point, button is left for the navigation.
+
+use core::fmt::{self, Write};
+use crate::text;
+use crate::ui::Event;
+use crate::units::TempUnit;
+
+/// Points of the curve.
+pub const POINTS: usize = 3;
+/// Tachometer pulses per revolution (two for the PC fans).
+const PULSES_PER_REV: u32 = 2;
+
+/// Temperature range and step, in tenths of °C.
+const TEMP_MAX: i16 = 990;
+const TEMP_STEP: i16 = 10;
+/// Duty step, in percent.
+const DUTY_STEP: i16 = 5;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Point {
+    /// Temperature, in tenths of °C.
+    pub temp: i16,
+    /// Duty, in percent.
+    pub duty: u8,
+}
+
+/// Field being edited.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Field {
+    Temp,
+    Duty,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Fan {
+    /// Curve points, ordered by temperature.
+    curve: [Point; POINTS],
+    /// Last temperature, in tenths of °C.
+    temperature: Option<i16>,
+    /// Duty set, in percent.
+    duty: u8,
+    /// Speed measured, in revolutions per minute.
+    rpm: u32,
+    /// Timer value of the last `update`.
+    last_ms: u32,
+    /// Page shown in the second row: temperature (0) or the curve point (1 to `POINTS`).
+    page: usize,
+    cursor: Option<Field>,
+}
+
+impl Default for Fan {
+    /// 20% at 30°C, 50% at 45°C, full speed at 60°C.
+    fn default() -> Fan {
+        Fan {
+            curve: [Point { temp: 300, duty: 20 }, Point { temp: 450, duty: 50 }, Point { temp: 600, duty: 100 }],
+            temperature: None,
+            duty: 0,
+            rpm: 0,
+            last_ms: 0,
+            page: 0,
+            cursor: None,
+        }
+    }
+}
+
+impl Fan {
+    /// Duty for the temperature (in tenths of °C), in percent.
+    pub fn duty_for(&self, temp: i16) -> u8 {
+        let first = self.curve[0];
+        if temp <= first.temp {
+            return first.duty;
+        }
+        for pair in self.curve.windows(2) {
+            let (low, high) = (pair[0], pair[1]);
+            if temp < high.temp {
+                let span = i32::from(high.temp - low.temp).max(1);
+                let rise = i32::from(high.duty) - i32::from(low.duty);
+                return (i32::from(low.duty) + rise * i32::from(temp - low.temp) / span) as u8;
+            }
+        }
+        self.curve[POINTS - 1].duty
+    }
+
+    /// Account the temperature (in tenths of °C) and the tachometer pulses counted since the last
+    /// call (`now_ms` is the millisecond timer). Returns the duty to set, in percent.
+    pub fn update(&mut self, now_ms: u32, temperature: i16, pulses: u32) -> u8 {
+        let elapsed_ms = now_ms.wrapping_sub(self.last_ms);
+        self.last_ms = now_ms;
+        if elapsed_ms > 0 {
+            self.rpm = (u64::from(pulses) * 60_000 / (u64::from(PULSES_PER_REV) * u64::from(elapsed_ms))) as u32;
+        }
+        self.temperature = Some(temperature);
+        self.duty = self.duty_for(temperature);
+        self.duty
+    }
+
+    /// Speed measured, in revolutions per minute.
+    pub fn rpm(&self) -> u32 {
+        self.rpm
+    }
+
+    pub fn curve(&self) -> &[Point; POINTS] {
+        &self.curve
+    }
+
+    /// Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        let step: i16 = match (event, self.cursor) {
+            (Event::Button, Some(Field::Temp)) => {
+                self.cursor = Some(Field::Duty);
+                return true;
+            }
+            (Event::Button, Some(Field::Duty)) => {
+                self.cursor = None;
+                return true;
+            }
+            (Event::Button, None) if self.page < POINTS => {
+                self.page += 1;
+                return true;
+            }
+            (Event::Button, None) => {
+                self.page = 0;
+                return false;
+            }
+            _ if self.page == 0 => return false,
+            (Event::EncoderUp, _) => 1,
+            (Event::EncoderDown, _) => -1,
+            _ => return false,
+        };
+        let index = self.page - 1;
+        let low = if index > 0 { self.curve[index - 1].temp } else { 0 };
+        let high = self.curve.get(index + 1).map_or(TEMP_MAX, |point| point.temp);
+        let point = &mut self.curve[index];
+        match *self.cursor.get_or_insert(Field::Temp) {
+            Field::Temp => point.temp = (point.temp + step * TEMP_STEP).clamp(low, high),
+            Field::Duty => point.duty = (i16::from(point.duty) + step * DUTY_STEP).clamp(0, 100) as u8,
+        }
+        true
+    }
+
+    /// Write the speed and the duty (`Fan 1230rpm 50%`).
+    pub fn write_status<W: Write>(&self, w: &mut W) -> fmt::Result {
+        w.write_str("Fan")?;
+        text::uint(w, self.rpm, 5)?;
+        w.write_str("rpm")?;
+        text::uint(w, u32::from(self.duty), 3)?;
+        w.write_char('%')
+    }
+
+    /// Write the page shown: the temperature (`Temp  23.5C`) or the curve point, with the field
+    /// being edited in brackets (`2[ 45.0C]  50% `).
+    pub fn write_page<W: Write>(&self, w: &mut W, unit: TempUnit) -> fmt::Result {
+        let Some(index) = self.page.checked_sub(1) else {
+            w.write_str("Temp")?;
+            if let Some(temperature) = self.temperature {
+                text::fixed(w, unit.from_tenths_c(temperature), 1, 6)?;
+                w.write_str(unit.symbol())?;
+            }
+            return Ok(());
+        };
+        let point = self.curve[index];
+        text::uint(w, index as u32 + 1, 1)?;
+        write_field(w, self.cursor == Some(Field::Temp), |w| {
+            text::fixed(w, unit.from_tenths_c(point.temp), 1, 5)?;
+            w.write_str(unit.symbol())
+        })?;
+        write_field(w, self.cursor == Some(Field::Duty), |w| {
+            text::uint(w, u32::from(point.duty), 3)?;
+            w.write_char('%')
+        })
+    }
+}
+
+/// Write a field, in brackets if it is being edited (spaces otherwise, so the text does not move).
+fn write_field<W: Write>(w: &mut W, edited: bool, write: impl FnOnce(&mut W) -> fmt::Result) -> fmt::Result {
+    w.write_char(if edited { '[' } else { ' ' })?;
+    write(w)?;
+    w.write_char(if edited { ']' } else { ' ' })
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn fan_curve() {
+        use crate::mock;
+        use crate::screens::{self, Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::Fan, &Stats::default(), settings);
+
+        // Duty is interpolated between the points, and kept outside of them
+        let fan = &mut settings.fan;
+        assert_eq!([200, 300, 375, 450, 525, 600, 700].map(|temp| fan.duty_for(temp)), [20, 20, 35, 50, 75, 100, 100]);
+        // 40 pulses in a second is 20 revolutions
+        assert_eq!(fan.update(1000, 375, 40), 35);
+        assert_eq!(fan.rpm(), 1200);
+        assert_eq!(render(&settings), ("Fan 1200rpm 35% ".into(), "Temp  37.5C     ".into()));
+        assert!(!settings.handle(Screen::Fan, Event::EncoderUp));
+
+        // Point temperature is kept between the neighbours
+        assert!(settings.handle(Screen::Fan, Event::Button));
+        assert_eq!(render(&settings).1, "1  30.0C   20%  ");
+        assert!(settings.handle(Screen::Fan, Event::EncoderUp));
+        assert_eq!(render(&settings).1, "1[ 31.0C]  20%  ");
+        for _ in 0..20 {
+            assert!(settings.handle(Screen::Fan, Event::EncoderUp));
+        }
+        assert_eq!(render(&settings).1, "1[ 45.0C]  20%  ");
+        assert!(settings.handle(Screen::Fan, Event::Button));
+        assert!(settings.handle(Screen::Fan, Event::EncoderUp));
+        assert!(settings.handle(Screen::Fan, Event::EncoderUp));
+        assert_eq!(render(&settings).1, "1  45.0C [ 30%] ");
+        assert!(settings.handle(Screen::Fan, Event::Button));
+        assert_eq!(settings.fan.duty_for(450), 30);
+        assert_eq!(settings.fan.duty_for(500), 66);
+        assert!(settings.handle(Screen::Fan, Event::Button));
+        assert!(settings.handle(Screen::Fan, Event::Button));
+        assert_eq!(render(&settings).1, "3  60.0C  100%  ");
+        assert!(!settings.handle(Screen::Fan, Event::Button));
+        assert_eq!(render(&settings).1, "Temp  37.5C     ");
+
+        // Temperature log, tachometer and totalizer (if enabled) are the last optional screens
+        // after the fan one
+        let mut ui = screens::navigation();
+        ui.handle(Event::Serial(b'n'), &settings);
+        assert_eq!(ui.state(), Screen::Fan);
+        ui.handle(Event::Button, &settings);
+        #[cfg(not(any(feature = "temp-log", feature = "tachometer", feature = "totalizer")))]
+        assert_eq!(ui.state(), Screen::Uptime);
+        #[cfg(all(feature = "tachometer", not(feature = "temp-log")))]
+        assert_eq!(ui.state(), Screen::Tachometer);
+        #[cfg(all(feature = "totalizer", not(any(feature = "temp-log", feature = "tachometer"))))]
+        assert_eq!(ui.state(), Screen::Totalizer);
+        #[cfg(feature = "temp-log")]
+        assert_eq!(ui.state(), Screen::TempLog);
+        ui.handle(Event::EncoderDown, &settings);
+        assert_eq!(ui.state(), Screen::Fan);
+    }
+}
@@ -0,0 +1,82 @@
+//! Fan PWM and tachometer on TIM3: PA7 (channel 2) is the 25kHz PWM output, PA6 (channel 1)
+//! captures the tachometer pulses. Used by the fan screen.
+//!
+//! Captures are only counted (by the TIM3 interrupt, see `interrupt`): a tachometer gives a few
+//! hundred pulses per second at most, so counting them over a second is precise enough, and the
+//! timer period stays free for the PWM.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use stm32f1::stm32f103::{RCC, TIM3};
+use crate::board::PortName;
+use crate::clock::Clocks;
+use crate::gpio::{self, GPIOExtras};
+
+#[cfg(feature = "spi-flash")]
+compile_error!("`fan` and `spi-flash` features both use PA6 and PA7");
+#[cfg(feature = "spi-slave")]
+compile_error!("`fan` and `spi-slave` features both use PA6 and PA7");
+
+const TACH: usize = 6;
+const PWM: usize = 7;
+/// PWM frequency, 4-pin fans expect 25kHz.
+const PWM_FREQ: u32 = 25_000;
+
+/// Tachometer pulses counted since the last `take_pulses`.
+static PULSES: AtomicU32 = AtomicU32::new(0);
+
+pub struct FanTimer {
+    tim: TIM3,
+    /// Timer period, in timer clocks.
+    period: u32,
+}
+
+impl FanTimer {
+    /// Configure TIM3 for the PWM (fan is stopped) and the tachometer capture. TIM3 interrupt
+    /// itself must be unmasked in NVIC by the caller.
+    pub fn new(tim: TIM3, rcc: &RCC, clocks: &Clocks) -> FanTimer {
+        let port = gpio::enable_port(rcc, PortName::A);
+        // PWM input of the fan is pulled up inside the fan, tachometer is an open collector
+        port.pin_config(PWM).output2().open_drain().alternate();
+        port.write_pin(TACH, true);
+        port.pin_config(TACH).input().pull_up_down();
+
+        rcc.apb1enr.modify(|_, w| w.tim3en().set_bit());
+        // Timer clock is doubled if APB1 is divided
+        let timer_clk = if clocks.pclk1 == clocks.hclk {
+            clocks.pclk1
+        } else {
+            clocks.pclk1 * 2
+        };
+        let period = timer_clk / PWM_FREQ;
+        tim.arr.write(|w| w.arr().bits((period - 1) as u16));
+        tim.ccr[1].write(|w| w.ccr().bits(0));
+        tim.ccmr1_output().write(|w| w.oc2m().pwm_mode1().oc2pe().enabled());
+        // Tachometer edges are filtered (8 samples at 1/32 of the timer clock), fan cables are noisy
+        tim.ccmr1_input().modify(|_, w| w.cc1s().ti1().ic1f().fdts_div32_n8());
+        // Capture on the falling edge
+        tim.ccer.write(|w| w.cc1p().set_bit().cc1e().set_bit().cc2e().set_bit());
+        tim.dier.write(|w| w.cc1ie().set_bit());
+        tim.egr.write(|w| w.ug().set_bit());
+        tim.cr1.write(|w| w.arpe().set_bit().cen().set_bit());
+        FanTimer { tim, period }
+    }
+
+    /// Set PWM duty, in percent.
+    pub fn set_duty(&mut self, percent: u8) {
+        let compare = self.period * u32::from(percent.min(100)) / 100;
+        self.tim.ccr[1].write(|w| w.ccr().bits(compare as u16));
+    }
+}
+
+/// Tachometer pulses counted since the last call.
+pub fn take_pulses() -> u32 {
+    PULSES.swap(0, Ordering::Relaxed)
+}
+
+/// Count the captured pulse. Called from the TIM3 interrupt handler.
+pub fn interrupt() {
+    // Reading the captured value clears the interrupt flag
+    let tim = unsafe { &*TIM3::ptr() };
+    tim.ccr[0].read();
+    PULSES.fetch_add(1, Ordering::Relaxed);
+}
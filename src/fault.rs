@@ -0,0 +1,226 @@
+//! Reporting of fatal errors (panics and hard faults) on the LCD.
+//!
+//! If the display was already initialized, panic location and message are printed on it (first
+//! row is location, second row is message, both truncated to the width of the display). Otherwise,
+//! on-board LED is blinking rapidly.
+//!
+//! Hard faults are reported as a register dump, spread across multiple pages. Pressing the button
+//! switches to the next page.
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+use cortex_m::peripheral::SCB;
+use cortex_m_rt::{exception, ExceptionFrame};
+use stm32f1::stm32f103::{gpioa, Peripherals, RCC};
+use lcd::Display;
+use lcd_example_bluepill::board;
+use lcd_example_bluepill::delay::CycleDelay;
+use lcd_example_bluepill::gpio::{self, GPIOExtras};
+use lcd_example_bluepill::hardware::LcdHardware;
+use lcd_example_bluepill::text::{self, Align};
+
+const COLUMNS: usize = 16;
+
+/// Set to `true` once display is initialized, so it is safe to print on it.
+static DISPLAY_READY: AtomicBool = AtomicBool::new(false);
+
+/// Mark display as initialized, so panics are printed on it.
+pub fn display_ready() {
+    DISPLAY_READY.store(true, Ordering::SeqCst);
+}
+
+/// Writer that silently drops everything after first `left` characters. Line breaks are replaced
+/// with spaces, since LCD cannot render them anyway.
+struct Truncate<'a, W: Write> {
+    inner: &'a mut W,
+    left: usize,
+}
+
+impl<W: Write> Write for Truncate<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.left == 0 {
+                break;
+            }
+            self.left -= 1;
+            self.inner.write_char(if c == '\n' { ' ' } else { c })?;
+        }
+        Ok(())
+    }
+}
+
+fn truncated<W: Write>(inner: &mut W) -> Truncate<'_, W> {
+    Truncate { inner, left: COLUMNS }
+}
+
+/// Peripherals are owned by `main`, but it's never going to use them again.
+unsafe fn steal() -> (cortex_m::Peripherals, Peripherals) {
+    (cortex_m::Peripherals::steal(), Peripherals::steal())
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cortex_m::interrupt::disable();
+    error!("panic");
+    let (mut cp, dp) = unsafe { steal() };
+    // Fault could happen before cycle counter is enabled, but we need it for delays
+    let delay = CycleDelay::ensure_running(&mut cp.DCB, &mut cp.DWT);
+
+    if DISPLAY_READY.load(Ordering::SeqCst) {
+        let mut display = Display::new(LcdHardware::new(&dp.GPIOB, delay));
+        display.clear();
+
+        if let Some(location) = info.location() {
+            // Only file name, directories are of no use on a small screen
+            let file = location.file().rsplit('/').next().unwrap_or("");
+            let mut w = truncated(&mut display);
+            w.write_str(file).ok();
+            w.write_char(':').ok();
+            text::uint(&mut w, location.line(), 0).ok();
+        }
+
+        display.position(0, 1);
+        write_message(&mut truncated(&mut display), info).ok();
+        loop {
+            cortex_m::asm::wfi();
+        }
+    }
+
+    blink(delay, &dp.RCC)
+}
+
+/// Configure on-board LED pin as an output.
+fn led_port(rcc: &RCC) -> &'static gpioa::RegisterBlock {
+    let led = board::LED;
+    let port = gpio::enable_port(rcc, led.port);
+    port.pin_config(led.index).push_pull().output2();
+    port
+}
+
+#[cfg(not(feature = "tiny-fmt"))]
+fn write_message<W: Write>(w: &mut W, info: &PanicInfo) -> fmt::Result {
+    write!(w, "{}", info.message())
+}
+
+/// Only static messages are printed, formatting them would pull in `core::fmt`.
+#[cfg(feature = "tiny-fmt")]
+fn write_message<W: Write>(w: &mut W, info: &PanicInfo) -> fmt::Result {
+    w.write_str(info.message().as_str().unwrap_or("(formatted)"))
+}
+
+/// Display is not available, blink on-board LED instead.
+fn blink(delay: CycleDelay, rcc: &RCC) -> ! {
+    let led = board::LED;
+    let port = led_port(rcc);
+    loop {
+        port.write_pin(led.index, led.level(true));
+        delay.delay_us(100_000);
+        port.write_pin(led.index, led.level(false));
+        delay.delay_us(100_000);
+    }
+}
+
+/// Report an error code on the on-board LED: `code` short blinks followed by a long pause,
+/// repeated forever. Distinct from the rapid blinking of a panic.
+pub fn blink_code(delay: CycleDelay, rcc: &RCC, code: u8) -> ! {
+    let led = board::LED;
+    let port = led_port(rcc);
+    loop {
+        for _ in 0..code {
+            port.write_pin(led.index, led.level(true));
+            delay.delay_us(200_000);
+            port.write_pin(led.index, led.level(false));
+            delay.delay_us(300_000);
+        }
+        for _ in 0..3 {
+            delay.delay_us(500_000);
+        }
+    }
+}
+
+/// Blink code for the display not being detected (clock errors use codes 1 to 3).
+pub const NO_DISPLAY: u8 = 4;
+
+/// Non-blocking version of `blink_code`, for errors the firmware keeps running with.
+pub struct ErrorLed {
+    port: &'static gpioa::RegisterBlock,
+    code: u8,
+    tick: u16,
+}
+
+impl ErrorLed {
+    /// `tick` must be called with this period, in milliseconds.
+    pub const TICK_MS: u32 = 100;
+
+    pub fn new(rcc: &RCC, code: u8) -> ErrorLed {
+        ErrorLed { port: led_port(rcc), code, tick: 0 }
+    }
+
+    /// Advance the pattern: each blink is 2 ticks on and 3 ticks off, followed by a 15 ticks pause.
+    pub fn tick(&mut self) {
+        let led = board::LED;
+        let blinks = u16::from(self.code) * 5;
+        let on = self.tick < blinks && self.tick % 5 < 2;
+        self.port.write_pin(led.index, led.level(on));
+        self.tick = (self.tick + 1) % (blinks + 15);
+    }
+}
+
+#[exception]
+unsafe fn HardFault(ef: &ExceptionFrame) -> ! {
+    error!("hard fault, pc={=u32:#010x}", ef.pc());
+    let (mut cp, dp) = steal();
+    let delay = CycleDelay::ensure_running(&mut cp.DCB, &mut cp.DWT);
+
+    if !DISPLAY_READY.load(Ordering::SeqCst) {
+        blink(delay, &dp.RCC);
+    }
+
+    let scb = &*SCB::PTR;
+    let registers = [
+        ("PC", ef.pc()),
+        ("LR", ef.lr()),
+        ("xPSR", ef.xpsr()),
+        ("SP", ef as *const ExceptionFrame as u32),
+        ("HFSR", scb.hfsr.read()),
+        ("CFSR", scb.cfsr.read()),
+        ("MMAR", scb.mmfar.read()),
+        ("BFAR", scb.bfar.read()),
+    ];
+
+    let button = board::BUTTON;
+    let port = gpio::enable_port(&dp.RCC, button.port);
+    port.pin_config(button.index).input().pull_up_down();
+    // Pull-up for active low button, pull-down otherwise
+    port.write_pin(button.index, button.active_low);
+
+    let mut display = Display::new(LcdHardware::new(&dp.GPIOB, delay));
+    let pages = registers.len() / 2;
+    let mut page = 0;
+    loop {
+        display.clear();
+        for (row, &(name, value)) in registers[page * 2..page * 2 + 2].iter().enumerate() {
+            display.position(0, row as u8);
+            text::str(&mut display, name, 4, Align::Left).ok();
+            display.write_char(' ').ok();
+            text::hex32(&mut display, value).ok();
+        }
+        display.position(13, 0);
+        text::uint(&mut display, (page + 1) as u32, 0).ok();
+        display.write_char('/').ok();
+        text::uint(&mut display, pages as u32, 0).ok();
+
+        wait_button(delay, port);
+        page = (page + 1) % pages;
+    }
+}
+
+/// Wait until button is pressed and released.
+fn wait_button(delay: CycleDelay, port: &gpioa::RegisterBlock) {
+    let button = board::BUTTON;
+    while port.read_pin(button.index) != button.level(true) {}
+    delay.delay_us(20_000); // Debounce
+    while port.read_pin(button.index) == button.level(true) {}
+    delay.delay_us(20_000);
+}
@@ -0,0 +1,144 @@
+//! SPI NOR flash identification (W25Qxx and compatible chips), for the flash screen: JEDEC ID and
+//! status registers, a quick check that the chip is wired correctly and answers.
+//!
+//! Only the standard commands are used, so any chip implementing JEDEC ID (0x9f) is recognized.
+//! Status registers 2 and 3 are Winbond-specific, other chips may answer with garbage there.
+
+use core::fmt::{self, Write};
+use crate::text::{self, Align};
+
+const READ_JEDEC_ID: u8 = 0x9f;
+const READ_STATUS: [u8; 3] = [0x05, 0x35, 0x15];
+
+/// SPI master with a single device on the bus.
+pub trait Spi {
+    /// Exchange `bytes` with the device (each byte sent is replaced with the byte received), chip
+    /// select is asserted for the whole transfer.
+    fn transfer(&mut self, bytes: &mut [u8]);
+}
+
+/// Identification of the chip.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Flash {
+    /// JEDEC manufacturer ID.
+    pub manufacturer: u8,
+    pub memory_type: u8,
+    /// Size of the chip is `2^capacity` bytes.
+    pub capacity: u8,
+    /// Status registers 1 to 3.
+    pub status: [u8; 3],
+}
+
+/// Read the identification. Returns `None` if there is no chip (MISO floating or stuck, so ID is
+/// all zeroes or all ones).
+pub fn probe<S: Spi>(spi: &mut S) -> Option<Flash> {
+    let mut id = [READ_JEDEC_ID, 0, 0, 0];
+    spi.transfer(&mut id);
+    if id[1..] == [0; 3] || id[1..] == [0xff; 3] {
+        return None;
+    }
+    let mut status = [0; 3];
+    for (command, value) in READ_STATUS.iter().zip(status.iter_mut()) {
+        let mut bytes = [*command, 0];
+        spi.transfer(&mut bytes);
+        *value = bytes[1];
+    }
+    Some(Flash {
+        manufacturer: id[1],
+        memory_type: id[2],
+        capacity: id[3],
+        status,
+    })
+}
+
+impl Flash {
+    /// Manufacturer name, if known.
+    pub fn manufacturer_name(&self) -> Option<&'static str> {
+        match self.manufacturer {
+            0x01 => Some("Spansion"),
+            0x1f => Some("Adesto"),
+            0x20 => Some("Micron"),
+            0x9d => Some("ISSI"),
+            0xbf => Some("SST"),
+            0xc2 => Some("Macronix"),
+            0xc8 => Some("GigaDev"),
+            0xef => Some("Winbond"),
+            _ => None,
+        }
+    }
+
+    /// Write manufacturer and capacity (`Winbond 128Mbit`). Unknown manufacturer is shown by its
+    /// ID, implausible capacity as `?`.
+    pub fn write_summary<W: Write>(&self, w: &mut W) -> fmt::Result {
+        match self.manufacturer_name() {
+            Some(name) => text::str(w, name, 8, Align::Left)?,
+            None => {
+                w.write_str("mfr ")?;
+                text::hex8(w, self.manufacturer)?;
+                w.write_str("  ")?;
+            }
+        }
+        match self.capacity {
+            // 2^17 bytes is 1Mbit
+            17..=29 => {
+                text::uint(w, 1 << (self.capacity - 17), 4)?;
+                w.write_str("Mbit")
+            }
+            10..=16 => {
+                text::uint(w, 1 << (self.capacity - 7), 4)?;
+                w.write_str("Kbit")
+            }
+            _ => w.write_str("   ?"),
+        }
+    }
+
+    /// Write JEDEC ID and status registers (`ef4018  00 02 60`).
+    pub fn write_details<W: Write>(&self, w: &mut W) -> fmt::Result {
+        for byte in [self.manufacturer, self.memory_type, self.capacity] {
+            text::hex8(w, byte)?;
+        }
+        w.write_char(' ')?;
+        for byte in self.status {
+            w.write_char(' ')?;
+            text::hex8(w, byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn flash_screen() {
+        use crate::flash::{self, Spi};
+        use crate::mock;
+        use crate::screens::{Screen, Settings, Stats};
+
+        /// W25Q128 answering commands, or nothing connected (MISO pulled up).
+        struct Chip(bool);
+
+        impl Spi for Chip {
+            fn transfer(&mut self, bytes: &mut [u8]) {
+                let answer: &[u8] = match (self.0, bytes[0]) {
+                    (false, _) => &[0xff; 4],
+                    (true, 0x9f) => &[0xff, 0xef, 0x40, 0x18],
+                    (true, 0x05) => &[0xff, 0x00],
+                    (true, 0x35) => &[0xff, 0x02],
+                    (true, 0x15) => &[0xff, 0x60],
+                    (true, _) => &[0xff; 4],
+                };
+                bytes.copy_from_slice(&answer[..bytes.len()]);
+            }
+        }
+
+        assert_eq!(flash::probe(&mut Chip(false)), None);
+        let chip = flash::probe(&mut Chip(true));
+
+        let render = |stats: &Stats| mock::render(Screen::Flash, stats, &Settings::default());
+        assert_eq!(render(&Stats::default()), ("Flash none      ".into(), "                ".into()));
+        let stats = Stats { flash: chip, ..Stats::default() };
+        assert_eq!(render(&stats), ("Winbond  128Mbit".into(), "ef4018  00 02 60".into()));
+        let unknown = chip.map(|chip| flash::Flash { manufacturer: 0x85, capacity: 0x10, ..chip });
+        assert_eq!(render(&Stats { flash: unknown, ..stats }).0, "mfr 85   512Kbit");
+    }
+}
@@ -0,0 +1,186 @@
+//! Number formatting without floats.
+//!
+//! `write!` pulls in a lot of `core::fmt` machinery (several kilobytes of flash). With the
+//! `tiny-fmt` feature, numbers are printed by the hand-rolled code below instead, and only
+//! `Write::write_str` is used. Without it, the same functions go through `core::fmt`.
+//!
+//! Fractional values are passed as integers in thousandths (millivolts, millidegrees), so float
+//! formatting (which is huge) is never needed.
+
+use core::fmt::{self, Write};
+
+/// Write fixed-point number given in thousandths with `decimals` digits after the decimal point
+/// (at most 3): `write_fixed(w, 3271, 2)` writes `3.27`. Value is rounded half away from zero;
+/// minus sign is only written if the rounded value is not zero.
+pub fn write_fixed<W: Write>(w: &mut W, value_milli: i32, decimals: u8) -> fmt::Result {
+    let decimals = u32::from(decimals.min(3));
+    let divisor = 10u32.pow(3 - decimals);
+    let scaled = (value_milli.unsigned_abs() + divisor / 2) / divisor;
+    if value_milli < 0 && scaled != 0 {
+        w.write_char('-')?;
+    }
+
+    let unit = 10u32.pow(decimals);
+    write_uint(w, scaled / unit)?;
+    if decimals > 0 {
+        w.write_char('.')?;
+        let fraction = scaled % unit;
+        for pos in (0..decimals).rev() {
+            let digit = (fraction / 10u32.pow(pos)) % 10;
+            w.write_char((b'0' + digit as u8) as char)?;
+        }
+    }
+    Ok(())
+}
+
+/// SI prefixes, from micro (1000^-2) to mega (1000^2). Micro is `u`, display character set has
+/// no `µ` in the ASCII range.
+const PREFIXES: [&str; 5] = ["u", "m", "", "k", "M"];
+
+/// Write value with three significant digits, scaled with SI prefix: `write_si(w, 12_345_678,
+/// 0, "Hz")` writes `12.3MHz`, `write_si(w, 3271, -1, "V")` writes `3.27V`. `scale` is the power
+/// of 1000 `value` is given in (-1 for millivolts, 0 for volts), from -2 to 2.
+pub fn write_si<W: Write>(w: &mut W, value: i32, scale: i8, unit: &str) -> fmt::Result {
+    // Value in thousandths of `1000^power` units
+    let mut milli = i64::from(value).abs() * 1000;
+    let mut power = i32::from(scale.clamp(-2, 2));
+    while milli >= 1_000_000 && power < 2 {
+        milli /= 1000;
+        power += 1;
+    }
+    while milli != 0 && milli < 1000 && power > -2 {
+        milli *= 1000;
+        power -= 1;
+    }
+
+    // Round to three significant digits, which could bump value into the next range
+    let step = 10i64.pow(3 - significant_decimals(milli));
+    milli = (milli + step / 2) / step * step;
+    if milli >= 1_000_000 && power < 2 {
+        milli /= 1000;
+        power += 1;
+    }
+
+    let milli = if value < 0 { -milli } else { milli } as i32;
+    write_fixed(w, milli, significant_decimals(milli.unsigned_abs().into()) as u8)?;
+    w.write_str(PREFIXES[(power + 2) as usize])?;
+    w.write_str(unit)
+}
+
+/// Digits after decimal point to get three significant digits for value given in thousandths.
+fn significant_decimals(milli: i64) -> u32 {
+    if milli < 10_000 {
+        2
+    } else if milli < 100_000 {
+        1
+    } else {
+        0
+    }
+}
+
+/// Write lowest `bits` bits of the value in binary, most significant first, in groups of four
+/// separated by spaces (`1010 0011`). Bits are counted from the lowest group, so `bits` which is
+/// not a multiple of four gives shorter group on the left.
+pub fn write_binary<W: Write>(w: &mut W, value: u32, bits: u8) -> fmt::Result {
+    for pos in (0..u32::from(bits.min(32))).rev() {
+        w.write_char(if value & (1 << pos) != 0 { '1' } else { '0' })?;
+        if pos != 0 && pos % 4 == 0 {
+            w.write_char(' ')?;
+        }
+    }
+    Ok(())
+}
+
+/// Write duration given in seconds as `03:25:17`, with days prepended if there is at least one
+/// day (`1d 03:25:17`).
+pub fn write_duration<W: Write>(w: &mut W, seconds: u32) -> fmt::Result {
+    let days = seconds / 86_400;
+    if days > 0 {
+        write_uint(w, days)?;
+        w.write_str("d ")?;
+    }
+    write_two_digits(w, seconds / 3600 % 24)?;
+    w.write_char(':')?;
+    write_two_digits(w, seconds / 60 % 60)?;
+    w.write_char(':')?;
+    write_two_digits(w, seconds % 60)
+}
+
+/// Write time given in milliseconds as minutes, seconds and centiseconds (`12:05.27`), truncated
+/// to the centisecond.
+pub fn write_centis<W: Write>(w: &mut W, millis: u32) -> fmt::Result {
+    write_uint(w, millis / 60_000)?;
+    w.write_char(':')?;
+    write_two_digits(w, millis / 1000 % 60)?;
+    w.write_char('.')?;
+    write_two_digits(w, millis / 10 % 100)
+}
+
+fn write_two_digits<W: Write>(w: &mut W, value: u32) -> fmt::Result {
+    w.write_char((b'0' + (value / 10 % 10) as u8) as char)?;
+    w.write_char((b'0' + (value % 10) as u8) as char)
+}
+
+/// Write unsigned integer in decimal.
+#[cfg(not(feature = "tiny-fmt"))]
+pub fn write_uint<W: Write>(w: &mut W, value: u32) -> fmt::Result {
+    write!(w, "{}", value)
+}
+
+/// Write lowercase hexadecimal number, zero-padded to `digits` digits.
+#[cfg(not(feature = "tiny-fmt"))]
+pub fn write_hex<W: Write>(w: &mut W, value: u32, digits: usize) -> fmt::Result {
+    write!(w, "{:01$x}", value, digits)
+}
+
+/// Write unsigned integer in decimal.
+#[cfg(feature = "tiny-fmt")]
+pub fn write_uint<W: Write>(w: &mut W, mut value: u32) -> fmt::Result {
+    // Digits are generated from the least significant one, filling the array from the end
+    let mut digits = [0u8; 10];
+    let mut start = digits.len();
+    for (pos, digit) in digits.iter_mut().enumerate().rev() {
+        *digit = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            start = pos;
+            break;
+        }
+    }
+    w.write_str(unsafe { core::str::from_utf8_unchecked(&digits[start..]) })
+}
+
+/// Write lowercase hexadecimal number, zero-padded to `digits` digits.
+#[cfg(feature = "tiny-fmt")]
+pub fn write_hex<W: Write>(w: &mut W, value: u32, digits: usize) -> fmt::Result {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    let significant = 8 - (value.leading_zeros() as usize / 4).min(7);
+    for pos in (0..digits.max(significant)).rev() {
+        let nibble = if pos < 8 { (value >> (pos * 4)) & 0xf } else { 0 };
+        w.write_char(HEX[nibble as usize] as char)?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn si_prefixes() {
+        use std::string::String;
+        use crate::fmt::write_si;
+
+        let si = |value, scale, unit| {
+            let mut s = String::new();
+            write_si(&mut s, value, scale, unit).unwrap();
+            s
+        };
+        assert_eq!(si(12_345_678, 0, "Hz"), "12.3MHz");
+        assert_eq!(si(999_999, 0, "Hz"), "1.00MHz");
+        assert_eq!(si(1_000, 0, "Hz"), "1.00kHz");
+        assert_eq!(si(999, 0, "Hz"), "999Hz");
+        assert_eq!(si(3271, -1, "V"), "3.27V");
+        assert_eq!(si(-45, -1, "V"), "-45.0mV");
+        assert_eq!(si(150, -2, "A"), "150uA");
+        assert_eq!(si(0, 0, "V"), "0.00V");
+    }
+}
@@ -0,0 +1,667 @@
+//! Shadow copy of the display contents. Screens are rendered into the framebuffer, and `flush`
+//! sends only the characters which differ from what is already shown, so redrawing the same screen
+//! costs nothing.
+//!
+//! Custom characters are shadowed the same way, double-buffered: framebuffer keeps track of the
+//! image in each location of CGRAM, and a character is sent as the location its image is in,
+//! which is not necessarily its own code. Only images of the characters in the text are loaded:
+//! the ones already in CGRAM are reused (wherever they are), the others are batched at the start of
+//! the flush, before any text, into the locations not shown on the display, if there are any. So
+//! the characters shown never change their images mid-frame (when the snake moves, say): the
+//! cells switch to the new images along with the text. Images never uploaded through the
+//! framebuffer are left alone, and so are their locations.
+//!
+//! Display memory can get corrupted by the noise on the cable, and the framebuffer would not know
+//! about that. `invalidate` makes the next `flush` re-send everything. Rows are overwritten in
+//! place (display is not cleared), so this does not cause any flicker.
+//!
+//! Corrupted cells can also be found by reading the display memory back (with the `read-back`
+//! feature): `verify` compares it with the text sent, and the cells which differ are re-sent by the
+//! next `flush`.
+//!
+//! Display can be split into zones (see `split`), each owned by a different task or screen
+//! (sensor value on the left, clock on the right, say), which writes it through its own `ZoneView`
+//! with the coordinates relative to the zone. Framebuffer arbitrates the writes: text is clipped to
+//! the zone it is written to, and writes to the framebuffer itself (the screen shown) skip the
+//! cells of the zones, so nobody overwrites anybody. Custom characters are shared by everyone.
+//!
+//! Whole display is arbitrated between its producers by priority (see `Owner`): latched alarm
+//! preempts the text sent over the bus, which preempts the screens. Contents of the preempted owner
+//! (text and custom characters) are kept aside, and put back when the display is handed back to
+//! it, so it is shown as it was even before it re-draws it.
+
+use core::fmt;
+use crate::display::{TextDisplay, COLUMNS, ROWS};
+
+/// Custom characters of the display.
+const GLYPHS: usize = 8;
+/// Zones the display can be split into.
+pub const ZONES: usize = 4;
+
+/// Rectangle of the display, in characters.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Zone {
+    pub col: u8,
+    pub row: u8,
+    pub width: u8,
+    pub height: u8,
+}
+
+impl Zone {
+    fn contains(&self, col: usize, row: usize) -> bool {
+        let (left, top) = (usize::from(self.col), usize::from(self.row));
+        (left..left + usize::from(self.width)).contains(&col) && (top..top + usize::from(self.height)).contains(&row)
+    }
+
+    fn overlaps(&self, other: &Zone) -> bool {
+        self.col < other.col + other.width
+            && other.col < self.col + self.width
+            && self.row < other.row + other.height
+            && other.row < self.row + self.height
+    }
+}
+
+/// Producer of the display contents, in the order of priority.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Owner {
+    /// Screen shown (or the screensaver).
+    Screen,
+    /// Text sent over SPI or serial.
+    Remote,
+    /// Latched threshold alarm.
+    Alarm,
+}
+
+const OWNERS: usize = 3;
+
+/// Contents kept aside for a preempted owner.
+#[derive(Copy, Clone)]
+struct Saved {
+    text: [[u8; COLUMNS]; ROWS],
+    glyphs: [Option<[u8; 8]>; GLYPHS],
+}
+
+/// Zone given to its owner by `Framebuffer::split`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ZoneId(usize);
+
+pub struct Framebuffer<D: TextDisplay> {
+    display: D,
+    /// Contents rendered since the last flush.
+    pending: [[u8; COLUMNS]; ROWS],
+    /// Contents of the display.
+    shown: [[u8; COLUMNS]; ROWS],
+    /// Custom characters uploaded, by their codes.
+    pending_glyphs: [Option<[u8; 8]>; GLYPHS],
+    /// Images in CGRAM, by their locations (`None` if not known).
+    shown_glyphs: [Option<[u8; 8]>; GLYPHS],
+    /// Location of the image of each custom character.
+    locations: [u8; GLYPHS],
+    /// `shown` is not trusted, next flush sends everything.
+    invalid: bool,
+    /// Text was sent since the last `begin_verify`.
+    unverified: bool,
+    zones: [Option<Zone>; ZONES],
+    owner: Owner,
+    /// Contents of the owners preempted by the one in charge.
+    saved: [Option<Saved>; OWNERS],
+    col: usize,
+    row: usize,
+}
+
+impl<D: TextDisplay> Framebuffer<D> {
+    /// Display must be cleared.
+    pub fn new(display: D) -> Framebuffer<D> {
+        Framebuffer {
+            display,
+            pending: [[b' '; COLUMNS]; ROWS],
+            shown: [[b' '; COLUMNS]; ROWS],
+            pending_glyphs: [None; GLYPHS],
+            shown_glyphs: [None; GLYPHS],
+            locations: core::array::from_fn(|c| c as u8),
+            invalid: false,
+            unverified: false,
+            zones: [None; ZONES],
+            owner: Owner::Screen,
+            saved: [None; OWNERS],
+            col: 0,
+            row: 0,
+        }
+    }
+
+    /// Give the `zone` of the display to a new owner. Returns `None` if the zone is empty, does
+    /// not fit the display, overlaps another zone, or there are `ZONES` of them already. Zone keeps
+    /// the contents it had.
+    pub fn split(&mut self, zone: Zone) -> Option<ZoneId> {
+        let fits = usize::from(zone.col + zone.width) <= COLUMNS && usize::from(zone.row + zone.height) <= ROWS;
+        if zone.width == 0 || zone.height == 0 || !fits || self.zones.iter().flatten().any(|other| other.overlaps(&zone)) {
+            return None;
+        }
+        let index = self.zones.iter().position(Option::is_none)?;
+        self.zones[index] = Some(zone);
+        Some(ZoneId(index))
+    }
+
+    /// Give the zone back to the framebuffer (the screen shown), which is to re-draw it.
+    pub fn merge(&mut self, id: ZoneId) {
+        self.zones[id.0] = None;
+    }
+
+    /// Writer of the zone, with the cursor at its top left corner.
+    pub fn zone(&mut self, id: ZoneId) -> ZoneView<'_, D> {
+        ZoneView { zone: self.zones[id.0].expect("zone was merged"), fb: self, col: 0, row: 0 }
+    }
+
+    /// Is the cell in a zone (so the framebuffer itself does not write it)?
+    fn zoned(&self, col: usize, row: usize) -> bool {
+        self.zones.iter().flatten().any(|zone| zone.contains(col, row))
+    }
+
+    /// Owner in charge of the display.
+    pub fn owner(&self) -> Owner {
+        self.owner
+    }
+
+    /// Hand the display to the `owner` (the one of the highest priority among those which want
+    /// it). Contents of the owner preempted are kept aside, and contents of the one the display
+    /// goes back to are restored (cells of the zones are left alone). Owners which did not get
+    /// the display back by then (remote text preempted by the alarm, then stopped) are forgotten.
+    pub fn arbitrate(&mut self, owner: Owner) {
+        if owner > self.owner {
+            self.saved[self.owner as usize] = Some(Saved { text: self.pending, glyphs: self.pending_glyphs });
+        } else if owner < self.owner {
+            if let Some(saved) = self.saved[owner as usize] {
+                for row in 0..ROWS {
+                    for col in 0..COLUMNS {
+                        if !self.zoned(col, row) {
+                            self.pending[row][col] = saved.text[row][col];
+                        }
+                    }
+                }
+                self.pending_glyphs = saved.glyphs;
+            }
+            self.saved[owner as usize..].fill(None);
+        }
+        self.owner = owner;
+    }
+
+    /// Forget what is shown on the display, so the next flush re-sends everything.
+    pub fn invalidate(&mut self) {
+        self.invalid = true;
+        self.shown_glyphs = [None; GLYPHS];
+    }
+
+    /// Start checking the text sent against the display memory: returns `true` if any was sent
+    /// since the last check. Display memory is then to be read back and passed to `verify`.
+    pub fn begin_verify(&mut self) -> bool {
+        core::mem::replace(&mut self.unverified, false)
+    }
+
+    /// Compare the display memory read back (`read`, row by row) with the text sent. Cells which
+    /// differ are taken as shown as they were read, so the next flush re-sends them. Returns the
+    /// number of such cells; nothing is compared if text was sent while the memory was read (it is
+    /// checked the next time).
+    pub fn verify(&mut self, read: &[[u8; COLUMNS]; ROWS]) -> usize {
+        if self.unverified {
+            return 0;
+        }
+        let mut errors = 0;
+        for (shown, read) in self.shown.iter_mut().flatten().zip(read.iter().flatten()) {
+            if shown != read {
+                *shown = *read;
+                errors += 1;
+            }
+        }
+        errors
+    }
+
+    /// Contents of the given row rendered so far (not necessarily sent to the display).
+    pub fn row(&self, row: usize) -> &str {
+        // Only ASCII is written into the framebuffer
+        unsafe { core::str::from_utf8_unchecked(&self.pending[row]) }
+    }
+
+    /// Is there anything to send to the display?
+    pub fn is_dirty(&self) -> bool {
+        let used = self.used();
+        self.invalid
+            || (0..GLYPHS).any(|c| used[c] && !self.loaded(c))
+            || (0..ROWS).any(|row| (0..COLUMNS).any(|col| self.sent(self.pending[row][col]) != self.shown[row][col]))
+    }
+
+    /// Custom characters in the text rendered.
+    fn used(&self) -> [bool; GLYPHS] {
+        let mut used = [false; GLYPHS];
+        for c in self.pending.iter().flatten().filter(|c| usize::from(**c) < GLYPHS) {
+            used[usize::from(*c)] = true;
+        }
+        used
+    }
+
+    /// Is the image of the custom character in CGRAM (or not uploaded through the framebuffer)?
+    fn loaded(&self, c: usize) -> bool {
+        self.pending_glyphs[c].is_none() || self.shown_glyphs[usize::from(self.locations[c])] == self.pending_glyphs[c]
+    }
+
+    /// Character sent for the rendered one: custom characters are sent as the locations of their
+    /// images.
+    fn sent(&self, c: u8) -> u8 {
+        self.locations.get(usize::from(c)).copied().unwrap_or(c)
+    }
+
+    /// Find the locations of the images of the custom characters in the text, loading the ones not
+    /// in CGRAM yet. Locations of the characters uploaded through the framebuffer are shared by
+    /// them, locations shown on the display are taken last.
+    fn load_glyphs(&mut self) -> fmt::Result {
+        let used = self.used();
+        let mut shown = [false; GLYPHS];
+        for c in self.shown.iter().flatten().filter(|c| usize::from(**c) < GLYPHS) {
+            shown[usize::from(*c)] = true;
+        }
+        let ours = |location: usize| self.pending_glyphs[location].is_some();
+        // Locations with the images needed by this frame
+        let mut taken = [false; GLYPHS];
+        for c in (0..GLYPHS).filter(|&c| used[c]) {
+            let Some(map) = self.pending_glyphs[c] else {
+                self.locations[c] = c as u8;
+                taken[c] = true;
+                continue;
+            };
+            let current = usize::from(self.locations[c]);
+            let found = core::iter::once(current)
+                .chain(0..GLYPHS)
+                .find(|&location| ours(location) && self.shown_glyphs[location] == Some(map));
+            if let Some(location) = found {
+                self.locations[c] = location as u8;
+                taken[location] = true;
+            }
+        }
+        for c in (0..GLYPHS).filter(|&c| used[c]) {
+            if self.loaded(c) {
+                continue;
+            }
+            let map = self.pending_glyphs[c].ok_or(fmt::Error)?;
+            // Same image loaded for another character
+            let same = (0..GLYPHS).find(|&location| ours(location) && self.shown_glyphs[location] == Some(map));
+            if let Some(location) = same {
+                self.locations[c] = location as u8;
+                taken[location] = true;
+                continue;
+            }
+            let current = usize::from(self.locations[c]);
+            let free = |location: &usize| ours(*location) && !taken[*location];
+            // Own location first, then the current one (its cells are to show the new image anyway)
+            let location = [c, current]
+                .into_iter()
+                .chain(0..GLYPHS)
+                .find(|location| free(location) && !shown[*location])
+                .or_else(|| [current, c].into_iter().chain(0..GLYPHS).find(free))
+                .ok_or(fmt::Error)?;
+            self.shown_glyphs[location] = None;
+            self.display.upload(location as u8, map)?;
+            self.shown_glyphs[location] = Some(map);
+            self.locations[c] = location as u8;
+            taken[location] = true;
+        }
+        Ok(())
+    }
+
+    /// Load the custom characters and then send changed text to the display. If sending fails,
+    /// characters which are not sent are retried on the next flush.
+    pub fn flush(&mut self) -> fmt::Result {
+        self.load_glyphs()?;
+        let invalid = self.invalid;
+        self.invalid = false;
+        for row in 0..ROWS {
+            let sent: [u8; COLUMNS] = core::array::from_fn(|col| self.sent(self.pending[row][col]));
+            let mut col = 0;
+            while col < COLUMNS {
+                let changed = |col: usize| invalid || sent[col] != self.shown[row][col];
+                if !changed(col) {
+                    col += 1;
+                    continue;
+                }
+                let start = col;
+                while col < COLUMNS && changed(col) {
+                    col += 1;
+                }
+
+                let run = &sent[start..col];
+                // Only ASCII is written into the framebuffer
+                let text = unsafe { core::str::from_utf8_unchecked(run) };
+                let result = self.display.position(start as u8, row as u8).and_then(|_| self.display.write_str(text));
+                if result.is_err() {
+                    self.invalid = invalid;
+                    return result;
+                }
+                self.shown[row][start..col].copy_from_slice(run);
+                self.unverified = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<D: TextDisplay> fmt::Write for Framebuffer<D> {
+    /// Text past the end of the row is dropped.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.col < COLUMNS {
+                if !self.zoned(self.col, self.row) {
+                    self.pending[self.row][self.col] = ascii(c);
+                }
+                self.col += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<D: TextDisplay> TextDisplay for Framebuffer<D> {
+    /// Cells of the zones are left alone.
+    fn clear(&mut self) -> fmt::Result {
+        for row in 0..ROWS {
+            for col in 0..COLUMNS {
+                if !self.zoned(col, row) {
+                    self.pending[row][col] = b' ';
+                }
+            }
+        }
+        self.col = 0;
+        self.row = 0;
+        Ok(())
+    }
+
+    fn position(&mut self, col: u8, row: u8) -> fmt::Result {
+        if usize::from(col) >= COLUMNS || usize::from(row) >= ROWS {
+            return Err(fmt::Error);
+        }
+        self.col = usize::from(col);
+        self.row = usize::from(row);
+        Ok(())
+    }
+
+    fn upload(&mut self, location: u8, map: [u8; 8]) -> fmt::Result {
+        let glyph = self.pending_glyphs.get_mut(usize::from(location)).ok_or(fmt::Error)?;
+        *glyph = Some(map);
+        Ok(())
+    }
+}
+
+/// Display character set is only ASCII-compatible, other characters are replaced.
+fn ascii(c: char) -> u8 {
+    if c.is_ascii() { c as u8 } else { b'?' }
+}
+
+/// Zone of the framebuffer, as a display of its own (see `Framebuffer::split`).
+pub struct ZoneView<'a, D: TextDisplay> {
+    fb: &'a mut Framebuffer<D>,
+    zone: Zone,
+    /// Cursor, relative to the zone.
+    col: usize,
+    row: usize,
+}
+
+impl<D: TextDisplay> fmt::Write for ZoneView<'_, D> {
+    /// Text past the right edge of the zone is dropped.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let (left, top) = (usize::from(self.zone.col), usize::from(self.zone.row));
+        for c in s.chars() {
+            if self.col < usize::from(self.zone.width) {
+                self.fb.pending[top + self.row][left + self.col] = ascii(c);
+                self.col += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<D: TextDisplay> TextDisplay for ZoneView<'_, D> {
+    /// Clear the zone.
+    fn clear(&mut self) -> fmt::Result {
+        let (left, top) = (usize::from(self.zone.col), usize::from(self.zone.row));
+        for row in top..top + usize::from(self.zone.height) {
+            self.fb.pending[row][left..left + usize::from(self.zone.width)].fill(b' ');
+        }
+        self.col = 0;
+        self.row = 0;
+        Ok(())
+    }
+
+    /// Position is relative to the zone.
+    fn position(&mut self, col: u8, row: u8) -> fmt::Result {
+        if col >= self.zone.width || row >= self.zone.height {
+            return Err(fmt::Error);
+        }
+        self.col = usize::from(col);
+        self.row = usize::from(row);
+        Ok(())
+    }
+
+    fn upload(&mut self, location: u8, map: [u8; 8]) -> fmt::Result {
+        self.fb.upload(location, map)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn framebuffer_sends_only_changes() {
+        use lcd::Display;
+        use crate::display::TextDisplay;
+        use crate::framebuffer::Framebuffer;
+        use crate::hardware::LcdHardware;
+        use crate::mock::{command, data, init, join_nibbles, MockHardware};
+
+        let mock = MockHardware::new();
+        init(&mock);
+        mock.reset();
+
+        let mut fb = Framebuffer::new(Display::new(LcdHardware::new(&mock, &mock)));
+        fb.write_at(0, 0, format_args!("ab")).unwrap();
+        fb.flush().unwrap();
+        assert_eq!(join_nibbles(&mock.transfers()), vec![command(0x80), data(b'a'), data(b'b')]);
+
+        // Only the changed character is sent
+        mock.reset();
+        fb.write_at(0, 0, format_args!("ac")).unwrap();
+        fb.flush().unwrap();
+        assert_eq!(join_nibbles(&mock.transfers()), vec![command(0x81), data(b'c')]);
+
+        // Nothing has changed
+        mock.reset();
+        assert_eq!(fb.row(0), "ac              ");
+        assert!(!fb.is_dirty());
+        fb.flush().unwrap();
+        assert!(mock.transfers().is_empty());
+
+        // Everything is re-sent after invalidation, row by row, without clearing the display
+        fb.invalidate();
+        fb.flush().unwrap();
+        let transfers = join_nibbles(&mock.transfers());
+        assert_eq!(transfers.len(), 2 * (1 + 16));
+        assert_eq!(&transfers[..3], &[command(0x80), data(b'a'), data(b'c')]);
+        assert_eq!(transfers[17], command(0x80 | 0x40));
+    }
+
+    #[test]
+    fn framebuffer_zones() {
+        use core::fmt::Write;
+        use crate::display::{NoDisplay, TextDisplay};
+        use crate::framebuffer::{Framebuffer, Zone};
+
+        let mut fb = Framebuffer::new(NoDisplay);
+        let zone = |col, row, width, height| Zone { col, row, width, height };
+        let sensor = fb.split(zone(0, 0, 10, 1)).unwrap();
+        let clock = fb.split(zone(10, 0, 6, 1)).unwrap();
+        assert_eq!(fb.split(zone(9, 0, 2, 2)), None);
+        assert_eq!(fb.split(zone(12, 1, 5, 1)), None);
+        assert_eq!(fb.split(zone(0, 1, 0, 1)), None);
+
+        // Each owner writes its own zone, clipped to it
+        fb.zone(sensor).write_at(0, 0, format_args!("Temp 23.5C, rising")).unwrap();
+        let mut view = fb.zone(clock);
+        view.write_str("07:30:15").unwrap();
+        assert!(view.position(6, 0).is_err());
+        assert!(view.position(0, 1).is_err());
+        view.position(5, 0).unwrap();
+        view.write_str("0").unwrap();
+        assert_eq!(fb.row(0), "Temp 23.5C07:300");
+
+        // Screen shown only gets the rest of the display
+        fb.write_at(0, 0, format_args!("Hello, world!")).unwrap();
+        fb.write_at(0, 1, format_args!("second row")).unwrap();
+        assert_eq!((fb.row(0), fb.row(1)), ("Temp 23.5C07:300", "second row      "));
+        fb.clear().unwrap();
+        assert_eq!((fb.row(0), fb.row(1)), ("Temp 23.5C07:300", "                "));
+        fb.zone(sensor).clear().unwrap();
+        assert_eq!(fb.row(0), "          07:300");
+
+        // Merged zone goes back to the screen
+        fb.merge(clock);
+        fb.write_at(0, 0, format_args!("Hello, world!")).unwrap();
+        assert_eq!(fb.row(0), "          ld!300");
+        let zones = [zone(0, 1, 4, 1), zone(4, 1, 4, 1), zone(8, 1, 4, 1)];
+        assert!(zones.into_iter().all(|zone| fb.split(zone).is_some()));
+        assert_eq!(fb.split(zone(12, 1, 4, 1)), None);
+        fb.flush().unwrap();
+        assert!(!fb.is_dirty());
+    }
+
+    #[test]
+    fn framebuffer_arbitration() {
+        use core::fmt::Write;
+        use crate::display::{NoDisplay, TextDisplay};
+        use crate::framebuffer::{Framebuffer, Owner};
+
+        let mut fb = Framebuffer::new(NoDisplay);
+        fb.write_at(0, 0, format_args!("Hello!")).unwrap();
+        fb.upload(0, [1; 8]).unwrap();
+        fb.flush().unwrap();
+        assert_eq!(fb.owner(), Owner::Screen);
+
+        // Remote text preempts the screen, then the alarm preempts the remote text
+        fb.arbitrate(Owner::Remote);
+        fb.clear().unwrap();
+        fb.write_str("remote").unwrap();
+        fb.arbitrate(Owner::Alarm);
+        fb.clear().unwrap();
+        fb.write_str("ALARM").unwrap();
+        fb.upload(0, [2; 8]).unwrap();
+        assert_eq!(fb.owner(), Owner::Alarm);
+
+        // Acknowledged alarm gives the remote text back, as it was
+        fb.arbitrate(Owner::Remote);
+        assert_eq!(fb.row(0), "remote          ");
+        fb.arbitrate(Owner::Screen);
+        assert_eq!(fb.row(0), "Hello!          ");
+        fb.flush().unwrap();
+        assert!(!fb.is_dirty());
+
+        // Remote text which stopped while preempted is not restored
+        fb.arbitrate(Owner::Remote);
+        fb.write_str("remote").unwrap();
+        fb.arbitrate(Owner::Alarm);
+        fb.clear().unwrap();
+        fb.arbitrate(Owner::Screen);
+        assert_eq!(fb.row(0), "Hello!          ");
+    }
+
+    #[test]
+    fn framebuffer_double_buffers_glyphs() {
+        use lcd::Display;
+        use crate::display::TextDisplay;
+        use crate::framebuffer::Framebuffer;
+        use crate::hardware::LcdHardware;
+        use crate::mock::{init, join_nibbles, MockHardware};
+        use crate::sim::Hd44780;
+
+        let mock = MockHardware::new();
+        init(&mock);
+        let mut lcd = Hd44780::new();
+        lcd.feed(&mock.transfers());
+        let mut fb = Framebuffer::new(Display::new(LcdHardware::new(&mock, &mock)));
+        let mut flush = |fb: &mut Framebuffer<_>| {
+            mock.reset();
+            fb.flush().unwrap();
+            let transfers = mock.transfers();
+            lcd.feed(&transfers);
+            let uploads = join_nibbles(&transfers).iter().filter(|t| !t.rs && t.data & 0xc0 == 0x40).count();
+            (uploads, lcd.cell_glyph(0, 0), lcd.cell_glyph(1, 0), lcd.glyph(1))
+        };
+        let (a, b) = ([0b11111; 8], [0b10101; 8]);
+
+        // Only the characters in the text are loaded
+        fb.upload(1, a).unwrap();
+        fb.upload(5, b).unwrap();
+        fb.write_at(0, 0, format_args!("\u{1}")).unwrap();
+        assert_eq!(flush(&mut fb), (1, Some(a), None, a));
+        assert!(!fb.is_dirty());
+
+        // New image of a character on the display goes to a location not shown, so the cell
+        // switches to it along with the text
+        fb.upload(1, b).unwrap();
+        assert!(fb.is_dirty());
+        assert_eq!(flush(&mut fb), (1, Some(b), None, a));
+
+        // Image already loaded is reused by another character
+        fb.upload(2, a).unwrap();
+        fb.write_at(1, 0, format_args!("\u{2}")).unwrap();
+        assert_eq!(flush(&mut fb), (0, Some(b), Some(a), a));
+        fb.upload(2, b).unwrap();
+        assert_eq!(flush(&mut fb), (0, Some(b), Some(b), a));
+        assert!(!fb.is_dirty());
+
+        // Everything is loaded again after invalidation
+        fb.invalidate();
+        assert_eq!(flush(&mut fb).0, 1);
+    }
+
+    #[test]
+    fn framebuffer_verifies_read_back() {
+        use lcd::Display;
+        use crate::display::{TextDisplay, COLUMNS, ROWS};
+        use crate::framebuffer::Framebuffer;
+        use crate::hardware::LcdHardware;
+        use crate::mock::{command, data, init, join_nibbles, MockHardware};
+
+        let mock = MockHardware::new();
+        init(&mock);
+        mock.reset();
+
+        let mut fb = Framebuffer::new(Display::new(LcdHardware::new(&mock, &mock)));
+        // Nothing sent, nothing to check
+        assert!(!fb.begin_verify());
+        fb.write_at(0, 0, format_args!("abc")).unwrap();
+        fb.flush().unwrap();
+        assert!(fb.begin_verify());
+        assert!(!fb.begin_verify());
+
+        // Memory matches the text sent
+        let mut read = [[b' '; COLUMNS]; ROWS];
+        read[0][..3].copy_from_slice(b"abc");
+        assert_eq!(fb.verify(&read), 0);
+        assert!(!fb.is_dirty());
+
+        // Corrupted cells are re-sent by the next flush
+        read[0][1] = b'x';
+        read[1][15] = 0xff;
+        assert_eq!(fb.verify(&read), 2);
+        assert!(fb.is_dirty());
+        mock.reset();
+        fb.flush().unwrap();
+        assert_eq!(
+            join_nibbles(&mock.transfers()),
+            vec![command(0x81), data(b'b'), command(0x80 | 0x4f), data(b' ')]
+        );
+
+        // Text sent while the memory was read is checked the next time
+        fb.write_at(0, 1, format_args!("d")).unwrap();
+        fb.flush().unwrap();
+        assert!(fb.begin_verify());
+        fb.write_at(0, 1, format_args!("e")).unwrap();
+        fb.flush().unwrap();
+        assert_eq!(fb.verify(&read), 0);
+        assert!(fb.begin_verify());
+    }
+}
@@ -0,0 +1,168 @@
+//! Extra API on top of the GPIO ports of the device crate (`stm32f1`).
+//!
+//! Pins are configured through the bit-band alias region, so configuring one pin does not disturb
+//! other pins (no read-modify-write, which could lead to data races). Bit ranges are written via
+//! BSRR register, for the same reason.
+
+use stm32f1::stm32f103::{gpioa, GPIOA, GPIOB, GPIOC, RCC};
+use vcell::VolatileCell;
+use crate::board::PortName;
+
+const PERIPHERALS_BASE: usize = 0x4000_0000;
+const PERIPHERALS_ALIAS: usize = 0x4200_0000;
+
+/// Pin configuration bits (MODE and CNF), accessed through the bit-band alias region.
+pub struct PinConfig {
+    mode_low: VolatileCell<u32>,
+    mode_high: VolatileCell<u32>,
+    cnf_low: VolatileCell<u32>,
+    cnf_high: VolatileCell<u32>,
+}
+
+impl PinConfig {
+    /// Input mode (reset state)
+    pub fn input(&self) -> &Self {
+        self.mode_low.set(0);
+        self.mode_high.set(0);
+        self
+    }
+
+    /// Output mode, max speed 2 MHz.
+    pub fn output2(&self) -> &Self {
+        self.mode_low.set(0);
+        self.mode_high.set(1);
+        self
+    }
+
+    /// Output mode, max speed 10 MHz.
+    pub fn output10(&self) -> &Self {
+        self.mode_low.set(1);
+        self.mode_high.set(0);
+        self
+    }
+
+    /// Output mode, max speed 50 MHz.
+    pub fn output50(&self) -> &Self {
+        self.mode_low.set(1);
+        self.mode_high.set(1);
+        self
+    }
+
+    // Output config
+
+    /// Push-pull
+    pub fn push_pull(&self) -> &Self {
+        self.cnf_low.set(0);
+        self
+    }
+
+    /// Open-drain
+    pub fn open_drain(&self) -> &Self {
+        self.cnf_low.set(1);
+        self
+    }
+
+    /// General purpose
+    pub fn general(&self) -> &Self {
+        self.cnf_high.set(0);
+        self
+    }
+
+    /// Alternate function
+    pub fn alternate(&self) -> &Self {
+        self.cnf_high.set(1);
+        self
+    }
+
+    // Input config
+
+    /// Analog mode
+    pub fn analog(&self) -> &Self {
+        self.cnf_low.set(0);
+        self.cnf_high.set(0);
+        self
+    }
+
+    /// Floating input (reset state)
+    pub fn floating(&self) -> &Self {
+        // Ordering is important: should never get reserved value of `11`
+        self.cnf_high.set(0);
+        self.cnf_low.set(1);
+        self
+    }
+
+    /// Input with pull-up / pull-down
+    pub fn pull_up_down(&self) -> &Self {
+        self.cnf_low.set(0);
+        self.cnf_high.set(1);
+        self
+    }
+}
+
+/// Convenient access to the bit blocks on GPIO ports.
+pub trait GPIOExtras {
+    /// Set `count` bits on the GPIO port starting from the bit number `offset`. Other bits are not
+    /// affected. Uses BSRR register to set/clear individual bits.
+    /// Bits must fit into 16 bits of the GPIO port.
+    fn write_pin_range(&self, offset: usize, count: usize, data: u16);
+
+    /// Set single bit at the given `offset` in GPIO port. `offset` must be in the range 0..16.
+    fn write_pin(&self, offset: usize, bit: bool) {
+        self.write_pin_range(offset, 1, u16::from(bit));
+    }
+
+    /// Get `count` bits on the GPIO port starting from the bit number `offset`.
+    fn read_pin_range(&self, offset: usize, count: usize) -> u16;
+
+    /// Get single bit at the given `offset` in GPIO port. `offset` must be in the range 0..16.
+    fn read_pin(&self, offset: usize) -> bool {
+        self.read_pin_range(offset, 1) != 0
+    }
+
+    /// Get access to configuration bits for `pin` of GPIO port.
+    fn pin_config(&self, pin: usize) -> &PinConfig;
+}
+
+impl GPIOExtras for gpioa::RegisterBlock {
+    fn write_pin_range(&self, offset: usize, count: usize, data: u16) {
+        let mask = (1 << count) - 1;
+        let bits = u32::from(data & mask) | // Set '1's
+            (u32::from(!data & mask) << 16); // Clear '0's
+        self.bsrr.write(|w| unsafe { w.bits(bits << offset) });
+    }
+
+    fn read_pin_range(&self, offset: usize, count: usize) -> u16 {
+        let mask = (1 << count) - 1;
+        ((self.idr.read().bits() >> offset) as u16) & mask
+    }
+
+    fn pin_config(&self, pin: usize) -> &PinConfig {
+        // CRL and CRH are adjacent, 4 configuration bits per pin
+        let byte_offset = (self as *const _ as usize) - PERIPHERALS_BASE;
+        let address = PERIPHERALS_ALIAS + byte_offset * 32;
+        let configs = address as *const PinConfig;
+        unsafe { &*configs.add(pin) }
+    }
+}
+
+/// Enable clock of the given GPIO port.
+pub fn enable_clock(rcc: &RCC, port: PortName) {
+    match port {
+        PortName::A => rcc.apb2enr.modify(|_, w| w.iopaen().enabled()),
+        PortName::B => rcc.apb2enr.modify(|_, w| w.iopben().enabled()),
+        PortName::C => rcc.apb2enr.modify(|_, w| w.iopcen().enabled()),
+    }
+}
+
+/// Enable clock of the given GPIO port and get access to it, bypassing ownership. Ports are only
+/// accessed via BSRR register and bit-band aliases, so it is safe to share them.
+pub fn enable_port(rcc: &RCC, port: PortName) -> &'static gpioa::RegisterBlock {
+    enable_clock(rcc, port);
+    unsafe {
+        match port {
+            PortName::A => &*GPIOA::ptr(),
+            PortName::B => &*GPIOB::ptr(),
+            PortName::C => &*GPIOC::ptr(),
+        }
+    }
+}
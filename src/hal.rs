@@ -0,0 +1,197 @@
+//! Adapters for [`embedded-hal`](https://crates.io/crates/embedded-hal) pins and delays, so
+//! `LcdHardware` could be reused with any HAL crate: `hal-02` feature for embedded-hal 0.2,
+//! `hal-1` for embedded-hal 1.0. HAL types are wrapped into `Hal02` or `Hal1` respectively.
+//!
+//! `Pins` maps pin numbers used by `LcdHardware` (`hardware::RS`, `RW`, `E` and `DATA`) onto the
+//! individual pins. Data pins must be readable, too. HAL pins cannot change direction, so with the
+//! `input` feature data pins must be open-drain outputs with pull-ups, which are released (set
+//! high) to be read. Pin errors are ignored, GPIO pins of microcontrollers never fail.
+
+use core::cell::{Cell, RefCell};
+use crate::hardware::{DATA, E, RS, RW};
+use crate::port::Port;
+
+/// Output pin.
+pub trait OutputPin {
+    fn set(&mut self, high: bool);
+}
+
+/// Output pin which could also be read (open-drain).
+pub trait IoPin: OutputPin {
+    fn is_high(&mut self) -> bool;
+}
+
+/// Pin or delay provider implementing embedded-hal 0.2 traits.
+#[cfg(feature = "hal-02")]
+pub struct Hal02<T>(pub T);
+
+#[cfg(feature = "hal-02")]
+impl<P: embedded_hal_02::digital::v2::OutputPin> OutputPin for Hal02<P> {
+    fn set(&mut self, high: bool) {
+        self.0.set_state(high.into()).ok();
+    }
+}
+
+#[cfg(feature = "hal-02")]
+impl<P: embedded_hal_02::digital::v2::OutputPin + embedded_hal_02::digital::v2::InputPin> IoPin for Hal02<P> {
+    fn is_high(&mut self) -> bool {
+        self.0.is_high().unwrap_or(false)
+    }
+}
+
+#[cfg(feature = "hal-02")]
+impl<D: embedded_hal_02::blocking::delay::DelayUs<u32>> lcd::Delay for Hal02<D> {
+    fn delay_us(&mut self, delay_usec: u32) {
+        self.0.delay_us(delay_usec);
+    }
+}
+
+/// Pin or delay provider implementing embedded-hal 1.0 traits.
+#[cfg(feature = "hal-1")]
+pub struct Hal1<T>(pub T);
+
+#[cfg(feature = "hal-1")]
+impl<P: embedded_hal::digital::OutputPin> OutputPin for Hal1<P> {
+    fn set(&mut self, high: bool) {
+        self.0.set_state(high.into()).ok();
+    }
+}
+
+#[cfg(feature = "hal-1")]
+impl<P: embedded_hal::digital::OutputPin + embedded_hal::digital::InputPin> IoPin for Hal1<P> {
+    fn is_high(&mut self) -> bool {
+        self.0.is_high().unwrap_or(false)
+    }
+}
+
+#[cfg(feature = "hal-1")]
+impl<D: embedded_hal::delay::DelayNs> lcd::Delay for Hal1<D> {
+    fn delay_us(&mut self, delay_usec: u32) {
+        self.0.delay_us(delay_usec);
+    }
+}
+
+/// LCD pins, presented to `LcdHardware` as a GPIO port. Control pins `C` and data pins `D` are of
+/// the same type each (HAL crates provide type-erased pins for that).
+pub struct Pins<C, D> {
+    rs: RefCell<C>,
+    rw: RefCell<C>,
+    e: RefCell<C>,
+    /// DB4-DB7
+    data: RefCell<[D; 4]>,
+    /// Levels control pins are set to, control pins are not read from the pins themselves.
+    levels: Cell<u16>,
+}
+
+impl<C: OutputPin, D: OutputPin> Pins<C, D> {
+    pub fn new(rs: C, rw: C, e: C, data: [D; 4]) -> Pins<C, D> {
+        Pins {
+            rs: RefCell::new(rs),
+            rw: RefCell::new(rw),
+            e: RefCell::new(e),
+            data: RefCell::new(data),
+            levels: Cell::new(0),
+        }
+    }
+
+    fn set(&self, pin: usize, high: bool) {
+        match pin {
+            RS => self.rs.borrow_mut().set(high),
+            RW => self.rw.borrow_mut().set(high),
+            E => self.e.borrow_mut().set(high),
+            _ if (DATA..DATA + 4).contains(&pin) => self.data.borrow_mut()[pin - DATA].set(high),
+            _ => {}
+        }
+        let mask = 1 << pin;
+        self.levels.set(if high { self.levels.get() | mask } else { self.levels.get() & !mask });
+    }
+}
+
+impl<C: OutputPin, D: IoPin> Port for Pins<C, D> {
+    fn write_pin_range(&self, offset: usize, count: usize, data: u16) {
+        for i in 0..count {
+            self.set(offset + i, data & (1 << i) != 0);
+        }
+    }
+
+    fn read_pin_range(&self, offset: usize, count: usize) -> u16 {
+        (0..count).fold(0, |bits, i| {
+            let pin = offset + i;
+            let high = if (DATA..DATA + 4).contains(&pin) {
+                self.data.borrow_mut()[pin - DATA].is_high()
+            } else {
+                self.levels.get() & (1 << pin) != 0
+            };
+            bits | (u16::from(high) << i)
+        })
+    }
+
+    /// Pins are always outputs.
+    fn output(&self, _pin: usize) {}
+
+    /// Data pins are always open-drain.
+    fn open_drain(&self, _pin: usize) {}
+
+    /// Release open-drain data pin, so the display could drive it.
+    fn input(&self, pin: usize) {
+        if (DATA..DATA + 4).contains(&pin) {
+            self.set(pin, true);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    #[cfg(feature = "hal-1")]
+    fn embedded_hal_pins_match_port() {
+        use lcd::{Display, DisplayBlink, DisplayCursor, DisplayMode, FunctionDots, FunctionLine};
+        use core::convert::Infallible;
+        use crate::hal::{Hal1, Pins};
+        use crate::hardware::{LcdHardware, DATA, E, RS, RW};
+        use crate::mock::{init, MockHardware};
+        use crate::port::Port;
+        use embedded_hal::digital::{ErrorType, InputPin, OutputPin};
+
+        /// Single pin of the mock port.
+        struct MockPin<'a>(&'a MockHardware, usize);
+
+        impl ErrorType for MockPin<'_> {
+            type Error = Infallible;
+        }
+
+        impl OutputPin for MockPin<'_> {
+            fn set_low(&mut self) -> Result<(), Infallible> {
+                self.0.write_pin(self.1, false);
+                Ok(())
+            }
+
+            fn set_high(&mut self) -> Result<(), Infallible> {
+                self.0.write_pin(self.1, true);
+                Ok(())
+            }
+        }
+
+        impl InputPin for MockPin<'_> {
+            fn is_high(&mut self) -> Result<bool, Infallible> {
+                Ok(self.0.read_pin_range(self.1, 1) != 0)
+            }
+
+            fn is_low(&mut self) -> Result<bool, Infallible> {
+                self.is_high().map(|high| !high)
+            }
+        }
+
+        let expected = MockHardware::new();
+        init(&expected);
+
+        let mock = MockHardware::new();
+        let pin = |pin| Hal1(MockPin(&mock, pin));
+        let pins = Pins::new(pin(RS), pin(RW), pin(E), [pin(DATA), pin(DATA + 1), pin(DATA + 2), pin(DATA + 3)]);
+        let mut display = Display::new(LcdHardware::new(pins, &mock));
+        display.init(FunctionLine::Line2, FunctionDots::Dots5x8);
+        display.display(DisplayMode::DisplayOn, DisplayCursor::CursorOff, DisplayBlink::BlinkOff);
+
+        assert_eq!(mock.transfers(), expected.transfers());
+    }
+}
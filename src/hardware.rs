@@ -0,0 +1,490 @@
+//! Binding of HD44780 instance to the real hardware
+
+use crate::board;
+#[cfg(feature = "input")]
+use crate::display::{COLUMNS, ROWS};
+use crate::port::Port;
+
+pub const RS: usize = board::LCD_RS;
+pub const RW: usize = board::LCD_RW;
+pub const E: usize = board::LCD_E;
+pub const DATA: usize = board::LCD_DATA; // Four pins, DB4-DB7
+
+/// How long to wait for a control line to reach the requested level, in microseconds.
+#[cfg(feature = "glitch-filter")]
+const SETTLE_US: u32 = 10;
+
+/// Time given to the data lines to change hands between the display and the MCU, in
+/// microseconds. Display stops driving them within 10ns after E goes low (tDHR); the rest is for the
+/// cable capacitance.
+#[cfg(feature = "input")]
+const TURNAROUND_US: u32 = 1;
+
+/// Binding of HD44780 instance to the real hardware
+pub struct LcdHardware<P: Port, D: lcd::Delay> {
+    port: P,
+    delay: D,
+    /// Requested level of RS, re-driven before every transaction with `glitch-filter`.
+    #[cfg(feature = "glitch-filter")]
+    rs: bool,
+    /// Requested level of R/W, re-driven before every transaction with `glitch-filter`.
+    #[cfg(feature = "glitch-filter")]
+    rw: bool,
+    /// `lcd` driver polls the busy flag (otherwise, it waits with fixed delays).
+    #[cfg(feature = "input")]
+    busy_flag: bool,
+}
+
+impl<P: Port, D: lcd::Delay> LcdHardware<P, D> {
+    /// Take over the LCD pins of the port. All pins are configured for output, low level (data
+    /// pins are released instead with the `open-drain` feature).
+    pub fn new(port: P, delay: D) -> LcdHardware<P, D> {
+        #[cfg(not(feature = "open-drain"))]
+        for i in 0..4 {
+            port.output(DATA + i);
+        }
+        #[cfg(feature = "open-drain")]
+        for i in 0..4 {
+            port.write_pin(DATA + i, true);
+            port.open_drain(DATA + i);
+        }
+
+        port.output(RS);
+        port.output(RW);
+        port.output(E);
+
+        port.write_pin(RS, false);
+        port.write_pin(RW, false);
+        port.write_pin(E, false);
+        LcdHardware {
+            port,
+            delay,
+            #[cfg(feature = "glitch-filter")]
+            rs: false,
+            #[cfg(feature = "glitch-filter")]
+            rw: false,
+            #[cfg(feature = "input")]
+            busy_flag: true,
+        }
+    }
+
+    /// Wait until output `pin` actually reads as `level` (long cables add a lot of capacitance),
+    /// but not longer than `SETTLE_US`.
+    #[cfg(feature = "glitch-filter")]
+    fn settle(&mut self, pin: usize, level: bool) {
+        for _ in 0..SETTLE_US {
+            if self.port.read_pin_range(pin, 1) == u16::from(level) {
+                break;
+            }
+            self.delay.delay_us(1);
+        }
+    }
+}
+
+/// Display is considered stuck if it is busy for longer than that (slowest command takes 1.52ms).
+#[cfg(feature = "input")]
+pub const BUSY_TIMEOUT_US: u32 = 10_000;
+
+#[cfg(feature = "input")]
+impl<P: Port, D: lcd::Delay> LcdHardware<P, D> {
+    /// Check that display is connected: write test patterns into the display memory and read them
+    /// back. Display must be initialized. Uses the last address of the first row, which is not
+    /// visible on 16x2 displays.
+    pub fn probe(&mut self) -> bool {
+        const ADDRESS: u8 = 0x27;
+        const SET_DDRAM_ADDR: u8 = 0x80;
+
+        [0x55, 0xaa].into_iter().all(|pattern| {
+            self.send(false, SET_DDRAM_ADDR | ADDRESS);
+            if !self.wait_ready_timeout(BUSY_TIMEOUT_US) {
+                return false;
+            }
+            self.send(true, pattern);
+            if !self.wait_ready_timeout(BUSY_TIMEOUT_US) {
+                return false;
+            }
+            self.send(false, SET_DDRAM_ADDR | ADDRESS);
+            if !self.wait_ready_timeout(BUSY_TIMEOUT_US) {
+                return false;
+            }
+            self.receive(true) == pattern
+        })
+    }
+
+    /// Read character image at the given location (0-7) from CGRAM. Returns `None` if display
+    /// does not respond. Display must be initialized.
+    pub fn read_character(&mut self, location: u8) -> Option<[u8; 8]> {
+        const SET_CGRAM_ADDR: u8 = 0x40;
+
+        self.send(false, SET_CGRAM_ADDR | ((location & 0x7) << 3));
+        let mut map = [0; 8];
+        for row in map.iter_mut() {
+            if !self.wait_ready_timeout(BUSY_TIMEOUT_US) {
+                return None;
+            }
+            // Only five lower bits are used, the rest is undefined
+            *row = self.receive(true) & 0x1f;
+        }
+        Some(map)
+    }
+
+    /// Read the `row` of the display memory, from its first column, into `text`. Returns `false`
+    /// if display does not respond. Display must be initialized; text written afterwards must be
+    /// positioned first (address counter is left after the bytes read).
+    pub fn read_row(&mut self, row: u8, text: &mut [u8]) -> bool {
+        const SET_DDRAM_ADDR: u8 = 0x80;
+
+        self.send(false, SET_DDRAM_ADDR | if row == 1 { 0x40 } else { 0 });
+        text.iter_mut().all(|byte| {
+            if !self.wait_ready_timeout(BUSY_TIMEOUT_US) {
+                return false;
+            }
+            *byte = self.receive(true);
+            true
+        })
+    }
+
+    /// Read the text shown (the visible part of the display memory), see `read_row`.
+    pub fn read_text(&mut self, text: &mut [[u8; COLUMNS]; ROWS]) -> bool {
+        text.iter_mut().enumerate().all(|(row, text)| self.read_row(row as u8, text))
+    }
+
+    /// Make `lcd` driver poll the busy flag (default) or wait with fixed delays, for benchmarking.
+    pub fn use_busy_flag(&mut self, enabled: bool) {
+        self.busy_flag = enabled;
+    }
+
+    /// Poll the busy flag until it is cleared, for at most `timeout_us` microseconds. Returns
+    /// `false` if display is still busy (`lcd` driver would wait forever in that case).
+    pub fn wait_ready_timeout(&mut self, timeout_us: u32) -> bool {
+        use lcd::Hardware;
+
+        self.rs(false);
+        self.rw(true);
+        self.delay.delay_us(1); // tAS
+        let mut ready = false;
+        // Every poll takes at least 4us (two nibbles)
+        for _ in 0..timeout_us.div_ceil(4) {
+            let high = self.read_nibble();
+            self.read_nibble();
+            if high & 0b1000 == 0 {
+                ready = true;
+                break;
+            }
+        }
+        self.rw(false);
+        ready
+    }
+
+    /// Send a byte in 4-bit mode: instruction if `rs` is `false`, data otherwise.
+    fn send(&mut self, rs: bool, byte: u8) {
+        use lcd::Hardware;
+
+        self.rs(rs);
+        self.delay.delay_us(1); // tAS
+        for nibble in [byte >> 4, byte & 0xf] {
+            self.data(nibble);
+            self.enable(true);
+            self.delay.delay_us(1);
+            self.enable(false);
+        }
+    }
+
+    /// Read a byte in 4-bit mode: busy flag and address if `rs` is `false`, data otherwise.
+    fn receive(&mut self, rs: bool) -> u8 {
+        use lcd::Hardware;
+
+        self.rs(rs);
+        self.rw(true);
+        self.delay.delay_us(1); // tAS
+        let high = self.read_nibble();
+        let low = self.read_nibble();
+        self.rw(false);
+        (high << 4) | (low & 0xf)
+    }
+
+    fn read_nibble(&mut self) -> u8 {
+        use lcd::Hardware;
+
+        self.enable(true);
+        self.delay.delay_us(1);
+        let nibble = self.read_data();
+        self.delay.delay_us(1);
+        self.enable(false);
+        nibble
+    }
+}
+
+impl<P: Port, D: lcd::Delay> lcd::Hardware for LcdHardware<P, D> {
+    fn rs(&mut self, bit: bool) {
+        #[cfg(feature = "glitch-filter")]
+        {
+            self.rs = bit;
+        }
+        self.port.write_pin(RS, bit);
+    }
+
+    /// With `glitch-filter`, control lines are re-driven to the requested levels before the pulse
+    /// (noise could have flipped them), and every edge is held until it is verified on the pin.
+    fn enable(&mut self, bit: bool) {
+        #[cfg(feature = "glitch-filter")]
+        if bit {
+            self.port.write_pin(RS, self.rs);
+            self.port.write_pin(RW, self.rw);
+            self.settle(RS, self.rs);
+            self.settle(RW, self.rw);
+            self.delay.delay_us(1); // tAS
+        }
+        self.port.write_pin(E, bit);
+        #[cfg(feature = "glitch-filter")]
+        {
+            self.settle(E, bit);
+            if !bit {
+                self.delay.delay_us(1); // tH
+            }
+        }
+    }
+
+    fn data(&mut self, data: u8) {
+        self.port.write_pin_range(DATA, 4, u16::from(data));
+    }
+
+    // Optional, if not implemented `lcd` library will use delays
+    #[cfg(feature = "input")]
+    fn can_read(&self) -> bool {
+        self.busy_flag
+    }
+
+    /// Display drives the data lines while R/W is high (and E is high), so the bus is turned around
+    /// strictly in order: MCU releases it before R/W goes high, and only takes it back after R/W
+    /// has been low for `TURNAROUND_US`. Otherwise, both would drive the lines at once, shorting
+    /// the opposite levels through the output stages.
+    #[cfg(feature = "input")]
+    fn rw(&mut self, bit: bool) {
+        debug_assert!(self.port.read_pin_range(E, 1) == 0, "R/W switched during the enable pulse");
+        #[cfg(feature = "glitch-filter")]
+        {
+            self.rw = bit;
+        }
+        if bit {
+            // Release the bus first
+            #[cfg(not(feature = "open-drain"))]
+            {
+                // LCD has OD output, set all to '0' just to be sure.
+                self.port.write_pin_range(DATA, 4, 0);
+                for i in 0..4 {
+                    self.port.input(DATA + i);
+                }
+            }
+            // Open-drain pins stay outputs, released by writing them high
+            #[cfg(feature = "open-drain")]
+            self.port.write_pin_range(DATA, 4, 0xf);
+            self.delay.delay_us(TURNAROUND_US);
+
+            // Only then, set R/W to 1 (read)
+            self.port.write_pin(RW, true);
+            #[cfg(feature = "glitch-filter")]
+            self.settle(RW, true);
+        } else {
+            // First, set R/W to 0 (write mode)
+            self.port.write_pin(RW, false);
+            #[cfg(feature = "glitch-filter")]
+            self.settle(RW, false);
+
+            // Give the display time to release the bus
+            self.delay.delay_us(TURNAROUND_US);
+
+            // Re-configure port back to output, driving low (as it was released)
+            #[cfg(not(feature = "open-drain"))]
+            {
+                self.port.write_pin_range(DATA, 4, 0);
+                for i in 0..4 {
+                    self.port.output(DATA + i);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "input")]
+    fn read_data(&mut self) -> u8 {
+        self.port.read_pin_range(DATA, 4) as u8
+    }
+}
+
+impl<P: Port, D: lcd::Delay> lcd::Delay for LcdHardware<P, D> {
+    fn delay_us(&mut self, delay_usec: u32) {
+        self.delay.delay_us(delay_usec);
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn init_sequence() {
+        use crate::mock::{command, init, join_nibbles, MockHardware};
+
+        let mock = MockHardware::new();
+        init(&mock);
+
+        let transfers = mock.transfers();
+        // Reset by instruction: function set (8-bit) three times, then switch to 4-bit mode
+        assert_eq!(&transfers[..4], &[command(0x3), command(0x3), command(0x3), command(0x2)]);
+        assert_eq!(
+            join_nibbles(&transfers[4..]),
+            vec![
+                command(0x28), // Function set: 4-bit, 2 lines, 5x8 dots
+                command(0x08), // Display off
+                command(0x01), // Clear
+                command(0x06), // Entry mode: increment, no shift
+                command(0x0c), // Display on, cursor off, blink off
+            ]
+        );
+        // Reset sequence requires waiting for more than 4.1ms + 100us
+        assert!(mock.elapsed_us() >= 4_100 + 100);
+    }
+
+    #[test]
+    fn write_sequence() {
+        use lcd::Display;
+        use crate::hardware::LcdHardware;
+        use crate::mock::{command, data, init, join_nibbles, MockHardware};
+
+        let mock = MockHardware::new();
+        init(&mock);
+        mock.reset();
+
+        let mut display = Display::new(LcdHardware::new(&mock, &mock));
+        display.position(3, 1);
+        display.print("Hi");
+
+        assert_eq!(
+            join_nibbles(&mock.transfers()),
+            vec![command(0x80 | 0x43), data(b'H'), data(b'i')]
+        );
+    }
+
+    #[test]
+    fn clear_waits_for_completion() {
+        use lcd::Display;
+        use crate::hardware::LcdHardware;
+        use crate::mock::{command, init, join_nibbles, MockHardware};
+
+        let mock = MockHardware::new();
+        init(&mock);
+        mock.reset();
+
+        let hw = LcdHardware::new(&mock, &mock);
+        // Mock display is never busy, so the fixed delays are checked
+        #[cfg(feature = "input")]
+        let hw = {
+            let mut hw = hw;
+            hw.use_busy_flag(false);
+            hw
+        };
+        Display::new(hw).clear();
+        assert_eq!(join_nibbles(&mock.transfers()), vec![command(0x01)]);
+        // Clear takes up to 1.52ms
+        assert!(mock.elapsed_us() >= 1_520);
+    }
+
+    #[test]
+    fn other_pins_are_not_affected() {
+        use crate::hardware::{DATA, E, RS, RW};
+        use crate::mock::{init, MockHardware};
+        use crate::port::Port;
+
+        let lcd_pins = [RS, RW, E, DATA, DATA + 1, DATA + 2, DATA + 3];
+        let others = (0..16)
+            .filter(|pin| !lcd_pins.contains(pin))
+            .fold(0u16, |acc, pin| acc | (1 << pin));
+
+        let mock = MockHardware::new();
+        mock.write_pin_range(0, 16, others);
+        init(&mock);
+
+        assert_eq!(mock.output() & others, others);
+        // E must be left low after every transfer
+        assert_eq!(mock.output() & (1 << E), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "input")]
+    fn display_memory_is_read_back() {
+        use lcd::Display;
+        use crate::display::{TextDisplay, COLUMNS, ROWS};
+        use crate::framebuffer::Framebuffer;
+        use crate::hardware::LcdHardware;
+        use crate::mock::{init, MockHardware};
+
+        let mock = MockHardware::new();
+        init(&mock);
+        let mut fb = Framebuffer::new(Display::new(LcdHardware::new(&mock, &mock)));
+        fb.write_at(0, 0, format_args!("abc")).unwrap();
+        fb.write_at(14, 1, format_args!("yz")).unwrap();
+        fb.flush().unwrap();
+        assert!(fb.begin_verify());
+
+        // Both rows are read from their first column
+        let mut hw = LcdHardware::new(&mock, &mock);
+        let mut read = [[0; COLUMNS]; ROWS];
+        assert!(hw.read_text(&mut read));
+        assert_eq!(&read, &[*b"abc             ", *b"              yz"]);
+        assert_eq!(fb.verify(&read), 0);
+
+        // Cell changed behind the framebuffer is found and re-sent
+        let mut display = Display::new(LcdHardware::new(&mock, &mock));
+        display.position(1, 0);
+        display.print("x");
+        assert!(hw.read_text(&mut read));
+        assert_eq!(&read[0], b"axc             ");
+        assert_eq!(fb.verify(&read), 1);
+        fb.flush().unwrap();
+        assert!(fb.begin_verify());
+        assert!(hw.read_text(&mut read));
+        assert_eq!(&read[0], b"abc             ");
+        assert_eq!(fb.verify(&read), 0);
+    }
+
+    #[test]
+    #[cfg(all(feature = "input", not(feature = "open-drain")))]
+    fn bus_is_turned_around_in_order() {
+        use lcd::Hardware;
+        use crate::hardware::{LcdHardware, DATA, TURNAROUND_US};
+        use crate::mock::{MockHardware, Op};
+
+        let mock = MockHardware::new();
+        let mut hw = LcdHardware::new(&mock, &mock);
+        mock.reset();
+        hw.rw(true);
+        hw.rw(false);
+        let ops = mock.ops();
+        let at = |op: Op| ops.iter().position(|&o| o == op).unwrap();
+
+        // MCU releases the bus, R/W goes high, then low, and the bus is driven again only after the
+        // turnaround
+        let released = (0..4).map(|i| at(Op::Input(DATA + i))).max().unwrap();
+        let read = at(Op::Rw(true));
+        let write = at(Op::Rw(false));
+        let turnaround = write + ops[write..].iter().position(|&o| o == Op::Delay(TURNAROUND_US)).unwrap();
+        let driven = (0..4).map(|i| at(Op::Output(DATA + i))).min().unwrap();
+        assert!(released < read && read < write && write < turnaround && turnaround < driven);
+    }
+
+    #[test]
+    #[cfg(feature = "open-drain")]
+    fn open_drain_bus_is_released() {
+        use lcd::{Display, FunctionDots, FunctionLine};
+        use crate::hardware::{LcdHardware, DATA, E, RS, RW};
+        use crate::mock::{command, MockHardware};
+
+        let mock = MockHardware::new();
+        let mut display = Display::new(LcdHardware::new(&mock, &mock));
+        // Data lines are released (pulled up), control lines are low
+        assert_eq!((mock.output() >> DATA) & 0xf, 0xf);
+        assert_eq!(mock.output() & (1 << RS | 1 << RW | 1 << E), 0);
+
+        // Writes are the same, high bits are released instead of driven
+        display.init(FunctionLine::Line2, FunctionDots::Dots5x8);
+        assert_eq!(&mock.transfers()[..4], &[command(0x3), command(0x3), command(0x3), command(0x2)]);
+    }
+}
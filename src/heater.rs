@@ -0,0 +1,160 @@
+//! PID demo: simulated heater (first-order model, there is no real one on the board), controlled
+//! by the [`Pid`](crate::pid::Pid) loop, with the gains tuned live on the screen.
+//!
+//! Button goes through the parameters (Kp, Ki, Kd, setpoint), encoder changes the one shown right
+//! away. After the setpoint, button is left for the navigation.
+//!
+//! Like the stopwatch, time is passed to `advance`, which steps the simulation and the loop every
+//! `STEP_MS`. If it is called too rarely, the simulation falls behind (so a long pause does not
+//! stall the main loop).
+
+use core::fmt::{self, Write};
+use crate::pid::Pid;
+use crate::text;
+use crate::ui::Event;
+use crate::units::TempUnit;
+
+/// Loop period, in milliseconds.
+pub const STEP_MS: u32 = 100;
+/// Steps done by a single `advance`, at most.
+const MAX_STEPS: u32 = 100;
+
+/// Ambient temperature, in thousandths of °C.
+const AMBIENT_MILLI_C: i32 = 20_000;
+/// Temperature rise above the ambient at full power, in thousandths of °C.
+const RISE_MILLI_C: i32 = 80_000;
+/// Time constant of the heater, in steps.
+const TAU_STEPS: i32 = 200;
+
+/// Output range, in permille of the full power.
+const OUTPUT_MAX: i32 = 1000;
+/// Gain range and step, in hundredths.
+const GAIN_MAX: i32 = 9999;
+const GAIN_STEP: i32 = 5;
+/// Setpoint range and step, in tenths of °C.
+const SETPOINT_MAX: i32 = 950;
+const SETPOINT_STEP: i32 = 5;
+
+/// Parameter shown in the second row.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Param {
+    Kp,
+    Ki,
+    Kd,
+    Setpoint,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Heater {
+    pid: Pid,
+    /// Setpoint, in tenths of °C.
+    setpoint: i32,
+    /// Heater temperature, in thousandths of °C.
+    temperature: i32,
+    /// Heater power, in permille.
+    output: i32,
+    /// Timer value of the last step.
+    last_ms: u32,
+    shown: Param,
+}
+
+impl Default for Heater {
+    /// Kp 5.00, Ki 0.50, Kd 0, heating up to 50°C.
+    fn default() -> Heater {
+        Heater {
+            pid: Pid::new(500, 50, 0, 0, OUTPUT_MAX),
+            setpoint: 500,
+            temperature: AMBIENT_MILLI_C,
+            output: 0,
+            last_ms: 0,
+            shown: Param::Kp,
+        }
+    }
+}
+
+impl Heater {
+    /// Run the loop and the simulation up to `now_ms` (the millisecond timer, which can wrap
+    /// around).
+    pub fn advance(&mut self, now_ms: u32) {
+        let steps = now_ms.wrapping_sub(self.last_ms) / STEP_MS;
+        self.last_ms = self.last_ms.wrapping_add(steps * STEP_MS);
+        for _ in 0..steps.min(MAX_STEPS) {
+            self.output = self.pid.update(self.setpoint, self.temperature(), STEP_MS);
+            let target = AMBIENT_MILLI_C + RISE_MILLI_C / OUTPUT_MAX * self.output;
+            self.temperature += (target - self.temperature) / TAU_STEPS;
+        }
+    }
+
+    /// Heater temperature (process value), in tenths of °C.
+    pub fn temperature(&self) -> i32 {
+        self.temperature / 100
+    }
+
+    /// Heater power (controller output), in permille.
+    pub fn output(&self) -> i32 {
+        self.output
+    }
+
+    pub fn pid(&self) -> &Pid {
+        &self.pid
+    }
+
+    /// Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        let step = match event {
+            Event::Button => {
+                self.shown = match self.shown {
+                    Param::Kp => Param::Ki,
+                    Param::Ki => Param::Kd,
+                    Param::Kd => Param::Setpoint,
+                    Param::Setpoint => {
+                        self.shown = Param::Kp;
+                        return false;
+                    }
+                };
+                return true;
+            }
+            Event::EncoderUp => 1,
+            Event::EncoderDown => -1,
+            _ => return false,
+        };
+        match self.shown {
+            Param::Kp => self.pid.kp = (self.pid.kp + step * GAIN_STEP).clamp(0, GAIN_MAX),
+            Param::Ki => self.pid.ki = (self.pid.ki + step * GAIN_STEP).clamp(0, GAIN_MAX),
+            Param::Kd => self.pid.kd = (self.pid.kd + step * GAIN_STEP).clamp(0, GAIN_MAX),
+            Param::Setpoint => self.setpoint = (self.setpoint + step * SETPOINT_STEP).clamp(0, SETPOINT_MAX),
+        }
+        true
+    }
+
+    /// Write the process value and the setpoint (`PV 47.3 SP 50.0`).
+    pub fn write_values<W: Write>(&self, w: &mut W, unit: TempUnit) -> fmt::Result {
+        w.write_str("PV")?;
+        text::fixed(w, unit.from_tenths_c(self.temperature() as i16), 1, 5)?;
+        w.write_str(" SP")?;
+        text::fixed(w, unit.from_tenths_c(self.setpoint as i16), 1, 5)
+    }
+
+    /// Write the parameter shown and the output (`Kp  5.00 Out 37%`).
+    pub fn write_param<W: Write>(&self, w: &mut W, unit: TempUnit) -> fmt::Result {
+        let (name, gain) = match self.shown {
+            Param::Kp => ("Kp", self.pid.kp),
+            Param::Ki => ("Ki", self.pid.ki),
+            Param::Kd => ("Kd", self.pid.kd),
+            Param::Setpoint => {
+                w.write_str("SP")?;
+                text::fixed(w, unit.from_tenths_c(self.setpoint as i16), 1, 6)?;
+                return write_output(w, self.output);
+            }
+        };
+        w.write_str(name)?;
+        text::fixed(w, gain * 10, 2, 6)?;
+        write_output(w, self.output)
+    }
+}
+
+fn write_output<W: Write>(w: &mut W, output: i32) -> fmt::Result {
+    w.write_str(" Out")?;
+    text::uint(w, (output / 10) as u32, 3)?;
+    w.write_char('%')
+}
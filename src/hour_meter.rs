@@ -0,0 +1,154 @@
+//! Hour meter: runtime of a piece of equipment (an engine, a pump), accumulated while its sense
+//! input is high (see `sense`), in tenths of an hour like on the mechanical ones. Trip hours are
+//! counted since the long press.
+//!
+//! Total is checkpointed to flash (see `kv`) as soon as another tenth of an hour has
+//! accumulated, so the runtime short of it is lost on reset (up to six minutes every time the
+//! board is powered along with the equipment). Running equipment appends a record every six
+//! minutes, so the record page spreads the wear and is erased every 17 hours (10000 erase cycles
+//! are 170000 hours). Trip start (the total at the long press) is rarely changed, so it is kept
+//! with the settings instead.
+
+use core::fmt::{self, Write};
+use crate::text;
+use crate::ui::Event;
+
+/// Tenth of an hour, in milliseconds.
+const TENTH_MS: u32 = 360_000;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct HourMeter {
+    /// Runtime, in tenths of an hour.
+    total: u32,
+    /// Total at the start of the trip.
+    trip_start: u32,
+    /// Runtime short of a tenth, in milliseconds.
+    pending_ms: u32,
+    /// Total checkpointed.
+    saved: u32,
+    /// Was the equipment running at the last update?
+    running: bool,
+    /// Timer value of the last update.
+    last_ms: u32,
+}
+
+impl HourMeter {
+    /// Start from the checkpointed total and the trip start kept with the settings (if any).
+    pub fn restore(&mut self, total: u32, trip_start: Option<u32>) {
+        self.total = total;
+        self.saved = total;
+        self.trip_start = trip_start.unwrap_or(0).min(total);
+    }
+
+    /// Account the time passed since the last call (`now_ms` is the millisecond timer, which can
+    /// wrap around), if the equipment was running then and still is.
+    pub fn update(&mut self, now_ms: u32, running: bool) {
+        let elapsed_ms = now_ms.wrapping_sub(self.last_ms);
+        self.last_ms = now_ms;
+        if self.running && running {
+            let pending_ms = self.pending_ms + elapsed_ms.min(TENTH_MS);
+            self.total = self.total.saturating_add(pending_ms / TENTH_MS);
+            self.pending_ms = pending_ms % TENTH_MS;
+        }
+        self.running = running;
+    }
+
+    pub fn running(&self) -> bool {
+        self.running
+    }
+
+    /// Runtime, in tenths of an hour.
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    /// Runtime since the trip start, in tenths of an hour.
+    pub fn trip(&self) -> u32 {
+        self.total - self.trip_start
+    }
+
+    /// Total at the start of the trip, in tenths of an hour.
+    pub fn trip_start(&self) -> u32 {
+        self.trip_start
+    }
+
+    /// Total to checkpoint, `None` if it is saved already.
+    pub fn checkpoint(&mut self) -> Option<u32> {
+        (self.total != self.saved).then(|| {
+            self.saved = self.total;
+            self.total
+        })
+    }
+
+    /// Long press starts the trip over. Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        if event != Event::LongPress {
+            return false;
+        }
+        self.trip_start = self.total;
+        true
+    }
+
+    /// Write the total (`Total   1234.5h`).
+    pub fn write_total<W: Write>(&self, w: &mut W) -> fmt::Result {
+        w.write_str("Total")?;
+        text::fixed(w, tenths(self.total), 1, 9)?;
+        w.write_str("h")
+    }
+
+    /// Write the trip hours and whether the equipment runs (`Trip   12.3h run`).
+    pub fn write_trip<W: Write>(&self, w: &mut W) -> fmt::Result {
+        w.write_str("Trip")?;
+        text::fixed(w, tenths(self.trip()), 1, 7)?;
+        w.write_str(if self.running { "h run" } else { "h" })
+    }
+}
+
+/// Tenths of an hour as thousandths, for `text::fixed`.
+fn tenths(value: u32) -> i32 {
+    i32::try_from(u64::from(value) * 100).unwrap_or(i32::MAX)
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn hour_meter() {
+        use crate::mock;
+        use crate::screens::{self, Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::HourMeter, &Stats::default(), settings);
+        settings.hour_meter.restore(12_345, Some(12_300));
+        assert_eq!(render(&settings), ("Total   1234.5h ".into(), "Trip    4.5h    ".into()));
+
+        // Runtime is only counted between two updates which found the equipment running, total is
+        // checkpointed once another tenth of an hour has accumulated
+        let meter = &mut settings.hour_meter;
+        meter.update(1000, true);
+        meter.update(200_000, true);
+        meter.update(300_000, false);
+        meter.update(900_000, true);
+        assert_eq!(meter.checkpoint(), None);
+        meter.update(1_061_000, true);
+        assert_eq!(meter.total(), 12_346);
+        assert_eq!(meter.checkpoint(), Some(12_346));
+        assert_eq!(meter.checkpoint(), None);
+        assert_eq!(render(&settings), ("Total   1234.6h ".into(), "Trip    4.6h run".into()));
+
+        // Long press starts the trip over, button is left for the navigation
+        assert!(Screen::HourMeter.long_press());
+        assert!(!settings.handle(Screen::HourMeter, Event::Button));
+        assert!(settings.handle(Screen::HourMeter, Event::LongPress));
+        assert_eq!(settings.hour_meter.trip_start(), 12_346);
+        assert_eq!(render(&settings).1, "Trip    0.0h run");
+
+        // Trip start past the total (total lost with its page) is the total
+        settings.hour_meter.restore(10, Some(12_346));
+        assert_eq!(settings.hour_meter.trip(), 0);
+
+        let mut ui = screens::navigation();
+        ui.handle(Event::Serial(b'#'), &settings);
+        assert_eq!(ui.state(), Screen::HourMeter);
+    }
+}
@@ -0,0 +1,147 @@
+//! Blocking I2C master on I2C2 (PB10 is SCL, PB11 is SDA), standard mode (100Khz). Used by the I2C
+//! scanner screen, the gesture sensor and the door lock keypad.
+//!
+//! Every wait is bounded: if the bus is stuck (device holding SDA low, no pull-ups), the peripheral
+//! is reset and `Error::Timeout` is returned.
+
+use stm32f1::stm32f103::{i2c1, I2C2, RCC};
+use crate::board::PortName;
+use crate::bus::I2c;
+use crate::clock::Clocks;
+use crate::gpio::{self, GPIOExtras};
+
+#[cfg(feature = "maple-mini")]
+compile_error!("I2C2 pins are used by the LCD on Maple Mini");
+
+const SCL: usize = 10;
+const SDA: usize = 11;
+
+/// Bus clock, in Hz.
+const SPEED: u32 = 100_000;
+/// Status register polls before giving up (a byte takes about 100us at 100Khz, and one poll
+/// takes at least 10 cycles at 72Mhz).
+const TIMEOUT_POLLS: u32 = 100_000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Device has not acknowledged its address or data.
+    Nack,
+    /// Bus is stuck, peripheral was reset.
+    Timeout,
+}
+
+pub struct I2cMaster {
+    i2c: I2C2,
+    pclk1: u32,
+}
+
+impl I2cMaster {
+    pub fn new(i2c: I2C2, rcc: &RCC, clocks: &Clocks) -> I2cMaster {
+        let port = gpio::enable_port(rcc, PortName::B);
+        port.pin_config(SCL).alternate().open_drain().output2();
+        port.pin_config(SDA).alternate().open_drain().output2();
+
+        rcc.apb1enr.modify(|_, w| w.i2c2en().set_bit());
+        let master = I2cMaster { i2c, pclk1: clocks.pclk1 };
+        master.init();
+        master
+    }
+
+    fn init(&self) {
+        let i2c = &self.i2c;
+        i2c.cr1.write(|w| w.swrst().set_bit());
+        i2c.cr1.reset();
+        i2c.cr2.write(|w| unsafe { w.freq().bits((self.pclk1 / 1_000_000) as u8) });
+        // Standard mode: SCL high and low times are both CCR periods of PCLK1
+        i2c.ccr.write(|w| unsafe { w.ccr().bits((self.pclk1 / (2 * SPEED)).max(4) as u16) });
+        // Maximum rise time is 1000ns in standard mode
+        i2c.trise.write(|w| w.trise().bits((self.pclk1 / 1_000_000 + 1) as u8));
+        i2c.cr1.write(|w| w.pe().set_bit());
+    }
+
+    /// Wait until `done` returns `true` for the status register. Fails on NACK (sending STOP) or
+    /// on timeout (resetting the peripheral).
+    fn wait<F: Fn(&i2c1::sr1::R) -> bool>(&self, done: F) -> Result<(), Error> {
+        for _ in 0..TIMEOUT_POLLS {
+            let sr1 = self.i2c.sr1.read();
+            if sr1.af().bit_is_set() {
+                self.i2c.sr1.modify(|_, w| w.af().clear_bit());
+                self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+                return Err(Error::Nack);
+            }
+            if done(&sr1) {
+                return Ok(());
+            }
+        }
+        self.init();
+        Err(Error::Timeout)
+    }
+
+    /// Send START (or repeated START) and the address byte, wait for the address to be
+    /// acknowledged. ADDR flag is left set.
+    fn start(&self, address: u8, read: bool) -> Result<(), Error> {
+        self.i2c.cr1.modify(|_, w| w.start().set_bit());
+        self.wait(|sr1| sr1.sb().bit_is_set())?;
+        self.i2c.dr.write(|w| w.dr().bits((address << 1) | u8::from(read)));
+        self.wait(|sr1| sr1.addr().bit_is_set())
+    }
+
+    fn send(&self, address: u8, bytes: &[u8]) -> Result<(), Error> {
+        self.wait_idle()?;
+        self.start(address, false)?;
+        // Cleared by reading SR2 after SR1
+        self.i2c.sr2.read();
+        for &byte in bytes {
+            self.wait(|sr1| sr1.tx_e().bit_is_set())?;
+            self.i2c.dr.write(|w| w.dr().bits(byte));
+        }
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        self.wait(|sr1| sr1.btf().bit_is_set())
+    }
+
+    fn receive(&self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        self.start(address, true)?;
+        let last = buffer.len().saturating_sub(1);
+        // Last byte is not acknowledged, so the device releases the bus
+        self.i2c.cr1.modify(|_, w| w.ack().bit(last > 0));
+        self.i2c.sr2.read();
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            if i == last {
+                self.i2c.cr1.modify(|_, w| w.ack().clear_bit().stop().set_bit());
+            }
+            self.wait(|sr1| sr1.rx_ne().bit_is_set())?;
+            *byte = self.i2c.dr.read().dr().bits();
+        }
+        if buffer.is_empty() {
+            self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+        }
+        Ok(())
+    }
+
+    fn wait_idle(&self) -> Result<(), Error> {
+        for _ in 0..TIMEOUT_POLLS {
+            if self.i2c.sr2.read().busy().bit_is_clear() {
+                return Ok(());
+            }
+        }
+        self.init();
+        Err(Error::Timeout)
+    }
+}
+
+impl I2c for I2cMaster {
+    type Error = Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error> {
+        self.send(address, bytes)?;
+        self.i2c.cr1.modify(|_, w| w.stop().set_bit());
+        Ok(())
+    }
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+        self.send(address, bytes)?;
+        self.receive(address, buffer)
+    }
+}
@@ -0,0 +1,84 @@
+//! I2C slave on I2C2 (PB10 is SCL, PB11 is SDA), so another device can use the board as an I2C
+//! display module (register map is described in `remote`).
+//!
+//! Only writes are supported, reads return `0xff`. I2C2 interrupts (`I2C2_EV` and `I2C2_ER`) must
+//! be unmasked in NVIC by the caller, and both handlers must call `poll`.
+
+use stm32f1::stm32f103::{I2C2, RCC};
+use crate::board::PortName;
+use crate::clock::Clocks;
+use crate::gpio::{self, GPIOExtras};
+
+#[cfg(feature = "maple-mini")]
+compile_error!("I2C2 pins are used by the LCD on Maple Mini");
+// Peripheral clocks are stopped in Stop mode, address match cannot wake the chip up
+#[cfg(feature = "stop-mode")]
+compile_error!("I2C slave does not work in Stop mode");
+
+const SCL: usize = 10;
+const SDA: usize = 11;
+
+/// Something happened on the bus.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SlaveEvent {
+    /// Master has addressed us.
+    Start,
+    /// Byte received.
+    Byte(u8),
+    /// Transfer is finished.
+    Stop,
+}
+
+pub struct I2cSlave {
+    i2c: I2C2,
+}
+
+impl I2cSlave {
+    /// Configure I2C2 as a slave with the given 7-bit `address`.
+    pub fn new(i2c: I2C2, rcc: &RCC, clocks: &Clocks, address: u8) -> I2cSlave {
+        let port = gpio::enable_port(rcc, PortName::B);
+        port.pin_config(SCL).alternate().open_drain().output2();
+        port.pin_config(SDA).alternate().open_drain().output2();
+
+        rcc.apb1enr.modify(|_, w| w.i2c2en().set_bit());
+        i2c.cr1.write(|w| w.swrst().set_bit());
+        i2c.cr1.reset();
+        i2c.cr2.write(|w| unsafe {
+            w.freq().bits((clocks.pclk1 / 1_000_000) as u8)
+                .itevten().set_bit()
+                .itbufen().set_bit()
+                .iterren().set_bit()
+        });
+        // Bit 14 must be kept set by software
+        i2c.oar1.write(|w| unsafe { w.bits((1 << 14) | (u32::from(address) << 1)) });
+        // ACK is cleared by hardware while the peripheral is disabled
+        i2c.cr1.write(|w| w.pe().set_bit());
+        i2c.cr1.modify(|_, w| w.ack().set_bit());
+        I2cSlave { i2c }
+    }
+
+    /// Handle pending interrupt flags. Returns the event, if any (call it again while interrupt is
+    /// pending, one event is returned per call).
+    pub fn poll(&mut self) -> Option<SlaveEvent> {
+        let sr1 = self.i2c.sr1.read();
+        if sr1.addr().bit_is_set() {
+            // Cleared by reading SR2 after SR1
+            self.i2c.sr2.read();
+            Some(SlaveEvent::Start)
+        } else if sr1.rx_ne().bit_is_set() {
+            Some(SlaveEvent::Byte(self.i2c.dr.read().dr().bits()))
+        } else if sr1.tx_e().bit_is_set() {
+            // Reads are not supported
+            self.i2c.dr.write(|w| w.dr().bits(0xff));
+            None
+        } else if sr1.stopf().bit_is_set() {
+            // Cleared by writing CR1 after reading SR1
+            self.i2c.cr1.modify(|_, w| w);
+            Some(SlaveEvent::Stop)
+        } else {
+            // Acknowledge failure ends every read, other errors just abort the transfer
+            self.i2c.sr1.modify(|_, w| w.af().clear_bit().berr().clear_bit().arlo().clear_bit().ovr().clear_bit());
+            None
+        }
+    }
+}
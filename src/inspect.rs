@@ -0,0 +1,156 @@
+//! Memory inspector: shows a few bytes at the address entered by the user, so memory and
+//! peripheral registers could be checked on units without a debugger attached.
+//!
+//! Encoder edits the address one hexadecimal digit at a time: turning it starts editing at the
+//! first digit, button moves to the next digit, and after the last one editing is done.
+//!
+//! Memory is read in words, so peripheral registers are accessed the way they expect. Note that
+//! reading some registers has side effects (for example, reading USART data register clears the
+//! receive flag).
+
+use core::fmt::{self, Write};
+use crate::text;
+use crate::ui::Event;
+
+/// Bytes shown, from the address rounded down to a word.
+pub const WINDOW: usize = 8;
+
+/// Address ranges which can be read without a bus fault: flash, system memory and option bytes,
+/// SRAM, peripherals and the core peripherals.
+const READABLE: [(u32, u32); 5] = [
+    (0x0800_0000, 0x0802_0000),
+    (0x1fff_f000, 0x1fff_f810),
+    (0x2000_0000, 0x2000_5000),
+    (0x4000_0000, 0x4002_3400),
+    (0xe000_0000, 0xe010_0000),
+];
+
+/// Digits in the address.
+const DIGITS: u8 = 8;
+
+/// Address being inspected, with the editing state.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Inspector {
+    pub address: u32,
+    /// Digit being edited (0 is the most significant one).
+    cursor: Option<u8>,
+}
+
+impl Default for Inspector {
+    /// Start of the flash (vector table).
+    fn default() -> Inspector {
+        Inspector {
+            address: 0x0800_0000,
+            cursor: None,
+        }
+    }
+}
+
+impl Inspector {
+    /// First address shown.
+    pub fn start(&self) -> u32 {
+        self.address & !3
+    }
+
+    /// Edit the address. Returns `true` if the event was used (button only moves between
+    /// the digits while editing, otherwise it is left for the navigation).
+    pub fn handle(&mut self, event: Event) -> bool {
+        let step: u32 = match (event, self.cursor) {
+            (Event::Button, Some(digit)) => {
+                self.cursor = (digit + 1 < DIGITS).then_some(digit + 1);
+                return true;
+            }
+            (Event::EncoderUp, _) => 1,
+            (Event::EncoderDown, _) => 0xf,
+            _ => return false,
+        };
+        let digit = *self.cursor.get_or_insert(0);
+        let shift = 4 * u32::from(DIGITS - 1 - digit);
+        let value = ((self.address >> shift) + step) & 0xf;
+        self.address = (self.address & !(0xf << shift)) | (value << shift);
+        true
+    }
+
+    /// Write the address, with the digit being edited in brackets (`@080[0]0000`).
+    pub fn write_address<W: Write>(&self, w: &mut W) -> fmt::Result {
+        w.write_char('@')?;
+        for digit in 0..DIGITS {
+            let value = (self.address >> (4 * u32::from(DIGITS - 1 - digit))) & 0xf;
+            let edited = self.cursor == Some(digit);
+            if edited {
+                w.write_char('[')?;
+            }
+            text::hex(w, value, 1)?;
+            if edited {
+                w.write_char(']')?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Can `WINDOW` bytes starting at `start` be read?
+pub fn is_readable(start: u32) -> bool {
+    let Some(end) = start.checked_add(WINDOW as u32) else {
+        return false;
+    };
+    READABLE.iter().any(|&(low, high)| start >= low && end <= high)
+}
+
+/// Read `WINDOW` bytes starting at `start` (must be word-aligned), `None` if the address cannot
+/// be read.
+#[cfg(target_arch = "arm")]
+pub fn read(start: u32) -> Option<[u8; WINDOW]> {
+    if !is_readable(start) {
+        return None;
+    }
+    let mut bytes = [0; WINDOW];
+    for (i, chunk) in bytes.chunks_mut(4).enumerate() {
+        let word = unsafe { core::ptr::read_volatile((start as *const u32).add(i)) };
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    Some(bytes)
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn inspector_edits_address() {
+        use crate::inspect::{self, Inspector};
+        use crate::mock;
+        use crate::screens::{Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        let mut settings = Settings::default();
+        // Button navigates away unless a digit is being edited
+        assert!(!settings.handle(Screen::Inspect, Event::Button));
+        assert!(settings.handle(Screen::Inspect, Event::EncoderDown));
+        assert!(settings.handle(Screen::Inspect, Event::EncoderDown));
+        assert_eq!(settings.inspector.address, 0xe800_0000);
+        for _ in 0..6 {
+            assert!(settings.handle(Screen::Inspect, Event::Button));
+        }
+        assert!(settings.handle(Screen::Inspect, Event::EncoderUp));
+        assert!(settings.handle(Screen::Inspect, Event::EncoderUp));
+        assert_eq!(settings.inspector.address, 0xe800_0020);
+        assert!(settings.handle(Screen::Inspect, Event::Button));
+        assert!(settings.handle(Screen::Inspect, Event::Button));
+        assert!(!settings.handle(Screen::Inspect, Event::Button));
+
+        assert!(inspect::is_readable(0x2000_4ff8));
+        assert!(!inspect::is_readable(0x2000_4ffc));
+        assert!(!inspect::is_readable(0xffff_fffc));
+
+        let mut settings = Settings::default();
+        settings.handle(Screen::Inspect, Event::EncoderUp);
+        settings.handle(Screen::Inspect, Event::Button);
+        let stats = Stats {
+            memory: Some([0x00, 0x50, 0x00, 0x20, 0xed, 0x01, 0x00, 0x08]),
+            ..Stats::default()
+        };
+        assert_eq!(mock::render(Screen::Inspect, &stats, &settings), ("@1[8]000000     ".into(), "00500020ed010008".into()));
+
+        let settings = Settings { inspector: Inspector::default(), ..settings };
+        assert_eq!(mock::render(Screen::Inspect, &Stats::default(), &settings), ("@08000000       ".into(), "unreadable      ".into()));
+    }
+}
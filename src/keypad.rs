@@ -0,0 +1,54 @@
+//! 4x4 matrix keypad behind a PCF8574 I/O expander on I2C (see `bus::I2c`), as sold together for
+//! the door locks: rows on P0-P3, columns on P4-P7.
+//!
+//! Expander pins are quasi-bidirectional: written high, they are weakly pulled up and can be read,
+//! written low, they sink. Rows are driven low one at a time with all the other pins high, and a
+//! pressed key pulls its column low. Key is reported once it is read the same on two scans in a
+//! row (contacts bounce for a few milliseconds, scans are `SCAN_MS` apart), and only once per press.
+//!
+//! Scan of a missing expander reads no keys, so it can be connected at any time.
+
+use crate::bus::I2c;
+
+/// 7-bit address (A0-A2 tied low; PCF8574A modules are at `0x38`).
+pub const ADDRESS: u8 = 0x20;
+/// Keypad is scanned that often, in milliseconds.
+pub const SCAN_MS: u32 = 20;
+/// Keys, as printed on the keypad (row by row).
+pub const KEYS: [[u8; 4]; 4] = [*b"123A", *b"456B", *b"789C", *b"*0#D"];
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Keypad {
+    /// Key read by the last scan.
+    last: Option<u8>,
+    /// Key reported for the current press.
+    reported: Option<u8>,
+}
+
+impl Keypad {
+    /// Key pressed (the first one found, with several pressed), `None` if there is none or the
+    /// expander does not answer.
+    fn scan<B: I2c>(bus: &mut B) -> Option<u8> {
+        for (row, keys) in KEYS.iter().enumerate() {
+            let mut pins = [0];
+            // Pins are read right after the row is driven, columns settle within the restart
+            bus.write_read(ADDRESS, &[!(1 << row)], &mut pins).ok()?;
+            if let Some(col) = (0..4).find(|col| pins[0] & (0x10 << col) == 0) {
+                return Some(keys[col]);
+            }
+        }
+        None
+    }
+
+    /// Scan the keypad. Returns the key, once it is pressed (and stable).
+    pub fn poll<B: I2c>(&mut self, bus: &mut B) -> Option<u8> {
+        let key = Keypad::scan(bus);
+        let stable = key == self.last;
+        self.last = key;
+        if !stable || key == self.reported {
+            return None;
+        }
+        self.reported = key;
+        key
+    }
+}
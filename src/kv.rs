@@ -0,0 +1,223 @@
+//! Key-value store in two flash pages (see `storage` for the ones on the board): settings,
+//! calibration constants and counters, each of them a 32-bit value under a 16-bit key.
+//!
+//! Values are appended to the active page as records of five half-words: the key, the value and
+//! the CRC-32 of the two (see `crc32`), written last, all of them low half first. The last intact
+//! record of a key wins: one torn by a reset (or corrupted later) is skipped, so the key falls back
+//! to its previous value, or to the default if there is none. Corrupted records are counted on
+//! mount (see `corrupt`), to warn about them.
+//!
+//! Once the page is full, the other one is erased, the latest value of every key is copied there,
+//! and its header (the magic value and the page sequence number) is written last: the copy only
+//! takes over once it is complete, so a reset during the compaction leaves the old page in charge.
+//! Pages take turns, so they wear evenly: with a counter checkpointed every `CHECKPOINT_MS`, each
+//! of them is erased every three hours (10000 erase cycles are about three years of it).
+
+use crate::crc32;
+
+/// Counters are checkpointed that often (if changed), in milliseconds.
+pub const CHECKPOINT_MS: u32 = 60_000;
+/// Pages in the area, half-words in each of them (1K pages).
+pub const PAGES: usize = 2;
+pub const PAGE_WORDS: usize = 512;
+/// Records in a page (header takes the first two half-words).
+pub const PAGE_RECORDS: usize = (PAGE_WORDS - HEADER_WORDS) / RECORD_WORDS;
+const HEADER_WORDS: usize = 2;
+const RECORD_WORDS: usize = 5;
+
+const MAGIC: u16 = 0x5e78;
+/// Erased flash (and the key which cannot be used).
+const ERASED: u16 = 0xffff;
+
+/// Flash area the store is kept in: `PAGES` pages of `PAGE_WORDS` half-words. Erased flash reads
+/// as all ones, and only erased half-words can be programmed.
+pub trait StoreArea {
+    fn read(&self, page: usize, index: usize) -> u16;
+    fn program(&mut self, page: usize, index: usize, value: u16);
+    fn erase(&mut self, page: usize);
+
+    /// CRC-32 of the `words` (see `crc32`).
+    fn crc32(&self, words: &[u32]) -> u32 {
+        crc32::software(words)
+    }
+}
+
+/// Active page and the records in it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Store {
+    page: usize,
+    seq: u16,
+    used: usize,
+    /// Records which did not pass the check on mount.
+    corrupt: usize,
+}
+
+impl Store {
+    /// Find the active page. Area without one (blank, or garbled) is formatted, which stalls the
+    /// CPU for 20-40ms.
+    pub fn mount<A: StoreArea>(area: &mut A) -> Store {
+        let valid = |page: usize| area.read(page, 0) == MAGIC;
+        let seq = |page: usize| area.read(page, 1);
+        let page = match (valid(0), valid(1)) {
+            (true, true) if seq(1) == seq(0).wrapping_add(1) => Some(1),
+            (true, _) => Some(0),
+            (false, true) => Some(1),
+            (false, false) => None,
+        };
+        let Some(page) = page else {
+            area.erase(0);
+            area.program(0, 1, 0);
+            area.program(0, 0, MAGIC);
+            return Store { page: 0, seq: 0, used: 0, corrupt: 0 };
+        };
+        // Key is never erased in a programmed record
+        let used = (0..PAGE_RECORDS)
+            .take_while(|&i| area.read(page, HEADER_WORDS + RECORD_WORDS * i) != ERASED)
+            .count();
+        let corrupt = (0..used).filter(|&i| record(area, page, i).is_none()).count();
+        Store { page, seq: seq(page), used, corrupt }
+    }
+
+    /// Records which did not pass the check on mount (torn by a reset, or corrupted).
+    pub fn corrupt(&self) -> usize {
+        self.corrupt
+    }
+
+    /// Latest value of the `key`, `None` if there is none.
+    pub fn get<A: StoreArea>(&self, area: &A, key: u16) -> Option<u32> {
+        (0..self.used)
+            .rev()
+            .filter_map(|i| record(area, self.page, i))
+            .find(|&(found, _)| found == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Keep the `value` of the `key` (nothing is written if it is kept already). Compaction, once
+    /// the page is full, stalls the CPU for 20-40ms.
+    pub fn set<A: StoreArea>(&mut self, area: &mut A, key: u16, value: u32) {
+        assert!(key != ERASED);
+        if self.get(area, key) == Some(value) {
+            return;
+        }
+        if self.used == PAGE_RECORDS {
+            self.compact(area);
+        }
+        assert!(self.used < PAGE_RECORDS, "too many keys");
+        write_record(area, self.page, self.used, key, value);
+        self.used += 1;
+    }
+
+    /// Copy the latest value of every key to the other page, which becomes the active one.
+    fn compact<A: StoreArea>(&mut self, area: &mut A) {
+        let next = (self.page + 1) % PAGES;
+        area.erase(next);
+        let mut used = 0;
+        for i in 0..self.used {
+            let Some((key, value)) = record(area, self.page, i) else {
+                continue;
+            };
+            let newer = (i + 1..self.used).any(|j| record(area, self.page, j).is_some_and(|(found, _)| found == key));
+            if newer {
+                continue;
+            }
+            write_record(area, next, used, key, value);
+            used += 1;
+        }
+        self.seq = self.seq.wrapping_add(1);
+        area.program(next, 1, self.seq);
+        area.program(next, 0, MAGIC);
+        self.page = next;
+        self.used = used;
+    }
+}
+
+/// Key and value of the record `index` of the `page`, `None` if it is torn or corrupted.
+fn record<A: StoreArea>(area: &A, page: usize, index: usize) -> Option<(u16, u32)> {
+    let at = HEADER_WORDS + RECORD_WORDS * index;
+    let [key, low, high, crc_low, crc_high] = core::array::from_fn(|i| area.read(page, at + i));
+    let value = (u32::from(high) << 16) | u32::from(low);
+    let crc = (u32::from(crc_high) << 16) | u32::from(crc_low);
+    (crc == area.crc32(&[u32::from(key), value])).then_some((key, value))
+}
+
+fn write_record<A: StoreArea>(area: &mut A, page: usize, index: usize, key: u16, value: u32) {
+    let crc = area.crc32(&[u32::from(key), value]);
+    let at = HEADER_WORDS + RECORD_WORDS * index;
+    let words = [key, value as u16, (value >> 16) as u16, crc as u16, (crc >> 16) as u16];
+    for (i, word) in words.into_iter().enumerate() {
+        area.program(page, at + i, word);
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn kv_store() {
+        use crate::crc32;
+        use crate::kv::{Store, StoreArea, PAGES, PAGE_RECORDS, PAGE_WORDS};
+        use crate::mock::MockFlash;
+
+        let record = |key: u16, value: u32| {
+            let crc = crc32::software(&[key.into(), value]);
+            [key, value as u16, (value >> 16) as u16, crc as u16, (crc >> 16) as u16]
+        };
+
+        // Result of the CRC unit for the same word (from the reference manual examples)
+        assert_eq!(crc32::software(&[0x1234_5678]), 0xdf8a_8a2b);
+
+        // Blank area is formatted, values survive the remount, the last one wins
+        let mut area = MockFlash::<PAGES, PAGE_WORDS>::new();
+        area.pages = [[0x1234; PAGE_WORDS]; PAGES];
+        let mut store = Store::mount(&mut area);
+        assert_eq!(area.erases, [1, 0]);
+        assert_eq!(store.get(&area, 1), None);
+        store.set(&mut area, 1, 70_000);
+        store.set(&mut area, 2, 5);
+        store.set(&mut area, 1, 70_123);
+        assert_eq!(area.pages[0][2..12], [record(1, 70_000), record(2, 5)].concat());
+        let mut store = Store::mount(&mut area);
+        assert_eq!((area.erases, store.corrupt()), ([1, 0], 0));
+        assert_eq!((store.get(&area, 1), store.get(&area, 2)), (Some(70_123), Some(5)));
+
+        // Value kept already is not written again, torn record is skipped
+        store.set(&mut area, 2, 5);
+        assert_eq!(area.pages[0][17], 0xffff);
+        area.program(0, 17, 2);
+        area.program(0, 18, 6);
+        let mut store = Store::mount(&mut area);
+        assert_eq!((store.get(&area, 2), store.corrupt()), (Some(5), 1));
+        store.set(&mut area, 2, 7);
+        assert_eq!(area.pages[0][22], 2);
+        assert_eq!(Store::mount(&mut area).get(&area, 2), Some(7));
+
+        // Full page is compacted into the other one, which takes turns with it
+        for value in 0..PAGE_RECORDS as u32 {
+            store.set(&mut area, 3, value);
+        }
+        assert_eq!(area.erases, [1, 1]);
+        assert_eq!(area.pages[1][..2], [0x5e78, 1]);
+        let last = PAGE_RECORDS as u32 - 1;
+        assert_eq!(area.pages[1][2..17], [record(1, 70_123), record(2, 7), record(3, last - 5)].concat());
+        let store = Store::mount(&mut area);
+        assert_eq!((store.get(&area, 1), store.get(&area, 2), store.get(&area, 3)), (Some(70_123), Some(7), Some(last)));
+        // Corrupted record falls back to the default
+        area.pages[1][2 + 5 + 1] = 8;
+        let mut store = Store::mount(&mut area);
+        assert_eq!((store.get(&area, 2), store.corrupt()), (None, 1));
+        store.set(&mut area, 2, 7);
+        for value in 0..2 * PAGE_RECORDS as u32 {
+            store.set(&mut area, 4, value);
+        }
+        assert_eq!(area.erases, [2, 2]);
+        assert_eq!(Store::mount(&mut area).get(&area, 4), Some(2 * PAGE_RECORDS as u32 - 1));
+
+        // Compaction cut short by a reset (the header is not written) leaves the old page in charge
+        let active = area.pages[1];
+        area.pages[0] = [0xffff; PAGE_WORDS];
+        area.pages[0][2..7].copy_from_slice(&active[2..7]);
+        let store = Store::mount(&mut area);
+        assert_eq!(store.get(&area, 3), Some(last));
+        assert_eq!(store.get(&area, 4), Some(2 * PAGE_RECORDS as u32 - 1));
+        assert_eq!(area.erases, [2, 2]);
+    }
+}
@@ -0,0 +1,114 @@
+//! LCD as a sink for the [`log`](https://crates.io/crates/log) records (`lcd-log` feature). The
+//! latest message is shown in the bottom row, over the current screen, for `SHOW_MS`; first
+//! column is the severity icon, message is truncated to the rest of the row.
+//!
+//! Records only replace the stored message, display is updated by the regular refresh (see
+//! `overlay`), so a burst of records costs nothing. To keep messages readable, a new message
+//! replaces the shown one only if that one has been shown for at least `MIN_SHOW_MS` or if the new
+//! one is more severe.
+
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use cortex_m::interrupt::{self, Mutex};
+use heapless::String;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use crate::display::{Line, TextDisplay, COLUMNS};
+use crate::screens::{ERROR_ICON, WARNING_ICON};
+use crate::time;
+
+/// How long a message is shown, in milliseconds.
+pub const SHOW_MS: u32 = 5_000;
+/// How long a message is shown at least before it could be replaced by a message of the same or
+/// lower severity, in milliseconds.
+pub const MIN_SHOW_MS: u32 = 1_000;
+
+/// Row messages are shown in.
+const ROW: u8 = 1;
+
+struct Message {
+    level: Level,
+    text: String<{ COLUMNS - 1 }>,
+    /// When the message was logged, in milliseconds since start.
+    since: u32,
+}
+
+impl Write for Message {
+    /// Text which does not fit is dropped.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.text.push(c).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct LcdLogger {
+    message: Mutex<RefCell<Option<Message>>>,
+}
+
+static LOGGER: LcdLogger = LcdLogger {
+    message: Mutex::new(RefCell::new(None)),
+};
+
+/// Install the logger, records above `level` are ignored.
+pub fn init(level: LevelFilter) {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// Show the current message (if any) over the row rendered by the screen.
+pub fn overlay<D: TextDisplay>(display: &mut D) -> fmt::Result {
+    let now = time::millis();
+    interrupt::free(|cs| {
+        let message = LOGGER.message.borrow(cs).borrow();
+        let Some(message) = message.as_ref().filter(|m| now.wrapping_sub(m.since) < SHOW_MS) else {
+            return Ok(());
+        };
+        let mut line = Line::new(display, ROW);
+        line.write_char(icon(message.level))?;
+        line.write_str(&message.text)?;
+        line.finish()
+    })
+}
+
+fn icon(level: Level) -> char {
+    match level {
+        Level::Error => ERROR_ICON,
+        Level::Warn => WARNING_ICON,
+        Level::Info => 'i',
+        Level::Debug | Level::Trace => 'd',
+    }
+}
+
+impl Log for LcdLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let now = time::millis();
+        interrupt::free(|cs| {
+            let mut current = self.message.borrow(cs).borrow_mut();
+            let replace = current.as_ref().is_none_or(|m| {
+                now.wrapping_sub(m.since) >= MIN_SHOW_MS || record.level() < m.level
+            });
+            if replace {
+                let mut message = Message {
+                    level: record.level(),
+                    text: String::new(),
+                    since: now,
+                };
+                message.write_fmt(*record.args()).ok();
+                *current = Some(message);
+            }
+        });
+    }
+
+    fn flush(&self) {}
+}
@@ -0,0 +1,172 @@
+//! Binding of the [`lcd`](https://crates.io/crates/lcd) crate to the STM32F103 boards ("Blue Pill",
+//! Maple Mini, Nucleo-F103RB).
+//!
+//! Hardware-independent parts (everything except the `stm32f1`-specific implementations) are
+//! also compiled on the host, so they could be tested against `MockHardware` (`mock` feature) or
+//! run in the terminal-based simulator (`simulator` feature).
+#![no_std]
+
+#[cfg(feature = "mock")]
+#[macro_use]
+extern crate std;
+
+pub mod board;
+pub mod port;
+pub mod hardware;
+pub mod screens;
+pub mod display;
+pub mod setup;
+pub mod queue;
+pub mod framebuffer;
+pub mod fmt;
+pub mod text;
+pub mod sched;
+pub mod ui;
+pub mod units;
+pub mod inspect;
+pub mod stopwatch;
+pub mod countdown;
+pub mod alarm;
+pub mod pomodoro;
+pub mod thermostat;
+pub mod threshold;
+pub mod statistics;
+pub mod pid;
+pub mod heater;
+pub mod reaction;
+pub mod pixels;
+pub mod progress;
+pub mod snake;
+pub mod random;
+pub mod dice;
+pub mod morse;
+pub mod calculator;
+pub mod trend;
+pub mod big;
+pub mod bus;
+pub mod remote;
+pub mod console;
+#[cfg(feature = "io-strobe")]
+pub mod strobe;
+#[cfg(feature = "itm-trace")]
+pub mod trace;
+pub mod modbus;
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(feature = "onewire")]
+pub mod onewire;
+#[cfg(feature = "spi-flash")]
+pub mod flash;
+#[cfg(feature = "fan")]
+pub mod fan;
+#[cfg(feature = "tachometer")]
+pub mod tachometer;
+#[cfg(feature = "totalizer")]
+pub mod totalizer;
+#[cfg(feature = "speedometer")]
+pub mod speedometer;
+#[cfg(feature = "hour-meter")]
+pub mod hour_meter;
+#[cfg(feature = "pin-monitor")]
+pub mod pin_monitor;
+#[cfg(feature = "weather")]
+pub mod weather;
+#[cfg(feature = "gesture")]
+pub mod apds9960;
+#[cfg(feature = "door-lock")]
+pub mod keypad;
+#[cfg(feature = "door-lock")]
+pub mod lock;
+#[cfg(feature = "access-log")]
+pub mod access_log;
+#[cfg(feature = "calibration")]
+pub mod calibration;
+#[cfg(feature = "battery")]
+pub mod battery;
+#[cfg(feature = "pir")]
+pub mod motion;
+#[cfg(feature = "backlight")]
+pub mod backlight;
+#[cfg(feature = "day-night")]
+pub mod schedule;
+#[cfg(feature = "dmx")]
+pub mod dmx;
+#[cfg(feature = "midi")]
+pub mod midi;
+#[cfg(feature = "terminal")]
+pub mod terminal;
+#[cfg(feature = "link")]
+pub mod link;
+#[cfg(any(feature = "shell", feature = "rtt-console"))]
+pub mod shell;
+#[cfg(feature = "temp-log")]
+pub mod logger;
+#[cfg(any(feature = "hal-02", feature = "hal-1"))]
+pub mod hal;
+#[cfg(target_arch = "arm")]
+pub mod gpio;
+#[cfg(target_arch = "arm")]
+pub mod delay;
+#[cfg(target_arch = "arm")]
+pub mod clock;
+#[cfg(target_arch = "arm")]
+pub mod watchdog;
+#[cfg(target_arch = "arm")]
+pub mod time;
+#[cfg(target_arch = "arm")]
+pub mod power;
+#[cfg(target_arch = "arm")]
+pub mod button;
+#[cfg(target_arch = "arm")]
+pub mod sensor;
+#[cfg(target_arch = "arm")]
+pub mod supply;
+pub mod scratchpad;
+pub mod crc32;
+pub mod kv;
+#[cfg(target_arch = "arm")]
+pub mod shared;
+#[cfg(target_arch = "arm")]
+pub mod timer;
+#[cfg(target_arch = "arm")]
+pub mod stack;
+#[cfg(target_arch = "arm")]
+pub mod backup;
+#[cfg(target_arch = "arm")]
+pub mod buzzer;
+#[cfg(target_arch = "arm")]
+pub mod relay;
+#[cfg(target_arch = "arm")]
+pub mod storage;
+#[cfg(all(target_arch = "arm", feature = "lcd-log"))]
+pub mod lcd_log;
+#[cfg(all(target_arch = "arm", feature = "i2c-slave"))]
+pub mod i2c_slave;
+#[cfg(all(target_arch = "arm", any(feature = "i2c-scan", feature = "gesture", feature = "door-lock")))]
+pub mod i2c_master;
+#[cfg(all(target_arch = "arm", feature = "spi-slave"))]
+pub mod spi_slave;
+#[cfg(all(target_arch = "arm", feature = "spi-flash"))]
+pub mod spi_master;
+#[cfg(all(target_arch = "arm", feature = "fan"))]
+pub mod fan_timer;
+#[cfg(all(target_arch = "arm", feature = "hour-meter"))]
+pub mod sense;
+#[cfg(all(target_arch = "arm", feature = "pir"))]
+pub mod pir;
+#[cfg(all(target_arch = "arm", feature = "backlight"))]
+pub mod backlight_pwm;
+#[cfg(all(target_arch = "arm", any(feature = "debug-log", feature = "rtt-console")))]
+pub mod rtt;
+#[cfg(all(target_arch = "arm", any(feature = "tachometer", feature = "totalizer")))]
+pub mod pulse_input;
+#[cfg(all(target_arch = "arm", feature = "pin-monitor"))]
+pub mod edges;
+#[cfg(all(target_arch = "arm", any(feature = "modbus", feature = "lcdproc", feature = "temp-log", feature = "dmx", feature = "midi", feature = "terminal", feature = "shell", feature = "link")))]
+pub mod serial;
+#[cfg(all(target_arch = "arm", feature = "stm32duino-bootloader"))]
+pub mod bootloader;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "mock")]
+pub mod sim;
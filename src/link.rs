@@ -0,0 +1,197 @@
+//! Display link: framed protocol on USART1 for driving the display from another MCU reliably, with
+//! every frame checked and answered, so the sender knows to send it again.
+//!
+//! Frame is `STX` (`0x02`), payload length (up to `MAX_PAYLOAD`), command, payload and the CRC of
+//! the length, command and payload (Modbus CRC, see `modbus::crc16`, low byte first). Receiver
+//! answers every frame with a single byte: `ACK` (`0x06`) if the frame is good and the command is
+//! done, `NAK` (`0x15`) if the CRC does not match or the command is unknown or malformed.
+//!
+//! | Command | Name      | Payload                                                       |
+//! |---------|-----------|---------------------------------------------------------------|
+//! | `0x00`  | PING      | none, only answered                                           |
+//! | `0x01`  | CLEAR     | none, clears the text                                         |
+//! | `0x02`  | TEXT      | cursor address (as in HD44780, see `remote`) and the text     |
+//! | `0x03`  | BACKLIGHT | `0` is off, anything else is on                               |
+//! | `0x04`  | RELEASE   | none, back to the screens                                     |
+//!
+//! Commands do the same when repeated, so a frame can be sent again if the answer is lost.
+//!
+//! Decoder waits for `STX` and then counts the frame by its length, so it could be thrown off by a
+//! noise byte or a lost one. It gets back in sync by:
+//!
+//! * taking the length over `MAX_PAYLOAD` as not a frame start;
+//! * scanning the bytes of the frame failing the CRC for the next `STX`, as the frame it started
+//!   at may be a noise byte with the real frame behind it (the failed frame is answered with `NAK`,
+//!   the frame found with its own answer, so the sender should go by the last one);
+//! * scanning the same way the frame which has stalled for `TIMEOUT_MS` (sender waits for the
+//!   answer longer than that before sending the frame again).
+
+use crate::modbus::crc16;
+use crate::remote::RemoteText;
+
+pub const STX: u8 = 0x02;
+pub const ACK: u8 = 0x06;
+pub const NAK: u8 = 0x15;
+
+pub const CMD_PING: u8 = 0x00;
+pub const CMD_CLEAR: u8 = 0x01;
+pub const CMD_TEXT: u8 = 0x02;
+pub const CMD_BACKLIGHT: u8 = 0x03;
+pub const CMD_RELEASE: u8 = 0x04;
+
+/// Longest payload: cursor address and a row of text, with room to spare.
+pub const MAX_PAYLOAD: usize = 32;
+/// Longest frame: `STX`, length, command, payload and CRC.
+const MAX_FRAME: usize = MAX_PAYLOAD + 5;
+/// Frame is dropped if the next byte does not come within that long, in milliseconds.
+pub const TIMEOUT_MS: u32 = 50;
+
+pub struct Link {
+    /// Bytes of the frame received so far, starting with `STX`.
+    frame: [u8; MAX_FRAME],
+    len: usize,
+    /// Time the last byte was received at.
+    last_ms: u32,
+}
+
+impl Link {
+    pub const fn new() -> Link {
+        Link { frame: [0; MAX_FRAME], len: 0, last_ms: 0 }
+    }
+
+    /// Byte received over serial at `now_ms` (the millisecond timer, which can wrap around).
+    /// Returns the answer to send, once the frame is complete.
+    pub fn receive(&mut self, text: &mut RemoteText, byte: u8, now_ms: u32) -> Option<u8> {
+        if self.len > 0 && now_ms.wrapping_sub(self.last_ms) > TIMEOUT_MS {
+            // Answer to a frame found in the stalled one is dropped, it is too late anyway
+            self.rescan(text, now_ms);
+        }
+        self.last_ms = now_ms;
+        match self.len {
+            0 if byte != STX => return None,
+            // Length too big, so the `STX` was not a frame start (but this byte may be)
+            1 if usize::from(byte) > MAX_PAYLOAD => {
+                self.len = usize::from(byte == STX);
+                return None;
+            }
+            _ => {}
+        }
+        self.frame[self.len] = byte;
+        self.len += 1;
+        if self.len < usize::from(self.frame[1]) + 5 {
+            return None;
+        }
+        let body = &self.frame[1..self.len - 2];
+        if crc16(body).to_le_bytes() == self.frame[self.len - 2..self.len] {
+            self.len = 0;
+            return Some(if execute(text, body[1], &body[2..]) { ACK } else { NAK });
+        }
+        Some(self.rescan(text, now_ms).unwrap_or(NAK))
+    }
+
+    /// Drop the frame received so far, and take its bytes again, starting after its `STX`. Returns
+    /// the answer to the last frame found in them.
+    fn rescan(&mut self, text: &mut RemoteText, now_ms: u32) -> Option<u8> {
+        let frame = self.frame;
+        let len = core::mem::take(&mut self.len);
+        frame[1..len].iter().fold(None, |answer, &byte| self.receive(text, byte, now_ms).or(answer))
+    }
+}
+
+impl Default for Link {
+    fn default() -> Link {
+        Link::new()
+    }
+}
+
+/// Do the command. Returns `false` if it is unknown or the payload is wrong.
+fn execute(text: &mut RemoteText, command: u8, payload: &[u8]) -> bool {
+    match (command, payload) {
+        (CMD_PING, []) => {}
+        (CMD_CLEAR, []) => text.clear(),
+        (CMD_TEXT, [address, chars @ ..]) => {
+            text.set_cursor(*address);
+            chars.iter().for_each(|&byte| text.write(byte));
+        }
+        (CMD_BACKLIGHT, [on]) => text.set_backlight(*on != 0),
+        (CMD_RELEASE, []) => {
+            text.release();
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Build the frame for the `command` (for the sender, and for testing).
+pub fn encode(command: u8, payload: &[u8]) -> heapless::Vec<u8, MAX_FRAME> {
+    let mut frame = heapless::Vec::new();
+    let len = payload.len().min(MAX_PAYLOAD);
+    frame.extend_from_slice(&[STX, len as u8, command]).ok();
+    frame.extend_from_slice(&payload[..len]).ok();
+    let crc = crc16(&frame[1..]);
+    frame.extend_from_slice(&crc.to_le_bytes()).ok();
+    frame
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn display_link_frames() {
+        use crate::link::*;
+        use crate::remote::RemoteText;
+
+        let mut link = Link::new();
+        let mut text = RemoteText::new();
+        let feed = |link: &mut Link, text: &mut RemoteText, bytes: &[u8], now_ms: u32| {
+            bytes.iter().filter_map(|&byte| link.receive(text, byte, now_ms)).collect::<std::vec::Vec<u8>>()
+        };
+
+        // Every frame is answered, text goes to the cursor address
+        assert_eq!(feed(&mut link, &mut text, &encode(CMD_PING, &[]), 0), [ACK]);
+        assert!(!text.is_active());
+        assert_eq!(feed(&mut link, &mut text, &encode(CMD_TEXT, b"\x00Hello"), 0), [ACK]);
+        assert_eq!(feed(&mut link, &mut text, &encode(CMD_TEXT, b"\x43link"), 0), [ACK]);
+        assert_eq!((text.row(0), text.row(1)), ("Hello           ", "   link         "));
+        assert_eq!(feed(&mut link, &mut text, &encode(CMD_BACKLIGHT, &[0]), 0), [ACK]);
+        assert!(!text.backlight());
+
+        // Corrupted frame, unknown command and wrong payload are refused
+        let mut frame = encode(CMD_TEXT, b"\x00Bad");
+        frame[4] ^= 0x20;
+        assert_eq!(feed(&mut link, &mut text, &frame, 0), [NAK]);
+        assert_eq!(text.row(0), "Hello           ");
+        assert_eq!(feed(&mut link, &mut text, &encode(0x7f, &[]), 0), [NAK]);
+        assert_eq!(feed(&mut link, &mut text, &encode(CMD_BACKLIGHT, &[1, 2]), 0), [NAK]);
+
+        // Noise before the frame: bytes outside of frames are skipped, so is the length too big
+        let mut bytes = std::vec![0x55, 0xff, STX, 0x80];
+        bytes.extend_from_slice(&encode(CMD_CLEAR, &[]));
+        assert_eq!(feed(&mut link, &mut text, &bytes, 0), [ACK]);
+        assert_eq!(text.row(0), "                ");
+
+        // Noise `STX` swallowing the real frame, which is found while scanning the failed one
+        let mut bytes = std::vec![STX, 6];
+        bytes.extend_from_slice(&encode(CMD_TEXT, b"\x00Sync"));
+        assert_eq!(feed(&mut link, &mut text, &bytes, 0), [NAK, ACK]);
+        assert_eq!(text.row(0), "Sync            ");
+
+        // ... or stalled, the frame inside is found once the next byte comes
+        let mut bytes = std::vec![STX, 20];
+        bytes.extend_from_slice(&encode(CMD_TEXT, b"\x00Late"));
+        assert_eq!(feed(&mut link, &mut text, &bytes, 0), []);
+        assert_eq!(feed(&mut link, &mut text, &encode(CMD_PING, &[]), 100), [ACK]);
+        assert_eq!(text.row(0), "Late            ");
+
+        // Lost byte: the stalled frame is dropped, the frame sent again is taken
+        let frame = encode(CMD_TEXT, b"\x40Again");
+        assert_eq!(feed(&mut link, &mut text, &frame[..frame.len() - 1], 100), []);
+        assert_eq!(feed(&mut link, &mut text, &frame, 100 + TIMEOUT_MS + 1), [ACK]);
+        assert_eq!(text.row(1), "Again           ");
+
+        // Frames are repeated safely, release returns to the screens
+        assert_eq!(feed(&mut link, &mut text, &frame, 200), [ACK]);
+        assert_eq!(text.row(1), "Again           ");
+        assert_eq!(feed(&mut link, &mut text, &encode(CMD_RELEASE, &[]), 200), [ACK]);
+        assert!(!text.is_active());
+    }
+}
@@ -0,0 +1,435 @@
+//! Door lock: PIN typed on the keypad (see `keypad`) opens the door strike on the relay output for
+//! `OPEN_MS`.
+//!
+//! Keys: digits are typed (shown masked), `#` enters, `*` clears. While the door is open, `A`
+//! starts the PIN change (new PIN is entered twice) and `D` locks right away; `C` cancels the PIN
+//! change. PIN is 4 to 8 digits, `1234` until it is changed.
+//!
+//! After `ATTEMPTS` wrong PINs in a row, keypad is locked out for `LOCKOUT_MS`, twice as long after
+//! every further lockout (up to 16 times), until the right PIN is entered. Digits typed are
+//! dropped if the PIN is not entered within `ENTRY_MS`.
+//!
+//! Only a hash of the PIN is kept (and stored in flash by the main loop, see `pin_hash`), so the
+//! PIN cannot be read from a flash dump as is. A 4-digit PIN is easily found from its hash, though:
+//! it is the lockout which protects the door, not the hash.
+//!
+//! Entered PINs which open the door, fail or change the PIN are kept for the access log (see
+//! `take_access`).
+//!
+//! Like the stopwatch, time is passed to `advance`, which must be called before every event and
+//! every render.
+
+use core::fmt::{self, Write};
+use crate::text;
+use crate::ui::Event;
+
+/// PIN length, in digits.
+const MIN_PIN: usize = 4;
+const MAX_PIN: usize = 8;
+/// PIN used until it is changed.
+const DEFAULT_PIN: &[u8] = b"1234";
+/// Door is open that long after the right PIN, in milliseconds.
+pub const OPEN_MS: u32 = 5000;
+/// Wrong PINs in a row before the lockout.
+const ATTEMPTS: u8 = 3;
+/// First lockout, in milliseconds.
+const LOCKOUT_MS: u32 = 30_000;
+/// Lockout doubles up to that many times.
+const MAX_DOUBLINGS: u8 = 4;
+/// Typed digits are dropped after that long without a key, in milliseconds.
+const ENTRY_MS: u32 = 10_000;
+/// Stored PIN hash meaning "no PIN set" (erased flash).
+const NO_PIN: u32 = u32::MAX;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    /// Waiting for the PIN.
+    Locked,
+    /// Door is open, since the given time.
+    Open(u32),
+    /// Keypad is locked out: since the given time, for that long.
+    Lockout(u32, u32),
+    /// New PIN being typed.
+    NewPin,
+    /// New PIN (its hash) being typed again.
+    Confirm(u32),
+}
+
+/// What the last entered PIN did, shown until the next key.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Notice {
+    None,
+    /// Wrong PIN, that many attempts are left.
+    Wrong(u8),
+    /// PIN is shorter than `MIN_PIN`.
+    TooShort,
+    /// PIN typed again is not the same.
+    Mismatch,
+    PinChanged,
+}
+
+/// What an entered PIN did, for the access log.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    Opened,
+    /// Wrong PIN, that many attempts are left.
+    Denied(u8),
+    /// Wrong PIN, keypad is locked out for that many seconds.
+    LockedOut(u32),
+    PinChanged,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DoorLock {
+    state: State,
+    notice: Notice,
+    /// Digits typed so far.
+    entry: [u8; MAX_PIN],
+    len: usize,
+    /// Hash of the PIN, `NO_PIN` for the default one.
+    pin_hash: u32,
+    /// Wrong PINs since the last lockout (or the right PIN).
+    failures: u8,
+    /// Lockouts since the last right PIN.
+    lockouts: u8,
+    /// Time of the last key.
+    key_ms: u32,
+    /// Timer value of the last `advance`.
+    last_ms: u32,
+    /// Access not taken yet.
+    access: Option<Access>,
+}
+
+impl Default for DoorLock {
+    fn default() -> DoorLock {
+        DoorLock {
+            state: State::Locked,
+            notice: Notice::None,
+            entry: [0; MAX_PIN],
+            len: 0,
+            pin_hash: NO_PIN,
+            failures: 0,
+            lockouts: 0,
+            key_ms: 0,
+            last_ms: 0,
+            access: None,
+        }
+    }
+}
+
+/// FNV-1a hash of the PIN digits, never `NO_PIN`.
+fn hash(pin: &[u8]) -> u32 {
+    let hash = pin.iter().fold(0x811c_9dc5u32, |hash, &digit| (hash ^ u32::from(digit)).wrapping_mul(0x0100_0193));
+    hash.min(NO_PIN - 1)
+}
+
+impl DoorLock {
+    /// Lock the door and end the lockout when their time is over, drop the digits typed too long
+    /// ago (`now_ms` is the millisecond timer, which can wrap around).
+    pub fn advance(&mut self, now_ms: u32) {
+        self.last_ms = now_ms;
+        match self.state {
+            State::Open(since_ms) if now_ms.wrapping_sub(since_ms) >= OPEN_MS => self.state = State::Locked,
+            State::Lockout(since_ms, length_ms) if now_ms.wrapping_sub(since_ms) >= length_ms => {
+                self.state = State::Locked;
+                self.notice = Notice::None;
+            }
+            _ => {}
+        }
+        if self.len > 0 && now_ms.wrapping_sub(self.key_ms) >= ENTRY_MS {
+            self.len = 0;
+        }
+    }
+
+    /// Should the door strike be powered?
+    pub fn is_open(&self) -> bool {
+        matches!(self.state, State::Open(_))
+    }
+
+    /// Hash of the PIN, `u32::MAX` if it was never changed.
+    pub fn pin_hash(&self) -> u32 {
+        self.pin_hash
+    }
+
+    /// Restore the PIN (as returned by `pin_hash`).
+    pub fn set_pin_hash(&mut self, pin_hash: u32) {
+        self.pin_hash = pin_hash;
+    }
+
+    /// What the last entered PIN did, once.
+    pub fn take_access(&mut self) -> Option<Access> {
+        self.access.take()
+    }
+
+    fn is_pin(&self, pin_hash: u32) -> bool {
+        match self.pin_hash {
+            NO_PIN => pin_hash == hash(DEFAULT_PIN),
+            stored => pin_hash == stored,
+        }
+    }
+
+    /// Keys (serial input) go to the lock, button and encoder are left for the navigation.
+    /// Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        match event {
+            Event::Serial(key @ (b'0'..=b'9' | b'*' | b'#' | b'A'..=b'D')) => {
+                self.key(key);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn key(&mut self, key: u8) {
+        self.key_ms = self.last_ms;
+        if matches!(self.state, State::Lockout(..)) {
+            return;
+        }
+        self.notice = Notice::None;
+        match (self.state, key) {
+            (State::Open(_), b'A') => self.state = State::NewPin,
+            (State::Open(_), b'D') => self.state = State::Locked,
+            (State::NewPin | State::Confirm(_), b'C') => self.state = State::Locked,
+            (State::Open(_), _) => {}
+            (_, b'0'..=b'9') if self.len < MAX_PIN => {
+                self.entry[self.len] = key;
+                self.len += 1;
+            }
+            (_, b'*') => self.len = 0,
+            (state, b'#') => {
+                let len = core::mem::take(&mut self.len);
+                if len < MIN_PIN {
+                    self.notice = Notice::TooShort;
+                    return;
+                }
+                self.enter(state, hash(&self.entry[..len]));
+            }
+            _ => {}
+        }
+    }
+
+    /// PIN (its hash) is entered in the `state`.
+    fn enter(&mut self, state: State, pin_hash: u32) {
+        match state {
+            State::Locked if self.is_pin(pin_hash) => {
+                self.state = State::Open(self.last_ms);
+                self.failures = 0;
+                self.lockouts = 0;
+                self.access = Some(Access::Opened);
+            }
+            State::Locked => {
+                self.failures += 1;
+                if self.failures < ATTEMPTS {
+                    self.notice = Notice::Wrong(ATTEMPTS - self.failures);
+                    self.access = Some(Access::Denied(ATTEMPTS - self.failures));
+                    return;
+                }
+                let length_ms = LOCKOUT_MS << self.lockouts.min(MAX_DOUBLINGS);
+                self.failures = 0;
+                self.state = State::Lockout(self.last_ms, length_ms);
+                self.lockouts = self.lockouts.saturating_add(1);
+                self.access = Some(Access::LockedOut(length_ms / 1000));
+            }
+            State::NewPin => self.state = State::Confirm(pin_hash),
+            State::Confirm(first) if first == pin_hash => {
+                self.pin_hash = pin_hash;
+                self.state = State::Locked;
+                self.notice = Notice::PinChanged;
+                self.access = Some(Access::PinChanged);
+            }
+            State::Confirm(_) => {
+                self.state = State::NewPin;
+                self.notice = Notice::Mismatch;
+            }
+            State::Open(_) | State::Lockout(..) => {}
+        }
+    }
+
+    /// Seconds left until `since_ms` plus `length_ms`, rounded up.
+    fn seconds_left(&self, since_ms: u32, length_ms: u32) -> u32 {
+        length_ms.saturating_sub(self.last_ms.wrapping_sub(since_ms)).div_ceil(1000)
+    }
+
+    /// Write the state of the lock, or what the last PIN entered did (`Wrong PIN, 2 left`).
+    pub fn write_status<W: Write>(&self, w: &mut W) -> fmt::Result {
+        match (self.state, self.notice) {
+            (State::Open(since_ms), _) => {
+                w.write_str("Open ")?;
+                text::uint(w, self.seconds_left(since_ms, OPEN_MS), 0)?;
+                w.write_char('s')
+            }
+            (State::Lockout(since_ms, length_ms), _) => {
+                w.write_str("Locked out ")?;
+                text::uint(w, self.seconds_left(since_ms, length_ms), 0)?;
+                w.write_char('s')
+            }
+            (_, Notice::Wrong(left)) => {
+                w.write_str("Wrong PIN, ")?;
+                text::uint(w, u32::from(left), 0)?;
+                w.write_str(" left")
+            }
+            (_, Notice::TooShort) => w.write_str("PIN too short"),
+            (_, Notice::Mismatch) => w.write_str("PINs differ"),
+            (_, Notice::PinChanged) => w.write_str("PIN changed"),
+            (State::NewPin, Notice::None) => w.write_str("New PIN"),
+            (State::Confirm(_), Notice::None) => w.write_str("Repeat new PIN"),
+            (State::Locked, Notice::None) => w.write_str("Locked"),
+        }
+    }
+
+    /// Write the PIN typed, masked (`PIN ****`), or the keys while the door is open.
+    pub fn write_entry<W: Write>(&self, w: &mut W) -> fmt::Result {
+        match self.state {
+            State::Open(_) => w.write_str("A:new PIN D:lock"),
+            State::Lockout(..) => Ok(()),
+            _ => {
+                w.write_str("PIN ")?;
+                (0..self.len).try_for_each(|_| w.write_char('*'))
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn door_lock() {
+        use crate::keypad::{Keypad, ADDRESS};
+        use crate::lock::{DoorLock, OPEN_MS};
+        use crate::mock::{MockDevice, MockI2c};
+        use crate::screens::{self, Screen};
+        use crate::ui::Event;
+
+        /// PCF8574 with the keypad: the key pressed (row and column) pulls its column low while
+        /// its row is driven low.
+        #[derive(Default)]
+        struct Expander {
+            pressed: Option<(usize, usize)>,
+        }
+
+        impl MockDevice for Expander {
+            fn write(&mut self, _: &[u8]) -> bool {
+                false
+            }
+
+            fn read(&mut self, bytes: &[u8], buffer: &mut [u8]) -> bool {
+                buffer[0] = match self.pressed {
+                    Some((row, col)) if bytes[0] & (1 << row) == 0 => bytes[0] & !(0x10 << col),
+                    _ => bytes[0],
+                };
+                true
+            }
+        }
+
+        // Key is reported once it is stable, once per press; no expander reads no keys
+        let mut keypad = Keypad::default();
+        let mut expander = MockI2c::new(&[], Expander { pressed: Some((3, 2)) });
+        assert_eq!(keypad.poll(&mut expander), None);
+        expander.present = vec![ADDRESS];
+        assert_eq!(keypad.poll(&mut expander), None);
+        assert_eq!(keypad.poll(&mut expander), Some(b'#'));
+        assert_eq!(keypad.poll(&mut expander), None);
+        expander.device.pressed = None;
+        assert_eq!(keypad.poll(&mut expander), None);
+        expander.device.pressed = Some((1, 3));
+        keypad.poll(&mut expander);
+        assert_eq!(keypad.poll(&mut expander), Some(b'B'));
+
+        // Key pressed shows the door lock screen, from any screen
+        let mut ui = screens::navigation();
+        assert!(ui.enter(Screen::Lock));
+        assert!(!ui.enter(Screen::Lock));
+        assert_eq!(ui.state(), Screen::Lock);
+
+        let mut lock = DoorLock::default();
+        let mut now_ms = 1000;
+        let keys = |lock: &mut DoorLock, keys: &[u8], now_ms: u32| {
+            lock.advance(now_ms);
+            keys.iter().for_each(|&key| assert!(lock.handle(Event::Serial(key))));
+        };
+        let rows = |lock: &DoorLock| {
+            let (mut status, mut entry) = (std::string::String::new(), std::string::String::new());
+            lock.write_status(&mut status).unwrap();
+            lock.write_entry(&mut entry).unwrap();
+            (status, entry)
+        };
+        assert!(!lock.handle(Event::Button));
+        assert_eq!(rows(&lock), ("Locked".into(), "PIN ".into()));
+
+        // Default PIN, masked; door closes after a while
+        keys(&mut lock, b"12", now_ms);
+        assert_eq!(rows(&lock), ("Locked".into(), "PIN **".into()));
+        keys(&mut lock, b"34#", now_ms);
+        assert!(lock.is_open());
+        assert_eq!(rows(&lock), ("Open 5s".into(), "A:new PIN D:lock".into()));
+        now_ms += OPEN_MS;
+        lock.advance(now_ms);
+        assert!(!lock.is_open());
+
+        // Clear, short PIN, and the digits typed too long ago are dropped
+        keys(&mut lock, b"99*12#", now_ms);
+        assert_eq!(rows(&lock).0, "PIN too short");
+        keys(&mut lock, b"12", now_ms);
+        keys(&mut lock, b"34#", now_ms + 10_000);
+        assert_eq!(rows(&lock).0, "PIN too short");
+
+        // PIN change, typed twice
+        keys(&mut lock, b"1234#A", now_ms);
+        assert_eq!(rows(&lock).0, "New PIN");
+        keys(&mut lock, b"2580#", now_ms);
+        assert_eq!(rows(&lock).0, "Repeat new PIN");
+        keys(&mut lock, b"2581#", now_ms);
+        assert_eq!(rows(&lock).0, "PINs differ");
+        keys(&mut lock, b"2580#2580#", now_ms);
+        assert_eq!(rows(&lock).0, "PIN changed");
+        assert!(!lock.is_open());
+        let pin_hash = lock.pin_hash();
+        assert_ne!(pin_hash, u32::MAX);
+
+        // Wrong PINs lock the keypad out, longer every time
+        keys(&mut lock, b"1234#", now_ms);
+        assert_eq!(rows(&lock).0, "Wrong PIN, 2 left");
+        keys(&mut lock, b"1234#1234#", now_ms);
+        assert_eq!(rows(&lock), ("Locked out 30s".into(), "".into()));
+        keys(&mut lock, b"2580#", now_ms);
+        assert!(!lock.is_open());
+        now_ms += 30_000;
+        keys(&mut lock, b"0000#0000#0000#", now_ms);
+        assert_eq!(rows(&lock).0, "Locked out 60s");
+        now_ms += 60_000;
+        keys(&mut lock, b"2580#", now_ms);
+        assert!(lock.is_open());
+        keys(&mut lock, b"D", now_ms);
+        assert!(!lock.is_open());
+
+        // PIN survives the restart
+        let mut lock = DoorLock::default();
+        lock.set_pin_hash(pin_hash);
+        keys(&mut lock, b"1234#", now_ms);
+        assert!(!lock.is_open());
+        keys(&mut lock, b"2580#", now_ms);
+        assert!(lock.is_open());
+    }
+
+    #[test]
+    #[cfg(feature = "access-log")]
+    fn door_lock_access() {
+        use crate::lock::{Access, DoorLock};
+        use crate::ui::Event;
+
+        let mut lock = DoorLock::default();
+        let mut keys = |keys: &[u8]| {
+            keys.iter().for_each(|&key| assert!(lock.handle(Event::Serial(key))));
+            lock.take_access()
+        };
+        assert_eq!(keys(b"12"), None);
+        assert_eq!(keys(b"34#"), Some(Access::Opened));
+        assert_eq!(keys(b"A2580#"), None);
+        assert_eq!(keys(b"2580#"), Some(Access::PinChanged));
+        assert_eq!(keys(b"1234#"), Some(Access::Denied(2)));
+        assert_eq!(keys(b"12#"), None);
+        assert_eq!(keys(b"1111#"), Some(Access::Denied(1)));
+        assert_eq!(keys(b"1111#"), Some(Access::LockedOut(30)));
+        assert_eq!(keys(b"2580#"), None);
+    }
+}
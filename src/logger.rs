@@ -0,0 +1,598 @@
+//! Temperature log: a record every `INTERVAL_MS`, kept in a circular area of `PAGES` flash pages
+//! (see `storage` for the one on the board), browsed on the log screen and dumped as CSV over
+//! serial.
+//!
+//! Every page starts with a header, the magic value and the page sequence number, followed by the
+//! records (uptime in minutes and temperature, one half-word each). Pages are filled in order;
+//! when the current one is full, the next one is erased (dropping the oldest records) and started
+//! with the next sequence number. On boot, the newest page is the one not followed by its
+//! successor, and the records go on after the last programmed one. Pages without the header
+//! (never used, or garbage) are not part of the log.
+//!
+//! Serial interface (see `Export`) takes commands, lines of text: `dump` sends the CSV, `stop`
+//! cancels it, `help` lists the commands. Output is queued for the USART interrupt, which pauses
+//! the transmission on XOFF and resumes it on XON (software flow control); the dump waits while the
+//! queue is full, so nothing is lost however long the receiver (or the display refresh, which
+//! delays the main loop) holds it up.
+
+use core::fmt::{self, Write};
+use heapless::{Deque, String};
+use crate::fmt::{write_fixed, write_uint};
+use crate::statistics::Accumulator;
+use crate::text;
+use crate::ui::Event;
+use crate::units::TempUnit;
+
+/// Pages in the area, half-words in each of them (1K pages).
+pub const PAGES: usize = 4;
+pub const PAGE_WORDS: usize = 512;
+/// Records in a page (header takes the first two half-words).
+pub const PAGE_RECORDS: usize = (PAGE_WORDS - HEADER_WORDS) / 2;
+const HEADER_WORDS: usize = 2;
+/// Record is taken every minute, so the log covers 12.75 to 17 hours.
+pub const INTERVAL_MS: u32 = 60_000;
+
+const MAGIC: u16 = 0x106e;
+/// Erased flash.
+const ERASED: u16 = 0xffff;
+
+/// Header of the CSV dump.
+pub const CSV_HEADER: &str = "record,uptime_min,temp_c\r\n";
+/// Flow control bytes: XOFF pauses the transmission, XON resumes it.
+pub const XON: u8 = 0x11;
+pub const XOFF: u8 = 0x13;
+/// Longest command, in characters (longer lines are unknown commands).
+const COMMAND_LEN: usize = 8;
+/// Longest line of the output, in bytes.
+const LINE_LEN: usize = 32;
+
+/// Flash area the records are kept in: `PAGES` pages of `PAGE_WORDS` half-words. Erased flash
+/// reads as all ones, and only erased half-words can be programmed.
+pub trait RecordArea {
+    fn read(&self, page: usize, index: usize) -> u16;
+    fn program(&mut self, page: usize, index: usize, value: u16);
+    fn erase(&mut self, page: usize);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// Uptime when the record was taken, in minutes (saturates, as all ones is an erased record).
+    pub uptime_min: u16,
+    /// Temperature, in tenths of °C.
+    pub temp: i16,
+}
+
+impl Record {
+    pub fn new(uptime_s: u32, temp: i16) -> Record {
+        Record {
+            uptime_min: (uptime_s / 60).min(u32::from(ERASED - 1)) as u16,
+            temp,
+        }
+    }
+}
+
+/// Lowest, highest and average temperature over all records, in tenths of °C.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Summary {
+    pub count: usize,
+    pub min: i16,
+    pub max: i16,
+    pub avg: i16,
+}
+
+/// Position of the newest record in the area.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Log {
+    /// Page the records go to, and its sequence number.
+    page: usize,
+    seq: u16,
+    /// Records in the page.
+    used: usize,
+    /// Records in the log.
+    count: usize,
+}
+
+impl Default for Log {
+    /// Empty log, the first record starts the first page.
+    fn default() -> Log {
+        Log {
+            page: PAGES - 1,
+            seq: ERASED,
+            used: PAGE_RECORDS,
+            count: 0,
+        }
+    }
+}
+
+impl Log {
+    /// Find the records kept in the area.
+    pub fn mount<A: RecordArea>(area: &A) -> Log {
+        let valid = |page: usize| area.read(page, 0) == MAGIC;
+        let seq = |page: usize| area.read(page, 1);
+        // Both pages are in the log, one right after the other
+        let follows = |page: usize, next: usize| valid(page) && valid(next) && seq(next) == seq(page).wrapping_add(1);
+        let Some(page) = (0..PAGES).find(|&page| valid(page) && !follows(page, (page + 1) % PAGES)) else {
+            return Log::default();
+        };
+        let used = (0..PAGE_RECORDS).take_while(|&i| area.read(page, HEADER_WORDS + 2 * i) != ERASED).count();
+        let mut count = used;
+        let mut newer = page;
+        for _ in 1..PAGES {
+            let older = (newer + PAGES - 1) % PAGES;
+            if !follows(older, newer) {
+                break;
+            }
+            count += PAGE_RECORDS;
+            newer = older;
+        }
+        Log { page, seq: seq(page), used, count }
+    }
+
+    /// Records in the log.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Add the record, erasing the next page when the current one is full. Page erase stalls the
+    /// CPU for 20-40ms.
+    pub fn append<A: RecordArea>(&mut self, area: &mut A, record: Record) {
+        if self.used == PAGE_RECORDS {
+            // Oldest page is dropped once all of them are used (older pages are always full)
+            self.count = self.count.min((PAGES - 1) * PAGE_RECORDS);
+            self.page = (self.page + 1) % PAGES;
+            self.seq = self.seq.wrapping_add(1);
+            self.used = 0;
+            area.erase(self.page);
+            area.program(self.page, 0, MAGIC);
+            area.program(self.page, 1, self.seq);
+        }
+        let index = HEADER_WORDS + 2 * self.used;
+        area.program(self.page, index, record.uptime_min);
+        area.program(self.page, index + 1, record.temp as u16);
+        self.used += 1;
+        self.count += 1;
+    }
+
+    /// Record `back` records before the newest one (zero is the newest).
+    pub fn get<A: RecordArea>(&self, area: &A, back: usize) -> Option<Record> {
+        if back >= self.count {
+            return None;
+        }
+        let (mut page, mut used, mut back) = (self.page, self.used, back);
+        while back >= used {
+            back -= used;
+            page = (page + PAGES - 1) % PAGES;
+            used = PAGE_RECORDS;
+        }
+        let index = HEADER_WORDS + 2 * (used - 1 - back);
+        Some(Record {
+            uptime_min: area.read(page, index),
+            temp: area.read(page, index + 1) as i16,
+        })
+    }
+
+    /// Summary of all the records, `None` if there are none.
+    pub fn summary<A: RecordArea>(&self, area: &A) -> Option<Summary> {
+        let mut stats = Accumulator::default();
+        for back in 0..self.count {
+            stats.push(self.get(area, back)?.temp);
+        }
+        let (min, max) = stats.range()?;
+        Some(Summary { count: self.count, min, max, avg: stats.mean()? })
+    }
+
+    /// Write the CSV line of the record `index` (zero is the oldest one): `12,345,23.4`.
+    pub fn write_csv<A: RecordArea, W: Write>(&self, area: &A, w: &mut W, index: usize) -> fmt::Result {
+        let record = self.get(area, self.count.wrapping_sub(index + 1)).ok_or(fmt::Error)?;
+        write_uint(w, index as u32)?;
+        w.write_char(',')?;
+        write_uint(w, u32::from(record.uptime_min))?;
+        w.write_char(',')?;
+        write_fixed(w, i32::from(record.temp) * 100, 1)?;
+        w.write_str("\r\n")
+    }
+}
+
+/// What the log screen shows: the summary or one of the records, scrolled through by the encoder
+/// (up goes back in time). Updated by the main loop from the `Log` (see `update`).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LogView {
+    /// Zero is the summary, one is the newest record.
+    position: usize,
+    summary: Option<Summary>,
+    record: Option<Record>,
+}
+
+impl LogView {
+    /// Record shown (counted back from the newest one), `None` for the summary.
+    pub fn shown(&self) -> Option<usize> {
+        self.position.checked_sub(1)
+    }
+
+    /// Read the summary and the record shown.
+    pub fn update<A: RecordArea>(&mut self, log: &Log, area: &A) {
+        self.summary = log.summary(area);
+        self.position = self.position.min(log.count());
+        self.record = self.shown().and_then(|back| log.get(area, back));
+    }
+
+    /// Returns `true` if the event was used (the view must be updated then).
+    pub fn handle(&mut self, event: Event) -> bool {
+        let count = self.summary.map_or(0, |summary| summary.count);
+        match event {
+            Event::EncoderUp => self.position = (self.position + 1).min(count),
+            Event::EncoderDown => self.position = self.position.saturating_sub(1),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Write the average and the number of records (`Avg 22.3C #1024`), or the record shown and
+    /// its number (`#  12   23.4C`).
+    pub fn write_status<W: Write>(&self, w: &mut W, unit: TempUnit) -> fmt::Result {
+        let Some(summary) = self.summary else {
+            return w.write_str("Log empty");
+        };
+        if let (Some(back), Some(record)) = (self.shown(), self.record) {
+            w.write_char('#')?;
+            text::uint(w, back as u32 + 1, 4)?;
+            text::fixed(w, unit.from_tenths_c(record.temp), 1, 7)?;
+            return w.write_str(unit.symbol());
+        }
+        w.write_str("Avg")?;
+        text::fixed(w, unit.from_tenths_c(summary.avg), 1, 5)?;
+        w.write_str(unit.symbol())?;
+        w.write_str(" #")?;
+        text::uint(w, summary.count as u32, 4)
+    }
+
+    /// Write the lowest and highest temperature (`Lo 21.2 Hi 25.0C`), or the uptime of the record
+    /// shown (`up     12:34:00`).
+    pub fn write_page<W: Write>(&self, w: &mut W, unit: TempUnit) -> fmt::Result {
+        let Some(summary) = self.summary else {
+            return Ok(());
+        };
+        if let Some(record) = self.record {
+            w.write_str("up")?;
+            return text::duration(w, u32::from(record.uptime_min) * 60, 14);
+        }
+        w.write_str("Lo")?;
+        text::fixed(w, unit.from_tenths_c(summary.min), 1, 5)?;
+        w.write_str(" Hi")?;
+        text::fixed(w, unit.from_tenths_c(summary.max), 1, 5)?;
+        w.write_str(unit.symbol())
+    }
+}
+
+/// Serial commands of the log. Received bytes are passed to `receive`, the output goes to the
+/// transmit queue in `poll` (flow control bytes are handled by the interrupt and never get here).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Export {
+    command: [u8; COMMAND_LEN],
+    /// Characters typed, more than `COMMAND_LEN` once the line is too long.
+    len: usize,
+    /// Reply to the last command, waiting for room in the queue.
+    reply: Option<&'static str>,
+    /// Next record of the dump and the end of it (records added during the dump are left out).
+    dump: Option<(usize, usize)>,
+}
+
+impl Export {
+    /// Is the dump in progress?
+    pub fn dumping(&self) -> bool {
+        self.dump.is_some()
+    }
+
+    /// Take the received byte, run the command once the line is complete. Empty lines are
+    /// ignored (so either CR, LF or both end the line), backspace deletes.
+    pub fn receive(&mut self, log: &Log, byte: u8) {
+        match byte {
+            b'\r' | b'\n' if self.len > 0 => {
+                let (command, len) = (self.command, core::mem::take(&mut self.len));
+                // Line too long is not a command
+                self.run(log, command.get(..len).unwrap_or_default());
+            }
+            b'\r' | b'\n' => {}
+            0x08 | 0x7f => self.len = self.len.saturating_sub(1),
+            _ => {
+                if let Some(c) = self.command.get_mut(self.len) {
+                    *c = byte.to_ascii_lowercase();
+                }
+                self.len += 1;
+            }
+        }
+    }
+
+    fn run(&mut self, log: &Log, command: &[u8]) {
+        self.reply = Some(match command {
+            b"dump" => {
+                self.dump = Some((0, log.count()));
+                CSV_HEADER
+            }
+            b"stop" => {
+                self.dump = None;
+                "stopped\r\n"
+            }
+            b"help" => "commands: dump, stop, help\r\n",
+            _ => "unknown command, try help\r\n",
+        });
+    }
+
+    /// Queue the reply and the next lines of the dump, as many as fit into `out` (whole lines
+    /// only, the rest waits for the next call).
+    pub fn poll<A: RecordArea, const N: usize>(&mut self, log: &Log, area: &A, out: &mut Deque<u8, N>) {
+        loop {
+            let mut line: String<LINE_LEN> = String::new();
+            let written = match (self.reply, self.dump) {
+                (Some(reply), _) => line.write_str(reply),
+                (None, Some((next, end))) if next < end.min(log.count()) => log.write_csv(area, &mut line, next),
+                _ => {
+                    self.dump = None;
+                    return;
+                }
+            };
+            if written.is_ok() && out.capacity() - out.len() < line.len() {
+                return;
+            }
+            if written.is_ok() {
+                for &byte in line.as_bytes() {
+                    // Cannot fail, there is room for the line
+                    let _ = out.push_back(byte);
+                }
+            }
+            if self.reply.take().is_none() {
+                self.dump = self.dump.map(|(next, end)| (next + 1, end));
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn temperature_log() {
+        use crate::logger::{Log, Record, Summary, CSV_HEADER, PAGES, PAGE_RECORDS, PAGE_WORDS};
+        use crate::mock::{self, MockFlash};
+        use crate::screens::{self, Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        // Never used area is empty, garbage is not taken for the records
+        let mut area = MockFlash::<PAGES, PAGE_WORDS>::new();
+        area.pages[2] = [0x1234; PAGE_WORDS];
+        let mut log = Log::mount(&area);
+        assert_eq!(log.count(), 0);
+        assert_eq!(log.summary(&area), None);
+
+        for minute in 0..10u32 {
+            log.append(&mut area, Record::new(minute * 60, 200 + minute as i16));
+        }
+        assert_eq!(area.erases.iter().sum::<usize>(), 1);
+        assert_eq!(log.get(&area, 0), Some(Record { uptime_min: 9, temp: 209 }));
+        assert_eq!(log.get(&area, 9), Some(Record { uptime_min: 0, temp: 200 }));
+        assert_eq!(log.get(&area, 10), None);
+        assert_eq!(log.summary(&area), Some(Summary { count: 10, min: 200, max: 209, avg: 205 }));
+        // Records are found again after a reset
+        assert_eq!(Log::mount(&area), log);
+
+        // Once all pages are used, the oldest one is erased
+        for minute in 10..(PAGES * PAGE_RECORDS + 5) as u32 {
+            log.append(&mut area, Record::new(minute * 60, -(minute as i16 % 100)));
+        }
+        assert_eq!(area.erases.iter().sum::<usize>(), PAGES + 1);
+        assert_eq!(log.count(), (PAGES - 1) * PAGE_RECORDS + 5);
+        let newest = (PAGES * PAGE_RECORDS + 4) as u16;
+        assert_eq!(log.get(&area, 0).map(|record| record.uptime_min), Some(newest));
+        assert_eq!(log.get(&area, log.count() - 1).map(|record| record.uptime_min), Some(newest + 1 - log.count() as u16));
+        assert_eq!(Log::mount(&area), log);
+        assert_eq!(log.summary(&area).map(|summary| (summary.min, summary.max)), Some((-99, 0)));
+
+        // CSV lines go from the oldest record
+        let mut log = Log::default();
+        let mut area = MockFlash::<PAGES, PAGE_WORDS>::new();
+        log.append(&mut area, Record::new(59, -15));
+        log.append(&mut area, Record::new(3600, 231));
+        let mut csv = std::string::String::from(CSV_HEADER);
+        for index in 0..log.count() {
+            log.write_csv(&area, &mut csv, index).unwrap();
+        }
+        assert_eq!(csv, "record,uptime_min,temp_c\r\n0,0,-1.5\r\n1,60,23.1\r\n");
+
+        // Screen shows the summary, encoder scrolls back through the records
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::TempLog, &Stats::default(), settings);
+        assert_eq!(render(&settings), ("Log empty       ".into(), "                ".into()));
+        settings.temp_log.update(&log, &area);
+        assert_eq!(render(&settings), ("Avg 10.8C #   2 ".into(), "Lo -1.5 Hi 23.1C".into()));
+        assert!(settings.handle(Screen::TempLog, Event::EncoderUp));
+        settings.temp_log.update(&log, &area);
+        assert_eq!(render(&settings), ("#   1   23.1C   ".into(), "up      01:00:00".into()));
+        for _ in 0..3 {
+            assert!(settings.handle(Screen::TempLog, Event::EncoderUp));
+        }
+        settings.temp_log.update(&log, &area);
+        assert_eq!(settings.temp_log.shown(), Some(1));
+        assert_eq!(render(&settings).0, "#   2   -1.5C   ");
+        assert!(settings.handle(Screen::TempLog, Event::EncoderDown));
+        assert!(settings.handle(Screen::TempLog, Event::EncoderDown));
+        settings.temp_log.update(&log, &area);
+        assert_eq!(settings.temp_log.shown(), None);
+        assert!(!settings.handle(Screen::TempLog, Event::Button));
+
+        // Log screen is the last of the optional ones, but the tachometer, the totalizer, the
+        // speedometer, the hour meter and the weather dashboard
+        let mut ui = screens::navigation();
+        ui.handle(Event::Serial(b'z'), &settings);
+        assert_eq!(ui.state(), Screen::TempLog);
+        ui.handle(Event::Button, &settings);
+        #[cfg(feature = "tachometer")]
+        {
+            assert_eq!(ui.state(), Screen::Tachometer);
+            ui.handle(Event::Button, &settings);
+        }
+        #[cfg(feature = "totalizer")]
+        {
+            assert_eq!(ui.state(), Screen::Totalizer);
+            ui.handle(Event::Button, &settings);
+        }
+        #[cfg(feature = "speedometer")]
+        {
+            assert_eq!(ui.state(), Screen::Speedometer);
+            ui.handle(Event::Button, &settings);
+        }
+        #[cfg(feature = "hour-meter")]
+        {
+            assert_eq!(ui.state(), Screen::HourMeter);
+            ui.handle(Event::Button, &settings);
+        }
+        #[cfg(feature = "weather")]
+        {
+            assert_eq!(ui.state(), Screen::Weather);
+            ui.handle(Event::Button, &settings);
+        }
+        #[cfg(feature = "pir")]
+        {
+            assert_eq!(ui.state(), Screen::Motion);
+            ui.handle(Event::Button, &settings);
+        }
+        #[cfg(feature = "dmx")]
+        {
+            assert_eq!(ui.state(), Screen::Dmx);
+            ui.handle(Event::Button, &settings);
+        }
+        #[cfg(feature = "midi")]
+        {
+            assert_eq!(ui.state(), Screen::Midi);
+            ui.handle(Event::Button, &settings);
+        }
+        #[cfg(feature = "door-lock")]
+        {
+            assert_eq!(ui.state(), Screen::Lock);
+            ui.handle(Event::Button, &settings);
+        }
+        #[cfg(feature = "access-log")]
+        {
+            assert_eq!(ui.state(), Screen::Events);
+            ui.handle(Event::Button, &settings);
+        }
+        #[cfg(feature = "calibration")]
+        {
+            assert_eq!(ui.state(), Screen::Calibration);
+            ui.handle(Event::Button, &settings);
+        }
+        assert_eq!(ui.state(), Screen::Uptime);
+        ui.handle(Event::EncoderDown, &settings);
+        #[cfg(feature = "calibration")]
+        {
+            assert_eq!(ui.state(), Screen::Calibration);
+            ui.handle(Event::EncoderDown, &settings);
+        }
+        #[cfg(feature = "access-log")]
+        {
+            assert_eq!(ui.state(), Screen::Events);
+            ui.handle(Event::EncoderDown, &settings);
+        }
+        #[cfg(feature = "door-lock")]
+        {
+            assert_eq!(ui.state(), Screen::Lock);
+            ui.handle(Event::EncoderDown, &settings);
+        }
+        #[cfg(feature = "midi")]
+        {
+            assert_eq!(ui.state(), Screen::Midi);
+            ui.handle(Event::EncoderDown, &settings);
+        }
+        #[cfg(feature = "dmx")]
+        {
+            assert_eq!(ui.state(), Screen::Dmx);
+            ui.handle(Event::EncoderDown, &settings);
+        }
+        #[cfg(feature = "pir")]
+        {
+            assert_eq!(ui.state(), Screen::Motion);
+            ui.handle(Event::EncoderDown, &settings);
+        }
+        #[cfg(feature = "weather")]
+        {
+            assert_eq!(ui.state(), Screen::Weather);
+            ui.handle(Event::EncoderDown, &settings);
+        }
+        #[cfg(feature = "hour-meter")]
+        {
+            assert_eq!(ui.state(), Screen::HourMeter);
+            ui.handle(Event::EncoderDown, &settings);
+        }
+        #[cfg(feature = "speedometer")]
+        {
+            assert_eq!(ui.state(), Screen::Speedometer);
+            ui.handle(Event::EncoderDown, &settings);
+        }
+        #[cfg(feature = "totalizer")]
+        {
+            assert_eq!(ui.state(), Screen::Totalizer);
+            ui.handle(Event::EncoderDown, &settings);
+        }
+        #[cfg(feature = "tachometer")]
+        {
+            assert_eq!(ui.state(), Screen::Tachometer);
+            ui.handle(Event::EncoderDown, &settings);
+        }
+        assert_eq!(ui.state(), Screen::TempLog);
+    }
+
+    #[test]
+    fn temperature_log_export() {
+        use heapless::Deque;
+        use crate::logger::{Export, Log, Record, PAGES, PAGE_WORDS};
+        use crate::mock::MockFlash;
+
+        let mut area = MockFlash::<PAGES, PAGE_WORDS>::new();
+        let mut log = Log::default();
+        for minute in 0..3u32 {
+            log.append(&mut area, Record::new(minute * 60, 215 + minute as i16));
+        }
+        fn send(export: &mut Export, log: &Log, line: &str) {
+            line.bytes().for_each(|byte| export.receive(log, byte));
+        }
+        // Receiver takes a few bytes at a time
+        fn drain(export: &mut Export, log: &Log, area: &MockFlash<PAGES, PAGE_WORDS>, out: &mut Deque<u8, 32>) -> std::string::String {
+            let mut received = std::string::String::new();
+            for _ in 0..100 {
+                export.poll(log, area, out);
+                for _ in 0..5 {
+                    received.extend(out.pop_front().map(char::from));
+                }
+            }
+            received
+        }
+        let mut export = Export::default();
+        // Transmit queue has room for a couple of lines
+        let mut out: Deque<u8, 32> = Deque::new();
+
+        send(&mut export, &log, "help\r\n");
+        assert_eq!(drain(&mut export, &log, &area, &mut out), "commands: dump, stop, help\r\n");
+        send(&mut export, &log, "dumb\x08p\r\n\r\n");
+        assert!(export.dumping());
+        // Records added during the dump are left out
+        export.poll(&log, &area, &mut out);
+        log.append(&mut area, Record::new(600, 300));
+        assert_eq!(drain(&mut export, &log, &area, &mut out), "record,uptime_min,temp_c\r\n0,0,21.5\r\n1,1,21.6\r\n2,2,21.7\r\n");
+        assert!(!export.dumping());
+
+        // Dump waits while the queue is full (transmission paused by XOFF)
+        send(&mut export, &log, "DUMP\n");
+        for _ in 0..10 {
+            export.poll(&log, &area, &mut out);
+        }
+        assert!(export.dumping());
+        assert!(out.len() > 20);
+        send(&mut export, &log, "stop\n");
+        let received = drain(&mut export, &log, &area, &mut out);
+        assert!(received.starts_with("record,"), "{}", received);
+        assert!(received.ends_with("stopped\r\n"), "{}", received);
+        assert!(!export.dumping());
+
+        send(&mut export, &log, "dump please\r");
+        assert_eq!(drain(&mut export, &log, &area, &mut out), "unknown command, try help\r\n");
+        assert!(!export.dumping());
+    }
+}
@@ -0,0 +1,58 @@
+//! Debug logging over RTT (via `defmt`), enabled by `debug-log` feature.
+//!
+//! The LCD is too small (and too slow) to be used as a debug console, so debug messages go to the
+//! debugger instead. Only short messages for the user could be shown on the display (`notify!`,
+//! with `lcd-log` feature). If `debug-log` feature is disabled, logging statements compile to
+//! nothing (arguments are still type-checked, but never evaluated).
+
+#[cfg(feature = "debug-log")]
+macro_rules! log {
+    ($level:ident, $($arg:tt)*) => {
+        ::defmt::$level!($($arg)*)
+    };
+}
+
+#[cfg(not(feature = "debug-log"))]
+macro_rules! log {
+    ($level:ident, $fmt:literal $(, $arg:expr)* $(,)?) => {{
+        let _ = || {
+            $(let _ = &$arg;)*
+        };
+    }};
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        log!(debug, $($arg)*)
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        log!(info, $($arg)*)
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        log!(error, $($arg)*)
+    };
+}
+
+/// Short message for the user, shown on the display with `lcd-log` feature (see `lcd_log`). Uses
+/// `core::fmt` syntax, unlike the debug log.
+#[cfg(feature = "lcd-log")]
+macro_rules! notify {
+    ($level:ident, $($arg:tt)*) => {
+        ::log::$level!($($arg)*)
+    };
+}
+
+#[cfg(not(feature = "lcd-log"))]
+macro_rules! notify {
+    ($level:ident, $($arg:tt)*) => {{
+        let _ = || {
+            let _ = format_args!($($arg)*);
+        };
+    }};
+}
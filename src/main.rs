@@ -1,141 +1,1879 @@
-#![feature(const_fn)]
-#![feature(used)]
-#![feature(proc_macro)]
 #![no_std]
+#![no_main]
 
-extern crate stm32f103xx;
-extern crate lcd;
-extern crate cortex_m;
-extern crate stm32_extras;
+use core::cell::RefCell;
+#[cfg(any(feature = "modbus", feature = "lcdproc", feature = "temp-log", feature = "midi", feature = "terminal", feature = "shell", feature = "link"))]
+use core::sync::atomic::{AtomicU32, Ordering};
+#[cfg(feature = "temp-log")]
+use core::sync::atomic::AtomicBool;
+use cortex_m::interrupt::Mutex;
+use cortex_m::peripheral::{DWT, NVIC, SCB};
+use cortex_m::peripheral::scb::VectActive;
+use cortex_m::register::primask;
+use cortex_m_rt::{entry, exception};
+#[cfg(any(feature = "modbus", feature = "lcdproc", feature = "temp-log", feature = "midi", feature = "terminal", feature = "shell", feature = "link"))]
+use heapless::Deque;
+use stm32f1::stm32f103::{gpioa, interrupt, Interrupt, Peripherals, GPIOB, RCC};
+#[cfg(feature = "io-strobe")]
+use stm32f1::stm32f103::GPIOA;
+use lcd::Display;
+use lcd_example_bluepill::{board, clock, delay, gpio, inspect, random, stack, time};
+use lcd_example_bluepill::backup::BootStats;
+use lcd_example_bluepill::scratchpad::{ALARM, ALERT};
+#[cfg(feature = "speedometer")]
+use lcd_example_bluepill::scratchpad::TRIP;
+use lcd_example_bluepill::board::PortName;
+#[cfg(feature = "bench")]
+use lcd_example_bluepill::bench;
+use lcd_example_bluepill::button::{Button, Press};
+use lcd_example_bluepill::buzzer::Buzzer;
+use lcd_example_bluepill::clock::ClockError;
+use lcd_example_bluepill::delay::CycleDelay;
+use lcd_example_bluepill::display::NoDisplay;
+#[cfg(feature = "read-back")]
+use lcd_example_bluepill::display::{COLUMNS, ROWS};
+#[cfg(feature = "input")]
+use lcd_example_bluepill::hardware::BUSY_TIMEOUT_US;
+use lcd_example_bluepill::hardware::LcdHardware;
+#[cfg(feature = "i2c-scan")]
+use lcd_example_bluepill::bus::Scanner;
+#[cfg(any(feature = "i2c-scan", feature = "gesture", feature = "door-lock"))]
+use lcd_example_bluepill::i2c_master::I2cMaster;
+#[cfg(feature = "gesture")]
+use lcd_example_bluepill::apds9960::{Apds9960, Gesture};
+#[cfg(feature = "door-lock")]
+use lcd_example_bluepill::keypad::{self, Keypad};
+#[cfg(all(feature = "door-lock", feature = "access-log"))]
+use lcd_example_bluepill::lock::Access;
+#[cfg(feature = "access-log")]
+use lcd_example_bluepill::access_log::{Entry, EventLog, Kind};
+#[cfg(feature = "calibration")]
+use lcd_example_bluepill::calibration::{Channel, Constants};
+#[cfg(feature = "i2c-slave")]
+use lcd_example_bluepill::i2c_slave::{I2cSlave, SlaveEvent};
+#[cfg(feature = "spi-flash")]
+use lcd_example_bluepill::flash;
+#[cfg(feature = "spi-flash")]
+use lcd_example_bluepill::spi_master::SpiMaster;
+#[cfg(feature = "fan")]
+use lcd_example_bluepill::fan_timer::{self, FanTimer};
+#[cfg(any(feature = "tachometer", feature = "totalizer"))]
+use lcd_example_bluepill::pulse_input;
+#[cfg(feature = "pin-monitor")]
+use lcd_example_bluepill::edges;
+#[cfg(any(feature = "totalizer", feature = "speedometer"))]
+use lcd_example_bluepill::kv;
+#[cfg(feature = "hour-meter")]
+use lcd_example_bluepill::sense::SenseInput;
+#[cfg(feature = "weather")]
+use lcd_example_bluepill::weather::Quantity;
+#[cfg(feature = "pir")]
+use lcd_example_bluepill::pir::PirInput;
+#[cfg(feature = "backlight")]
+use lcd_example_bluepill::backlight_pwm::BacklightPwm;
+#[cfg(feature = "onewire")]
+use lcd_example_bluepill::onewire::{self, Devices, PinBus, DS18B20};
+#[cfg(feature = "temp-log")]
+use lcd_example_bluepill::logger::{self, Export, Log, Record};
+use lcd_example_bluepill::remote::RemoteText;
+use lcd_example_bluepill::threshold::Source;
+#[cfg(feature = "spi-slave")]
+use lcd_example_bluepill::remote::SpiFrames;
+#[cfg(feature = "modbus")]
+use lcd_example_bluepill::modbus::{Frame, ModbusSlave};
+#[cfg(feature = "lcdproc")]
+use lcd_example_bluepill::remote::Hd44780Serial;
+#[cfg(feature = "terminal")]
+use lcd_example_bluepill::terminal::Terminal;
+#[cfg(feature = "link")]
+use lcd_example_bluepill::link::Link;
+#[cfg(any(feature = "shell", feature = "rtt-console"))]
+use lcd_example_bluepill::shell::Shell;
+#[cfg(feature = "rtt-console")]
+use lcd_example_bluepill::rtt::Rtt;
+#[cfg(any(feature = "modbus", feature = "lcdproc", feature = "temp-log", feature = "dmx", feature = "midi", feature = "terminal", feature = "shell", feature = "link"))]
+use lcd_example_bluepill::serial::{Parity, Serial};
+#[cfg(feature = "dmx")]
+use lcd_example_bluepill::dmx::Receiver;
+#[cfg(feature = "midi")]
+use lcd_example_bluepill::midi::{self, Message};
+#[cfg(feature = "spi-slave")]
+use lcd_example_bluepill::spi_slave::{SpiSlave, BUFFER_SIZE};
+#[cfg(not(feature = "stop-mode"))]
+use lcd_example_bluepill::power::Wfi;
+#[cfg(feature = "stop-mode")]
+use lcd_example_bluepill::power::Stop;
+use lcd_example_bluepill::power::{Idle, Power};
+#[cfg(feature = "battery")]
+use lcd_example_bluepill::{battery, power};
+use lcd_example_bluepill::relay::Relay;
+use lcd_example_bluepill::sched::{LoopStats, Scheduler, Task};
+use lcd_example_bluepill::screens::{self, Screen, Settings, Stats, SCREEN_TIME_MS, SPLASH_TIME_US};
+use lcd_example_bluepill::framebuffer::{Framebuffer, Owner};
+use lcd_example_bluepill::queue::{Engine, Queue, Writer};
+use lcd_example_bluepill::sensor::TempSensor;
+use lcd_example_bluepill::kv::Store;
+use lcd_example_bluepill::storage::StorePages;
+#[cfg(feature = "temp-log")]
+use lcd_example_bluepill::storage::LogArea;
+#[cfg(feature = "access-log")]
+use lcd_example_bluepill::storage::EventPages;
+use lcd_example_bluepill::shared::SharedDisplay;
+use lcd_example_bluepill::supply::SupplyMonitor;
+use lcd_example_bluepill::timer::OneShot;
+#[cfg(feature = "io-strobe")]
+use lcd_example_bluepill::strobe::StrobedPort;
+#[cfg(feature = "itm-trace")]
+use lcd_example_bluepill::trace::{ItmSink, TracedPort};
+use lcd_example_bluepill::ui::{Event, StateMachine};
+use lcd_example_bluepill::watchdog::{ResetCause, Watchdog};
+
+#[macro_use]
+mod logging;
+mod fault;
+
+/// Port LCD is connected to (pins are defined in `board`). Any of the GPIOA, GPIOB or GPIOC could
+/// be used, clock setup in `main` and the fault handlers need to take the same port.
+type LcdPort = GPIOB;
+#[cfg(not(feature = "io-strobe"))]
+type StrobePort = LcdPort;
+/// Strobe is inside of the trace, so time spent writing ITM packets is not counted as display I/O.
+#[cfg(feature = "io-strobe")]
+type StrobePort = StrobedPort<LcdPort, GPIOA>;
+#[cfg(not(feature = "itm-trace"))]
+type LcdHw = LcdHardware<StrobePort, CycleDelay>;
+#[cfg(feature = "itm-trace")]
+type LcdHw = LcdHardware<TracedPort<StrobePort, ItmSink>, CycleDelay>;
+type LcdDisplay = Display<LcdHw>;
+
+/// Display is shared with interrupt handlers, which can update it, too. Screens are rendered into
+/// the framebuffer, changes are queued and sent to the display from the TIM2 interrupt.
+static DISPLAY: SharedDisplay<Framebuffer<Writer<'static>>> = SharedDisplay::new();
+
+type LcdEngine = (Engine<'static, LcdHw>, OneShot);
+
+/// Sends queued commands to the display, driven by the one-shot timer.
+static ENGINE: Mutex<RefCell<Option<LcdEngine>>> = Mutex::new(RefCell::new(None));
+
+/// Text pushed by the I2C or SPI master, replaces the screens while it is active.
+static REMOTE: Mutex<RefCell<RemoteText>> = Mutex::new(RefCell::new(RemoteText::new()));
+
+#[cfg(feature = "i2c-slave")]
+static I2C_SLAVE: Mutex<RefCell<Option<I2cSlave>>> = Mutex::new(RefCell::new(None));
+
+#[cfg(all(feature = "i2c-scan", feature = "i2c-slave"))]
+compile_error!("`i2c-scan` and `i2c-slave` features both use I2C2");
+#[cfg(all(feature = "gesture", feature = "i2c-slave"))]
+compile_error!("`gesture` and `i2c-slave` features both use I2C2");
+#[cfg(all(feature = "door-lock", feature = "i2c-slave"))]
+compile_error!("`door-lock` and `i2c-slave` features both use I2C2");
+
+/// Address of the board on I2C bus (as an I2C display module).
+#[cfg(feature = "i2c-slave")]
+const I2C_ADDRESS: u8 = 0x28;
+
+#[cfg(all(feature = "modbus", feature = "lcdproc"))]
+compile_error!("`modbus` and `lcdproc` features both use USART1");
+#[cfg(all(feature = "temp-log", any(feature = "modbus", feature = "lcdproc")))]
+compile_error!("`temp-log` feature uses USART1, so does `modbus` and `lcdproc`");
+#[cfg(all(feature = "dmx", any(feature = "modbus", feature = "lcdproc", feature = "temp-log")))]
+compile_error!("`dmx` feature uses USART1, so does `modbus`, `lcdproc` and `temp-log`");
+#[cfg(all(feature = "midi", any(feature = "modbus", feature = "lcdproc", feature = "temp-log", feature = "dmx")))]
+compile_error!("`midi` feature uses USART1, so does `modbus`, `lcdproc`, `temp-log` and `dmx`");
+#[cfg(all(feature = "terminal", any(feature = "modbus", feature = "lcdproc", feature = "temp-log", feature = "dmx", feature = "midi")))]
+compile_error!("`terminal` feature uses USART1, so does `modbus`, `lcdproc`, `temp-log`, `dmx` and `midi`");
+#[cfg(all(feature = "shell", any(feature = "modbus", feature = "lcdproc", feature = "temp-log", feature = "dmx", feature = "midi", feature = "terminal")))]
+compile_error!("`shell` feature uses USART1, so does `modbus`, `lcdproc`, `temp-log`, `dmx`, `midi` and `terminal`");
+#[cfg(all(feature = "link", any(feature = "modbus", feature = "lcdproc", feature = "temp-log", feature = "dmx", feature = "midi", feature = "terminal", feature = "shell")))]
+compile_error!("`link` feature uses USART1, so does `modbus`, `lcdproc`, `temp-log`, `dmx`, `midi`, `terminal` and `shell`");
+
+/// Bytes received over USART1, with the time the last one was received at.
+#[cfg(any(feature = "modbus", feature = "lcdproc", feature = "temp-log", feature = "midi", feature = "terminal", feature = "shell", feature = "link"))]
+static SERIAL_RX: Mutex<RefCell<Deque<u8, SERIAL_RX_SIZE>>> = Mutex::new(RefCell::new(Deque::new()));
+#[cfg(any(feature = "modbus", feature = "lcdproc", feature = "temp-log", feature = "midi", feature = "terminal", feature = "shell", feature = "link"))]
+const SERIAL_RX_SIZE: usize = 256;
+#[cfg(any(feature = "modbus", feature = "lcdproc", feature = "temp-log", feature = "midi", feature = "terminal", feature = "shell", feature = "link"))]
+static SERIAL_RX_MS: AtomicU32 = AtomicU32::new(0);
+/// Bytes sent over USART1 by the interrupt, unless paused by the flow control (of the log).
+#[cfg(any(feature = "temp-log", feature = "shell"))]
+static SERIAL_TX: Mutex<RefCell<Deque<u8, SERIAL_TX_SIZE>>> = Mutex::new(RefCell::new(Deque::new()));
+#[cfg(any(feature = "temp-log", feature = "shell"))]
+const SERIAL_TX_SIZE: usize = 256;
+#[cfg(feature = "temp-log")]
+static SERIAL_TX_PAUSED: AtomicBool = AtomicBool::new(false);
+/// DMX512 frames received over USART1.
+#[cfg(feature = "dmx")]
+static DMX_RX: Mutex<RefCell<Receiver>> = Mutex::new(RefCell::new(Receiver::new()));
+
+/// Modbus slave address, baud rate (with even parity, as required by the standard) and RS-485
+/// driver enable pin (PA8).
+#[cfg(feature = "modbus")]
+const MODBUS_ADDRESS: u8 = 1;
+#[cfg(feature = "modbus")]
+const MODBUS_BAUD: u32 = 19200;
+#[cfg(feature = "modbus")]
+const MODBUS_DE: usize = 8;
+/// Frames are separated by 3.5 characters of silence (1.8ms at 19200 baud), rounded up for the
+/// millisecond timer resolution.
+#[cfg(feature = "modbus")]
+const MODBUS_GAP_MS: u32 = 3;
+
+/// Baud rate of the serial HD44780 protocol.
+#[cfg(feature = "lcdproc")]
+const LCDPROC_BAUD: u32 = 9600;
+
+/// Baud rate of the command shell.
+#[cfg(feature = "shell")]
+const SHELL_BAUD: u32 = 115_200;
+
+/// Baud rate of the display link.
+#[cfg(feature = "link")]
+const LINK_BAUD: u32 = 115_200;
+
+/// Baud rate of the serial terminal.
+#[cfg(feature = "terminal")]
+const TERMINAL_BAUD: u32 = 115_200;
+
+/// Baud rate of DMX512 (2 stop bits are received as 1 stop bit and idle line).
+#[cfg(feature = "dmx")]
+const DMX_BAUD: u32 = 250_000;
 
-use core::fmt::Write;
-use stm32f103xx::{SYST, GPIOB, RCC};
-use lcd::*;
-use stm32_extras::GPIOExtras;
+/// Baud rate of the temperature log commands and dump.
+#[cfg(feature = "temp-log")]
+const LOG_BAUD: u32 = 115_200;
+/// How often the temperature is logged (the task is a no-op without the `temp-log` feature).
+#[cfg(feature = "temp-log")]
+const LOG_INTERVAL_MS: u32 = logger::INTERVAL_MS;
+#[cfg(not(feature = "temp-log"))]
+const LOG_INTERVAL_MS: u32 = 60_000;
+/// How often the keypad is scanned (the task is a no-op without the `door-lock` feature).
+#[cfg(feature = "door-lock")]
+const KEYPAD_SCAN_MS: u32 = keypad::SCAN_MS;
+#[cfg(not(feature = "door-lock"))]
+const KEYPAD_SCAN_MS: u32 = 20;
+/// How often the pulse total and the odometer are checkpointed (the task is a no-op without the
+/// `totalizer` and `speedometer` features).
+#[cfg(any(feature = "totalizer", feature = "speedometer"))]
+const CHECKPOINT_MS: u32 = kv::CHECKPOINT_MS;
+#[cfg(not(any(feature = "totalizer", feature = "speedometer")))]
+const CHECKPOINT_MS: u32 = 60_000;
 
-/// Delay for a given amount of microseconds. Should not be used for precise delays.
-/// Assumes SYST ticks every microsecand and the reload value of 0xffffff (maximum).
-/// `delay` must be less than 0x8000_0000 (SYST is only 24-bit)
-pub fn delay_us(syst: &SYST, delay: u32) {
-    // Essentialy, we do modulo 24-bit arithmetic.
-    let stop_at: u32 = syst.get_current().wrapping_sub(delay - 1);
-    // Run while `stop_at` is less than the counter value ("sign" bit of the difference is zero)
-    // "sign" bit is 24th bit as SYST is 24-bit timer
-    // Run while "(current - (start - delay)) | mod 0x800000 >= 0"
-    while (syst.get_current().wrapping_sub(stop_at) & 0x00800000) == 0 { }
+/// Keys of the values in the flash store (see `kv`). Best reaction times take a key each, from
+/// `KEY_SCORES` on.
+const KEY_SCORES: u16 = 0x0100;
+#[cfg(feature = "hour-meter")]
+const KEY_TRIP_START: u16 = 0x0200;
+#[cfg(feature = "door-lock")]
+const KEY_PIN_HASH: u16 = 0x0201;
+/// Battery cutoff voltage, in millivolts.
+#[cfg(feature = "battery")]
+const KEY_BATTERY_CUTOFF: u16 = 0x0202;
+/// Day and night profiles: their start times, and their brightness and contrast.
+#[cfg(feature = "day-night")]
+const KEY_SCHEDULE_STARTS: u16 = 0x0203;
+#[cfg(feature = "day-night")]
+const KEY_SCHEDULE_PROFILES: u16 = 0x0204;
+/// Counters (checkpoints of the totals).
+#[cfg(feature = "totalizer")]
+const KEY_TOTALIZER: u16 = 0x0300;
+#[cfg(feature = "speedometer")]
+const KEY_ODOMETER: u16 = 0x0301;
+#[cfg(feature = "hour-meter")]
+const KEY_HOUR_METER: u16 = 0x0302;
+/// ADC calibration constants, the offset and the gain of each channel, from `KEY_CALIBRATION` on.
+#[cfg(feature = "calibration")]
+const KEY_CALIBRATION: u16 = 0x0400;
+
+/// Pin of GPIOA which is high during display I/O (PA1).
+#[cfg(feature = "io-strobe")]
+const STROBE_PIN: usize = 1;
+
+/// How long display needs after power-up before it can be initialized, in milliseconds. Datasheet
+/// says 40ms after VCC rises to 2.7V, but some clone modules need much more.
+#[cfg(not(feature = "slow-lcd"))]
+const LCD_POWER_ON_MS: u32 = 50;
+#[cfg(feature = "slow-lcd")]
+const LCD_POWER_ON_MS: u32 = 500;
+/// How many times display initialization is attempted: until it is verified with `input` feature,
+/// or every time without it. Attempts are separated by the power-on wait.
+const LCD_INIT_ATTEMPTS: u32 = 3;
+/// How long each step of the self-test is shown, in microseconds.
+const SELF_TEST_STEP_US: u32 = 500_000;
+/// How long benchmark results are shown, in microseconds.
+#[cfg(feature = "bench")]
+const BENCH_REPORT_US: u32 = 2_000_000;
+/// Period of the defensive full redraw, which recovers the display from noise-corrupted contents.
+const FULL_REDRAW_MS: u32 = 10_000;
+/// How often the display memory is read back, if there is new text to check (`read-back` feature).
+const VERIFY_MS: u32 = 20;
+/// Watchdog timeout, should be well above the time it takes to show one screen.
+const WATCHDOG_TIMEOUT_MS: u32 = 2000;
+/// Show low voltage warning below this supply voltage, in millivolts.
+const LOW_VOLTAGE_MV: u16 = 2900;
+/// How often total runtime is saved into the backup registers (runtime since the last save is
+/// lost on reset).
+const BOOT_STATS_SAVE_MS: u32 = 10_000;
+/// Warn once free stack gets below this, in bytes.
+const STACK_LOW_BYTES: u32 = 1024;
+
+#[entry]
+fn main() -> ! {
+    stack::paint();
+    #[cfg(any(feature = "debug-log", feature = "rtt-console"))]
+    lcd_example_bluepill::rtt::init();
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = Peripherals::take().unwrap();
+
+    info!("board: {=str}", board::NAME);
+    let reset_cause = ResetCause::take(&dp.RCC);
+    info!("reset cause: {=str}", reset_cause.label());
+    // Keep going on HSI if clock setup fails: LCD does not need fast clocks, so we can still
+    // report the error on it.
+    let status = clock::setup(&dp.RCC, &dp.FLASH);
+    let clocks = match &status {
+        Ok(clocks) => *clocks,
+        Err(err) => {
+            error!("clocks: {=str}", err.message());
+            clock::HSI_CLOCKS
+        }
+    };
+    info!("clocks: SYSCLK at {=u32}Hz", clocks.sysclk);
+
+    // Used for delays
+    let delay = CycleDelay::new(cp.DCB, cp.DWT, clocks.hclk);
+    time::start(cp.SYST, clocks.hclk);
+
+    #[cfg(feature = "stm32duino-bootloader")]
+    lcd_example_bluepill::bootloader::release_usb(&dp.RCC, delay);
+
+    // Setup GPIOB for LCD
+    gpio::enable_clock(&dp.RCC, PortName::B);
+
+    // Init display
+    debug!("lcd: init");
+    let port = dp.GPIOB;
+    #[cfg(feature = "io-strobe")]
+    let port = {
+        gpio::enable_clock(&dp.RCC, PortName::A);
+        StrobedPort::new(port, dp.GPIOA, STROBE_PIN)
+    };
+    #[cfg(feature = "itm-trace")]
+    let port = TracedPort::new(port, ItmSink::new(cp.ITM));
+    let (mut display, present) = init_display(LcdHardware::new(port, delay), delay);
+    if present {
+        fault::display_ready();
+        info!("lcd: ready");
+    } else {
+        error!("lcd: not detected, running headless");
+    }
+
+    if let Err(err) = status {
+        halt(present.then_some(display), delay, &dp.RCC, err);
+    }
+
+    let button = Button::new(&dp.RCC);
+    let buzzer = Buzzer::new(&dp.RCC);
+    let relay = Relay::new(&dp.RCC);
+    let mut sensor = TempSensor::new(dp.ADC1, &dp.RCC);
+    random::mix(sensor.read_noise(32) ^ DWT::cycle_count());
+    let supply = SupplyMonitor::start(&dp.PWR, &dp.RCC, LOW_VOLTAGE_MV);
+    let boot_stats = BootStats::start(dp.BKP, &dp.PWR, &dp.RCC);
+    info!("boot: #{=u16}", boot_stats.boots());
+    #[cfg(feature = "tamper")]
+    if boot_stats.tampered() {
+        error!("backup: tamper event, registers cleared");
+    }
+    let mut settings = Settings::default();
+    if !boot_stats.fresh() {
+        settings.alarm_clock.set_backup(ALARM.map(|field| boot_stats.get(field)));
+        settings.thresholds.set_backup(boot_stats.get(ALERT));
+        #[cfg(feature = "speedometer")]
+        settings.speedometer.restore_trip(boot_stats.get(TRIP) * 10);
+    }
+    #[cfg(feature = "weather")]
+    settings.weather.attach(Quantity::Temperature);
+    let store = (StorePages, Store::mount(&mut StorePages));
+    let stored = |key: u16| store.1.get(&store.0, key);
+    settings.reaction.set_scores(core::array::from_fn(|i| stored(KEY_SCORES + i as u16).map_or(u16::MAX, |score| score as u16)));
+    #[cfg(feature = "door-lock")]
+    if let Some(pin_hash) = stored(KEY_PIN_HASH) {
+        settings.lock.set_pin_hash(pin_hash);
+    }
+    #[cfg(feature = "battery")]
+    if let Some(cutoff_mv) = stored(KEY_BATTERY_CUTOFF) {
+        settings.battery.set_cutoff_mv(cutoff_mv as u16);
+    }
+    #[cfg(feature = "day-night")]
+    if let (Some(starts), Some(profiles)) = (stored(KEY_SCHEDULE_STARTS), stored(KEY_SCHEDULE_PROFILES)) {
+        settings.schedule.set_store(starts, profiles);
+    }
+    #[cfg(feature = "calibration")]
+    for (i, channel) in Channel::ALL.into_iter().enumerate() {
+        let key = KEY_CALIBRATION + 2 * i as u16;
+        if let (Some(offset), Some(gain)) = (stored(key), stored(key + 1)) {
+            settings.calibration.restore(channel, Constants { offset: offset as i32, gain });
+        }
+    }
+    // Battery ran out (or was about to): the display is parked and the chip waits for new batteries
+    // in Standby, without the watchdog (which is only started later)
+    #[cfg(feature = "battery")]
+    {
+        let vdd_mv = sensor.read_vdd();
+        #[cfg(feature = "calibration")]
+        let vdd_mv = settings.calibration.update(Channel::Supply, i32::from(vdd_mv)).clamp(0, i32::from(u16::MAX)) as u16;
+        if !settings.battery.can_start(vdd_mv) {
+            error!("battery: empty, {=u16}mV", vdd_mv);
+            if present {
+                display.clear();
+                screens::SETUP.off(&mut display);
+            }
+            power::standby(cp.SCB, &dp.PWR);
+        }
+    }
+    #[cfg(feature = "access-log")]
+    let events = {
+        let log = EventLog::mount(&EventPages);
+        info!("events: {=usize} records", log.count());
+        settings.events.update(&log, &EventPages);
+        (EventPages, log)
+    };
+    let timer = OneShot::new(dp.TIM2, &dp.RCC, &clocks);
+    // Only read, for the registers screen
+    let gpioa = gpio::enable_port(&dp.RCC, PortName::A);
+    let error_led = (!present).then(|| fault::ErrorLed::new(&dp.RCC, fault::NO_DISPLAY));
+    #[cfg(feature = "spi-slave")]
+    let spi = {
+        let buffer = cortex_m::singleton!(: [u8; BUFFER_SIZE] = [0; BUFFER_SIZE]).unwrap();
+        (SpiSlave::new(dp.SPI1, dp.DMA1, &dp.RCC, buffer), SpiFrames::new())
+    };
+    #[cfg(feature = "modbus")]
+    let modbus = {
+        let serial = Serial::new(dp.USART1, &dp.RCC, &clocks, MODBUS_BAUD, Parity::Even, Some(MODBUS_DE));
+        unsafe { NVIC::unmask(Interrupt::USART1) };
+        (serial, ModbusSlave::new(MODBUS_ADDRESS), Frame::new())
+    };
+    #[cfg(feature = "lcdproc")]
+    {
+        // Nothing is sent back, so the port does not need to be kept
+        Serial::new(dp.USART1, &dp.RCC, &clocks, LCDPROC_BAUD, Parity::None, None);
+        unsafe { NVIC::unmask(Interrupt::USART1) };
+    }
+    #[cfg(feature = "terminal")]
+    {
+        // Terminal does not answer, so the port does not need to be kept
+        Serial::new(dp.USART1, &dp.RCC, &clocks, TERMINAL_BAUD, Parity::None, None);
+        unsafe { NVIC::unmask(Interrupt::USART1) };
+    }
+    #[cfg(feature = "link")]
+    let link = {
+        let serial = Serial::new(dp.USART1, &dp.RCC, &clocks, LINK_BAUD, Parity::None, None);
+        unsafe { NVIC::unmask(Interrupt::USART1) };
+        (serial, Link::new())
+    };
+    #[cfg(feature = "shell")]
+    {
+        // Both directions are handled by the interrupt, so the port does not need to be kept
+        Serial::new(dp.USART1, &dp.RCC, &clocks, SHELL_BAUD, Parity::None, None);
+        unsafe { NVIC::unmask(Interrupt::USART1) };
+    }
+    #[cfg(feature = "temp-log")]
+    let temp_log = {
+        // Both directions are handled by the interrupt, so the port does not need to be kept
+        Serial::new(dp.USART1, &dp.RCC, &clocks, LOG_BAUD, Parity::None, None);
+        unsafe { NVIC::unmask(Interrupt::USART1) };
+        let log = Log::mount(&LogArea);
+        info!("log: {=usize} records", log.count());
+        settings.temp_log.update(&log, &LogArea);
+        (LogArea, log, Export::default())
+    };
+    #[cfg(feature = "dmx")]
+    {
+        // Only received, by the interrupt, so the port does not need to be kept
+        Serial::new(dp.USART1, &dp.RCC, &clocks, DMX_BAUD, Parity::None, None);
+        unsafe { NVIC::unmask(Interrupt::USART1) };
+    }
+    #[cfg(feature = "midi")]
+    {
+        // MIDI IN is receive only, so the port does not need to be kept
+        Serial::new(dp.USART1, &dp.RCC, &clocks, midi::BAUD, Parity::None, None);
+        unsafe { NVIC::unmask(Interrupt::USART1) };
+    }
+    #[cfg(any(feature = "i2c-scan", feature = "gesture", feature = "door-lock"))]
+    let i2c = I2cMaster::new(dp.I2C2, &dp.RCC, &clocks);
+    #[cfg(feature = "onewire")]
+    let onewire = PinBus::new(&dp.RCC, delay);
+    #[cfg(feature = "spi-flash")]
+    let flash = SpiMaster::new(dp.SPI1, &dp.RCC);
+    #[cfg(feature = "fan")]
+    let fan = {
+        let fan = FanTimer::new(dp.TIM3, &dp.RCC, &clocks);
+        unsafe { NVIC::unmask(Interrupt::TIM3) };
+        fan
+    };
+    #[cfg(any(feature = "tachometer", feature = "totalizer"))]
+    {
+        pulse_input::init(&dp.RCC);
+        unsafe { NVIC::unmask(pulse_input::INTERRUPT) };
+    }
+    #[cfg(feature = "pin-monitor")]
+    {
+        edges::init(&dp.RCC);
+        for interrupt in edges::INTERRUPTS {
+            unsafe { NVIC::unmask(interrupt) };
+        }
+    }
+    #[cfg(feature = "totalizer")]
+    if let Some(total) = stored(KEY_TOTALIZER) {
+        info!("totalizer: {=u32} pulses", total);
+        settings.totalizer.restore(total);
+    }
+    #[cfg(feature = "speedometer")]
+    if let Some(odometer_m) = stored(KEY_ODOMETER) {
+        info!("odometer: {=u32} m", odometer_m);
+        settings.speedometer.restore(odometer_m);
+    }
+    #[cfg(feature = "hour-meter")]
+    let hour_meter = {
+        let total = stored(KEY_HOUR_METER).unwrap_or(0);
+        info!("hour meter: {=u32} tenths of an hour", total);
+        settings.hour_meter.restore(total, stored(KEY_TRIP_START));
+        SenseInput::new(&dp.RCC)
+    };
+    #[cfg(feature = "pir")]
+    let pir = PirInput::new(&dp.RCC);
+    #[cfg(feature = "backlight")]
+    let backlight = BacklightPwm::new(dp.TIM1, &dp.RCC, &clocks);
+    #[cfg(feature = "i2c-slave")]
+    {
+        let slave = I2cSlave::new(dp.I2C2, &dp.RCC, &clocks, I2C_ADDRESS);
+        cortex_m::interrupt::free(|cs| I2C_SLAVE.borrow(cs).replace(Some(slave)));
+        unsafe {
+            NVIC::unmask(Interrupt::I2C2_EV);
+            NVIC::unmask(Interrupt::I2C2_ER);
+        }
+    }
+
+    #[cfg(not(feature = "stop-mode"))]
+    let idle = Wfi;
+    #[cfg(feature = "stop-mode")]
+    let idle = Stop::new(cp.SCB, dp.PWR, dp.RTC, dp.EXTI, dp.RCC, dp.FLASH);
+
+    if present {
+        display = self_test(display, delay);
+        #[cfg(feature = "bench")]
+        {
+            display = benchmark(display, delay);
+        }
+        if screens::splash(&mut display, reset_cause.label()).is_err() {
+            error!("lcd: splash failed");
+        }
+        delay.delay_us(SPLASH_TIME_US);
+        display.clear();
+    }
+
+    // Started after the splash screen, which does not feed it
+    let watchdog = Watchdog::start(dp.IWDG, WATCHDOG_TIMEOUT_MS);
+
+    // Switch to the queued driver. Without the display, screens are rendered into the framebuffer
+    // and logged instead.
+    let headless = if present {
+        let queue: &'static mut Queue = cortex_m::singleton!(: Queue = Queue::new()).unwrap();
+        let (producer, consumer) = queue.split();
+        let engine = Engine::new(display.unwrap(), consumer);
+        cortex_m::interrupt::free(|cs| ENGINE.borrow(cs).replace(Some((engine, timer))));
+        DISPLAY.init(Framebuffer::new(Writer::new(producer, kick_engine, can_wait)));
+        #[cfg(feature = "lcd-log")]
+        lcd_example_bluepill::lcd_log::init(log::LevelFilter::Info);
+        unsafe { NVIC::unmask(Interrupt::TIM2) };
+        None
+    } else {
+        Some(Framebuffer::new(NoDisplay))
+    };
+    // Records which did not pass the check (their keys fell back to the previous values, or the
+    // defaults) are reported once the display log is set up
+    if store.1.corrupt() > 0 {
+        error!("store: {=usize} corrupt records", store.1.corrupt());
+        notify!(warn, "Settings corrupt");
+    }
+    #[cfg(feature = "access-log")]
+    if events.1.corrupt() > 0 {
+        error!("events: {=usize} corrupt records", events.1.corrupt());
+        notify!(warn, "Events corrupt");
+    }
+    let app = App {
+        watchdog,
+        button,
+        press_held: false,
+        buzzer,
+        relay,
+        sensor,
+        supply,
+        gpioa,
+        ui: screens::navigation(),
+        settings,
+        stats: Stats {
+            boots: boot_stats.boots(),
+            reset_cause: reset_cause.label(),
+            ..Stats::default()
+        },
+        boot_stats,
+        store,
+        stack_low: false,
+        headless,
+        error_led,
+        #[cfg(feature = "spi-slave")]
+        spi,
+        #[cfg(feature = "modbus")]
+        modbus,
+        #[cfg(feature = "lcdproc")]
+        lcdproc: Hd44780Serial::new(),
+        #[cfg(feature = "terminal")]
+        terminal: Terminal::new(),
+        #[cfg(feature = "link")]
+        link,
+        #[cfg(feature = "shell")]
+        shell: Shell::new(),
+        #[cfg(feature = "rtt-console")]
+        rtt: (Rtt::take().unwrap(), Shell::new()),
+        #[cfg(any(feature = "i2c-scan", feature = "gesture", feature = "door-lock"))]
+        i2c,
+        #[cfg(feature = "i2c-scan")]
+        scanner: Scanner::new(),
+        #[cfg(feature = "gesture")]
+        gesture: Apds9960::default(),
+        #[cfg(feature = "door-lock")]
+        keypad: Keypad::default(),
+        #[cfg(feature = "onewire")]
+        onewire,
+        #[cfg(feature = "spi-flash")]
+        flash,
+        #[cfg(feature = "fan")]
+        fan,
+        #[cfg(feature = "temp-log")]
+        temp_log,
+        #[cfg(feature = "hour-meter")]
+        hour_meter,
+        #[cfg(feature = "pir")]
+        pir,
+        #[cfg(feature = "backlight")]
+        backlight,
+        #[cfg(feature = "access-log")]
+        events,
+        #[cfg(feature = "access-log")]
+        alarm_ringing: None,
+    };
+    run(app, Power::new(idle))
 }
 
-const RS: usize = 12; // PB12 is RS
-const RW: usize = 13; // PB13 is RW
-const E: usize = 14; // PB14 is E
-const DATA: usize = 6; // PB6-PB9 is DB4-DB7
+/// Initialize display and check that it is connected. Without the `input` feature, display cannot
+/// be read, so it is assumed to be connected, and the initialization is just repeated after the
+/// fixed delays (it is harmless for the module that got the first one).
+fn init_display(hw: LcdHw, delay: CycleDelay) -> (LcdDisplay, bool) {
+    // Time since reset counts, too
+    let elapsed_ms = time::millis();
+    if elapsed_ms < LCD_POWER_ON_MS {
+        delay.delay_us((LCD_POWER_ON_MS - elapsed_ms) * 1000);
+    }
 
+    #[cfg(feature = "input")]
+    {
+        let mut hw = hw;
+        for attempt in 1..=LCD_INIT_ATTEMPTS {
+            // Initialization waits on the busy flag forever, so make sure it is not stuck first
+            if hw.wait_ready_timeout(BUSY_TIMEOUT_US) {
+                let mut display = Display::new(hw);
+                screens::init(&mut display);
+                hw = display.unwrap();
+                if hw.probe() {
+                    return (Display::new(hw), true);
+                }
+            }
+            if attempt < LCD_INIT_ATTEMPTS {
+                error!("lcd: init attempt {=u32} failed, retrying", attempt);
+                delay.delay_us(LCD_POWER_ON_MS * 1000);
+            }
+        }
+        (Display::new(hw), false)
+    }
+    #[cfg(not(feature = "input"))]
+    {
+        let mut display = Display::new(hw);
+        screens::init(&mut display);
+        for _ in 1..LCD_INIT_ATTEMPTS {
+            delay.delay_us(LCD_POWER_ON_MS * 1000);
+            screens::init(&mut display);
+        }
+        (display, true)
+    }
+}
+
+/// Fill the display with blocks, then with checkerboard, so dead pixels and cells are easy to spot.
+/// Then check the custom characters memory (with `input` feature) and show the result.
+fn self_test(mut display: LcdDisplay, delay: CycleDelay) -> LcdDisplay {
+    screens::fill(&mut display, screens::FULL_BLOCK);
+    delay.delay_us(SELF_TEST_STEP_US);
+    screens::fill(&mut display, screens::CHECKERBOARD as u8);
+    delay.delay_us(SELF_TEST_STEP_US);
+
+    #[cfg(feature = "input")]
+    let (mut display, failure) = {
+        let mut hw = display.unwrap();
+        let cgram_ok = hw.read_character(screens::CHECKERBOARD as u8) == Some(screens::CHECKERBOARD_MAP);
+        (Display::new(hw), (!cgram_ok).then_some("CGRAM"))
+    };
+    #[cfg(not(feature = "input"))]
+    let failure: Option<&str> = None;
+
+    match failure {
+        Some(check) => error!("self-test: {=str} failed", check),
+        None => info!("self-test: pass"),
+    }
+    screens::self_test_result(&mut display, failure).ok();
+    delay.delay_us(SELF_TEST_STEP_US);
+    display
+}
+
+/// Measure display throughput with the busy flag polling (`input` feature only) and with the fixed
+/// delays, show and log the results.
+#[cfg(feature = "bench")]
+fn benchmark(mut display: LcdDisplay, delay: CycleDelay) -> LcdDisplay {
+    #[cfg(feature = "input")]
+    {
+        bench_mode(&mut display, delay, "busy");
+        let mut hw = display.unwrap();
+        hw.use_busy_flag(false);
+        display = Display::new(hw);
+    }
+    bench_mode(&mut display, delay, "fixed");
+    #[cfg(feature = "input")]
+    {
+        let mut hw = display.unwrap();
+        hw.use_busy_flag(true);
+        display = Display::new(hw);
+    }
+    display
+}
+
+#[cfg(feature = "bench")]
+fn bench_mode(display: &mut LcdDisplay, delay: CycleDelay, mode: &str) {
+    let results = bench::measure(display, &delay);
+    info!(
+        "bench: {=str}: {=u32} chars/s, redraw {=u32}us",
+        mode,
+        results.chars_per_sec,
+        results.redraw_us
+    );
+    display.clear();
+    bench::report(display, mode, &results).ok();
+    delay.delay_us(BENCH_REPORT_US);
+}
+
+/// Report clock error and stop. LED pattern is for the case display is not connected or is not
+/// working.
+fn halt(display: Option<LcdDisplay>, delay: CycleDelay, rcc: &RCC, err: ClockError) -> ! {
+    if let Some(mut display) = display {
+        screens::error(&mut display, "Clock error", err.message()).ok();
+    }
+    fault::blink_code(delay, rcc, err.code())
+}
 
-/// Binding of HD44780 instance to the real hardware
-pub struct LcdHardware<'a> {
-    syst: &'a SYST,
-    gpiob: &'a GPIOB,
+/// State shared by the tasks.
+struct App {
+    watchdog: Watchdog,
+    button: Button,
+    /// Button is pressed on the screen which takes the long press, the press is not reported yet.
+    press_held: bool,
+    /// Timer and alarm clock beeps.
+    buzzer: Buzzer,
+    /// Heater, switched by the thermostat (door strike, with the `door-lock` feature).
+    relay: Relay,
+    sensor: TempSensor,
+    supply: SupplyMonitor,
+    gpioa: &'static gpioa::RegisterBlock,
+    ui: StateMachine<Screen, Event, Settings>,
+    settings: Settings,
+    stats: Stats,
+    boot_stats: BootStats,
+    /// Flash store pages and the active page (settings and counters).
+    store: (StorePages, Store),
+    /// Free stack got below `STACK_LOW_BYTES` (warning is shown once).
+    stack_low: bool,
+    /// Display is not connected, screens are rendered here and logged.
+    headless: Option<Framebuffer<NoDisplay>>,
+    /// Reports missing display.
+    error_led: Option<fault::ErrorLed>,
+    #[cfg(feature = "spi-slave")]
+    spi: (SpiSlave, SpiFrames),
+    /// Serial port, slave and the frame being received.
+    #[cfg(feature = "modbus")]
+    modbus: (Serial, ModbusSlave, Frame),
+    /// Parser of the serial stream (the port itself is only read, by the interrupt).
+    #[cfg(feature = "lcdproc")]
+    lcdproc: Hd44780Serial,
+    /// Terminal screen, the window of which is copied to the remote text.
+    #[cfg(feature = "terminal")]
+    terminal: Terminal,
+    #[cfg(feature = "link")]
+    link: (Serial, Link),
+    /// Command line being typed and the output (the port itself is only used by the interrupt).
+    #[cfg(feature = "shell")]
+    shell: Shell,
+    /// Console over the debugger and its own shell.
+    #[cfg(feature = "rtt-console")]
+    rtt: (Rtt, Shell),
+    /// I2C2 master, shared by the scanner, the gesture sensor and the keypad.
+    #[cfg(any(feature = "i2c-scan", feature = "gesture", feature = "door-lock"))]
+    i2c: I2cMaster,
+    #[cfg(feature = "i2c-scan")]
+    scanner: Scanner,
+    #[cfg(feature = "gesture")]
+    gesture: Apds9960,
+    #[cfg(feature = "door-lock")]
+    keypad: Keypad,
+    #[cfg(feature = "onewire")]
+    onewire: PinBus,
+    #[cfg(feature = "spi-flash")]
+    flash: SpiMaster,
+    #[cfg(feature = "fan")]
+    fan: FanTimer,
+    /// Log area, position in it and the serial commands.
+    #[cfg(feature = "temp-log")]
+    temp_log: (LogArea, Log, Export),
+    /// Sense input of the hour meter.
+    #[cfg(feature = "hour-meter")]
+    hour_meter: SenseInput,
+    #[cfg(feature = "pir")]
+    pir: PirInput,
+    #[cfg(feature = "backlight")]
+    backlight: BacklightPwm,
+    /// Access log area and the position in it.
+    #[cfg(feature = "access-log")]
+    events: (EventPages, EventLog),
+    /// Alarm ringing when the access log was last told (it is logged when it starts ringing).
+    #[cfg(feature = "access-log")]
+    alarm_ringing: Option<usize>,
 }
 
-impl<'a> lcd::Hardware for LcdHardware<'a> {
-    fn rs(&self, bit: bool) {
-        self.gpiob.write_pin(RS, bit);
+const TASKS: [Task<App>; 32] = [
+    Task { name: "watchdog", period_ms: 500, run: feed_watchdog },
+    Task { name: "button", period_ms: 10, run: scan_button },
+    Task { name: "sensor", period_ms: 1000, run: poll_sensor },
+    Task { name: "timer", period_ms: SCREEN_TIME_MS, run: timer },
+    Task { name: "display", period_ms: 100, run: refresh_display },
+    Task { name: "health", period_ms: 1000, run: check_display },
+    Task { name: "verify", period_ms: VERIFY_MS, run: verify_display },
+    Task { name: "redraw", period_ms: FULL_REDRAW_MS, run: full_redraw },
+    Task { name: "led", period_ms: fault::ErrorLed::TICK_MS, run: blink_error },
+    Task { name: "remote", period_ms: 1, run: poll_remote },
+    Task { name: "stack", period_ms: 1000, run: check_stack },
+    Task { name: "boot stats", period_ms: BOOT_STATS_SAVE_MS, run: save_boot_stats },
+    Task { name: "i2c scan", period_ms: 10, run: scan_i2c },
+    Task { name: "gesture", period_ms: 20, run: poll_gesture },
+    Task { name: "keypad", period_ms: KEYPAD_SCAN_MS, run: poll_keypad },
+    Task { name: "1-wire", period_ms: 1000, run: poll_onewire },
+    Task { name: "flash", period_ms: 1000, run: probe_flash },
+    Task { name: "alarm", period_ms: 10, run: sound_alarm },
+    Task { name: "fan", period_ms: 1000, run: control_fan },
+    Task { name: "tachometer", period_ms: 100, run: measure_speed },
+    Task { name: "totalizer", period_ms: 100, run: count_pulses },
+    Task { name: "checkpoint", period_ms: CHECKPOINT_MS, run: save_counters },
+    Task { name: "hour meter", period_ms: 1000, run: count_hours },
+    Task { name: "motion", period_ms: 100, run: watch_motion },
+    Task { name: "pins", period_ms: 100, run: monitor_pins },
+    Task { name: "backlight", period_ms: 100, run: adapt_backlight },
+    Task { name: "dmx", period_ms: 100, run: monitor_dmx },
+    Task { name: "midi", period_ms: 10, run: receive_midi },
+    Task { name: "log", period_ms: LOG_INTERVAL_MS, run: log_temperature },
+    Task { name: "log export", period_ms: 5, run: export_log },
+    Task { name: "shell", period_ms: 10, run: run_shell },
+    Task { name: "rtt", period_ms: 10, run: run_rtt_console },
+];
+
+fn feed_watchdog(app: &mut App) {
+    app.watchdog.feed();
+}
+
+/// Report the button press right away or, on the screens which take the long press, either the
+/// long press or (if the button is released before that) the press, on the release.
+fn scan_button(app: &mut App) {
+    let event = match app.button.scan() {
+        Some(Press::Down) if app.ui.state().long_press() => {
+            app.press_held = true;
+            None
+        }
+        Some(Press::Down) => Some(Event::Button),
+        Some(Press::Long) if core::mem::take(&mut app.press_held) => Some(Event::LongPress),
+        Some(Press::Up) if core::mem::take(&mut app.press_held) => Some(Event::Button),
+        _ => None,
+    };
+    if let Some(event) = event {
+        debug!("button: {=str}", if event == Event::LongPress { "long press" } else { "pressed" });
+        dispatch(app, event);
     }
+}
 
-    fn enable(&self, bit: bool) {
-        self.gpiob.write_pin(E, bit);
+fn poll_sensor(app: &mut App) {
+    app.stats.temperature = app.sensor.read();
+    #[cfg(feature = "calibration")]
+    {
+        let temperature = app.settings.calibration.update(Channel::Temperature, i32::from(app.stats.temperature));
+        app.stats.temperature = temperature.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16;
+    }
+    app.settings.trend.push(app.stats.temperature);
+    random::mix(app.sensor.read_noise(8) ^ DWT::cycle_count());
+    app.stats.vdd_mv = app.sensor.read_vdd();
+    #[cfg(feature = "calibration")]
+    {
+        let vdd_mv = app.settings.calibration.update(Channel::Supply, i32::from(app.stats.vdd_mv));
+        app.stats.vdd_mv = vdd_mv.clamp(0, i32::from(u16::MAX)) as u16;
+    }
+    // Startup check parks the display and stops the chip
+    #[cfg(feature = "battery")]
+    if app.settings.battery.update(app.stats.vdd_mv) {
+        error!("battery: empty, shutting down");
+        save_boot_stats(app);
+        SCB::sys_reset();
+    }
+    let heating = app.settings.thermostat.is_heating();
+    app.settings.thermostat.update(app.stats.temperature);
+    // Chip temperature stands in for the air one without a 1-Wire sensor
+    #[cfg(all(feature = "weather", not(feature = "onewire")))]
+    {
+        app.settings.weather.advance(time::millis());
+        app.settings.weather.update(Quantity::Temperature, Some(i32::from(app.stats.temperature)));
     }
+    if app.settings.thermostat.is_heating() != heating {
+        info!("thermostat: heater {=str}", if heating { "off" } else { "on" });
+    }
+    // Relay is the door strike with the door lock
+    #[cfg(not(feature = "door-lock"))]
+    app.relay.set(app.settings.thermostat.is_heating());
+    let low_voltage = app.supply.is_low();
+    if low_voltage != app.stats.low_voltage {
+        info!("supply: {=str}", if low_voltage { "low voltage" } else { "voltage ok" });
+        if low_voltage {
+            notify!(warn, "Low voltage");
+        }
+        app.stats.low_voltage = low_voltage;
+    }
+    check_threshold(app, Source::Temperature, i32::from(app.stats.temperature));
+    check_threshold(app, Source::Supply, i32::from(app.stats.vdd_mv));
+}
 
-    fn data(&self, data: u8) {
-        self.gpiob.write_pin_range(DATA, 4, u16::from(data));
+/// Check the measured value against its alarm thresholds, show the alarm right away (and log it to
+/// the access log) if it got latched.
+fn check_threshold(app: &mut App, source: Source, value: i32) {
+    app.settings.advance(time::millis());
+    if app.settings.thresholds.check(source, value) {
+        info!("threshold: {=str} alarm", source.name());
+        #[cfg(feature = "access-log")]
+        record_event(app, Kind::Threshold, source as u16);
+        save_backup(app);
+        refresh_display(app);
     }
 }
 
-impl<'a> lcd::Delay for LcdHardware<'a> {
-    fn delay_us(&self, delay_usec: u32) {
-        delay_us(self.syst, delay_usec);
+fn check_stack(app: &mut App) {
+    app.stats.stack_free = stack::free_bytes() as u32;
+    if app.stats.stack_free < STACK_LOW_BYTES && !app.stack_low {
+        error!("stack: {=u32} bytes free", app.stats.stack_free);
+        notify!(warn, "Stack low");
+        app.stack_low = true;
     }
 }
 
-// Optional, if not implemented `lcd` library will use delays
-#[cfg(feature = "input")]
-impl<'a> lcd::InputCapableHardware for LcdHardware<'a> {
-    fn rw(&self, bit: bool) {
-        if bit {
-            // LCD has OD output, set all to '0' just to be sure.
-            self.gpiob.write_pin_range(DATA, 4, 0);
+fn save_boot_stats(app: &mut App) {
+    save_backup(app);
+    #[cfg(feature = "tamper")]
+    let tampered = app.boot_stats.tampered();
+    app.boot_stats.save(time::uptime_secs());
+    #[cfg(feature = "tamper")]
+    if app.boot_stats.tampered() && !tampered {
+        error!("backup: tamper event, registers cleared");
+    }
+}
+
+/// Keep the alarms, the threshold alarm not acknowledged yet and the speedometer trip in the backup
+/// registers (only the registers which have changed are written).
+fn save_backup(app: &mut App) {
+    for (field, saved) in ALARM.into_iter().zip(app.settings.alarm_clock.to_backup()) {
+        app.boot_stats.set(field, saved);
+    }
+    app.boot_stats.set(ALERT, app.settings.thresholds.to_backup());
+    #[cfg(feature = "speedometer")]
+    app.boot_stats.set(TRIP, (app.settings.speedometer.trip() / 10).min(TRIP.max()));
+}
+
+fn timer(app: &mut App) {
+    dispatch(app, Event::Timer);
+}
+
+/// Feed event to the settings editor or to the UI state machine, redraw immediately if anything
+/// has changed.
+fn dispatch(app: &mut App, event: Event) {
+    // Button returns from the remote text to the screens
+    if event == Event::Button && cortex_m::interrupt::free(|cs| REMOTE.borrow(cs).borrow_mut().release()) {
+        debug!("remote: released");
+        refresh_display(app);
+        return;
+    }
+    // Exact time of the events (of the user input, especially) is a good source of entropy
+    random::mix(DWT::cycle_count());
+    app.settings.advance(time::millis());
+    if event != Event::Timer && wake(app) {
+        return;
+    }
+    if app.settings.handle(app.ui.state(), event) {
+        debug!("settings: changed");
+        save_backup(app);
+        if app.ui.state() == Screen::Reaction {
+            save_settings(app);
+        }
+        // ... and so are the battery cutoff and the day and night profiles
+        #[cfg(any(feature = "battery", feature = "day-night"))]
+        if app.ui.state() == Screen::Settings {
+            save_settings(app);
+        }
+        // Trip start is kept with the settings
+        #[cfg(feature = "hour-meter")]
+        if app.ui.state() == Screen::HourMeter {
+            save_settings(app);
+        }
+        // ... and so is the PIN
+        #[cfg(feature = "door-lock")]
+        if app.ui.state() == Screen::Lock {
+            save_settings(app);
+        }
+        // ... and the calibration constants
+        #[cfg(feature = "calibration")]
+        if app.ui.state() == Screen::Calibration {
+            save_settings(app);
+        }
+        // Reset total is saved right away
+        #[cfg(feature = "totalizer")]
+        if app.ui.state() == Screen::Totalizer {
+            save_counters(app);
+        }
+        #[cfg(feature = "temp-log")]
+        if app.ui.state() == Screen::TempLog {
+            let (area, log, _) = &app.temp_log;
+            app.settings.temp_log.update(log, area);
+        }
+        #[cfg(feature = "access-log")]
+        if app.ui.state() == Screen::Events {
+            let (area, log) = &app.events;
+            app.settings.events.update(log, area);
+        }
+        refresh_display(app);
+    } else if app.ui.handle(event, &app.settings) {
+        debug!("screen: {=str}", app.ui.state().name());
+        refresh_display(app);
+    }
+}
+
+/// Take the user input as activity, waking the display from the screensaver (with the `pir`
+/// feature, otherwise it is a no-op). Returns `true` if the input only woke the display; while
+/// something buzzes, it is used as usual (to silence it).
+fn wake(app: &mut App) -> bool {
+    #[cfg(feature = "pir")]
+    if app.settings.motion.wake() && !app.settings.buzzing() {
+        debug!("screensaver: off");
+        refresh_display(app);
+        return true;
+    }
+    let _ = app;
+    false
+}
+
+/// Store the best reaction times, the hour meter trip start, the door lock PIN and the battery
+/// cutoff in flash (only the values which have changed are written).
+fn save_settings(app: &mut App) {
+    let (pages, store) = &mut app.store;
+    for (i, score) in app.settings.reaction.scores().into_iter().enumerate() {
+        store.set(pages, KEY_SCORES + i as u16, u32::from(score));
+    }
+    #[cfg(feature = "hour-meter")]
+    store.set(pages, KEY_TRIP_START, app.settings.hour_meter.trip_start());
+    #[cfg(feature = "door-lock")]
+    store.set(pages, KEY_PIN_HASH, app.settings.lock.pin_hash());
+    #[cfg(feature = "battery")]
+    store.set(pages, KEY_BATTERY_CUTOFF, u32::from(app.settings.battery.cutoff_mv()));
+    #[cfg(feature = "day-night")]
+    {
+        let [starts, profiles] = app.settings.schedule.to_store();
+        store.set(pages, KEY_SCHEDULE_STARTS, starts);
+        store.set(pages, KEY_SCHEDULE_PROFILES, profiles);
+    }
+    #[cfg(feature = "calibration")]
+    for (i, channel) in Channel::ALL.into_iter().enumerate() {
+        let constants = app.settings.calibration.constants(channel);
+        let key = KEY_CALIBRATION + 2 * i as u16;
+        store.set(pages, key, constants.offset as u32);
+        store.set(pages, key + 1, constants.gain);
+    }
+}
+
+fn refresh_display(app: &mut App) {
+    app.stats.gpioa_idr = app.gpioa.idr.read().bits() as u16;
+    app.stats.gpioa_odr = app.gpioa.odr.read().bits() as u16;
+    app.stats.uptime_s = time::uptime_secs();
+    app.stats.runtime_s = app.boot_stats.runtime_secs(app.stats.uptime_s);
+    app.stats.memory = inspect::read(app.settings.inspector.start());
+    app.settings.advance(time::millis());
+    let screen = app.ui.state();
+    let remote = cortex_m::interrupt::free(|cs| {
+        let remote = REMOTE.borrow(cs).borrow();
+        remote.is_active().then(|| remote.clone())
+    });
+    // Latched threshold alarm preempts the remote text, which preempts the screens
+    let owner = if app.settings.thresholds.latched().is_some() {
+        Owner::Alarm
+    } else if remote.is_some() {
+        Owner::Remote
+    } else {
+        Owner::Screen
+    };
+    #[cfg(feature = "pir")]
+    let blank = app.settings.motion.asleep();
+    #[cfg(not(feature = "pir"))]
+    let blank = false;
+    if let Some(fb) = app.headless.as_mut() {
+        fb.arbitrate(owner);
+        match (owner, &remote) {
+            (Owner::Alarm, _) => screens::alert(fb, &app.settings).ok(),
+            (Owner::Remote, Some(remote)) => remote.render(fb).ok(),
+            _ if blank => screens::blank(fb).ok(),
+            _ => screen.render(fb, &app.stats, &app.settings).ok(),
+        };
+        if fb.is_dirty() {
+            info!("lcd: |{=str}|{=str}|", fb.row(0), fb.row(1));
+            fb.flush().ok();
+        }
+        return;
+    }
+    // Failed render is retried on the next refresh; failures are counted and flagged on the
+    // screen instead
+    let rendered = DISPLAY.lock(|display| {
+        display.arbitrate(owner);
+        match (owner, &remote) {
+            (Owner::Alarm, _) => screens::alert(display, &app.settings)?,
+            (Owner::Remote, Some(remote)) => remote.render(display)?,
+            _ if blank => screens::blank(display)?,
+            _ => screen.render(display, &app.stats, &app.settings)?,
+        }
+        #[cfg(feature = "lcd-log")]
+        lcd_example_bluepill::lcd_log::overlay(display)?;
+        display.flush()
+    });
+    if let Some(Err(_)) = rendered {
+        app.stats.render_errors = app.stats.render_errors.saturating_add(1);
+        error!("lcd: queue overflow");
+    }
+}
+
+/// Apply the text received over SPI or serial so far (with the `spi-slave`, `modbus`, `lcdproc`,
+/// `terminal` or `link` feature, otherwise it is a no-op).
+fn poll_remote(app: &mut App) {
+    #[cfg(feature = "spi-slave")]
+    {
+        let (spi, frames) = &mut app.spi;
+        cortex_m::interrupt::free(|cs| {
+            let mut remote = REMOTE.borrow(cs).borrow_mut();
+            spi.read(|byte| frames.receive(&mut remote, byte));
+        });
+    }
+    #[cfg(feature = "modbus")]
+    {
+        let (serial, slave, frame) = &mut app.modbus;
+        let last_ms = cortex_m::interrupt::free(|cs| {
+            let mut rx = SERIAL_RX.borrow(cs).borrow_mut();
+            while let Some(byte) = rx.pop_front() {
+                // Frame which does not fit fails the CRC check
+                frame.push(byte).ok();
+            }
+            SERIAL_RX_MS.load(Ordering::Relaxed)
+        });
+        if !frame.is_empty() && time::millis().wrapping_sub(last_ms) >= MODBUS_GAP_MS {
+            let response = cortex_m::interrupt::free(|cs| slave.handle(frame, &mut REMOTE.borrow(cs).borrow_mut()));
+            frame.clear();
+            if let Some(response) = response {
+                serial.write(&response);
+            }
+        }
+    }
+    #[cfg(feature = "lcdproc")]
+    {
+        let parser = &mut app.lcdproc;
+        cortex_m::interrupt::free(|cs| {
+            let mut remote = REMOTE.borrow(cs).borrow_mut();
+            let mut rx = SERIAL_RX.borrow(cs).borrow_mut();
+            while let Some(byte) = rx.pop_front() {
+                parser.receive(&mut remote, byte);
+            }
+        });
+    }
+    #[cfg(feature = "terminal")]
+    {
+        let terminal = &mut app.terminal;
+        cortex_m::interrupt::free(|cs| {
+            let mut rx = SERIAL_RX.borrow(cs).borrow_mut();
+            while let Some(byte) = rx.pop_front() {
+                terminal.receive(byte);
+            }
+            terminal.show(&mut REMOTE.borrow(cs).borrow_mut());
+        });
+    }
+    #[cfg(feature = "link")]
+    {
+        let (serial, link) = &mut app.link;
+        let now_ms = time::millis();
+        let mut answers = heapless::Vec::<u8, 8>::new();
+        cortex_m::interrupt::free(|cs| {
+            let mut remote = REMOTE.borrow(cs).borrow_mut();
+            let mut rx = SERIAL_RX.borrow(cs).borrow_mut();
+            // Answers which do not fit are dropped, sender sends those frames again
+            while let Some(byte) = rx.pop_front() {
+                if let Some(answer) = link.receive(&mut remote, byte, now_ms) {
+                    answers.push(answer).ok();
+                }
+            }
+        });
+        if !answers.is_empty() {
+            serial.write(&answers);
+        }
+    }
+    #[cfg(not(any(feature = "spi-slave", feature = "modbus", feature = "lcdproc", feature = "terminal", feature = "link")))]
+    let _ = app;
+}
 
-            // Re-configure port for input
-            for i in 0..4 {
-                self.gpiob.pin_config(DATA + i).input().floating();
+/// Probe the next I2C address (with the `i2c-scan` feature, otherwise it is a no-op).
+fn scan_i2c(app: &mut App) {
+    #[cfg(feature = "i2c-scan")]
+    {
+        if let Some(devices) = app.scanner.poll(&mut app.i2c) {
+            app.settings.i2c.update(devices);
+        }
+    }
+    #[cfg(not(feature = "i2c-scan"))]
+    let _ = app;
+}
+
+/// Drain the gesture sensor FIFO and page through the screens on a left or right swipe, the way
+/// the encoder does (with the `gesture` feature, otherwise it is a no-op). Swipes go past the
+/// settings, so they page even on the screens which take the encoder.
+fn poll_gesture(app: &mut App) {
+    #[cfg(feature = "gesture")]
+    {
+        let event = match app.gesture.poll(&mut app.i2c) {
+            Some(Gesture::Right) => Event::EncoderUp,
+            Some(Gesture::Left) => Event::EncoderDown,
+            _ => return,
+        };
+        random::mix(DWT::cycle_count());
+        app.settings.advance(time::millis());
+        if wake(app) {
+            return;
+        }
+        if app.ui.handle(event, &app.settings) {
+            debug!("gesture: {=str}", app.ui.state().name());
+            refresh_display(app);
+        }
+    }
+    #[cfg(not(feature = "gesture"))]
+    let _ = app;
+}
+
+/// Scan the keypad and pass the key to the door lock, showing its screen first (with the
+/// `door-lock` feature, otherwise it is a no-op). Door strike on the relay follows the lock, PINs
+/// entered go to the access log.
+fn poll_keypad(app: &mut App) {
+    #[cfg(feature = "door-lock")]
+    {
+        if let Some(key) = app.keypad.poll(&mut app.i2c) {
+            if app.ui.enter(Screen::Lock) {
+                debug!("keypad: {=str}", app.ui.state().name());
             }
+            dispatch(app, Event::Serial(key));
+        }
+        #[cfg(feature = "access-log")]
+        if let Some(access) = app.settings.lock.take_access() {
+            let (kind, detail) = match access {
+                Access::Opened => (Kind::DoorOpened, 0),
+                Access::Denied(left) => (Kind::WrongPin, u16::from(left)),
+                Access::LockedOut(seconds) => (Kind::LockedOut, seconds.min(u32::from(u16::MAX)) as u16),
+                Access::PinChanged => (Kind::PinChanged, 0),
+            };
+            record_event(app, kind, detail);
+        }
+        app.settings.lock.advance(time::millis());
+        app.relay.set(app.settings.lock.is_open());
+    }
+    #[cfg(not(feature = "door-lock"))]
+    let _ = app;
+}
 
-            // Finally, set R/W to 1 (read)
-            self.gpiob.write_pin(RW, true);
-        } else {
-            // First, set R/W to 0 (write mode)
-            self.gpiob.write_pin(RW, false);
+/// Re-scan the 1-Wire bus (with the `onewire` feature, otherwise it is a no-op). Temperatures are
+/// read from the conversion started by the previous run, a second ago (conversion takes 750ms).
+fn poll_onewire(app: &mut App) {
+    #[cfg(feature = "onewire")]
+    {
+        let bus = &mut app.onewire;
+        let previous = app.settings.onewire;
+        let mut found = Devices::default();
+        let mut search = onewire::Search::new();
+        while let Some(rom) = search.next(bus) {
+            // Sensors found by this search only have a conversion result next time
+            let converted = rom[0] == DS18B20 && previous.roms().contains(&rom);
+            found.add(rom, converted.then(|| onewire::read_temperature(bus, &rom)).flatten());
+        }
+        onewire::convert_all(bus);
+        app.settings.onewire.update(found);
+        #[cfg(feature = "weather")]
+        {
+            app.settings.weather.advance(time::millis());
+            app.settings.weather.update(Quantity::Temperature, app.settings.onewire.temperature().map(i32::from));
+        }
+    }
+    #[cfg(not(feature = "onewire"))]
+    let _ = app;
+}
+
+/// Read SPI flash identification, so the chip can be connected while the screen is shown (with
+/// the `spi-flash` feature, otherwise it is a no-op).
+fn probe_flash(app: &mut App) {
+    #[cfg(feature = "spi-flash")]
+    {
+        app.stats.flash = flash::probe(&mut app.flash);
+    }
+    #[cfg(not(feature = "spi-flash"))]
+    let _ = app;
+}
 
-            // To be sure LCD is in read mode
-            delay_us(self.syst, 1);
+/// Set fan duty by the chip temperature and measure its speed (with the `fan` feature, otherwise
+/// it is a no-op).
+fn control_fan(app: &mut App) {
+    #[cfg(feature = "fan")]
+    {
+        let duty = app.settings.fan.update(time::millis(), app.stats.temperature, fan_timer::take_pulses());
+        app.fan.set_duty(duty);
+        check_threshold(app, Source::FanSpeed, app.settings.fan.rpm() as i32);
+    }
+    #[cfg(not(feature = "fan"))]
+    let _ = app;
+}
 
-            // Re-configure port back to output
-            for i in 0..4 {
-                self.gpiob.pin_config(DATA + i).push_pull().output2();
+/// Measure the shaft speed and, with the `speedometer` feature, the wheel speed and distance
+/// (with the `tachometer` feature, otherwise it is a no-op).
+fn measure_speed(app: &mut App) {
+    #[cfg(feature = "tachometer")]
+    {
+        let (pulses, last_pulse_ms) = pulse_input::read();
+        let tachometer = &mut app.settings.tachometer;
+        tachometer.update(time::millis(), pulses, last_pulse_ms);
+        #[cfg(feature = "speedometer")]
+        app.settings.speedometer.update(tachometer.rpm(), pulses, tachometer.pulses_per_rev());
+    }
+    #[cfg(not(feature = "tachometer"))]
+    let _ = app;
+}
+
+/// Add the pulses counted to the total (with the `totalizer` feature, otherwise it is a no-op).
+fn count_pulses(app: &mut App) {
+    #[cfg(feature = "totalizer")]
+    app.settings.totalizer.update(pulse_input::read().0);
+    #[cfg(not(feature = "totalizer"))]
+    let _ = app;
+}
+
+/// Checkpoint the total and the odometer, if they have changed (with the `totalizer` and
+/// `speedometer` features, otherwise it is a no-op).
+fn save_counters(app: &mut App) {
+    #[cfg(feature = "totalizer")]
+    if let Some(total) = app.settings.totalizer.checkpoint() {
+        let (pages, store) = &mut app.store;
+        store.set(pages, KEY_TOTALIZER, total);
+    }
+    #[cfg(feature = "speedometer")]
+    if let Some(odometer_m) = app.settings.speedometer.checkpoint() {
+        let (pages, store) = &mut app.store;
+        store.set(pages, KEY_ODOMETER, odometer_m);
+    }
+    #[cfg(not(any(feature = "totalizer", feature = "speedometer")))]
+    let _ = app;
+}
+
+/// Account the equipment runtime and checkpoint the total as soon as it changes (with the
+/// `hour-meter` feature, otherwise it is a no-op).
+fn count_hours(app: &mut App) {
+    #[cfg(feature = "hour-meter")]
+    {
+        app.settings.hour_meter.update(time::millis(), app.hour_meter.running());
+        if let Some(total) = app.settings.hour_meter.checkpoint() {
+            let (pages, store) = &mut app.store;
+            store.set(pages, KEY_HOUR_METER, total);
+        }
+    }
+    #[cfg(not(feature = "hour-meter"))]
+    let _ = app;
+}
+
+/// Update the edges counted on the monitored pins and their levels (with the `pin-monitor`
+/// feature, otherwise it is a no-op).
+fn monitor_pins(app: &mut App) {
+    #[cfg(feature = "pin-monitor")]
+    app.settings.pins.update(edges::read(), app.gpioa.idr.read().bits() as u16);
+    #[cfg(not(feature = "pin-monitor"))]
+    let _ = app;
+}
+
+/// Log the motion seen by the PIR sensor and keep the display awake while it lasts (with the `pir`
+/// feature, otherwise it is a no-op).
+fn watch_motion(app: &mut App) {
+    #[cfg(feature = "pir")]
+    {
+        let asleep = app.settings.motion.asleep();
+        if app.settings.motion.update(app.pir.motion(), time::uptime_secs()) {
+            info!("motion: {=u32}", app.settings.motion.count());
+        }
+        if asleep && !app.settings.motion.asleep() {
+            debug!("screensaver: off");
+            refresh_display(app);
+        }
+    }
+    #[cfg(not(feature = "pir"))]
+    let _ = app;
+}
+
+/// Follow the ambient light with the backlight brightness (with the `backlight` feature, otherwise
+/// it is a no-op), and set the contrast of the day or night profile (with the `day-night` feature).
+/// Screensaver (with the `pir` feature) turns the backlight off.
+fn adapt_backlight(app: &mut App) {
+    #[cfg(feature = "backlight")]
+    {
+        let light = app.sensor.read_light();
+        #[cfg(feature = "calibration")]
+        let light = app.settings.calibration.update(Channel::Light, i32::from(light)).clamp(0, i32::from(u16::MAX)) as u16;
+        app.settings.backlight.update(light);
+        #[cfg(feature = "pir")]
+        let duty = if app.settings.motion.asleep() { 0 } else { app.settings.backlight.duty() };
+        #[cfg(not(feature = "pir"))]
+        let duty = app.settings.backlight.duty();
+        app.backlight.set_duty(duty);
+        #[cfg(feature = "day-night")]
+        app.backlight.set_contrast(app.settings.schedule.contrast());
+    }
+    #[cfg(not(feature = "backlight"))]
+    let _ = app;
+}
+
+/// Show the DMX512 channels received by the USART1 interrupt (with the `dmx` feature, otherwise it
+/// is a no-op).
+fn monitor_dmx(app: &mut App) {
+    #[cfg(feature = "dmx")]
+    cortex_m::interrupt::free(|cs| app.settings.dmx.update(&DMX_RX.borrow(cs).borrow(), time::millis()));
+    #[cfg(not(feature = "dmx"))]
+    let _ = app;
+}
+
+/// Parse the MIDI bytes received by the USART1 interrupt (with the `midi` feature, otherwise it is
+/// a no-op).
+fn receive_midi(app: &mut App) {
+    #[cfg(feature = "midi")]
+    cortex_m::interrupt::free(|cs| {
+        let mut rx = SERIAL_RX.borrow(cs).borrow_mut();
+        while let Some(byte) = rx.pop_front() {
+            if let Some(Message::NoteOn { channel, note, velocity }) = app.settings.midi.receive(byte) {
+                debug!("midi: note {=u8} {=u8} ch{=u8}", note, velocity, channel + 1);
             }
         }
+    });
+    #[cfg(not(feature = "midi"))]
+    let _ = app;
+}
+
+/// Append the chip temperature to the log and refresh the log screen (with the `temp-log`
+/// feature, otherwise it is a no-op).
+fn log_temperature(app: &mut App) {
+    #[cfg(feature = "temp-log")]
+    {
+        let (area, log, _) = &mut app.temp_log;
+        log.append(area, Record::new(time::uptime_secs(), app.stats.temperature));
+        app.settings.temp_log.update(log, area);
     }
+    #[cfg(not(feature = "temp-log"))]
+    let _ = app;
+}
 
-    fn read_data(&self) -> u8 {
-        self.gpiob.read_pin_range(6, 4) as u8
+/// Run the log commands received over serial and queue their output for the USART1 interrupt
+/// (with the `temp-log` feature, otherwise it is a no-op). Queue holds about 20ms of the output at
+/// 115200 baud, so the task runs often enough to keep the transmission going.
+fn export_log(app: &mut App) {
+    #[cfg(feature = "temp-log")]
+    {
+        let (area, log, export) = &mut app.temp_log;
+        let dumping = export.dumping();
+        cortex_m::interrupt::free(|cs| {
+            let mut rx = SERIAL_RX.borrow(cs).borrow_mut();
+            while let Some(byte) = rx.pop_front() {
+                export.receive(log, byte);
+            }
+        });
+        if export.dumping() && !dumping {
+            info!("log: dump");
+        }
+        cortex_m::interrupt::free(|cs| {
+            let mut tx = SERIAL_TX.borrow(cs).borrow_mut();
+            export.poll(log, area, &mut tx);
+            if !tx.is_empty() {
+                Serial::start_transmit();
+            }
+        });
     }
+    #[cfg(not(feature = "temp-log"))]
+    let _ = app;
 }
 
-fn main() {
-    cortex_m::interrupt::free(
-        |cs| {
-            let syst = SYST.borrow(cs);
-            let rcc = RCC.borrow(cs);
-            let gpiob = GPIOB.borrow(cs);
-            run(&syst, &rcc, &gpiob);
+/// Run the shell commands received over serial and queue their output for the USART1 interrupt
+/// (with the `shell` feature, otherwise it is a no-op).
+fn run_shell(app: &mut App) {
+    #[cfg(feature = "shell")]
+    {
+        let changed = cortex_m::interrupt::free(|cs| {
+            let mut remote = REMOTE.borrow(cs).borrow_mut();
+            let mut rx = SERIAL_RX.borrow(cs).borrow_mut();
+            let mut changed = false;
+            while let Some(byte) = rx.pop_front() {
+                changed |= app.shell.receive(byte, &mut app.settings, &mut app.ui, &app.stats, &mut remote);
+            }
+            changed
+        });
+        if changed {
+            debug!("shell: changed");
+            #[cfg(feature = "battery")]
+            save_settings(app);
+            refresh_display(app);
         }
-    );
+        #[cfg(feature = "access-log")]
+        app.shell.export(&app.events.1, &app.events.0);
+        cortex_m::interrupt::free(|cs| {
+            let mut tx = SERIAL_TX.borrow(cs).borrow_mut();
+            app.shell.poll(&mut tx);
+            if !tx.is_empty() {
+                Serial::start_transmit();
+            }
+        });
+    }
+    #[cfg(not(feature = "shell"))]
+    let _ = app;
 }
 
-fn run(syst: &SYST, rcc: &RCC, gpiob: &GPIOB) {
-    // Used for delays
-    // SysTick is 1/8 AHB (1Mhz with default clock settings)
-    syst.enable_counter();
-    syst.set_reload(0x00ffffff);
+/// Run the shell commands typed in the debugger RTT console and send their output back (with the
+/// `rtt-console` feature, otherwise it is a no-op).
+fn run_rtt_console(app: &mut App) {
+    #[cfg(feature = "rtt-console")]
+    {
+        let mut buf = [0; 16];
+        let mut changed = false;
+        loop {
+            let count = app.rtt.0.read(&mut buf);
+            if count == 0 {
+                break;
+            }
+            let (_, shell) = &mut app.rtt;
+            changed |= cortex_m::interrupt::free(|cs| {
+                let mut remote = REMOTE.borrow(cs).borrow_mut();
+                buf[..count].iter().fold(false, |changed, &byte| {
+                    shell.receive(byte, &mut app.settings, &mut app.ui, &app.stats, &mut remote) | changed
+                })
+            });
+        }
+        if changed {
+            debug!("rtt: changed");
+            #[cfg(feature = "battery")]
+            save_settings(app);
+            refresh_display(app);
+        }
+        let (rtt, shell) = &mut app.rtt;
+        #[cfg(feature = "access-log")]
+        shell.export(&app.events.1, &app.events.0);
+        let sent = rtt.write(shell.pending());
+        shell.consume(sent);
+    }
+    #[cfg(not(feature = "rtt-console"))]
+    let _ = app;
+}
 
-    // Setup GPIOB for LCD (all ports are in output mode)
-    rcc.apb2enr.modify(|_, w| w.iopben().enabled());
+/// Run the timers and beep when the time is up, an alarm rings or Morse code is keyed (runs often
+/// enough for the shortest Morse element, 40ms). Reaction game is redrawn right away when the
+/// display lights up (or goes off, if nobody reacted). Alarm which starts ringing goes to the
+/// access log.
+fn sound_alarm(app: &mut App) {
+    let lit = app.settings.reaction.lit();
+    app.settings.advance(time::millis());
+    app.buzzer.set(app.settings.buzzing());
+    if app.settings.reaction.lit() != lit {
+        refresh_display(app);
+    }
+    #[cfg(feature = "access-log")]
+    {
+        let ringing = app.settings.alarm_clock.ringing();
+        if let Some(alarm) = ringing.filter(|_| app.alarm_ringing.is_none()) {
+            record_event(app, Kind::AlarmRang, alarm as u16);
+        }
+        app.alarm_ringing = ringing;
+    }
+}
 
-    for i in 0..4 {
-        gpiob.pin_config(DATA + i).push_pull().output2();
+/// Add the event to the access log, at the current runtime (with the `access-log` feature).
+#[cfg(feature = "access-log")]
+fn record_event(app: &mut App, kind: Kind, detail: u16) {
+    let runtime_s = app.boot_stats.runtime_secs(time::uptime_secs());
+    info!("events: {=str} {=u16}", kind.name(), detail);
+    let (area, log) = &mut app.events;
+    log.append(area, Entry { runtime_s, kind, detail });
+    app.settings.events.update(log, area);
+}
+
+fn blink_error(app: &mut App) {
+    if let Some(led) = app.error_led.as_mut() {
+        led.tick();
+    }
+}
+
+/// Re-send the whole screen on the next refresh.
+fn full_redraw(_app: &mut App) {
+    DISPLAY.lock(|display| display.invalidate());
+}
+
+/// Re-initialize display if its busy flag is stuck (display can latch garbage after ESD, for
+/// example). Busy flag can only be read with the `input` feature, otherwise it is a no-op.
+fn check_display(app: &mut App) {
+    #[cfg(feature = "input")]
+    {
+        let Some((mut engine, timer)) = take_engine() else {
+            return;
+        };
+        let stuck = !engine.hardware().wait_ready_timeout(BUSY_TIMEOUT_US);
+        if stuck {
+            error!("lcd: busy flag is stuck, re-initializing");
+            notify!(error, "Display reset");
+            app.stats.display_resets = app.stats.display_resets.saturating_add(1);
+            let (hw, consumer) = engine.into_parts();
+            let mut display = Display::new(hw);
+            screens::init(&mut display);
+            engine = Engine::new(display.unwrap(), consumer);
+        }
+        put_engine((engine, timer));
+        if stuck {
+            // Display memory is cleared by the initialization
+            DISPLAY.lock(|display| display.invalidate());
+            refresh_display(app);
+        }
     }
+    #[cfg(not(feature = "input"))]
+    let _ = app;
+}
 
-    gpiob.pin_config(RS).push_pull().output2();
-    gpiob.pin_config(RW).push_pull().output2();
-    gpiob.pin_config(E).push_pull().output2();
+/// Read the display memory back once the text flushed is sent, and re-send the cells which did not
+/// make it, counting them (`read-back` feature, otherwise it is a no-op).
+fn verify_display(app: &mut App) {
+    #[cfg(feature = "read-back")]
+    {
+        let Some((mut engine, timer)) = take_engine() else {
+            return;
+        };
+        // Text is only in the display memory once the queue is drained
+        let started = engine.is_idle() && DISPLAY.lock(|display| display.begin_verify()).unwrap_or(false);
+        let mut read = [[b' '; COLUMNS]; ROWS];
+        let done = started && engine.hardware().read_text(&mut read);
+        put_engine((engine, timer));
+        // Display not responding is left to the health check
+        if !done {
+            return;
+        }
+        let errors = DISPLAY.lock(|display| display.verify(&read)).unwrap_or(0);
+        if errors > 0 {
+            error!("lcd: {=usize} cells read back wrong, re-sending", errors);
+            app.stats.readback_errors = app.stats.readback_errors.saturating_add(errors as u16);
+            refresh_display(app);
+        }
+    }
+    #[cfg(not(feature = "read-back"))]
+    let _ = app;
+}
 
-    gpiob.write_pin(RS, false);
-    gpiob.write_pin(RW, false);
-    gpiob.write_pin(E, false);
+/// Take the engine out, so the interrupt does not step it while the display is accessed directly.
+/// Only taken when the timer is idle: otherwise, interrupt would fire with no engine to clear it.
+#[cfg(feature = "input")]
+fn take_engine() -> Option<LcdEngine> {
+    cortex_m::interrupt::free(|cs| {
+        let mut engine = ENGINE.borrow(cs).borrow_mut();
+        if engine.as_ref().is_none_or(|(_, timer)| timer.is_running()) {
+            return None;
+        }
+        let (engine, mut timer) = engine.take()?;
+        timer.clear_interrupt();
+        NVIC::unpend(Interrupt::TIM2);
+        Some((engine, timer))
+    })
+}
 
-    // Init display
-    let mut display = Display::new(LcdHardware { syst, gpiob });
-    display.init(FunctionLine::Line2, FunctionDots::Dots5x8);
-    display.display(DisplayMode::DisplayOn, DisplayCursor::CursorOff, DisplayBlink::BlinkOff);
+/// Put the engine back, and let it send whatever was queued meanwhile.
+#[cfg(feature = "input")]
+fn put_engine(engine: LcdEngine) {
+    cortex_m::interrupt::free(|cs| ENGINE.borrow(cs).replace(Some(engine)));
+    kick_engine();
+}
 
-    // Print in loop
+fn run<I: Idle>(mut app: App, mut power: Power<I>) -> ! {
+    let mut scheduler = Scheduler::new(TASKS, time::millis());
+    let mut loop_stats = LoopStats::new(time::millis());
     loop {
-        display.position(0, 0);
-        write!(&mut display, "Hello!").unwrap();
-        delay_us(syst, 500_000);
+        let start = DWT::cycle_count();
+        scheduler.run_pending(&mut app, time::millis());
+        let busy_us = delay::since_us(start);
+        let deadline = scheduler.next_deadline(time::millis());
+        // Tasks due within the same tick are run together
+        #[cfg(feature = "battery")]
+        let deadline = battery::align(deadline);
+        power.sleep_until(deadline);
+        loop_stats.record(time::millis(), busy_us);
+        app.stats.duty_cycle = power.duty_cycle();
+        app.stats.loop_rate = loop_stats.rate();
+        app.stats.loop_max_us = loop_stats.max_us();
+    }
+}
+
+/// Run the engine if it is idle.
+fn kick_engine() {
+    NVIC::pend(Interrupt::TIM2);
+}
+
+/// Writer can wait for the queue space only if the engine interrupt can preempt us.
+fn can_wait() -> bool {
+    primask::read().is_inactive() && SCB::vect_active() == VectActive::ThreadMode
+}
 
-        display.position(0, 0);
-        write!(&mut display, "Bye!  ").unwrap();
-        delay_us(syst, 500_000);
+#[cfg(feature = "i2c-slave")]
+fn i2c_slave_interrupt() {
+    cortex_m::interrupt::free(|cs| {
+        let Some(event) = I2C_SLAVE.borrow(cs).borrow_mut().as_mut().and_then(|slave| slave.poll()) else {
+            return;
+        };
+        let mut remote = REMOTE.borrow(cs).borrow_mut();
+        match event {
+            SlaveEvent::Start => remote.start(),
+            SlaveEvent::Byte(byte) => remote.receive(byte),
+            SlaveEvent::Stop => {}
+        }
+    });
+}
+
+#[cfg(feature = "i2c-slave")]
+#[interrupt]
+fn I2C2_EV() {
+    i2c_slave_interrupt();
+}
+
+#[cfg(feature = "i2c-slave")]
+#[interrupt]
+fn I2C2_ER() {
+    i2c_slave_interrupt();
+}
+
+#[cfg(any(feature = "modbus", feature = "lcdproc", feature = "temp-log", feature = "midi", feature = "terminal", feature = "shell", feature = "link"))]
+#[interrupt]
+fn USART1() {
+    if let Some(byte) = Serial::receive() {
+        #[cfg(feature = "temp-log")]
+        if byte == logger::XOFF || byte == logger::XON {
+            SERIAL_TX_PAUSED.store(byte == logger::XOFF, Ordering::Relaxed);
+            // Transmitter empty interrupt stops right away if paused (or if there is nothing to send)
+            Serial::start_transmit();
+            return;
+        }
+        cortex_m::interrupt::free(|cs| SERIAL_RX.borrow(cs).borrow_mut().push_back(byte).ok());
+        SERIAL_RX_MS.store(time::millis(), Ordering::Relaxed);
     }
+    #[cfg(any(feature = "temp-log", feature = "shell"))]
+    Serial::transmit(|| {
+        #[cfg(feature = "temp-log")]
+        if SERIAL_TX_PAUSED.load(Ordering::Relaxed) {
+            return None;
+        }
+        cortex_m::interrupt::free(|cs| SERIAL_TX.borrow(cs).borrow_mut().pop_front())
+    });
+}
+
+#[cfg(feature = "dmx")]
+#[interrupt]
+fn USART1() {
+    if let Some((byte, framing_error)) = Serial::receive_framed() {
+        cortex_m::interrupt::free(|cs| DMX_RX.borrow(cs).borrow_mut().receive(byte, framing_error));
+    }
+}
+
+#[cfg(feature = "fan")]
+#[interrupt]
+fn TIM3() {
+    fan_timer::interrupt();
+}
+
+// Pulse input is on line 2 (PA2) or, on Nucleo-F103RB, line 1 (PC1)
+#[cfg(any(feature = "tachometer", feature = "totalizer"))]
+#[interrupt]
+fn EXTI1() {
+    pulse_input::interrupt();
+}
+
+#[cfg(any(feature = "tachometer", feature = "totalizer"))]
+#[interrupt]
+fn EXTI2() {
+    pulse_input::interrupt();
+}
+
+// Pin monitor takes lines 1-3 and 5-7 (the last three share an interrupt)
+#[cfg(feature = "pin-monitor")]
+#[interrupt]
+fn EXTI1() {
+    edges::interrupt();
+}
+
+#[cfg(feature = "pin-monitor")]
+#[interrupt]
+fn EXTI2() {
+    edges::interrupt();
+}
+
+#[cfg(feature = "pin-monitor")]
+#[interrupt]
+fn EXTI3() {
+    edges::interrupt();
+}
+
+#[cfg(feature = "pin-monitor")]
+#[interrupt]
+fn EXTI9_5() {
+    edges::interrupt();
+}
+
+#[interrupt]
+fn TIM2() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some((engine, timer)) = ENGINE.borrow(cs).borrow_mut().as_mut() {
+            timer.clear_interrupt();
+            // Kicked while waiting for the previous command to complete
+            if timer.is_running() {
+                return;
+            }
+            if let Some(us) = engine.step() {
+                timer.start_us(us);
+            }
+        }
+    });
+}
+
+#[exception]
+fn SysTick() {
+    time::tick();
 }
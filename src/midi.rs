@@ -0,0 +1,224 @@
+//! MIDI note display: messages received on USART1 (see `serial`) from a MIDI IN port, the last
+//! note played and the activity on each channel.
+//!
+//! MIDI is 31.25 kbaud, 8 data bits and 1 stop bit. Messages are a status byte (the high bit set,
+//! message type and channel) and one or two data bytes; the status byte may be left out when it
+//! is the same as the last one (running status). Real-time bytes (clock, active sensing) can come
+//! between any bytes and are skipped, system messages (SysEx) are skipped and cancel the running
+//! status.
+//!
+//! Top row shows the last note-on, with its velocity and channel; bottom row is the activity meter
+//! of the 16 channels, one column each: a bar as high as the velocity of the note played (lowest
+//! one for the other messages), falling by one level every `DECAY_MS`.
+//!
+//! Like the stopwatch, time is passed to `advance`, which must be called before every render.
+
+use core::fmt::{self, Write};
+use crate::text;
+
+/// Baud rate of MIDI.
+pub const BAUD: u32 = 31_250;
+/// MIDI channels (shown as 1 to 16).
+pub const CHANNELS: usize = 16;
+/// Bar heights (bars are the trend glyphs, see `trend::GLYPHS`).
+const LEVELS: u8 = 7;
+/// Activity bar falls by one level after that long, in milliseconds.
+const DECAY_MS: u32 = 100;
+/// Note names, from C.
+const NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    /// Note-on with a non-zero velocity.
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    /// Note-off, or note-on with zero velocity.
+    NoteOff { channel: u8, note: u8 },
+    /// Any other channel message (control change, program change, pitch bend, ...).
+    Channel { channel: u8 },
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Parser {
+    /// Running status, `None` until a channel message status (and after system messages).
+    status: Option<u8>,
+    data: [u8; 2],
+    /// Data bytes received for the status.
+    received: usize,
+}
+
+impl Parser {
+    /// Account the received byte. Returns the message once its last data byte is received.
+    pub fn receive(&mut self, byte: u8) -> Option<Message> {
+        match byte {
+            // Real-time bytes do not interrupt the message
+            0xf8..=0xff => None,
+            0x80..=0xf7 => {
+                self.status = (byte < 0xf0).then_some(byte);
+                self.received = 0;
+                None
+            }
+            _ => {
+                let status = self.status?;
+                self.data[self.received] = byte;
+                self.received += 1;
+                // Program change and channel pressure have one data byte, the others two
+                let length = if matches!(status & 0xf0, 0xc0 | 0xd0) { 1 } else { 2 };
+                if self.received < length {
+                    return None;
+                }
+                self.received = 0;
+                let channel = status & 0x0f;
+                Some(match (status & 0xf0, self.data) {
+                    (0x90, [note, velocity]) if velocity > 0 => Message::NoteOn { channel, note, velocity },
+                    (0x80 | 0x90, [note, _]) => Message::NoteOff { channel, note },
+                    _ => Message::Channel { channel },
+                })
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Midi {
+    parser: Parser,
+    /// Last note-on: channel, note and velocity.
+    last: Option<(u8, u8, u8)>,
+    /// Activity bar height of each channel.
+    levels: [u8; CHANNELS],
+    /// Time since the bars last fell, in milliseconds.
+    decay_ms: u32,
+    /// Timer value of the last `advance`.
+    last_ms: u32,
+}
+
+impl Midi {
+    /// Account the time passed since the last call (`now_ms` is the millisecond timer, which can
+    /// wrap around): activity bars fall.
+    pub fn advance(&mut self, now_ms: u32) {
+        let elapsed_ms = now_ms.wrapping_sub(self.last_ms);
+        self.last_ms = now_ms;
+        self.decay_ms = self.decay_ms.saturating_add(elapsed_ms);
+        let steps = self.decay_ms / DECAY_MS;
+        self.decay_ms %= DECAY_MS;
+        let steps = steps.min(u32::from(LEVELS)) as u8;
+        for level in self.levels.iter_mut() {
+            *level = level.saturating_sub(steps);
+        }
+    }
+
+    /// Account the received byte. Returns the message, once it is complete.
+    pub fn receive(&mut self, byte: u8) -> Option<Message> {
+        let message = self.parser.receive(byte)?;
+        let (channel, level) = match message {
+            Message::NoteOn { channel, note, velocity } => {
+                self.last = Some((channel, note, velocity));
+                (channel, (u32::from(velocity) * u32::from(LEVELS)).div_ceil(127) as u8)
+            }
+            Message::NoteOff { channel, .. } | Message::Channel { channel } => (channel, 1),
+        };
+        let bar = &mut self.levels[usize::from(channel)];
+        *bar = (*bar).max(level);
+        Some(message)
+    }
+
+    /// Last note-on: channel (0 to 15), note and velocity, `None` if there were none.
+    pub fn last(&self) -> Option<(u8, u8, u8)> {
+        self.last
+    }
+
+    /// Activity bar height of the `channel` (0 to 15).
+    pub fn level(&self, channel: usize) -> u8 {
+        self.levels[channel]
+    }
+
+    /// Write the last note-on: note, velocity and channel (`C#4  v100  ch10`).
+    pub fn write_note<W: Write>(&self, w: &mut W) -> fmt::Result {
+        let Some((channel, note, velocity)) = self.last else {
+            return w.write_str("no notes");
+        };
+        write_name(w, note)?;
+        w.write_str(" v")?;
+        text::uint(w, u32::from(velocity), 3)?;
+        w.write_str("  ch")?;
+        text::uint(w, u32::from(channel) + 1, 2)
+    }
+
+    /// Write the activity bars of the channels, one column each.
+    pub fn write_activity<W: Write>(&self, w: &mut W) -> fmt::Result {
+        self.levels
+            .iter()
+            .try_for_each(|&level| w.write_char(if level == 0 { ' ' } else { char::from(level) }))
+    }
+}
+
+/// Write the note name with the octave (middle C, note 60, is `C4`), padded to 4 characters.
+fn write_name<W: Write>(w: &mut W, note: u8) -> fmt::Result {
+    let name = NAMES[usize::from(note % 12)];
+    w.write_str(name)?;
+    let octave = note / 12;
+    if octave == 0 {
+        w.write_str("-1")?;
+    } else {
+        text::uint(w, u32::from(octave - 1), 1)?;
+    }
+    let width = name.len() + if octave == 0 { 2 } else { 1 };
+    (width..4).try_for_each(|_| w.write_char(' '))
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn midi_notes() {
+        use crate::midi::{Message, Parser};
+        use crate::mock;
+        use crate::screens::{self, Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        let mut parser = Parser::default();
+        let mut parse = |bytes: &[u8]| bytes.iter().filter_map(|&byte| parser.receive(byte)).collect::<std::vec::Vec<_>>();
+        // Data bytes without the status are skipped
+        assert_eq!(parse(&[0x3c, 0x40]), []);
+        // Running status, note-on with zero velocity is the note-off, real-time bytes in between
+        assert_eq!(
+            parse(&[0x99, 0x24, 0xf8, 0x64, 0x26, 0x50, 0x24, 0x00]),
+            [
+                Message::NoteOn { channel: 9, note: 0x24, velocity: 0x64 },
+                Message::NoteOn { channel: 9, note: 0x26, velocity: 0x50 },
+                Message::NoteOff { channel: 9, note: 0x24 },
+            ]
+        );
+        // Program change has one data byte, SysEx cancels the running status
+        assert_eq!(parse(&[0xc0, 0x05, 0x06]), [Message::Channel { channel: 0 }, Message::Channel { channel: 0 }]);
+        assert_eq!(parse(&[0xf0, 0x7e, 0x7f, 0xf7, 0x3c, 0x40]), []);
+        assert_eq!(parse(&[0x81, 0x3c, 0x40]), [Message::NoteOff { channel: 1, note: 0x3c }]);
+
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::Midi, &Stats::default(), settings);
+        assert_eq!(render(&settings), ("no notes        ".into(), " ".repeat(16)));
+
+        // Middle C at full velocity on channel 1, C#-1 softly on channel 10, control change on 16
+        for byte in [0x90, 60, 127, 0x99, 1, 20, 0xbf, 7, 100] {
+            settings.midi.receive(byte);
+        }
+        assert_eq!(settings.midi.last(), Some((9, 1, 20)));
+        assert_eq!(render(&settings), ("C#-1 v 20  ch10 ".into(), "#        #     #".into()));
+        assert_eq!((settings.midi.level(0), settings.midi.level(9), settings.midi.level(15)), (7, 2, 1));
+        settings.midi.receive(0x90);
+        settings.midi.receive(60);
+        settings.midi.receive(100);
+        assert_eq!(render(&settings).0, "C4   v100  ch 1 ");
+
+        // Bars fall over time
+        settings.advance(150);
+        assert_eq!((settings.midi.level(0), settings.midi.level(9), settings.midi.level(15)), (6, 1, 0));
+        settings.advance(200);
+        assert_eq!((settings.midi.level(0), settings.midi.level(9)), (5, 0));
+        settings.advance(10_000);
+        assert_eq!(render(&settings).1, " ".repeat(16));
+        assert_eq!(render(&settings).0, "C4   v100  ch 1 ");
+
+        let mut ui = screens::navigation();
+        ui.handle(Event::Serial(b'$'), &settings);
+        assert_eq!(ui.state(), Screen::Midi);
+    }
+}
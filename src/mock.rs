@@ -0,0 +1,334 @@
+//! Host-side mock of the GPIO port, recording everything the LCD would receive.
+//!
+//! Used to verify the exact HD44780 command stream (including the nibble ordering and the pin
+//! mapping done by `LcdHardware`) without the real hardware. Reads are answered by the simulated
+//! controller (see `sim`), which is fed with the same transfers.
+//!
+//! Fakes of the other peripherals shared by the tests are here, too: flash pages (`MockFlash`) and
+//! I2C bus (`MockI2c`).
+
+use std::cell::{Cell, RefCell};
+use std::string::String;
+use std::vec::Vec;
+use lcd::Display;
+use crate::bus::I2c;
+use crate::port::Port;
+use crate::screens::{self, Screen, Settings, Stats};
+use crate::hardware::{LcdHardware, DATA, E, RS, RW};
+use crate::setup::Setup;
+use crate::sim::Hd44780;
+
+/// Single transfer latched by the LCD. LCD latches RS and data lines on the falling edge of E.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Transfer {
+    pub rs: bool,
+    pub data: u8,
+}
+
+/// Pin operation recorded by `MockHardware`, for checking the order they are done in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+    /// Pin is configured as push-pull output.
+    Output(usize),
+    /// Pin is configured as open-drain output.
+    OpenDrain(usize),
+    /// Pin is configured as floating input.
+    Input(usize),
+    /// R/W is set to the level.
+    Rw(bool),
+    /// Delay, in microseconds.
+    Delay(u32),
+}
+
+/// Mock GPIO port (also provides delays) recording all transfers latched by the LCD (reads, with
+/// R/W high, are not recorded), and the pin operations. Pins configured as inputs read low, unless
+/// the LCD drives them.
+#[derive(Default)]
+pub struct MockHardware {
+    output: Cell<u16>,
+    transfers: RefCell<Vec<Transfer>>,
+    ops: RefCell<Vec<Op>>,
+    /// Pins configured as inputs.
+    inputs: Cell<u16>,
+    elapsed: Cell<u32>,
+    lcd: RefCell<Hd44780>,
+    /// Nibble the LCD drives on the data lines while E is high during a read.
+    driven: Cell<Option<u8>>,
+}
+
+impl MockHardware {
+    pub fn new() -> MockHardware {
+        MockHardware::default()
+    }
+
+    /// Current state of the output pins.
+    pub fn output(&self) -> u16 {
+        self.output.get()
+    }
+
+    /// Transfers latched so far (4 bits each in 4-bit mode).
+    pub fn transfers(&self) -> Vec<Transfer> {
+        self.transfers.borrow().clone()
+    }
+
+    /// Pin operations done so far.
+    pub fn ops(&self) -> Vec<Op> {
+        self.ops.borrow().clone()
+    }
+
+    /// Total amount of microseconds spent in delays so far.
+    pub fn elapsed_us(&self) -> u32 {
+        self.elapsed.get()
+    }
+
+    /// Forget all transfers, pin operations and delays recorded so far.
+    pub fn reset(&self) {
+        self.transfers.borrow_mut().clear();
+        self.ops.borrow_mut().clear();
+        self.elapsed.set(0);
+    }
+}
+
+/// Instruction byte, as joined by `join_nibbles`.
+pub fn command(data: u8) -> Transfer {
+    Transfer { rs: false, data }
+}
+
+/// Data byte, as joined by `join_nibbles`.
+pub fn data(data: u8) -> Transfer {
+    Transfer { rs: true, data }
+}
+
+/// Initialize the display the way the firmware does, and turn it on.
+pub fn init(mock: &MockHardware) {
+    let mut display = Display::new(LcdHardware::new(mock, mock));
+    Setup::new().init(&mut display);
+    Setup::new().on(&mut display);
+}
+
+/// Render the `screen` on a freshly initialized display, and return the LCD showing it.
+pub fn render_lcd(screen: Screen, stats: &Stats, settings: &Settings) -> Hd44780 {
+    let mock = MockHardware::new();
+    let mut display = Display::new(LcdHardware::new(&mock, &mock));
+    screens::init(&mut display);
+    screen.render(&mut display, stats, settings).unwrap();
+    let mut lcd = Hd44780::new();
+    lcd.feed(&mock.transfers());
+    lcd
+}
+
+/// Render the `screen` on a freshly initialized display, and return both rows as the LCD shows
+/// them.
+pub fn render(screen: Screen, stats: &Stats, settings: &Settings) -> (String, String) {
+    let lcd = render_lcd(screen, stats, settings);
+    (lcd.row(0, 16), lcd.row(1, 16))
+}
+
+/// Join pairs of 4-bit transfers into bytes (high nibble goes first).
+pub fn join_nibbles(transfers: &[Transfer]) -> Vec<Transfer> {
+    assert!(transfers.len().is_multiple_of(2), "odd number of nibbles");
+    transfers
+        .chunks(2)
+        .map(|pair| {
+            assert_eq!(pair[0].rs, pair[1].rs, "RS changed in the middle of the byte");
+            Transfer {
+                rs: pair[0].rs,
+                data: (pair[0].data << 4) | pair[1].data,
+            }
+        })
+        .collect()
+}
+
+impl Port for MockHardware {
+    fn write_pin_range(&self, offset: usize, count: usize, data: u16) {
+        let mask = (((1u32 << count) - 1) << offset) as u16;
+        let prev = self.output.get();
+        let next = (prev & !mask) | ((data << offset) & mask);
+        self.output.set(next);
+        if mask & (1 << RW) != 0 {
+            self.ops.borrow_mut().push(Op::Rw(next & (1 << RW) != 0));
+        }
+
+        let rs = next & (1 << RS) != 0;
+        let read = next & (1 << RW) != 0;
+        if prev & (1 << E) == 0 && next & (1 << E) != 0 && read {
+            self.driven.set(Some(self.lcd.borrow_mut().read(rs)));
+        }
+        if prev & (1 << E) != 0 && next & (1 << E) == 0 {
+            if read {
+                self.driven.set(None);
+            } else {
+                let transfer = Transfer {
+                    rs,
+                    data: ((next >> DATA) & 0xf) as u8,
+                };
+                self.transfers.borrow_mut().push(transfer);
+                self.lcd.borrow_mut().feed(&[transfer]);
+            }
+        }
+    }
+
+    fn read_pin_range(&self, offset: usize, count: usize) -> u16 {
+        let mask = ((1u32 << count) - 1) as u16;
+        let inputs = self.inputs.get();
+        let mut pins = self.output.get() & !inputs;
+        if let Some(nibble) = self.driven.get() {
+            pins = (pins & !(0xf << DATA)) | (u16::from(nibble) << DATA);
+        }
+        (pins >> offset) & mask
+    }
+
+    fn output(&self, pin: usize) {
+        self.inputs.set(self.inputs.get() & !(1 << pin));
+        self.ops.borrow_mut().push(Op::Output(pin));
+    }
+
+    fn open_drain(&self, pin: usize) {
+        self.inputs.set(self.inputs.get() & !(1 << pin));
+        self.ops.borrow_mut().push(Op::OpenDrain(pin));
+    }
+
+    fn input(&self, pin: usize) {
+        self.inputs.set(self.inputs.get() | (1 << pin));
+        self.ops.borrow_mut().push(Op::Input(pin));
+    }
+}
+
+impl lcd::Delay for &MockHardware {
+    fn delay_us(&mut self, delay_usec: u32) {
+        self.ops.borrow_mut().push(Op::Delay(delay_usec));
+        self.elapsed.set(self.elapsed.get() + delay_usec);
+    }
+}
+
+/// Time is the total of the delays.
+#[cfg(feature = "bench")]
+impl crate::bench::Clock for MockHardware {
+    fn now(&self) -> u32 {
+        self.elapsed.get()
+    }
+
+    fn since_us(&self, start: u32) -> u32 {
+        self.elapsed.get().wrapping_sub(start)
+    }
+}
+
+/// Flash area of `P` pages, `W` half-words each, counting erases by page. Programming a half-word
+/// which is not erased panics (flash could only clear bits, and is never expected to).
+pub struct MockFlash<const P: usize, const W: usize> {
+    pub pages: [[u16; W]; P],
+    pub erases: [usize; P],
+}
+
+impl<const P: usize, const W: usize> MockFlash<P, W> {
+    /// All pages erased.
+    pub fn new() -> MockFlash<P, W> {
+        MockFlash {
+            pages: [[0xffff; W]; P],
+            erases: [0; P],
+        }
+    }
+}
+
+impl<const P: usize, const W: usize> Default for MockFlash<P, W> {
+    fn default() -> MockFlash<P, W> {
+        MockFlash::new()
+    }
+}
+
+/// Implement the flash area traits of the modules keeping data in flash (they are all the same).
+macro_rules! flash_area {
+    ($($(#[$cfg:meta])* $Area:path),+) => {
+        $(
+            $(#[$cfg])*
+            impl<const P: usize, const W: usize> $Area for MockFlash<P, W> {
+                fn read(&self, page: usize, index: usize) -> u16 {
+                    self.pages[page][index]
+                }
+
+                fn program(&mut self, page: usize, index: usize, value: u16) {
+                    assert_eq!(self.pages[page][index], 0xffff, "programmed twice");
+                    self.pages[page][index] = value;
+                }
+
+                fn erase(&mut self, page: usize) {
+                    self.pages[page] = [0xffff; W];
+                    self.erases[page] += 1;
+                }
+            }
+        )+
+    };
+}
+
+flash_area!(
+    crate::kv::StoreArea,
+    #[cfg(feature = "temp-log")]
+    crate::logger::RecordArea,
+    #[cfg(feature = "access-log")]
+    crate::access_log::EventArea
+);
+
+/// I2C device on `MockI2c`.
+pub trait MockDevice {
+    /// Take `bytes` written to the device. Returns `false` if they are not acknowledged.
+    fn write(&mut self, bytes: &[u8]) -> bool {
+        let _ = bytes;
+        true
+    }
+
+    /// Fill `buffer` with the answer to `bytes` (register address, usually). Returns `false` if
+    /// the device does not answer.
+    fn read(&mut self, bytes: &[u8], buffer: &mut [u8]) -> bool;
+}
+
+/// Device taking all writes, answering nothing.
+impl MockDevice for () {
+    fn read(&mut self, _bytes: &[u8], _buffer: &mut [u8]) -> bool {
+        false
+    }
+}
+
+/// Device answering reads with a closure.
+impl<F: FnMut(&[u8], &mut [u8]) -> bool> MockDevice for F {
+    fn read(&mut self, bytes: &[u8], buffer: &mut [u8]) -> bool {
+        self(bytes, buffer)
+    }
+}
+
+/// I2C bus: devices at the `present` addresses acknowledge, `device` answers them all. Transactions
+/// acknowledged are recorded (address and bytes written, including the ones before a read).
+pub struct MockI2c<D: MockDevice> {
+    pub present: Vec<u8>,
+    pub device: D,
+    pub transactions: Vec<(u8, Vec<u8>)>,
+}
+
+impl<D: MockDevice> MockI2c<D> {
+    pub fn new(present: &[u8], device: D) -> MockI2c<D> {
+        MockI2c {
+            present: present.to_vec(),
+            device,
+            transactions: Vec::new(),
+        }
+    }
+}
+
+impl<D: MockDevice> I2c for MockI2c<D> {
+    type Error = ();
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), ()> {
+        if !self.present.contains(&address) || !self.device.write(bytes) {
+            return Err(());
+        }
+        self.transactions.push((address, bytes.to_vec()));
+        Ok(())
+    }
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), ()> {
+        if !self.present.contains(&address) || !self.device.read(bytes, buffer) {
+            return Err(());
+        }
+        self.transactions.push((address, bytes.to_vec()));
+        Ok(())
+    }
+}
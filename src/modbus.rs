@@ -0,0 +1,216 @@
+//! Modbus RTU slave, so the board could be used as an industrial status display (over RS-485, see
+//! `serial`). Written text replaces the demo screens, same as the text pushed over I2C or SPI (see
+//! `remote`).
+//!
+//! Holding registers:
+//!
+//! | Register | Contents                                                     |
+//! |----------|--------------------------------------------------------------|
+//! | 0-7      | first row, two characters per register (high byte goes first) |
+//! | 8-15     | second row                                                   |
+//! | 16       | backlight, `0` is off                                        |
+//! | 17       | contrast, 0-100                                              |
+//!
+//! Board has no backlight or contrast control, these registers are only stored. Supported
+//! functions are 3 (read holding registers), 6 (write single register) and 16 (write multiple
+//! registers).
+
+use heapless::Vec;
+use crate::display::{COLUMNS, ROWS};
+use crate::remote::RemoteText;
+
+/// Maximum size of RTU frame.
+pub const MAX_FRAME: usize = 256;
+
+pub type Frame = Vec<u8, MAX_FRAME>;
+
+const READ_HOLDING: u8 = 3;
+const WRITE_SINGLE: u8 = 6;
+const WRITE_MULTIPLE: u8 = 16;
+
+const ILLEGAL_FUNCTION: u8 = 1;
+const ILLEGAL_ADDRESS: u8 = 2;
+const ILLEGAL_VALUE: u8 = 3;
+
+/// Registers per row.
+const ROW_REGISTERS: u16 = (COLUMNS / 2) as u16;
+const REG_BACKLIGHT: u16 = ROW_REGISTERS * ROWS as u16;
+const REG_CONTRAST: u16 = REG_BACKLIGHT + 1;
+const REGISTERS: u16 = REG_CONTRAST + 1;
+
+/// Modbus CRC (polynomial 0xa001, reflected), sent low byte first.
+pub fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0xffff, |crc, &byte| {
+        (0..8).fold(crc ^ u16::from(byte), |crc, _| {
+            if crc & 1 != 0 { (crc >> 1) ^ 0xa001 } else { crc >> 1 }
+        })
+    })
+}
+
+pub struct ModbusSlave {
+    address: u8,
+    contrast: u16,
+}
+
+impl ModbusSlave {
+    pub fn new(address: u8) -> ModbusSlave {
+        ModbusSlave { address, contrast: 50 }
+    }
+
+    /// Handle request `frame` (complete, with CRC). Returns the response to send, if any (there
+    /// is no response to broadcasts and to the frames which are broken or addressed to others).
+    pub fn handle(&mut self, frame: &[u8], text: &mut RemoteText) -> Option<Frame> {
+        if frame.len() < 4 {
+            return None;
+        }
+        let (payload, crc) = frame.split_at(frame.len() - 2);
+        if crc16(payload) != u16::from_le_bytes([crc[0], crc[1]]) {
+            return None;
+        }
+        let (address, function, body) = (payload[0], payload[1], &payload[2..]);
+        if address != self.address && address != 0 {
+            return None;
+        }
+
+        let mut response = Frame::new();
+        response.extend_from_slice(&[address, function]).ok()?;
+        if let Err(code) = self.execute(function, body, text, &mut response) {
+            response.truncate(1);
+            response.extend_from_slice(&[function | 0x80, code]).ok()?;
+        }
+        if address == 0 {
+            return None;
+        }
+        let crc = crc16(&response);
+        response.extend_from_slice(&crc.to_le_bytes()).ok()?;
+        Some(response)
+    }
+
+    /// Execute the function, response goes after the address and function code.
+    fn execute(&mut self, function: u8, body: &[u8], text: &mut RemoteText, response: &mut Frame) -> Result<(), u8> {
+        let word = |index: usize| {
+            body.get(index * 2..index * 2 + 2)
+                .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+                .ok_or(ILLEGAL_VALUE)
+        };
+        match function {
+            READ_HOLDING => {
+                let (start, count) = (word(0)?, word(1)?);
+                check_range(start, count, 125)?;
+                response.push((count * 2) as u8).map_err(|_| ILLEGAL_VALUE)?;
+                for register in start..start + count {
+                    let value = self.read(register, text);
+                    response.extend_from_slice(&value.to_be_bytes()).map_err(|_| ILLEGAL_VALUE)?;
+                }
+            }
+            WRITE_SINGLE => {
+                let (register, value) = (word(0)?, word(1)?);
+                check_range(register, 1, 1)?;
+                self.write(register, value, text);
+                response.extend_from_slice(&body[..4]).map_err(|_| ILLEGAL_VALUE)?;
+            }
+            WRITE_MULTIPLE => {
+                let (start, count) = (word(0)?, word(1)?);
+                check_range(start, count, 123)?;
+                if body.get(4).map(|&len| usize::from(len)) != Some(usize::from(count) * 2)
+                    || body.len() != 5 + usize::from(count) * 2
+                {
+                    return Err(ILLEGAL_VALUE);
+                }
+                for (i, register) in (start..start + count).enumerate() {
+                    let value = u16::from_be_bytes([body[5 + i * 2], body[6 + i * 2]]);
+                    self.write(register, value, text);
+                }
+                response.extend_from_slice(&body[..4]).map_err(|_| ILLEGAL_VALUE)?;
+            }
+            _ => return Err(ILLEGAL_FUNCTION),
+        }
+        Ok(())
+    }
+
+    fn read(&self, register: u16, text: &RemoteText) -> u16 {
+        match register {
+            REG_BACKLIGHT => u16::from(text.backlight()),
+            REG_CONTRAST => self.contrast,
+            _ => {
+                let row = text.row(usize::from(register / ROW_REGISTERS)).as_bytes();
+                let col = usize::from(register % ROW_REGISTERS) * 2;
+                u16::from_be_bytes([row[col], row[col + 1]])
+            }
+        }
+    }
+
+    fn write(&mut self, register: u16, value: u16, text: &mut RemoteText) {
+        match register {
+            REG_BACKLIGHT => text.set_backlight(value != 0),
+            REG_CONTRAST => self.contrast = value.min(100),
+            _ => {
+                let row = (register / ROW_REGISTERS) as u8;
+                let col = (register % ROW_REGISTERS) as u8 * 2;
+                text.set_cursor(row * 0x40 + col);
+                let [high, low] = value.to_be_bytes();
+                text.write(high);
+                text.write(low);
+            }
+        }
+    }
+}
+
+/// Check that `count` registers starting from `start` exist, and that `count` is within the
+/// protocol limit `max`.
+fn check_range(start: u16, count: u16, max: u16) -> Result<(), u8> {
+    if count == 0 || count > max {
+        return Err(ILLEGAL_VALUE);
+    }
+    if u32::from(start) + u32::from(count) > u32::from(REGISTERS) {
+        return Err(ILLEGAL_ADDRESS);
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn modbus_holding_registers() {
+        use std::vec::Vec;
+        use crate::modbus::{crc16, ModbusSlave};
+        use crate::remote::RemoteText;
+
+        fn request(bytes: &[u8]) -> Vec<u8> {
+            let mut frame = bytes.to_vec();
+            frame.extend_from_slice(&crc16(bytes).to_le_bytes());
+            frame
+        }
+
+        fn handle(slave: &mut ModbusSlave, text: &mut RemoteText, frame: &[u8]) -> Option<Vec<u8>> {
+            slave.handle(frame, text).map(|response| response.to_vec())
+        }
+
+        assert_eq!(crc16(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x01]).to_le_bytes(), [0x84, 0x0a]);
+
+        let mut slave = ModbusSlave::new(1);
+        let mut text = RemoteText::new();
+        // Write "Hi" into the second row, then read it back
+        let write = request(&[1, 16, 0, 8, 0, 1, 2, b'H', b'i']);
+        assert_eq!(handle(&mut slave, &mut text, &write).unwrap(), request(&[1, 16, 0, 8, 0, 1]));
+        assert_eq!(text.row(1), "Hi              ");
+        let read = request(&[1, 3, 0, 8, 0, 2]);
+        assert_eq!(handle(&mut slave, &mut text, &read).unwrap(), request(&[1, 3, 4, b'H', b'i', b' ', b' ']));
+
+        // Backlight
+        let write = request(&[1, 6, 0, 16, 0, 0]);
+        assert_eq!(handle(&mut slave, &mut text, &write).unwrap(), write);
+        assert!(!text.backlight());
+
+        // Errors: illegal address, illegal function, broken CRC, other slave, broadcast
+        let read = request(&[1, 3, 0, 17, 0, 2]);
+        assert_eq!(handle(&mut slave, &mut text, &read).unwrap(), request(&[1, 0x83, 2]));
+        assert_eq!(handle(&mut slave, &mut text, &request(&[1, 5, 0, 0, 0, 0])).unwrap(), request(&[1, 0x85, 1]));
+        let mut broken = request(&[1, 3, 0, 0, 0, 1]);
+        broken[2] ^= 1;
+        assert_eq!(handle(&mut slave, &mut text, &broken), None);
+        assert_eq!(handle(&mut slave, &mut text, &request(&[2, 3, 0, 0, 0, 1])), None);
+        assert_eq!(handle(&mut slave, &mut text, &request(&[0, 6, 0, 0, 0x41, 0x42])), None);
+        assert_eq!(text.row(0), "AB              ");
+    }
+}
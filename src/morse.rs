@@ -0,0 +1,319 @@
+//! Morse code trainer: keys text out on the buzzer, with the character being keyed shown in
+//! brackets and its code in the second row.
+//!
+//! Text is typed over serial (on this screen, serial input goes to the text: backspace deletes,
+//! enter starts keying); without any text, button keys one of the practice words. Button stops
+//! keying, and once it is over, button is left for the navigation. Encoder sets the speed.
+//!
+//! Timing follows the "PARIS" standard: a dot is one unit (1200ms divided by the words per minute),
+//! a dash is three, the gaps are one unit between the elements, three between the characters and
+//! seven between the words. Like the stopwatch, time is passed to `advance`, which must be called
+//! often (see `buzzing`).
+
+use core::fmt::{self, Write};
+use crate::display::COLUMNS;
+use crate::random;
+use crate::text::{self, Align};
+use crate::ui::Event;
+
+/// Text length, in characters.
+const TEXT_LEN: usize = 32;
+/// Speed range, in words per minute.
+const WPM_MIN: u8 = 5;
+const WPM_MAX: u8 = 30;
+const WPM_DEFAULT: u8 = 15;
+/// Length of the "PARIS" word, in units (including the word gap).
+const UNIT_MS_AT_1_WPM: u32 = 1200;
+
+/// Keyed when there is no text.
+const PRACTICE: [&str; 8] = ["PARIS", "SOS", "CQ CQ DE", "HELLO WORLD", "73", "QRZ?", "THE QUICK FOX", "ROGER"];
+
+/// Characters visible around the one being keyed (the brackets take two more columns).
+const WINDOW: usize = COLUMNS - 3;
+
+/// Code of the character: dots and dashes. `None` if the character cannot be keyed.
+fn code(c: u8) -> Option<&'static str> {
+    const LETTERS: [&str; 26] = [
+        ".-", "-...", "-.-.", "-..", ".", "..-.", "--.", "....", "..", ".---", "-.-", ".-..", "--", "-.", "---", ".--.",
+        "--.-", ".-.", "...", "-", "..-", "...-", ".--", "-..-", "-.--", "--..",
+    ];
+    const DIGITS: [&str; 10] = ["-----", ".----", "..---", "...--", "....-", ".....", "-....", "--...", "---..", "----."];
+    Some(match c.to_ascii_uppercase() {
+        c @ b'A'..=b'Z' => LETTERS[usize::from(c - b'A')],
+        c @ b'0'..=b'9' => DIGITS[usize::from(c - b'0')],
+        b'.' => ".-.-.-",
+        b',' => "--..--",
+        b'?' => "..--..",
+        b'/' => "-..-.",
+        b'=' => "-...-",
+        _ => return None,
+    })
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    /// Text is being typed.
+    Idle,
+    /// Keying the element of the character (`index` into the text), key is `on` for `left_ms`
+    /// more (or it is a gap).
+    Keying { index: usize, element: usize, on: bool, left_ms: u32 },
+    /// Whole text is keyed (or keying was stopped).
+    Done,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Morse {
+    text: [u8; TEXT_LEN],
+    len: usize,
+    /// Speed, in words per minute.
+    wpm: u8,
+    state: State,
+    /// Timer value of the last `advance`.
+    last_ms: u32,
+}
+
+impl Default for Morse {
+    fn default() -> Morse {
+        Morse {
+            text: [b' '; TEXT_LEN],
+            len: 0,
+            wpm: WPM_DEFAULT,
+            state: State::Idle,
+            last_ms: 0,
+        }
+    }
+}
+
+impl Morse {
+    /// Key the text up to `now_ms` (the millisecond timer, which can wrap around).
+    pub fn advance(&mut self, now_ms: u32) {
+        let mut passed = now_ms.wrapping_sub(self.last_ms);
+        self.last_ms = now_ms;
+        while let State::Keying { left_ms, .. } = &mut self.state {
+            if passed < *left_ms {
+                *left_ms -= passed;
+                break;
+            }
+            passed -= *left_ms;
+            self.next();
+        }
+    }
+
+    /// Go to the next element or gap.
+    fn next(&mut self) {
+        let State::Keying { mut index, mut element, on, .. } = self.state else {
+            return;
+        };
+        let unit_ms = UNIT_MS_AT_1_WPM / u32::from(self.wpm);
+        let left_ms = if on {
+            // Gap after the element, longer after the last one of the character
+            element += 1;
+            if element < code(self.text[index]).map_or(0, str::len) {
+                unit_ms
+            } else {
+                index += 1;
+                element = 0;
+                3 * unit_ms
+            }
+        } else if let Some(code) = code(self.text[index]) {
+            let on_ms = if code.as_bytes()[element] == b'-' { 3 * unit_ms } else { unit_ms };
+            self.state = State::Keying { index, element, on: true, left_ms: on_ms };
+            return;
+        } else {
+            // Space (or a character which cannot be keyed), makes a word gap with the gap before it
+            index += 1;
+            4 * unit_ms
+        };
+        self.state = if index < self.len {
+            State::Keying { index, element, on: false, left_ms }
+        } else {
+            State::Done
+        };
+    }
+
+    /// Should the buzzer be on now?
+    pub fn buzzing(&self) -> bool {
+        matches!(self.state, State::Keying { on: true, .. })
+    }
+
+    /// Text typed (or the practice word).
+    pub fn text(&self) -> &[u8] {
+        &self.text[..self.len]
+    }
+
+    /// Index of the character being keyed.
+    pub fn keyed(&self) -> Option<usize> {
+        match self.state {
+            State::Keying { index, .. } => Some(index),
+            _ => None,
+        }
+    }
+
+    fn start(&mut self) {
+        if self.len == 0 {
+            let word = PRACTICE[random::below(PRACTICE.len() as u32) as usize].as_bytes();
+            self.text[..word.len()].copy_from_slice(word);
+            self.len = word.len();
+        }
+        self.state = State::Keying { index: 0, element: 0, on: false, left_ms: 0 };
+        self.next();
+    }
+
+    /// Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        match (self.state, event) {
+            (State::Idle, Event::Button | Event::Serial(b'\r' | b'\n')) => self.start(),
+            (State::Keying { .. }, Event::Button) => self.state = State::Done,
+            (State::Done, Event::Button) => {
+                self.state = State::Idle;
+                return false;
+            }
+            (_, Event::EncoderUp) => self.wpm = (self.wpm + 1).min(WPM_MAX),
+            (_, Event::EncoderDown) => self.wpm = (self.wpm - 1).max(WPM_MIN),
+            (State::Idle, Event::Serial(0x08 | 0x7f)) => self.len = self.len.saturating_sub(1),
+            (State::Idle, Event::Serial(c)) => {
+                if (c == b' ' || code(c).is_some()) && self.len < TEXT_LEN {
+                    self.text[self.len] = c.to_ascii_uppercase();
+                    self.len += 1;
+                }
+            }
+            (State::Done, Event::Serial(c)) => {
+                // New text replaces the one keyed
+                self.state = State::Idle;
+                self.len = 0;
+                return self.handle(Event::Serial(c));
+            }
+            // Keying is not disturbed by the typing
+            (State::Keying { .. }, Event::Serial(_)) => {}
+            _ => return false,
+        }
+        true
+    }
+
+    /// Write the text: while keying, the part of it around the character being keyed, which is
+    /// in brackets (`SO[S]`), otherwise its end with the cursor (`HELLO_`).
+    pub fn write_text<W: Write>(&self, w: &mut W) -> fmt::Result {
+        let text = self.text();
+        let Some(index) = self.keyed() else {
+            if text.is_empty() {
+                return w.write_str("Morse_");
+            }
+            let start = text.len().saturating_sub(WINDOW);
+            write_chars(w, &text[start..])?;
+            return if self.state == State::Idle { w.write_char('_') } else { Ok(()) };
+        };
+        let start = index.saturating_sub(WINDOW / 2).min(text.len().saturating_sub(WINDOW));
+        write_chars(w, &text[start..index])?;
+        w.write_char('[')?;
+        write_chars(w, &text[index..=index])?;
+        w.write_char(']')?;
+        write_chars(w, &text[index + 1..text.len().min(start + WINDOW)])
+    }
+
+    /// Write the speed and the code of the character being keyed (`WPM 15       ...`).
+    pub fn write_code<W: Write>(&self, w: &mut W) -> fmt::Result {
+        w.write_str("WPM")?;
+        text::uint(w, u32::from(self.wpm), 3)?;
+        let shown = match self.keyed() {
+            Some(index) => code(self.text[index]).unwrap_or(""),
+            None if self.state == State::Done => "done",
+            None => "",
+        };
+        text::str(w, shown, COLUMNS - 6, Align::Right)
+    }
+}
+
+fn write_chars<W: Write>(w: &mut W, chars: &[u8]) -> fmt::Result {
+    chars.iter().try_for_each(|&c| w.write_char(char::from(c)))
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn morse_keying() {
+        use crate::mock;
+        use crate::screens::{self, Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::Morse, &Stats::default(), settings);
+        let type_text = |settings: &mut Settings, text: &str| {
+            for c in text.bytes() {
+                assert!(settings.handle(Screen::Morse, Event::Serial(c)));
+            }
+        };
+        // Key the text at 20 WPM (60ms units), buzzer sampled in the middle of every unit
+        let key = |settings: &mut Settings, start_ms: u32| {
+            let mut keyed = std::string::String::new();
+            let mut unit = 0;
+            while settings.morse.keyed().is_some() {
+                settings.advance(start_ms + unit * 60 + 30);
+                keyed.push(if settings.buzzing() { '1' } else { '0' });
+                unit += 1;
+            }
+            std::string::String::from(keyed.trim_end_matches('0'))
+        };
+
+        assert_eq!(render(&settings), ("Morse_          ".into(), "WPM 15          ".into()));
+        type_text(&mut settings, "sox\x08s");
+        assert_eq!(render(&settings).0, "SOS_            ");
+        for _ in 0..5 {
+            assert!(settings.handle(Screen::Morse, Event::EncoderUp));
+        }
+
+        // Character keyed is in brackets, its code is in the second row
+        settings.advance(0);
+        assert!(settings.handle(Screen::Morse, Event::Serial(b'\r')));
+        assert_eq!(render(&settings), ("[S]OS           ".into(), "WPM 20       ...".into()));
+        assert_eq!(key(&mut settings, 0), "101010001110111011100010101");
+        assert_eq!(render(&settings), ("SOS             ".into(), "WPM 20      done".into()));
+
+        // Button on the keyed text leaves the screen, next press keys it again
+        assert!(!settings.handle(Screen::Morse, Event::Button));
+        assert_eq!(render(&settings).0, "SOS_            ");
+        settings.advance(10_000);
+        assert!(settings.handle(Screen::Morse, Event::Button));
+        assert!(settings.buzzing());
+        settings.advance(10_500);
+        assert_eq!(render(&settings), ("S[O]S           ".into(), "WPM 20       ---".into()));
+        assert!(settings.handle(Screen::Morse, Event::Button));
+        assert!(!settings.buzzing());
+
+        // Word gap is seven units; new text replaces the keyed one
+        type_text(&mut settings, "E E");
+        assert_eq!(render(&settings).0, "E E_            ");
+        settings.advance(20_000);
+        assert!(settings.handle(Screen::Morse, Event::Button));
+        assert_eq!(key(&mut settings, 20_000), "100000001");
+
+        // Long text scrolls around the character keyed
+        assert!(!settings.handle(Screen::Morse, Event::Button));
+        type_text(&mut settings, "\x08\x08\x08THE QUICK BROWN FOX");
+        assert_eq!(render(&settings).0, "ICK BROWN FOX_  ");
+        settings.advance(30_000);
+        assert!(settings.handle(Screen::Morse, Event::Button));
+        assert_eq!(render(&settings).0, "[T]HE QUICK BRO ");
+        let mut now_ms = 30_000;
+        while settings.morse.keyed() != Some(10) {
+            now_ms += 10;
+            settings.advance(now_ms);
+        }
+        assert_eq!(render(&settings).0, "QUICK [B]ROWN F ");
+
+        // Without any text, one of the practice words is keyed
+        let mut morse = Settings::default().morse;
+        assert!(morse.handle(Event::Button));
+        assert!(!morse.text().is_empty());
+        assert!(morse.buzzing());
+
+        // Morse screen goes after the dice one
+        let mut ui = screens::navigation();
+        ui.handle(Event::Serial(b'y'), &settings);
+        ui.handle(Event::Button, &settings);
+        assert_eq!(ui.state(), Screen::Morse);
+        ui.handle(Event::Button, &settings);
+        assert_eq!(ui.state(), Screen::Calculator);
+        ui.handle(Event::Serial(b'v'), &settings);
+        assert_eq!(ui.state(), Screen::Morse);
+    }
+}
@@ -0,0 +1,183 @@
+//! Motion wake: the screensaver blanks the display after `SAVER_MS` without user input or motion
+//! seen by the PIR sensor (see `pir`), and the motion (or any input) brings it back. Both rows go
+//! blank, and so does the backlight with the `backlight` feature. Input which wakes the display is
+//! not used otherwise (so the first press does not change the screen nobody was looking at).
+//!
+//! Every motion (PIR output going high) is logged with the uptime, the last `HISTORY` of them are
+//! kept for the motion history screen. Button goes back through them, past the oldest one it goes
+//! on to the next screen.
+//!
+//! Like the stopwatch, time is passed to `advance`, which must be called before every event and
+//! every render.
+
+use core::fmt::{self, Write};
+use crate::text;
+use crate::ui::Event;
+
+/// Display is blanked after that long without input or motion, in milliseconds.
+pub const SAVER_MS: u32 = 60_000;
+/// Motion events kept.
+pub const HISTORY: usize = 8;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Motion {
+    /// Uptime at the last `HISTORY` motions, in seconds (ring buffer).
+    events: [u32; HISTORY],
+    /// Motions detected since boot.
+    count: u32,
+    /// Motion shown, counting back from the last one.
+    shown: u32,
+    /// Was motion detected at the last update?
+    detected: bool,
+    /// Time since the last input or motion, in milliseconds.
+    idle_ms: u32,
+    /// Timer value of the last `advance`.
+    last_ms: u32,
+}
+
+impl Motion {
+    /// Account the time passed since the last call (`now_ms` is the millisecond timer, which can
+    /// wrap around).
+    pub fn advance(&mut self, now_ms: u32) {
+        let elapsed_ms = now_ms.wrapping_sub(self.last_ms);
+        self.last_ms = now_ms;
+        self.idle_ms = self.idle_ms.saturating_add(elapsed_ms);
+    }
+
+    /// Is the display blanked?
+    pub fn asleep(&self) -> bool {
+        self.idle_ms >= SAVER_MS
+    }
+
+    /// Account the user input. Returns `true` if it woke the display (and should not be used
+    /// otherwise).
+    pub fn wake(&mut self) -> bool {
+        let asleep = self.asleep();
+        self.idle_ms = 0;
+        asleep
+    }
+
+    /// Account the PIR output (`uptime_s` is the uptime, in seconds): motion keeps the display
+    /// awake, its start is logged. Returns `true` if motion has started.
+    pub fn update(&mut self, detected: bool, uptime_s: u32) -> bool {
+        let started = detected && !self.detected;
+        self.detected = detected;
+        if detected {
+            self.idle_ms = 0;
+        }
+        if started {
+            self.events[self.count as usize % HISTORY] = uptime_s;
+            self.count = self.count.wrapping_add(1);
+            self.shown = 0;
+        }
+        started
+    }
+
+    /// Motions detected since boot.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Uptime at the motion shown, in seconds, `None` if there were none.
+    pub fn shown(&self) -> Option<u32> {
+        (self.count > 0).then(|| self.events[(self.count - 1 - self.shown) as usize % HISTORY])
+    }
+
+    /// Button goes to the previous motion; past the oldest one kept, back to the last one.
+    /// Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        if event != Event::Button {
+            return false;
+        }
+        let kept = self.count.min(HISTORY as u32);
+        self.shown += 1;
+        if self.shown >= kept {
+            self.shown = 0;
+            return false;
+        }
+        true
+    }
+
+    /// Write the motion count, and whether there is motion now (`Motion   12 now`).
+    pub fn write_count<W: Write>(&self, w: &mut W) -> fmt::Result {
+        w.write_str("Motion")?;
+        text::uint(w, self.count, 5)?;
+        if self.detected {
+            w.write_str(" now")?;
+        }
+        Ok(())
+    }
+
+    /// Write the motion shown, its number and the uptime at it (`# 12 1d 03:25:17`).
+    pub fn write_event<W: Write>(&self, w: &mut W) -> fmt::Result {
+        let Some(uptime_s) = self.shown() else {
+            return w.write_str("no motion");
+        };
+        w.write_char('#')?;
+        text::uint(w, self.count - self.shown, 3)?;
+        text::duration(w, uptime_s, 12)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn motion_screensaver() {
+        use crate::display::NoDisplay;
+        use crate::framebuffer::Framebuffer;
+        use crate::mock;
+        use crate::motion::SAVER_MS;
+        use crate::screens::{self, Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::Motion, &Stats::default(), settings);
+        assert_eq!(render(&settings), ("Motion    0     ".into(), "no motion       ".into()));
+
+        // Motion is logged as it starts
+        let motion = &mut settings.motion;
+        assert!(motion.update(true, 5));
+        assert!(!motion.update(true, 6));
+        assert_eq!(render(&settings), ("Motion    1 now ".into(), "#  1    00:00:05".into()));
+        let motion = &mut settings.motion;
+        motion.update(false, 7);
+        motion.update(true, 3600);
+        motion.update(false, 3601);
+        assert_eq!(render(&settings), ("Motion    2     ".into(), "#  2    01:00:00".into()));
+
+        // Button goes back through the motions, then on to the next screen
+        assert!(settings.handle(Screen::Motion, Event::Button));
+        assert_eq!(render(&settings).1, "#  1    00:00:05");
+        assert!(!settings.handle(Screen::Motion, Event::Button));
+        assert_eq!(render(&settings).1, "#  2    01:00:00");
+
+        // Display is blanked without motion or input, input wakes it (and is used for nothing else)
+        settings.advance(SAVER_MS - 1);
+        assert!(!settings.motion.asleep());
+        settings.advance(SAVER_MS);
+        assert!(settings.motion.asleep());
+        let mut fb = Framebuffer::new(NoDisplay);
+        Screen::Motion.render(&mut fb, &Stats::default(), &settings).unwrap();
+        screens::blank(&mut fb).unwrap();
+        assert_eq!((fb.row(0), fb.row(1)), ("                ", "                "));
+        assert!(settings.motion.wake());
+        assert!(!settings.motion.wake());
+
+        // And so does motion, which also keeps it awake
+        settings.advance(2 * SAVER_MS);
+        assert!(settings.motion.asleep());
+        assert!(settings.motion.update(true, 4000));
+        assert!(!settings.motion.asleep());
+        settings.advance(3 * SAVER_MS);
+        assert!(!settings.motion.update(true, 4060));
+        assert!(!settings.motion.asleep());
+        settings.motion.update(false, 4061);
+        assert_eq!(settings.motion.count(), 3);
+        settings.advance(4 * SAVER_MS);
+        assert!(settings.motion.asleep());
+
+        let mut ui = screens::navigation();
+        ui.handle(Event::Serial(b'&'), &settings);
+        assert_eq!(ui.state(), Screen::Motion);
+    }
+}
@@ -0,0 +1,456 @@
+//! 1-Wire bus: device enumeration (ROM search) and DS18B20 temperature sensors.
+//!
+//! Bit-level access is provided by the `OneWire` implementation (bit-banged pin on the target),
+//! everything else is hardware-independent. ROM search is the algorithm from Maxim application
+//! note 187.
+
+use core::fmt::{self, Write};
+use crate::text;
+use crate::ui::Event;
+
+const SEARCH_ROM: u8 = 0xf0;
+const MATCH_ROM: u8 = 0x55;
+const SKIP_ROM: u8 = 0xcc;
+const CONVERT_T: u8 = 0x44;
+const READ_SCRATCHPAD: u8 = 0xbe;
+
+/// Family code of DS18B20.
+pub const DS18B20: u8 = 0x28;
+
+/// Device ROM code: family code, 48-bit serial number and CRC, in the order sent on the bus.
+pub type Rom = [u8; 8];
+
+/// Bit-level access to the bus.
+pub trait OneWire {
+    /// Send reset pulse. Returns `true` if any device has answered with a presence pulse.
+    fn reset(&mut self) -> bool;
+
+    fn write_bit(&mut self, bit: bool);
+
+    fn read_bit(&mut self) -> bool;
+
+    /// Write byte, least significant bit first.
+    fn write_byte(&mut self, byte: u8) {
+        for i in 0..8 {
+            self.write_bit(byte & (1 << i) != 0);
+        }
+    }
+
+    /// Read byte, least significant bit first.
+    fn read_byte(&mut self) -> u8 {
+        (0..8).fold(0, |byte, i| byte | (u8::from(self.read_bit()) << i))
+    }
+}
+
+/// Dallas/Maxim CRC-8 (polynomial x^8 + x^5 + x^4 + 1), used for ROM codes and scratchpads. CRC of
+/// the data followed by its CRC is zero.
+pub fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| if crc & 1 != 0 { (crc >> 1) ^ 0x8c } else { crc >> 1 })
+    })
+}
+
+/// State of the ROM search, each `next` call finds one more device.
+pub struct Search {
+    rom: Rom,
+    /// Bit position (1-based) of the last discrepancy where `0` was taken, 0 if there is none.
+    last_discrepancy: u8,
+    done: bool,
+}
+
+impl Default for Search {
+    fn default() -> Search {
+        Search::new()
+    }
+}
+
+impl Search {
+    pub fn new() -> Search {
+        Search {
+            rom: [0; 8],
+            last_discrepancy: 0,
+            done: false,
+        }
+    }
+
+    /// Find the next device. Returns `None` once all devices are found, if there are no devices
+    /// or if the bus is not reliable (CRC mismatch or no device answering in the middle of the
+    /// search).
+    pub fn next<B: OneWire>(&mut self, bus: &mut B) -> Option<Rom> {
+        if self.done || !bus.reset() {
+            return None;
+        }
+        bus.write_byte(SEARCH_ROM);
+        let mut discrepancy = 0;
+        for position in 1..=64u8 {
+            let index = usize::from((position - 1) / 8);
+            let mask = 1 << ((position - 1) % 8);
+            // Every device sends the bit, then its complement (open drain, so it is wired-AND)
+            let bit = bus.read_bit();
+            let complement = bus.read_bit();
+            let direction = match (bit, complement) {
+                (true, true) => {
+                    self.done = true;
+                    return None;
+                }
+                (true, false) => true,
+                (false, true) => false,
+                // Devices differ: take the same path as the last time before the last
+                // discrepancy, `1` at it and `0` after it
+                (false, false) => {
+                    let direction = match position.cmp(&self.last_discrepancy) {
+                        core::cmp::Ordering::Less => self.rom[index] & mask != 0,
+                        core::cmp::Ordering::Equal => true,
+                        core::cmp::Ordering::Greater => false,
+                    };
+                    if !direction {
+                        discrepancy = position;
+                    }
+                    direction
+                }
+            };
+            if direction {
+                self.rom[index] |= mask;
+            } else {
+                self.rom[index] &= !mask;
+            }
+            // Devices with the other bit drop out of the search
+            bus.write_bit(direction);
+        }
+        self.last_discrepancy = discrepancy;
+        self.done = discrepancy == 0;
+        if crc8(&self.rom) != 0 {
+            self.done = true;
+            return None;
+        }
+        Some(self.rom)
+    }
+}
+
+/// Start temperature conversion on all DS18B20 on the bus (takes up to 750ms). Returns `false`
+/// if there are no devices.
+pub fn convert_all<B: OneWire>(bus: &mut B) -> bool {
+    if !bus.reset() {
+        return false;
+    }
+    bus.write_byte(SKIP_ROM);
+    bus.write_byte(CONVERT_T);
+    true
+}
+
+/// Read the result of the last conversion of the DS18B20, in tenths of °C. `None` if the device
+/// does not answer or the scratchpad is corrupted.
+pub fn read_temperature<B: OneWire>(bus: &mut B, rom: &Rom) -> Option<i16> {
+    if !bus.reset() {
+        return None;
+    }
+    bus.write_byte(MATCH_ROM);
+    for &byte in rom {
+        bus.write_byte(byte);
+    }
+    bus.write_byte(READ_SCRATCHPAD);
+    let mut scratchpad = [0; 9];
+    for byte in scratchpad.iter_mut() {
+        *byte = bus.read_byte();
+    }
+    // Disconnected bus reads all ones, which passes the CRC check
+    if crc8(&scratchpad) != 0 || scratchpad == [0xff; 9] {
+        return None;
+    }
+    // Sixteenths of °C
+    let raw = i16::from_le_bytes([scratchpad[0], scratchpad[1]]);
+    Some(((i32::from(raw) * 10) >> 4) as i16)
+}
+
+/// Devices shown by the 1-Wire screen.
+pub const MAX_DEVICES: usize = 4;
+
+/// Devices found on the bus, shown one per page.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Devices {
+    roms: [Rom; MAX_DEVICES],
+    /// Temperatures of DS18B20s, in tenths of °C.
+    temperatures: [Option<i16>; MAX_DEVICES],
+    count: usize,
+    page: usize,
+}
+
+impl Devices {
+    /// Replace devices with the new scan. Page is kept, if it still exists.
+    pub fn update(&mut self, found: Devices) {
+        let page = self.page;
+        *self = found;
+        if page < self.count {
+            self.page = page;
+        }
+    }
+
+    /// Add device found on the bus. Devices past `MAX_DEVICES` are ignored.
+    pub fn add(&mut self, rom: Rom, temperature: Option<i16>) {
+        if self.count < MAX_DEVICES {
+            self.roms[self.count] = rom;
+            self.temperatures[self.count] = temperature;
+            self.count += 1;
+        }
+    }
+
+    /// Devices found so far.
+    pub fn roms(&self) -> &[Rom] {
+        &self.roms[..self.count]
+    }
+
+    /// Temperature of the first DS18B20 which has one, in tenths of °C.
+    pub fn temperature(&self) -> Option<i16> {
+        self.temperatures[..self.count].iter().flatten().next().copied()
+    }
+
+    /// Button shows the next device. Returns `false` on the last one (button is left for the
+    /// navigation then, and the first device is shown next time).
+    pub fn handle(&mut self, event: Event) -> bool {
+        if event != Event::Button {
+            return false;
+        }
+        if self.page + 1 < self.count {
+            self.page += 1;
+            true
+        } else {
+            self.page = 0;
+            false
+        }
+    }
+
+    /// Write number of the current device and its temperature (`1-Wire 1   23.5C`), `write_temp`
+    /// formats the temperature (given in tenths of °C).
+    pub fn write_summary<W: Write>(&self, w: &mut W, write_temp: impl Fn(&mut W, i16) -> fmt::Result) -> fmt::Result {
+        w.write_str("1-Wire")?;
+        if self.count == 0 {
+            return w.write_str(" none");
+        }
+        let page = self.page.min(self.count - 1);
+        text::uint(w, page as u32 + 1, 2)?;
+        match self.temperatures[page] {
+            Some(temp) => write_temp(w, temp),
+            None => Ok(()),
+        }
+    }
+
+    /// Write ROM code of the current device, family code first (`28ff4c1a03160455`).
+    pub fn write_rom<W: Write>(&self, w: &mut W) -> fmt::Result {
+        if self.count == 0 {
+            return Ok(());
+        }
+        for &byte in &self.roms[self.page.min(self.count - 1)] {
+            text::hex8(w, byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_arch = "arm")]
+pub use self::pin::PinBus;
+
+#[cfg(target_arch = "arm")]
+mod pin {
+    use stm32f1::stm32f103::{gpioa, RCC};
+    use crate::board::PortName;
+    use crate::delay::CycleDelay;
+    use crate::gpio::{self, GPIOExtras};
+    use super::OneWire;
+
+    /// Bus pin (PB0), needs an external pull-up (4.7K).
+    const PIN: usize = 0;
+
+    /// Bit-banged 1-Wire bus (standard speed). Interrupts are disabled for each time slot, so the
+    /// timing is not disturbed, but not between the slots.
+    pub struct PinBus {
+        port: &'static gpioa::RegisterBlock,
+        delay: CycleDelay,
+    }
+
+    impl PinBus {
+        /// Configure the pin as an open-drain output, released (high).
+        pub fn new(rcc: &RCC, delay: CycleDelay) -> PinBus {
+            // Only the bus pin is touched, the rest of the port is used by the LCD
+            let port = gpio::enable_port(rcc, PortName::B);
+            port.write_pin(PIN, true);
+            port.pin_config(PIN).output2().open_drain().general();
+            PinBus { port, delay }
+        }
+
+        /// Pull the bus low for `low_us`, release it and sample it after `sample_us`, then wait
+        /// for `recovery_us`.
+        fn slot(&self, low_us: u32, sample_us: u32, recovery_us: u32) -> bool {
+            let level = cortex_m::interrupt::free(|_| {
+                self.port.write_pin(PIN, false);
+                self.delay.delay_us(low_us);
+                self.port.write_pin(PIN, true);
+                self.delay.delay_us(sample_us);
+                self.port.read_pin(PIN)
+            });
+            self.delay.delay_us(recovery_us);
+            level
+        }
+    }
+
+    impl OneWire for PinBus {
+        fn reset(&mut self) -> bool {
+            !self.slot(480, 70, 410)
+        }
+
+        fn write_bit(&mut self, bit: bool) {
+            if bit {
+                self.slot(6, 64, 0);
+            } else {
+                self.slot(60, 10, 0);
+            }
+        }
+
+        fn read_bit(&mut self) -> bool {
+            self.slot(6, 9, 55)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn onewire_search_and_temperatures() {
+        use std::vec::Vec;
+        use crate::mock;
+        use crate::onewire::{self, crc8, OneWire, Rom, Search, DS18B20};
+        use crate::screens::{Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        enum State {
+            Command(u8, u8),
+            Search(usize, bool),
+            Match(usize),
+            Read(usize),
+            Idle,
+        }
+
+        /// Devices (ROM code and scratchpad) on an open-drain bus: every read is wired-AND of the
+        /// devices still taking part in the transaction.
+        struct Bus {
+            devices: Vec<(Rom, [u8; 9])>,
+            active: Vec<bool>,
+            state: State,
+        }
+
+        fn bit(bytes: &[u8], position: usize) -> bool {
+            bytes[position / 8] & (1 << (position % 8)) != 0
+        }
+
+        impl Bus {
+            fn wired_and(&self, bit: impl Fn(&(Rom, [u8; 9])) -> bool) -> bool {
+                self.devices.iter().zip(&self.active).all(|(device, &active)| !active || bit(device))
+            }
+
+            fn select(&mut self, position: usize, value: bool) {
+                for (device, active) in self.devices.iter().zip(self.active.iter_mut()) {
+                    *active &= bit(&device.0, position) == value;
+                }
+            }
+        }
+
+        impl OneWire for Bus {
+            fn reset(&mut self) -> bool {
+                self.active = vec![true; self.devices.len()];
+                self.state = State::Command(0, 0);
+                !self.devices.is_empty()
+            }
+
+            fn write_bit(&mut self, value: bool) {
+                self.state = match self.state {
+                    State::Command(byte, 7) => match byte | (u8::from(value) << 7) {
+                        0xf0 => State::Search(0, false),
+                        0x55 => State::Match(0),
+                        0xcc => State::Command(0, 0),
+                        0xbe => State::Read(0),
+                        _ => State::Idle,
+                    },
+                    State::Command(byte, count) => State::Command(byte | (u8::from(value) << count), count + 1),
+                    State::Search(position, _) => {
+                        self.select(position, value);
+                        if position == 63 { State::Idle } else { State::Search(position + 1, false) }
+                    }
+                    State::Match(position) => {
+                        self.select(position, value);
+                        if position == 63 { State::Command(0, 0) } else { State::Match(position + 1) }
+                    }
+                    _ => State::Idle,
+                };
+            }
+
+            fn read_bit(&mut self) -> bool {
+                match self.state {
+                    State::Search(position, complement) => {
+                        self.state = State::Search(position, true);
+                        self.wired_and(|device| bit(&device.0, position) != complement)
+                    }
+                    State::Read(position) => {
+                        self.state = State::Read(position + 1);
+                        self.wired_and(|device| bit(&device.1, position))
+                    }
+                    _ => true,
+                }
+            }
+        }
+
+        fn rom(family: u8, serial: u8) -> Rom {
+            let mut rom = [family, serial, 0x4c, 0x1a, 0x03, 0x16, 0x04, 0];
+            rom[7] = crc8(&rom[..7]);
+            rom
+        }
+
+        fn scratchpad(sixteenths: i16) -> [u8; 9] {
+            let [lsb, msb] = sixteenths.to_le_bytes();
+            let mut scratchpad = [lsb, msb, 0x4b, 0x46, 0x7f, 0xff, 0x0c, 0x10, 0];
+            scratchpad[8] = crc8(&scratchpad[..8]);
+            scratchpad
+        }
+
+        // Example from the Maxim application note 27
+        assert_eq!(crc8(&[0x02, 0x1c, 0xb8, 0x01, 0x00, 0x00, 0x00]), 0xa2);
+
+        let sensor = rom(DS18B20, 0xff);
+        let cold = rom(DS18B20, 0x7e);
+        let serial_number = rom(0x01, 0xff);
+        let mut bus = Bus {
+            devices: vec![(sensor, scratchpad(376)), (cold, scratchpad(-168)), (serial_number, [0xff; 9])],
+            active: Vec::new(),
+            state: State::Idle,
+        };
+        let mut search = Search::new();
+        let mut found = Vec::new();
+        while let Some(rom) = search.next(&mut bus) {
+            found.push(rom);
+        }
+        // `0` is taken first where devices differ
+        assert_eq!(found, vec![cold, sensor, serial_number]);
+        assert!(onewire::convert_all(&mut bus));
+        assert_eq!(onewire::read_temperature(&mut bus, &sensor), Some(235));
+        assert_eq!(onewire::read_temperature(&mut bus, &cold), Some(-105));
+        assert_eq!(onewire::read_temperature(&mut bus, &serial_number), None);
+
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::OneWire, &Stats::default(), settings);
+        assert_eq!(render(&settings).0, "1-Wire none     ");
+
+        let mut devices = onewire::Devices::default();
+        for rom in &found {
+            let temperature = if rom[0] == DS18B20 { onewire::read_temperature(&mut bus, rom) } else { None };
+            devices.add(*rom, temperature);
+        }
+        settings.onewire.update(devices);
+        assert_eq!(render(&settings), ("1-Wire 1  -10.5C".into(), "287e4c1a031604bd".into()));
+        assert!(settings.handle(Screen::OneWire, Event::Button));
+        assert_eq!(render(&settings).0, "1-Wire 2   23.5C");
+        assert!(settings.handle(Screen::OneWire, Event::Button));
+        // Page is kept across updates, devices other than DS18B20 have no temperature
+        settings.onewire.update(devices);
+        assert_eq!(render(&settings), ("1-Wire 3        ".into(), "01ff4c1a03160443".into()));
+        assert!(!settings.handle(Screen::OneWire, Event::Button));
+        assert_eq!(render(&settings).0, "1-Wire 1  -10.5C");
+    }
+}
@@ -0,0 +1,117 @@
+//! Fixed-point PID controller.
+//!
+//! Gains are given in hundredths, so `kp: 250` is 2.5 units of the output per unit of the error.
+//! Integral gain is per second of the accumulated error, derivative gain is per unit of the error
+//! change per second. Derivative is taken of the measurement rather than of the error, so changing
+//! the setpoint does not kick the output. Integral is kept within the output limits, so it does not
+//! wind up while the output is saturated.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Pid {
+    /// Proportional gain, in hundredths.
+    pub kp: i32,
+    /// Integral gain, in hundredths.
+    pub ki: i32,
+    /// Derivative gain, in hundredths.
+    pub kd: i32,
+    /// Output limits.
+    pub min: i32,
+    pub max: i32,
+    /// Integral term, in hundredths of the output.
+    integral: i64,
+    /// Measurement of the last update.
+    last: Option<i32>,
+}
+
+impl Pid {
+    pub const fn new(kp: i32, ki: i32, kd: i32, min: i32, max: i32) -> Pid {
+        Pid { kp, ki, kd, min, max, integral: 0, last: None }
+    }
+
+    /// Forget the accumulated error and the last measurement.
+    pub fn reset(&mut self) {
+        self.integral = 0;
+        self.last = None;
+    }
+
+    /// Compute the output for the `measured` value, `dt_ms` milliseconds after the last update.
+    pub fn update(&mut self, setpoint: i32, measured: i32, dt_ms: u32) -> i32 {
+        let error = i64::from(setpoint) - i64::from(measured);
+        let dt_ms = i64::from(dt_ms.max(1));
+        let proportional = i64::from(self.kp) * error;
+        self.integral = (self.integral + i64::from(self.ki) * error * dt_ms / 1000)
+            .clamp(i64::from(self.min) * 100, i64::from(self.max) * 100);
+        let derivative = match self.last {
+            Some(last) => -i64::from(self.kd) * (i64::from(measured) - i64::from(last)) * 1000 / dt_ms,
+            None => 0,
+        };
+        self.last = Some(measured);
+        ((proportional + self.integral + derivative) / 100).clamp(i64::from(self.min), i64::from(self.max)) as i32
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn pid_tuning() {
+        use crate::mock;
+        use crate::pid::Pid;
+        use crate::screens::{Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        // Integral is kept within the output limits, so the output drops as soon as the error
+        // changes sign
+        let mut pid = Pid::new(100, 100, 0, 0, 100);
+        for _ in 0..100 {
+            assert_eq!(pid.update(1000, 0, 1000), 100);
+        }
+        assert_eq!(pid.update(0, 10, 1000), 80);
+        // Derivative is taken of the measurement, setpoint change does not kick the output
+        let mut pid = Pid::new(0, 0, 100, -1000, 1000);
+        assert_eq!(pid.update(0, 0, 100), 0);
+        assert_eq!(pid.update(50, 0, 100), 0);
+        assert_eq!(pid.update(50, 10, 100), -100);
+
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::Pid, &Stats::default(), settings);
+        assert_eq!(render(&settings), ("PV 20.0 SP 50.0 ".into(), "Kp  5.00 Out  0%".into()));
+
+        // Heater settles at the setpoint within a minute
+        settings.advance(100);
+        assert_eq!(settings.heater.output(), 1000);
+        for now_ms in (1..=60).map(|secs| secs * 1000) {
+            settings.advance(now_ms);
+        }
+        assert_eq!(render(&settings), ("PV 50.0 SP 50.0 ".into(), "Kp  5.00 Out 37%".into()));
+
+        // Encoder changes the parameter shown, button goes to the next one
+        assert!(settings.handle(Screen::Pid, Event::Button));
+        assert!(settings.handle(Screen::Pid, Event::EncoderUp));
+        assert_eq!(settings.heater.pid().ki, 55);
+        assert_eq!(render(&settings).1, "Ki  0.55 Out 37%");
+        assert!(settings.handle(Screen::Pid, Event::Button));
+        assert!(settings.handle(Screen::Pid, Event::EncoderDown));
+        assert_eq!(render(&settings).1, "Kd  0.00 Out 37%");
+        assert!(settings.handle(Screen::Pid, Event::Button));
+        for _ in 0..10 {
+            assert!(settings.handle(Screen::Pid, Event::EncoderDown));
+        }
+        assert_eq!(render(&settings), ("PV 50.0 SP 45.0 ".into(), "SP  45.0 Out 37%".into()));
+        assert!(!settings.handle(Screen::Pid, Event::Button));
+        assert_eq!(render(&settings).1, "Kp  5.00 Out 37%");
+
+        // Heater cools down to the new setpoint
+        settings.advance(60_100);
+        assert!(settings.heater.output() < 200);
+        for now_ms in (61..=180).map(|secs| secs * 1000) {
+            settings.advance(now_ms);
+        }
+        assert!((445..=455).contains(&settings.heater.temperature()));
+
+        // Long pause only runs a limited number of steps (simulation falls behind)
+        let before = settings.heater.temperature();
+        settings.heater.advance(10_000_000);
+        settings.heater.advance(10_000_000);
+        assert_eq!(settings.heater.temperature(), before);
+    }
+}
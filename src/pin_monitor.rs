@@ -0,0 +1,144 @@
+//! Pin monitor on the registers screen: a basic logic event counter on the GPIOA pins PA1, PA2,
+//! PA3, PA5, PA6 and PA7 (see `edges` for the EXTI side). Rising and falling edges of each pin
+//! are counted, and its level is shown.
+//!
+//! Encoder selects the pin whose counts are shown, long press resets its counts (the other pins
+//! keep theirs). Counts start with the first update, and are shown up to 99999.
+
+use core::fmt::{self, Write};
+use crate::text;
+use crate::ui::Event;
+
+/// GPIOA pins monitored.
+pub const PINS: [usize; 6] = [1, 2, 3, 5, 6, 7];
+pub const PIN_COUNT: usize = PINS.len();
+/// Counts are shown up to that (five digits).
+const COUNT_MAX: u32 = 99_999;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PinMonitor {
+    /// Edges counted (wrapping around) at the last update, rising and falling, by pin.
+    edges: [(u32, u32); PIN_COUNT],
+    /// Edges counted at the first update, or when the pin was reset.
+    reset: [(u32, u32); PIN_COUNT],
+    /// GPIOA input data register at the last update.
+    levels: u16,
+    /// Index of the pin shown.
+    selected: usize,
+    started: bool,
+}
+
+impl PinMonitor {
+    /// Account the edges counted so far (wrapping around) and the GPIOA input levels.
+    pub fn update(&mut self, edges: [(u32, u32); PIN_COUNT], levels: u16) {
+        if !self.started {
+            self.reset = edges;
+            self.started = true;
+        }
+        self.edges = edges;
+        self.levels = levels;
+    }
+
+    /// Pin shown (its number on GPIOA).
+    pub fn selected(&self) -> usize {
+        PINS[self.selected]
+    }
+
+    /// Rising and falling edges of the pin shown, since the reset.
+    pub fn counts(&self) -> (u32, u32) {
+        let ((rising, falling), (rising_reset, falling_reset)) = (self.edges[self.selected], self.reset[self.selected]);
+        (rising.wrapping_sub(rising_reset), falling.wrapping_sub(falling_reset))
+    }
+
+    /// Encoder selects the pin, long press resets its counts. Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        match event {
+            Event::EncoderUp => self.selected = (self.selected + 1) % PIN_COUNT,
+            Event::EncoderDown => self.selected = (self.selected + PIN_COUNT - 1) % PIN_COUNT,
+            Event::LongPress => self.reset[self.selected] = self.edges[self.selected],
+            _ => return false,
+        }
+        true
+    }
+
+    /// Write the counts of the pin shown (`PA3^  120v  119`).
+    pub fn write_counts<W: Write>(&self, w: &mut W) -> fmt::Result {
+        let (rising, falling) = self.counts();
+        w.write_str("PA")?;
+        w.write_char(digit(self.selected()))?;
+        w.write_char('^')?;
+        text::uint(w, rising.min(COUNT_MAX), 5)?;
+        w.write_char('v')?;
+        text::uint(w, falling.min(COUNT_MAX), 5)
+    }
+
+    /// Write the levels of all the pins, the one shown marked (`1H2L>3H5L6L7L`).
+    pub fn write_levels<W: Write>(&self, w: &mut W) -> fmt::Result {
+        for (i, pin) in PINS.iter().enumerate() {
+            if i == self.selected {
+                w.write_char('>')?;
+            }
+            w.write_char(digit(*pin))?;
+            w.write_char(if self.levels & (1 << pin) != 0 { 'H' } else { 'L' })?;
+        }
+        Ok(())
+    }
+}
+
+/// Pin numbers monitored are single digits.
+fn digit(pin: usize) -> char {
+    char::from(b'0' + pin as u8)
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn pin_monitor() {
+        use crate::mock;
+        use crate::screens::{Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::Registers, &Stats::default(), settings);
+
+        // Edges counted before the first update are not shown
+        let pins = &mut settings.pins;
+        let mut edges = [(7, 7); 6];
+        pins.update(edges, 0);
+        edges[0] = (19, 18);
+        edges[2] = (u32::MAX, 7);
+        pins.update(edges, 1 << 1 | 1 << 6);
+        assert_eq!(pins.counts(), (12, 11));
+        assert_eq!(render(&settings), ("PA1^   12v   11 ".into(), ">1H2L3L5L6H7L   ".into()));
+
+        // Encoder selects the pin, counts wrap around with the interrupt counters
+        let pins = &mut settings.pins;
+        assert!(pins.handle(Event::EncoderUp));
+        assert!(pins.handle(Event::EncoderUp));
+        assert_eq!(pins.selected(), 3);
+        assert_eq!(pins.counts(), (u32::MAX - 7, 0));
+        edges[2] = (10, 7);
+        pins.update(edges, 0);
+        assert_eq!(pins.counts(), (3, 0));
+        assert!(pins.handle(Event::EncoderDown));
+        assert!(pins.handle(Event::EncoderDown));
+        assert!(pins.handle(Event::EncoderDown));
+        assert_eq!(pins.selected(), 7);
+        assert!(!pins.handle(Event::Button));
+
+        // Long press resets the pin shown only
+        assert!(pins.handle(Event::EncoderUp));
+        assert!(settings.handle(Screen::Registers, Event::LongPress));
+        assert_eq!(settings.pins.counts(), (0, 0));
+        assert!(settings.pins.handle(Event::EncoderUp));
+        assert!(settings.pins.handle(Event::EncoderUp));
+        assert_eq!(settings.pins.counts(), (3, 0));
+        assert!(Screen::Registers.long_press());
+
+        // Counts are capped to the width
+        let pins = &mut settings.pins;
+        edges[2] = (200_000, 7);
+        pins.update(edges, 0);
+        assert_eq!(render(&settings).0, "PA3^99999v    0 ");
+    }
+}
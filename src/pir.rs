@@ -0,0 +1,26 @@
+//! PIR motion sensor input (see `board::PIR`): high while motion is detected.
+
+use stm32f1::stm32f103::{gpioa, RCC};
+use crate::board;
+use crate::gpio::{self, GPIOExtras};
+
+pub struct PirInput {
+    port: &'static gpioa::RegisterBlock,
+}
+
+impl PirInput {
+    /// Configure the pin as an input with a pull-down (disconnected sensor detects nothing).
+    pub fn new(rcc: &RCC) -> PirInput {
+        let pir = board::PIR;
+        let port = gpio::enable_port(rcc, pir.port);
+        port.write_pin(pir.index, pir.level(false));
+        port.pin_config(pir.index).input().pull_up_down();
+        PirInput { port }
+    }
+
+    /// Is motion detected?
+    pub fn motion(&self) -> bool {
+        let pir = board::PIR;
+        self.port.read_pin(pir.index) == pir.level(true)
+    }
+}
@@ -0,0 +1,216 @@
+//! Pseudo-pixel graphics on the character display: `PixelRegion` is a rectangle of character
+//! cells, 5x8 pixels each, drawn with the custom characters. There are only eight of them, shared
+//! by everything on the screen, so they are handed out by `Cgram` on every render: blank cells are
+//! drawn as spaces, cells with the same image share a character, and a character reserved by
+//! someone else (the low battery icon, say) is left alone.
+//!
+//! If a region has more distinct images than there are characters left, the images used by the
+//! fewest cells are evicted, and their cells are drawn with the closest image which got a
+//! character (the one with the fewest pixels different), so the picture degrades instead of
+//! showing garbage.
+
+use core::cmp::Reverse;
+use core::fmt::{self, Write};
+use crate::display::TextDisplay;
+
+/// Custom characters of the display.
+const GLYPHS: usize = 8;
+/// Cell size, in pixels.
+pub const CELL_WIDTH: usize = 5;
+pub const CELL_HEIGHT: usize = 8;
+
+/// Custom characters handed out during a single render.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cgram {
+    slots: [Slot; GLYPHS],
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Slot {
+    Free,
+    /// Programmed by its owner.
+    Reserved,
+    Taken([u8; 8]),
+}
+
+impl Default for Cgram {
+    fn default() -> Cgram {
+        Cgram { slots: [Slot::Free; GLYPHS] }
+    }
+}
+
+impl Cgram {
+    /// Keep the character out of the allocation (its owner uploads it).
+    pub fn reserve(&mut self, location: u8) {
+        self.slots[usize::from(location)] = Slot::Reserved;
+    }
+
+    /// Character with the image, the one already taken for the same image if any. Returns `None`
+    /// if all the characters are taken.
+    pub fn take(&mut self, map: [u8; 8]) -> Option<char> {
+        let location = match self.slots.iter().position(|slot| *slot == Slot::Taken(map)) {
+            Some(location) => location,
+            None => {
+                let location = self.slots.iter().position(|slot| *slot == Slot::Free)?;
+                self.slots[location] = Slot::Taken(map);
+                location
+            }
+        };
+        Some(char::from(location as u8))
+    }
+
+    /// Characters left.
+    pub fn free(&self) -> usize {
+        self.slots.iter().filter(|slot| **slot == Slot::Free).count()
+    }
+
+    /// Upload the images of the characters taken (with the framebuffer, only the changed ones are
+    /// sent).
+    pub fn upload<D: TextDisplay>(&self, display: &mut D) -> fmt::Result {
+        for (location, slot) in self.slots.iter().enumerate() {
+            if let Slot::Taken(map) = slot {
+                display.upload(location as u8, *map)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rectangle of `CELLS` character cells, `columns` wide, row by row.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PixelRegion<const CELLS: usize> {
+    columns: usize,
+    /// Images of the cells, a byte per pixel row (the leftmost pixel in bit 4).
+    cells: [[u8; 8]; CELLS],
+}
+
+impl<const CELLS: usize> PixelRegion<CELLS> {
+    /// Blank region, `columns` cells wide.
+    pub const fn new(columns: usize) -> PixelRegion<CELLS> {
+        PixelRegion { columns, cells: [[0; 8]; CELLS] }
+    }
+
+    /// Size, in pixels.
+    pub fn width(&self) -> usize {
+        self.columns * CELL_WIDTH
+    }
+
+    pub fn height(&self) -> usize {
+        CELLS / self.columns * CELL_HEIGHT
+    }
+
+    pub fn clear(&mut self) {
+        self.cells = [[0; 8]; CELLS];
+    }
+
+    /// Light the pixel (or put it out). Pixels outside of the region are ignored.
+    pub fn set(&mut self, x: usize, y: usize, lit: bool) {
+        if x >= self.width() || y >= self.height() {
+            return;
+        }
+        let cell = &mut self.cells[y / CELL_HEIGHT * self.columns + x / CELL_WIDTH];
+        let bit = 1 << (CELL_WIDTH - 1 - x % CELL_WIDTH);
+        if lit {
+            cell[y % CELL_HEIGHT] |= bit;
+        } else {
+            cell[y % CELL_HEIGHT] &= !bit;
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        x < self.width()
+            && y < self.height()
+            && self.cells[y / CELL_HEIGHT * self.columns + x / CELL_WIDTH][y % CELL_HEIGHT] & (1 << (CELL_WIDTH - 1 - x % CELL_WIDTH)) != 0
+    }
+
+    /// Take the characters for the cells from `cgram`, the images used by the most cells first.
+    /// Returns the characters to write for the cells (see `write_row`).
+    pub fn allocate(&self, cgram: &mut Cgram) -> [char; CELLS] {
+        // Distinct images, with the number of cells using each
+        let mut images = [[0; 8]; CELLS];
+        let mut uses = [0; CELLS];
+        let mut distinct = 0;
+        for cell in self.cells.iter().filter(|cell| **cell != [0; 8]) {
+            match images[..distinct].iter().position(|image| image == cell) {
+                Some(i) => uses[i] += 1,
+                None => {
+                    images[distinct] = *cell;
+                    uses[distinct] = 1;
+                    distinct += 1;
+                }
+            }
+        }
+        let mut order: [usize; CELLS] = core::array::from_fn(|i| i);
+        order[..distinct].sort_unstable_by_key(|&i| (Reverse(uses[i]), i));
+        let mut codes = [None; CELLS];
+        for i in order[..distinct].iter().copied() {
+            codes[i] = cgram.take(images[i]);
+        }
+        core::array::from_fn(|cell| {
+            let map = self.cells[cell];
+            if map == [0; 8] {
+                return ' ';
+            }
+            (0..distinct)
+                .filter_map(|i| codes[i].map(|code| (distance(&images[i], &map), code)))
+                .min_by_key(|(distance, _)| *distance)
+                .map_or(' ', |(_, code)| code)
+        })
+    }
+
+    /// Write the cells of the `row`, as `allocate` assigned them.
+    pub fn write_row<W: Write>(&self, w: &mut W, codes: &[char; CELLS], row: usize) -> fmt::Result {
+        codes[row * self.columns..(row + 1) * self.columns].iter().try_for_each(|c| w.write_char(*c))
+    }
+}
+
+/// Number of pixels different in the two images.
+fn distance(a: &[u8; 8], b: &[u8; 8]) -> u32 {
+    a.iter().zip(b).map(|(a, b)| (a ^ b).count_ones()).sum()
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn pixel_region() {
+        use std::string::String;
+        use crate::pixels::{Cgram, PixelRegion};
+
+        // Blank cells are spaces, cells with the same image share a character
+        let mut region = PixelRegion::<8>::new(4);
+        assert_eq!((region.width(), region.height()), (20, 16));
+        for x in [2, 7, 12] {
+            region.set(x, 0, true);
+        }
+        region.set(0, 15, true);
+        region.set(20, 0, true);
+        assert!(region.pixel(7, 0) && !region.pixel(8, 0) && !region.pixel(20, 0));
+        let mut cgram = Cgram::default();
+        cgram.reserve(0);
+        let codes = region.allocate(&mut cgram);
+        assert_eq!(codes, ['\u{1}', '\u{1}', '\u{1}', ' ', '\u{2}', ' ', ' ', ' ']);
+        assert_eq!(cgram.free(), 5);
+        let mut row = String::new();
+        region.write_row(&mut row, &codes, 1).unwrap();
+        assert_eq!(row, "\u{2}   ");
+
+        // Another widget gets the characters left, and shares the images it has in common
+        let mut other = PixelRegion::<8>::new(8);
+        other.set(2, 0, true);
+        for cell in 1..7 {
+            other.set(cell * 5, cell, true);
+        }
+        other.set(35, 5, true);
+        other.set(36, 5, true);
+        let codes = other.allocate(&mut cgram);
+        assert_eq!(cgram.free(), 0);
+        assert_eq!(codes[..6], ['\u{1}', '\u{3}', '\u{4}', '\u{5}', '\u{6}', '\u{7}']);
+        // The ones left over are drawn with the closest image
+        assert_eq!(codes[6..], ['\u{1}', '\u{7}']);
+        cgram.reserve(1);
+        assert_eq!(cgram.take([0; 8]), None);
+
+        region.clear();
+        assert_eq!(region.allocate(&mut Cgram::default()), [' '; 8]);
+    }
+}
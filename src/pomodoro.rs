@@ -0,0 +1,261 @@
+//! Pomodoro timer: work and break phases, one after another, with a beep at the end of each.
+//!
+//! Durations are set the way the countdown time is: turning the encoder starts editing the work
+//! duration, button moves to the break duration, and button on the break duration starts the
+//! first work phase. Turning the encoder while running stops the timer. Timer keeps running while
+//! other screens are shown.
+//!
+//! Like the stopwatch, time is passed to `advance`, which must be called before every event and
+//! every render (and often enough for the beeps, see `buzzing`).
+
+use core::fmt::{self, Write};
+use crate::display::COLUMNS;
+use crate::text::{self, Align};
+use crate::ui::Event;
+
+/// Beeps at the end of a phase.
+const BEEPS: u32 = 3;
+/// Beep period, in milliseconds, half of it on.
+const BEEP_MS: u32 = 400;
+
+const MINUTE_MS: u32 = 60_000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Work,
+    Break,
+}
+
+impl Phase {
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Work => "Work",
+            Phase::Break => "Break",
+        }
+    }
+}
+
+/// Field being edited.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Field {
+    Work,
+    Break,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Pomodoro {
+    /// Work phase duration, in minutes (1 to 99).
+    work_min: u8,
+    /// Break phase duration, in minutes (1 to 99).
+    break_min: u8,
+    cursor: Option<Field>,
+    /// Phase running and the time left in it.
+    running: Option<(Phase, u32)>,
+    /// Time since the last phase ended, while it beeps.
+    beeping: Option<u32>,
+    /// Work phases finished since boot.
+    done: u32,
+    /// Timer value of the last `advance`.
+    last_ms: u32,
+}
+
+impl Default for Pomodoro {
+    /// 25 minutes of work, 5 minutes of break.
+    fn default() -> Pomodoro {
+        Pomodoro {
+            work_min: 25,
+            break_min: 5,
+            cursor: None,
+            running: None,
+            beeping: None,
+            done: 0,
+            last_ms: 0,
+        }
+    }
+}
+
+impl Pomodoro {
+    /// Account the time passed since the last call (`now_ms` is the millisecond timer, which can
+    /// wrap around), going to the next phase when the time is up.
+    pub fn advance(&mut self, now_ms: u32) {
+        let mut passed = now_ms.wrapping_sub(self.last_ms);
+        self.last_ms = now_ms;
+        if let Some(since_ms) = self.beeping {
+            self.beeping = since_ms.checked_add(passed).filter(|&since_ms| since_ms < BEEPS * BEEP_MS);
+        }
+        let Some((mut phase, mut left_ms)) = self.running else {
+            return;
+        };
+        while passed >= left_ms {
+            passed -= left_ms;
+            if phase == Phase::Work {
+                self.done += 1;
+            }
+            phase = match phase {
+                Phase::Work => Phase::Break,
+                Phase::Break => Phase::Work,
+            };
+            left_ms = self.duration_ms(phase);
+            self.beeping = Some(passed).filter(|&since_ms| since_ms < BEEPS * BEEP_MS);
+        }
+        self.running = Some((phase, left_ms - passed));
+    }
+
+    fn duration_ms(&self, phase: Phase) -> u32 {
+        let minutes = match phase {
+            Phase::Work => self.work_min,
+            Phase::Break => self.break_min,
+        };
+        u32::from(minutes) * MINUTE_MS
+    }
+
+    /// Phase running, if any.
+    pub fn phase(&self) -> Option<Phase> {
+        self.running.map(|(phase, _)| phase)
+    }
+
+    /// Work phases finished since boot.
+    pub fn done(&self) -> u32 {
+        self.done
+    }
+
+    /// Should the buzzer be on now? Buzzer beeps a few times when a phase ends.
+    pub fn buzzing(&self) -> bool {
+        match self.beeping {
+            Some(since_ms) => since_ms % BEEP_MS < BEEP_MS / 2,
+            None => false,
+        }
+    }
+
+    /// Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        let step: i32 = match (self.running, event) {
+            (Some(_), Event::EncoderUp | Event::EncoderDown) => {
+                self.running = None;
+                self.beeping = None;
+                return true;
+            }
+            (Some(_), _) => return false,
+            (None, Event::Button) => {
+                match self.cursor {
+                    Some(Field::Work) => self.cursor = Some(Field::Break),
+                    Some(Field::Break) => {
+                        self.cursor = None;
+                        self.running = Some((Phase::Work, self.duration_ms(Phase::Work)));
+                    }
+                    None => return false,
+                }
+                return true;
+            }
+            (None, Event::EncoderUp) => 1,
+            (None, Event::EncoderDown) => -1,
+            _ => return false,
+        };
+        let minutes = match *self.cursor.get_or_insert(Field::Work) {
+            Field::Work => &mut self.work_min,
+            Field::Break => &mut self.break_min,
+        };
+        *minutes = ((i32::from(*minutes) - 1 + step).rem_euclid(99) + 1) as u8;
+        true
+    }
+
+    /// Write the phase, the time left in it and the number of work phases done
+    /// (`Work  24:59   3`), or, while stopped, the title and the number of work phases done.
+    pub fn write_status<W: Write>(&self, w: &mut W) -> fmt::Result {
+        let Some((phase, left_ms)) = self.running else {
+            w.write_str("Pomodoro")?;
+            return text::uint(w, self.done, 7);
+        };
+        text::str(w, phase.name(), 6, Align::Left)?;
+        // Rounded up, so zero is never shown
+        let secs = left_ms.div_ceil(1000);
+        write_two(w, (secs / 60) as u8)?;
+        w.write_char(':')?;
+        write_two(w, (secs % 60) as u8)?;
+        text::uint(w, self.done, 4)
+    }
+
+    /// Write the progress of the phase as a bar across the row, or, while stopped, the durations
+    /// with the one being edited in brackets (`Work[25]Brk 05`).
+    pub fn write_progress<W: Write>(&self, w: &mut W) -> fmt::Result {
+        let Some((phase, left_ms)) = self.running else {
+            w.write_str("Work")?;
+            write_field(w, self.work_min, self.cursor == Some(Field::Work))?;
+            w.write_str("Brk")?;
+            return write_field(w, self.break_min, self.cursor == Some(Field::Break));
+        };
+        let duration_ms = self.duration_ms(phase);
+        text::bar(w, duration_ms - left_ms, duration_ms, COLUMNS)
+    }
+}
+
+fn write_two<W: Write>(w: &mut W, value: u8) -> fmt::Result {
+    w.write_char((b'0' + value / 10 % 10) as char)?;
+    w.write_char((b'0' + value % 10) as char)
+}
+
+/// Write two-digit value, in brackets if it is being edited (spaces otherwise, so the text does
+/// not move).
+fn write_field<W: Write>(w: &mut W, value: u8, edited: bool) -> fmt::Result {
+    w.write_char(if edited { '[' } else { ' ' })?;
+    write_two(w, value)?;
+    w.write_char(if edited { ']' } else { ' ' })
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn pomodoro_phases() {
+        use crate::mock;
+        use crate::pomodoro::Phase;
+        use crate::screens::{Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::Pomodoro, &Stats::default(), settings);
+        assert_eq!(render(&settings), ("Pomodoro      0 ".into(), "Work 25 Brk 05  ".into()));
+
+        // One minute of work, one minute of break (durations wrap around within 1-99)
+        for _ in 0..24 {
+            assert!(settings.handle(Screen::Pomodoro, Event::EncoderDown));
+        }
+        assert_eq!(render(&settings).1, "Work[01]Brk 05  ");
+        assert!(settings.handle(Screen::Pomodoro, Event::Button));
+        for _ in 0..5 {
+            assert!(settings.handle(Screen::Pomodoro, Event::EncoderDown));
+        }
+        assert_eq!(render(&settings).1, "Work 01 Brk[99] ");
+        assert!(settings.handle(Screen::Pomodoro, Event::EncoderUp));
+        assert_eq!(render(&settings).1, "Work 01 Brk[01] ");
+        assert!(settings.handle(Screen::Pomodoro, Event::Button));
+        assert_eq!(settings.pomodoro.phase(), Some(Phase::Work));
+        assert_eq!(render(&settings), ("Work  01:00   0 ".into(), "----------------".into()));
+
+        settings.advance(15_000);
+        assert_eq!(render(&settings), ("Work  00:45   0 ".into(), "####------------".into()));
+        assert!(!settings.buzzing());
+
+        // Break starts with a few beeps
+        settings.advance(60_000);
+        assert_eq!(settings.pomodoro.phase(), Some(Phase::Break));
+        assert!(settings.buzzing());
+        assert_eq!(render(&settings), ("Break 01:00   1 ".into(), "----------------".into()));
+        settings.advance(60_250);
+        assert!(!settings.buzzing());
+        settings.advance(60_400);
+        assert!(settings.buzzing());
+        settings.advance(61_200);
+        assert!(!settings.buzzing());
+
+        // Several phases can pass between the calls
+        settings.advance(210_000);
+        assert_eq!(settings.pomodoro.done(), 2);
+        assert_eq!(render(&settings), ("Break 00:30   2 ".into(), "########--------".into()));
+
+        // Button is left for the navigation, encoder stops the timer
+        assert!(!settings.handle(Screen::Pomodoro, Event::Button));
+        assert!(settings.handle(Screen::Pomodoro, Event::EncoderUp));
+        assert_eq!(settings.pomodoro.phase(), None);
+        assert_eq!(render(&settings), ("Pomodoro      2 ".into(), "Work 01 Brk 01  ".into()));
+    }
+}
@@ -0,0 +1,88 @@
+//! Minimal GPIO port interface required by `LcdHardware`.
+
+/// GPIO port the LCD is connected to.
+pub trait Port {
+    /// Set `count` bits on the GPIO port starting from the bit number `offset`. Other bits are not
+    /// affected.
+    fn write_pin_range(&self, offset: usize, count: usize, data: u16);
+
+    /// Set single bit at the given `offset` in GPIO port.
+    fn write_pin(&self, offset: usize, bit: bool) {
+        self.write_pin_range(offset, 1, u16::from(bit));
+    }
+
+    /// Get `count` bits on the GPIO port starting from the bit number `offset`.
+    fn read_pin_range(&self, offset: usize, count: usize) -> u16;
+
+    /// Configure pin for output (push-pull).
+    fn output(&self, pin: usize);
+
+    /// Configure pin for output (open-drain): written high, it is released, and pulled up
+    /// externally.
+    fn open_drain(&self, pin: usize);
+
+    /// Configure pin for input (floating).
+    fn input(&self, pin: usize);
+}
+
+impl<P: Port + ?Sized> Port for &P {
+    fn write_pin_range(&self, offset: usize, count: usize, data: u16) {
+        P::write_pin_range(self, offset, count, data);
+    }
+
+    fn read_pin_range(&self, offset: usize, count: usize) -> u16 {
+        P::read_pin_range(self, offset, count)
+    }
+
+    fn output(&self, pin: usize) {
+        P::output(self, pin);
+    }
+
+    fn open_drain(&self, pin: usize) {
+        P::open_drain(self, pin);
+    }
+
+    fn input(&self, pin: usize) {
+        P::input(self, pin);
+    }
+}
+
+#[cfg(target_arch = "arm")]
+mod stm32f103 {
+    use stm32f1::stm32f103::{GPIOA, GPIOB, GPIOC};
+    use crate::gpio::GPIOExtras;
+    use super::Port;
+
+    /// Implement `Port` for GPIO port peripherals (they all share the same register block).
+    macro_rules! port {
+        ($($GPIO:ident),+) => {
+            $(
+                impl Port for $GPIO {
+                    fn write_pin_range(&self, offset: usize, count: usize, data: u16) {
+                        GPIOExtras::write_pin_range(&**self, offset, count, data);
+                    }
+
+                    fn read_pin_range(&self, offset: usize, count: usize) -> u16 {
+                        GPIOExtras::read_pin_range(&**self, offset, count)
+                    }
+
+                    /// Pin could have been pulled up or down, so the general purpose output is set
+                    /// explicitly (same bit selects the alternate function).
+                    fn output(&self, pin: usize) {
+                        self.pin_config(pin).general().push_pull().output2();
+                    }
+
+                    fn open_drain(&self, pin: usize) {
+                        self.pin_config(pin).general().open_drain().output2();
+                    }
+
+                    fn input(&self, pin: usize) {
+                        self.pin_config(pin).input().floating();
+                    }
+                }
+            )+
+        };
+    }
+
+    port!(GPIOA, GPIOB, GPIOC);
+}
@@ -0,0 +1,222 @@
+//! Low-power idle between display refreshes.
+//!
+//! By default, core sleeps with WFI until the next SysTick (or any other) interrupt. With the
+//! `stop-mode` feature, the whole chip goes into Stop mode instead and is woken up by the RTC
+//! alarm, which saves much more power, but takes longer to wake up (clocks need to be
+//! reconfigured). Note that debugger loses the connection in Stop mode.
+
+use crate::time;
+
+/// Estimated share of time the core is awake, over a sliding window of about `WINDOW_MS`.
+struct DutyCycle {
+    busy_ms: u32,
+    idle_ms: u32,
+}
+
+/// Both counters are halved once total time exceeds this, so old history fades away.
+const WINDOW_MS: u32 = 10_000;
+
+impl DutyCycle {
+    fn record(&mut self, busy_ms: u32, idle_ms: u32) {
+        self.busy_ms += busy_ms;
+        self.idle_ms += idle_ms;
+        if self.busy_ms + self.idle_ms > WINDOW_MS {
+            self.busy_ms /= 2;
+            self.idle_ms /= 2;
+        }
+    }
+
+    fn percent(&self) -> u8 {
+        let total = self.busy_ms + self.idle_ms;
+        if total == 0 {
+            return 100;
+        }
+        (self.busy_ms * 100 / total) as u8
+    }
+}
+
+/// A way to wait until a deadline with the core stopped.
+pub trait Idle {
+    /// Sleep until `deadline` (see `time::millis`).
+    fn idle_until(&mut self, deadline: u32);
+}
+
+/// Sleep mode: only the core clock is stopped, SysTick wakes it up every millisecond.
+pub struct Wfi;
+
+impl Idle for Wfi {
+    fn idle_until(&mut self, deadline: u32) {
+        while !time::reached(deadline) {
+            cortex_m::asm::wfi();
+        }
+    }
+}
+
+/// Power manager: puts the core to sleep and keeps track of the duty cycle.
+pub struct Power<I: Idle> {
+    idle: I,
+    duty: DutyCycle,
+    woken_at: u32,
+}
+
+impl<I: Idle> Power<I> {
+    pub fn new(idle: I) -> Power<I> {
+        Power {
+            idle,
+            duty: DutyCycle { busy_ms: 0, idle_ms: 0 },
+            woken_at: time::millis(),
+        }
+    }
+
+    /// Sleep until `deadline` (see `time::millis`). Returns immediately if deadline is already
+    /// reached.
+    pub fn sleep_until(&mut self, deadline: u32) {
+        let start = time::millis();
+        if !time::reached(deadline) {
+            self.idle.idle_until(deadline);
+        }
+        let now = time::millis();
+        self.duty.record(start.wrapping_sub(self.woken_at), now.wrapping_sub(start));
+        self.woken_at = now;
+    }
+
+    /// Estimated percentage of time the core is awake.
+    pub fn duty_cycle(&self) -> u8 {
+        self.duty.percent()
+    }
+}
+
+/// Put the chip into Standby mode for good: everything but the backup domain is powered down, only
+/// a reset (or the watchdog, if it was started) wakes it up, starting over.
+#[cfg(feature = "battery")]
+pub fn standby(mut scb: cortex_m::peripheral::SCB, pwr: &stm32f1::stm32f103::PWR) -> ! {
+    pwr.cr.modify(|_, w| w.pdds().set_bit().cwuf().set_bit());
+    scb.set_sleepdeep();
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
+#[cfg(feature = "stop-mode")]
+pub use self::stop::Stop;
+
+#[cfg(feature = "stop-mode")]
+mod stop {
+    use cortex_m::peripheral::{NVIC, SCB};
+    use stm32f1::stm32f103::{Interrupt, EXTI, FLASH, PWR, RCC, RTC};
+    use crate::{clock, delay, time};
+    use super::Idle;
+
+    /// RTC runs from LSI (there is no LSE on some boards), 40Khz / 40 gives 1ms resolution.
+    const RTC_PRESCALER: u32 = 40;
+
+    /// Stop mode: all clocks except LSI are stopped, RTC alarm wakes the chip up.
+    pub struct Stop {
+        scb: SCB,
+        pwr: PWR,
+        rtc: RTC,
+        exti: EXTI,
+        rcc: RCC,
+        flash: FLASH,
+    }
+
+    impl Stop {
+        /// Configure RTC to count milliseconds and to raise an EXTI event on alarm. Resets the
+        /// backup domain if RTC was clocked from another source.
+        pub fn new(scb: SCB, pwr: PWR, rtc: RTC, exti: EXTI, rcc: RCC, flash: FLASH) -> Stop {
+            rcc.apb1enr.modify(|_, w| w.pwren().set_bit().bkpen().set_bit());
+            pwr.cr.modify(|_, w| w.dbp().set_bit());
+
+            rcc.csr.modify(|_, w| w.lsion().set_bit());
+            while rcc.csr.read().lsirdy().bit_is_clear() {}
+
+            if !rcc.bdcr.read().rtcsel().is_lsi() {
+                rcc.bdcr.modify(|_, w| w.bdrst().set_bit());
+                rcc.bdcr.modify(|_, w| w.bdrst().clear_bit());
+                rcc.bdcr.modify(|_, w| w.rtcsel().lsi());
+            }
+            rcc.bdcr.modify(|_, w| w.rtcen().set_bit());
+
+            let stop = Stop { scb, pwr, rtc, exti, rcc, flash };
+            stop.configure(|rtc| {
+                rtc.prlh.write(|w| w.prlh().bits(0));
+                rtc.prll.write(|w| w.prll().bits((RTC_PRESCALER - 1) as u16));
+            });
+            stop.rtc.crh.modify(|_, w| w.alrie().set_bit());
+
+            // RTC alarm is connected to EXTI line 17
+            stop.exti.rtsr.modify(|_, w| w.tr17().set_bit());
+            stop.exti.imr.modify(|_, w| w.mr17().set_bit());
+            stop
+        }
+
+        /// Write RTC configuration registers (prescaler, counter, alarm).
+        fn configure<F: FnOnce(&RTC)>(&self, f: F) {
+            let rtc = &self.rtc;
+            while rtc.crl.read().rtoff().bit_is_clear() {}
+            rtc.crl.modify(|_, w| w.cnf().set_bit());
+            f(rtc);
+            rtc.crl.modify(|_, w| w.cnf().clear_bit());
+            while rtc.crl.read().rtoff().bit_is_clear() {}
+        }
+
+        /// Read RTC counter. Registers need to be re-synchronized after wakeup, since APB1 clock
+        /// was stopped.
+        fn counter(&self) -> u32 {
+            let rtc = &self.rtc;
+            rtc.crl.modify(|_, w| w.rsf().clear_bit());
+            while rtc.crl.read().rsf().bit_is_clear() {}
+            loop {
+                let high = rtc.cnth.read().cnth().bits();
+                let low = rtc.cntl.read().cntl().bits();
+                if high == rtc.cnth.read().cnth().bits() {
+                    return (u32::from(high) << 16) | u32::from(low);
+                }
+            }
+        }
+
+        fn clear_alarm(&self) {
+            self.rtc.crl.modify(|_, w| w.alrf().clear_bit());
+            self.exti.pr.write(|w| w.pr17().set_bit());
+            NVIC::unpend(Interrupt::RTCALARM);
+        }
+    }
+
+    impl Idle for Stop {
+        fn idle_until(&mut self, deadline: u32) {
+            let ms = deadline.wrapping_sub(time::millis());
+            let start = self.counter();
+            let alarm = start.wrapping_add(ms);
+            self.configure(|rtc| {
+                rtc.alrh.write(|w| w.alrh().bits((alarm >> 16) as u16));
+                rtc.alrl.write(|w| w.alrl().bits(alarm as u16));
+            });
+
+            cortex_m::interrupt::free(|_| {
+                self.clear_alarm();
+                // Pending SysTick would wake us up immediately; it is stopped in Stop mode anyway
+                SCB::clear_pendst();
+
+                // RTC alarm interrupt is never taken (interrupts are disabled), but it still
+                // wakes the core up.
+                unsafe { NVIC::unmask(Interrupt::RTCALARM) };
+                self.pwr.cr.modify(|_, w| w.pdds().clear_bit().lpds().set_bit());
+                self.scb.set_sleepdeep();
+                while self.rtc.crl.read().alrf().bit_is_clear() {
+                    cortex_m::asm::wfi();
+                }
+                self.scb.clear_sleepdeep();
+                NVIC::mask(Interrupt::RTCALARM);
+                self.clear_alarm();
+
+                // Woken up on HSI, restore clocks
+                let clocks = match clock::setup(&self.rcc, &self.flash) {
+                    Ok(clocks) => clocks,
+                    Err(err) => panic!("{}", err),
+                };
+                delay::set_hclk(clocks.hclk);
+                time::advance(self.counter().wrapping_sub(start));
+            });
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Progress bar for the long operations (erasing flash, reading a card, calibrating a sensor),
+//! which hold up the main loop, so the screens are not refreshed meanwhile. `with_progress` shows
+//! the label and the percentage done in the top row, and the bar in the bottom one, in steps of a
+//! pixel column: the bar is a pixel region (see `pixels`), so it takes two custom characters at
+//! most (the full cell and the partial one), and the low battery icon keeps its own.
+//!
+//! Operation reports its progress as often as it likes. The display is only written when the bar
+//! or the percentage has changed, and not more often than every `MIN_INTERVAL_MS` (writing both
+//! rows takes a couple of milliseconds, and the queued driver drops what does not fit), except for
+//! the last update, which is always shown. With the framebuffer, the caller flushes it.
+
+use core::fmt::{self, Write};
+use crate::display::{Line, TextDisplay, COLUMNS};
+use crate::pixels::{Cgram, PixelRegion, CELL_HEIGHT, CELL_WIDTH};
+use crate::screens::LOW_BATTERY;
+use crate::text::{self, Align};
+
+/// Display is not written more often than that, in milliseconds.
+pub const MIN_INTERVAL_MS: u32 = 100;
+
+/// Progress of the operation, see `with_progress`.
+pub struct Progress<'a, D: TextDisplay> {
+    display: &'a mut D,
+    millis: fn() -> u32,
+    label: &'a str,
+    total: u32,
+    done: u32,
+    /// Bar length (in pixel columns) and percentage shown, and when it was shown.
+    shown: Option<(usize, u32, u32)>,
+}
+
+/// Run the operation `f`, which reports to the `Progress` how much of the `total` work is done,
+/// showing the `label` (cut to 12 characters) and the bar. `millis` is the clock.
+pub fn with_progress<D: TextDisplay, R>(
+    display: &mut D,
+    millis: fn() -> u32,
+    label: &str,
+    total: u32,
+    f: impl FnOnce(&mut Progress<'_, D>) -> R,
+) -> R {
+    let mut progress = Progress { display, millis, label, total, done: 0, shown: None };
+    progress.update();
+    f(&mut progress)
+}
+
+impl<D: TextDisplay> Progress<'_, D> {
+    /// Work done so far.
+    pub fn done(&self) -> u32 {
+        self.done
+    }
+
+    /// Set the work done (up to the total).
+    pub fn set(&mut self, done: u32) {
+        self.done = done.min(self.total);
+        self.update();
+    }
+
+    /// Add `count` to the work done.
+    pub fn advance(&mut self, count: u32) {
+        self.set(self.done.saturating_add(count));
+    }
+
+    /// Bar length, in pixel columns, and the percentage done.
+    fn measure(&self) -> (usize, u32) {
+        let part = |scale: u64| (u64::from(self.done) * scale).checked_div(u64::from(self.total)).unwrap_or(scale);
+        (part((COLUMNS * CELL_WIDTH) as u64) as usize, part(100) as u32)
+    }
+
+    /// Show the progress if it has changed, and the display is due. Failed write is retried on the
+    /// next update.
+    fn update(&mut self) {
+        let (bar, percent) = self.measure();
+        let now = (self.millis)();
+        let due = match self.shown {
+            None => true,
+            Some((shown_bar, shown_percent, since)) => {
+                (bar, percent) != (shown_bar, shown_percent)
+                    && (self.done == self.total || now.wrapping_sub(since) >= MIN_INTERVAL_MS)
+            }
+        };
+        if due {
+            self.shown = self.render(bar, percent).ok().map(|_| (bar, percent, now));
+        }
+    }
+
+    fn render(&mut self, bar: usize, percent: u32) -> fmt::Result {
+        let mut line = Line::new(self.display, 0);
+        let label = self.label.get(..COLUMNS - 4).unwrap_or(self.label);
+        text::str(&mut line, label, COLUMNS - 4, Align::Left)?;
+        text::uint(&mut line, percent, 3)?;
+        line.write_char('%')?;
+        line.finish()?;
+
+        let mut region = PixelRegion::<COLUMNS>::new(COLUMNS);
+        for x in 0..bar {
+            // Top and bottom pixel rows are left blank
+            for y in 1..CELL_HEIGHT - 1 {
+                region.set(x, y, true);
+            }
+        }
+        let mut cgram = Cgram::default();
+        cgram.reserve(LOW_BATTERY as u8);
+        let codes = region.allocate(&mut cgram);
+        cgram.upload(self.display)?;
+        let mut line = Line::new(self.display, 1);
+        region.write_row(&mut line, &codes, 0)?;
+        line.finish()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn progress_bar() {
+        use lcd::Display;
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use crate::hardware::LcdHardware;
+        use crate::mock::{init, MockHardware};
+        use crate::progress::{with_progress, MIN_INTERVAL_MS};
+        use crate::sim::Hd44780;
+
+        static NOW: AtomicU32 = AtomicU32::new(0);
+        let millis = || NOW.load(Ordering::Relaxed);
+        let mock = MockHardware::new();
+        init(&mock);
+        let mut display = Display::new(LcdHardware::new(&mock, &mock));
+        let mut lcd = Hd44780::new();
+        let mut shown = || {
+            lcd.feed(&mock.transfers());
+            let written = !mock.transfers().is_empty();
+            mock.reset();
+            (lcd.row(0, 16), lcd.row(1, 16), written)
+        };
+
+        let result = with_progress(&mut display, millis, "Erasing flash pages", 64, |progress| {
+            // Label is cut, the bar is empty at the start
+            assert_eq!(shown(), ("Erasing flas  0%".into(), " ".repeat(16), true));
+
+            // Bar grows a pixel column at a time, but not faster than the display could take
+            progress.set(3);
+            assert_eq!(shown(), ("Erasing flas  0%".into(), " ".repeat(16), false));
+            NOW.store(MIN_INTERVAL_MS, Ordering::Relaxed);
+            progress.advance(1);
+            assert_eq!(shown(), ("Erasing flas  6%".into(), format!("#{}", " ".repeat(15)), true));
+            NOW.store(2 * MIN_INTERVAL_MS, Ordering::Relaxed);
+            progress.set(34);
+            assert_eq!(shown(), ("Erasing flas 53%".into(), format!("{}{}", "#".repeat(9), " ".repeat(7)), true));
+
+            // Nothing changed, nothing is written; the end is always shown
+            NOW.store(3 * MIN_INTERVAL_MS, Ordering::Relaxed);
+            progress.set(34);
+            assert_eq!(shown().2, false);
+            progress.set(100);
+            assert_eq!(progress.done(), 64);
+            assert_eq!(shown(), ("Erasing flas100%".into(), "#".repeat(16), true));
+            7
+        });
+        assert_eq!(result, 7);
+
+        // Nothing to do is done
+        with_progress(&mut display, millis, "Reading", 0, |_| ());
+        assert_eq!(shown().0, "Reading     100%");
+        // Bar takes two custom characters at most: the full cell and the partial one
+        assert_eq!(lcd.glyph(1), [0, 0b11111, 0b11111, 0b11111, 0b11111, 0b11111, 0b11111, 0]);
+    }
+}
@@ -0,0 +1,62 @@
+//! Pulse input on EXTI: hall (or opto) sensor, an open collector pulling `board::TACH` low, for
+//! the tachometer and the totalizer (which count the same pulses, if both are enabled).
+//!
+//! The interrupt (see `interrupt`) counts the falling edges and stamps the last one with the
+//! millisecond timer; there is no input filter on EXTI, so the sensor must give clean edges (hall
+//! switches do, reed switches need a debouncing capacitor). In Stop mode (`stop-mode` feature) the
+//! timer is behind until the wakeup is accounted, so the stamps are off there.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use stm32f1::stm32f103::{Interrupt, AFIO, EXTI, RCC};
+use crate::board::{self, PortName};
+use crate::gpio::{self, GPIOExtras};
+use crate::time;
+
+/// EXTI interrupt of the pin, the caller must unmask it in NVIC.
+pub const INTERRUPT: Interrupt = match board::TACH.index {
+    1 => Interrupt::EXTI1,
+    2 => Interrupt::EXTI2,
+    _ => panic!("tachometer pin has no interrupt of its own"),
+};
+
+/// Pulses counted, wrapping around.
+static PULSES: AtomicU32 = AtomicU32::new(0);
+/// Timer value at the last pulse.
+static LAST_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Configure the pin as an input with a pull-up and its EXTI line to interrupt on the falling
+/// edge. EXTI itself is shared with the Stop mode, which only touches its own line.
+pub fn init(rcc: &RCC) {
+    let pin = board::TACH;
+    let port = gpio::enable_port(rcc, pin.port);
+    port.write_pin(pin.index, true);
+    port.pin_config(pin.index).input().pull_up_down();
+
+    rcc.apb2enr.modify(|_, w| w.afioen().set_bit());
+    let afio = unsafe { &*AFIO::ptr() };
+    let code = match pin.port {
+        PortName::A => 0,
+        PortName::B => 1,
+        PortName::C => 2,
+    };
+    let shift = 4 * pin.index;
+    afio.exticr1.modify(|r, w| unsafe { w.bits((r.bits() & !(0xf << shift)) | (code << shift)) });
+
+    let exti = unsafe { &*EXTI::ptr() };
+    let line = 1 << pin.index;
+    exti.ftsr.modify(|r, w| unsafe { w.bits(r.bits() | line) });
+    exti.imr.modify(|r, w| unsafe { w.bits(r.bits() | line) });
+}
+
+/// Pulses counted so far (wrapping around) and the timer value at the last one.
+pub fn read() -> (u32, u32) {
+    cortex_m::interrupt::free(|_| (PULSES.load(Ordering::Relaxed), LAST_MS.load(Ordering::Relaxed)))
+}
+
+/// Count the pulse. Called from the EXTI interrupt handler.
+pub fn interrupt() {
+    let exti = unsafe { &*EXTI::ptr() };
+    exti.pr.write(|w| unsafe { w.bits(1 << board::TACH.index) });
+    LAST_MS.store(time::millis(), Ordering::Relaxed);
+    PULSES.fetch_add(1, Ordering::Relaxed);
+}
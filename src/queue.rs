@@ -0,0 +1,202 @@
+//! Command-queue LCD driver: writes are queued and clocked out to the display asynchronously.
+//!
+//! `Writer` pushes commands into a lock-free single-producer/single-consumer queue, and `Engine`
+//! sends them one by one, from a timer interrupt. After each command, engine tells how long to
+//! wait until the next one (37us for most of the commands, 1.52ms for clear and home), so nobody
+//! busy-waits for the display to complete the command.
+//!
+//! Display must be initialized by the blocking `lcd::Display` first; then hardware is moved into
+//! the `Engine`.
+
+use core::fmt;
+use heapless::spsc::{Consumer, Producer};
+use crate::display::TextDisplay;
+
+/// Queue capacity (queue of size `N` holds `N - 1` commands). Enough for redrawing the whole
+/// 16x2 display twice, or once together with all the custom characters.
+pub const QUEUE_SIZE: usize = 128;
+
+pub type Queue = heapless::spsc::Queue<Command, QUEUE_SIZE>;
+
+const CLEAR: u8 = 0x01;
+const SET_CGRAM_ADDR: u8 = 0x40;
+const SET_DDRAM_ADDR: u8 = 0x80;
+
+/// Command execution time, with some margin (datasheet says 37us).
+const COMMAND_US: u32 = 50;
+/// Clear and home execution time, with some margin (datasheet says 1.52ms).
+const CLEAR_US: u32 = 2000;
+/// Extra time to update address counter after data write (tADD).
+const ADDRESS_UPDATE_US: u32 = 5;
+
+/// Single transfer to the display.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Instruction (RS is low).
+    Instruction(u8),
+    /// Data write (RS is high).
+    Data(u8),
+}
+
+impl Command {
+    /// How long it takes to execute the command.
+    fn execution_us(self) -> u32 {
+        match self {
+            // Clear and home are the only instructions with the highest bits cleared
+            Command::Instruction(cmd) if cmd & 0xfc == 0 => CLEAR_US,
+            Command::Instruction(_) => COMMAND_US,
+            Command::Data(_) => COMMAND_US + ADDRESS_UPDATE_US,
+        }
+    }
+}
+
+/// Producer side: text display writing into the queue.
+pub struct Writer<'a> {
+    producer: Producer<'a, Command, QUEUE_SIZE>,
+    /// Wake up the engine (for example, pend the timer interrupt).
+    kick: fn(),
+    /// Can we wait for the engine to free some space in the queue?
+    can_wait: fn() -> bool,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(producer: Producer<'a, Command, QUEUE_SIZE>, kick: fn(), can_wait: fn() -> bool) -> Writer<'a> {
+        Writer {
+            producer,
+            kick,
+            can_wait,
+        }
+    }
+
+    /// Queue a command. If queue is full, waits until engine sends some of the commands; fails
+    /// if waiting is not possible (engine cannot run while interrupts are disabled).
+    pub fn push(&mut self, cmd: Command) -> fmt::Result {
+        while !self.producer.ready() {
+            if !(self.can_wait)() {
+                return Err(fmt::Error);
+            }
+            (self.kick)();
+        }
+        self.producer.enqueue(cmd).map_err(|_| fmt::Error)?;
+        (self.kick)();
+        Ok(())
+    }
+}
+
+impl fmt::Write for Writer<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &b in s.as_bytes() {
+            self.push(Command::Data(b))?;
+        }
+        Ok(())
+    }
+}
+
+impl TextDisplay for Writer<'_> {
+    fn clear(&mut self) -> fmt::Result {
+        self.push(Command::Instruction(CLEAR))
+    }
+
+    fn position(&mut self, col: u8, row: u8) -> fmt::Result {
+        let offset = if row == 1 { 0x40 } else { 0 };
+        self.push(Command::Instruction(SET_DDRAM_ADDR | (col + offset)))
+    }
+
+    fn upload(&mut self, location: u8, map: [u8; 8]) -> fmt::Result {
+        if location > 7 {
+            return Err(fmt::Error);
+        }
+        self.push(Command::Instruction(SET_CGRAM_ADDR | (location << 3)))?;
+        map.iter().try_for_each(|&row| self.push(Command::Data(row)))
+    }
+}
+
+/// Consumer side: sends commands to the display, one command per `step`.
+pub struct Engine<'a, HW: lcd::Hardware + lcd::Delay> {
+    hw: HW,
+    consumer: Consumer<'a, Command, QUEUE_SIZE>,
+}
+
+impl<'a, HW: lcd::Hardware + lcd::Delay> Engine<'a, HW> {
+    /// `hw` must be already initialized for 4-bit mode.
+    pub fn new(hw: HW, consumer: Consumer<'a, Command, QUEUE_SIZE>) -> Engine<'a, HW> {
+        Engine { hw, consumer }
+    }
+
+    /// Access the hardware (for example, to check display health). Engine must not be stepped
+    /// in the meantime.
+    pub fn hardware(&mut self) -> &mut HW {
+        &mut self.hw
+    }
+
+    /// Have all the commands queued been sent?
+    pub fn is_idle(&self) -> bool {
+        self.consumer.len() == 0
+    }
+
+    /// Take the engine apart (for example, to re-initialize the display with the blocking driver).
+    pub fn into_parts(self) -> (HW, Consumer<'a, Command, QUEUE_SIZE>) {
+        (self.hw, self.consumer)
+    }
+
+    /// Send the next command, if any. Returns time to wait until the next step, in microseconds,
+    /// or `None` if queue is empty. Takes few microseconds (pulses on the enable line).
+    pub fn step(&mut self) -> Option<u32> {
+        let cmd = self.consumer.dequeue()?;
+        let (rs, byte) = match cmd {
+            Command::Instruction(b) => (false, b),
+            Command::Data(b) => (true, b),
+        };
+
+        self.hw.rs(rs);
+        self.hw.delay_us(1); // tAS
+        self.send_nibble(byte >> 4);
+        self.send_nibble(byte & 0xf);
+        Some(cmd.execution_us())
+    }
+
+    fn send_nibble(&mut self, nibble: u8) {
+        self.hw.data(nibble);
+        self.hw.enable(true);
+        self.hw.delay_us(1); // minimum delay is 450 ns
+        self.hw.enable(false);
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn queued_writes_match_blocking() {
+        use core::fmt::Write;
+        use std::vec::Vec;
+        use crate::display::TextDisplay;
+        use crate::hardware::LcdHardware;
+        use crate::mock::{command, data, init, join_nibbles, MockHardware};
+        use crate::queue::{Engine, Queue, Writer};
+
+        let mock = MockHardware::new();
+        init(&mock);
+        mock.reset();
+
+        let mut queue = Queue::new();
+        let (producer, consumer) = queue.split();
+        let mut writer = Writer::new(producer, || {}, || false);
+        writer.clear().unwrap();
+        writer.position(3, 1).unwrap();
+        writer.write_str("Hi").unwrap();
+
+        let mut engine = Engine::new(LcdHardware::new(&mock, &mock), consumer);
+        let mut waits = Vec::new();
+        while let Some(us) = engine.step() {
+            waits.push(us);
+        }
+
+        assert_eq!(
+            join_nibbles(&mock.transfers()),
+            vec![command(0x01), command(0x80 | 0x43), data(b'H'), data(b'i')]
+        );
+        // Clear takes up to 1.52ms, other commands take 37us
+        assert!(waits[0] >= 1_520);
+        assert!(waits[1..].iter().all(|&us| us >= 37));
+    }
+}
@@ -0,0 +1,37 @@
+//! Random numbers for the games and anything else which needs them (retry delays, for example).
+//!
+//! Generator is a Weyl sequence scrambled by the MurmurHash3 finalizer. Its state is a single
+//! atomic word, so numbers can be taken anywhere (interrupt handlers included) without locking.
+//! Entropy is stirred into the state by `mix`: on the board, that is the ADC noise and the cycle
+//! counter at the user input (see `main`). Good enough for dice, not for cryptography.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Weyl sequence increment (golden ratio), odd, so the sequence goes through every state.
+const INCREMENT: u32 = 0x9e37_79b9;
+
+static STATE: AtomicU32 = AtomicU32::new(0x2545_f491);
+
+/// Stir `entropy` into the generator state.
+pub fn mix(entropy: u32) {
+    let _ = STATE.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |state| Some(scramble(state ^ entropy)));
+}
+
+/// Next random number.
+pub fn next_u32() -> u32 {
+    scramble(STATE.fetch_add(INCREMENT, Ordering::Relaxed).wrapping_add(INCREMENT))
+}
+
+/// Random number below `bound` (zero if `bound` is zero). Bias is at most `bound / 2^32`.
+pub fn below(bound: u32) -> u32 {
+    ((u64::from(next_u32()) * u64::from(bound)) >> 32) as u32
+}
+
+/// MurmurHash3 finalizer: every input bit affects every output bit.
+fn scramble(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85eb_ca6b);
+    x ^= x >> 13;
+    x = x.wrapping_mul(0xc2b2_ae35);
+    x ^ (x >> 16)
+}
@@ -0,0 +1,267 @@
+//! Reaction-time game: button starts a round, after a random delay (1 to 4 seconds) the whole
+//! display lights up and the button must be pressed as fast as possible. Pressing it before that
+//! is a false start. Three best times are kept (and stored in flash by the main loop, see
+//! `scores`).
+//!
+//! There is no backlight control on the board, so the "flash" is both rows filled with the full
+//! custom character.
+//!
+//! Button pressed on the outcome of the round is left for the navigation (the next round starts
+//! from the beginning when the screen is shown again), encoder cancels the round.
+//! Like the stopwatch, time is passed to `advance`, which must be called before every event and
+//! every render.
+
+use core::fmt::{self, Write};
+use crate::big;
+use crate::display::COLUMNS;
+use crate::random;
+use crate::text::{self, Align};
+use crate::ui::Event;
+
+/// Best times kept.
+pub const SCORES: usize = 3;
+/// Empty place in the best times.
+const EMPTY: u16 = u16::MAX;
+
+/// Delay before the display lights up, in milliseconds: at least `DELAY_MIN_MS`, plus up to
+/// `DELAY_SPAN_MS`.
+const DELAY_MIN_MS: u32 = 1000;
+const DELAY_SPAN_MS: u32 = 3000;
+/// Round is lost if button is not pressed in time, in milliseconds.
+const TIMEOUT_MS: u32 = 2000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Reaction time, in milliseconds, and the place in the best times, if it got there.
+    Time(u16, Option<usize>),
+    /// Button was pressed before the display lit up.
+    TooSoon,
+    /// Button was not pressed in `TIMEOUT_MS`.
+    TooSlow,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    Idle,
+    /// Waiting for the display to light up: round start and the delay.
+    Waiting(u32, u32),
+    /// Display is lit, since the given time.
+    Go(u32),
+    Done(Outcome),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Reaction {
+    state: State,
+    /// Best times, in milliseconds, fastest first (`EMPTY` if there is none yet).
+    best: [u16; SCORES],
+    /// Timer value of the last `advance`.
+    last_ms: u32,
+}
+
+impl Default for Reaction {
+    fn default() -> Reaction {
+        Reaction {
+            state: State::Idle,
+            best: [EMPTY; SCORES],
+            last_ms: 0,
+        }
+    }
+}
+
+impl Reaction {
+    /// Light up the display when the delay is over, end the round when it is not reacted to in
+    /// time (`now_ms` is the millisecond timer, which can wrap around).
+    pub fn advance(&mut self, now_ms: u32) {
+        self.last_ms = now_ms;
+        match self.state {
+            State::Waiting(start_ms, delay_ms) if now_ms.wrapping_sub(start_ms) >= delay_ms => {
+                self.state = State::Go(now_ms);
+            }
+            State::Go(since_ms) if now_ms.wrapping_sub(since_ms) >= TIMEOUT_MS => {
+                self.state = State::Done(Outcome::TooSlow);
+            }
+            _ => {}
+        }
+    }
+
+    /// Is the display lit (should be re-rendered right away when it becomes so)?
+    pub fn lit(&self) -> bool {
+        matches!(self.state, State::Go(_))
+    }
+
+    /// Outcome of the last round, if it is shown.
+    pub fn outcome(&self) -> Option<Outcome> {
+        match self.state {
+            State::Done(outcome) => Some(outcome),
+            _ => None,
+        }
+    }
+
+    /// Best times, in milliseconds, fastest first (`u16::MAX` for the empty places).
+    pub fn scores(&self) -> [u16; SCORES] {
+        self.best
+    }
+
+    /// Restore best times (as returned by `scores`).
+    pub fn set_scores(&mut self, scores: [u16; SCORES]) {
+        self.best = scores;
+        self.best.sort_unstable();
+    }
+
+    /// Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        self.state = match (self.state, event) {
+            (State::Done(_), Event::Button) => {
+                self.state = State::Idle;
+                return false;
+            }
+            (State::Idle, Event::Button) => State::Waiting(self.last_ms, DELAY_MIN_MS + random::below(DELAY_SPAN_MS)),
+            (State::Waiting(..), Event::Button) => State::Done(Outcome::TooSoon),
+            (State::Go(since_ms), Event::Button) => {
+                let time = self.last_ms.wrapping_sub(since_ms).min(u32::from(EMPTY - 1)) as u16;
+                State::Done(Outcome::Time(time, self.record(time)))
+            }
+            (State::Waiting(..) | State::Go(_), Event::EncoderUp | Event::EncoderDown) => State::Idle,
+            _ => return false,
+        };
+        true
+    }
+
+    /// Put the time into the best times, returns its place there (zero-based).
+    fn record(&mut self, time: u16) -> Option<usize> {
+        let place = self.best.iter().position(|&best| time < best)?;
+        self.best[place..].rotate_right(1);
+        self.best[place] = time;
+        Some(place)
+    }
+
+    /// Write the prompt or the outcome of the round (`Time  215ms  #1`). Full row while lit.
+    pub fn write_status<W: Write>(&self, w: &mut W) -> fmt::Result {
+        match self.state {
+            State::Idle => w.write_str("Reaction  press"),
+            State::Waiting(..) => w.write_str("Wait..."),
+            State::Go(_) => write_lit(w),
+            State::Done(Outcome::TooSoon) => w.write_str("Too soon!"),
+            State::Done(Outcome::TooSlow) => w.write_str("Too slow!"),
+            State::Done(Outcome::Time(time, place)) => {
+                w.write_str("Time")?;
+                text::uint(w, u32::from(time), 5)?;
+                w.write_str("ms")?;
+                if let Some(place) = place {
+                    w.write_str("  #")?;
+                    text::uint(w, place as u32 + 1, 1)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Write the best times (`Best 215 243   -`), nothing while waiting. Full row while lit.
+    pub fn write_scores<W: Write>(&self, w: &mut W) -> fmt::Result {
+        match self.state {
+            State::Waiting(..) => Ok(()),
+            State::Go(_) => write_lit(w),
+            _ => {
+                w.write_str("Best")?;
+                for best in self.best {
+                    if best == EMPTY {
+                        text::str(w, "-", 4, Align::Right)?;
+                    } else {
+                        text::uint(w, u32::from(best), 4)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn write_lit<W: Write>(w: &mut W) -> fmt::Result {
+    (0..COLUMNS).try_for_each(|_| w.write_char(big::FULL))
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn reaction_game() {
+        use crate::mock;
+        use crate::reaction::Outcome;
+        use crate::screens::{self, Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::Reaction, &Stats::default(), settings);
+        // Round started at `start_ms`, display lit at `start_ms + 5000` (the longest delay), button
+        // pressed `time_ms` later
+        let play = |settings: &mut Settings, start_ms: u32, time_ms: u32| {
+            settings.advance(start_ms);
+            // Button on the outcome leaves the screen, next round starts when it is back
+            assert!(!settings.handle(Screen::Reaction, Event::Button));
+            assert!(settings.handle(Screen::Reaction, Event::Button));
+            settings.advance(start_ms + 5000);
+            assert!(settings.reaction.lit());
+            settings.advance(start_ms + 5000 + time_ms);
+            settings.handle(Screen::Reaction, Event::Button);
+            settings.reaction.outcome()
+        };
+
+        assert_eq!(render(&settings), ("Reaction  press ".into(), "Best   -   -   -".into()));
+        assert!(!settings.handle(Screen::Reaction, Event::EncoderUp));
+
+        // False start
+        assert!(settings.handle(Screen::Reaction, Event::Button));
+        assert_eq!(render(&settings), ("Wait...         ".into(), "                ".into()));
+        settings.advance(999);
+        assert!(!settings.reaction.lit());
+        assert!(settings.handle(Screen::Reaction, Event::Button));
+        assert_eq!(render(&settings), ("Too soon!       ".into(), "Best   -   -   -".into()));
+
+        // Display lights up after the delay, both rows are filled
+        settings.advance(10_000);
+        assert!(!settings.handle(Screen::Reaction, Event::Button));
+        assert_eq!(render(&settings).0, "Reaction  press ");
+        assert!(settings.handle(Screen::Reaction, Event::Button));
+        settings.advance(15_000);
+        assert_eq!(render(&settings), ("################".into(), "################".into()));
+        settings.advance(15_215);
+        assert!(settings.handle(Screen::Reaction, Event::Button));
+        assert_eq!(render(&settings), ("Time  215ms  #1 ".into(), "Best 215   -   -".into()));
+
+        // Best times are kept sorted, slower ones do not get there
+        assert_eq!(play(&mut settings, 20_000, 300), Some(Outcome::Time(300, Some(1))));
+        assert_eq!(play(&mut settings, 30_000, 200), Some(Outcome::Time(200, Some(0))));
+        assert_eq!(play(&mut settings, 40_000, 400), Some(Outcome::Time(400, None)));
+        assert_eq!(render(&settings), ("Time  400ms     ".into(), "Best 200 215 300".into()));
+        assert!(!settings.handle(Screen::Reaction, Event::Button));
+        assert!(settings.handle(Screen::Reaction, Event::Button));
+        settings.advance(55_000);
+        settings.advance(57_000);
+        assert_eq!(settings.reaction.outcome(), Some(Outcome::TooSlow));
+        assert_eq!(render(&settings).0, "Too slow!       ");
+
+        // Encoder cancels the round
+        assert!(!settings.handle(Screen::Reaction, Event::Button));
+        assert!(settings.handle(Screen::Reaction, Event::Button));
+        assert!(settings.handle(Screen::Reaction, Event::EncoderDown));
+        assert_eq!(render(&settings).0, "Reaction  press ");
+
+        // Best times are restored from the storage
+        let scores = settings.reaction.scores();
+        let mut restored = Settings::default();
+        restored.reaction.set_scores([u16::MAX, 150, 250]);
+        assert_eq!(restored.reaction.scores(), [150, 250, u16::MAX]);
+        restored.reaction.set_scores(scores);
+        assert_eq!(restored.reaction.scores(), [200, 215, 300]);
+
+        // Reaction screen goes after the PID one, encoder navigates away
+        let mut ui = screens::navigation();
+        ui.handle(Event::Serial(b'g'), &settings);
+        ui.handle(Event::EncoderUp, &settings);
+        assert_eq!(ui.state(), Screen::Reaction);
+        ui.handle(Event::EncoderUp, &settings);
+        assert_eq!(ui.state(), Screen::Snake);
+        ui.handle(Event::Serial(b'x'), &settings);
+        assert_eq!(ui.state(), Screen::Reaction);
+    }
+}
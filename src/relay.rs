@@ -0,0 +1,26 @@
+//! Heater relay (see `board::RELAY`), switched by the thermostat (or the door strike, switched by
+//! the door lock).
+
+use stm32f1::stm32f103::{gpioa, RCC};
+use crate::board;
+use crate::gpio::{self, GPIOExtras};
+
+pub struct Relay {
+    port: &'static gpioa::RegisterBlock,
+}
+
+impl Relay {
+    /// Configure relay pin as an output, relay is off.
+    pub fn new(rcc: &RCC) -> Relay {
+        let relay = board::RELAY;
+        let port = gpio::enable_port(rcc, relay.port);
+        port.write_pin(relay.index, relay.level(false));
+        port.pin_config(relay.index).push_pull().output2();
+        Relay { port }
+    }
+
+    pub fn set(&mut self, on: bool) {
+        let relay = board::RELAY;
+        self.port.write_pin(relay.index, relay.level(on));
+    }
+}
@@ -0,0 +1,310 @@
+//! Text pushed to the display by another device, which uses the board as a display module (see
+//! `i2c_slave`, `spi_slave`, `modbus` and `Hd44780Serial`). Remote text replaces the demo screens while it is active.
+//!
+//! I2C register map (first byte of an I2C write selects the register, the rest are written into it):
+//!
+//! | Register | Name      | Write                                                          |
+//! |----------|-----------|----------------------------------------------------------------|
+//! | `0x00`   | CLEAR     | any byte clears the text and moves the cursor home             |
+//! | `0x01`   | CURSOR    | cursor address, as in HD44780 (second row starts at `0x40`)    |
+//! | `0x02`   | DATA      | characters, written at the cursor (which advances)             |
+//! | `0x03`   | BACKLIGHT | `0` is off, anything else is on                                |
+//!
+//! SPI frames (`SpiFrames`) are `0xa5` (sync byte), cursor address (as in CURSOR register), text
+//! length (up to 16) and the text itself. Bytes outside of frames are ignored, so the receiver
+//! re-synchronizes on the next frame after an error.
+//!
+//! Serial stream (`Hd44780Serial`) is the protocol of serial HD44780 backpacks, as used by lcdproc
+//! and LCD Smartie: bytes are characters, except `0xfe`, which is followed by an HD44780
+//! instruction. Only clear, return home and set DDRAM address instructions are supported, other
+//! instructions are ignored. Custom characters cannot be changed, data written after set CGRAM
+//! address is dropped.
+//!
+//! Serial terminal (`terminal::Terminal`, with the `terminal` feature) takes VT100 control codes
+//! instead, and copies a window of its screen here.
+//!
+//! Characters past the end of the row are dropped, non-ASCII characters are replaced with `?`.
+//! Board has no backlight control, BACKLIGHT is only stored (for compatibility with the drivers
+//! which set it).
+
+use core::fmt::{self, Write};
+use crate::display::{Line, TextDisplay, COLUMNS, ROWS};
+
+pub const REG_CLEAR: u8 = 0x00;
+pub const REG_CURSOR: u8 = 0x01;
+pub const REG_DATA: u8 = 0x02;
+pub const REG_BACKLIGHT: u8 = 0x03;
+
+/// Offset of the second row in the cursor address.
+const ROW_OFFSET: u8 = 0x40;
+
+#[derive(Clone)]
+pub struct RemoteText {
+    rows: [[u8; COLUMNS]; ROWS],
+    col: usize,
+    row: usize,
+    backlight: bool,
+    /// Text was written since the last `release`.
+    active: bool,
+    /// Register selected by the current write.
+    register: Option<u8>,
+}
+
+impl RemoteText {
+    pub const fn new() -> RemoteText {
+        RemoteText {
+            rows: [[b' '; COLUMNS]; ROWS],
+            col: 0,
+            row: 0,
+            backlight: true,
+            active: false,
+            register: None,
+        }
+    }
+
+    /// Has remote written anything since the last `release`?
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Return the display to the demo screens (until the remote writes again). Returns `false` if
+    /// remote text was not shown anyway.
+    pub fn release(&mut self) -> bool {
+        core::mem::replace(&mut self.active, false)
+    }
+
+    pub fn backlight(&self) -> bool {
+        self.backlight
+    }
+
+    /// Contents of the given row.
+    pub fn row(&self, row: usize) -> &str {
+        // Only ASCII is stored
+        unsafe { core::str::from_utf8_unchecked(&self.rows[row]) }
+    }
+
+    /// Clear the text and move the cursor home.
+    pub fn clear(&mut self) {
+        self.rows = [[b' '; COLUMNS]; ROWS];
+        self.col = 0;
+        self.row = 0;
+        self.active = true;
+    }
+
+    /// Move the cursor to the HD44780-style `address` (second row starts at `0x40`).
+    pub fn set_cursor(&mut self, address: u8) {
+        self.row = usize::from(address >= ROW_OFFSET).min(ROWS - 1);
+        self.col = usize::from(address % ROW_OFFSET).min(COLUMNS);
+        self.active = true;
+    }
+
+    /// Write character at the cursor, cursor advances.
+    pub fn write(&mut self, byte: u8) {
+        if self.col < COLUMNS {
+            self.rows[self.row][self.col] = if byte.is_ascii() { byte } else { b'?' };
+            self.col += 1;
+        }
+        self.active = true;
+    }
+
+    pub fn set_backlight(&mut self, on: bool) {
+        self.backlight = on;
+        self.active = true;
+    }
+
+    /// Start of a new I2C write, next byte selects the register.
+    pub fn start(&mut self) {
+        self.register = None;
+    }
+
+    /// Byte received over I2C.
+    pub fn receive(&mut self, byte: u8) {
+        let Some(register) = self.register else {
+            self.register = Some(byte);
+            return;
+        };
+        match register {
+            REG_CLEAR => self.clear(),
+            REG_CURSOR => self.set_cursor(byte),
+            REG_DATA => self.write(byte),
+            REG_BACKLIGHT => self.set_backlight(byte != 0),
+            _ => {}
+        }
+    }
+
+    /// Show the text (rows are written in full).
+    pub fn render<D: TextDisplay>(&self, display: &mut D) -> fmt::Result {
+        for row in 0..ROWS {
+            let mut line = Line::new(display, row as u8);
+            line.write_str(self.row(row))?;
+            line.finish()?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for RemoteText {
+    fn default() -> RemoteText {
+        RemoteText::new()
+    }
+}
+
+/// First byte of an SPI frame.
+pub const SPI_SYNC: u8 = 0xa5;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum FrameState {
+    Sync,
+    Cursor,
+    Length,
+    Text(u8),
+}
+
+/// Parser of the SPI frames.
+pub struct SpiFrames {
+    state: FrameState,
+}
+
+impl SpiFrames {
+    pub const fn new() -> SpiFrames {
+        SpiFrames { state: FrameState::Sync }
+    }
+
+    /// Byte received over SPI.
+    pub fn receive(&mut self, text: &mut RemoteText, byte: u8) {
+        self.state = match self.state {
+            FrameState::Sync if byte == SPI_SYNC => FrameState::Cursor,
+            FrameState::Sync => FrameState::Sync,
+            FrameState::Cursor => {
+                text.set_cursor(byte);
+                FrameState::Length
+            }
+            FrameState::Length if byte == 0 || usize::from(byte) > COLUMNS => FrameState::Sync,
+            FrameState::Length => FrameState::Text(byte),
+            FrameState::Text(left) => {
+                text.write(byte);
+                if left > 1 { FrameState::Text(left - 1) } else { FrameState::Sync }
+            }
+        };
+    }
+}
+
+impl Default for SpiFrames {
+    fn default() -> SpiFrames {
+        SpiFrames::new()
+    }
+}
+
+/// Prefix of HD44780 instructions in the serial stream.
+pub const SERIAL_INSTRUCTION: u8 = 0xfe;
+
+/// Parser of the serial stream.
+pub struct Hd44780Serial {
+    /// Next byte is an instruction.
+    instruction: bool,
+    /// Data goes to CGRAM (custom characters).
+    cgram: bool,
+}
+
+impl Hd44780Serial {
+    pub const fn new() -> Hd44780Serial {
+        Hd44780Serial {
+            instruction: false,
+            cgram: false,
+        }
+    }
+
+    /// Byte received over serial.
+    pub fn receive(&mut self, text: &mut RemoteText, byte: u8) {
+        if self.instruction {
+            self.instruction = false;
+            match byte {
+                0x80..=0xff => text.set_cursor(byte & 0x7f),
+                0x40..=0x7f => {
+                    self.cgram = true;
+                    return;
+                }
+                0x02..=0x03 => text.set_cursor(0),
+                0x01 => text.clear(),
+                _ => return,
+            }
+            self.cgram = false;
+        } else if byte == SERIAL_INSTRUCTION {
+            self.instruction = true;
+        } else if !self.cgram {
+            text.write(byte);
+        }
+    }
+}
+
+impl Default for Hd44780Serial {
+    fn default() -> Hd44780Serial {
+        Hd44780Serial::new()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn remote_register_map() {
+        use crate::remote::{RemoteText, REG_BACKLIGHT, REG_CLEAR, REG_CURSOR, REG_DATA};
+
+        let mut remote = RemoteText::new();
+        let mut write = |bytes: &[u8]| {
+            remote.start();
+            bytes.iter().for_each(|&byte| remote.receive(byte));
+        };
+        write(&[REG_CURSOR, 0x43]);
+        write(&[REG_DATA, b'H', b'i', 0xff]);
+        write(&[REG_CURSOR, 0x0e]);
+        write(&[REG_DATA, b'a', b'b', b'c']);
+        write(&[REG_BACKLIGHT, 0]);
+        assert_eq!(remote.row(0), "              ab");
+        assert_eq!(remote.row(1), "   Hi?          ");
+        assert!(!remote.backlight());
+
+        assert!(remote.release());
+        assert!(!remote.release());
+        remote.start();
+        remote.receive(REG_CLEAR);
+        remote.receive(0);
+        assert!(remote.is_active());
+        assert_eq!(remote.row(0), " ".repeat(16));
+        assert_eq!(remote.row(1), " ".repeat(16));
+    }
+
+    #[test]
+    fn remote_spi_frames() {
+        use crate::remote::{RemoteText, SpiFrames, SPI_SYNC};
+
+        let mut remote = RemoteText::new();
+        let mut frames = SpiFrames::new();
+        let bytes = [
+            0x00, 0x42, // noise before the frame
+            SPI_SYNC, 0x40, 3, b'a', b'b', b'c',
+            SPI_SYNC, 0x02, 17, b'x', // too long, dropped until the next sync
+            SPI_SYNC, 0x0e, 4, b'1', b'2', b'3', b'4',
+        ];
+        bytes.iter().for_each(|&byte| frames.receive(&mut remote, byte));
+        assert_eq!(remote.row(0), "              12");
+        assert_eq!(remote.row(1), "abc             ");
+    }
+
+    #[test]
+    fn remote_hd44780_serial() {
+        use crate::remote::{Hd44780Serial, RemoteText, SERIAL_INSTRUCTION as I};
+
+        let mut remote = RemoteText::new();
+        let mut parser = Hd44780Serial::new();
+        let bytes = [
+            I, 0x01, b'C', b'P', b'U',
+            I, 0x48, 0x1f, 0x1f, // custom character, dropped
+            I, 0x80 | 0x44, b'4', b'2', b'%',
+            I, 0x0c, // display on, ignored
+            I, 0x02, b'c',
+        ];
+        bytes.iter().for_each(|&byte| parser.receive(&mut remote, byte));
+        assert_eq!(remote.row(0), "cPU             ");
+        assert_eq!(remote.row(1), "    42%         ");
+    }
+}
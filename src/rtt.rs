@@ -0,0 +1,98 @@
+//! RTT (Real-Time Transfer): channels between the firmware and the debugger, which finds the
+//! control block in RAM by its ID and polls the buffers over SWD. No pins are used.
+//!
+//! The control block is set up by `rtt-target`, so any RTT host works (`probe-rs attach`, J-Link
+//! RTT Viewer, OpenOCD `rtt server`). It is the only one in the firmware, shared by the features:
+//! up channel 0 carries the `defmt` log (`debug-log` feature), up channel 1 and down channel 0 are
+//! the terminal (`rtt-console` feature, see `Rtt`). Channels of the features which are disabled
+//! are left without buffers.
+//!
+//! Terminal never waits for the debugger: output which does not fit is left for the next `write`.
+//! Log waits only if the host asks for it (blocking mode), otherwise frames which do not fit are
+//! dropped.
+
+#[cfg(feature = "rtt-console")]
+use core::cell::RefCell;
+#[cfg(feature = "rtt-console")]
+use cortex_m::interrupt::{self, Mutex};
+use rtt_target::rtt_init;
+#[cfg(feature = "rtt-console")]
+use rtt_target::{ChannelMode, DownChannel, UpChannel};
+
+/// Log buffer size, in bytes.
+#[cfg(feature = "debug-log")]
+const LOG_SIZE: usize = 1024;
+/// Terminal output buffer size, in bytes (about a screenful of the shell output).
+#[cfg(feature = "rtt-console")]
+const UP_SIZE: usize = 512;
+/// Terminal input buffer size, in bytes (debugger writes a line at a time).
+#[cfg(feature = "rtt-console")]
+const DOWN_SIZE: usize = 64;
+
+/// Terminal channels, until they are taken.
+#[cfg(feature = "rtt-console")]
+static TERMINAL: Mutex<RefCell<Option<Rtt>>> = Mutex::new(RefCell::new(None));
+
+/// Set up the control block with the channels of the enabled features. Must be called once, first
+/// thing at startup: the log written before it is dropped.
+pub fn init() {
+    #[cfg(all(feature = "debug-log", feature = "rtt-console"))]
+    let channels = rtt_init! {
+        up: {
+            0: { size: LOG_SIZE, name: "defmt" }
+            1: { size: UP_SIZE, mode: ChannelMode::NoBlockTrim, name: "Terminal" }
+        }
+        down: {
+            0: { size: DOWN_SIZE, name: "Terminal" }
+        }
+    };
+    #[cfg(all(feature = "debug-log", not(feature = "rtt-console")))]
+    let channels = rtt_init! {
+        up: {
+            0: { size: LOG_SIZE, name: "defmt" }
+        }
+    };
+    #[cfg(all(not(feature = "debug-log"), feature = "rtt-console"))]
+    let channels = rtt_init! {
+        up: {
+            0: { }
+            1: { size: UP_SIZE, mode: ChannelMode::NoBlockTrim, name: "Terminal" }
+        }
+        down: {
+            0: { size: DOWN_SIZE, name: "Terminal" }
+        }
+    };
+
+    #[cfg(feature = "debug-log")]
+    rtt_target::set_defmt_channel(channels.up.0);
+    #[cfg(feature = "rtt-console")]
+    {
+        let terminal = Rtt { up: channels.up.1, down: channels.down.0 };
+        interrupt::free(|cs| *TERMINAL.borrow(cs).borrow_mut() = Some(terminal));
+    }
+}
+
+/// The terminal, there is only one.
+#[cfg(feature = "rtt-console")]
+pub struct Rtt {
+    up: UpChannel,
+    down: DownChannel,
+}
+
+#[cfg(feature = "rtt-console")]
+impl Rtt {
+    /// Take the terminal channels set up by `init`, `None` if it was done already.
+    pub fn take() -> Option<Rtt> {
+        interrupt::free(|cs| TERMINAL.borrow(cs).borrow_mut().take())
+    }
+
+    /// Send as many of the `bytes` as fit into the output buffer. Returns the number sent.
+    pub fn write(&mut self, bytes: &[u8]) -> usize {
+        self.up.write(bytes)
+    }
+
+    /// Take the bytes written by the debugger, as many as fit into `buf`. Returns the number taken.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        self.down.read(buf)
+    }
+}
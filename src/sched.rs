@@ -0,0 +1,104 @@
+//! Cooperative scheduler: a fixed table of tasks, each run periodically at its own rate.
+//!
+//! Tasks are plain functions taking the shared context. They run to completion, so they must not
+//! block for long. Time is passed in explicitly (in milliseconds), so the scheduler does not depend
+//! on any particular time source.
+
+/// Periodic task.
+pub struct Task<C> {
+    /// Task name (for logging).
+    pub name: &'static str,
+    /// How often to run the task, in milliseconds.
+    pub period_ms: u32,
+    /// Task body.
+    pub run: fn(&mut C),
+}
+
+/// Scheduler over a fixed table of `N` tasks sharing the context `C`.
+pub struct Scheduler<C, const N: usize> {
+    tasks: [Task<C>; N],
+    next_run: [u32; N],
+}
+
+impl<C, const N: usize> Scheduler<C, N> {
+    /// Create scheduler, all tasks are due immediately.
+    pub fn new(tasks: [Task<C>; N], now: u32) -> Scheduler<C, N> {
+        Scheduler {
+            tasks,
+            next_run: [now; N],
+        }
+    }
+
+    /// Run all tasks that are due, in the table order.
+    pub fn run_pending(&mut self, ctx: &mut C, now: u32) {
+        for (task, next_run) in self.tasks.iter().zip(self.next_run.iter_mut()) {
+            if !reached(now, *next_run) {
+                continue;
+            }
+            (task.run)(ctx);
+            *next_run = next_run.wrapping_add(task.period_ms);
+            // If we are late for more than a whole period, skip missed runs
+            if reached(now, *next_run) {
+                *next_run = now.wrapping_add(task.period_ms);
+            }
+        }
+    }
+
+    /// Earliest time any of the tasks is due.
+    pub fn next_deadline(&self, now: u32) -> u32 {
+        let wait = self
+            .next_run
+            .iter()
+            .map(|next_run| next_run.wrapping_sub(now) as i32)
+            .min()
+            .unwrap_or(0);
+        now.wrapping_add(wait.max(0) as u32)
+    }
+}
+
+fn reached(now: u32, deadline: u32) -> bool {
+    (now.wrapping_sub(deadline) as i32) >= 0
+}
+
+/// Main loop statistics: iteration rate (over one second windows) and the longest iteration.
+#[derive(Default)]
+pub struct LoopStats {
+    window_start: u32,
+    count: u32,
+    rate: u32,
+    max_us: u32,
+}
+
+/// Iteration rate is recalculated this often, in milliseconds.
+const RATE_WINDOW_MS: u32 = 1000;
+
+impl LoopStats {
+    pub fn new(now: u32) -> LoopStats {
+        LoopStats {
+            window_start: now,
+            ..LoopStats::default()
+        }
+    }
+
+    /// Record one iteration which took `busy_us` microseconds (not counting the sleep).
+    pub fn record(&mut self, now: u32, busy_us: u32) {
+        self.count += 1;
+        self.max_us = self.max_us.max(busy_us);
+        let elapsed = now.wrapping_sub(self.window_start);
+        if elapsed >= RATE_WINDOW_MS {
+            self.rate = (u64::from(self.count) * 1000 / u64::from(elapsed)) as u32;
+            self.count = 0;
+            self.window_start = now;
+        }
+    }
+
+    /// Iterations per second, over the last complete window.
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    /// Longest iteration since the start, in microseconds.
+    pub fn max_us(&self) -> u32 {
+        self.max_us
+    }
+}
@@ -0,0 +1,266 @@
+//! Day and night profiles of the display: backlight brightness (manual, or following the ambient
+//! light, see `backlight`) and contrast, each profile taking over at its own time of day.
+//!
+//! Time of day is the one of the alarm clock (see `alarm`), kept from the millisecond timer (by
+//! the RTC in Stop mode). It starts at 00:00 after every reset, so the night profile is in charge
+//! until the clock is set.
+//!
+//! Brightness of the profile is applied when it takes over (and when it is edited while in
+//! charge), so it can still be changed on the settings screen until the next switch. Contrast is
+//! always the one of the profile in charge. Profiles are edited on the settings screen and kept
+//! in flash by the main loop (see `to_store`).
+
+use core::fmt::{self, Write};
+use crate::backlight;
+use crate::display::COLUMNS;
+use crate::text::{self, Align};
+
+const DAY_MINUTES: u16 = 24 * 60;
+/// Start time step, in minutes.
+const START_STEP: u16 = 15;
+/// Contrast step, in percents.
+const CONTRAST_STEP: u8 = 5;
+/// Brightness kept in flash for "follow the ambient light".
+const AUTO: u32 = 0xff;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Night,
+}
+
+/// Setting of a profile.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Field {
+    /// Time the profile takes over.
+    Start,
+    Backlight,
+    Contrast,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Profile {
+    /// Minute of the day the profile takes over at.
+    pub start: u16,
+    /// Backlight brightness, in percents, `None` to follow the ambient light.
+    pub backlight: Option<u8>,
+    /// Display contrast, in percents.
+    pub contrast: u8,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    day: Profile,
+    night: Profile,
+    /// Profile in charge, `None` before the first `update`.
+    active: Option<Period>,
+}
+
+impl Default for Schedule {
+    /// Day from 07:00, following the ambient light, night from 22:00, dimmed.
+    fn default() -> Schedule {
+        Schedule {
+            day: Profile { start: 7 * 60, backlight: None, contrast: 60 },
+            night: Profile { start: 22 * 60, backlight: Some(20), contrast: 60 },
+            active: None,
+        }
+    }
+}
+
+impl Schedule {
+    pub fn profile(&self, period: Period) -> &Profile {
+        match period {
+            Period::Day => &self.day,
+            Period::Night => &self.night,
+        }
+    }
+
+    fn profile_mut(&mut self, period: Period) -> &mut Profile {
+        match period {
+            Period::Day => &mut self.day,
+            Period::Night => &mut self.night,
+        }
+    }
+
+    /// Period at the `minute` of the day (night goes over midnight, unless it starts before the
+    /// day).
+    fn period_at(&self, minute: u16) -> Period {
+        let day = if self.day.start <= self.night.start {
+            (self.day.start..self.night.start).contains(&minute)
+        } else {
+            !(self.night.start..self.day.start).contains(&minute)
+        };
+        if day { Period::Day } else { Period::Night }
+    }
+
+    /// Profile in charge (the day one before the first `update`).
+    pub fn active(&self) -> Period {
+        self.active.unwrap_or(Period::Day)
+    }
+
+    /// Contrast of the profile in charge, in percents.
+    pub fn contrast(&self) -> u8 {
+        self.profile(self.active()).contrast
+    }
+
+    /// Switch to the profile for the `minute_of_day`. Returns the profile if it has just taken
+    /// over (its brightness is to be applied).
+    pub fn update(&mut self, minute_of_day: u32) -> Option<Profile> {
+        let period = self.period_at((minute_of_day % u32::from(DAY_MINUTES)) as u16);
+        if self.active == Some(period) {
+            return None;
+        }
+        self.active = Some(period);
+        Some(*self.profile(period))
+    }
+
+    /// Change the `field` of the profile by `step` steps. Returns the brightness to apply if it
+    /// is the one of the profile in charge.
+    pub fn adjust(&mut self, period: Period, field: Field, step: i8) -> Option<Option<u8>> {
+        let profile = self.profile_mut(period);
+        match field {
+            Field::Start => {
+                let start = i32::from(profile.start) + i32::from(step) * i32::from(START_STEP);
+                profile.start = start.rem_euclid(i32::from(DAY_MINUTES)) as u16;
+            }
+            Field::Backlight => profile.backlight = backlight::adjust_manual(profile.backlight, step),
+            Field::Contrast => {
+                let contrast = i16::from(profile.contrast) + i16::from(step) * i16::from(CONTRAST_STEP);
+                profile.contrast = contrast.clamp(0, 100) as u8;
+            }
+        }
+        let backlight = profile.backlight;
+        (field == Field::Backlight && self.active == Some(period)).then_some(backlight)
+    }
+
+    /// Profiles, as kept in flash: the start times (the day one in the low half), and the
+    /// brightness and contrast of the day, then of the night (a byte each, `AUTO` brightness
+    /// follows the ambient light).
+    pub fn to_store(&self) -> [u32; 2] {
+        let settings = |profile: &Profile| profile.backlight.map_or(AUTO, u32::from) | u32::from(profile.contrast) << 8;
+        [
+            u32::from(self.day.start) | u32::from(self.night.start) << 16,
+            settings(&self.day) | settings(&self.night) << 16,
+        ]
+    }
+
+    /// Restore profiles saved by `to_store`. Invalid ones (erased flash) keep the defaults.
+    pub fn set_store(&mut self, starts: u32, settings: u32) {
+        for (i, period) in [Period::Day, Period::Night].into_iter().enumerate() {
+            let start = (starts >> (16 * i)) as u16;
+            let (backlight, contrast) = ((settings >> (16 * i)) & 0xff, (settings >> (16 * i + 8)) & 0xff);
+            if start < DAY_MINUTES && (backlight <= 100 || backlight == AUTO) && contrast <= 100 {
+                *self.profile_mut(period) = Profile {
+                    start,
+                    backlight: (backlight != AUTO).then_some(backlight as u8),
+                    contrast: contrast as u8,
+                };
+            }
+        }
+    }
+
+    /// Write the `field` of the profile (`Day from   07:00`, `Night light  20%`,
+    /// `Day contr    60%`).
+    pub fn write_preference<W: Write>(&self, w: &mut W, period: Period, field: Field) -> fmt::Result {
+        let profile = self.profile(period);
+        let name = match period {
+            Period::Day => "Day",
+            Period::Night => "Night",
+        };
+        w.write_str(name)?;
+        match field {
+            Field::Start => {
+                text::str(w, " from", COLUMNS - 5 - name.len(), Align::Left)?;
+                write_two(w, profile.start / 60)?;
+                w.write_char(':')?;
+                write_two(w, profile.start % 60)
+            }
+            Field::Backlight => {
+                text::str(w, " light", COLUMNS - 4 - name.len(), Align::Left)?;
+                backlight::write_manual(w, profile.backlight)
+            }
+            Field::Contrast => {
+                text::str(w, " contr", COLUMNS - 4 - name.len(), Align::Left)?;
+                text::uint(w, u32::from(profile.contrast), 3)?;
+                w.write_char('%')
+            }
+        }
+    }
+}
+
+/// Write `value` (below 100) as two digits.
+fn write_two<W: Write>(w: &mut W, value: u16) -> fmt::Result {
+    w.write_char((b'0' + (value / 10 % 10) as u8) as char)?;
+    w.write_char((b'0' + (value % 10) as u8) as char)
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn day_night_schedule() {
+        use crate::mock;
+        use crate::schedule::{Field, Period, Schedule};
+        use crate::screens::{Preference, Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        let render = |settings: &Settings| mock::render(Screen::Settings, &Stats::default(), settings).1;
+        // Clock starts at midnight, in the night
+        let mut settings = Settings::default();
+        settings.advance(0);
+        assert_eq!(settings.schedule.active(), Period::Night);
+        assert_eq!(settings.backlight.manual(), Some(20));
+        assert_eq!(settings.schedule.contrast(), 60);
+
+        // Profiles follow the backlight preference
+        while settings.preference != Preference::Schedule(Period::Day, Field::Start) {
+            assert!(settings.handle(Screen::Settings, Event::Button));
+        }
+        assert_eq!(render(&settings), "Day from   07:00");
+        assert!(settings.handle(Screen::Settings, Event::EncoderDown));
+        assert_eq!(render(&settings), "Day from   06:45");
+        assert!(settings.handle(Screen::Settings, Event::Button));
+        assert_eq!(render(&settings), "Day light   auto");
+        assert!(settings.handle(Screen::Settings, Event::Button));
+        assert!(settings.handle(Screen::Settings, Event::EncoderUp));
+        assert_eq!(render(&settings), "Day contr    65%");
+        assert!(settings.handle(Screen::Settings, Event::Button));
+        assert_eq!(render(&settings), "Night from 22:00");
+        // Brightness of the profile in charge is applied right away
+        assert!(settings.handle(Screen::Settings, Event::Button));
+        assert!(settings.handle(Screen::Settings, Event::EncoderUp));
+        assert_eq!(render(&settings), "Night light  30%");
+        assert_eq!(settings.backlight.manual(), Some(30));
+        assert!(settings.handle(Screen::Settings, Event::Button));
+        assert_eq!(render(&settings), "Night contr  60%");
+        #[cfg(not(feature = "battery"))]
+        assert!(!settings.handle(Screen::Settings, Event::Button));
+
+        // Day takes over at 06:45, and the brightness is set on the settings screen until the night
+        settings.advance(6 * 3_600_000 + 44 * 60_000);
+        assert_eq!(settings.schedule.active(), Period::Night);
+        settings.advance(6 * 3_600_000 + 45 * 60_000);
+        assert_eq!(settings.schedule.active(), Period::Day);
+        assert_eq!(settings.backlight.manual(), None);
+        assert_eq!(settings.schedule.contrast(), 65);
+        settings.backlight.adjust(5);
+        settings.advance(22 * 3_600_000);
+        assert_eq!(settings.backlight.manual(), Some(30));
+        assert_eq!(settings.schedule.contrast(), 60);
+
+        // Night before the day does not go over midnight, profiles are kept in flash
+        let mut schedule = Schedule::default();
+        schedule.adjust(Period::Night, Field::Start, -64);
+        assert_eq!(schedule.profile(Period::Night).start, 6 * 60);
+        assert_eq!(schedule.update(5 * 60).map(|profile| profile.start), Some(7 * 60));
+        assert_eq!(schedule.update(6 * 60).map(|profile| profile.start), Some(6 * 60));
+        assert_eq!(schedule.update(6 * 60 + 59), None);
+        assert_eq!(schedule.update(7 * 60).map(|profile| profile.backlight), Some(None));
+        let [starts, profiles] = schedule.to_store();
+        assert_eq!((starts, profiles), (6 * 60 << 16 | 7 * 60, 60 << 24 | 20 << 16 | 60 << 8 | 0xff));
+        let mut restored = Schedule::default();
+        restored.set_store(starts, profiles);
+        assert_eq!(restored.to_store(), [starts, profiles]);
+        restored.set_store(u32::MAX, u32::MAX);
+        assert_eq!(restored.to_store(), [starts, profiles]);
+    }
+}
@@ -0,0 +1,308 @@
+//! Backup register scratchpad: the ten 16-bit data registers of the backup domain (see `backup`)
+//! as a map of typed fields, packed bit by bit, as there are only 160 bits for everything that
+//! has to survive a reset: the boot counter, the alarms, the unacknowledged threshold alarm and
+//! the speedometer trip.
+//!
+//! Fields are declared below by their first bit (bit 0 is the lowest bit of the first register)
+//! and width, and can straddle registers. Values wider than their field are cut to it, so
+//! whoever keeps a counter saturates it at `Field::max` first.
+//!
+//! Registers are read once, when mounted, and kept in RAM: they are validated by the magic value
+//! (starting over from zeroes otherwise, see `fresh`), and could be reset behind our back (by the
+//! Stop mode setup or the tamper pin), so `rewrite` writes them all again.
+
+use core::marker::PhantomData;
+use crate::alarm::ALARMS;
+
+/// Data registers of the backup domain (DR1 to DR10 on the medium-density F103).
+pub const REGISTERS: usize = 10;
+
+/// Marks registers as initialized (and tells this layout from the older ones).
+const MAGIC_VALUE: u8 = 0xb7;
+
+/// Backup registers, by index (zero is DR1).
+pub trait Registers {
+    fn read(&self, index: usize) -> u16;
+    fn write(&mut self, index: usize, value: u16);
+}
+
+impl Registers for [u16; REGISTERS] {
+    fn read(&self, index: usize) -> u16 {
+        self[index]
+    }
+
+    fn write(&mut self, index: usize, value: u16) {
+        self[index] = value;
+    }
+}
+
+/// Type kept in a field.
+pub trait Value: Copy {
+    fn from_bits(bits: u32) -> Self;
+    fn to_bits(self) -> u32;
+}
+
+impl Value for bool {
+    fn from_bits(bits: u32) -> bool {
+        bits != 0
+    }
+
+    fn to_bits(self) -> u32 {
+        u32::from(self)
+    }
+}
+
+impl Value for u8 {
+    fn from_bits(bits: u32) -> u8 {
+        bits as u8
+    }
+
+    fn to_bits(self) -> u32 {
+        u32::from(self)
+    }
+}
+
+impl Value for u16 {
+    fn from_bits(bits: u32) -> u16 {
+        bits as u16
+    }
+
+    fn to_bits(self) -> u32 {
+        u32::from(self)
+    }
+}
+
+impl Value for u32 {
+    fn from_bits(bits: u32) -> u32 {
+        bits
+    }
+
+    fn to_bits(self) -> u32 {
+        self
+    }
+}
+
+/// `width` bits (up to 32) starting at bit `offset` of the scratchpad, holding a `T`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Field<T> {
+    offset: usize,
+    width: usize,
+    value: PhantomData<T>,
+}
+
+impl<T> Clone for Field<T> {
+    fn clone(&self) -> Field<T> {
+        *self
+    }
+}
+
+impl<T> Copy for Field<T> {}
+
+impl<T: Value> Field<T> {
+    pub const fn new(offset: usize, width: usize) -> Field<T> {
+        assert!(width > 0 && width <= 32 && offset + width <= 16 * REGISTERS);
+        Field { offset, width, value: PhantomData }
+    }
+
+    /// First bit after the field.
+    pub const fn end(self) -> usize {
+        self.offset + self.width
+    }
+
+    /// Highest value the field holds.
+    pub const fn max(self) -> u32 {
+        u32::MAX >> (32 - self.width)
+    }
+
+    fn get(self, regs: &[u16; REGISTERS]) -> T {
+        let bits = (0..self.width).fold(0, |bits, i| {
+            let at = self.offset + i;
+            bits | (u32::from(regs[at / 16] >> (at % 16)) & 1) << i
+        });
+        T::from_bits(bits)
+    }
+
+    fn set(self, regs: &mut [u16; REGISTERS], value: T) {
+        let bits = value.to_bits();
+        for i in 0..self.width {
+            let at = self.offset + i;
+            let mask = 1 << (at % 16);
+            if bits >> i & 1 != 0 {
+                regs[at / 16] |= mask;
+            } else {
+                regs[at / 16] &= !mask;
+            }
+        }
+    }
+}
+
+/// Register map, starting with the magic value.
+pub const MAGIC: Field<u8> = Field::new(0, 8);
+/// Number of boots (see `backup::BootStats`).
+pub const BOOTS: Field<u16> = Field::new(MAGIC.end(), 16);
+/// Total runtime of the previous boots, in seconds.
+pub const RUNTIME: Field<u32> = Field::new(BOOTS.end(), 32);
+/// Alarm clock alarms (see `AlarmClock::to_backup`).
+pub const ALARM: [Field<u32>; ALARMS] = {
+    let mut fields = [Field::new(RUNTIME.end(), 18); ALARMS];
+    let mut i = 1;
+    while i < ALARMS {
+        fields[i] = Field::new(fields[i - 1].end(), 18);
+        i += 1;
+    }
+    fields
+};
+/// Threshold alarm not acknowledged yet (see `Thresholds::to_backup`).
+pub const ALERT: Field<u32> = Field::new(ALARM[ALARMS - 1].end(), 29);
+/// Speedometer trip distance, in tens of meters (up to 20971.51 km).
+pub const TRIP: Field<u32> = Field::new(ALERT.end(), 21);
+
+const _: () = assert!(TRIP.end() <= 16 * REGISTERS);
+
+/// RAM copy of the registers.
+pub struct Scratchpad<R> {
+    regs: R,
+    copy: [u16; REGISTERS],
+    /// Registers did not hold valid data when mounted.
+    fresh: bool,
+}
+
+impl<R: Registers> Scratchpad<R> {
+    /// Read the registers, clearing them if they are not valid.
+    pub fn mount(regs: R) -> Scratchpad<R> {
+        let copy = core::array::from_fn(|i| regs.read(i));
+        let mut scratchpad = Scratchpad { regs, copy, fresh: false };
+        if scratchpad.get(MAGIC) != MAGIC_VALUE {
+            scratchpad.clear();
+        }
+        scratchpad
+    }
+
+    /// Start over from zeroes.
+    pub fn clear(&mut self) {
+        self.copy = [0; REGISTERS];
+        MAGIC.set(&mut self.copy, MAGIC_VALUE);
+        self.fresh = true;
+        self.rewrite();
+    }
+
+    /// Did the registers have to be cleared (or the scratchpad was cleared since)?
+    pub fn fresh(&self) -> bool {
+        self.fresh
+    }
+
+    pub fn get<T: Value>(&self, field: Field<T>) -> T {
+        field.get(&self.copy)
+    }
+
+    /// Set the field, writing the registers which have changed.
+    pub fn set<T: Value>(&mut self, field: Field<T>, value: T) {
+        let old = self.copy;
+        field.set(&mut self.copy, value);
+        for (index, (&old, &new)) in old.iter().zip(self.copy.iter()).enumerate() {
+            if old != new {
+                self.regs.write(index, new);
+            }
+        }
+    }
+
+    /// Write all the registers again.
+    pub fn rewrite(&mut self) {
+        for (index, &value) in self.copy.iter().enumerate() {
+            self.regs.write(index, value);
+        }
+    }
+
+    /// Registers themselves (for the tamper pin setup).
+    pub fn registers(&self) -> &R {
+        &self.regs
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn backup_scratchpad() {
+        use crate::alarm::AlarmClock;
+        use crate::scratchpad::{Registers, Scratchpad, ALARM, ALERT, BOOTS, REGISTERS, RUNTIME, TRIP};
+        use crate::threshold::{Alert, Bound, Source, Thresholds};
+        use crate::ui::Event;
+
+        struct Backup {
+            regs: [u16; REGISTERS],
+            writes: usize,
+        }
+        impl Registers for Backup {
+            fn read(&self, index: usize) -> u16 {
+                self.regs[index]
+            }
+
+            fn write(&mut self, index: usize, value: u16) {
+                self.regs[index] = value;
+                self.writes += 1;
+            }
+        }
+
+        // Garbage is cleared, all of it
+        let mut scratchpad = Scratchpad::mount(Backup { regs: [0x5a5a; REGISTERS], writes: 0 });
+        assert!(scratchpad.fresh());
+        assert_eq!(scratchpad.get(BOOTS), 0);
+        assert_eq!(scratchpad.get(ALERT), 0);
+        assert_eq!(scratchpad.registers().regs, [0xb7, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        // Only the registers which have changed are written, fields straddle them
+        let writes = scratchpad.registers().writes;
+        scratchpad.set(BOOTS, 7);
+        assert_eq!(scratchpad.registers().writes, writes + 1);
+        scratchpad.set(RUNTIME, 0x1234_5678);
+        assert_eq!(scratchpad.registers().writes, writes + 4);
+        assert_eq!(scratchpad.registers().regs[..4], [0x07b7, 0x7800, 0x3456, 0x0012]);
+        scratchpad.set(RUNTIME, 0x1234_5678);
+        assert_eq!(scratchpad.registers().writes, writes + 4);
+
+        // Neighbours are left alone
+        scratchpad.set(TRIP, TRIP.max());
+        scratchpad.set(ALERT, ALERT.max());
+        scratchpad.set(ALERT, 0);
+        assert_eq!(scratchpad.get(TRIP), 2_097_151);
+        let mut clock = AlarmClock::default();
+        clock.set_backup([390 | 0b101 << 11, 420, 1439 | 0x7f << 11]);
+        for (field, saved) in ALARM.into_iter().zip(clock.to_backup()) {
+            scratchpad.set(field, saved);
+        }
+        assert_eq!(scratchpad.get(RUNTIME), 0x1234_5678);
+
+        // Valid registers are kept
+        let regs = scratchpad.registers().regs;
+        let scratchpad = Scratchpad::mount(Backup { regs, writes: 0 });
+        assert!(!scratchpad.fresh());
+        assert_eq!(scratchpad.get(BOOTS), 7);
+        assert_eq!(scratchpad.get(TRIP), 2_097_151);
+        let mut restored = AlarmClock::default();
+        restored.set_backup(ALARM.map(|field| scratchpad.get(field)));
+        assert_eq!(restored.alarms(), clock.alarms());
+        assert_eq!((restored.alarms()[2].hour, restored.alarms()[2].minute, restored.alarms()[2].days), (23, 59, 0x7f));
+
+        // Alarm not acknowledged is latched again, and is not latched twice
+        let mut thresholds = Thresholds::default();
+        assert_eq!(thresholds.to_backup(), 0);
+        assert!(thresholds.check(Source::Temperature, 713));
+        let saved = thresholds.to_backup();
+        let mut restored = Thresholds::default();
+        restored.set_backup(saved);
+        let alert = Alert { source: Source::Temperature, bound: Bound::High, value: 713, limit: 600 };
+        assert_eq!(restored.latched(), Some(alert));
+        assert!(restored.handle_latched(Event::Button));
+        assert!(!restored.check(Source::Temperature, 720));
+        assert!(!restored.check(Source::Temperature, 500));
+        assert!(restored.check(Source::Temperature, 710));
+        assert_eq!(restored.to_backup(), saved & !(0xffff << 4) | 710 << 4);
+
+        // Nothing latched, or garbage
+        restored = Thresholds::default();
+        restored.set_backup(0);
+        restored.set_backup(saved | 0x1ff << 20);
+        restored.set_backup(0b111);
+        assert_eq!(restored.latched(), None);
+    }
+}
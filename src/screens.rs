@@ -0,0 +1,1549 @@
+//! Demo screens. Independent of the hardware, so the same code runs both on the board and in the
+//! simulator.
+
+use core::fmt::{self, Write};
+use crate::big;
+use crate::display::{Line, TextDisplay, COLUMNS, ROWS};
+#[cfg(feature = "spi-flash")]
+use crate::flash::Flash;
+#[cfg(feature = "i2c-scan")]
+use crate::bus::ScanResults;
+use crate::inspect::{Inspector, WINDOW};
+use crate::alarm::AlarmClock;
+use crate::countdown::Countdown;
+use crate::pomodoro::Pomodoro;
+use crate::thermostat::Thermostat;
+use crate::threshold::Thresholds;
+use crate::heater::Heater;
+use crate::reaction::Reaction;
+use crate::pixels::{Cgram, PixelRegion};
+use crate::snake::{self, Snake};
+use crate::dice::Dice;
+use crate::morse::Morse;
+use crate::calculator::Calculator;
+use crate::stopwatch::Stopwatch;
+use crate::trend::{self, Trend};
+#[cfg(feature = "onewire")]
+use crate::onewire::Devices;
+#[cfg(feature = "fan")]
+use crate::fan::Fan;
+#[cfg(feature = "temp-log")]
+use crate::logger::LogView;
+#[cfg(feature = "pin-monitor")]
+use crate::pin_monitor::PinMonitor;
+#[cfg(feature = "tachometer")]
+use crate::tachometer::Tachometer;
+#[cfg(feature = "totalizer")]
+use crate::totalizer::Totalizer;
+#[cfg(feature = "speedometer")]
+use crate::speedometer::Speedometer;
+#[cfg(feature = "hour-meter")]
+use crate::hour_meter::HourMeter;
+#[cfg(feature = "weather")]
+use crate::weather::Dashboard;
+#[cfg(feature = "pir")]
+use crate::motion::Motion;
+#[cfg(feature = "backlight")]
+use crate::backlight::Backlight;
+#[cfg(feature = "day-night")]
+use crate::schedule::{Field, Period, Schedule};
+#[cfg(feature = "dmx")]
+use crate::dmx::Monitor;
+#[cfg(feature = "midi")]
+use crate::midi::Midi;
+#[cfg(feature = "door-lock")]
+use crate::lock::DoorLock;
+#[cfg(feature = "access-log")]
+use crate::access_log::EventView;
+#[cfg(feature = "calibration")]
+use crate::calibration::Calibration;
+#[cfg(feature = "battery")]
+use crate::battery::Battery;
+use crate::text::{self, Align};
+use crate::ui::{Event, StateMachine, Transition};
+use crate::units::TempUnit;
+use crate::setup::Setup;
+use lcd::Display;
+
+/// How long each screen is shown, in milliseconds.
+pub const SCREEN_TIME_MS: u32 = 500;
+
+/// Chip temperature trend is not scaled to less than 2°C (in tenths), the sensor is noisier than
+/// that.
+const TREND_MIN_SPAN: i16 = 20;
+
+/// Custom character: low battery icon.
+pub const LOW_BATTERY: char = '\u{0}';
+
+const LOW_BATTERY_MAP: [u8; 8] = [
+    0b01110,
+    0b11011,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b10001,
+    0b11111,
+    0b11111,
+];
+
+/// Custom character: checkerboard, used by the self-test.
+pub const CHECKERBOARD: char = '\u{1}';
+
+pub const CHECKERBOARD_MAP: [u8; 8] = [
+    0b10101,
+    0b01010,
+    0b10101,
+    0b01010,
+    0b10101,
+    0b01010,
+    0b10101,
+    0b01010,
+];
+
+/// Custom character: error icon (for the log messages).
+pub const ERROR_ICON: char = '\u{2}';
+
+const ERROR_ICON_MAP: [u8; 8] = [
+    0b11111,
+    0b11011,
+    0b11011,
+    0b11011,
+    0b11111,
+    0b11011,
+    0b11111,
+    0b00000,
+];
+
+/// Custom character: warning icon (for the log messages).
+pub const WARNING_ICON: char = '\u{3}';
+
+const WARNING_ICON_MAP: [u8; 8] = [
+    0b00100,
+    0b01110,
+    0b01010,
+    0b11011,
+    0b11111,
+    0b11011,
+    0b11111,
+    0b00000,
+];
+
+/// Custom characters used by the screens (all eight of them): uploaded by `init`, and restored
+/// by `Screen::render` after the snake game took them over.
+const GLYPHS: [(char, [u8; 8]); 8] = [
+    (LOW_BATTERY, LOW_BATTERY_MAP),
+    (CHECKERBOARD, CHECKERBOARD_MAP),
+    (ERROR_ICON, ERROR_ICON_MAP),
+    (WARNING_ICON, WARNING_ICON_MAP),
+    (big::TOP, big::TOP_MAP),
+    (big::BOTTOM, big::BOTTOM_MAP),
+    (big::BOTH, big::BOTH_MAP),
+    (big::FULL, big::FULL_MAP),
+];
+
+/// Full block character of the display character set (not ASCII, so it cannot be written as text).
+pub const FULL_BLOCK: u8 = 0xff;
+
+/// Display settings: two lines, 5x8 font, cursor hidden.
+pub const SETUP: Setup = Setup::new();
+
+/// Initialize display, upload custom characters and turn display on.
+pub fn init<HW: lcd::Hardware + lcd::Delay>(display: &mut Display<HW>) {
+    SETUP.init(display);
+    for (c, map) in GLYPHS {
+        display.upload_character(c as u8, map);
+    }
+    SETUP.on(display);
+}
+
+/// Fill every cell of the display with the given character code (for the self-test).
+pub fn fill<HW: lcd::Hardware + lcd::Delay>(display: &mut Display<HW>, code: u8) {
+    for row in 0..ROWS as u8 {
+        display.position(0, row);
+        for _ in 0..COLUMNS {
+            display.write(code);
+        }
+    }
+}
+
+/// Render the screensaver: both rows blank (backlight, if any, is turned off by its own task).
+pub fn blank<D: TextDisplay>(display: &mut D) -> fmt::Result {
+    for row in 0..ROWS {
+        Line::new(display, row as u8).finish()?;
+    }
+    Ok(())
+}
+
+/// Render the latched threshold alarm (flashing), in place of any screen or the remote text.
+pub fn alert<D: TextDisplay>(display: &mut D, settings: &Settings) -> fmt::Result {
+    for (c, map) in GLYPHS {
+        display.upload(c as u8, map)?;
+    }
+    #[cfg(feature = "battery")]
+    display.upload(LOW_BATTERY as u8, settings.battery.glyph())?;
+    for row in 0..ROWS {
+        let mut line = Line::new(display, row as u8);
+        settings.thresholds.write_alert(&mut line, row, settings.temp_unit)?;
+        line.finish()?;
+    }
+    Ok(())
+}
+
+/// Report self-test result, `failure` is the name of the failed check.
+pub fn self_test_result<D: TextDisplay>(display: &mut D, failure: Option<&str>) -> fmt::Result {
+    display.clear()?;
+    display.write_at(0, 0, format_args!("Self-test"))?;
+    match failure {
+        Some(check) => display.write_at(0, 1, format_args!("FAIL: {}", check)),
+        None => display.write_at(0, 1, format_args!("pass")),
+    }
+}
+
+/// How long the splash screen is shown, in microseconds.
+pub const SPLASH_TIME_US: u32 = 800_000;
+
+/// Splash screen shown once on boot, `status` goes to the second row.
+pub fn splash<D: TextDisplay>(display: &mut D, status: &str) -> fmt::Result {
+    display.clear()?;
+    display.write_str("LCD example")?;
+    display.write_at(0, 1, format_args!("{}", status))
+}
+
+/// Runtime figures shown on the diagnostics screen.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Estimated percentage of time the core is awake.
+    pub duty_cycle: u8,
+    /// Chip temperature, in tenths of °C.
+    pub temperature: i16,
+    /// Supply voltage, in millivolts.
+    pub vdd_mv: u16,
+    /// GPIOA input data register.
+    pub gpioa_idr: u16,
+    /// GPIOA output data register.
+    pub gpioa_odr: u16,
+    /// Time since boot, in seconds.
+    pub uptime_s: u32,
+    /// How many times rendering has failed (display queue overflow).
+    pub render_errors: u16,
+    /// Supply voltage is below the warning threshold.
+    pub low_voltage: bool,
+    /// How many times display was re-initialized after it got stuck.
+    pub display_resets: u16,
+    /// Cells found wrong when the display memory was read back (`read-back` feature).
+    pub readback_errors: u16,
+    /// Main loop iterations per second.
+    pub loop_rate: u32,
+    /// Longest main loop iteration (not counting the sleep), in microseconds.
+    pub loop_max_us: u32,
+    /// Stack bytes never used since boot.
+    pub stack_free: u32,
+    /// Number of boots (kept in the backup registers).
+    pub boots: u16,
+    /// Cause of the last reset.
+    pub reset_cause: &'static str,
+    /// Total runtime over all boots, in seconds.
+    pub runtime_s: u32,
+    /// Memory at the inspected address, `None` if it cannot be read.
+    pub memory: Option<[u8; WINDOW]>,
+    /// SPI flash identification, `None` if there is no chip.
+    #[cfg(feature = "spi-flash")]
+    pub flash: Option<Flash>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Screen {
+    Hello,
+    Bye,
+    Diagnostics,
+    /// Chip temperature trend.
+    Trend,
+    Load,
+    Registers,
+    Inspect,
+    /// Devices found on the I2C bus.
+    #[cfg(feature = "i2c-scan")]
+    I2cScan,
+    /// Devices found on the 1-Wire bus.
+    #[cfg(feature = "onewire")]
+    OneWire,
+    /// SPI flash identification.
+    #[cfg(feature = "spi-flash")]
+    Flash,
+    /// Fan speed and curve.
+    #[cfg(feature = "fan")]
+    Fan,
+    /// Temperature log in flash.
+    #[cfg(feature = "temp-log")]
+    TempLog,
+    /// Shaft speed measured by the hall sensor, and its peak.
+    #[cfg(feature = "tachometer")]
+    Tachometer,
+    /// Pulses counted over resets.
+    #[cfg(feature = "totalizer")]
+    Totalizer,
+    /// Bicycle speed in big digits, trip distance and odometer.
+    #[cfg(feature = "speedometer")]
+    Speedometer,
+    /// Equipment runtime, total and trip.
+    #[cfg(feature = "hour-meter")]
+    HourMeter,
+    /// Readings of the ambient sensors.
+    #[cfg(feature = "weather")]
+    Weather,
+    /// PIR motion history.
+    #[cfg(feature = "pir")]
+    Motion,
+    /// DMX512 channel values.
+    #[cfg(feature = "dmx")]
+    Dmx,
+    /// Last MIDI note and the channel activity.
+    #[cfg(feature = "midi")]
+    Midi,
+    /// Door lock, PIN entry.
+    #[cfg(feature = "door-lock")]
+    Lock,
+    /// Access log, newest event first.
+    #[cfg(feature = "access-log")]
+    Events,
+    /// ADC calibration.
+    #[cfg(feature = "calibration")]
+    Calibration,
+    Uptime,
+    Boots,
+    Stopwatch,
+    Countdown,
+    Alarm,
+    Pomodoro,
+    Thermostat,
+    /// Alarm thresholds of the measured values.
+    Limits,
+    /// PID loop tuning (simulated heater).
+    Pid,
+    /// Reaction-time game.
+    Reaction,
+    /// Snake game, drawn with the custom characters.
+    Snake,
+    /// Dice roller.
+    Dice,
+    /// Morse code trainer.
+    Morse,
+    /// Fixed-point calculator.
+    Calculator,
+    Settings,
+}
+
+impl Screen {
+    /// Short name of the screen (for logging).
+    pub fn name(self) -> &'static str {
+        match self {
+            Screen::Hello => "hello",
+            Screen::Bye => "bye",
+            Screen::Diagnostics => "diagnostics",
+            Screen::Trend => "trend",
+            Screen::Load => "load",
+            Screen::Registers => "registers",
+            Screen::Inspect => "inspect",
+            #[cfg(feature = "i2c-scan")]
+            Screen::I2cScan => "i2c scan",
+            #[cfg(feature = "onewire")]
+            Screen::OneWire => "1-wire",
+            #[cfg(feature = "spi-flash")]
+            Screen::Flash => "flash",
+            #[cfg(feature = "fan")]
+            Screen::Fan => "fan",
+            #[cfg(feature = "temp-log")]
+            Screen::TempLog => "temperature log",
+            #[cfg(feature = "tachometer")]
+            Screen::Tachometer => "tachometer",
+            #[cfg(feature = "totalizer")]
+            Screen::Totalizer => "totalizer",
+            #[cfg(feature = "speedometer")]
+            Screen::Speedometer => "speedometer",
+            #[cfg(feature = "hour-meter")]
+            Screen::HourMeter => "hour meter",
+            #[cfg(feature = "weather")]
+            Screen::Weather => "weather",
+            #[cfg(feature = "pir")]
+            Screen::Motion => "motion",
+            #[cfg(feature = "dmx")]
+            Screen::Dmx => "DMX",
+            #[cfg(feature = "midi")]
+            Screen::Midi => "MIDI",
+            #[cfg(feature = "door-lock")]
+            Screen::Lock => "lock",
+            #[cfg(feature = "access-log")]
+            Screen::Events => "events",
+            #[cfg(feature = "calibration")]
+            Screen::Calibration => "calibration",
+            Screen::Uptime => "uptime",
+            Screen::Boots => "boots",
+            Screen::Stopwatch => "stopwatch",
+            Screen::Countdown => "countdown",
+            Screen::Alarm => "alarm clock",
+            Screen::Pomodoro => "pomodoro",
+            Screen::Thermostat => "thermostat",
+            Screen::Limits => "limits",
+            Screen::Pid => "pid",
+            Screen::Reaction => "reaction",
+            Screen::Snake => "snake",
+            Screen::Dice => "dice",
+            Screen::Morse => "morse",
+            Screen::Calculator => "calculator",
+            Screen::Settings => "settings",
+        }
+    }
+
+    /// Does the screen take the long button press (`Event::LongPress`)? On these screens, `Button`
+    /// is reported on the release, once it is known the press is not the long one.
+    pub fn long_press(self) -> bool {
+        match self {
+            Screen::Thermostat => true,
+            #[cfg(feature = "pin-monitor")]
+            Screen::Registers => true,
+            #[cfg(feature = "tachometer")]
+            Screen::Tachometer => true,
+            #[cfg(feature = "totalizer")]
+            Screen::Totalizer => true,
+            #[cfg(feature = "speedometer")]
+            Screen::Speedometer => true,
+            #[cfg(feature = "hour-meter")]
+            Screen::HourMeter => true,
+            #[cfg(feature = "calibration")]
+            Screen::Calibration => true,
+            _ => false,
+        }
+    }
+
+    /// Does the screen draw bars (with the trend glyphs)?
+    fn bars(self) -> bool {
+        match self {
+            Screen::Trend => true,
+            #[cfg(feature = "dmx")]
+            Screen::Dmx => true,
+            #[cfg(feature = "midi")]
+            Screen::Midi => true,
+            _ => false,
+        }
+    }
+
+    /// Render the screen. Screens are rendered over each other, so each row is written in full,
+    /// padded with spaces. Rows are staged in a buffer and sent to the display in one burst.
+    ///
+    /// If supply voltage is low, low battery icon is shown in the top right corner of every screen.
+    /// Otherwise, if rendering has ever failed, `!` is shown there (the number of failures and of
+    /// display re-initializations is shown on the uptime screen). Otherwise, with the `battery`
+    /// feature, battery level icon is shown there (in place of the low battery one) once the
+    /// battery is measured, if the screen leaves that cell blank.
+    ///
+    /// Snake screen hands out all the custom characters but the low battery icon to its field (see
+    /// `pixels`), trend, DMX and MIDI screens re-program them for the bars, other screens restore
+    /// them (which costs nothing with the framebuffer, if they are not changed).
+    ///
+    /// Latched threshold alarm is shown instead of any screen, until it is acknowledged.
+    pub fn render<D: TextDisplay>(self, display: &mut D, stats: &Stats, settings: &Settings) -> fmt::Result {
+        if settings.thresholds.latched().is_some() {
+            return alert(display, settings);
+        }
+        let mut field = None;
+        if self == Screen::Snake {
+            let mut cgram = Cgram::default();
+            cgram.reserve(LOW_BATTERY as u8);
+            display.upload(LOW_BATTERY as u8, LOW_BATTERY_MAP)?;
+            let region = settings.snake.field();
+            let codes = region.allocate(&mut cgram);
+            cgram.upload(display)?;
+            field = Some((region, codes));
+        } else if self.bars() {
+            for (c, map) in trend::GLYPHS {
+                display.upload(c as u8, map)?;
+            }
+        } else {
+            for (c, map) in GLYPHS {
+                display.upload(c as u8, map)?;
+            }
+        }
+        #[cfg(feature = "battery")]
+        display.upload(LOW_BATTERY as u8, settings.battery.glyph())?;
+        let mut line = Line::new(display, 0);
+        match self {
+            Screen::Hello => line.write_str("Hello!")?,
+            Screen::Bye => line.write_str("Bye!")?,
+            #[cfg(not(feature = "read-back"))]
+            Screen::Diagnostics => {
+                line.write_str("Duty cycle ")?;
+                text::uint(&mut line, u32::from(stats.duty_cycle), 3)?;
+                line.write_str("%")?;
+            }
+            #[cfg(feature = "read-back")]
+            Screen::Diagnostics => {
+                line.write_str("Duty")?;
+                text::uint(&mut line, u32::from(stats.duty_cycle), 3)?;
+                line.write_str("% vfy")?;
+                text::uint(&mut line, u32::from(stats.readback_errors), 3)?;
+            }
+            Screen::Trend => {
+                line.write_str("Temp")?;
+                match settings.trend.latest() {
+                    Some(temp) => text::fixed(&mut line, settings.temp_unit.from_tenths_c(temp), 1, 7)?,
+                    None => text::str(&mut line, "-", 7, Align::Right)?,
+                }
+                line.write_str(settings.temp_unit.symbol())?;
+            }
+            Screen::Load => {
+                line.write_str("Loop")?;
+                text::uint(&mut line, stats.loop_rate, 5)?;
+                line.write_str("/s")?;
+                text::uint(&mut line, u32::from(100 - stats.duty_cycle.min(100)), 3)?;
+                line.write_str("%")?;
+            }
+            #[cfg(feature = "pin-monitor")]
+            Screen::Registers => settings.pins.write_counts(&mut line)?,
+            #[cfg(not(feature = "pin-monitor"))]
+            Screen::Registers => {
+                line.write_str("I:")?;
+                text::hex16(&mut line, stats.gpioa_idr)?;
+                line.write_str("  O:")?;
+                text::hex16(&mut line, stats.gpioa_odr)?;
+            }
+            Screen::Inspect => settings.inspector.write_address(&mut line)?,
+            #[cfg(feature = "i2c-scan")]
+            Screen::I2cScan => settings.i2c.write_summary(&mut line)?,
+            #[cfg(feature = "onewire")]
+            Screen::OneWire => settings.onewire.write_summary(&mut line, |w, temp| {
+                text::fixed(w, settings.temp_unit.from_tenths_c(temp), 1, 7)?;
+                w.write_str(settings.temp_unit.symbol())
+            })?,
+            #[cfg(feature = "spi-flash")]
+            Screen::Flash => match stats.flash {
+                Some(flash) => flash.write_summary(&mut line)?,
+                None => line.write_str("Flash none")?,
+            },
+            #[cfg(feature = "fan")]
+            Screen::Fan => settings.fan.write_status(&mut line)?,
+            #[cfg(feature = "temp-log")]
+            Screen::TempLog => settings.temp_log.write_status(&mut line, settings.temp_unit)?,
+            #[cfg(feature = "tachometer")]
+            Screen::Tachometer => settings.tachometer.write_speed(&mut line)?,
+            #[cfg(feature = "totalizer")]
+            Screen::Totalizer => settings.totalizer.write_total(&mut line)?,
+            #[cfg(feature = "speedometer")]
+            Screen::Speedometer => settings.speedometer.write_row(&mut line, 0)?,
+            #[cfg(feature = "hour-meter")]
+            Screen::HourMeter => settings.hour_meter.write_total(&mut line)?,
+            #[cfg(feature = "weather")]
+            Screen::Weather => settings.weather.write_row(&mut line, 0, settings.temp_unit)?,
+            #[cfg(feature = "pir")]
+            Screen::Motion => settings.motion.write_count(&mut line)?,
+            #[cfg(feature = "dmx")]
+            Screen::Dmx => settings.dmx.write_channel(&mut line)?,
+            #[cfg(feature = "midi")]
+            Screen::Midi => settings.midi.write_note(&mut line)?,
+            #[cfg(feature = "door-lock")]
+            Screen::Lock => settings.lock.write_status(&mut line)?,
+            #[cfg(feature = "access-log")]
+            Screen::Events => settings.events.write_event(&mut line)?,
+            #[cfg(feature = "calibration")]
+            Screen::Calibration => settings.calibration.write_status(&mut line, settings.temp_unit)?,
+            Screen::Uptime => {
+                line.write_str("err")?;
+                text::uint(&mut line, u32::from(stats.render_errors), 4)?;
+                line.write_str(" rst")?;
+                text::uint(&mut line, u32::from(stats.display_resets), 4)?;
+            }
+            Screen::Boots => {
+                text::str(&mut line, stats.reset_cause, 10, Align::Left)?;
+                text::uint(&mut line, u32::from(stats.boots), 5)?;
+            }
+            Screen::Stopwatch => settings.stopwatch.write_time(&mut line)?,
+            Screen::Countdown => settings.countdown.write_row(&mut line, 0)?,
+            Screen::Alarm => settings.alarm_clock.write_time(&mut line)?,
+            Screen::Pomodoro => settings.pomodoro.write_status(&mut line)?,
+            Screen::Thermostat => settings.thermostat.write_status(&mut line, settings.temp_unit)?,
+            Screen::Limits => settings.thresholds.write_status(&mut line, settings.temp_unit)?,
+            Screen::Pid => settings.heater.write_values(&mut line, settings.temp_unit)?,
+            Screen::Reaction => settings.reaction.write_status(&mut line)?,
+            Screen::Snake => {
+                write_field_row(&mut line, &field, 0)?;
+                settings.snake.write_status(&mut line)?;
+            }
+            Screen::Dice => settings.dice.write_row(&mut line, 0)?,
+            Screen::Morse => settings.morse.write_text(&mut line)?,
+            Screen::Calculator => settings.calculator.write_expr(&mut line)?,
+            Screen::Settings => line.write_str("Settings")?,
+        }
+        if stats.low_voltage {
+            line.flag(LOW_BATTERY);
+        } else if stats.render_errors > 0 {
+            line.flag('!');
+        }
+        #[cfg(feature = "battery")]
+        if settings.battery.is_measured() {
+            line.indicator(LOW_BATTERY);
+        }
+        line.finish()?;
+
+        let mut line = Line::new(display, 1);
+        match self {
+            Screen::Hello | Screen::Bye => {}
+            Screen::Diagnostics => {
+                text::fixed(&mut line, settings.temp_unit.from_tenths_c(stats.temperature), 1, 5)?;
+                line.write_str(settings.temp_unit.symbol())?;
+                line.write_str("     ")?;
+                text::si(&mut line, i32::from(stats.vdd_mv), -1, "V", 5)?;
+            }
+            Screen::Trend => settings.trend.write_bars(&mut line)?,
+            Screen::Load => {
+                line.write_str("max")?;
+                text::uint(&mut line, stats.loop_max_us, 5)?;
+                line.write_str("us")?;
+                text::uint(&mut line, stats.stack_free, 5)?;
+                line.write_str("B")?;
+            }
+            #[cfg(feature = "pin-monitor")]
+            Screen::Registers => settings.pins.write_levels(&mut line)?,
+            #[cfg(not(feature = "pin-monitor"))]
+            Screen::Registers => {
+                line.write_str("PA7-0 ")?;
+                text::binary(&mut line, u32::from(stats.gpioa_idr), 8)?;
+            }
+            Screen::Inspect => match stats.memory {
+                Some(bytes) => {
+                    for byte in bytes {
+                        text::hex8(&mut line, byte)?;
+                    }
+                }
+                None => line.write_str("unreadable")?,
+            },
+            #[cfg(feature = "i2c-scan")]
+            Screen::I2cScan => settings.i2c.write_page(&mut line)?,
+            #[cfg(feature = "onewire")]
+            Screen::OneWire => settings.onewire.write_rom(&mut line)?,
+            #[cfg(feature = "spi-flash")]
+            Screen::Flash => {
+                if let Some(flash) = stats.flash {
+                    flash.write_details(&mut line)?;
+                }
+            }
+            #[cfg(feature = "fan")]
+            Screen::Fan => settings.fan.write_page(&mut line, settings.temp_unit)?,
+            #[cfg(feature = "temp-log")]
+            Screen::TempLog => settings.temp_log.write_page(&mut line, settings.temp_unit)?,
+            #[cfg(feature = "tachometer")]
+            Screen::Tachometer => settings.tachometer.write_peak(&mut line)?,
+            #[cfg(feature = "totalizer")]
+            Screen::Totalizer => settings.totalizer.write_status(&mut line)?,
+            #[cfg(feature = "speedometer")]
+            Screen::Speedometer => settings.speedometer.write_row(&mut line, 1)?,
+            #[cfg(feature = "hour-meter")]
+            Screen::HourMeter => settings.hour_meter.write_trip(&mut line)?,
+            #[cfg(feature = "weather")]
+            Screen::Weather => settings.weather.write_row(&mut line, 1, settings.temp_unit)?,
+            #[cfg(feature = "pir")]
+            Screen::Motion => settings.motion.write_event(&mut line)?,
+            #[cfg(feature = "dmx")]
+            Screen::Dmx => settings.dmx.write_bars(&mut line)?,
+            #[cfg(feature = "midi")]
+            Screen::Midi => settings.midi.write_activity(&mut line)?,
+            #[cfg(feature = "door-lock")]
+            Screen::Lock => settings.lock.write_entry(&mut line)?,
+            #[cfg(feature = "access-log")]
+            Screen::Events => settings.events.write_time(&mut line)?,
+            #[cfg(feature = "calibration")]
+            Screen::Calibration => settings.calibration.write_page(&mut line, settings.temp_unit)?,
+            Screen::Uptime => {
+                line.write_str("up")?;
+                text::duration(&mut line, stats.uptime_s, COLUMNS - 2)?;
+            }
+            Screen::Boots => {
+                line.write_str("run")?;
+                text::duration(&mut line, stats.runtime_s, COLUMNS - 3)?;
+            }
+            Screen::Stopwatch => settings.stopwatch.write_lap(&mut line)?,
+            Screen::Countdown => settings.countdown.write_row(&mut line, 1)?,
+            Screen::Alarm => settings.alarm_clock.write_page(&mut line)?,
+            Screen::Pomodoro => settings.pomodoro.write_progress(&mut line)?,
+            Screen::Thermostat => settings.thermostat.write_page(&mut line, settings.temp_unit)?,
+            Screen::Limits => settings.thresholds.write_limits(&mut line, settings.temp_unit)?,
+            Screen::Pid => settings.heater.write_param(&mut line, settings.temp_unit)?,
+            Screen::Reaction => settings.reaction.write_scores(&mut line)?,
+            Screen::Snake => {
+                write_field_row(&mut line, &field, 1)?;
+                settings.snake.write_best(&mut line)?;
+            }
+            Screen::Dice => settings.dice.write_row(&mut line, 1)?,
+            Screen::Morse => settings.morse.write_code(&mut line)?,
+            Screen::Calculator => settings.calculator.write_value(&mut line)?,
+            Screen::Settings => match settings.preference {
+                Preference::TempUnit => {
+                    text::str(&mut line, "Temp unit", COLUMNS - 1, Align::Left)?;
+                    line.write_str(settings.temp_unit.symbol())?;
+                }
+                #[cfg(feature = "tachometer")]
+                Preference::PulsesPerRev => {
+                    text::str(&mut line, "Pulses/rev", COLUMNS - 2, Align::Left)?;
+                    text::uint(&mut line, u32::from(settings.tachometer.pulses_per_rev()), 2)?;
+                }
+                #[cfg(feature = "speedometer")]
+                Preference::WheelCircumference => {
+                    text::str(&mut line, "Wheel", COLUMNS - 6, Align::Left)?;
+                    text::uint(&mut line, u32::from(settings.speedometer.circumference()), 4)?;
+                    line.write_str("mm")?;
+                }
+                #[cfg(feature = "backlight")]
+                Preference::Backlight => settings.backlight.write_preference(&mut line)?,
+                #[cfg(feature = "day-night")]
+                Preference::Schedule(period, field) => settings.schedule.write_preference(&mut line, period, field)?,
+                #[cfg(feature = "battery")]
+                Preference::BatteryCutoff => settings.battery.write_preference(&mut line)?,
+            },
+        }
+        line.finish()
+    }
+}
+
+/// Preference shown on the settings screen.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Preference {
+    TempUnit,
+    /// Tachometer pulses per revolution.
+    #[cfg(feature = "tachometer")]
+    PulsesPerRev,
+    /// Speedometer wheel circumference.
+    #[cfg(feature = "speedometer")]
+    WheelCircumference,
+    /// Backlight brightness, or following the ambient light.
+    #[cfg(feature = "backlight")]
+    Backlight,
+    /// Setting of the day or night profile of the display.
+    #[cfg(feature = "day-night")]
+    Schedule(Period, Field),
+    /// Battery voltage the board shuts down at.
+    #[cfg(feature = "battery")]
+    BatteryCutoff,
+}
+
+impl Preference {
+    const ALL: &'static [Preference] = &[
+        Preference::TempUnit,
+        #[cfg(feature = "tachometer")]
+        Preference::PulsesPerRev,
+        #[cfg(feature = "speedometer")]
+        Preference::WheelCircumference,
+        #[cfg(feature = "backlight")]
+        Preference::Backlight,
+        #[cfg(feature = "day-night")]
+        Preference::Schedule(Period::Day, Field::Start),
+        #[cfg(feature = "day-night")]
+        Preference::Schedule(Period::Day, Field::Backlight),
+        #[cfg(feature = "day-night")]
+        Preference::Schedule(Period::Day, Field::Contrast),
+        #[cfg(feature = "day-night")]
+        Preference::Schedule(Period::Night, Field::Start),
+        #[cfg(feature = "day-night")]
+        Preference::Schedule(Period::Night, Field::Backlight),
+        #[cfg(feature = "day-night")]
+        Preference::Schedule(Period::Night, Field::Contrast),
+        #[cfg(feature = "battery")]
+        Preference::BatteryCutoff,
+    ];
+
+    /// Preference shown after this one, `None` after the last one.
+    fn next(self) -> Option<Preference> {
+        let index = Preference::ALL.iter().position(|&preference| preference == self)?;
+        Preference::ALL.get(index + 1).copied()
+    }
+}
+
+/// User preferences. Also the context for the transition guards.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Settings {
+    /// Switch between "Hello" and "Bye" screens on timer.
+    pub auto_rotate: bool,
+    /// Unit temperatures are shown in.
+    pub temp_unit: TempUnit,
+    /// Preference shown on the settings screen (button goes through them).
+    pub preference: Preference,
+    /// Address shown on the inspector screen.
+    pub inspector: Inspector,
+    /// Stopwatch, running in the background (time is advanced by the main loop).
+    pub stopwatch: Stopwatch,
+    /// Countdown timer, running in the background, too.
+    pub countdown: Countdown,
+    /// Alarm clock, alarms ring on any screen.
+    pub alarm_clock: AlarmClock,
+    /// Pomodoro timer, running in the background.
+    pub pomodoro: Pomodoro,
+    /// Thermostat (temperature is fed by the sensor task).
+    pub thermostat: Thermostat,
+    /// Alarm thresholds (measured values are checked by the tasks taking them).
+    pub thresholds: Thresholds,
+    /// Simulated heater and its PID loop, running in the background.
+    pub heater: Heater,
+    /// Reaction-time game and its best times (kept in flash by the main loop).
+    pub reaction: Reaction,
+    /// Snake game, paused by the button before leaving the screen.
+    pub snake: Snake,
+    /// Die selected and the last roll.
+    pub dice: Dice,
+    /// Morse code trainer, keying in the background.
+    pub morse: Morse,
+    /// Calculator expression and its value.
+    pub calculator: Calculator,
+    /// Chip temperature samples shown on the trend screen (fed by the sensor task).
+    pub trend: Trend<COLUMNS>,
+    /// Results shown on the I2C scanner screen (updated by the scanner).
+    #[cfg(feature = "i2c-scan")]
+    pub i2c: ScanResults,
+    /// Devices shown on the 1-Wire screen (updated by the bus poller).
+    #[cfg(feature = "onewire")]
+    pub onewire: Devices,
+    /// Fan curve and speed shown on the fan screen (updated by the fan task).
+    #[cfg(feature = "fan")]
+    pub fan: Fan,
+    /// Temperature log summary and the record shown (updated by the main loop from the log).
+    #[cfg(feature = "temp-log")]
+    pub temp_log: LogView,
+    /// Shaft speed shown on the tachometer screen (updated by the tachometer task).
+    #[cfg(feature = "tachometer")]
+    pub tachometer: Tachometer,
+    /// Pulse total shown on the totalizer screen (updated and checkpointed by the main loop).
+    #[cfg(feature = "totalizer")]
+    pub totalizer: Totalizer,
+    /// Bicycle speed and distances shown on the speedometer screen (updated by the tachometer task,
+    /// odometer is checkpointed by the main loop).
+    #[cfg(feature = "speedometer")]
+    pub speedometer: Speedometer,
+    /// Runtime shown on the hour meter screen (updated and checkpointed by the hour meter task).
+    #[cfg(feature = "hour-meter")]
+    pub hour_meter: HourMeter,
+    /// Edges counted on the registers screen (updated by the pin monitor task).
+    #[cfg(feature = "pin-monitor")]
+    pub pins: PinMonitor,
+    /// Ambient readings shown on the weather screen (fed by the sensor tasks).
+    #[cfg(feature = "weather")]
+    pub weather: Dashboard,
+    /// Screensaver and the motion history (fed by the PIR task).
+    #[cfg(feature = "pir")]
+    pub motion: Motion,
+    /// Backlight brightness (fed with the ambient light by the backlight task).
+    #[cfg(feature = "backlight")]
+    pub backlight: Backlight,
+    /// Day and night profiles of the backlight and the contrast (switched by the clock of the
+    /// alarm clock, kept in flash by the main loop).
+    #[cfg(feature = "day-night")]
+    pub schedule: Schedule,
+    /// DMX512 channels shown on the DMX screen (fed from the receiver by the DMX task).
+    #[cfg(feature = "dmx")]
+    pub dmx: Monitor,
+    /// MIDI messages shown on the MIDI screen (fed with the received bytes by the MIDI task).
+    #[cfg(feature = "midi")]
+    pub midi: Midi,
+    /// Door lock (keys are fed by the keypad task, PIN is kept in flash by the main loop).
+    #[cfg(feature = "door-lock")]
+    pub lock: DoorLock,
+    /// Access log event shown on the events screen (read from flash by the main loop).
+    #[cfg(feature = "access-log")]
+    pub events: EventView,
+    /// ADC calibration constants (the readings are fed and corrected by the sensor tasks, constants
+    /// are kept in flash by the main loop).
+    #[cfg(feature = "calibration")]
+    pub calibration: Calibration,
+    /// Battery gauge and its cutoff (fed with the supply voltage by the main loop, cutoff is kept
+    /// in flash).
+    #[cfg(feature = "battery")]
+    pub battery: Battery,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            auto_rotate: true,
+            temp_unit: TempUnit::Celsius,
+            preference: Preference::TempUnit,
+            inspector: Inspector::default(),
+            stopwatch: Stopwatch::default(),
+            countdown: Countdown::default(),
+            alarm_clock: AlarmClock::default(),
+            pomodoro: Pomodoro::default(),
+            thermostat: Thermostat::default(),
+            thresholds: Thresholds::default(),
+            heater: Heater::default(),
+            reaction: Reaction::default(),
+            snake: Snake::default(),
+            dice: Dice::default(),
+            morse: Morse::default(),
+            calculator: Calculator::default(),
+            trend: Trend::new(TREND_MIN_SPAN),
+            #[cfg(feature = "i2c-scan")]
+            i2c: ScanResults::default(),
+            #[cfg(feature = "onewire")]
+            onewire: Devices::default(),
+            #[cfg(feature = "fan")]
+            fan: Fan::default(),
+            #[cfg(feature = "temp-log")]
+            temp_log: LogView::default(),
+            #[cfg(feature = "tachometer")]
+            tachometer: Tachometer::default(),
+            #[cfg(feature = "totalizer")]
+            totalizer: Totalizer::default(),
+            #[cfg(feature = "speedometer")]
+            speedometer: Speedometer::default(),
+            #[cfg(feature = "hour-meter")]
+            hour_meter: HourMeter::default(),
+            #[cfg(feature = "pin-monitor")]
+            pins: PinMonitor::default(),
+            #[cfg(feature = "weather")]
+            weather: Dashboard::default(),
+            #[cfg(feature = "pir")]
+            motion: Motion::default(),
+            #[cfg(feature = "backlight")]
+            backlight: Backlight::default(),
+            #[cfg(feature = "day-night")]
+            schedule: Schedule::default(),
+            #[cfg(feature = "dmx")]
+            dmx: Monitor::default(),
+            #[cfg(feature = "midi")]
+            midi: Midi::default(),
+            #[cfg(feature = "door-lock")]
+            lock: DoorLock::default(),
+            #[cfg(feature = "access-log")]
+            events: EventView::default(),
+            #[cfg(feature = "calibration")]
+            calibration: Calibration::default(),
+            #[cfg(feature = "battery")]
+            battery: Battery::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Advance the stopwatch, the countdown timer, the alarm clock, the pomodoro timer, the PID
+    /// demo, the games and the Morse keying to `now_ms` (the millisecond timer), and switch the
+    /// day and night profiles by the clock. Must be called before `handle` and before rendering.
+    pub fn advance(&mut self, now_ms: u32) {
+        self.stopwatch.advance(now_ms);
+        self.countdown.advance(now_ms);
+        self.alarm_clock.advance(now_ms);
+        self.pomodoro.advance(now_ms);
+        self.heater.advance(now_ms);
+        self.reaction.advance(now_ms);
+        self.snake.advance(now_ms);
+        self.morse.advance(now_ms);
+        self.thresholds.advance(now_ms);
+        #[cfg(feature = "totalizer")]
+        self.totalizer.advance(now_ms);
+        #[cfg(feature = "weather")]
+        self.weather.advance(now_ms);
+        #[cfg(feature = "pir")]
+        self.motion.advance(now_ms);
+        #[cfg(feature = "midi")]
+        self.midi.advance(now_ms);
+        #[cfg(feature = "door-lock")]
+        self.lock.advance(now_ms);
+        #[cfg(feature = "day-night")]
+        if let Some(profile) = self.schedule.update(self.alarm_clock.minute_of_day()) {
+            self.backlight.set_manual(profile.backlight);
+        }
+    }
+
+    /// Should the buzzer be on now (countdown or pomodoro time is up, an alarm rings, a threshold
+    /// alarm is latched, or Morse code is keyed)?
+    pub fn buzzing(&self) -> bool {
+        self.countdown.buzzing()
+            || self.alarm_clock.buzzing()
+            || self.pomodoro.buzzing()
+            || self.thresholds.buzzing()
+            || self.morse.buzzing()
+    }
+
+    /// Let the `screen` take the `event` first (each screen module documents the keys it takes);
+    /// navigation (`TRANSITIONS`) only gets the events it did not take. Latched threshold alarm and
+    /// ringing alarm take the events on any screen. Returns `true` if settings were changed (event
+    /// should not be used for navigation then).
+    pub fn handle(&mut self, screen: Screen, event: Event) -> bool {
+        if self.thresholds.handle_latched(event) || self.alarm_clock.handle_ringing(event) {
+            return true;
+        }
+        match (screen, event) {
+            (Screen::Settings, Event::Button) => match self.preference.next() {
+                Some(preference) => {
+                    self.preference = preference;
+                    true
+                }
+                None => {
+                    self.preference = Preference::TempUnit;
+                    false
+                }
+            },
+            (Screen::Settings, Event::EncoderUp | Event::EncoderDown) => {
+                match self.preference {
+                    Preference::TempUnit => self.temp_unit = self.temp_unit.toggled(),
+                    #[cfg(feature = "tachometer")]
+                    Preference::PulsesPerRev => self.tachometer.adjust_pulses_per_rev(if event == Event::EncoderUp { 1 } else { -1 }),
+                    #[cfg(feature = "speedometer")]
+                    Preference::WheelCircumference => self.speedometer.adjust_circumference(if event == Event::EncoderUp { 1 } else { -1 }),
+                    #[cfg(feature = "backlight")]
+                    Preference::Backlight => self.backlight.adjust(if event == Event::EncoderUp { 1 } else { -1 }),
+                    #[cfg(feature = "day-night")]
+                    Preference::Schedule(period, field) => {
+                        if let Some(backlight) = self.schedule.adjust(period, field, if event == Event::EncoderUp { 1 } else { -1 }) {
+                            self.backlight.set_manual(backlight);
+                        }
+                    }
+                    #[cfg(feature = "battery")]
+                    Preference::BatteryCutoff => self.battery.adjust(if event == Event::EncoderUp { 1 } else { -1 }),
+                }
+                true
+            }
+            (Screen::Inspect, event) => self.inspector.handle(event),
+            (Screen::Stopwatch, event) => self.stopwatch.handle(event),
+            (Screen::Countdown, event) => self.countdown.handle(event),
+            (Screen::Alarm, event) => self.alarm_clock.handle(event),
+            (Screen::Pomodoro, event) => self.pomodoro.handle(event),
+            (Screen::Thermostat, event) => self.thermostat.handle(event),
+            (Screen::Limits, event) => self.thresholds.handle(event),
+            (Screen::Pid, event) => self.heater.handle(event),
+            (Screen::Reaction, event) => self.reaction.handle(event),
+            (Screen::Snake, event) => self.snake.handle(event),
+            (Screen::Dice, event) => self.dice.handle(event),
+            (Screen::Morse, event) => self.morse.handle(event),
+            (Screen::Calculator, event) => self.calculator.handle(event),
+            #[cfg(feature = "i2c-scan")]
+            (Screen::I2cScan, event) => self.i2c.handle(event),
+            #[cfg(feature = "onewire")]
+            (Screen::OneWire, event) => self.onewire.handle(event),
+            #[cfg(feature = "fan")]
+            (Screen::Fan, event) => self.fan.handle(event),
+            #[cfg(feature = "temp-log")]
+            (Screen::TempLog, event) => self.temp_log.handle(event),
+            #[cfg(feature = "pin-monitor")]
+            (Screen::Registers, event) => self.pins.handle(event),
+            #[cfg(feature = "tachometer")]
+            (Screen::Tachometer, event) => self.tachometer.handle(event),
+            #[cfg(feature = "totalizer")]
+            (Screen::Totalizer, event) => self.totalizer.handle(event),
+            #[cfg(feature = "speedometer")]
+            (Screen::Speedometer, event) => self.speedometer.handle(event),
+            #[cfg(feature = "hour-meter")]
+            (Screen::HourMeter, event) => self.hour_meter.handle(event),
+            #[cfg(feature = "pir")]
+            (Screen::Motion, event) => self.motion.handle(event),
+            #[cfg(feature = "dmx")]
+            (Screen::Dmx, event) => self.dmx.handle(event),
+            #[cfg(feature = "door-lock")]
+            (Screen::Lock, event) => self.lock.handle(event),
+            #[cfg(feature = "access-log")]
+            (Screen::Events, event) => self.events.handle(event),
+            #[cfg(feature = "calibration")]
+            (Screen::Calibration, event) => self.calibration.handle(event),
+            _ => false,
+        }
+    }
+}
+
+fn auto_rotate(settings: &Settings) -> bool {
+    settings.auto_rotate
+}
+
+/// Rows in the transition table (the I2C scanner, 1-Wire, flash, fan, temperature log, tachometer,
+/// totalizer, speedometer, hour meter, weather, motion, DMX, MIDI, door lock, events and
+/// calibration screens are optional).
+const TRANSITION_COUNT: usize = 90
+    + 4 * (I2C_SCAN.is_some() as usize
+        + ONEWIRE.is_some() as usize
+        + FLASH.is_some() as usize
+        + FAN.is_some() as usize
+        + TEMP_LOG.is_some() as usize
+        + TACHOMETER.is_some() as usize
+        + TOTALIZER.is_some() as usize
+        + SPEEDOMETER.is_some() as usize
+        + HOUR_METER.is_some() as usize
+        + WEATHER.is_some() as usize
+        + MOTION.is_some() as usize
+        + DMX.is_some() as usize
+        + MIDI.is_some() as usize
+        + LOCK.is_some() as usize
+        + EVENTS.is_some() as usize
+        + CALIBRATION.is_some() as usize);
+
+// Optional screens go between the inspector and the uptime screen, in this order
+#[cfg(feature = "i2c-scan")]
+const I2C_SCAN: Option<Screen> = Some(Screen::I2cScan);
+#[cfg(not(feature = "i2c-scan"))]
+const I2C_SCAN: Option<Screen> = None;
+#[cfg(feature = "onewire")]
+const ONEWIRE: Option<Screen> = Some(Screen::OneWire);
+#[cfg(not(feature = "onewire"))]
+const ONEWIRE: Option<Screen> = None;
+#[cfg(feature = "spi-flash")]
+const FLASH: Option<Screen> = Some(Screen::Flash);
+#[cfg(not(feature = "spi-flash"))]
+const FLASH: Option<Screen> = None;
+#[cfg(feature = "fan")]
+const FAN: Option<Screen> = Some(Screen::Fan);
+#[cfg(not(feature = "fan"))]
+const FAN: Option<Screen> = None;
+#[cfg(feature = "temp-log")]
+const TEMP_LOG: Option<Screen> = Some(Screen::TempLog);
+#[cfg(not(feature = "temp-log"))]
+const TEMP_LOG: Option<Screen> = None;
+#[cfg(feature = "tachometer")]
+const TACHOMETER: Option<Screen> = Some(Screen::Tachometer);
+#[cfg(not(feature = "tachometer"))]
+const TACHOMETER: Option<Screen> = None;
+#[cfg(feature = "totalizer")]
+const TOTALIZER: Option<Screen> = Some(Screen::Totalizer);
+#[cfg(not(feature = "totalizer"))]
+const TOTALIZER: Option<Screen> = None;
+#[cfg(feature = "speedometer")]
+const SPEEDOMETER: Option<Screen> = Some(Screen::Speedometer);
+#[cfg(not(feature = "speedometer"))]
+const SPEEDOMETER: Option<Screen> = None;
+#[cfg(feature = "hour-meter")]
+const HOUR_METER: Option<Screen> = Some(Screen::HourMeter);
+#[cfg(not(feature = "hour-meter"))]
+const HOUR_METER: Option<Screen> = None;
+#[cfg(feature = "weather")]
+const WEATHER: Option<Screen> = Some(Screen::Weather);
+#[cfg(not(feature = "weather"))]
+const WEATHER: Option<Screen> = None;
+#[cfg(feature = "pir")]
+const MOTION: Option<Screen> = Some(Screen::Motion);
+#[cfg(not(feature = "pir"))]
+const MOTION: Option<Screen> = None;
+#[cfg(feature = "dmx")]
+const DMX: Option<Screen> = Some(Screen::Dmx);
+#[cfg(not(feature = "dmx"))]
+const DMX: Option<Screen> = None;
+#[cfg(feature = "midi")]
+const MIDI: Option<Screen> = Some(Screen::Midi);
+#[cfg(not(feature = "midi"))]
+const MIDI: Option<Screen> = None;
+#[cfg(feature = "door-lock")]
+const LOCK: Option<Screen> = Some(Screen::Lock);
+#[cfg(not(feature = "door-lock"))]
+const LOCK: Option<Screen> = None;
+#[cfg(feature = "access-log")]
+const EVENTS: Option<Screen> = Some(Screen::Events);
+#[cfg(not(feature = "access-log"))]
+const EVENTS: Option<Screen> = None;
+#[cfg(feature = "calibration")]
+const CALIBRATION: Option<Screen> = Some(Screen::Calibration);
+#[cfg(not(feature = "calibration"))]
+const CALIBRATION: Option<Screen> = None;
+
+/// First of the optional `screens` which is enabled, `or` if none of them is.
+const fn first_of(screens: &[Option<Screen>], or: Screen) -> Screen {
+    let mut i = 0;
+    while i < screens.len() {
+        if let Some(screen) = screens[i] {
+            return screen;
+        }
+        i += 1;
+    }
+    or
+}
+
+/// Navigation between screens: button and encoder go through all screens in a loop, timer
+/// rotates between greeting screens only, serial commands jump directly to the screen. Gets only
+/// the events the screen itself did not take (see `Settings::handle`).
+pub static TRANSITIONS: [Transition<Screen, Event, Settings>; TRANSITION_COUNT] = [
+    Transition { from: Some(Screen::Hello), event: Event::Button, guard: None, to: Screen::Bye },
+    Transition { from: Some(Screen::Bye), event: Event::Button, guard: None, to: Screen::Diagnostics },
+    Transition { from: Some(Screen::Diagnostics), event: Event::Button, guard: None, to: Screen::Trend },
+    Transition { from: Some(Screen::Trend), event: Event::Button, guard: None, to: Screen::Load },
+    Transition { from: Some(Screen::Load), event: Event::Button, guard: None, to: Screen::Registers },
+    Transition { from: Some(Screen::Registers), event: Event::Button, guard: None, to: Screen::Inspect },
+    Transition { from: Some(Screen::Inspect), event: Event::Button, guard: None, to: first_of(&[I2C_SCAN, ONEWIRE, FLASH, FAN, TEMP_LOG, TACHOMETER, TOTALIZER, SPEEDOMETER, HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "i2c-scan")]
+    Transition { from: Some(Screen::I2cScan), event: Event::Button, guard: None, to: first_of(&[ONEWIRE, FLASH, FAN, TEMP_LOG, TACHOMETER, TOTALIZER, SPEEDOMETER, HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "onewire")]
+    Transition { from: Some(Screen::OneWire), event: Event::Button, guard: None, to: first_of(&[FLASH, FAN, TEMP_LOG, TACHOMETER, TOTALIZER, SPEEDOMETER, HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "spi-flash")]
+    Transition { from: Some(Screen::Flash), event: Event::Button, guard: None, to: first_of(&[FAN, TEMP_LOG, TACHOMETER, TOTALIZER, SPEEDOMETER, HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "fan")]
+    Transition { from: Some(Screen::Fan), event: Event::Button, guard: None, to: first_of(&[TEMP_LOG, TACHOMETER, TOTALIZER, SPEEDOMETER, HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "temp-log")]
+    Transition { from: Some(Screen::TempLog), event: Event::Button, guard: None, to: first_of(&[TACHOMETER, TOTALIZER, SPEEDOMETER, HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "tachometer")]
+    Transition { from: Some(Screen::Tachometer), event: Event::Button, guard: None, to: first_of(&[TOTALIZER, SPEEDOMETER, HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "totalizer")]
+    Transition { from: Some(Screen::Totalizer), event: Event::Button, guard: None, to: first_of(&[SPEEDOMETER, HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "speedometer")]
+    Transition { from: Some(Screen::Speedometer), event: Event::Button, guard: None, to: first_of(&[HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "hour-meter")]
+    Transition { from: Some(Screen::HourMeter), event: Event::Button, guard: None, to: first_of(&[WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "weather")]
+    Transition { from: Some(Screen::Weather), event: Event::Button, guard: None, to: first_of(&[MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "pir")]
+    Transition { from: Some(Screen::Motion), event: Event::Button, guard: None, to: first_of(&[DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "dmx")]
+    Transition { from: Some(Screen::Dmx), event: Event::Button, guard: None, to: first_of(&[MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "midi")]
+    Transition { from: Some(Screen::Midi), event: Event::Button, guard: None, to: first_of(&[LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "door-lock")]
+    Transition { from: Some(Screen::Lock), event: Event::Button, guard: None, to: first_of(&[EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "access-log")]
+    Transition { from: Some(Screen::Events), event: Event::Button, guard: None, to: first_of(&[CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "calibration")]
+    Transition { from: Some(Screen::Calibration), event: Event::Button, guard: None, to: Screen::Uptime },
+    Transition { from: Some(Screen::Uptime), event: Event::Button, guard: None, to: Screen::Boots },
+    Transition { from: Some(Screen::Boots), event: Event::Button, guard: None, to: Screen::Stopwatch },
+    Transition { from: Some(Screen::Stopwatch), event: Event::Button, guard: None, to: Screen::Countdown },
+    Transition { from: Some(Screen::Countdown), event: Event::Button, guard: None, to: Screen::Alarm },
+    Transition { from: Some(Screen::Alarm), event: Event::Button, guard: None, to: Screen::Pomodoro },
+    Transition { from: Some(Screen::Pomodoro), event: Event::Button, guard: None, to: Screen::Thermostat },
+    Transition { from: Some(Screen::Thermostat), event: Event::Button, guard: None, to: Screen::Limits },
+    Transition { from: Some(Screen::Limits), event: Event::Button, guard: None, to: Screen::Pid },
+    Transition { from: Some(Screen::Pid), event: Event::Button, guard: None, to: Screen::Reaction },
+    Transition { from: Some(Screen::Reaction), event: Event::Button, guard: None, to: Screen::Snake },
+    Transition { from: Some(Screen::Snake), event: Event::Button, guard: None, to: Screen::Dice },
+    Transition { from: Some(Screen::Dice), event: Event::Button, guard: None, to: Screen::Morse },
+    Transition { from: Some(Screen::Morse), event: Event::Button, guard: None, to: Screen::Calculator },
+    Transition { from: Some(Screen::Calculator), event: Event::Button, guard: None, to: Screen::Settings },
+    Transition { from: Some(Screen::Settings), event: Event::Button, guard: None, to: Screen::Hello },
+    Transition { from: Some(Screen::Hello), event: Event::EncoderUp, guard: None, to: Screen::Bye },
+    Transition { from: Some(Screen::Bye), event: Event::EncoderUp, guard: None, to: Screen::Diagnostics },
+    Transition { from: Some(Screen::Diagnostics), event: Event::EncoderUp, guard: None, to: Screen::Trend },
+    Transition { from: Some(Screen::Trend), event: Event::EncoderUp, guard: None, to: Screen::Load },
+    Transition { from: Some(Screen::Load), event: Event::EncoderUp, guard: None, to: Screen::Registers },
+    Transition { from: Some(Screen::Registers), event: Event::EncoderUp, guard: None, to: Screen::Inspect },
+    Transition { from: Some(Screen::Inspect), event: Event::EncoderUp, guard: None, to: first_of(&[I2C_SCAN, ONEWIRE, FLASH, FAN, TEMP_LOG, TACHOMETER, TOTALIZER, SPEEDOMETER, HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "i2c-scan")]
+    Transition { from: Some(Screen::I2cScan), event: Event::EncoderUp, guard: None, to: first_of(&[ONEWIRE, FLASH, FAN, TEMP_LOG, TACHOMETER, TOTALIZER, SPEEDOMETER, HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "onewire")]
+    Transition { from: Some(Screen::OneWire), event: Event::EncoderUp, guard: None, to: first_of(&[FLASH, FAN, TEMP_LOG, TACHOMETER, TOTALIZER, SPEEDOMETER, HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "spi-flash")]
+    Transition { from: Some(Screen::Flash), event: Event::EncoderUp, guard: None, to: first_of(&[FAN, TEMP_LOG, TACHOMETER, TOTALIZER, SPEEDOMETER, HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "fan")]
+    Transition { from: Some(Screen::Fan), event: Event::EncoderUp, guard: None, to: first_of(&[TEMP_LOG, TACHOMETER, TOTALIZER, SPEEDOMETER, HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "temp-log")]
+    Transition { from: Some(Screen::TempLog), event: Event::EncoderUp, guard: None, to: first_of(&[TACHOMETER, TOTALIZER, SPEEDOMETER, HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "tachometer")]
+    Transition { from: Some(Screen::Tachometer), event: Event::EncoderUp, guard: None, to: first_of(&[TOTALIZER, SPEEDOMETER, HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "totalizer")]
+    Transition { from: Some(Screen::Totalizer), event: Event::EncoderUp, guard: None, to: first_of(&[SPEEDOMETER, HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "speedometer")]
+    Transition { from: Some(Screen::Speedometer), event: Event::EncoderUp, guard: None, to: first_of(&[HOUR_METER, WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "hour-meter")]
+    Transition { from: Some(Screen::HourMeter), event: Event::EncoderUp, guard: None, to: first_of(&[WEATHER, MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "weather")]
+    Transition { from: Some(Screen::Weather), event: Event::EncoderUp, guard: None, to: first_of(&[MOTION, DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "pir")]
+    Transition { from: Some(Screen::Motion), event: Event::EncoderUp, guard: None, to: first_of(&[DMX, MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "dmx")]
+    Transition { from: Some(Screen::Dmx), event: Event::EncoderUp, guard: None, to: first_of(&[MIDI, LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "midi")]
+    Transition { from: Some(Screen::Midi), event: Event::EncoderUp, guard: None, to: first_of(&[LOCK, EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "door-lock")]
+    Transition { from: Some(Screen::Lock), event: Event::EncoderUp, guard: None, to: first_of(&[EVENTS, CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "access-log")]
+    Transition { from: Some(Screen::Events), event: Event::EncoderUp, guard: None, to: first_of(&[CALIBRATION], Screen::Uptime) },
+    #[cfg(feature = "calibration")]
+    Transition { from: Some(Screen::Calibration), event: Event::EncoderUp, guard: None, to: Screen::Uptime },
+    Transition { from: Some(Screen::Uptime), event: Event::EncoderUp, guard: None, to: Screen::Boots },
+    Transition { from: Some(Screen::Boots), event: Event::EncoderUp, guard: None, to: Screen::Stopwatch },
+    Transition { from: Some(Screen::Stopwatch), event: Event::EncoderUp, guard: None, to: Screen::Countdown },
+    Transition { from: Some(Screen::Countdown), event: Event::EncoderUp, guard: None, to: Screen::Alarm },
+    Transition { from: Some(Screen::Alarm), event: Event::EncoderUp, guard: None, to: Screen::Pomodoro },
+    Transition { from: Some(Screen::Pomodoro), event: Event::EncoderUp, guard: None, to: Screen::Thermostat },
+    Transition { from: Some(Screen::Thermostat), event: Event::EncoderUp, guard: None, to: Screen::Limits },
+    Transition { from: Some(Screen::Limits), event: Event::EncoderUp, guard: None, to: Screen::Pid },
+    Transition { from: Some(Screen::Pid), event: Event::EncoderUp, guard: None, to: Screen::Reaction },
+    Transition { from: Some(Screen::Reaction), event: Event::EncoderUp, guard: None, to: Screen::Snake },
+    Transition { from: Some(Screen::Snake), event: Event::EncoderUp, guard: None, to: Screen::Dice },
+    Transition { from: Some(Screen::Dice), event: Event::EncoderUp, guard: None, to: Screen::Morse },
+    Transition { from: Some(Screen::Morse), event: Event::EncoderUp, guard: None, to: Screen::Calculator },
+    Transition { from: Some(Screen::Calculator), event: Event::EncoderUp, guard: None, to: Screen::Settings },
+    Transition { from: Some(Screen::Settings), event: Event::EncoderUp, guard: None, to: Screen::Hello },
+    Transition { from: Some(Screen::Hello), event: Event::EncoderDown, guard: None, to: Screen::Settings },
+    Transition { from: Some(Screen::Bye), event: Event::EncoderDown, guard: None, to: Screen::Hello },
+    Transition { from: Some(Screen::Diagnostics), event: Event::EncoderDown, guard: None, to: Screen::Bye },
+    Transition { from: Some(Screen::Trend), event: Event::EncoderDown, guard: None, to: Screen::Diagnostics },
+    Transition { from: Some(Screen::Load), event: Event::EncoderDown, guard: None, to: Screen::Trend },
+    Transition { from: Some(Screen::Registers), event: Event::EncoderDown, guard: None, to: Screen::Load },
+    Transition { from: Some(Screen::Inspect), event: Event::EncoderDown, guard: None, to: Screen::Registers },
+    #[cfg(feature = "i2c-scan")]
+    Transition { from: Some(Screen::I2cScan), event: Event::EncoderDown, guard: None, to: Screen::Inspect },
+    #[cfg(feature = "onewire")]
+    Transition { from: Some(Screen::OneWire), event: Event::EncoderDown, guard: None, to: first_of(&[I2C_SCAN], Screen::Inspect) },
+    #[cfg(feature = "spi-flash")]
+    Transition { from: Some(Screen::Flash), event: Event::EncoderDown, guard: None, to: first_of(&[ONEWIRE, I2C_SCAN], Screen::Inspect) },
+    #[cfg(feature = "fan")]
+    Transition { from: Some(Screen::Fan), event: Event::EncoderDown, guard: None, to: first_of(&[FLASH, ONEWIRE, I2C_SCAN], Screen::Inspect) },
+    #[cfg(feature = "temp-log")]
+    Transition { from: Some(Screen::TempLog), event: Event::EncoderDown, guard: None, to: first_of(&[FAN, FLASH, ONEWIRE, I2C_SCAN], Screen::Inspect) },
+    #[cfg(feature = "tachometer")]
+    Transition { from: Some(Screen::Tachometer), event: Event::EncoderDown, guard: None, to: first_of(&[TEMP_LOG, FAN, FLASH, ONEWIRE, I2C_SCAN], Screen::Inspect) },
+    #[cfg(feature = "totalizer")]
+    Transition { from: Some(Screen::Totalizer), event: Event::EncoderDown, guard: None, to: first_of(&[TACHOMETER, TEMP_LOG, FAN, FLASH, ONEWIRE, I2C_SCAN], Screen::Inspect) },
+    #[cfg(feature = "speedometer")]
+    Transition { from: Some(Screen::Speedometer), event: Event::EncoderDown, guard: None, to: first_of(&[TOTALIZER, TACHOMETER, TEMP_LOG, FAN, FLASH, ONEWIRE, I2C_SCAN], Screen::Inspect) },
+    #[cfg(feature = "hour-meter")]
+    Transition { from: Some(Screen::HourMeter), event: Event::EncoderDown, guard: None, to: first_of(&[SPEEDOMETER, TOTALIZER, TACHOMETER, TEMP_LOG, FAN, FLASH, ONEWIRE, I2C_SCAN], Screen::Inspect) },
+    #[cfg(feature = "weather")]
+    Transition { from: Some(Screen::Weather), event: Event::EncoderDown, guard: None, to: first_of(&[HOUR_METER, SPEEDOMETER, TOTALIZER, TACHOMETER, TEMP_LOG, FAN, FLASH, ONEWIRE, I2C_SCAN], Screen::Inspect) },
+    #[cfg(feature = "pir")]
+    Transition { from: Some(Screen::Motion), event: Event::EncoderDown, guard: None, to: first_of(&[WEATHER, HOUR_METER, SPEEDOMETER, TOTALIZER, TACHOMETER, TEMP_LOG, FAN, FLASH, ONEWIRE, I2C_SCAN], Screen::Inspect) },
+    #[cfg(feature = "dmx")]
+    Transition { from: Some(Screen::Dmx), event: Event::EncoderDown, guard: None, to: first_of(&[MOTION, WEATHER, HOUR_METER, SPEEDOMETER, TOTALIZER, TACHOMETER, TEMP_LOG, FAN, FLASH, ONEWIRE, I2C_SCAN], Screen::Inspect) },
+    #[cfg(feature = "midi")]
+    Transition { from: Some(Screen::Midi), event: Event::EncoderDown, guard: None, to: first_of(&[DMX, MOTION, WEATHER, HOUR_METER, SPEEDOMETER, TOTALIZER, TACHOMETER, TEMP_LOG, FAN, FLASH, ONEWIRE, I2C_SCAN], Screen::Inspect) },
+    #[cfg(feature = "door-lock")]
+    Transition { from: Some(Screen::Lock), event: Event::EncoderDown, guard: None, to: first_of(&[MIDI, DMX, MOTION, WEATHER, HOUR_METER, SPEEDOMETER, TOTALIZER, TACHOMETER, TEMP_LOG, FAN, FLASH, ONEWIRE, I2C_SCAN], Screen::Inspect) },
+    #[cfg(feature = "access-log")]
+    Transition { from: Some(Screen::Events), event: Event::EncoderDown, guard: None, to: first_of(&[LOCK, MIDI, DMX, MOTION, WEATHER, HOUR_METER, SPEEDOMETER, TOTALIZER, TACHOMETER, TEMP_LOG, FAN, FLASH, ONEWIRE, I2C_SCAN], Screen::Inspect) },
+    #[cfg(feature = "calibration")]
+    Transition { from: Some(Screen::Calibration), event: Event::EncoderDown, guard: None, to: first_of(&[EVENTS, LOCK, MIDI, DMX, MOTION, WEATHER, HOUR_METER, SPEEDOMETER, TOTALIZER, TACHOMETER, TEMP_LOG, FAN, FLASH, ONEWIRE, I2C_SCAN], Screen::Inspect) },
+    Transition { from: Some(Screen::Uptime), event: Event::EncoderDown, guard: None, to: first_of(&[CALIBRATION, EVENTS, LOCK, MIDI, DMX, MOTION, WEATHER, HOUR_METER, SPEEDOMETER, TOTALIZER, TACHOMETER, TEMP_LOG, FAN, FLASH, ONEWIRE, I2C_SCAN], Screen::Inspect) },
+    Transition { from: Some(Screen::Boots), event: Event::EncoderDown, guard: None, to: Screen::Uptime },
+    Transition { from: Some(Screen::Stopwatch), event: Event::EncoderDown, guard: None, to: Screen::Boots },
+    Transition { from: Some(Screen::Countdown), event: Event::EncoderDown, guard: None, to: Screen::Stopwatch },
+    Transition { from: Some(Screen::Alarm), event: Event::EncoderDown, guard: None, to: Screen::Countdown },
+    Transition { from: Some(Screen::Pomodoro), event: Event::EncoderDown, guard: None, to: Screen::Alarm },
+    Transition { from: Some(Screen::Thermostat), event: Event::EncoderDown, guard: None, to: Screen::Pomodoro },
+    Transition { from: Some(Screen::Limits), event: Event::EncoderDown, guard: None, to: Screen::Thermostat },
+    Transition { from: Some(Screen::Pid), event: Event::EncoderDown, guard: None, to: Screen::Limits },
+    Transition { from: Some(Screen::Reaction), event: Event::EncoderDown, guard: None, to: Screen::Pid },
+    Transition { from: Some(Screen::Snake), event: Event::EncoderDown, guard: None, to: Screen::Reaction },
+    Transition { from: Some(Screen::Dice), event: Event::EncoderDown, guard: None, to: Screen::Snake },
+    Transition { from: Some(Screen::Morse), event: Event::EncoderDown, guard: None, to: Screen::Dice },
+    Transition { from: Some(Screen::Calculator), event: Event::EncoderDown, guard: None, to: Screen::Morse },
+    Transition { from: Some(Screen::Settings), event: Event::EncoderDown, guard: None, to: Screen::Calculator },
+    Transition { from: Some(Screen::Hello), event: Event::Timer, guard: Some(auto_rotate), to: Screen::Bye },
+    Transition { from: Some(Screen::Bye), event: Event::Timer, guard: Some(auto_rotate), to: Screen::Hello },
+    Transition { from: None, event: Event::Serial(b'h'), guard: None, to: Screen::Hello },
+    Transition { from: None, event: Event::Serial(b'b'), guard: None, to: Screen::Bye },
+    Transition { from: None, event: Event::Serial(b'd'), guard: None, to: Screen::Diagnostics },
+    Transition { from: None, event: Event::Serial(b'j'), guard: None, to: Screen::Trend },
+    Transition { from: None, event: Event::Serial(b'l'), guard: None, to: Screen::Load },
+    Transition { from: None, event: Event::Serial(b'r'), guard: None, to: Screen::Registers },
+    Transition { from: None, event: Event::Serial(b'm'), guard: None, to: Screen::Inspect },
+    #[cfg(feature = "i2c-scan")]
+    Transition { from: None, event: Event::Serial(b'i'), guard: None, to: Screen::I2cScan },
+    #[cfg(feature = "onewire")]
+    Transition { from: None, event: Event::Serial(b'w'), guard: None, to: Screen::OneWire },
+    #[cfg(feature = "spi-flash")]
+    Transition { from: None, event: Event::Serial(b'f'), guard: None, to: Screen::Flash },
+    #[cfg(feature = "fan")]
+    Transition { from: None, event: Event::Serial(b'n'), guard: None, to: Screen::Fan },
+    #[cfg(feature = "temp-log")]
+    Transition { from: None, event: Event::Serial(b'z'), guard: None, to: Screen::TempLog },
+    #[cfg(feature = "tachometer")]
+    Transition { from: None, event: Event::Serial(b'*'), guard: None, to: Screen::Tachometer },
+    #[cfg(feature = "totalizer")]
+    Transition { from: None, event: Event::Serial(b'+'), guard: None, to: Screen::Totalizer },
+    #[cfg(feature = "speedometer")]
+    Transition { from: None, event: Event::Serial(b'>'), guard: None, to: Screen::Speedometer },
+    #[cfg(feature = "hour-meter")]
+    Transition { from: None, event: Event::Serial(b'#'), guard: None, to: Screen::HourMeter },
+    #[cfg(feature = "weather")]
+    Transition { from: None, event: Event::Serial(b'~'), guard: None, to: Screen::Weather },
+    #[cfg(feature = "pir")]
+    Transition { from: None, event: Event::Serial(b'&'), guard: None, to: Screen::Motion },
+    #[cfg(feature = "dmx")]
+    Transition { from: None, event: Event::Serial(b'%'), guard: None, to: Screen::Dmx },
+    #[cfg(feature = "midi")]
+    Transition { from: None, event: Event::Serial(b'$'), guard: None, to: Screen::Midi },
+    #[cfg(feature = "door-lock")]
+    Transition { from: None, event: Event::Serial(b'@'), guard: None, to: Screen::Lock },
+    #[cfg(feature = "access-log")]
+    Transition { from: None, event: Event::Serial(b'^'), guard: None, to: Screen::Events },
+    #[cfg(feature = "calibration")]
+    Transition { from: None, event: Event::Serial(b'q'), guard: None, to: Screen::Calibration },
+    Transition { from: None, event: Event::Serial(b'u'), guard: None, to: Screen::Uptime },
+    Transition { from: None, event: Event::Serial(b'o'), guard: None, to: Screen::Boots },
+    Transition { from: None, event: Event::Serial(b't'), guard: None, to: Screen::Stopwatch },
+    Transition { from: None, event: Event::Serial(b'c'), guard: None, to: Screen::Countdown },
+    Transition { from: None, event: Event::Serial(b'a'), guard: None, to: Screen::Alarm },
+    Transition { from: None, event: Event::Serial(b'p'), guard: None, to: Screen::Pomodoro },
+    Transition { from: None, event: Event::Serial(b'e'), guard: None, to: Screen::Thermostat },
+    Transition { from: None, event: Event::Serial(b'!'), guard: None, to: Screen::Limits },
+    Transition { from: None, event: Event::Serial(b'g'), guard: None, to: Screen::Pid },
+    Transition { from: None, event: Event::Serial(b'x'), guard: None, to: Screen::Reaction },
+    Transition { from: None, event: Event::Serial(b'k'), guard: None, to: Screen::Snake },
+    Transition { from: None, event: Event::Serial(b'y'), guard: None, to: Screen::Dice },
+    Transition { from: None, event: Event::Serial(b'v'), guard: None, to: Screen::Morse },
+    Transition { from: None, event: Event::Serial(b'='), guard: None, to: Screen::Calculator },
+    Transition { from: None, event: Event::Serial(b's'), guard: None, to: Screen::Settings },
+];
+
+/// Snake field, with the characters of its cells.
+type SnakeField = (PixelRegion<{ snake::CHAR_COLUMNS * snake::CHAR_ROWS }>, [char; snake::CHAR_COLUMNS * snake::CHAR_ROWS]);
+
+/// Write one `row` (0 or 1) of the snake field: custom characters (or blanks), four per row.
+fn write_field_row<W: Write>(w: &mut W, field: &Option<SnakeField>, row: usize) -> fmt::Result {
+    match field {
+        Some((region, codes)) => region.write_row(w, codes, row),
+        None => Ok(()),
+    }
+}
+
+/// UI state machine, starting at the "Hello" screen.
+pub fn navigation() -> StateMachine<Screen, Event, Settings> {
+    StateMachine::new(Screen::Hello, &TRANSITIONS)
+}
+
+/// Show an error which stops the demo: `title` on the first row, `message` on the second one.
+pub fn error<D: TextDisplay>(display: &mut D, title: &str, message: &str) -> fmt::Result {
+    display.clear()?;
+    display.write_at(0, 0, format_args!("{}", title))?;
+    display.write_at(0, 1, format_args!("{}", message))
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn diagnostics_screen_fixed_point() {
+        use crate::mock;
+        use crate::screens::{Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        let stats = Stats {
+            duty_cycle: 7,
+            temperature: -55,
+            vdd_mv: 3275,
+            ..Stats::default()
+        };
+        let (top, bottom) = mock::render(Screen::Diagnostics, &stats, &Settings::default());
+        #[cfg(not(feature = "read-back"))]
+        assert_eq!(top, "Duty cycle   7% ");
+        #[cfg(feature = "read-back")]
+        assert_eq!(top, "Duty  7% vfy  0 ");
+        assert_eq!(bottom, " -5.5C     3.28V");
+
+        // Unit is switched by the encoder on the settings screen
+        let mut settings = Settings::default();
+        assert!(!settings.handle(Screen::Diagnostics, Event::EncoderUp));
+        assert!(settings.handle(Screen::Settings, Event::EncoderUp));
+        assert_eq!(mock::render(Screen::Diagnostics, &stats, &settings).1, " 22.1F     3.28V");
+    }
+
+    #[test]
+    #[cfg(not(feature = "pin-monitor"))]
+    fn registers_screen_hex_and_binary() {
+        use crate::mock;
+        use crate::screens::{Screen, Settings, Stats};
+
+        let stats = Stats {
+            gpioa_idr: 0x80a3,
+            gpioa_odr: 0x0010,
+            ..Stats::default()
+        };
+        let rows = mock::render(Screen::Registers, &stats, &Settings::default());
+        assert_eq!(rows, ("I:80a3  O:0010  ".into(), "PA7-0 1010 0011 ".into()));
+    }
+
+    #[test]
+    fn uptime_screen_duration() {
+        use crate::mock;
+        use crate::screens::{Screen, Settings, Stats};
+
+        let render = |uptime_s| {
+            let stats = Stats { uptime_s, ..Stats::default() };
+            mock::render(Screen::Uptime, &stats, &Settings::default()).1
+        };
+
+        assert_eq!(render(59), "up      00:00:59");
+        assert_eq!(render(86_400 + 3 * 3600 + 25 * 60 + 17), "up   1d 03:25:17");
+        // Past the point where milliseconds wrap around (49 days 17 hours)
+        assert_eq!(render(50 * 86_400), "up  50d 00:00:00");
+    }
+
+    #[test]
+    fn render_errors_are_reported() {
+        use std::vec::Vec;
+        use crate::mock;
+        use crate::queue::{Queue, Writer};
+        use crate::screens::{Screen, Settings, Stats};
+
+        // Engine is not running, so the queue overflows after a few screens
+        let mut queue = Queue::new();
+        let (producer, _consumer) = queue.split();
+        let mut writer = Writer::new(producer, || {}, || false);
+        let (stats, settings) = (Stats::default(), Settings::default());
+        let results: Vec<_> = (0..4).map(|_| Screen::Hello.render(&mut writer, &stats, &settings)).collect();
+        assert!(results[0].is_ok());
+        assert!(results[3].is_err());
+
+        let stats = Stats { render_errors: 3, ..Stats::default() };
+        assert_eq!(mock::render(Screen::Uptime, &stats, &settings).0, "err   3 rst   0!");
+
+        // Low voltage warning (custom character) takes precedence
+        let stats = Stats { low_voltage: true, ..stats };
+        assert_eq!(mock::render(Screen::Uptime, &stats, &settings).0, "err   3 rst   0#");
+    }
+
+    #[test]
+    fn self_test_fills_every_cell() {
+        use lcd::Display;
+        use crate::hardware::LcdHardware;
+        use crate::mock::MockHardware;
+        use crate::screens::{self, CHECKERBOARD, FULL_BLOCK};
+        use crate::sim::Hd44780;
+
+        let mock = MockHardware::new();
+        let mut display = Display::new(LcdHardware::new(&mock, &mock));
+        screens::init(&mut display);
+        let mut lcd = Hd44780::new();
+
+        screens::fill(&mut display, FULL_BLOCK);
+        lcd.feed(&mock.transfers());
+        mock.reset();
+        assert_eq!(lcd.row(0, 16), "?".repeat(16));
+        assert_eq!(lcd.row(1, 16), "?".repeat(16));
+
+        // Custom characters are shown as `#` by the simulator
+        screens::fill(&mut display, CHECKERBOARD as u8);
+        lcd.feed(&mock.transfers());
+        mock.reset();
+        assert_eq!(lcd.row(0, 16), "#".repeat(16));
+        assert_eq!(lcd.row(1, 16), "#".repeat(16));
+
+        screens::self_test_result(&mut display, Some("CGRAM")).unwrap();
+        lcd.feed(&mock.transfers());
+        assert_eq!(lcd.row(0, 16), "Self-test       ");
+        assert_eq!(lcd.row(1, 16), "FAIL: CGRAM     ");
+    }
+
+    #[test]
+    fn load_screen_loop_stats() {
+        use crate::mock;
+        use crate::sched::LoopStats;
+        use crate::screens::{Screen, Settings, Stats};
+
+        let mut loop_stats = LoopStats::new(0);
+        for ms in 1..=500 {
+            loop_stats.record(ms * 2, if ms == 100 { 1234 } else { 40 });
+        }
+        // First one-second window is complete at the last iteration
+        assert_eq!(loop_stats.rate(), 500);
+        assert_eq!(loop_stats.max_us(), 1234);
+
+        let stats = Stats {
+            duty_cycle: 3,
+            loop_rate: loop_stats.rate(),
+            loop_max_us: loop_stats.max_us(),
+            stack_free: 9876,
+            ..Stats::default()
+        };
+        let rows = mock::render(Screen::Load, &stats, &Settings::default());
+        assert_eq!(rows, ("Loop  500/s 97% ".into(), "max 1234us 9876B".into()));
+    }
+
+    #[test]
+    fn boots_screen() {
+        use crate::mock;
+        use crate::screens::{Screen, Settings, Stats};
+
+        let stats = Stats {
+            boots: 1234,
+            reset_cause: "WDG reset",
+            runtime_s: 3 * 86_400 + 7,
+            ..Stats::default()
+        };
+        let rows = mock::render(Screen::Boots, &stats, &Settings::default());
+        assert_eq!(rows, ("WDG reset  1234 ".into(), "run  3d 00:00:07".into()));
+    }
+}
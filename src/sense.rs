@@ -0,0 +1,26 @@
+//! Hour meter sense input (see `board::SENSE`): high while the equipment runs.
+
+use stm32f1::stm32f103::{gpioa, RCC};
+use crate::board;
+use crate::gpio::{self, GPIOExtras};
+
+pub struct SenseInput {
+    port: &'static gpioa::RegisterBlock,
+}
+
+impl SenseInput {
+    /// Configure the pin as an input with a pull-down (disconnected input is stopped equipment).
+    pub fn new(rcc: &RCC) -> SenseInput {
+        let sense = board::SENSE;
+        let port = gpio::enable_port(rcc, sense.port);
+        port.write_pin(sense.index, sense.level(false));
+        port.pin_config(sense.index).input().pull_up_down();
+        SenseInput { port }
+    }
+
+    /// Is the equipment running?
+    pub fn running(&self) -> bool {
+        let sense = board::SENSE;
+        self.port.read_pin(sense.index) == sense.level(true)
+    }
+}
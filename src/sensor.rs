@@ -0,0 +1,100 @@
+//! Internal temperature sensor, read via ADC1.
+//!
+//! Sensor voltage is measured against the internal reference voltage (VREFINT), so the result
+//! does not depend on the supply voltage. The other way around, VREFINT measured against the
+//! supply gives the supply voltage itself.
+//!
+//! With the `backlight` feature, ambient light is read, too: a light dependent resistor between
+//! 3.3V and PA4 (ADC channel 4), with a 10K resistor from PA4 to the ground.
+
+use stm32f1::stm32f103::{ADC1, RCC};
+#[cfg(feature = "backlight")]
+use crate::board::PortName;
+#[cfg(feature = "backlight")]
+use crate::gpio::{self, GPIOExtras};
+
+#[cfg(all(feature = "backlight", any(feature = "spi-flash", feature = "spi-slave")))]
+compile_error!("`backlight` feature uses PA4, so does `spi-flash` and `spi-slave`");
+
+/// Temperature sensor channel.
+const CHANNEL_TEMP: u8 = 16;
+/// Internal reference voltage channel.
+const CHANNEL_VREFINT: u8 = 17;
+/// Light dependent resistor channel (PA4).
+#[cfg(feature = "backlight")]
+const CHANNEL_LIGHT: u8 = 4;
+#[cfg(feature = "backlight")]
+const LIGHT_PIN: usize = 4;
+
+/// VREFINT, in millivolts (typical).
+const VREFINT_MV: i32 = 1200;
+/// Sensor voltage at 25°C, in millivolts (typical).
+const V25_MV: i32 = 1430;
+/// Sensor slope, in microvolts per °C (typical).
+const AVG_SLOPE_UV: i32 = 4300;
+/// Full scale of the 12-bit ADC.
+const FULL_SCALE: i32 = 4095;
+
+pub struct TempSensor {
+    adc: ADC1,
+}
+
+impl TempSensor {
+    /// Power up and calibrate the ADC. ADC clock is set to PCLK2 / 6 (12Mhz, maximum is 14Mhz).
+    pub fn new(adc: ADC1, rcc: &RCC) -> TempSensor {
+        rcc.cfgr.modify(|_, w| w.adcpre().div6());
+        rcc.apb2enr.modify(|_, w| w.adc1en().set_bit());
+
+        // Sensor requires at least 17.1us sampling time
+        adc.smpr1.modify(|_, w| w.smp16().cycles239_5().smp17().cycles239_5());
+        // Divider has high impedance, so the light is sampled for long, too
+        #[cfg(feature = "backlight")]
+        {
+            gpio::enable_port(rcc, PortName::A).pin_config(LIGHT_PIN).input().analog();
+            adc.smpr2.modify(|_, w| w.smp4().cycles239_5());
+        }
+        // Conversion is started by software (SWSTART is an "external" trigger, too)
+        adc.cr2.modify(|_, w| w.adon().set_bit().tsvrefe().set_bit().exttrig().set_bit().extsel().swstart());
+
+        // ADC needs at least two ADC clock cycles after power up before calibration
+        cortex_m::asm::delay(100);
+        adc.cr2.modify(|_, w| w.rstcal().initialize());
+        while adc.cr2.read().rstcal().bit_is_set() {}
+        adc.cr2.modify(|_, w| w.cal().start());
+        while adc.cr2.read().cal().bit_is_set() {}
+
+        TempSensor { adc }
+    }
+
+    fn convert(&mut self, channel: u8) -> i32 {
+        self.adc.sqr3.write(|w| unsafe { w.sq1().bits(channel) });
+        self.adc.cr2.modify(|_, w| w.swstart().set_bit());
+        while self.adc.sr.read().eoc().bit_is_clear() {}
+        i32::from(self.adc.dr.read().data().bits())
+    }
+
+    /// Read temperature, in tenths of °C.
+    pub fn read(&mut self) -> i16 {
+        let vrefint = self.convert(CHANNEL_VREFINT).max(1);
+        let sense_mv = self.convert(CHANNEL_TEMP) * VREFINT_MV / vrefint;
+        (250 + (V25_MV - sense_mv) * 10_000 / AVG_SLOPE_UV) as i16
+    }
+
+    /// Collect `bits` bits of noise (up to 32): the lowest bits of the sensor conversions. Takes
+    /// about 20us per bit.
+    pub fn read_noise(&mut self, bits: u32) -> u32 {
+        (0..bits.min(32)).fold(0, |noise, _| (noise << 1) | (self.convert(CHANNEL_TEMP) as u32 & 1))
+    }
+
+    /// Read ambient light, in ADC counts (higher is brighter).
+    #[cfg(feature = "backlight")]
+    pub fn read_light(&mut self) -> u16 {
+        self.convert(CHANNEL_LIGHT) as u16
+    }
+
+    /// Read supply voltage (VDDA), in millivolts.
+    pub fn read_vdd(&mut self) -> u16 {
+        let vrefint = self.convert(CHANNEL_VREFINT).max(1);
+        (VREFINT_MV * FULL_SCALE / vrefint) as u16
+    }
+}
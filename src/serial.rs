@@ -0,0 +1,115 @@
+//! USART1 (PA9 is TX, PA10 is RX), with optional RS-485 driver enable pin on GPIOA.
+//!
+//! Bytes are received by the USART1 interrupt (see `receive`). Transmission is either blocking
+//! (`write`), or done by the interrupt too, one byte at a time (see `transmit`).
+
+use stm32f1::stm32f103::{gpioa, usart1, RCC, USART1};
+use crate::board::PortName;
+use crate::clock::Clocks;
+use crate::gpio::{self, GPIOExtras};
+
+const TX: usize = 9;
+const RX: usize = 10;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Even,
+}
+
+pub struct Serial {
+    usart: USART1,
+    port: &'static gpioa::RegisterBlock,
+    /// RS-485 driver enable pin (on GPIOA), high while transmitting.
+    de: Option<usize>,
+}
+
+impl Serial {
+    /// Configure USART1 for 8 data bits and 1 stop bit. USART1 interrupt is raised for every
+    /// received byte, it must be unmasked in NVIC by the caller.
+    pub fn new(usart: USART1, rcc: &RCC, clocks: &Clocks, baud: u32, parity: Parity, de: Option<usize>) -> Serial {
+        let port = gpio::enable_port(rcc, PortName::A);
+        port.pin_config(TX).alternate().push_pull().output2();
+        port.pin_config(RX).input().floating();
+        if let Some(de) = de {
+            port.write_pin(de, false);
+            port.pin_config(de).general().push_pull().output2();
+        }
+
+        rcc.apb2enr.modify(|_, w| w.usart1en().set_bit());
+        // Mantissa and fraction together are the divider in 1/16 units
+        usart.brr.write(|w| unsafe { w.bits((clocks.pclk2 + baud / 2) / baud) });
+        let even = parity == Parity::Even;
+        // Parity bit is counted as a data bit
+        usart.cr1.write(|w| {
+            w.ue().set_bit()
+                .m().bit(even)
+                .pce().bit(even)
+                .te().set_bit()
+                .re().set_bit()
+                .rxneie().set_bit()
+        });
+        Serial { usart, port, de }
+    }
+
+    /// Get the received byte, if any. Called from the USART1 interrupt; only reads the receiver
+    /// registers, so it does not interfere with `write`.
+    pub fn receive() -> Option<u8> {
+        let usart: &usart1::RegisterBlock = unsafe { &*USART1::ptr() };
+        // Errors come with a received byte; reading DR clears them. DR is not read otherwise, as
+        // the interrupt can be raised by the transmitter too, and the byte could arrive meanwhile.
+        if usart.sr.read().rxne().bit_is_clear() {
+            return None;
+        }
+        Some(usart.dr.read().dr().bits() as u8)
+    }
+
+    /// Get the received byte, if any, and whether its stop bit was missing (framing error, which is
+    /// also how the break is received). Called from the USART1 interrupt, like `receive`.
+    pub fn receive_framed() -> Option<(u8, bool)> {
+        let usart: &usart1::RegisterBlock = unsafe { &*USART1::ptr() };
+        // Status must be read before DR, which clears the error
+        let sr = usart.sr.read();
+        if sr.rxne().bit_is_clear() {
+            return None;
+        }
+        Some((usart.dr.read().dr().bits() as u8, sr.fe().bit_is_set()))
+    }
+
+    /// Enable the transmitter empty interrupt, so the USART1 interrupt sends the bytes (see
+    /// `transmit`). Must not be interrupted by the USART1 interrupt.
+    pub fn start_transmit() {
+        let usart: &usart1::RegisterBlock = unsafe { &*USART1::ptr() };
+        usart.cr1.modify(|_, w| w.txeie().set_bit());
+    }
+
+    /// Send the byte returned by `next` once the transmitter is ready for it. Called from the
+    /// USART1 interrupt; when `next` has nothing to send, the transmitter empty interrupt is
+    /// disabled until `start_transmit`. RS-485 driver enable pin is not driven, and the bytes
+    /// must not be mixed with the ones sent by `write`.
+    pub fn transmit(next: impl FnOnce() -> Option<u8>) {
+        let usart: &usart1::RegisterBlock = unsafe { &*USART1::ptr() };
+        if usart.cr1.read().txeie().bit_is_clear() || usart.sr.read().txe().bit_is_clear() {
+            return;
+        }
+        match next() {
+            Some(byte) => usart.dr.write(|w| w.dr().bits(u16::from(byte))),
+            None => usart.cr1.modify(|_, w| w.txeie().clear_bit()),
+        }
+    }
+
+    /// Send `bytes`, waiting until the last one is transmitted.
+    pub fn write(&mut self, bytes: &[u8]) {
+        if let Some(de) = self.de {
+            self.port.write_pin(de, true);
+        }
+        for &byte in bytes {
+            while self.usart.sr.read().txe().bit_is_clear() {}
+            self.usart.dr.write(|w| w.dr().bits(u16::from(byte)));
+        }
+        while self.usart.sr.read().tc().bit_is_clear() {}
+        if let Some(de) = self.de {
+            self.port.write_pin(de, false);
+        }
+    }
+}
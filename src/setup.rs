@@ -0,0 +1,140 @@
+//! HD44780 controller settings chosen at initialization: function set (lines, font), entry mode
+//! (cursor direction, display shift) and display control (display, cursor, blink). `Setup` builds
+//! them and knows the command bytes they make, so they could be checked against the datasheet on
+//! the host, without the display.
+//!
+//! Applying it is split in two, so the custom characters could be uploaded in between, while the
+//! display is still off: `init` runs the reset sequence and sets the function and the entry mode,
+//! `on` turns the display on.
+
+use lcd::{
+    Command, Display, DisplayBlink, DisplayCursor, DisplayMode, EntryModeDirection, EntryModeShift, FunctionDots,
+    FunctionLine, FunctionMode,
+};
+
+#[derive(Copy, Clone, Debug)]
+pub struct Setup {
+    line: FunctionLine,
+    dots: FunctionDots,
+    direction: EntryModeDirection,
+    shift: EntryModeShift,
+    cursor: DisplayCursor,
+    blink: DisplayBlink,
+}
+
+impl Setup {
+    /// Two lines, 5x8 font, cursor moving right without shifting the display, cursor hidden.
+    pub const fn new() -> Setup {
+        Setup {
+            line: FunctionLine::Line2,
+            dots: FunctionDots::Dots5x8,
+            direction: EntryModeDirection::EntryRight,
+            shift: EntryModeShift::NoShift,
+            cursor: DisplayCursor::CursorOff,
+            blink: DisplayBlink::BlinkOff,
+        }
+    }
+
+    pub const fn lines(mut self, line: FunctionLine) -> Setup {
+        self.line = line;
+        self
+    }
+
+    pub const fn dots(mut self, dots: FunctionDots) -> Setup {
+        self.dots = dots;
+        self
+    }
+
+    /// Direction the cursor moves after a character is written, and whether the display shifts
+    /// with it.
+    pub const fn entry(mut self, direction: EntryModeDirection, shift: EntryModeShift) -> Setup {
+        self.direction = direction;
+        self.shift = shift;
+        self
+    }
+
+    pub const fn cursor(mut self, cursor: DisplayCursor, blink: DisplayBlink) -> Setup {
+        self.cursor = cursor;
+        self.blink = blink;
+        self
+    }
+
+    /// Function set command, for the bus `mode`.
+    pub const fn function_set(&self, mode: FunctionMode) -> u8 {
+        Command::FunctionSet as u8 | mode as u8 | self.line as u8 | self.dots as u8
+    }
+
+    pub const fn entry_mode_set(&self) -> u8 {
+        Command::EntryModeSet as u8 | self.direction as u8 | self.shift as u8
+    }
+
+    /// Display control command, with the display on or off.
+    pub const fn display_control(&self, mode: DisplayMode) -> u8 {
+        Command::DisplayControl as u8 | mode as u8 | self.cursor as u8 | self.blink as u8
+    }
+
+    /// Commands sent by `init` and `on`, after the reset sequence (the clear and the display off
+    /// sent by the driver in between are not included).
+    pub const fn commands(&self, mode: FunctionMode) -> [u8; 3] {
+        [self.function_set(mode), self.entry_mode_set(), self.display_control(DisplayMode::DisplayOn)]
+    }
+
+    /// Reset the controller and set the function and the entry mode. Display is left off and
+    /// cleared.
+    pub fn init<HW: lcd::Hardware + lcd::Delay>(&self, display: &mut Display<HW>) {
+        // Driver sets the entry mode itself, to the default one
+        display.init(self.line, self.dots);
+        if self.entry_mode_set() != Setup::new().entry_mode_set() {
+            display.entry_mode(self.direction, self.shift);
+        }
+    }
+
+    pub fn on<HW: lcd::Hardware + lcd::Delay>(&self, display: &mut Display<HW>) {
+        display.display(DisplayMode::DisplayOn, self.cursor, self.blink);
+    }
+
+    /// Turn the display off (display memory is kept).
+    pub fn off<HW: lcd::Hardware + lcd::Delay>(&self, display: &mut Display<HW>) {
+        display.display(DisplayMode::DisplayOff, self.cursor, self.blink);
+    }
+}
+
+impl Default for Setup {
+    fn default() -> Setup {
+        Setup::new()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn setup_commands() {
+        use lcd::{Display, DisplayBlink, DisplayCursor, DisplayMode, EntryModeDirection, EntryModeShift, FunctionDots, FunctionLine, FunctionMode};
+        use crate::hardware::LcdHardware;
+        use crate::mock::{command, join_nibbles, MockHardware};
+        use crate::setup::Setup;
+
+        // Datasheet, table 6: function set 001DNF--, entry mode set 000001IS, display control
+        // 00001DCB
+        let setup = Setup::new();
+        assert_eq!(setup.commands(FunctionMode::Bit4), [0x28, 0x06, 0x0c]);
+        assert_eq!(setup.commands(FunctionMode::Bit8), [0x38, 0x06, 0x0c]);
+        assert_eq!(setup.display_control(DisplayMode::DisplayOff), 0x08);
+        let setup = Setup::new()
+            .lines(FunctionLine::Line1)
+            .dots(FunctionDots::Dots5x10)
+            .entry(EntryModeDirection::EntryLeft, EntryModeShift::Shift)
+            .cursor(DisplayCursor::CursorOn, DisplayBlink::BlinkOn);
+        assert_eq!(setup.commands(FunctionMode::Bit4), [0x24, 0x05, 0x0f]);
+
+        // Commands are the ones sent, after the reset sequence
+        let mock = MockHardware::new();
+        let mut display = Display::new(LcdHardware::new(&mock, &mock));
+        setup.init(&mut display);
+        setup.on(&mut display);
+        assert_eq!(
+            join_nibbles(&mock.transfers()[4..]),
+            vec![command(0x24), command(0x08), command(0x01), command(0x06), command(0x05), command(0x0f)]
+        );
+    }
+}
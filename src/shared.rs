@@ -0,0 +1,43 @@
+//! Display shared between the main loop and interrupt handlers.
+//!
+//! Display is kept in a `Mutex<RefCell<_>>`, so every access happens in a critical section. Keep
+//! the updates short: interrupts are disabled while the display is locked. With the queued driver
+//! (`queue::Writer`) that is not an issue, since writes only put commands into the queue.
+
+use core::cell::RefCell;
+use cortex_m::interrupt::{self, Mutex};
+
+pub struct SharedDisplay<D> {
+    inner: Mutex<RefCell<Option<D>>>,
+}
+
+impl<D> SharedDisplay<D> {
+    /// Create an empty shared display, so it could be put into a `static`.
+    pub const fn new() -> SharedDisplay<D> {
+        SharedDisplay {
+            inner: Mutex::new(RefCell::new(None)),
+        }
+    }
+
+    /// Move initialized display in.
+    pub fn init(&self, display: D) {
+        interrupt::free(|cs| {
+            self.inner.borrow(cs).replace(Some(display));
+        });
+    }
+
+    /// Run `f` with exclusive access to the display. Returns `None` if display is not initialized
+    /// yet (or if it is already locked higher up the stack by the same context).
+    pub fn lock<R, F: FnOnce(&mut D) -> R>(&self, f: F) -> Option<R> {
+        interrupt::free(|cs| {
+            let mut display = self.inner.borrow(cs).try_borrow_mut().ok()?;
+            display.as_mut().map(f)
+        })
+    }
+}
+
+impl<D> Default for SharedDisplay<D> {
+    fn default() -> SharedDisplay<D> {
+        SharedDisplay::new()
+    }
+}
@@ -0,0 +1,464 @@
+//! Command shell on USART1 (or over RTT, see `rtt`), for configuring the board in the field
+//! without rebuilding the firmware.
+//!
+//! Commands are lines of words separated by spaces (`help` lists them): `get` and `set` read and
+//! change the settings, `screen` switches the screens (by the name, as logged), `text` shows text
+//! instead of the screens (like the other remote text sources, see `remote`), `diag` dumps the
+//! diagnostics, `events` dumps the access log as CSV (with the `access-log` feature, see `export`).
+//! Typed characters are echoed, backspace deletes; the prompt is shown after every command.
+//!
+//! Output (echo included) is buffered here and queued for the USART interrupt by `poll`, which
+//! sends what fits into the queue and keeps the rest for the next call (or taken by the RTT console
+//! with `pending` and `consume`).
+
+use core::fmt::{self, Write};
+use heapless::{Deque, String};
+use crate::display::COLUMNS;
+use crate::fmt::{write_fixed, write_uint};
+#[cfg(feature = "access-log")]
+use crate::access_log::{self, EventArea, EventLog};
+use crate::remote::RemoteText;
+use crate::screens::{Screen, Settings, Stats, TRANSITIONS};
+use crate::text;
+use crate::ui::{Event, StateMachine};
+use crate::units::TempUnit;
+
+/// Longest line, in characters (the rest is dropped).
+const LINE_LEN: usize = 40;
+/// Output buffered, in bytes.
+const OUTPUT_LEN: usize = 512;
+
+const PROMPT: &str = "> ";
+const HELP: &[&str] = &[
+    "help                this list\r\n",
+    "get [name]          show the settings (or one of them)\r\n",
+    "set <name> <value>  change a setting\r\n",
+    "screen [name]       show or switch the screen\r\n",
+    "text <1|2> <text>   show the text instead of the screens\r\n",
+    "text off            back to the screens\r\n",
+    #[cfg(feature = "access-log")]
+    "events              dump the access log as CSV\r\n",
+    "diag                dump the diagnostics\r\n",
+];
+
+pub struct Shell {
+    line: [u8; LINE_LEN],
+    len: usize,
+    output: String<OUTPUT_LEN>,
+    /// Output bytes already queued.
+    sent: usize,
+    /// Access log records being dumped: the next one and the end.
+    #[cfg(feature = "access-log")]
+    dump: Option<(usize, usize)>,
+}
+
+impl Shell {
+    pub const fn new() -> Shell {
+        Shell {
+            line: [0; LINE_LEN],
+            len: 0,
+            output: String::new(),
+            sent: 0,
+            #[cfg(feature = "access-log")]
+            dump: None,
+        }
+    }
+
+    /// Take the received byte, run the command once the line is complete. Empty lines only show
+    /// the prompt (so with both CR and LF ending the line, it is shown twice). Returns `true` if the
+    /// command has changed the settings, the screen or the text (display should be refreshed).
+    pub fn receive(
+        &mut self,
+        byte: u8,
+        settings: &mut Settings,
+        ui: &mut StateMachine<Screen, Event, Settings>,
+        stats: &Stats,
+        remote: &mut RemoteText,
+    ) -> bool {
+        match byte {
+            b'\r' | b'\n' => {
+                let len = core::mem::take(&mut self.len);
+                let line = self.line;
+                // Only printable ASCII is stored
+                let line = unsafe { core::str::from_utf8_unchecked(&line[..len]) };
+                self.print("\r\n");
+                let changed = match self.run(line, settings, ui, stats, remote) {
+                    Ok(changed) => changed,
+                    Err(message) => {
+                        self.print(message);
+                        self.print("\r\n");
+                        false
+                    }
+                };
+                // Prompt follows the dump
+                #[cfg(feature = "access-log")]
+                if self.dump.is_some() {
+                    return changed;
+                }
+                self.print(PROMPT);
+                changed
+            }
+            0x08 | 0x7f => {
+                if self.len > 0 {
+                    self.len -= 1;
+                    self.print("\x08 \x08");
+                }
+                false
+            }
+            0x20..=0x7e if self.len < LINE_LEN => {
+                self.line[self.len] = byte;
+                self.len += 1;
+                self.output.push(char::from(byte)).ok();
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Run the command `line`. Returns `true` if it has changed anything shown, the error message
+    /// if the command is wrong.
+    fn run(
+        &mut self,
+        line: &str,
+        settings: &mut Settings,
+        ui: &mut StateMachine<Screen, Event, Settings>,
+        stats: &Stats,
+        remote: &mut RemoteText,
+    ) -> Result<bool, &'static str> {
+        let (command, args) = word(line);
+        match command {
+            "" => Ok(false),
+            "help" => {
+                HELP.iter().for_each(|line| self.print(line));
+                Ok(false)
+            }
+            "get" => {
+                let mut found = false;
+                for &name in NAMES.iter().filter(|&&name| args.is_empty() || args == name) {
+                    found = true;
+                    let _ = write_setting(&mut self.output, settings, name);
+                }
+                if found { Ok(false) } else { Err("unknown setting") }
+            }
+            "set" => {
+                let (name, value) = word(args);
+                set(settings, name, value)?;
+                let _ = write_setting(&mut self.output, settings, name);
+                Ok(true)
+            }
+            "screen" if args.is_empty() => {
+                self.print(ui.state().name());
+                self.print("\r\n");
+                Ok(false)
+            }
+            "screen" => {
+                // Every screen has its serial key
+                let event = TRANSITIONS
+                    .iter()
+                    .find(|t| t.from.is_none() && matches!(t.event, Event::Serial(_)) && t.to.name() == args)
+                    .ok_or("unknown screen")?
+                    .event;
+                Ok(ui.handle(event, settings))
+            }
+            "text" if args == "off" => Ok(remote.release()),
+            "text" => {
+                let (row, text) = word(args);
+                let address = match row {
+                    "1" => 0x00,
+                    "2" => 0x40,
+                    _ => return Err("row is 1 or 2"),
+                };
+                remote.set_cursor(address);
+                text.bytes().chain(core::iter::repeat(b' ')).take(COLUMNS).for_each(|byte| remote.write(byte));
+                Ok(true)
+            }
+            "diag" => {
+                let _ = write_diagnostics(&mut self.output, stats, settings.temp_unit);
+                Ok(false)
+            }
+            #[cfg(feature = "access-log")]
+            "events" => {
+                self.print(access_log::CSV_HEADER);
+                self.dump = Some((0, settings.events.count()));
+                Ok(false)
+            }
+            _ => Err("unknown command, try help"),
+        }
+    }
+
+    fn print(&mut self, s: &str) {
+        // Output which does not fit is dropped
+        let _ = self.output.push_str(s);
+    }
+
+    /// Continue the `events` dump: add the access log records to the output, as many as fit, and
+    /// the prompt after the last one. Must be called before `poll` (and `pending`).
+    #[cfg(feature = "access-log")]
+    pub fn export<A: EventArea>(&mut self, log: &EventLog, area: &A) {
+        let Some((mut next, end)) = self.dump else {
+            return;
+        };
+        while next < end {
+            let mut line = String::<48>::new();
+            // Record gone (log has wrapped around since the dump started) ends the dump
+            if log.write_csv(area, &mut line, next).is_err() {
+                break;
+            }
+            if self.output.push_str(&line).is_err() {
+                self.dump = Some((next, end));
+                return;
+            }
+            next += 1;
+        }
+        self.dump = None;
+        self.print(PROMPT);
+    }
+
+    /// Queue the output, as much as fits into `out`.
+    pub fn poll<const N: usize>(&mut self, out: &mut Deque<u8, N>) {
+        let queued = self.pending().iter().take_while(|&&byte| out.push_back(byte).is_ok()).count();
+        self.consume(queued);
+    }
+
+    /// Output not sent yet.
+    pub fn pending(&self) -> &[u8] {
+        &self.output.as_bytes()[self.sent..]
+    }
+
+    /// Account the first `count` bytes of the `pending` output as sent.
+    pub fn consume(&mut self, count: usize) {
+        self.sent += count;
+        if self.sent >= self.output.len() {
+            self.output.clear();
+            self.sent = 0;
+        }
+    }
+}
+
+impl Default for Shell {
+    fn default() -> Shell {
+        Shell::new()
+    }
+}
+
+/// First word of `s` and the rest, without the spaces around.
+fn word(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    let (word, rest) = s.split_once(' ').unwrap_or((s, ""));
+    (word, rest.trim())
+}
+
+/// Settings, as named by `get` and `set`.
+const NAMES: &[&str] = &[
+    "unit",
+    "rotate",
+    #[cfg(feature = "tachometer")]
+    "ppr",
+    #[cfg(feature = "speedometer")]
+    "wheel",
+    #[cfg(feature = "backlight")]
+    "backlight",
+    #[cfg(feature = "battery")]
+    "cutoff",
+];
+
+/// Write the setting `name` and its value (`unit C`).
+fn write_setting<W: Write>(w: &mut W, settings: &Settings, name: &str) -> fmt::Result {
+    w.write_str(name)?;
+    w.write_char(' ')?;
+    match name {
+        "unit" => w.write_str(settings.temp_unit.symbol())?,
+        "rotate" => w.write_str(if settings.auto_rotate { "on" } else { "off" })?,
+        #[cfg(feature = "tachometer")]
+        "ppr" => write_uint(w, u32::from(settings.tachometer.pulses_per_rev()))?,
+        #[cfg(feature = "speedometer")]
+        "wheel" => {
+            write_uint(w, u32::from(settings.speedometer.circumference()))?;
+            w.write_str("mm")?;
+        }
+        #[cfg(feature = "backlight")]
+        "backlight" => match settings.backlight.manual() {
+            Some(percent) => {
+                write_uint(w, u32::from(percent))?;
+                w.write_char('%')?;
+            }
+            None => w.write_str("auto")?,
+        },
+        #[cfg(feature = "battery")]
+        "cutoff" => {
+            write_uint(w, u32::from(settings.battery.cutoff_mv()))?;
+            w.write_str("mV")?;
+        }
+        _ => {}
+    }
+    w.write_str("\r\n")
+}
+
+/// Change the setting `name` to the `value` (numbers are limited to the range of the setting).
+fn set(settings: &mut Settings, name: &str, value: &str) -> Result<(), &'static str> {
+    match (name, value) {
+        ("unit", "c" | "C") => settings.temp_unit = TempUnit::Celsius,
+        ("unit", "f" | "F") => settings.temp_unit = TempUnit::Fahrenheit,
+        ("unit", _) => return Err("unit is C or F"),
+        ("rotate", "on") => settings.auto_rotate = true,
+        ("rotate", "off") => settings.auto_rotate = false,
+        ("rotate", _) => return Err("rotate is on or off"),
+        #[cfg(feature = "tachometer")]
+        ("ppr", _) => {
+            let step = (i32::from(number(value)?) - i32::from(settings.tachometer.pulses_per_rev())).clamp(-128, 127);
+            settings.tachometer.adjust_pulses_per_rev(step as i8);
+        }
+        #[cfg(feature = "speedometer")]
+        ("wheel", _) => settings.speedometer.set_circumference(number(value)?),
+        #[cfg(feature = "backlight")]
+        ("backlight", "auto") => settings.backlight.adjust(i8::MIN),
+        #[cfg(feature = "backlight")]
+        ("backlight", _) => {
+            // Brightness is set in steps of 10%, below the lowest one it follows the light
+            let steps = (number(value)?.min(100) / 10) as i8;
+            settings.backlight.adjust(i8::MIN);
+            settings.backlight.adjust(steps);
+        }
+        #[cfg(feature = "battery")]
+        ("cutoff", _) => settings.battery.set_cutoff_mv(number(value)?),
+        _ => return Err("unknown setting"),
+    }
+    Ok(())
+}
+
+/// Numeric value of a setting, with or without the unit.
+#[cfg(any(feature = "tachometer", feature = "speedometer", feature = "backlight", feature = "battery"))]
+fn number(value: &str) -> Result<u16, &'static str> {
+    value.trim_end_matches(['%', 'm', 'V']).parse().map_err(|_| "value is a number")
+}
+
+/// Write the diagnostics: the values shown on the diagnostics, load, uptime and boots screens.
+fn write_diagnostics<W: Write>(w: &mut W, stats: &Stats, unit: TempUnit) -> fmt::Result {
+    w.write_str("uptime ")?;
+    text::duration(w, stats.uptime_s, 0)?;
+    w.write_str("\r\nboots ")?;
+    write_uint(w, u32::from(stats.boots))?;
+    w.write_str(", last reset ")?;
+    w.write_str(stats.reset_cause)?;
+    w.write_str("\r\ntemperature ")?;
+    write_fixed(w, unit.from_tenths_c(stats.temperature), 1)?;
+    w.write_str(unit.symbol())?;
+    w.write_str(", supply ")?;
+    write_uint(w, u32::from(stats.vdd_mv))?;
+    w.write_str("mV")?;
+    if stats.low_voltage {
+        w.write_str(" (low)")?;
+    }
+    w.write_str("\r\nduty cycle ")?;
+    write_uint(w, u32::from(stats.duty_cycle))?;
+    w.write_str("%, loop ")?;
+    write_uint(w, stats.loop_rate)?;
+    w.write_str("/s, longest ")?;
+    write_uint(w, stats.loop_max_us)?;
+    w.write_str("us\r\nstack free ")?;
+    write_uint(w, stats.stack_free)?;
+    w.write_str("B\r\nrender errors ")?;
+    write_uint(w, u32::from(stats.render_errors))?;
+    w.write_str(", display resets ")?;
+    write_uint(w, u32::from(stats.display_resets))?;
+    w.write_str(", read-back errors ")?;
+    write_uint(w, u32::from(stats.readback_errors))?;
+    w.write_str("\r\n")
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn shell_commands() {
+        use heapless::Deque;
+        use crate::remote::RemoteText;
+        use crate::screens::{self, Screen, Settings, Stats};
+        use crate::shell::Shell;
+        use crate::units::TempUnit;
+
+        let mut shell = Shell::new();
+        let mut settings = Settings::default();
+        let mut ui = screens::navigation();
+        let stats = Stats { uptime_s: 3725, boots: 12, reset_cause: "power on", temperature: 235, vdd_mv: 3300, ..Stats::default() };
+        let mut remote = RemoteText::new();
+        let mut out: Deque<u8, 64> = Deque::new();
+        let mut typed = |shell: &mut Shell, settings: &mut Settings, ui: &mut _, remote: &mut RemoteText, line: &str| {
+            let mut changed = false;
+            for byte in line.bytes() {
+                changed |= shell.receive(byte, settings, ui, &stats, remote);
+            }
+            // Output is queued as it fits
+            let mut output = std::vec::Vec::new();
+            loop {
+                shell.poll(&mut out);
+                if out.is_empty() {
+                    break;
+                }
+                output.extend(core::iter::from_fn(|| out.pop_front()));
+            }
+            (changed, std::string::String::from_utf8(output).unwrap())
+        };
+
+        // Typed characters are echoed, backspace deletes
+        let (changed, output) = typed(&mut shell, &mut settings, &mut ui, &mut remote, "helq\x7fp\r");
+        assert!(!changed);
+        assert!(output.starts_with("helq\x08 \x08p\r\nhelp                this list\r\n"));
+        assert!(output.ends_with("diag                dump the diagnostics\r\n> "));
+        assert_eq!(typed(&mut shell, &mut settings, &mut ui, &mut remote, "reboot\r").1, "reboot\r\nunknown command, try help\r\n> ");
+
+        // Settings
+        let get = typed(&mut shell, &mut settings, &mut ui, &mut remote, "get\r").1;
+        assert!(get.starts_with("get\r\nunit C\r\nrotate on\r\n"));
+        assert_eq!(typed(&mut shell, &mut settings, &mut ui, &mut remote, "set unit f\r"), (true, "set unit f\r\nunit F\r\n> ".into()));
+        assert_eq!(settings.temp_unit, TempUnit::Fahrenheit);
+        assert_eq!(typed(&mut shell, &mut settings, &mut ui, &mut remote, "set  rotate   off \n"), (true, "set  rotate   off \r\nrotate off\r\n> ".into()));
+        assert!(!settings.auto_rotate);
+        assert_eq!(typed(&mut shell, &mut settings, &mut ui, &mut remote, "set rotate maybe\r").1, "set rotate maybe\r\nrotate is on or off\r\n> ");
+        assert_eq!(typed(&mut shell, &mut settings, &mut ui, &mut remote, "get volume\r").1, "get volume\r\nunknown setting\r\n> ");
+        #[cfg(feature = "tachometer")]
+        {
+            typed(&mut shell, &mut settings, &mut ui, &mut remote, "set ppr 99\r");
+            assert_eq!(typed(&mut shell, &mut settings, &mut ui, &mut remote, "get ppr\r").1, "get ppr\r\nppr 24\r\n> ");
+        }
+        #[cfg(feature = "speedometer")]
+        {
+            typed(&mut shell, &mut settings, &mut ui, &mut remote, "set wheel 2097mm\r");
+            assert_eq!(settings.speedometer.circumference(), 2095);
+        }
+        #[cfg(feature = "backlight")]
+        {
+            assert_eq!(typed(&mut shell, &mut settings, &mut ui, &mut remote, "set backlight 65%\r").1, "set backlight 65%\r\nbacklight 60%\r\n> ");
+            typed(&mut shell, &mut settings, &mut ui, &mut remote, "set backlight auto\r");
+            assert_eq!(settings.backlight.manual(), None);
+        }
+
+        // Screens are switched by name
+        assert_eq!(typed(&mut shell, &mut settings, &mut ui, &mut remote, "screen\r").1, "screen\r\nhello\r\n> ");
+        assert!(typed(&mut shell, &mut settings, &mut ui, &mut remote, "screen alarm clock\r").0);
+        assert_eq!(ui.state(), Screen::Alarm);
+        assert_eq!(typed(&mut shell, &mut settings, &mut ui, &mut remote, "screen nope\r").1, "screen nope\r\nunknown screen\r\n> ");
+
+        // Text is shown instead of the screens, until released
+        assert!(typed(&mut shell, &mut settings, &mut ui, &mut remote, "text 2 Hi  there\r").0);
+        assert!(remote.is_active());
+        assert_eq!((remote.row(0), remote.row(1)), ("                ", "Hi  there       "));
+        assert_eq!(typed(&mut shell, &mut settings, &mut ui, &mut remote, "text 3 x\r").1, "text 3 x\r\nrow is 1 or 2\r\n> ");
+        assert!(typed(&mut shell, &mut settings, &mut ui, &mut remote, "text off\r").0);
+        assert!(!remote.is_active());
+
+        // Diagnostics, in the unit set
+        let diag = typed(&mut shell, &mut settings, &mut ui, &mut remote, "diag\r").1;
+        assert!(diag.starts_with("diag\r\nuptime 01:02:05\r\nboots 12, last reset power on\r\ntemperature 74.3F, supply 3300mV\r\n"));
+        assert!(diag.ends_with("render errors 0, display resets 0, read-back errors 0\r\n> "));
+
+        // Output can be taken in parts (by the RTT console)
+        for byte in b"screen\r" {
+            shell.receive(*byte, &mut settings, &mut ui, &stats, &mut remote);
+        }
+        assert_eq!(shell.pending(), b"screen\r\nalarm clock\r\n> ");
+        shell.consume(8);
+        assert_eq!(shell.pending(), b"alarm clock\r\n> ");
+        shell.consume(15);
+        assert_eq!(shell.pending(), b"");
+    }
+}
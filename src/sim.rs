@@ -0,0 +1,157 @@
+//! Simulated HD44780 controller, decoding transfers recorded by `MockHardware`.
+//!
+//! Only covers what is needed to render the text: DDRAM writes, address counter, clear, home,
+//! entry mode and display on/off. CGRAM is kept, so custom character images could be checked.
+//! Memory could be read back, too (see `read`); display is never busy.
+
+use std::string::String;
+use crate::mock::Transfer;
+
+const DDRAM_SIZE: usize = 0x80;
+const CGRAM_SIZE: usize = 0x40;
+const ROW_OFFSETS: [u8; 4] = [0x00, 0x40, 0x14, 0x54];
+
+pub struct Hd44780 {
+    ddram: [u8; DDRAM_SIZE],
+    cgram_data: [u8; CGRAM_SIZE],
+    address: u8,
+    cgram: bool,
+    increment: bool,
+    display_on: bool,
+    bit4: bool,
+    // High nibble received in 4-bit mode, waiting for the low one
+    pending: Option<Transfer>,
+    // Low nibble of the byte read in 4-bit mode, waiting for the second read
+    read_low: Option<u8>,
+}
+
+impl Default for Hd44780 {
+    fn default() -> Hd44780 {
+        Hd44780 {
+            ddram: [b' '; DDRAM_SIZE],
+            cgram_data: [0; CGRAM_SIZE],
+            address: 0,
+            cgram: false,
+            increment: true,
+            display_on: false,
+            // 8-bit mode after power on
+            bit4: false,
+            pending: None,
+            read_low: None,
+        }
+    }
+}
+
+impl Hd44780 {
+    pub fn new() -> Hd44780 {
+        Hd44780::default()
+    }
+
+    /// Feed transfers latched on the data lines DB4-DB7 (display is wired in 4-bit mode).
+    pub fn feed(&mut self, transfers: &[Transfer]) {
+        for &transfer in transfers {
+            if !self.bit4 {
+                // DB0-DB3 are not connected, so they read as zeroes
+                self.execute(transfer.rs, transfer.data << 4);
+            } else if let Some(high) = self.pending.take() {
+                self.execute(high.rs, (high.data << 4) | transfer.data);
+            } else {
+                self.pending = Some(transfer);
+            }
+        }
+    }
+
+    /// Value put on the data lines DB4-DB7 by a read: busy flag (always clear) and address counter
+    /// if `rs` is `false`, memory at the address counter otherwise (address counter advances). High
+    /// nibble goes first in 4-bit mode.
+    pub fn read(&mut self, rs: bool) -> u8 {
+        if let Some(low) = self.read_low.take() {
+            return low;
+        }
+        let byte = if !rs {
+            self.address
+        } else {
+            let byte = if self.cgram {
+                self.cgram_data[usize::from(self.address) % CGRAM_SIZE]
+            } else {
+                self.ddram[usize::from(self.address) % DDRAM_SIZE]
+            };
+            self.advance();
+            byte
+        };
+        if self.bit4 {
+            self.read_low = Some(byte & 0xf);
+        }
+        byte >> 4
+    }
+
+    fn execute(&mut self, rs: bool, data: u8) {
+        if rs {
+            if self.cgram {
+                // Only five lower bits are stored
+                self.cgram_data[usize::from(self.address) % CGRAM_SIZE] = data & 0x1f;
+            } else {
+                self.ddram[usize::from(self.address) % DDRAM_SIZE] = data;
+            }
+            self.advance();
+        } else if data & 0x80 != 0 {
+            self.cgram = false;
+            self.address = data & 0x7f;
+        } else if data & 0x40 != 0 {
+            self.cgram = true;
+            self.address = data & 0x3f;
+        } else if data & 0x20 != 0 {
+            self.bit4 = data & 0x10 == 0;
+        } else if data & 0x10 != 0 {
+            // Cursor / display shift, not simulated
+        } else if data & 0x08 != 0 {
+            self.display_on = data & 0x04 != 0;
+        } else if data & 0x04 != 0 {
+            self.increment = data & 0x02 != 0;
+        } else if data & 0x02 != 0 {
+            self.cgram = false;
+            self.address = 0;
+        } else if data & 0x01 != 0 {
+            self.ddram = [b' '; DDRAM_SIZE];
+            self.cgram = false;
+            self.address = 0;
+            self.increment = true;
+        }
+    }
+
+    fn advance(&mut self) {
+        self.address = if self.increment {
+            self.address.wrapping_add(1)
+        } else {
+            self.address.wrapping_sub(1)
+        } & 0x7f;
+    }
+
+    /// Text currently shown in the given row (blank if display is off). Custom characters are
+    /// shown as `#`.
+    pub fn row(&self, row: usize, columns: usize) -> String {
+        (0..columns)
+            .map(|col| {
+                let c = self.ddram[(usize::from(ROW_OFFSETS[row]) + col) % DDRAM_SIZE];
+                match c {
+                    _ if !self.display_on => ' ',
+                    0..=7 => '#',
+                    0x20..=0x7e => c as char,
+                    _ => '?',
+                }
+            })
+            .collect()
+    }
+
+    /// Image of the custom character shown in the given cell (`None` for a standard one).
+    pub fn cell_glyph(&self, col: usize, row: usize) -> Option<[u8; 8]> {
+        let c = self.ddram[(usize::from(ROW_OFFSETS[row]) + col) % DDRAM_SIZE];
+        (c < 8).then(|| self.glyph(usize::from(c)))
+    }
+
+    /// Image of the custom character at the given location (0-7).
+    pub fn glyph(&self, location: usize) -> [u8; 8] {
+        let start = (location % 8) * 8;
+        self.cgram_data[start..start + 8].try_into().unwrap()
+    }
+}
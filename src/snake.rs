@@ -0,0 +1,434 @@
+//! Snake on a 20x16 pseudo-pixel field: four columns by two rows of 5x8 cells, drawn with the
+//! custom characters handed out on every render (see `pixels`), so the blank cells take none and
+//! the low battery icon keeps its own (the standard characters are restored by the other screens,
+//! see `Screen::render`).
+//!
+//! Encoder turns the snake (up turns it clockwise, down counterclockwise), at most once per step,
+//! so it cannot turn back into itself. Encoder also starts the game and resumes it after a pause;
+//! button pauses the running game, otherwise it is left for the navigation. Walls and the snake
+//! itself are deadly.
+//!
+//! Like the stopwatch, time is passed to `advance`, which moves the snake every `STEP_MS`.
+
+use core::fmt::{self, Write};
+use crate::pixels::{PixelRegion, CELL_HEIGHT, CELL_WIDTH};
+use crate::random;
+use crate::text;
+use crate::ui::Event;
+
+/// Field size, in pixels.
+pub const WIDTH: usize = 20;
+pub const HEIGHT: usize = 16;
+/// Field size, in characters (each one is 5x8 pixels).
+pub const CHAR_COLUMNS: usize = WIDTH / CELL_WIDTH;
+pub const CHAR_ROWS: usize = HEIGHT / CELL_HEIGHT;
+const CELLS: usize = WIDTH * HEIGHT;
+
+/// Snake moves every `STEP_MS` milliseconds.
+pub const STEP_MS: u32 = 300;
+/// Steps done by a single `advance`, at most.
+const MAX_STEPS: u32 = 10;
+const START_LENGTH: usize = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    /// New game, waiting for the encoder.
+    Ready,
+    Running,
+    Paused,
+    Over,
+}
+
+/// Direction of a move, clockwise from up.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Dir {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+impl Dir {
+    fn from_bits(bits: u8) -> Dir {
+        match bits & 3 {
+            0 => Dir::Up,
+            1 => Dir::Right,
+            2 => Dir::Down,
+            _ => Dir::Left,
+        }
+    }
+
+    /// Turn clockwise (`1`) or counterclockwise (`-1`).
+    fn turned(self, step: i8) -> Dir {
+        Dir::from_bits((self as i8 + step) as u8)
+    }
+
+    /// Pixel next to `(x, y)` in this direction, `None` if it is outside of the field.
+    fn next(self, (x, y): (u8, u8)) -> Option<(u8, u8)> {
+        let (x, y) = match self {
+            Dir::Up => (x, y.checked_sub(1)?),
+            Dir::Right => (x + 1, y),
+            Dir::Down => (x, y + 1),
+            Dir::Left => (x.checked_sub(1)?, y),
+        };
+        (usize::from(x) < WIDTH && usize::from(y) < HEIGHT).then_some((x, y))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Snake {
+    state: State,
+    /// Pixels taken by the snake, one bit per column.
+    body: [u32; HEIGHT],
+    /// Moves from the tail to the head (ring buffer), two bits each.
+    moves: [u8; CELLS / 4],
+    /// Index of the tail move in `moves`.
+    first: usize,
+    /// Snake length, in pixels (one more than the moves kept).
+    length: usize,
+    head: (u8, u8),
+    tail: (u8, u8),
+    /// Direction of the last step and of the next one.
+    heading: Dir,
+    next: Dir,
+    food: (u8, u8),
+    /// Food eaten in this game and the best count since boot.
+    score: u16,
+    best: u16,
+    /// Steps done since boot (changes when there is something new to show).
+    steps: u32,
+    /// Timer value of the last step (or of the last `advance`, while not running).
+    last_ms: u32,
+}
+
+impl Default for Snake {
+    fn default() -> Snake {
+        let mut snake = Snake {
+            state: State::Ready,
+            body: [0; HEIGHT],
+            moves: [0; CELLS / 4],
+            first: 0,
+            length: 0,
+            head: (0, 0),
+            tail: (0, 0),
+            heading: Dir::Right,
+            next: Dir::Right,
+            food: (0, 0),
+            score: 0,
+            best: 0,
+            steps: 0,
+            last_ms: 0,
+        };
+        snake.restart();
+        snake
+    }
+}
+
+impl Snake {
+    /// New game: short snake heading right in the middle of the field.
+    fn restart(&mut self) {
+        self.body = [0; HEIGHT];
+        self.first = 0;
+        self.length = 1;
+        self.tail = (WIDTH as u8 / 2 - START_LENGTH as u8, HEIGHT as u8 / 2);
+        self.head = self.tail;
+        self.set(self.head, true);
+        for _ in 1..START_LENGTH {
+            self.grow(Dir::Right);
+        }
+        self.heading = Dir::Right;
+        self.next = Dir::Right;
+        self.score = 0;
+        self.state = State::Ready;
+        self.place_food();
+    }
+
+    /// Move the snake up to `now_ms` (the millisecond timer, which can wrap around).
+    pub fn advance(&mut self, now_ms: u32) {
+        if self.state != State::Running {
+            self.last_ms = now_ms;
+            return;
+        }
+        let steps = now_ms.wrapping_sub(self.last_ms) / STEP_MS;
+        self.last_ms = self.last_ms.wrapping_add(steps * STEP_MS);
+        for _ in 0..steps.min(MAX_STEPS) {
+            self.step();
+            if self.state != State::Running {
+                break;
+            }
+        }
+    }
+
+    fn step(&mut self) {
+        self.steps = self.steps.wrapping_add(1);
+        self.heading = self.next;
+        let Some(head) = self.heading.next(self.head) else {
+            return self.game_over();
+        };
+        let eating = head == self.food;
+        if !eating {
+            // Tail moves away first, so the head can follow it
+            self.set(self.tail, false);
+            self.tail = self.tail_move().next(self.tail).unwrap_or(self.tail);
+            self.first = (self.first + 1) % CELLS;
+            self.length -= 1;
+        }
+        if self.taken(head) {
+            return self.game_over();
+        }
+        self.grow(self.heading);
+        if eating {
+            self.score += 1;
+            self.best = self.best.max(self.score);
+            if self.length == CELLS {
+                // Whole field is taken, nothing more to eat
+                return self.game_over();
+            }
+            self.place_food();
+        }
+    }
+
+    /// Move the head one pixel further (head must be inside of the field).
+    fn grow(&mut self, dir: Dir) {
+        let index = (self.first + self.length - 1) % CELLS;
+        let shift = (index % 4) * 2;
+        self.moves[index / 4] = (self.moves[index / 4] & !(3 << shift)) | ((dir as u8) << shift);
+        self.length += 1;
+        self.head = dir.next(self.head).unwrap_or(self.head);
+        self.set(self.head, true);
+    }
+
+    /// Move from the tail to the next pixel of the snake.
+    fn tail_move(&self) -> Dir {
+        Dir::from_bits(self.moves[self.first / 4] >> ((self.first % 4) * 2))
+    }
+
+    fn game_over(&mut self) {
+        self.state = State::Over;
+    }
+
+    /// Put the food on a random free pixel.
+    fn place_food(&mut self) {
+        let skip = random::below((CELLS - self.length) as u32) as usize;
+        let mut free = (0..CELLS).map(|cell| ((cell % WIDTH) as u8, (cell / WIDTH) as u8)).filter(|&pixel| !self.taken(pixel));
+        // Cannot fail, there are more free pixels than `skip`
+        self.food = free.nth(skip).unwrap_or_default();
+    }
+
+    fn taken(&self, (x, y): (u8, u8)) -> bool {
+        self.body[usize::from(y)] & (1 << x) != 0
+    }
+
+    fn set(&mut self, (x, y): (u8, u8), taken: bool) {
+        if taken {
+            self.body[usize::from(y)] |= 1 << x;
+        } else {
+            self.body[usize::from(y)] &= !(1 << x);
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Food eaten in this game.
+    pub fn score(&self) -> u16 {
+        self.score
+    }
+
+    /// Snake length, in pixels.
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Head and food positions, in pixels.
+    pub fn head(&self) -> (u8, u8) {
+        self.head
+    }
+
+    pub fn food(&self) -> (u8, u8) {
+        self.food
+    }
+
+    /// Steps done since boot.
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+
+    /// Is the pixel lit (taken by the snake or by the food)?
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.taken((x as u8, y as u8)) || self.food == (x as u8, y as u8)
+    }
+
+    /// Field, as a region of pseudo-pixels.
+    pub fn field(&self) -> PixelRegion<{ CHAR_COLUMNS * CHAR_ROWS }> {
+        let mut field = PixelRegion::new(CHAR_COLUMNS);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                field.set(x, y, self.pixel(x, y));
+            }
+        }
+        field
+    }
+
+    /// Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        let step = match event {
+            Event::EncoderUp => 1,
+            Event::EncoderDown => -1,
+            Event::Button if self.state == State::Running => {
+                self.state = State::Paused;
+                return true;
+            }
+            _ => return false,
+        };
+        match self.state {
+            State::Running => self.next = self.heading.turned(step),
+            State::Ready | State::Paused => self.state = State::Running,
+            State::Over => self.restart(),
+        }
+        true
+    }
+
+    /// Write the state and the score (` Score   12`), to the right of the field.
+    pub fn write_status<W: Write>(&self, w: &mut W) -> fmt::Result {
+        let name = match self.state {
+            State::Ready => return w.write_str(" Snake"),
+            State::Running => " Score",
+            State::Paused => " Pause",
+            State::Over => " Over",
+        };
+        w.write_str(name)?;
+        text::uint(w, u32::from(self.score), 11 - name.len())
+    }
+
+    /// Write the best score (` Best     15`), to the right of the field.
+    pub fn write_best<W: Write>(&self, w: &mut W) -> fmt::Result {
+        w.write_str(" Best")?;
+        text::uint(w, u32::from(self.best), 6)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn snake_game() {
+        use lcd::Display;
+        use std::string::String;
+        use crate::framebuffer::Framebuffer;
+        use crate::hardware::LcdHardware;
+        use crate::mock::{self, join_nibbles, MockHardware};
+        use crate::screens::{self, Screen, Settings, Stats};
+        use crate::sim::Hd44780;
+        use crate::snake::{State, STEP_MS};
+        use crate::ui::Event;
+
+        let mock = MockHardware::new();
+        let mut display = Display::new(LcdHardware::new(&mock, &mock));
+        screens::init(&mut display);
+        let mut lcd = Hd44780::new();
+        lcd.feed(&mock.transfers());
+        mock.reset();
+        let mut fb = Framebuffer::new(display);
+        let mut settings = Settings::default();
+        let mut render = |screen: Screen, settings: &Settings| {
+            screen.render(&mut fb, &Stats::default(), settings).unwrap();
+            fb.flush().unwrap();
+            let transfers = mock.transfers();
+            lcd.feed(&transfers);
+            mock.reset();
+            // Custom characters uploaded
+            let uploads = join_nibbles(&transfers).iter().filter(|t| !t.rs && t.data & 0xc0 == 0x40).count();
+            // Image in the second cell of the second row
+            (mock::render(screen, &Stats::default(), settings), uploads, lcd.cell_glyph(1, 1).unwrap_or_default())
+        };
+
+        // Field takes the first four columns, snake is in the middle, heading right. Blank cells take
+        // no custom characters (only the snake and the food are drawn)
+        let lit = |rows: &(String, String)| rows.0[..4].chars().chain(rows.1[..4].chars()).filter(|c| *c == '#').count();
+        let (rows, uploads, glyph) = render(Screen::Snake, &settings);
+        assert_eq!((&rows.0[4..], &rows.1[4..]), (" Snake      ", " Best     0 "));
+        assert_eq!(&rows.1[1..2], "#");
+        assert!((1..=2).contains(&lit(&rows)));
+        assert_eq!(uploads, lit(&rows));
+        assert_eq!(glyph[0] & 0b00111, 0b00111);
+        assert_eq!(render(Screen::Snake, &settings).1, 0);
+        assert!(!settings.handle(Screen::Snake, Event::Button));
+
+        // Encoder starts the game, snake moves every step
+        settings.advance(0);
+        assert!(settings.handle(Screen::Snake, Event::EncoderUp));
+        assert_eq!(settings.snake.head(), (9, 8));
+        settings.advance(STEP_MS);
+        assert_eq!(settings.snake.head(), (10, 8));
+        // Only changed characters are re-sent: tail left the second one, head entered the third
+        let (rows, uploads, glyph) = render(Screen::Snake, &settings);
+        assert_eq!(&rows.0[4..], " Score    0 ");
+        assert_eq!(&rows.1[1..3], "##");
+        assert!(uploads <= 3);
+        assert_eq!(glyph[0] & 0b00111, 0b00011);
+
+        // One turn per step, so snake never turns back
+        assert!(settings.handle(Screen::Snake, Event::EncoderDown));
+        assert!(settings.handle(Screen::Snake, Event::EncoderDown));
+        settings.advance(2 * STEP_MS);
+        assert_eq!(settings.snake.head(), (10, 7));
+
+        // Paused game does not move
+        assert!(settings.handle(Screen::Snake, Event::Button));
+        settings.advance(100 * STEP_MS);
+        assert_eq!(settings.snake.state(), State::Paused);
+        assert_eq!(settings.snake.head(), (10, 7));
+        assert!(!settings.handle(Screen::Snake, Event::Button));
+        assert!(settings.handle(Screen::Snake, Event::EncoderUp));
+
+        // Steer towards the food
+        let mut now_ms = 100 * STEP_MS;
+        let mut heading = (0, -1);
+        while settings.snake.score() < 3 {
+            let ((x, y), (food_x, food_y)) = (settings.snake.head(), settings.snake.food());
+            let wanted = if food_x != x { ((food_x as i32 - x as i32).signum(), 0) } else { (0, (food_y as i32 - y as i32).signum()) };
+            // Food behind is reached by turning away from the wall
+            let (right, left) = ((-heading.1, heading.0), (heading.1, -heading.0));
+            let inside = |(dx, dy): (i32, i32)| (0..20).contains(&(x as i32 + dx)) && (0..16).contains(&(y as i32 + dy));
+            if wanted == right || (wanted != heading && wanted != left && inside(right)) {
+                assert!(settings.handle(Screen::Snake, Event::EncoderUp));
+                heading = right;
+            } else if wanted != heading {
+                assert!(settings.handle(Screen::Snake, Event::EncoderDown));
+                heading = left;
+            }
+            now_ms += STEP_MS;
+            settings.advance(now_ms);
+            assert_eq!(settings.snake.state(), State::Running);
+        }
+        assert_eq!(settings.snake.length(), 6);
+        let (rows, _, _) = render(Screen::Snake, &settings);
+        assert_eq!((&rows.0[4..], &rows.1[4..]), (" Score    3 ", " Best     3 "));
+
+        // Wall is deadly
+        while settings.snake.state() == State::Running {
+            now_ms += STEP_MS;
+            settings.advance(now_ms);
+        }
+        assert_eq!(&render(Screen::Snake, &settings).0 .0[4..], " Over     3 ");
+        assert!(settings.handle(Screen::Snake, Event::EncoderUp));
+        assert_eq!(settings.snake.state(), State::Ready);
+        assert_eq!(settings.snake.length(), 3);
+
+        // Other screens load the standard characters they show (none on the settings screen)
+        let (rows, uploads, _) = render(Screen::Settings, &settings);
+        assert_eq!(rows.0, "Settings        ");
+        assert_eq!(uploads, 0);
+
+        // Snake screen goes after the reaction one
+        let mut ui = screens::navigation();
+        ui.handle(Event::Serial(b'x'), &settings);
+        ui.handle(Event::EncoderUp, &settings);
+        assert_eq!(ui.state(), Screen::Snake);
+        ui.handle(Event::EncoderUp, &settings);
+        assert_eq!(ui.state(), Screen::Dice);
+        ui.handle(Event::Serial(b'k'), &settings);
+        assert_eq!(ui.state(), Screen::Snake);
+    }
+}
@@ -0,0 +1,256 @@
+//! Bicycle speedometer: speed, trip distance and odometer of a wheel, put together from the
+//! tachometer (its speed and pulses per revolution: a magnet, or a few, on the wheel), the wheel
+//! circumference set on the settings screen and the big digits.
+//!
+//! Speed is the tachometer speed times the circumference, shown in km/h with the big digits (one
+//! decimal, up to 99.9 km/h, zero until measured). Distance is accumulated from the pulses counted,
+//! in whole revolutions, so a changed circumference only applies from then on. Odometer is
+//! checkpointed to flash (see `kv`) every `kv::CHECKPOINT_MS` if it has changed, trip distance is
+//! kept in the backup registers (see `restore_trip`): it survives resets (losing the meters short
+//! of ten), and starts over on the long press.
+//!
+//! Button goes from the speed page to the distance page (and then on to the next screen).
+
+use core::fmt::{self, Write};
+use crate::big;
+use crate::text;
+use crate::ui::Event;
+
+/// Wheel circumference range and the step it is edited by, in millimeters.
+const CIRCUMFERENCE_MIN: u16 = 1000;
+const CIRCUMFERENCE_MAX: u16 = 3000;
+const CIRCUMFERENCE_STEP: u16 = 5;
+/// Highest speed shown, in tenths of km/h (two big digits).
+const SPEED_MAX: u32 = 999;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Speedometer {
+    circumference_mm: u16,
+    /// Speed, in tenths of km/h.
+    speed: u32,
+    /// Pulse input count at the last update.
+    seen: u32,
+    /// Pulses short of a whole revolution.
+    pending_pulses: u32,
+    /// Distance short of a whole meter, in millimeters.
+    pending_mm: u32,
+    /// Distances, in meters.
+    odometer_m: u32,
+    trip_m: u32,
+    /// Odometer checkpointed.
+    saved_m: u32,
+    /// Is the distance page shown (instead of the speed one)?
+    distance_page: bool,
+}
+
+impl Default for Speedometer {
+    /// 700x25C road wheel.
+    fn default() -> Speedometer {
+        Speedometer {
+            circumference_mm: 2105,
+            speed: 0,
+            seen: 0,
+            pending_pulses: 0,
+            pending_mm: 0,
+            odometer_m: 0,
+            trip_m: 0,
+            saved_m: 0,
+            distance_page: false,
+        }
+    }
+}
+
+impl Speedometer {
+    /// Start from the checkpointed odometer.
+    pub fn restore(&mut self, odometer_m: u32) {
+        self.odometer_m = odometer_m;
+        self.saved_m = odometer_m;
+    }
+
+    /// Restore the trip distance saved in the backup registers, in meters.
+    pub fn restore_trip(&mut self, trip_m: u32) {
+        self.trip_m = trip_m;
+    }
+
+    /// Account the wheel speed measured by the tachometer (in hundredths of rpm, `None` until
+    /// measured) and the pulses counted by the pulse input so far (wrapping around, zero at boot).
+    pub fn update(&mut self, rpm: Option<u32>, pulses: u32, pulses_per_rev: u8) {
+        let circumference_mm = u64::from(self.circumference_mm);
+        // Hundredths of a revolution per minute times millimeters is 6 / 10^6 of a tenth of km/h
+        let speed = (u64::from(rpm.unwrap_or(0)) * circumference_mm * 6 + 500_000) / 1_000_000;
+        self.speed = speed.min(u64::from(SPEED_MAX)) as u32;
+
+        let counted = self.pending_pulses.saturating_add(pulses.wrapping_sub(self.seen));
+        self.seen = pulses;
+        let pulses_per_rev = u32::from(pulses_per_rev.max(1));
+        self.pending_pulses = counted % pulses_per_rev;
+        let mm = u64::from(self.pending_mm) + u64::from(counted / pulses_per_rev) * circumference_mm;
+        self.pending_mm = (mm % 1000) as u32;
+        let meters = u32::try_from(mm / 1000).unwrap_or(u32::MAX);
+        self.odometer_m = self.odometer_m.saturating_add(meters);
+        self.trip_m = self.trip_m.saturating_add(meters);
+    }
+
+    /// Speed, in tenths of km/h.
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    /// Odometer, in meters.
+    pub fn odometer(&self) -> u32 {
+        self.odometer_m
+    }
+
+    /// Trip distance, in meters.
+    pub fn trip(&self) -> u32 {
+        self.trip_m
+    }
+
+    /// Odometer to checkpoint, `None` if it is saved already.
+    pub fn checkpoint(&mut self) -> Option<u32> {
+        (self.odometer_m != self.saved_m).then(|| {
+            self.saved_m = self.odometer_m;
+            self.odometer_m
+        })
+    }
+
+    /// Wheel circumference, in millimeters.
+    pub fn circumference(&self) -> u16 {
+        self.circumference_mm
+    }
+
+    /// Set the wheel circumference, in millimeters (rounded to the step, limited to the range).
+    pub fn set_circumference(&mut self, circumference_mm: u16) {
+        let circumference_mm = circumference_mm.saturating_add(CIRCUMFERENCE_STEP / 2) / CIRCUMFERENCE_STEP * CIRCUMFERENCE_STEP;
+        self.circumference_mm = circumference_mm.clamp(CIRCUMFERENCE_MIN, CIRCUMFERENCE_MAX);
+    }
+
+    /// Change the wheel circumference by `step` steps.
+    pub fn adjust_circumference(&mut self, step: i8) {
+        let circumference_mm = i32::from(self.circumference_mm) + i32::from(step) * i32::from(CIRCUMFERENCE_STEP);
+        self.circumference_mm = circumference_mm.clamp(CIRCUMFERENCE_MIN.into(), CIRCUMFERENCE_MAX.into()) as u16;
+    }
+
+    /// Button goes to the distance page, long press starts the trip over. Returns `true` if the
+    /// event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        match event {
+            Event::Button => {
+                self.distance_page = !self.distance_page;
+                self.distance_page
+            }
+            Event::LongPress => {
+                self.trip_m = 0;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Write one `row` (0 or 1) of the page shown: the whole km/h in big digits, followed by the
+    /// unit in the top row and by the decimal (`.3`) in the bottom one, or the distances
+    /// (`Trip    12.35km` and `Odo    1234.6km`).
+    pub fn write_row<W: Write>(&self, w: &mut W, row: usize) -> fmt::Result {
+        if self.distance_page {
+            return if row == 0 {
+                w.write_str("Trip")?;
+                text::fixed(w, milli(self.trip_m), 2, 9)?;
+                w.write_str("km")
+            } else {
+                w.write_str("Odo")?;
+                text::fixed(w, milli(self.odometer_m), 1, 10)?;
+                w.write_str("km")
+            };
+        }
+        let (tens, units) = (self.speed / 100, self.speed / 10 % 10);
+        if tens == 0 {
+            // Leading zero is blank
+            (0..=big::DIGIT_WIDTH).try_for_each(|_| w.write_char(' '))?;
+        } else {
+            write_digit(w, tens, row)?;
+            w.write_char(' ')?;
+        }
+        write_digit(w, units, row)?;
+        if row == 0 {
+            w.write_str(" km/h")
+        } else {
+            w.write_char('.')?;
+            w.write_char(char::from_digit(self.speed % 10, 10).unwrap_or('0'))
+        }
+    }
+}
+
+/// Write one `row` of the big `digit`.
+fn write_digit<W: Write>(w: &mut W, digit: u32, row: usize) -> fmt::Result {
+    let mut buf = [0; 4];
+    big::write_row(w, char::from_digit(digit, 10).unwrap_or('0').encode_utf8(&mut buf), row)
+}
+
+/// Distance in meters as thousandths of a km, for `text::fixed`.
+fn milli(meters: u32) -> i32 {
+    i32::try_from(meters).unwrap_or(i32::MAX)
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn speedometer() {
+        use crate::mock;
+        use crate::screens::{self, Preference, Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        let mut settings = Settings::default();
+        let render = |screen: Screen, settings: &Settings| mock::render(screen, &Stats::default(), settings);
+        // Big digits are custom characters, leading zero is blank
+        assert_eq!(render(Screen::Speedometer, &settings), ("    ### km/h    ".into(), "    ###.0       ".into()));
+
+        // 200 rpm of a 2105mm wheel is 25.3 km/h, distance is counted in whole revolutions (two
+        // pulses each)
+        settings.speedometer.restore(1_234_000);
+        settings.speedometer.update(Some(20000), 11, 2);
+        assert_eq!(settings.speedometer.speed(), 253);
+        assert_eq!(settings.speedometer.trip(), 10);
+        assert_eq!(render(Screen::Speedometer, &settings), ("### ### km/h    ".into(), "### ###.3       ".into()));
+        settings.speedometer.update(Some(20000), 1011, 2);
+        assert_eq!(settings.speedometer.trip(), 1063);
+        assert_eq!(settings.speedometer.odometer(), 1_235_063);
+        settings.speedometer.update(Some(100_000), 1011, 2);
+        assert_eq!(settings.speedometer.speed(), 999);
+
+        // Button goes to the distances and on to the next screen, long press starts the trip over
+        assert!(Screen::Speedometer.long_press());
+        assert!(settings.handle(Screen::Speedometer, Event::Button));
+        assert_eq!(render(Screen::Speedometer, &settings), ("Trip     1.06km ".into(), "Odo    1235.1km ".into()));
+        assert!(!settings.handle(Screen::Speedometer, Event::Timer));
+        assert!(settings.handle(Screen::Speedometer, Event::LongPress));
+        assert_eq!(settings.speedometer.trip(), 0);
+        assert_eq!(settings.speedometer.odometer(), 1_235_063);
+        assert!(!settings.handle(Screen::Speedometer, Event::Button));
+
+        // Odometer is only checkpointed when changed
+        assert_eq!(settings.speedometer.checkpoint(), Some(1_235_063));
+        assert_eq!(settings.speedometer.checkpoint(), None);
+
+        // Wheel circumference is the last preference on the settings screen (but the backlight)
+        assert!(settings.handle(Screen::Settings, Event::Button));
+        assert!(settings.handle(Screen::Settings, Event::Button));
+        assert_eq!(settings.preference, Preference::WheelCircumference);
+        assert_eq!(render(Screen::Settings, &settings).1, "Wheel     2105mm");
+        assert!(settings.handle(Screen::Settings, Event::EncoderDown));
+        assert!(settings.handle(Screen::Settings, Event::EncoderDown));
+        assert_eq!(render(Screen::Settings, &settings).1, "Wheel     2095mm");
+        #[cfg(feature = "backlight")]
+        assert!(settings.handle(Screen::Settings, Event::Button));
+        #[cfg(feature = "day-night")]
+        for _ in 0..6 {
+            assert!(settings.handle(Screen::Settings, Event::Button));
+        }
+        #[cfg(feature = "battery")]
+        assert!(settings.handle(Screen::Settings, Event::Button));
+        assert!(!settings.handle(Screen::Settings, Event::Button));
+
+        let mut ui = screens::navigation();
+        ui.handle(Event::Serial(b'>'), &settings);
+        assert_eq!(ui.state(), Screen::Speedometer);
+    }
+}
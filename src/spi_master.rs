@@ -0,0 +1,61 @@
+//! Blocking SPI master on SPI1 (PA4 is CS, PA5 is SCK, PA6 is MISO, PA7 is MOSI), mode 0. Used by
+//! the flash screen.
+//!
+//! CS is driven as a plain output (hardware NSS management only supports a single transfer).
+
+use stm32f1::stm32f103::{gpioa, RCC, SPI1};
+use crate::board::PortName;
+use crate::flash::Spi;
+use crate::gpio::{self, GPIOExtras};
+
+#[cfg(feature = "nucleo-f103rb")]
+compile_error!("SPI1 SCK pin is used by the LED on Nucleo-F103RB");
+#[cfg(feature = "spi-slave")]
+compile_error!("`spi-flash` and `spi-slave` features both use SPI1");
+
+const CS: usize = 4;
+const SCK: usize = 5;
+const MISO: usize = 6;
+const MOSI: usize = 7;
+
+pub struct SpiMaster {
+    spi: SPI1,
+    port: &'static gpioa::RegisterBlock,
+}
+
+impl SpiMaster {
+    /// Configure SPI1 as a master, clock is PCLK2 / 8 (9Mhz at 72Mhz, within limits of any SPI
+    /// flash).
+    pub fn new(spi: SPI1, rcc: &RCC) -> SpiMaster {
+        let port = gpio::enable_port(rcc, PortName::A);
+        port.write_pin(CS, true);
+        port.pin_config(CS).output50().push_pull().general();
+        port.pin_config(SCK).output50().push_pull().alternate();
+        port.pin_config(MOSI).output50().push_pull().alternate();
+        port.pin_config(MISO).input().floating();
+
+        rcc.apb2enr.modify(|_, w| w.spi1en().set_bit());
+        // Software slave management, internal NSS is kept high so the peripheral stays a master
+        spi.cr1.write(|w| w.br().div8().mstr().set_bit().ssm().set_bit().ssi().set_bit().spe().set_bit());
+        SpiMaster { spi, port }
+    }
+
+    fn exchange(&self, byte: u8) -> u8 {
+        while self.spi.sr.read().txe().bit_is_clear() {}
+        self.spi.dr.write(|w| w.dr().bits(u16::from(byte)));
+        while self.spi.sr.read().rxne().bit_is_clear() {}
+        self.spi.dr.read().dr().bits() as u8
+    }
+}
+
+impl Spi for SpiMaster {
+    fn transfer(&mut self, bytes: &mut [u8]) {
+        self.port.write_pin(CS, false);
+        for byte in bytes.iter_mut() {
+            *byte = self.exchange(*byte);
+        }
+        // Reference manual asks to wait for BSY to clear before deselecting the slave
+        while self.spi.sr.read().bsy().bit_is_set() {}
+        self.port.write_pin(CS, true);
+    }
+}
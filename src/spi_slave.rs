@@ -0,0 +1,75 @@
+//! SPI slave receiver on SPI1 (PA4 is NSS, PA5 is SCK, PA7 is MOSI), so an SPI master can push
+//! text to the display (frames are described in `remote`).
+//!
+//! It is not on SPI2, the natural choice for a slave: SPI2 pins (PB12 is NSS, PB13 is SCK, PB15 is
+//! MOSI; there is no alternate mapping on STM32F103) are taken by the LCD on every supported board:
+//! RS, RW and E on Blue Pill and Nucleo-F103RB, the data bus on Maple Mini and with `open-drain`.
+//!
+//! Received bytes are stored by DMA (channel 2 of DMA1) into a circular buffer, so nothing is lost
+//! while the main loop is busy (display delays, for example). `read` must be called often enough
+//! for the buffer not to wrap around: at 1Mbit/s, the whole buffer is filled in 2ms.
+
+use stm32f1::stm32f103::{DMA1, RCC, SPI1};
+use crate::board::PortName;
+use crate::gpio::{self, GPIOExtras};
+
+#[cfg(feature = "nucleo-f103rb")]
+compile_error!("SPI1 SCK pin is used by the LED on Nucleo-F103RB");
+// Peripheral clocks are stopped in Stop mode
+#[cfg(feature = "stop-mode")]
+compile_error!("SPI slave does not work in Stop mode");
+
+const NSS: usize = 4;
+const SCK: usize = 5;
+const MOSI: usize = 7;
+
+/// Size of the DMA buffer.
+pub const BUFFER_SIZE: usize = 256;
+
+pub struct SpiSlave {
+    _spi: SPI1,
+    dma: DMA1,
+    /// Written by DMA, only read here.
+    buffer: &'static mut [u8; BUFFER_SIZE],
+    /// Position of the next byte to read in `buffer`.
+    position: usize,
+}
+
+impl SpiSlave {
+    /// Configure SPI1 as a receive-only slave (mode 0, 8 bits, MSB first) and start DMA.
+    pub fn new(spi: SPI1, dma: DMA1, rcc: &RCC, buffer: &'static mut [u8; BUFFER_SIZE]) -> SpiSlave {
+        let port = gpio::enable_port(rcc, PortName::A);
+        for pin in [NSS, SCK, MOSI] {
+            port.pin_config(pin).input().floating();
+        }
+
+        rcc.apb2enr.modify(|_, w| w.spi1en().set_bit());
+        rcc.ahbenr.modify(|_, w| w.dma1en().set_bit());
+
+        let channel = &dma.ch2;
+        channel.par.write(|w| unsafe { w.pa().bits(spi.dr.as_ptr() as u32) });
+        channel.mar.write(|w| unsafe { w.ma().bits(buffer.as_ptr() as u32) });
+        channel.ndtr.write(|w| w.ndt().bits(BUFFER_SIZE as u16));
+        channel.cr.write(|w| w.minc().set_bit().circ().set_bit().en().set_bit());
+
+        spi.cr2.write(|w| w.rxdmaen().set_bit());
+        spi.cr1.write(|w| w.rxonly().set_bit().spe().set_bit());
+
+        SpiSlave {
+            _spi: spi,
+            dma,
+            buffer,
+            position: 0,
+        }
+    }
+
+    /// Pass bytes received since the last call to `f`.
+    pub fn read<F: FnMut(u8)>(&mut self, mut f: F) {
+        // DMA counts remaining transfers down, reloaded at the end of the buffer
+        let end = BUFFER_SIZE - usize::from(self.dma.ch2.ndtr.read().ndt().bits());
+        while self.position != end % BUFFER_SIZE {
+            f(unsafe { core::ptr::read_volatile(self.buffer.as_ptr().add(self.position)) });
+            self.position = (self.position + 1) % BUFFER_SIZE;
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! Stack usage monitoring. Unused part of the stack is painted with a pattern at startup, the
+//! lowest address where the pattern is overwritten is the high-water mark.
+//!
+//! Stack grows down from `_stack_start` (end of RAM) to `_stack_end` (end of the static data),
+//! these are provided by the `cortex-m-rt` linker script.
+
+use core::ptr;
+
+/// Pattern unused stack is painted with.
+const PAINT: u32 = 0xdead_beef;
+/// Part of the stack below the current stack pointer which is left alone (painting itself needs
+/// some stack), in bytes.
+const MARGIN: usize = 64;
+
+extern "C" {
+    static mut _stack_end: u32;
+}
+
+fn stack_end() -> *mut u32 {
+    ptr::addr_of_mut!(_stack_end)
+}
+
+/// Paint the unused part of the stack. Must be called once, early in `main`.
+pub fn paint() {
+    let sp = cortex_m::register::msp::read() as usize - MARGIN;
+    let mut word = stack_end();
+    while (word as usize) < sp {
+        unsafe {
+            ptr::write_volatile(word, PAINT);
+            word = word.add(1);
+        }
+    }
+}
+
+/// Bytes of the stack which were never used since `paint`.
+pub fn free_bytes() -> usize {
+    let start = stack_end();
+    let sp = cortex_m::register::msp::read() as usize;
+    let mut word = start;
+    while (word as usize) < sp && unsafe { ptr::read_volatile(word) } == PAINT {
+        word = unsafe { word.add(1) };
+    }
+    word as usize - start as usize
+}
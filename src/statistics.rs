@@ -0,0 +1,118 @@
+//! Running statistics of a measured value: count, lowest, highest, mean and standard deviation,
+//! for the screens to attach to the values they show (instead of keeping their own).
+//!
+//! Values are integers in whatever fixed-point unit the source uses (tenths of °C, millivolts,
+//! rpm), and so are the mean and the deviation (rounded half away from zero). Only the sums are
+//! kept, so the accumulator is small; they are big enough for 2^32 samples of any `i16`.
+//!
+//! Screens start their statistics over on the long button press (see `Screen::long_press`).
+
+/// Quotient rounded half away from zero.
+fn div_round(value: i128, count: i128) -> i128 {
+    (value + value.signum() * count / 2) / count
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Accumulator {
+    count: u32,
+    min: i32,
+    max: i32,
+    sum: i64,
+    sum_squares: u64,
+}
+
+impl Accumulator {
+    /// Account the sample.
+    pub fn push(&mut self, value: i16) {
+        let value = i32::from(value);
+        if self.count == 0 {
+            (self.min, self.max) = (value, value);
+        }
+        self.count = self.count.saturating_add(1);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += i64::from(value);
+        self.sum_squares += u64::from(value.unsigned_abs()).pow(2);
+    }
+
+    /// Start over.
+    pub fn reset(&mut self) {
+        *self = Accumulator::default();
+    }
+
+    /// Samples taken.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Lowest and highest sample, if any.
+    pub fn range(&self) -> Option<(i16, i16)> {
+        (self.count > 0).then_some((self.min as i16, self.max as i16))
+    }
+
+    /// Mean of the samples, if any.
+    pub fn mean(&self) -> Option<i16> {
+        (self.count > 0).then(|| div_round(i128::from(self.sum), i128::from(self.count)) as i16)
+    }
+
+    /// Standard deviation of the samples (population one), if any.
+    pub fn stddev(&self) -> Option<i16> {
+        if self.count == 0 {
+            return None;
+        }
+        // n * sum(x^2) - sum(x)^2 is n^2 times the variance
+        let count = i128::from(self.count);
+        let scaled = count * i128::from(self.sum_squares) - i128::from(self.sum).pow(2);
+        // Square root of 4 * n^2 * variance is 2n times the deviation, rounded down (which does not
+        // change the rounded quotient)
+        let twice = (4 * scaled.max(0) as u128).isqrt() as i128;
+        // Can be just past `i16::MAX` (samples split between the extremes)
+        Some(div_round(twice, 2 * count).min(i16::MAX.into()) as i16)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn statistics_accumulator() {
+        use crate::mock;
+        use crate::screens::{Screen, Settings, Stats};
+        use crate::statistics::Accumulator;
+        use crate::ui::Event;
+
+        let mut stats = Accumulator::default();
+        assert_eq!((stats.range(), stats.mean(), stats.stddev()), (None, None, None));
+        for value in [2, 4, 4, 4, 5, 5, 7, 9] {
+            stats.push(value);
+        }
+        assert_eq!(stats.count(), 8);
+        assert_eq!((stats.range(), stats.mean(), stats.stddev()), (Some((2, 9)), Some(5), Some(2)));
+        // Mean and deviation are rounded half away from zero
+        stats.reset();
+        for value in [-3, -4] {
+            stats.push(value);
+        }
+        assert_eq!((stats.mean(), stats.stddev()), (Some(-4), Some(1)));
+        stats.reset();
+        for value in [i16::MIN, i16::MAX] {
+            stats.push(value);
+        }
+        assert_eq!((stats.mean(), stats.stddev()), (Some(-1), Some(i16::MAX)));
+
+        // Thermostat shows the mean and the deviation on the second page, long press starts over
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::Thermostat, &Stats::default(), settings);
+        assert!(Screen::Thermostat.long_press());
+        assert!(!Screen::Reaction.long_press());
+        for temperature in [250, 252, 246, 256] {
+            settings.thermostat.update(temperature);
+        }
+        assert!(settings.handle(Screen::Thermostat, Event::Button));
+        assert_eq!(render(&settings), ("Avg 25.1 sd 0.4 ".into(), "Lo 24.6 Hi 25.6C".into()));
+        assert!(settings.handle(Screen::Thermostat, Event::LongPress));
+        assert_eq!(render(&settings), ("Avg 25.6 sd 0.0 ".into(), "Lo 25.6 Hi 25.6C".into()));
+        assert!(!settings.handle(Screen::Thermostat, Event::Button));
+        assert!(settings.handle(Screen::Thermostat, Event::LongPress));
+        assert_eq!(settings.thermostat.range(), Some((256, 256)));
+    }
+}
@@ -0,0 +1,149 @@
+//! Stopwatch with lap times, counted from the millisecond timer.
+//!
+//! Encoder starts and stops the stopwatch (turned up) and records a lap or, when stopped, resets
+//! it (turned down). While stopped, button goes back through the recorded laps; after the oldest
+//! one it is left for the navigation. Stopwatch keeps running while other screens are shown.
+//!
+//! State does not depend on the clock: time is passed to `advance`, which must be called before
+//! every event and every render.
+
+use core::fmt::{self, Write};
+use crate::text::{self, Align};
+use crate::ui::Event;
+
+/// Laps kept in memory (older ones are forgotten, but still counted).
+pub const LAPS: usize = 8;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stopwatch {
+    running: bool,
+    /// Time measured so far, in milliseconds.
+    elapsed_ms: u32,
+    /// Timer value of the last `advance`.
+    last_ms: u32,
+    /// `elapsed_ms` at the end of the last lap.
+    lap_start_ms: u32,
+    /// Lap times, the last lap is at `(laps_done - 1) % LAPS`.
+    laps: [u32; LAPS],
+    /// Laps recorded since the reset.
+    laps_done: u32,
+    /// Lap shown, counted back from the last one.
+    shown: u32,
+}
+
+impl Stopwatch {
+    /// Account the time passed since the last call (`now_ms` is the millisecond timer, which can
+    /// wrap around).
+    pub fn advance(&mut self, now_ms: u32) {
+        if self.running {
+            self.elapsed_ms = self.elapsed_ms.saturating_add(now_ms.wrapping_sub(self.last_ms));
+        }
+        self.last_ms = now_ms;
+    }
+
+    /// Time measured so far, in milliseconds.
+    pub fn elapsed_ms(&self) -> u32 {
+        self.elapsed_ms
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Laps kept in memory, oldest first.
+    pub fn laps(&self) -> impl Iterator<Item = u32> + '_ {
+        let kept = self.laps_done.min(LAPS as u32);
+        (self.laps_done - kept..self.laps_done).map(move |lap| self.laps[lap as usize % LAPS])
+    }
+
+    fn lap(&mut self) {
+        self.laps[self.laps_done as usize % LAPS] = self.elapsed_ms - self.lap_start_ms;
+        self.laps_done += 1;
+        self.lap_start_ms = self.elapsed_ms;
+        self.shown = 0;
+    }
+
+    /// Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        match event {
+            Event::EncoderUp => {
+                self.running = !self.running;
+                self.shown = 0;
+            }
+            Event::EncoderDown if self.running => self.lap(),
+            Event::EncoderDown => *self = Stopwatch { last_ms: self.last_ms, ..Stopwatch::default() },
+            Event::Button if !self.running && self.shown + 1 < self.laps_done.min(LAPS as u32) => self.shown += 1,
+            Event::Button => {
+                self.shown = 0;
+                return false;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Write the state and the time (`Run     1:05.27`, last column is left for the flag).
+    pub fn write_time<W: Write>(&self, w: &mut W) -> fmt::Result {
+        text::str(w, if self.running { "Run" } else { "Stop" }, 5, Align::Left)?;
+        text::centis(w, self.elapsed_ms, 10)
+    }
+
+    /// Write the lap shown, with its number (`Lap 3    0:21.50`). Nothing is written if there are
+    /// no laps.
+    pub fn write_lap<W: Write>(&self, w: &mut W) -> fmt::Result {
+        if self.laps_done == 0 {
+            return Ok(());
+        }
+        let lap = self.laps_done - 1 - self.shown;
+        w.write_str("Lap")?;
+        text::uint(w, lap + 1, 2)?;
+        text::centis(w, self.laps[lap as usize % LAPS], 11)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn stopwatch_laps() {
+        use std::vec::Vec;
+        use crate::mock;
+        use crate::screens::{Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        let mut settings = Settings::default();
+        // Time is advanced before every event and render, as the main loop does
+        let event = |settings: &mut Settings, now_ms: u32, event: Event| {
+            settings.stopwatch.advance(now_ms);
+            settings.handle(Screen::Stopwatch, event)
+        };
+        let render = |settings: &mut Settings, now_ms: u32| {
+            settings.stopwatch.advance(now_ms);
+            mock::render(Screen::Stopwatch, &Stats::default(), settings)
+        };
+
+        assert_eq!(render(&mut settings, 1000), ("Stop    0:00.00 ".into(), "                ".into()));
+        assert!(!event(&mut settings, 1500, Event::Button));
+        assert!(event(&mut settings, 2000, Event::EncoderUp));
+        assert!(event(&mut settings, 14_345, Event::EncoderDown));
+        assert!(event(&mut settings, 75_000, Event::EncoderDown));
+        // Button does not stop the stopwatch, it is left for the navigation
+        assert!(!event(&mut settings, 76_000, Event::Button));
+        assert_eq!(render(&mut settings, 76_789), ("Run     1:14.78 ".into(), "Lap 2    1:00.65".into()));
+
+        // Time is not counted while stopped, and keeps counting across the timer wrap-around
+        assert!(event(&mut settings, 76_789, Event::EncoderUp));
+        assert!(event(&mut settings, u32::MAX - 210, Event::EncoderUp));
+        assert!(event(&mut settings, 789, Event::EncoderUp));
+        assert_eq!(render(&mut settings, 5000), ("Stop    1:15.78 ".into(), "Lap 2    1:00.65".into()));
+        assert_eq!(settings.stopwatch.laps().collect::<Vec<_>>(), vec![12_345, 60_655]);
+
+        // While stopped, button goes back through the laps, then leaves the screen
+        assert!(event(&mut settings, 6000, Event::Button));
+        assert_eq!(render(&mut settings, 6000).1, "Lap 1    0:12.34");
+        assert!(!event(&mut settings, 7000, Event::Button));
+        assert_eq!(render(&mut settings, 7000).1, "Lap 2    1:00.65");
+
+        assert!(event(&mut settings, 8000, Event::EncoderDown));
+        assert_eq!(render(&mut settings, 9000), ("Stop    0:00.00 ".into(), "                ".into()));
+    }
+}
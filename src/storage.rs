@@ -0,0 +1,160 @@
+//! Key-value store (settings, calibration constants and counters) in the last two pages of flash,
+//! the temperature log area in the pages before it and the access log before them (all of them are
+//! left out of the firmware by `memory/*.x`).
+//!
+//! CPU stalls while a page is erased (20-40ms), watchdog timeout is much longer.
+
+use core::ptr;
+use stm32f1::stm32f103::{flash, CRC, FLASH, RCC};
+use crate::kv::{self, StoreArea};
+#[cfg(feature = "temp-log")]
+use crate::logger::{RecordArea, PAGES, PAGE_WORDS};
+#[cfg(feature = "access-log")]
+use crate::access_log::{self, EventArea};
+
+/// Store pages, the last two of the 128K flash.
+const STORE_AREA: u32 = 0x0802_0000 - (kv::PAGES * kv::PAGE_WORDS * 2) as u32;
+/// Temperature log pages, before the store (four of them, reserved with or without the log).
+#[cfg(any(feature = "temp-log", feature = "access-log"))]
+const LOG_AREA: u32 = STORE_AREA - 4 * 1024;
+#[cfg(feature = "temp-log")]
+const _: () = assert!(LOG_AREA + (PAGES * PAGE_WORDS * 2) as u32 <= STORE_AREA);
+/// Access log pages, before the log pages.
+#[cfg(feature = "access-log")]
+const EVENT_AREA: u32 = LOG_AREA - (access_log::PAGES * access_log::PAGE_WORDS * 2) as u32;
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xcdef_89ab;
+
+/// Store pages (see `kv`).
+pub struct StorePages;
+
+impl StorePages {
+    fn address(page: usize, index: usize) -> u32 {
+        STORE_AREA + (2 * (page * kv::PAGE_WORDS + index)) as u32
+    }
+}
+
+impl StoreArea for StorePages {
+    fn read(&self, page: usize, index: usize) -> u16 {
+        unsafe { ptr::read_volatile(StorePages::address(page, index) as *const u16) }
+    }
+
+    fn program(&mut self, page: usize, index: usize, value: u16) {
+        let flash = unlock();
+        program(flash, StorePages::address(page, index), value);
+        lock(flash);
+    }
+
+    fn erase(&mut self, page: usize) {
+        let flash = unlock();
+        erase(flash, StorePages::address(page, 0));
+        lock(flash);
+    }
+
+    fn crc32(&self, words: &[u32]) -> u32 {
+        crc32(words)
+    }
+}
+
+/// CRC-32 of the `words` by the CRC unit (see `crc32`). Nothing else uses it, so its clock is
+/// simply enabled every time.
+fn crc32(words: &[u32]) -> u32 {
+    let rcc = unsafe { &*RCC::ptr() };
+    rcc.ahbenr.modify(|_, w| w.crcen().set_bit());
+    let crc = unsafe { &*CRC::ptr() };
+    crc.cr.write(|w| w.reset().set_bit());
+    for &word in words {
+        crc.dr.write(|w| w.dr().bits(word));
+    }
+    crc.dr.read().dr().bits()
+}
+
+/// Flash peripheral is owned by the clock setup (and by the Stop mode), which only touch the
+/// access control register.
+fn unlock() -> &'static flash::RegisterBlock {
+    let flash = unsafe { &*FLASH::ptr() };
+    flash.keyr.write(|w| unsafe { w.key().bits(KEY1) });
+    flash.keyr.write(|w| unsafe { w.key().bits(KEY2) });
+    flash
+}
+
+fn lock(flash: &flash::RegisterBlock) {
+    flash.cr.write(|w| w.lock().set_bit());
+}
+
+fn erase(flash: &flash::RegisterBlock, page: u32) {
+    flash.cr.write(|w| w.per().set_bit());
+    flash.ar.write(|w| unsafe { w.far().bits(page) });
+    flash.cr.modify(|_, w| w.strt().set_bit());
+    while flash.sr.read().bsy().bit_is_set() {}
+}
+
+fn program(flash: &flash::RegisterBlock, address: u32, value: u16) {
+    flash.cr.write(|w| w.pg().set_bit());
+    unsafe { ptr::write_volatile(address as *mut u16, value) };
+    while flash.sr.read().bsy().bit_is_set() {}
+}
+
+/// Temperature log pages (see `logger`).
+#[cfg(feature = "temp-log")]
+pub struct LogArea;
+
+#[cfg(feature = "temp-log")]
+impl LogArea {
+    fn address(page: usize, index: usize) -> u32 {
+        LOG_AREA + (2 * (page * PAGE_WORDS + index)) as u32
+    }
+}
+
+#[cfg(feature = "temp-log")]
+impl RecordArea for LogArea {
+    fn read(&self, page: usize, index: usize) -> u16 {
+        unsafe { ptr::read_volatile(LogArea::address(page, index) as *const u16) }
+    }
+
+    fn program(&mut self, page: usize, index: usize, value: u16) {
+        let flash = unlock();
+        program(flash, LogArea::address(page, index), value);
+        lock(flash);
+    }
+
+    fn erase(&mut self, page: usize) {
+        let flash = unlock();
+        erase(flash, LogArea::address(page, 0));
+        lock(flash);
+    }
+}
+
+/// Access log pages (see `access_log`).
+#[cfg(feature = "access-log")]
+pub struct EventPages;
+
+#[cfg(feature = "access-log")]
+impl EventPages {
+    fn address(page: usize, index: usize) -> u32 {
+        EVENT_AREA + (2 * (page * access_log::PAGE_WORDS + index)) as u32
+    }
+}
+
+#[cfg(feature = "access-log")]
+impl EventArea for EventPages {
+    fn read(&self, page: usize, index: usize) -> u16 {
+        unsafe { ptr::read_volatile(EventPages::address(page, index) as *const u16) }
+    }
+
+    fn program(&mut self, page: usize, index: usize, value: u16) {
+        let flash = unlock();
+        program(flash, EventPages::address(page, index), value);
+        lock(flash);
+    }
+
+    fn erase(&mut self, page: usize) {
+        let flash = unlock();
+        erase(flash, EventPages::address(page, 0));
+        lock(flash);
+    }
+
+    fn crc32(&self, words: &[u32]) -> u32 {
+        crc32(words)
+    }
+}
@@ -0,0 +1,135 @@
+//! Debug strobe around the display I/O (`io-strobe` feature), to see on a scope how much time the
+//! firmware spends talking to the display.
+//!
+//! `StrobedPort` sits between `LcdHardware` and the GPIO port and drives a spare pin high:
+//!  * from the E pulse of the first nibble of a byte until the end of the E pulse of the second
+//!    nibble (transfer of one byte in 4-bit mode);
+//!  * while R/W is high (busy flag polling and other reads).
+//!
+//! Setup time before the first E pulse is not included. Initialization sends four single nibbles
+//! first, they are shown as two bytes. Without `input` feature, `lcd` driver waits for commands
+//! with fixed delays, these are not marked.
+
+use core::cell::Cell;
+use crate::hardware::{E, RW};
+use crate::port::Port;
+
+pub struct StrobedPort<P: Port, S: Port> {
+    port: P,
+    strobe: S,
+    pin: usize,
+    /// Last written levels of the LCD pins.
+    output: Cell<u16>,
+    /// Nibbles written since the strobe went high.
+    nibbles: Cell<u8>,
+}
+
+impl<P: Port, S: Port> StrobedPort<P, S> {
+    /// Strobe is driven on `pin` of the `strobe` port, which is configured for output.
+    pub fn new(port: P, strobe: S, pin: usize) -> StrobedPort<P, S> {
+        strobe.write_pin(pin, false);
+        strobe.output(pin);
+        StrobedPort {
+            port,
+            strobe,
+            pin,
+            output: Cell::new(0),
+            nibbles: Cell::new(0),
+        }
+    }
+}
+
+impl<P: Port, S: Port> Port for StrobedPort<P, S> {
+    fn write_pin_range(&self, offset: usize, count: usize, data: u16) {
+        let mask = (((1u32 << count) - 1) << offset) as u16;
+        let prev = self.output.get();
+        let next = (prev & !mask) | ((data << offset) & mask);
+        self.output.set(next);
+        let rises = |pin: usize| prev & (1 << pin) == 0 && next & (1 << pin) != 0;
+        let falls = |pin: usize| prev & (1 << pin) != 0 && next & (1 << pin) == 0;
+
+        let reading = next & (1 << RW) != 0;
+        // Read could follow a single nibble (initialization), with the strobe already high
+        if (rises(RW) || (!reading && rises(E))) && self.nibbles.get() == 0 {
+            self.strobe.write_pin(self.pin, true);
+        }
+        self.port.write_pin_range(offset, count, data);
+        if falls(RW) {
+            self.nibbles.set(0);
+            self.strobe.write_pin(self.pin, false);
+        } else if !reading && falls(E) {
+            self.nibbles.set(self.nibbles.get() + 1);
+            if self.nibbles.get() == 2 {
+                self.nibbles.set(0);
+                self.strobe.write_pin(self.pin, false);
+            }
+        }
+    }
+
+    fn read_pin_range(&self, offset: usize, count: usize) -> u16 {
+        self.port.read_pin_range(offset, count)
+    }
+
+    fn output(&self, pin: usize) {
+        self.port.output(pin);
+    }
+
+    fn open_drain(&self, pin: usize) {
+        self.port.open_drain(pin);
+    }
+
+    fn input(&self, pin: usize) {
+        self.port.input(pin);
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn strobe_covers_every_byte() {
+        use lcd::{Display, FunctionDots, FunctionLine};
+        use std::cell::RefCell;
+        use std::vec::Vec;
+        use crate::hardware::LcdHardware;
+        use crate::mock::MockHardware;
+        use crate::port::Port;
+        use crate::strobe::StrobedPort;
+
+        /// Levels written to the strobe pin.
+        #[derive(Default)]
+        struct Scope(RefCell<Vec<bool>>);
+
+        impl Port for Scope {
+            fn write_pin_range(&self, _offset: usize, _count: usize, data: u16) {
+                self.0.borrow_mut().push(data != 0);
+            }
+
+            fn read_pin_range(&self, _offset: usize, _count: usize) -> u16 {
+                0
+            }
+
+            fn output(&self, _pin: usize) {}
+
+            fn open_drain(&self, _pin: usize) {}
+
+            fn input(&self, _pin: usize) {}
+        }
+
+        let mock = MockHardware::new();
+        let scope = Scope::default();
+        let mut display = Display::new(LcdHardware::new(StrobedPort::new(&mock, &scope, 1), &mock));
+        display.init(FunctionLine::Line2, FunctionDots::Dots5x8);
+        display.print("Hi");
+
+        let levels = scope.0.borrow();
+        assert!(!levels[0]);
+        assert!(levels.windows(2).all(|w| w[0] != w[1]));
+        assert_eq!(levels.last(), Some(&false));
+        // Busy flag polling adds its own pulses with `input`
+        let pulses = levels.iter().filter(|&&level| level).count();
+        #[cfg(not(feature = "input"))]
+        assert_eq!(pulses * 2, mock.transfers().len());
+        #[cfg(feature = "input")]
+        assert!(pulses * 2 > mock.transfers().len());
+    }
+}
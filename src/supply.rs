@@ -0,0 +1,33 @@
+//! Supply voltage monitoring via the programmable voltage detector (PVD).
+//!
+//! STM32F1 brown-out reset threshold is fixed (around 1.9V), so it cannot be used for an early
+//! warning. PVD compares VDD against a programmable threshold (2.2V to 2.9V, in 100mV steps) and
+//! keeps working in Stop mode.
+
+use stm32f1::stm32f103::{pwr, PWR, RCC};
+
+/// Lowest PVD threshold, in millivolts.
+const PVD_MIN_MV: u16 = 2200;
+/// PVD threshold step, in millivolts.
+const PVD_STEP_MV: u16 = 100;
+
+pub struct SupplyMonitor {
+    pwr: &'static pwr::RegisterBlock,
+}
+
+impl SupplyMonitor {
+    /// Enable PVD with the threshold closest to `threshold_mv` (rounded down, clamped to the
+    /// supported range).
+    pub fn start(pwr: &PWR, rcc: &RCC, threshold_mv: u16) -> SupplyMonitor {
+        rcc.apb1enr.modify(|_, w| w.pwren().set_bit());
+        let level = (threshold_mv.saturating_sub(PVD_MIN_MV) / PVD_STEP_MV).min(7) as u8;
+        pwr.cr.modify(|_, w| unsafe { w.pls().bits(level) }.pvde().set_bit());
+        // Only the status register is read, so it is safe to share `PWR` (Stop mode needs it)
+        SupplyMonitor { pwr: unsafe { &*PWR::ptr() } }
+    }
+
+    /// Is supply voltage below the threshold?
+    pub fn is_low(&self) -> bool {
+        self.pwr.csr.read().pvdo().bit_is_set()
+    }
+}
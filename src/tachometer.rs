@@ -0,0 +1,226 @@
+//! Tachometer: speed of a shaft measured by a hall (or opto) sensor, which gives the configured
+//! number of pulses per revolution (see `pulse_input` for the EXTI side), and the peak speed.
+//!
+//! Speed is measured between pulses, not over a fixed window: it is the pulses counted since the
+//! reference pulse over the time between them, once at least `GATE_MS` has passed. So the gate
+//! time ranges itself from `GATE_MS` at high speeds up to a single pulse interval at low ones, and
+//! is always a whole number of pulses. With no pulse for `TIMEOUT_MS`, the shaft is stopped. The
+//! speed shown ranges itself, too: two decimals below 100 rpm, one below 1000 rpm, none above.
+//!
+//! Pulses per revolution are set on the settings screen. The peak speed is held until the long
+//! button press starts it over.
+
+use core::fmt::{self, Write};
+use crate::text::{self, Align};
+use crate::ui::Event;
+
+/// Shortest time the speed is measured over, in milliseconds.
+const GATE_MS: u32 = 500;
+/// Shaft is stopped if there is no pulse for that long, in milliseconds (10 rpm at one pulse per
+/// revolution is the slowest speed measured).
+const TIMEOUT_MS: u32 = 6000;
+/// Pulses per revolution range.
+const PULSES_PER_REV_MAX: u8 = 24;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Tachometer {
+    pulses_per_rev: u8,
+    /// Pulse count and time the speed is measured from: the reference pulse or, before the first
+    /// pulse (and once the shaft is stopped), the update which found no pulses.
+    reference: Option<(u32, u32)>,
+    /// Is the reference a pulse?
+    pulsed: bool,
+    /// Speed measured, in hundredths of rpm.
+    rpm: Option<u32>,
+    /// Highest speed measured, in hundredths of rpm.
+    peak: u32,
+}
+
+impl Default for Tachometer {
+    /// One pulse per revolution (a single magnet on the shaft).
+    fn default() -> Tachometer {
+        Tachometer { pulses_per_rev: 1, reference: None, pulsed: false, rpm: None, peak: 0 }
+    }
+}
+
+impl Tachometer {
+    /// Account the pulses counted so far (wrapping around) and the time of the last one (`now_ms`
+    /// and `last_pulse_ms` are the millisecond timer).
+    pub fn update(&mut self, now_ms: u32, pulses: u32, last_pulse_ms: u32) {
+        let Some((count, at_ms)) = self.reference else {
+            self.reference = Some((pulses, now_ms));
+            return;
+        };
+        if pulses == count {
+            if now_ms.wrapping_sub(at_ms) >= TIMEOUT_MS {
+                self.reference = Some((pulses, now_ms));
+                self.pulsed = false;
+                self.rpm = Some(0);
+            }
+            return;
+        }
+        if !self.pulsed {
+            // Speed is measured from the next pulse on
+            self.reference = Some((pulses, last_pulse_ms));
+            self.pulsed = true;
+            return;
+        }
+        let span_ms = last_pulse_ms.wrapping_sub(at_ms);
+        if span_ms < GATE_MS {
+            return;
+        }
+        // Hundredths of a pulse per minute
+        let rate = u64::from(pulses.wrapping_sub(count)) * 6_000_000;
+        let rpm = (rate / (u64::from(span_ms) * u64::from(self.pulses_per_rev))) as u32;
+        self.rpm = Some(rpm);
+        self.peak = self.peak.max(rpm);
+        self.reference = Some((pulses, last_pulse_ms));
+    }
+
+    /// Speed measured, in hundredths of rpm (`None` until measured).
+    pub fn rpm(&self) -> Option<u32> {
+        self.rpm
+    }
+
+    /// Highest speed measured, in hundredths of rpm.
+    pub fn peak(&self) -> u32 {
+        self.peak
+    }
+
+    pub fn pulses_per_rev(&self) -> u8 {
+        self.pulses_per_rev
+    }
+
+    /// Change the pulses per revolution by `step`. Speed is measured anew (from the next pulse).
+    pub fn adjust_pulses_per_rev(&mut self, step: i8) {
+        self.pulses_per_rev = self.pulses_per_rev.saturating_add_signed(step).clamp(1, PULSES_PER_REV_MAX);
+        self.pulsed = false;
+        self.rpm = None;
+        self.peak = 0;
+    }
+
+    /// Long press starts the peak speed over. Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        if event != Event::LongPress {
+            return false;
+        }
+        self.peak = self.rpm.unwrap_or(0);
+        true
+    }
+
+    /// Write the speed (`Tach   1234 rpm`).
+    pub fn write_speed<W: Write>(&self, w: &mut W) -> fmt::Result {
+        w.write_str("Tach")?;
+        match self.rpm {
+            Some(rpm) => write_rpm(w, rpm, 7)?,
+            None => text::str(w, "-", 7, Align::Right)?,
+        }
+        w.write_str(" rpm")
+    }
+
+    /// Write the peak speed (`Peak   4321 rpm`).
+    pub fn write_peak<W: Write>(&self, w: &mut W) -> fmt::Result {
+        w.write_str("Peak")?;
+        write_rpm(w, self.peak, 7)?;
+        w.write_str(" rpm")
+    }
+}
+
+/// Write the speed given in hundredths of rpm, with two decimals below 100 rpm, one below 1000 rpm
+/// and none above, right-aligned to `width` characters.
+fn write_rpm<W: Write>(w: &mut W, rpm: u32, width: usize) -> fmt::Result {
+    let decimals = match rpm {
+        0..=9_999 => 2,
+        10_000..=99_999 => 1,
+        _ => 0,
+    };
+    let milli = i32::try_from(u64::from(rpm) * 10).unwrap_or(i32::MAX);
+    text::fixed(w, milli, decimals, width)
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn tachometer() {
+        use crate::mock;
+        use crate::screens::{self, Preference, Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        let mut settings = Settings::default();
+        let render = |screen: Screen, settings: &Settings| mock::render(screen, &Stats::default(), settings);
+        assert_eq!(render(Screen::Tachometer, &settings), ("Tach      - rpm ".into(), "Peak   0.00 rpm ".into()));
+
+        // Stopped shaft is only known after the timeout
+        let tach = &mut settings.tachometer;
+        tach.update(1000, 0, 0);
+        tach.update(6900, 0, 0);
+        assert_eq!(tach.rpm(), None);
+        tach.update(7000, 0, 0);
+        assert_eq!(tach.rpm(), Some(0));
+
+        // First pulse is the reference, speed is measured once the gate time has passed (three
+        // pulses in 600ms at one pulse per revolution is 300 rpm)
+        tach.update(7100, 1, 7050);
+        assert_eq!(tach.rpm(), Some(0));
+        tach.update(7200, 2, 7250);
+        assert_eq!(tach.rpm(), Some(0));
+        tach.update(7700, 4, 7650);
+        assert_eq!(tach.rpm(), Some(30000));
+        assert_eq!(render(Screen::Tachometer, &settings), ("Tach  300.0 rpm ".into(), "Peak  300.0 rpm ".into()));
+
+        // Display ranges itself, peak is held
+        let tach = &mut settings.tachometer;
+        tach.update(8200, 24, 8150);
+        assert_eq!(render(Screen::Tachometer, &settings), ("Tach   2400 rpm ".into(), "Peak   2400 rpm ".into()));
+        let tach = &mut settings.tachometer;
+        tach.update(16000, 25, 15650);
+        assert_eq!(tach.rpm(), Some(800));
+        assert_eq!(render(Screen::Tachometer, &settings), ("Tach   8.00 rpm ".into(), "Peak   2400 rpm ".into()));
+
+        // Long press starts the peak over, button is left for the navigation
+        assert!(Screen::Tachometer.long_press());
+        assert!(settings.handle(Screen::Tachometer, Event::LongPress));
+        assert_eq!(settings.tachometer.peak(), 800);
+        assert!(!settings.handle(Screen::Tachometer, Event::Button));
+
+        // No pulses for the timeout is a stop, the next pulse is the reference again
+        let tach = &mut settings.tachometer;
+        tach.update(22000, 25, 15650);
+        assert_eq!(tach.rpm(), Some(0));
+        tach.update(22100, 26, 22050);
+        tach.update(22700, 28, 22650);
+        assert_eq!(tach.rpm(), Some(20000));
+
+        // Pulses per revolution are set on the settings screen (second preference), speed is
+        // measured anew
+        assert_eq!(render(Screen::Settings, &settings).1, "Temp unit      C");
+        assert!(settings.handle(Screen::Settings, Event::Button));
+        assert_eq!(settings.preference, Preference::PulsesPerRev);
+        assert!(settings.handle(Screen::Settings, Event::EncoderUp));
+        assert!(settings.handle(Screen::Settings, Event::EncoderDown));
+        assert!(settings.handle(Screen::Settings, Event::EncoderDown));
+        assert!(settings.handle(Screen::Settings, Event::EncoderUp));
+        assert_eq!(render(Screen::Settings, &settings).1, "Pulses/rev     2");
+        assert_eq!(settings.tachometer.rpm(), None);
+        let tach = &mut settings.tachometer;
+        tach.update(22800, 29, 22750);
+        tach.update(23400, 31, 23350);
+        assert_eq!(tach.rpm(), Some(10000));
+        #[cfg(feature = "speedometer")]
+        assert!(settings.handle(Screen::Settings, Event::Button));
+        #[cfg(feature = "backlight")]
+        assert!(settings.handle(Screen::Settings, Event::Button));
+        #[cfg(feature = "day-night")]
+        for _ in 0..6 {
+            assert!(settings.handle(Screen::Settings, Event::Button));
+        }
+        #[cfg(feature = "battery")]
+        assert!(settings.handle(Screen::Settings, Event::Button));
+        assert!(!settings.handle(Screen::Settings, Event::Button));
+        assert_eq!(settings.preference, Preference::TempUnit);
+
+        let mut ui = screens::navigation();
+        ui.handle(Event::Serial(b'*'), &settings);
+        assert_eq!(ui.state(), Screen::Tachometer);
+    }
+}
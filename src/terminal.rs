@@ -0,0 +1,273 @@
+//! Serial terminal: a VT100 subset on USART1, so `echo`, shells and simple CLI tools could write to
+//! the display (see `remote`, this is the serial bridge with the terminal control codes instead of
+//! the HD44780 instructions).
+//!
+//! Terminal screen is `LINES` lines of `COLUMNS` characters; the display is a window of two of them,
+//! which follows the cursor. Text wraps at the end of the line, and line feed on the last line
+//! scrolls the screen up. Supported are:
+//!
+//! * printable ASCII (other characters are shown as `?`), carriage return, line feed (which returns
+//!   the carriage too, like the `onlcr` translation of the tty does), backspace (moves the cursor
+//!   left, so `\b \b` erases) and tab (to the next multiple of 8 columns);
+//! * `ESC [ row ; col H` (or `f`), cursor position, and `ESC [ n A` (`B`, `C`, `D`), cursor up (down,
+//!   forward, back);
+//! * `ESC [ n J`, erase in display, and `ESC [ n K`, erase in line: from the cursor (0), up to it (1)
+//!   or all (2);
+//! * `ESC c`, reset.
+//!
+//! Other escape sequences (colors, modes) are parsed and ignored, other control characters are
+//! ignored.
+
+use crate::display::{COLUMNS, ROWS};
+use crate::remote::RemoteText;
+
+/// Lines of the terminal screen (as many as VT100 has).
+pub const LINES: usize = 24;
+/// Tab stops are every that many columns.
+const TAB: usize = 8;
+/// Parameters of a control sequence, more are ignored.
+const PARAMS: usize = 2;
+
+const ESC: u8 = 0x1b;
+const BACKSPACE: u8 = 0x08;
+const TAB_CHAR: u8 = 0x09;
+const LINE_FEED: u8 = 0x0a;
+const CARRIAGE_RETURN: u8 = 0x0d;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    Ground,
+    /// After `ESC`.
+    Escape,
+    /// After `ESC [`, parameters being received.
+    Csi,
+}
+
+pub struct Terminal {
+    lines: [[u8; COLUMNS]; LINES],
+    /// Cursor; column is `COLUMNS` after the last column is written (next character wraps).
+    row: usize,
+    col: usize,
+    /// First line of the window shown.
+    top: usize,
+    state: State,
+    params: [u16; PARAMS],
+    /// Parameter being received.
+    param: usize,
+    /// Screen has changed since the last `show`.
+    changed: bool,
+}
+
+impl Terminal {
+    pub const fn new() -> Terminal {
+        Terminal {
+            lines: [[b' '; COLUMNS]; LINES],
+            row: 0,
+            col: 0,
+            top: 0,
+            state: State::Ground,
+            params: [0; PARAMS],
+            param: 0,
+            changed: false,
+        }
+    }
+
+    /// Cursor line and column.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.row, self.col.min(COLUMNS - 1))
+    }
+
+    /// Contents of the given line of the terminal screen.
+    pub fn line(&self, line: usize) -> &str {
+        // Only printable ASCII is stored
+        unsafe { core::str::from_utf8_unchecked(&self.lines[line]) }
+    }
+
+    /// Byte received over serial.
+    pub fn receive(&mut self, byte: u8) {
+        self.changed = true;
+        match (self.state, byte) {
+            (_, ESC) => self.state = State::Escape,
+            (State::Escape, b'[') => {
+                self.state = State::Csi;
+                self.params = [0; PARAMS];
+                self.param = 0;
+            }
+            (State::Escape, b'c') => {
+                *self = Terminal::new();
+                self.changed = true;
+            }
+            (State::Escape, _) => self.state = State::Ground,
+            (State::Csi, b'0'..=b'9') => {
+                if let Some(param) = self.params.get_mut(self.param) {
+                    *param = param.saturating_mul(10).saturating_add(u16::from(byte - b'0'));
+                }
+            }
+            (State::Csi, b';') => self.param += 1,
+            // Private mode marker and intermediate bytes
+            (State::Csi, 0x20..=0x3f) => {}
+            (State::Csi, 0x40..=0x7e) => {
+                self.state = State::Ground;
+                self.control(byte);
+            }
+            (State::Csi, _) => {
+                self.state = State::Ground;
+                self.receive(byte);
+            }
+            (State::Ground, 0x20..=0x7e) => self.print(byte),
+            (State::Ground, 0x80..=0xff) => self.print(b'?'),
+            (State::Ground, CARRIAGE_RETURN) => self.col = 0,
+            (State::Ground, LINE_FEED) => self.new_line(),
+            (State::Ground, BACKSPACE) => self.col = self.col.min(COLUMNS - 1).saturating_sub(1),
+            (State::Ground, TAB_CHAR) => self.col = ((self.col / TAB + 1) * TAB).min(COLUMNS - 1),
+            (State::Ground, _) => {}
+        }
+        self.follow();
+    }
+
+    /// Parameter of the control sequence, `default` if it is missing (or zero).
+    fn param(&self, index: usize, default: usize) -> usize {
+        match self.params[index] {
+            0 => default,
+            param => usize::from(param),
+        }
+    }
+
+    /// Run the control sequence ending with the `command`.
+    fn control(&mut self, command: u8) {
+        let n = self.param(0, 1);
+        let col = self.col.min(COLUMNS - 1);
+        match command {
+            b'H' | b'f' => {
+                self.row = n.min(LINES) - 1;
+                self.col = self.param(1, 1).min(COLUMNS) - 1;
+            }
+            b'A' => self.row = self.row.saturating_sub(n),
+            b'B' => self.row = (self.row + n).min(LINES - 1),
+            b'C' => self.col = (col + n).min(COLUMNS - 1),
+            b'D' => self.col = col.saturating_sub(n),
+            b'J' => match self.params[0] {
+                0 => {
+                    self.lines[self.row][col..].fill(b' ');
+                    self.lines[self.row + 1..].iter_mut().for_each(|line| line.fill(b' '));
+                }
+                1 => {
+                    self.lines[..self.row].iter_mut().for_each(|line| line.fill(b' '));
+                    self.lines[self.row][..=col].fill(b' ');
+                }
+                _ => self.lines.iter_mut().for_each(|line| line.fill(b' ')),
+            },
+            b'K' => match self.params[0] {
+                0 => self.lines[self.row][col..].fill(b' '),
+                1 => self.lines[self.row][..=col].fill(b' '),
+                _ => self.lines[self.row].fill(b' '),
+            },
+            _ => {}
+        }
+    }
+
+    /// Write the character at the cursor, wrapping to the next line past the last column.
+    fn print(&mut self, byte: u8) {
+        if self.col >= COLUMNS {
+            self.new_line();
+        }
+        self.lines[self.row][self.col] = byte;
+        self.col += 1;
+    }
+
+    /// Move to the start of the next line, scrolling the screen up on the last one.
+    fn new_line(&mut self) {
+        self.col = 0;
+        if self.row + 1 < LINES {
+            self.row += 1;
+        } else {
+            self.lines.copy_within(1.., 0);
+            self.lines[LINES - 1] = [b' '; COLUMNS];
+        }
+    }
+
+    /// Scroll the window so the cursor line is in it.
+    fn follow(&mut self) {
+        self.top = self.top.clamp((self.row + 1).saturating_sub(ROWS), self.row);
+    }
+
+    /// Copy the window to the remote text, if the screen has changed since the last call. Returns
+    /// `true` if it has.
+    pub fn show(&mut self, text: &mut RemoteText) -> bool {
+        if !core::mem::replace(&mut self.changed, false) {
+            return false;
+        }
+        for row in 0..ROWS {
+            text.set_cursor(if row == 0 { 0x00 } else { 0x40 });
+            self.lines[self.top + row].iter().for_each(|&byte| text.write(byte));
+        }
+        true
+    }
+}
+
+impl Default for Terminal {
+    fn default() -> Terminal {
+        Terminal::new()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn terminal_emulation() {
+        use crate::remote::RemoteText;
+        use crate::terminal::{Terminal, LINES};
+
+        let mut terminal = Terminal::new();
+        let mut text = RemoteText::new();
+        let feed = |terminal: &mut Terminal, bytes: &[u8]| bytes.iter().for_each(|&byte| terminal.receive(byte));
+        assert!(!terminal.show(&mut text));
+        assert!(!text.is_active());
+
+        // Line feed returns the carriage too, long lines wrap, window follows the cursor
+        feed(&mut terminal, b"$ echo hi\nhi\n");
+        assert!(terminal.show(&mut text));
+        assert!(text.is_active());
+        assert!(!terminal.show(&mut text));
+        assert_eq!((text.row(0), text.row(1)), ("hi              ", "                "));
+        assert_eq!(terminal.line(0), "$ echo hi       ");
+        feed(&mut terminal, b"0123456789abcdefXY\r\n");
+        terminal.show(&mut text);
+        assert_eq!((text.row(0), text.row(1)), ("XY              ", "                "));
+        assert_eq!((terminal.line(2), terminal.cursor()), ("0123456789abcdef", (4, 0)));
+
+        // Backspace, carriage return and tab; other control codes, colors and modes are skipped
+        feed(&mut terminal, b"abc\x08\x08 \rX\x07\tY\x1b[1;31mR\x1b[0m\x1b[?25l");
+        terminal.show(&mut text);
+        assert_eq!(text.row(1), "X c     YR      ");
+        assert_eq!(terminal.cursor(), (4, 10));
+
+        // Cursor positioning (one-based) and moves, clamped to the screen
+        feed(&mut terminal, b"\x1b[2;5H*\x1b[B\x1b[3D+\x1b[A\x1b[99C!\x1b[99;99H$\x1b[H@");
+        assert_eq!(terminal.line(1), "hi  *          !");
+        assert_eq!(terminal.line(2), "01+3456789abcdef");
+        assert_eq!(terminal.line(LINES - 1), "               $");
+        assert_eq!(terminal.cursor(), (0, 1));
+        terminal.show(&mut text);
+        assert_eq!((text.row(0), text.row(1)), ("@ echo hi       ", "hi  *          !"));
+
+        // Erase in line and in display
+        feed(&mut terminal, b"\x1b[3;5H\x1b[K\x1b[2;3H\x1b[1K");
+        assert_eq!((terminal.line(1), terminal.line(2)), ("    *          !", "01+3            "));
+        feed(&mut terminal, b"\x1b[2;2H\x1b[J");
+        assert_eq!(terminal.line(0), "@ echo hi       ");
+        assert!((1..LINES).all(|line| terminal.line(line) == " ".repeat(16)));
+
+        // Line feed on the last line scrolls the screen up
+        feed(&mut terminal, b"\x1b[24;1Hlast\nnew");
+        assert_eq!((terminal.line(0), terminal.line(LINES - 2)), ("                ", "last            "));
+        terminal.show(&mut text);
+        assert_eq!((text.row(0), text.row(1)), ("last            ", "new             "));
+
+        // Reset clears the screen
+        feed(&mut terminal, b"\x1bc");
+        assert_eq!(terminal.cursor(), (0, 0));
+        assert!(terminal.show(&mut text));
+        assert_eq!((text.row(0), text.row(1)), ("                ", "                "));
+    }
+}
@@ -0,0 +1,138 @@
+//! Text helpers for the screens: padded strings and numbers. Numbers are formatted by the
+//! [`fmt`](crate::fmt) module.
+
+use core::fmt::{self, Write};
+use crate::big::FULL;
+use crate::fmt::{write_binary, write_centis, write_duration, write_fixed, write_hex, write_si, write_uint};
+
+/// Alignment of the text within the field.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// Fixed-size buffer for a formatted number (fits the longest duration, `49710d 06:28:15`).
+struct Buf {
+    bytes: [u8; 16],
+    len: usize,
+}
+
+impl Buf {
+    fn new() -> Buf {
+        Buf { bytes: [0; 16], len: 0 }
+    }
+
+    fn as_str(&self) -> &str {
+        // Only whole `str`s and ASCII bytes are ever written into the buffer
+        unsafe { core::str::from_utf8_unchecked(&self.bytes[..self.len]) }
+    }
+}
+
+impl Write for Buf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.bytes.len() {
+            return Err(fmt::Error);
+        }
+        self.bytes[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Write `count` spaces.
+fn spaces<W: Write>(w: &mut W, count: usize) -> fmt::Result {
+    for _ in 0..count {
+        w.write_char(' ')?;
+    }
+    Ok(())
+}
+
+/// Write `s` padded with spaces to `width` characters. Longer strings are written as is. Text is
+/// assumed to be ASCII (display character set is ASCII-based anyway).
+pub fn str<W: Write>(w: &mut W, s: &str, width: usize, align: Align) -> fmt::Result {
+    let pad = width.saturating_sub(s.len());
+    if align == Align::Right {
+        spaces(w, pad)?;
+    }
+    w.write_str(s)?;
+    if align == Align::Left {
+        spaces(w, pad)?;
+    }
+    Ok(())
+}
+
+/// Write unsigned integer, right-aligned to `width` characters.
+pub fn uint<W: Write>(w: &mut W, value: u32, width: usize) -> fmt::Result {
+    let mut buf = Buf::new();
+    write_uint(&mut buf, value)?;
+    str(w, buf.as_str(), width, Align::Right)
+}
+
+/// Write fixed-point number given in thousandths with `decimals` digits after the decimal point
+/// (`3.27`, `-12.5`), right-aligned to `width` characters.
+pub fn fixed<W: Write>(w: &mut W, value_milli: i32, decimals: u8, width: usize) -> fmt::Result {
+    let mut buf = Buf::new();
+    write_fixed(&mut buf, value_milli, decimals)?;
+    str(w, buf.as_str(), width, Align::Right)
+}
+
+/// Write value scaled with SI prefix (`12.3MHz`, `3.27V`, `150uA`), right-aligned to `width`
+/// characters. See [`write_si`].
+pub fn si<W: Write>(w: &mut W, value: i32, scale: i8, unit: &str, width: usize) -> fmt::Result {
+    let mut buf = Buf::new();
+    write_si(&mut buf, value, scale, unit)?;
+    str(w, buf.as_str(), width, Align::Right)
+}
+
+/// Write duration given in seconds (`1d 03:25:17`), right-aligned to `width` characters.
+pub fn duration<W: Write>(w: &mut W, seconds: u32, width: usize) -> fmt::Result {
+    let mut buf = Buf::new();
+    write_duration(&mut buf, seconds)?;
+    str(w, buf.as_str(), width, Align::Right)
+}
+
+/// Write time given in milliseconds (`12:05.27`), right-aligned to `width` characters.
+pub fn centis<W: Write>(w: &mut W, millis: u32, width: usize) -> fmt::Result {
+    let mut buf = Buf::new();
+    write_centis(&mut buf, millis)?;
+    str(w, buf.as_str(), width, Align::Right)
+}
+
+/// Write lowercase hexadecimal number, zero-padded to `digits` digits.
+pub fn hex<W: Write>(w: &mut W, value: u32, digits: usize) -> fmt::Result {
+    let mut buf = Buf::new();
+    write_hex(&mut buf, value, digits)?;
+    w.write_str(buf.as_str())
+}
+
+/// Write byte as two hexadecimal digits.
+pub fn hex8<W: Write>(w: &mut W, value: u8) -> fmt::Result {
+    hex(w, u32::from(value), 2)
+}
+
+/// Write half-word as four hexadecimal digits.
+pub fn hex16<W: Write>(w: &mut W, value: u16) -> fmt::Result {
+    hex(w, u32::from(value), 4)
+}
+
+/// Write word as eight hexadecimal digits.
+pub fn hex32<W: Write>(w: &mut W, value: u32) -> fmt::Result {
+    hex(w, value, 8)
+}
+
+/// Write lowest `bits` bits of the value in binary, grouped by four bits (`1010 0011`).
+pub fn binary<W: Write>(w: &mut W, value: u32, bits: u8) -> fmt::Result {
+    write_binary(w, value, bits)
+}
+
+/// Write a progress bar `width` characters wide: `value` out of `max` cells are full blocks, the
+/// rest are dashes.
+pub fn bar<W: Write>(w: &mut W, value: u32, max: u32, width: usize) -> fmt::Result {
+    let full = if max == 0 { width } else { (u64::from(value.min(max)) * width as u64 / u64::from(max)) as usize };
+    for cell in 0..width {
+        w.write_char(if cell < full { FULL } else { '-' })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,232 @@
+//! Thermostat: switches the heater relay by the chip temperature, with hysteresis, and keeps the
+//! statistics of the temperature (lowest, highest, mean and deviation, see `statistics`).
+//!
+//! Heater is turned on below `setpoint - hysteresis` and off above `setpoint + hysteresis`, in
+//! between it is left as is (so it does not chatter around the setpoint).
+//!
+//! Button goes through the pages: setpoint and hysteresis, then the lowest and the highest
+//! temperature. On the first page, turning the encoder starts editing the setpoint, button moves to
+//! the hysteresis and then finishes editing (the way the countdown time is edited). On the second
+//! page, encoder starts the statistics over, and so does the long press on any page.
+
+use core::fmt::{self, Write};
+use crate::statistics::Accumulator;
+use crate::text::{self, Align};
+use crate::ui::Event;
+use crate::units::TempUnit;
+
+/// Setpoint range and step, in tenths of °C.
+const SETPOINT_MIN: i16 = 0;
+const SETPOINT_MAX: i16 = 990;
+const SETPOINT_STEP: i16 = 5;
+/// Hysteresis range, in tenths of °C (step is 0.1°C).
+const HYSTERESIS_MIN: i16 = 1;
+const HYSTERESIS_MAX: i16 = 50;
+
+/// Field being edited.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Field {
+    Setpoint,
+    Hysteresis,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Thermostat {
+    /// Temperature to keep, in tenths of °C.
+    setpoint: i16,
+    /// Half of the band the temperature is kept in, in tenths of °C.
+    hysteresis: i16,
+    heating: bool,
+    /// Last temperature, in tenths of °C.
+    temperature: Option<i16>,
+    /// Temperature since boot (or since the statistics were started over).
+    stats: Accumulator,
+    /// Page shown in the second row: setpoint (0) or the temperature range (1).
+    page: usize,
+    cursor: Option<Field>,
+}
+
+impl Default for Thermostat {
+    /// 25°C, +-0.5°C.
+    fn default() -> Thermostat {
+        Thermostat {
+            setpoint: 250,
+            hysteresis: 5,
+            heating: false,
+            temperature: None,
+            stats: Accumulator::default(),
+            page: 0,
+            cursor: None,
+        }
+    }
+}
+
+impl Thermostat {
+    /// Account the new temperature reading (in tenths of °C) and decide if the heater should be on.
+    pub fn update(&mut self, temperature: i16) {
+        self.temperature = Some(temperature);
+        self.stats.push(temperature);
+        if temperature < self.setpoint - self.hysteresis {
+            self.heating = true;
+        } else if temperature > self.setpoint + self.hysteresis {
+            self.heating = false;
+        }
+    }
+
+    /// Should the heater relay be on?
+    pub fn is_heating(&self) -> bool {
+        self.heating
+    }
+
+    /// Lowest and highest temperature seen, in tenths of °C.
+    pub fn range(&self) -> Option<(i16, i16)> {
+        self.stats.range()
+    }
+
+    /// Start the statistics over, from the last temperature.
+    fn restart(&mut self) {
+        self.stats.reset();
+        if let Some(temperature) = self.temperature {
+            self.stats.push(temperature);
+        }
+    }
+
+    /// Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        let step = match (event, self.cursor) {
+            (Event::Button, Some(Field::Setpoint)) => {
+                self.cursor = Some(Field::Hysteresis);
+                return true;
+            }
+            (Event::Button, Some(Field::Hysteresis)) => {
+                self.cursor = None;
+                return true;
+            }
+            (Event::Button, None) if self.page == 0 => {
+                self.page = 1;
+                return true;
+            }
+            (Event::Button, None) => {
+                self.page = 0;
+                return false;
+            }
+            (Event::EncoderUp | Event::EncoderDown, _) if self.page == 1 => {
+                self.restart();
+                return true;
+            }
+            (Event::LongPress, _) => {
+                self.restart();
+                return true;
+            }
+            (Event::EncoderUp, _) => 1,
+            (Event::EncoderDown, _) => -1,
+            _ => return false,
+        };
+        match *self.cursor.get_or_insert(Field::Setpoint) {
+            Field::Setpoint => self.setpoint = (self.setpoint + step * SETPOINT_STEP).clamp(SETPOINT_MIN, SETPOINT_MAX),
+            Field::Hysteresis => self.hysteresis = (self.hysteresis + step).clamp(HYSTERESIS_MIN, HYSTERESIS_MAX),
+        }
+        true
+    }
+
+    /// Write the heater state and the temperature (`Heat  23.5C`), or, on the second page, the mean
+    /// temperature and its standard deviation (`Avg 23.1 sd 0.4`).
+    pub fn write_status<W: Write>(&self, w: &mut W, unit: TempUnit) -> fmt::Result {
+        if let (1, Some(mean), Some(stddev)) = (self.page, self.stats.mean(), self.stats.stddev()) {
+            w.write_str("Avg")?;
+            text::fixed(w, unit.from_tenths_c(mean), 1, 5)?;
+            w.write_str(" sd")?;
+            return text::fixed(w, unit.delta_from_tenths_c(stddev), 1, 4);
+        }
+        text::str(w, if self.heating { "Heat" } else { "Idle" }, 5, Align::Left)?;
+        if let Some(temperature) = self.temperature {
+            text::fixed(w, unit.from_tenths_c(temperature), 1, 5)?;
+            w.write_str(unit.symbol())?;
+        }
+        Ok(())
+    }
+
+    /// Write the page shown: setpoint and hysteresis, with the field being edited in brackets
+    /// (`Set[ 25.0]H 0.5`), or the lowest and the highest temperature (`Lo 21.0 Hi 25.3C`).
+    pub fn write_page<W: Write>(&self, w: &mut W, unit: TempUnit) -> fmt::Result {
+        if self.page == 0 {
+            w.write_str("Set")?;
+            write_field(w, unit.from_tenths_c(self.setpoint), 5, self.cursor == Some(Field::Setpoint))?;
+            w.write_char('H')?;
+            return write_field(w, unit.delta_from_tenths_c(self.hysteresis), 3, self.cursor == Some(Field::Hysteresis));
+        }
+        let Some((low, high)) = self.range() else {
+            return Ok(());
+        };
+        w.write_str("Lo")?;
+        text::fixed(w, unit.from_tenths_c(low), 1, 5)?;
+        w.write_str(" Hi")?;
+        text::fixed(w, unit.from_tenths_c(high), 1, 5)?;
+        w.write_str(unit.symbol())
+    }
+}
+
+/// Write a temperature given in thousandths, in brackets if it is being edited (spaces otherwise,
+/// so the text does not move).
+fn write_field<W: Write>(w: &mut W, value_milli: i32, width: usize, edited: bool) -> fmt::Result {
+    w.write_char(if edited { '[' } else { ' ' })?;
+    text::fixed(w, value_milli, 1, width)?;
+    w.write_char(if edited { ']' } else { ' ' })
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn thermostat_hysteresis() {
+        use crate::mock;
+        use crate::screens::{Screen, Settings, Stats};
+        use crate::ui::Event;
+        use crate::units::TempUnit;
+
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::Thermostat, &Stats::default(), settings);
+        assert_eq!(render(&settings), ("Idle            ".into(), "Set  25.0 H 0.5 ".into()));
+
+        // Heater stays on until the temperature is above the band, and off until it is below
+        let update = |settings: &mut Settings, temperature: i16| {
+            settings.thermostat.update(temperature);
+            settings.thermostat.is_heating()
+        };
+        assert!(update(&mut settings, 240));
+        assert_eq!(render(&settings).0, "Heat  24.0C     ");
+        assert!(update(&mut settings, 255));
+        assert!(!update(&mut settings, 256));
+        assert!(!update(&mut settings, 245));
+        assert!(update(&mut settings, 244));
+        assert!(update(&mut settings, 252));
+
+        // Setpoint goes in half-degree steps, hysteresis in tenths (down to 0.1)
+        assert!(settings.handle(Screen::Thermostat, Event::EncoderUp));
+        assert_eq!(render(&settings).1, "Set[ 25.5]H 0.5 ");
+        assert!(settings.handle(Screen::Thermostat, Event::Button));
+        for _ in 0..10 {
+            assert!(settings.handle(Screen::Thermostat, Event::EncoderDown));
+        }
+        assert_eq!(render(&settings).1, "Set  25.5 H[0.1]");
+        for _ in 0..9 {
+            assert!(settings.handle(Screen::Thermostat, Event::EncoderUp));
+        }
+        assert!(settings.handle(Screen::Thermostat, Event::Button));
+        assert_eq!(render(&settings).1, "Set  25.5 H 1.0 ");
+        settings.temp_unit = TempUnit::Fahrenheit;
+        assert_eq!(render(&settings).1, "Set  77.9 H 1.8 ");
+        settings.temp_unit = TempUnit::Celsius;
+        assert!(update(&mut settings, 265));
+        assert!(!update(&mut settings, 266));
+
+        // Second page is the temperature range, encoder starts it over
+        assert!(settings.handle(Screen::Thermostat, Event::Button));
+        assert_eq!(render(&settings).1, "Lo 24.0 Hi 26.6C");
+        assert!(settings.handle(Screen::Thermostat, Event::EncoderDown));
+        assert_eq!(settings.thermostat.range(), Some((266, 266)));
+        update(&mut settings, 250);
+        assert_eq!(render(&settings).1, "Lo 25.0 Hi 26.6C");
+        assert!(!settings.handle(Screen::Thermostat, Event::Button));
+        assert_eq!(render(&settings).1, "Set  25.5 H 1.0 ");
+    }
+}
@@ -0,0 +1,425 @@
+//! Alarm thresholds: low and high limits of the measured values (chip temperature, supply voltage
+//! and, with the `fan` feature, fan speed), set on the limits screen. Whoever takes the
+//! measurement passes it to `check`.
+//!
+//! Value getting past a limit latches the alarm, which takes over the display on any screen and
+//! beeps until the button acknowledges it (see `handle_latched`). There is no backlight control on
+//! the board, so the display is "flashed" by filling both rows with the full character, like in
+//! the reaction game. Limit which stays violated does not latch the alarm again until the value
+//! gets back within the limits; one violated while another alarm is latched latches once that one
+//! is acknowledged. Alarm not acknowledged yet is kept in the backup registers (see `to_backup`),
+//! so a reset does not silence it.
+//!
+//! On the limits screen, button goes through the sources. Turning the encoder starts editing the
+//! low limit of the one shown, button moves to the high limit and then finishes editing (the way
+//! the thermostat setpoint is edited). Limit is turned off past the end of its range.
+//!
+//! Like the stopwatch, time is passed to `advance`, which must be called before every event and
+//! every render.
+
+use core::fmt::{self, Write};
+use crate::big;
+use crate::display::COLUMNS;
+use crate::text::{self, Align};
+use crate::ui::Event;
+use crate::units::TempUnit;
+
+/// Display flashes and the buzzer beeps with this period, in milliseconds.
+const FLASH_MS: u32 = 500;
+
+/// Measured values which can be limited.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// Chip temperature, in tenths of °C.
+    Temperature,
+    /// Supply voltage, in millivolts.
+    Supply,
+    /// Fan speed, in rpm.
+    #[cfg(feature = "fan")]
+    FanSpeed,
+}
+
+/// Number of sources.
+pub const SOURCES: usize = 2 + cfg!(feature = "fan") as usize;
+
+impl Source {
+    const ALL: [Source; SOURCES] = [
+        Source::Temperature,
+        Source::Supply,
+        #[cfg(feature = "fan")]
+        Source::FanSpeed,
+    ];
+
+    /// Source by its position (as in the enum), `None` if there is no such source.
+    pub fn from_index(index: usize) -> Option<Source> {
+        Source::ALL.get(index).copied()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Temperature => "Temp",
+            Source::Supply => "Vdd",
+            #[cfg(feature = "fan")]
+            Source::FanSpeed => "Fan",
+        }
+    }
+
+    /// Lowest and highest limit, and the step they are edited by.
+    fn range(self) -> (i32, i32, i32) {
+        match self {
+            Source::Temperature => (-400, 1250, 5),
+            Source::Supply => (2000, 3600, 50),
+            #[cfg(feature = "fan")]
+            Source::FanSpeed => (0, 9950, 50),
+        }
+    }
+
+    /// Write the value, right-aligned to `width` characters (without the unit).
+    fn write_value<W: Write>(self, w: &mut W, value: i32, unit: TempUnit, width: usize) -> fmt::Result {
+        match self {
+            Source::Temperature => text::fixed(w, unit.from_tenths_c(value as i16), 1, width),
+            Source::Supply => text::fixed(w, value, 2, width),
+            #[cfg(feature = "fan")]
+            Source::FanSpeed => text::uint(w, value as u32, width),
+        }
+    }
+
+    fn symbol(self, unit: TempUnit) -> &'static str {
+        match self {
+            Source::Temperature => unit.symbol(),
+            Source::Supply => "V",
+            #[cfg(feature = "fan")]
+            Source::FanSpeed => "",
+        }
+    }
+}
+
+/// Low or high limit.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Low,
+    High,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Limit {
+    /// Limits, `None` if turned off.
+    low: Option<i32>,
+    high: Option<i32>,
+    /// Last value checked.
+    value: Option<i32>,
+    /// Value is past a limit, and the alarm was latched for it.
+    violated: bool,
+}
+
+impl Limit {
+    const OFF: Limit = Limit { low: None, high: None, value: None, violated: false };
+}
+
+/// Latched alarm: the value which got past the limit.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Alert {
+    pub source: Source,
+    pub bound: Bound,
+    pub value: i32,
+    pub limit: i32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Thresholds {
+    limits: [Limit; SOURCES],
+    latched: Option<Alert>,
+    /// How long the alarm has been latched for, in milliseconds.
+    latched_ms: u32,
+    /// Timer value of the last `advance`.
+    last_ms: u32,
+    /// Source shown on the limits screen.
+    page: usize,
+    cursor: Option<Bound>,
+}
+
+impl Default for Thresholds {
+    /// Chip temperature above 60°C, other limits are off.
+    fn default() -> Thresholds {
+        let mut limits = [Limit::OFF; SOURCES];
+        limits[Source::Temperature as usize].high = Some(600);
+        Thresholds {
+            limits,
+            latched: None,
+            latched_ms: 0,
+            last_ms: 0,
+            page: 0,
+            cursor: None,
+        }
+    }
+}
+
+impl Thresholds {
+    /// Account the time passed since the last call (`now_ms` is the millisecond timer, which can
+    /// wrap around).
+    pub fn advance(&mut self, now_ms: u32) {
+        if self.latched.is_some() {
+            self.latched_ms = self.latched_ms.saturating_add(now_ms.wrapping_sub(self.last_ms));
+        }
+        self.last_ms = now_ms;
+    }
+
+    /// Check the new value of the source against its limits. Returns `true` if the alarm was
+    /// latched (display should be refreshed right away then).
+    pub fn check(&mut self, source: Source, value: i32) -> bool {
+        let limit = &mut self.limits[source as usize];
+        limit.value = Some(value);
+        let violated = match (limit.low, limit.high) {
+            (Some(low), _) if value < low => Some((Bound::Low, low)),
+            (_, Some(high)) if value > high => Some((Bound::High, high)),
+            _ => None,
+        };
+        match violated {
+            None => limit.violated = false,
+            Some((bound, limit_value)) if !limit.violated && self.latched.is_none() => {
+                limit.violated = true;
+                self.latched = Some(Alert { source, bound, value, limit: limit_value });
+                self.latched_ms = 0;
+                return true;
+            }
+            // Latched already, or waiting for the other alarm to be acknowledged
+            Some(_) => {}
+        }
+        false
+    }
+
+    /// Alarm latched, if any.
+    pub fn latched(&self) -> Option<Alert> {
+        self.latched
+    }
+
+    /// Should the buzzer be on now?
+    pub fn buzzing(&self) -> bool {
+        self.latched.is_some() && self.flashed()
+    }
+
+    /// Is the display flashed now (in the second half of the period)?
+    fn flashed(&self) -> bool {
+        self.latched_ms % FLASH_MS >= FLASH_MS / 2
+    }
+
+    /// Button acknowledges the latched alarm, other events are swallowed while it is latched.
+    /// Returns `true` if the event was used.
+    pub fn handle_latched(&mut self, event: Event) -> bool {
+        if self.latched.is_none() {
+            return false;
+        }
+        if event == Event::Button {
+            self.latched = None;
+        }
+        true
+    }
+
+    /// Latched alarm, as kept in the backup registers (see `scratchpad::ALERT`): latched (bit 0),
+    /// the source (bits 1-2), high bound (bit 3), the value (16 bits, saturated) and the limit (9
+    /// bits, in steps from the lowest one). Zero if there is none.
+    pub fn to_backup(&self) -> u32 {
+        let Some(alert) = self.latched else {
+            return 0;
+        };
+        let (min, _, by) = alert.source.range();
+        let value = alert.value.clamp(i16::MIN.into(), i16::MAX.into()) as u16;
+        let step = ((alert.limit - min) / by) as u32;
+        1 | (alert.source as u32) << 1 | u32::from(alert.bound == Bound::High) << 3 | u32::from(value) << 4 | step << 20
+    }
+
+    /// Latch the alarm saved by `to_backup` again (its limit counts as violated, so it is not
+    /// latched twice). Nothing is latched if there was none, or it is not valid.
+    pub fn set_backup(&mut self, saved: u32) {
+        let source = Source::from_index((saved >> 1 & 0b11) as usize);
+        let (Some(source), true) = (source, saved & 1 != 0) else {
+            return;
+        };
+        let (min, max, by) = source.range();
+        let limit = min + (saved >> 20 & 0x1ff) as i32 * by;
+        if limit > max {
+            return;
+        }
+        let bound = if saved & 1 << 3 != 0 { Bound::High } else { Bound::Low };
+        let value = i32::from((saved >> 4) as u16 as i16);
+        self.limits[source as usize].violated = true;
+        self.latched = Some(Alert { source, bound, value, limit });
+        self.latched_ms = 0;
+    }
+
+    /// Limits screen: returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        let step = match (event, self.cursor) {
+            (Event::Button, Some(Bound::Low)) => {
+                self.cursor = Some(Bound::High);
+                return true;
+            }
+            (Event::Button, Some(Bound::High)) => {
+                self.cursor = None;
+                return true;
+            }
+            (Event::Button, None) if self.page + 1 < SOURCES => {
+                self.page += 1;
+                return true;
+            }
+            (Event::Button, None) => {
+                self.page = 0;
+                return false;
+            }
+            (Event::EncoderUp, _) => 1,
+            (Event::EncoderDown, _) => -1,
+            _ => return false,
+        };
+        let (min, max, by) = Source::ALL[self.page].range();
+        let limit = &mut self.limits[self.page];
+        match *self.cursor.get_or_insert(Bound::Low) {
+            Bound::Low => {
+                let high = limit.high.unwrap_or(max);
+                limit.low = match limit.low {
+                    None if step > 0 => Some(min),
+                    None => None,
+                    Some(low) if low + step * by < min => None,
+                    Some(low) => Some((low + step * by).min(high)),
+                };
+            }
+            Bound::High => {
+                let low = limit.low.unwrap_or(min);
+                limit.high = match limit.high {
+                    None if step < 0 => Some(max),
+                    None => None,
+                    Some(high) if high + step * by > max => None,
+                    Some(high) => Some((high + step * by).max(low)),
+                };
+            }
+        }
+        // New limits are checked against the value from scratch
+        limit.violated = false;
+        true
+    }
+
+    /// Write the source shown and its last value (`Temp      23.4C`).
+    pub fn write_status<W: Write>(&self, w: &mut W, unit: TempUnit) -> fmt::Result {
+        let source = Source::ALL[self.page];
+        text::str(w, source.name(), 5, Align::Left)?;
+        if let Some(value) = self.limits[self.page].value {
+            source.write_value(w, value, unit, 9)?;
+            w.write_str(source.symbol(unit))?;
+        }
+        Ok(())
+    }
+
+    /// Write the limits of the source shown, with the one being edited in brackets
+    /// (`L[-10.0]H  60.0 `).
+    pub fn write_limits<W: Write>(&self, w: &mut W, unit: TempUnit) -> fmt::Result {
+        let source = Source::ALL[self.page];
+        let limit = &self.limits[self.page];
+        for (name, bound, value) in [('L', Bound::Low, limit.low), ('H', Bound::High, limit.high)] {
+            let edited = self.cursor == Some(bound);
+            w.write_char(name)?;
+            w.write_char(if edited { '[' } else { ' ' })?;
+            match value {
+                Some(value) => source.write_value(w, value, unit, 5)?,
+                None => text::str(w, "off", 5, Align::Right)?,
+            }
+            w.write_char(if edited { ']' } else { ' ' })?;
+        }
+        Ok(())
+    }
+
+    /// Write one `row` (0 or 1) of the latched alarm: the source and the limit crossed
+    /// (`ALARM Temp high`), and the value against the limit (`  71.3C >  60.0C`). Both rows are
+    /// full while flashed.
+    pub fn write_alert<W: Write>(&self, w: &mut W, row: usize, unit: TempUnit) -> fmt::Result {
+        let Some(alert) = self.latched else {
+            return Ok(());
+        };
+        if self.flashed() {
+            return (0..COLUMNS).try_for_each(|_| w.write_char(big::FULL));
+        }
+        let source = alert.source;
+        if row == 0 {
+            w.write_str("ALARM ")?;
+            w.write_str(source.name())?;
+            return w.write_str(if alert.bound == Bound::Low { " low" } else { " high" });
+        }
+        source.write_value(w, alert.value, unit, 6)?;
+        text::str(w, source.symbol(unit), 1, Align::Left)?;
+        w.write_str(if alert.bound == Bound::Low { " <" } else { " >" })?;
+        source.write_value(w, alert.limit, unit, 6)?;
+        w.write_str(source.symbol(unit))
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn threshold_alarm() {
+        use crate::mock;
+        use crate::screens::{self, Screen, Settings, Stats};
+        use crate::threshold::{Bound, Source};
+        use crate::ui::Event;
+
+        let render = |screen: Screen, settings: &Settings| mock::render(screen, &Stats::default(), settings);
+        let mut settings = Settings::default();
+        settings.advance(0);
+        assert!(!settings.thresholds.check(Source::Temperature, 235));
+        assert!(!settings.thresholds.check(Source::Supply, 2500));
+        assert_eq!(render(Screen::Limits, &settings), ("Temp      23.5C ".into(), "L   off H  60.0 ".into()));
+
+        // Encoder edits the low limit, button moves to the high one, the limit is off past the end
+        // of the range
+        assert!(settings.handle(Screen::Limits, Event::Button));
+        assert!(settings.handle(Screen::Limits, Event::EncoderUp));
+        assert_eq!(render(Screen::Limits, &settings), ("Vdd       2.50V ".into(), "L[ 2.00]H   off ".into()));
+        for _ in 0..18 {
+            settings.handle(Screen::Limits, Event::EncoderUp);
+        }
+        assert!(settings.handle(Screen::Limits, Event::Button));
+        assert!(settings.handle(Screen::Limits, Event::EncoderDown));
+        assert_eq!(render(Screen::Limits, &settings).1, "L  2.90 H[ 3.60]");
+        assert!(settings.handle(Screen::Limits, Event::Button));
+        // Button leaves the screen after the last source
+        while settings.handle(Screen::Limits, Event::Button) {}
+        assert_eq!(render(Screen::Limits, &settings).0, "Temp      23.5C ");
+
+        // Value below the limit latches the alarm, shown on any screen, flashing and beeping
+        assert!(settings.thresholds.check(Source::Supply, 2750));
+        assert_eq!(settings.thresholds.latched().map(|alert| (alert.source, alert.bound)), Some((Source::Supply, Bound::Low)));
+        assert_eq!(render(Screen::Hello, &settings), ("ALARM Vdd low   ".into(), "  2.75V <  2.90V".into()));
+        assert!(!settings.buzzing());
+        settings.advance(300);
+        assert!(settings.buzzing());
+        assert_eq!(render(Screen::Hello, &settings), ("################".into(), "################".into()));
+        // Other alarms wait, the one still violated is not latched again
+        assert!(!settings.thresholds.check(Source::Temperature, 700));
+        assert!(!settings.thresholds.check(Source::Supply, 2700));
+
+        // Alarm takes all the events until the button acknowledges it
+        let mut ui = screens::navigation();
+        assert!(settings.handle(Screen::Hello, Event::EncoderUp));
+        assert!(settings.handle(Screen::Hello, Event::Serial(b's')));
+        assert!(settings.handle(Screen::Hello, Event::Button));
+        assert_eq!(ui.state(), Screen::Hello);
+        assert_eq!(render(Screen::Hello, &settings).0, "Hello!          ");
+        assert!(!settings.buzzing());
+        assert!(!settings.thresholds.check(Source::Supply, 2700));
+        assert!(settings.thresholds.check(Source::Temperature, 700));
+        assert!(settings.handle(Screen::Hello, Event::Button));
+        assert!(!settings.thresholds.check(Source::Temperature, 700));
+        // Back within the limits, and past them again
+        assert!(!settings.thresholds.check(Source::Temperature, 500));
+        assert!(settings.thresholds.check(Source::Temperature, 650));
+        assert!(settings.handle(Screen::Hello, Event::Button));
+
+        ui.handle(Event::Serial(b'e'), &settings);
+        ui.handle(Event::Button, &settings);
+        assert_eq!(ui.state(), Screen::Limits);
+        ui.handle(Event::EncoderUp, &settings);
+        assert_eq!(ui.state(), Screen::Pid);
+        ui.handle(Event::EncoderDown, &settings);
+        assert_eq!(ui.state(), Screen::Limits);
+        ui.handle(Event::Serial(b'h'), &settings);
+        ui.handle(Event::Serial(b'!'), &settings);
+        assert_eq!(ui.state(), Screen::Limits);
+    }
+}
@@ -0,0 +1,54 @@
+//! Millisecond time base. SysTick fires an interrupt every millisecond, which advances the counter;
+//! `SysTick` exception handler must call `tick`.
+//!
+//! Millisecond counter wraps around every 49 days, which is fine for the deadlines. For the
+//! uptime, number of wrap-arounds is counted, too.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use cortex_m::peripheral::syst::SystClkSource;
+use cortex_m::peripheral::SYST;
+
+static MILLIS: AtomicU32 = AtomicU32::new(0);
+/// How many times `MILLIS` has wrapped around.
+static WRAPS: AtomicU32 = AtomicU32::new(0);
+
+/// Configure SysTick to interrupt every millisecond. `hclk` is the core clock frequency.
+pub fn start(mut syst: SYST, hclk: u32) {
+    syst.set_clock_source(SystClkSource::Core);
+    syst.set_reload(hclk / 1000 - 1);
+    syst.clear_current();
+    syst.enable_interrupt();
+    syst.enable_counter();
+}
+
+/// Milliseconds since `start`. Wraps around every 49 days.
+pub fn millis() -> u32 {
+    MILLIS.load(Ordering::Relaxed)
+}
+
+/// Advance time by one millisecond. Called from the SysTick handler.
+pub fn tick() {
+    advance(1);
+}
+
+/// Advance time by `ms` milliseconds, for the time SysTick was not running (in Stop mode).
+pub fn advance(ms: u32) {
+    let prev = MILLIS.fetch_add(ms, Ordering::Relaxed);
+    if prev.checked_add(ms).is_none() {
+        WRAPS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Seconds since `start`, does not wrap around (for the next 136 years).
+pub fn uptime_secs() -> u32 {
+    // Counters are updated together from the interrupt (or with interrupts disabled)
+    let (wraps, millis) =
+        cortex_m::interrupt::free(|_| (WRAPS.load(Ordering::Relaxed), MILLIS.load(Ordering::Relaxed)));
+    (((u64::from(wraps) << 32) | u64::from(millis)) / 1000) as u32
+}
+
+/// Is `deadline` (in milliseconds since `start`) reached? Works across wrap-around as long as the
+/// deadline is less than 24 days away.
+pub fn reached(deadline: u32) -> bool {
+    (millis().wrapping_sub(deadline) as i32) >= 0
+}
@@ -0,0 +1,47 @@
+//! One-shot microsecond timer on TIM2, drives the queued LCD driver (see `queue`).
+
+use stm32f1::stm32f103::{RCC, TIM2};
+use crate::clock::Clocks;
+
+pub struct OneShot {
+    tim: TIM2,
+}
+
+impl OneShot {
+    /// Configure TIM2 to count microseconds and to raise an interrupt once time is up. TIM2
+    /// interrupt itself must be unmasked in NVIC by the caller.
+    pub fn new(tim: TIM2, rcc: &RCC, clocks: &Clocks) -> OneShot {
+        rcc.apb1enr.modify(|_, w| w.tim2en().set_bit());
+
+        // Timer clock is doubled if APB1 is divided
+        let timer_clk = if clocks.pclk1 == clocks.hclk {
+            clocks.pclk1
+        } else {
+            clocks.pclk1 * 2
+        };
+        tim.psc.write(|w| w.psc().bits((timer_clk / 1_000_000 - 1) as u16));
+        // Only counter overflow sets the interrupt flag, not the UG bit
+        tim.cr1.write(|w| w.opm().set_bit().urs().set_bit());
+        // Load prescaler
+        tim.egr.write(|w| w.ug().set_bit());
+        tim.dier.write(|w| w.uie().set_bit());
+        OneShot { tim }
+    }
+
+    /// Fire the interrupt after given amount of microseconds (up to 65535).
+    pub fn start_us(&mut self, us: u32) {
+        self.tim.arr.write(|w| w.arr().bits(us.clamp(1, 0xffff) as u16));
+        self.tim.cnt.reset();
+        self.tim.cr1.modify(|_, w| w.cen().set_bit());
+    }
+
+    /// Is timer counting? Timer stops by itself once time is up.
+    pub fn is_running(&self) -> bool {
+        self.tim.cr1.read().cen().bit_is_set()
+    }
+
+    /// Clear the interrupt flag.
+    pub fn clear_interrupt(&mut self) {
+        self.tim.sr.modify(|_, w| w.uif().clear_bit());
+    }
+}
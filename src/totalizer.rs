@@ -0,0 +1,139 @@
+//! Totalizer: counts the pulses of the pulse input (a flow meter, say; see `pulse_input`) over
+//! resets, checkpointing the total to flash (see `kv`) every `kv::CHECKPOINT_MS` if it has
+//! changed. So pulses since the last checkpoint are lost on reset.
+//!
+//! Total is only reset by the long press twice: the first one arms the reset for `ARM_MS` (button
+//! or encoder disarms it), the second one resets the total, which is saved right away.
+//!
+//! Like the stopwatch, time is passed to `advance`, which must be called before every event and
+//! every render.
+
+use core::fmt::{self, Write};
+use crate::text;
+use crate::ui::Event;
+
+/// Second long press must follow the first one within that time, in milliseconds.
+const ARM_MS: u32 = 5000;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Totalizer {
+    /// Pulses counted since the last reset.
+    total: u32,
+    /// Total checkpointed.
+    saved: u32,
+    /// Pulse input count at the last update.
+    seen: u32,
+    /// How long the reset has been armed for, in milliseconds, `None` if it is not.
+    armed_ms: Option<u32>,
+    /// Timer value of the last `advance`.
+    last_ms: u32,
+}
+
+impl Totalizer {
+    /// Start from the checkpointed total.
+    pub fn restore(&mut self, total: u32) {
+        self.total = total;
+        self.saved = total;
+    }
+
+    /// Account the pulses counted by the pulse input so far (wrapping around, zero at boot).
+    pub fn update(&mut self, pulses: u32) {
+        self.total = self.total.saturating_add(pulses.wrapping_sub(self.seen));
+        self.seen = pulses;
+    }
+
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    /// Total to checkpoint, `None` if it is saved already.
+    pub fn checkpoint(&mut self) -> Option<u32> {
+        (self.total != self.saved).then(|| {
+            self.saved = self.total;
+            self.total
+        })
+    }
+
+    /// Account the time passed since the last call (`now_ms` is the millisecond timer, which can
+    /// wrap around). Reset is disarmed after `ARM_MS`.
+    pub fn advance(&mut self, now_ms: u32) {
+        let elapsed_ms = now_ms.wrapping_sub(self.last_ms);
+        self.last_ms = now_ms;
+        self.armed_ms = self.armed_ms.map(|armed_ms| armed_ms.saturating_add(elapsed_ms)).filter(|&armed_ms| armed_ms < ARM_MS);
+    }
+
+    /// Long press arms the reset, the second one resets the total; button or encoder while armed
+    /// disarms it. Returns `true` if the event was used.
+    pub fn handle(&mut self, event: Event) -> bool {
+        match (event, self.armed_ms) {
+            (Event::LongPress, None) => self.armed_ms = Some(0),
+            (Event::LongPress, Some(_)) => {
+                self.armed_ms = None;
+                self.total = 0;
+            }
+            (Event::Button | Event::EncoderUp | Event::EncoderDown, Some(_)) => self.armed_ms = None,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Write the total (`Total    1234567`).
+    pub fn write_total<W: Write>(&self, w: &mut W) -> fmt::Result {
+        w.write_str("Total")?;
+        text::uint(w, self.total, 10)
+    }
+
+    /// Write the total checkpointed (`Saved    1234500`) or, while armed, the reset prompt.
+    pub fn write_status<W: Write>(&self, w: &mut W) -> fmt::Result {
+        if self.armed_ms.is_some() {
+            return w.write_str("Hold to reset");
+        }
+        w.write_str("Saved")?;
+        text::uint(w, self.saved, 10)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn totalizer_checkpoints() {
+        use crate::mock;
+        use crate::screens::{self, Screen, Settings, Stats};
+        use crate::ui::Event;
+
+        // Pulses are added to the restored total, which is only checkpointed when changed
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::Totalizer, &Stats::default(), settings);
+        settings.totalizer.restore(169);
+        assert_eq!(settings.totalizer.checkpoint(), None);
+        settings.totalizer.update(30);
+        settings.totalizer.update(31);
+        assert_eq!(render(&settings), ("Total       200 ".into(), "Saved       169 ".into()));
+        assert_eq!(settings.totalizer.checkpoint(), Some(200));
+        assert_eq!(settings.totalizer.checkpoint(), None);
+
+        // Reset takes two long presses within five seconds, button disarms it
+        assert!(Screen::Totalizer.long_press());
+        settings.advance(1000);
+        assert!(settings.handle(Screen::Totalizer, Event::LongPress));
+        assert_eq!(render(&settings).1, "Hold to reset   ");
+        assert!(!settings.handle(Screen::Totalizer, Event::Timer));
+        assert!(settings.handle(Screen::Totalizer, Event::Button));
+        assert_eq!(render(&settings).1, "Saved       200 ");
+        assert!(!settings.handle(Screen::Totalizer, Event::Button));
+        assert!(settings.handle(Screen::Totalizer, Event::LongPress));
+        settings.advance(6000);
+        assert_eq!(render(&settings).1, "Saved       200 ");
+        assert!(settings.handle(Screen::Totalizer, Event::LongPress));
+        settings.advance(10_000);
+        assert!(settings.handle(Screen::Totalizer, Event::LongPress));
+        assert_eq!(settings.totalizer.total(), 0);
+        assert_eq!(settings.totalizer.checkpoint(), Some(0));
+        settings.totalizer.update(33);
+        assert_eq!(render(&settings), ("Total         2 ".into(), "Saved         0 ".into()));
+
+        let mut ui = screens::navigation();
+        ui.handle(Event::Serial(b'+'), &settings);
+        assert_eq!(ui.state(), Screen::Totalizer);
+    }
+}
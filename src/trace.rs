@@ -0,0 +1,173 @@
+//! Trace of the HD44780 bus (`itm-trace` feature), for validating the timing against a logic
+//! analyzer capture.
+//!
+//! `TracedPort` sits between `LcdHardware` and the GPIO port and reports every nibble latched by
+//! the display (on the falling edge of E), both written and read. Delays are not reported
+//! separately, they are the gaps between the timestamps.
+//!
+//! On the target, events are sent over ITM (`ItmSink`): two 32-bit words per event on stimulus
+//! port 1, cycle counter value first, then the event (bits 0-3 are the data, bit 4 is RS, bit 8 is
+//! set for reads). ITM and SWO must be configured by the debugger; if ITM or the stimulus port is
+//! disabled, events are dropped. Sending an event takes a few microseconds (more with a slow SWO
+//! clock), which adds to the gaps between the nibbles.
+
+use core::cell::Cell;
+use crate::hardware::{DATA, E, RS, RW};
+use crate::port::Port;
+
+/// Nibble latched by the display.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TraceEvent {
+    pub rs: bool,
+    /// Display is driving the data lines.
+    pub read: bool,
+    pub data: u8,
+}
+
+impl TraceEvent {
+    /// Event packed into a word (see module documentation).
+    pub fn to_bits(self) -> u32 {
+        (u32::from(self.read) << 8) | (u32::from(self.rs) << 4) | u32::from(self.data)
+    }
+}
+
+/// Receiver of the trace events. Called right before E goes low.
+pub trait TraceSink {
+    fn record(&self, event: TraceEvent);
+}
+
+/// GPIO port reporting the transfers to the sink.
+pub struct TracedPort<P: Port, S: TraceSink> {
+    port: P,
+    sink: S,
+    /// Last written levels of the LCD pins.
+    output: Cell<u16>,
+}
+
+impl<P: Port, S: TraceSink> TracedPort<P, S> {
+    pub fn new(port: P, sink: S) -> TracedPort<P, S> {
+        TracedPort {
+            port,
+            sink,
+            output: Cell::new(0),
+        }
+    }
+}
+
+impl<P: Port, S: TraceSink> Port for TracedPort<P, S> {
+    fn write_pin_range(&self, offset: usize, count: usize, data: u16) {
+        let mask = (((1u32 << count) - 1) << offset) as u16;
+        let prev = self.output.get();
+        let next = (prev & !mask) | ((data << offset) & mask);
+        self.output.set(next);
+
+        if prev & (1 << E) != 0 && next & (1 << E) == 0 {
+            let read = prev & (1 << RW) != 0;
+            let data = if read {
+                // Display releases the data lines once E goes low
+                self.port.read_pin_range(DATA, 4) as u8
+            } else {
+                ((prev >> DATA) & 0xf) as u8
+            };
+            self.sink.record(TraceEvent {
+                rs: prev & (1 << RS) != 0,
+                read,
+                data,
+            });
+        }
+        self.port.write_pin_range(offset, count, data);
+    }
+
+    fn read_pin_range(&self, offset: usize, count: usize) -> u16 {
+        self.port.read_pin_range(offset, count)
+    }
+
+    fn output(&self, pin: usize) {
+        self.port.output(pin);
+    }
+
+    fn open_drain(&self, pin: usize) {
+        self.port.open_drain(pin);
+    }
+
+    fn input(&self, pin: usize) {
+        self.port.input(pin);
+    }
+}
+
+#[cfg(target_arch = "arm")]
+pub use self::itm::ItmSink;
+
+#[cfg(target_arch = "arm")]
+mod itm {
+    use cortex_m::peripheral::{DWT, ITM};
+    use super::{TraceEvent, TraceSink};
+
+    /// ITM stimulus port used for the trace.
+    const PORT: usize = 1;
+    /// ITMENA bit of the trace control register.
+    const TCR_ITMENA: u32 = 1;
+
+    /// Sends trace events over ITM, timestamped by the cycle counter (which must be running, see
+    /// `delay::CycleDelay`).
+    pub struct ItmSink {
+        _private: (),
+    }
+
+    impl ItmSink {
+        /// ITM is configured by the debugger, it is only written here.
+        pub fn new(_itm: ITM) -> ItmSink {
+            ItmSink { _private: () }
+        }
+    }
+
+    impl TraceSink for ItmSink {
+        fn record(&self, event: TraceEvent) {
+            // Stimulus ports are only written, other ITM registers only read
+            let itm = unsafe { &mut *ITM::PTR };
+            if itm.tcr.read() & TCR_ITMENA == 0 || itm.ter[0].read() & (1 << PORT) == 0 {
+                return;
+            }
+            let stim = &mut itm.stim[PORT];
+            for word in [DWT::cycle_count(), event.to_bits()] {
+                while !stim.is_fifo_ready() {}
+                stim.write_u32(word);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn trace_reports_every_nibble() {
+        use lcd::{Display, FunctionDots, FunctionLine};
+        use std::cell::RefCell;
+        use std::vec::Vec;
+        use crate::hardware::LcdHardware;
+        use crate::mock::{MockHardware, Transfer};
+        use crate::trace::{TraceEvent, TraceSink, TracedPort};
+
+        #[derive(Default)]
+        struct Recorder(RefCell<Vec<TraceEvent>>);
+
+        impl TraceSink for &Recorder {
+            fn record(&self, event: TraceEvent) {
+                self.0.borrow_mut().push(event);
+            }
+        }
+
+        let mock = MockHardware::new();
+        let recorder = Recorder::default();
+        let mut display = Display::new(LcdHardware::new(TracedPort::new(&mock, &recorder), &mock));
+        display.init(FunctionLine::Line2, FunctionDots::Dots5x8);
+        display.print("Hi");
+
+        let events = recorder.0.borrow();
+        let traced: Vec<Transfer> = events.iter().filter(|e| !e.read).map(|e| Transfer { rs: e.rs, data: e.data }).collect();
+        assert_eq!(traced, mock.transfers());
+        // Busy flag is only read with `input`
+        assert_eq!(events.iter().any(|e| e.read), cfg!(feature = "input"));
+        assert_eq!(TraceEvent { rs: true, read: true, data: 0xa }.to_bits(), 0x11a);
+    }
+}
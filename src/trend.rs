@@ -0,0 +1,147 @@
+//! Trend widget: the last `N` samples of a value, drawn as column bars on one row (one character
+//! per sample, the newest one on the right), for the screens to show next to the value itself.
+//!
+//! Bars are scaled between the lowest and the highest sample kept, but never to less than the
+//! minimum span given (so the noise of a steady value does not fill the whole height). Bars are
+//! the custom characters 1 to 7 (`GLYPHS`, bar height in pixels is the character code), which the
+//! screen showing the trend must upload; the low battery icon (character 0) is left alone.
+
+use core::fmt::{self, Write};
+
+/// Bar heights, in pixels (the top row of the cell is left blank, separating the rows).
+const LEVELS: usize = 7;
+
+/// Custom characters of the bars, from the lowest one.
+pub const GLYPHS: [(char, [u8; 8]); LEVELS] = glyphs();
+
+const fn glyphs() -> [(char, [u8; 8]); LEVELS] {
+    let mut glyphs = [('\u{0}', [0; 8]); LEVELS];
+    let mut level = 1;
+    while level <= LEVELS {
+        let mut map = [0; 8];
+        let mut row = 8 - level;
+        while row < 8 {
+            map[row] = 0b11111;
+            row += 1;
+        }
+        glyphs[level - 1] = (level as u8 as char, map);
+        level += 1;
+    }
+    glyphs
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Trend<const N: usize> {
+    samples: [i16; N],
+    /// Samples taken, up to `N` (the oldest one is overwritten from then on).
+    len: usize,
+    /// Where the next sample goes.
+    next: usize,
+    /// Smallest range the bars are scaled to.
+    min_span: i16,
+}
+
+impl<const N: usize> Trend<N> {
+    pub const fn new(min_span: i16) -> Trend<N> {
+        Trend { samples: [0; N], len: 0, next: 0, min_span }
+    }
+
+    /// Add the sample, dropping the oldest one if all `N` are taken.
+    pub fn push(&mut self, value: i16) {
+        self.samples[self.next] = value;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+    }
+
+    /// Samples kept, from the oldest one.
+    pub fn samples(&self) -> impl Iterator<Item = i16> + '_ {
+        (0..self.len).map(move |i| self.samples[(self.next + N - self.len + i) % N])
+    }
+
+    /// Newest sample, if any.
+    pub fn latest(&self) -> Option<i16> {
+        self.samples().last()
+    }
+
+    /// Lowest and highest sample, if any.
+    pub fn range(&self) -> Option<(i16, i16)> {
+        self.samples().fold(None, |range, value| match range {
+            None => Some((value, value)),
+            Some((min, max)) => Some((min.min(value), max.max(value))),
+        })
+    }
+
+    /// Bar height of every sample (1 to 7), from the oldest one. Range narrower than the minimum
+    /// span is widened around its middle, so a steady value is drawn at the half height.
+    pub fn levels(&self) -> impl Iterator<Item = u8> + '_ {
+        let (min, max) = self.range().unwrap_or_default();
+        let (min, max) = (i32::from(min), i32::from(max));
+        let span = (max - min).max(i32::from(self.min_span)).max(2);
+        let low = min - (span - (max - min)) / 2;
+        let steps = LEVELS as i32 - 1;
+        self.samples().map(move |value| (1 + ((i32::from(value) - low) * steps + span / 2) / span).clamp(1, LEVELS as i32) as u8)
+    }
+
+    /// Write the bars, `N` characters (spaces on the left until all samples are taken).
+    pub fn write_bars<W: Write>(&self, w: &mut W) -> fmt::Result {
+        (self.len..N).try_for_each(|_| w.write_char(' '))?;
+        self.levels().try_for_each(|level| w.write_char(char::from(level)))
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn trend_screen() {
+        use crate::mock;
+        use crate::screens::{self, Screen, Settings, Stats};
+        use crate::trend::Trend;
+        use crate::ui::Event;
+
+        // Bars are scaled between the lowest and the highest sample, the oldest ones are dropped
+        let mut trend: Trend<4> = Trend::new(0);
+        assert_eq!(trend.latest(), None);
+        for value in [5, 0, 60, 30, 10] {
+            trend.push(value);
+        }
+        assert_eq!(trend.samples().collect::<std::vec::Vec<_>>(), [0, 60, 30, 10]);
+        assert_eq!(trend.range(), Some((0, 60)));
+        assert_eq!(trend.levels().collect::<std::vec::Vec<_>>(), [1, 7, 4, 2]);
+        // Steady value is in the middle, small changes are not blown up to the full height
+        let mut trend: Trend<3> = Trend::new(20);
+        trend.push(215);
+        assert_eq!(trend.levels().collect::<std::vec::Vec<_>>(), [4]);
+        trend.push(217);
+        trend.push(213);
+        assert_eq!(trend.levels().collect::<std::vec::Vec<_>>(), [4, 5, 3]);
+
+        let mut settings = Settings::default();
+        assert_eq!(mock::render(Screen::Trend, &Stats::default(), &settings), ("Temp      -C    ".into(), " ".repeat(16)));
+
+        for temp in [200, 210, 230] {
+            settings.trend.push(temp);
+        }
+        let lcd = mock::render_lcd(Screen::Trend, &Stats::default(), &settings);
+        assert_eq!(lcd.row(0, 16), "Temp   23.0C    ");
+        assert_eq!(lcd.row(1, 16), "             ###");
+        // Bars are drawn with the custom characters (height is the character code), the low
+        // battery icon is kept
+        assert_eq!(lcd.glyph(3), [0, 0, 0, 0, 0, 0b11111, 0b11111, 0b11111]);
+        let low_battery = lcd.glyph(0);
+        let lcd = mock::render_lcd(Screen::Hello, &Stats::default(), &settings);
+        assert_eq!(lcd.glyph(0), low_battery);
+        assert_ne!(lcd.glyph(3), [0, 0, 0, 0, 0, 0b11111, 0b11111, 0b11111]);
+
+        let mut ui = screens::navigation();
+        ui.handle(Event::Serial(b'd'), &settings);
+        ui.handle(Event::Button, &settings);
+        assert_eq!(ui.state(), Screen::Trend);
+        ui.handle(Event::EncoderUp, &settings);
+        assert_eq!(ui.state(), Screen::Load);
+        ui.handle(Event::EncoderDown, &settings);
+        assert_eq!(ui.state(), Screen::Trend);
+        ui.handle(Event::Serial(b'h'), &settings);
+        ui.handle(Event::Serial(b'j'), &settings);
+        assert_eq!(ui.state(), Screen::Trend);
+    }
+}
@@ -0,0 +1,81 @@
+//! Event-driven UI state machine. States are screens, events are user input (button, encoder),
+//! timer ticks or serial commands.
+//!
+//! Transitions are described by a static table rather than by code, so adding a screen is a matter
+//! of adding a few rows to the table.
+
+/// Input to the state machine.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// User button is pressed.
+    Button,
+    /// User button is held down for a while, on the screens which take it (see
+    /// `Screen::long_press`), where `Button` is reported on the release instead.
+    LongPress,
+    /// Rotary encoder is turned clockwise by one step.
+    EncoderUp,
+    /// Rotary encoder is turned counterclockwise by one step.
+    EncoderDown,
+    /// Periodic timer tick.
+    Timer,
+    /// Command byte received over serial.
+    Serial(u8),
+}
+
+/// Single row of the transition table.
+pub struct Transition<S, E, C> {
+    /// State transition applies to, `None` for any state.
+    pub from: Option<S>,
+    /// Event triggering the transition.
+    pub event: E,
+    /// Additional condition on the context, transition is ignored if it returns `false`.
+    pub guard: Option<fn(&C) -> bool>,
+    /// New state.
+    pub to: S,
+}
+
+/// State machine over states `S`, events `E` with the guard context `C`.
+pub struct StateMachine<S: 'static, E: 'static, C: 'static> {
+    state: S,
+    table: &'static [Transition<S, E, C>],
+}
+
+impl<S: Copy + PartialEq, E: PartialEq, C> StateMachine<S, E, C> {
+    pub fn new(initial: S, table: &'static [Transition<S, E, C>]) -> StateMachine<S, E, C> {
+        StateMachine {
+            state: initial,
+            table,
+        }
+    }
+
+    /// Current state.
+    pub fn state(&self) -> S {
+        self.state
+    }
+
+    /// Handle an event: first row of the table matching current state and the event, with the
+    /// guard passing, is applied. Returns `true` if state was changed.
+    pub fn handle(&mut self, event: E, ctx: &C) -> bool {
+        let state = self.state;
+        let transition = self.table.iter().find(|t| {
+            t.from.is_none_or(|from| from == state)
+                && t.event == event
+                && t.guard.is_none_or(|guard| guard(ctx))
+        });
+        match transition {
+            Some(t) if t.to != state => {
+                self.state = t.to;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Switch to `state` directly, bypassing the table (for the input which selects its own
+    /// screen, like the keypad of the door lock). Returns `true` if state was changed.
+    pub fn enter(&mut self, state: S) -> bool {
+        let changed = state != self.state;
+        self.state = state;
+        changed
+    }
+}
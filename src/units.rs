@@ -0,0 +1,45 @@
+//! Units of measurement selectable by the user. Conversions are done in fixed point.
+
+/// Temperature unit.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TempUnit {
+    Celsius,
+    Fahrenheit,
+}
+
+impl TempUnit {
+    /// Unit symbol, shown after the value.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            TempUnit::Celsius => "C",
+            TempUnit::Fahrenheit => "F",
+        }
+    }
+
+    /// The other unit.
+    pub fn toggled(self) -> TempUnit {
+        match self {
+            TempUnit::Celsius => TempUnit::Fahrenheit,
+            TempUnit::Fahrenheit => TempUnit::Celsius,
+        }
+    }
+
+    /// Convert temperature given in tenths of °C into thousandths of this unit (for
+    /// `fmt::write_fixed`).
+    pub fn from_tenths_c(self, tenths_c: i16) -> i32 {
+        let milli_c = i32::from(tenths_c) * 100;
+        match self {
+            TempUnit::Celsius => milli_c,
+            TempUnit::Fahrenheit => milli_c * 9 / 5 + 32_000,
+        }
+    }
+
+    /// Convert temperature difference given in tenths of °C into thousandths of this unit.
+    pub fn delta_from_tenths_c(self, tenths_c: i16) -> i32 {
+        let milli_c = i32::from(tenths_c) * 100;
+        match self {
+            TempUnit::Celsius => milli_c,
+            TempUnit::Fahrenheit => milli_c * 9 / 5,
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Independent watchdog (IWDG) and detection of the last reset cause.
+
+use stm32f1::stm32f103::{IWDG, RCC};
+
+/// LSI frequency. It is an RC oscillator, actual frequency varies between 30Khz and 60Khz.
+const LSI_FREQ: u32 = 40_000;
+
+/// Watchdog counter runs at LSI / 64.
+const PRESCALER: u32 = 64;
+
+/// Independent watchdog. Once started, it cannot be stopped, so it has to be fed regularly.
+pub struct Watchdog {
+    iwdg: IWDG,
+}
+
+impl Watchdog {
+    /// Start the watchdog, which resets the MCU if not fed within `timeout_ms` milliseconds.
+    /// Maximum timeout is 6.5 seconds (nominal, the real one depends on LSI frequency).
+    pub fn start(iwdg: IWDG, timeout_ms: u32) -> Watchdog {
+        let reload = (timeout_ms * (LSI_FREQ / 1000) / PRESCALER).clamp(1, 0xfff);
+
+        // Start first, so LSI is turned on
+        iwdg.kr.write(|w| w.key().start());
+        iwdg.kr.write(|w| w.key().enable());
+        iwdg.pr.write(|w| w.pr().divide_by64());
+        iwdg.rlr.write(|w| w.rl().bits(reload as u16));
+        while iwdg.sr.read().pvu().bit_is_set() || iwdg.sr.read().rvu().bit_is_set() {}
+        iwdg.kr.write(|w| w.key().reset());
+        Watchdog { iwdg }
+    }
+
+    /// Reload the watchdog counter.
+    pub fn feed(&mut self) {
+        self.iwdg.kr.write(|w| w.key().reset());
+    }
+}
+
+/// What caused the last reset.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResetCause {
+    /// Watchdog (either independent or window) was not fed in time.
+    Watchdog,
+    /// Reset requested by the software (`SCB::sys_reset`).
+    Software,
+    /// Power-on or brown-out.
+    PowerOn,
+    /// External reset via NRST pin (reset button or a debugger).
+    Pin,
+    /// Low-power management reset (entering Standby/Stop when not allowed by the option bytes).
+    LowPower,
+    /// None of the flags is set.
+    Unknown,
+}
+
+impl ResetCause {
+    /// Read reset flags and clear them, so the next reset is reported properly.
+    pub fn take(rcc: &RCC) -> ResetCause {
+        let csr = rcc.csr.read();
+        rcc.csr.modify(|_, w| w.rmvf().clear());
+
+        // NRST pin is driven low on every reset, so pin flag is checked last
+        if csr.iwdgrstf().bit_is_set() || csr.wwdgrstf().bit_is_set() {
+            ResetCause::Watchdog
+        } else if csr.sftrstf().bit_is_set() {
+            ResetCause::Software
+        } else if csr.porrstf().bit_is_set() {
+            ResetCause::PowerOn
+        } else if csr.lpwrrstf().bit_is_set() {
+            ResetCause::LowPower
+        } else if csr.pinrstf().bit_is_set() {
+            ResetCause::Pin
+        } else {
+            ResetCause::Unknown
+        }
+    }
+
+    /// Short label for the splash screen.
+    pub fn label(self) -> &'static str {
+        match self {
+            ResetCause::Watchdog => "WDG reset",
+            ResetCause::Software => "SW reset",
+            ResetCause::PowerOn => "POR",
+            ResetCause::Pin => "pin reset",
+            ResetCause::LowPower => "LPWR reset",
+            ResetCause::Unknown => "reset",
+        }
+    }
+}
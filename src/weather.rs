@@ -0,0 +1,194 @@
+//! Weather dashboard: readings of whatever ambient sensors are compiled in, on one screen.
+//!
+//! Sensors attach the quantities they measure at startup (see `attach`) and pass their readings
+//! to `update`, so the dashboard knows nothing about the drivers. Only the quantities attached are
+//! laid out: up to two go one per row with their names, three or four go two per row (the units
+//! tell them apart). Sensor which did not answer, or has not reported for `STALE_MS`, shows `--`
+//! instead of the value. In this tree, temperature comes from the first DS18B20 on the 1-Wire bus
+//! (with the `onewire` feature) or, without it, from the chip sensor; humidity, pressure and light
+//! are ready for the sensors which measure them.
+//!
+//! Like the stopwatch, time is passed to `advance`, which must be called before every update and
+//! every render.
+
+use core::fmt::{self, Write};
+use crate::text::{self, Align};
+use crate::units::TempUnit;
+
+/// Reading older than that is shown as missing, in milliseconds.
+const STALE_MS: u32 = 10_000;
+/// Width of a value with its unit, in characters.
+const CELL: usize = 7;
+/// Width of a name in the one-per-row layout, in characters.
+const NAME: usize = 8;
+
+/// Quantities shown, in the order of the layout.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Quantity {
+    /// Air temperature, in tenths of °C.
+    Temperature,
+    /// Relative humidity, in tenths of %.
+    Humidity,
+    /// Barometric pressure, in tenths of hPa.
+    Pressure,
+    /// Illuminance, in lux.
+    Light,
+}
+
+/// Number of quantities.
+const QUANTITIES: usize = 4;
+
+impl Quantity {
+    const ALL: [Quantity; QUANTITIES] = [Quantity::Temperature, Quantity::Humidity, Quantity::Pressure, Quantity::Light];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Quantity::Temperature => "Temp",
+            Quantity::Humidity => "Humidity",
+            Quantity::Pressure => "Pressure",
+            Quantity::Light => "Light",
+        }
+    }
+
+    /// Write the value with its unit (or `--` and the unit), right-aligned to `CELL` characters.
+    fn write_cell<W: Write>(self, w: &mut W, value: Option<i32>, unit: TempUnit) -> fmt::Result {
+        let symbol = match self {
+            Quantity::Temperature => unit.symbol(),
+            Quantity::Humidity => "%",
+            Quantity::Pressure => "hPa",
+            Quantity::Light => "lx",
+        };
+        let width = CELL - symbol.len();
+        match (self, value) {
+            (_, None) => text::str(w, "--", width, Align::Right)?,
+            (Quantity::Temperature, Some(value)) => text::fixed(w, unit.from_tenths_c(value as i16), 1, width)?,
+            // Whole units are plenty, and fit
+            (Quantity::Humidity | Quantity::Pressure, Some(value)) => text::fixed(w, value * 100, 0, width)?,
+            (Quantity::Light, Some(value)) => text::uint(w, value.max(0) as u32, width)?,
+        }
+        w.write_str(symbol)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Reading {
+    value: i32,
+    /// Timer value at the reading.
+    at_ms: u32,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Dashboard {
+    /// Attached quantities and their last readings.
+    readings: [Option<Option<Reading>>; QUANTITIES],
+    /// Timer value of the last `advance`.
+    now_ms: u32,
+}
+
+impl Dashboard {
+    /// Account the time (`now_ms` is the millisecond timer, which can wrap around).
+    pub fn advance(&mut self, now_ms: u32) {
+        self.now_ms = now_ms;
+    }
+
+    /// Lay the quantity out, it is missing until the first reading.
+    pub fn attach(&mut self, quantity: Quantity) {
+        self.readings[quantity as usize].get_or_insert(None);
+    }
+
+    /// Account the reading of the attached quantity (`None` if the sensor did not answer).
+    pub fn update(&mut self, quantity: Quantity, value: Option<i32>) {
+        let now_ms = self.now_ms;
+        if let Some(reading) = &mut self.readings[quantity as usize] {
+            *reading = value.map(|value| Reading { value, at_ms: now_ms });
+        }
+    }
+
+    /// Current value of the quantity, `None` if it is missing (or not attached).
+    pub fn value(&self, quantity: Quantity) -> Option<i32> {
+        let reading = self.readings[quantity as usize].flatten()?;
+        (self.now_ms.wrapping_sub(reading.at_ms) < STALE_MS).then_some(reading.value)
+    }
+
+    /// Write one `row` (0 or 1) of the layout: up to two quantities one per row
+    /// (`Temp      23.4C`), more two per row (`  23.4C     45%`).
+    pub fn write_row<W: Write>(&self, w: &mut W, row: usize, unit: TempUnit) -> fmt::Result {
+        let mut attached = Quantity::ALL.into_iter().filter(|&quantity| self.readings[quantity as usize].is_some());
+        let count = attached.clone().count();
+        if count == 0 {
+            return w.write_str(if row == 0 { "Weather" } else { "no sensors" });
+        }
+        if count <= 2 {
+            return match attached.nth(row) {
+                Some(quantity) => {
+                    text::str(w, quantity.name(), NAME, Align::Left)?;
+                    quantity.write_cell(w, self.value(quantity), unit)
+                }
+                None => Ok(()),
+            };
+        }
+        for (i, quantity) in attached.skip(2 * row).take(2).enumerate() {
+            if i > 0 {
+                w.write_char(' ')?;
+            }
+            quantity.write_cell(w, self.value(quantity), unit)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    #[test]
+    fn weather_dashboard() {
+        use crate::mock;
+        use crate::screens::{self, Screen, Settings, Stats};
+        use crate::ui::Event;
+        use crate::units::TempUnit;
+        use crate::weather::Quantity;
+
+        let mut settings = Settings::default();
+        let render = |settings: &Settings| mock::render(Screen::Weather, &Stats::default(), settings);
+        assert_eq!(render(&settings), ("Weather         ".into(), "no sensors      ".into()));
+
+        // Attached quantity is missing until the first reading, up to two go one per row
+        let weather = &mut settings.weather;
+        weather.attach(Quantity::Temperature);
+        assert_eq!(render(&settings), ("Temp        --C ".into(), "                ".into()));
+        let weather = &mut settings.weather;
+        weather.advance(1000);
+        weather.update(Quantity::Temperature, Some(234));
+        weather.attach(Quantity::Humidity);
+        weather.update(Quantity::Humidity, Some(452));
+        // Not attached, not shown
+        weather.update(Quantity::Light, Some(350));
+        assert_eq!(weather.value(Quantity::Light), None);
+        assert_eq!(render(&settings), ("Temp      23.4C ".into(), "Humidity    45% ".into()));
+        settings.temp_unit = TempUnit::Fahrenheit;
+        assert_eq!(render(&settings).0, "Temp      74.1F ");
+        settings.temp_unit = TempUnit::Celsius;
+
+        // More go two per row
+        let weather = &mut settings.weather;
+        weather.attach(Quantity::Pressure);
+        weather.advance(5000);
+        weather.update(Quantity::Pressure, Some(10_132));
+        assert_eq!(render(&settings), ("  23.4C     45% ".into(), "1013hPa         ".into()));
+        let weather = &mut settings.weather;
+        weather.attach(Quantity::Light);
+        weather.update(Quantity::Light, Some(350));
+        assert_eq!(render(&settings), ("  23.4C     45% ".into(), "1013hPa   350lx ".into()));
+
+        // Stale reading and the sensor which did not answer are missing
+        let weather = &mut settings.weather;
+        weather.advance(11_000);
+        weather.update(Quantity::Light, None);
+        assert_eq!(weather.value(Quantity::Temperature), None);
+        assert_eq!(weather.value(Quantity::Pressure), Some(10_132));
+        assert_eq!(render(&settings), ("    --C     --% ".into(), "1013hPa    --lx ".into()));
+
+        let mut ui = screens::navigation();
+        ui.handle(Event::Serial(b'~'), &settings);
+        assert_eq!(ui.state(), Screen::Weather);
+    }
+}